@@ -1,9 +1,11 @@
 use std::{
-    fs::File,
-    io::{self, BufReader, Cursor, Read},
-    path::PathBuf,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::{Path, PathBuf},
 };
 
+use flate2::read::GzDecoder;
 use image::RgbImage;
 use image::{io::Reader as ImageReader, ImageError};
 use zip::{result::ZipError, ZipArchive};
@@ -23,8 +25,14 @@ enum ErrorKind {
     Io(#[from] io::Error),
     #[error("zip: {0}")]
     Zip(#[from] ZipError),
+    #[error("rar: {0}")]
+    Rar(#[from] unrar::error::UnrarError),
+    #[error("7z: {0}")]
+    SevenZip(#[from] sevenz_rust::Error),
     #[error("image: {0}")]
     Image(#[from] ImageError),
+    #[error("unrecognized archive extension: {0:?}, expected one of cbz/zip/cbr/rar/7z/cbt/tar, or a directory")]
+    UnknownExtension(Option<String>),
 }
 
 trait ErrContext<T> {
@@ -43,56 +51,389 @@ where
     }
 }
 
-pub struct CbzReader {
+/// Files that sit alongside the pages in a comic archive but aren't pages themselves,
+/// matched case-insensitively against the entry's basename.
+const METADATA_FILES: &[&str] = &["ComicInfo.xml", "Thumbs.db", ".DS_Store"];
+
+/// Extensions `image::ImageReader::with_guessed_format` can be expected to handle,
+/// used here only to separate pages from metadata before anything is decoded.
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tiff", "tif", "avif",
+];
+
+/// Whether `name` (an archive entry name or a filesystem filename) looks like a comic
+/// page rather than something like `ComicInfo.xml` or a thumbnail cache.
+fn is_page(name: &str) -> bool {
+    let base = Path::new(name)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or(name);
+
+    if METADATA_FILES.iter().any(|m| base.eq_ignore_ascii_case(m)) {
+        return false;
+    }
+
+    let extension = Path::new(base)
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(|s| s.to_ascii_lowercase());
+
+    matches!(extension, Some(ext) if IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Sorts page names the way a reader would flip through them: `2.jpg` before `010.jpg`
+/// before `0100.jpg`, rather than the lexicographic order archive tools tend to store
+/// entries in.
+fn natural_key(names: &mut [String]) {
+    names.sort_by(|a, b| natord::compare(a, b));
+}
+
+/// Decodes one page's raw bytes via the same [`ImageReader::with_guessed_format`] path
+/// regardless of which backend it came from.
+fn decode_page(name: &str, bytes: Vec<u8>, ctx: impl Fn() -> String) -> Result<RgbImage> {
+    let ctx = || format!("{} -> {name}", ctx());
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .context(ctx)?
+        .decode()
+        .context(ctx)?;
+    Ok(image.to_rgb8())
+}
+
+/// One backend capable of serving the pages of a comic archive (or a plain directory of
+/// page images) in order. Implementations are expected to have already filtered out
+/// non-page entries and sorted the survivors into natural/reading order by the time
+/// they're constructed, so `next` only has to decode and advance.
+trait ArchiveReader {
+    fn next(&mut self) -> Result<Option<RgbImage>>;
+}
+
+/// CBZ/ZIP, read lazily by index since `zip` supports random access. The archive's own
+/// index order has nothing to do with page order, so the natural-sorted subset of
+/// indices is precomputed once up front.
+struct ZipBackend {
     path: PathBuf,
     archive: ZipArchive<BufReader<File>>,
-    index: usize,
+    order: Vec<usize>,
+    pos: usize,
 }
 
-impl CbzReader {
-    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
-        let path = path.into();
+impl ZipBackend {
+    fn open(path: PathBuf) -> Result<Self> {
         let ctx = || path.display();
         let file = File::open(&path).context(ctx)?;
-        let archive = ZipArchive::new(BufReader::new(file)).context(ctx)?;
+        let mut archive = ZipArchive::new(BufReader::new(file)).context(ctx)?;
+
+        let mut named: Vec<(usize, String)> = Vec::new();
+        for i in 0..archive.len() {
+            let entry = archive.by_index(i).context(ctx)?;
+            if entry.is_file() && is_page(entry.name()) {
+                named.push((i, entry.name().to_string()));
+            }
+        }
+        named.sort_by(|a, b| natord::compare(&a.1, &b.1));
+        let order = named.into_iter().map(|(i, _)| i).collect();
+
         Ok(Self {
             path,
             archive,
-            index: 0,
+            order,
+            pos: 0,
         })
     }
+}
 
-    pub fn next(&mut self) -> Result<Option<RgbImage>> {
-        while self.index < self.archive.len() {
-            let ctx = || self.path.display();
-            let mut file = self
-                .archive
-                .by_index({
-                    let i = self.index;
-                    self.index += 1;
-                    i
-                })
-                .context(ctx)?;
-
-            if !file.is_file() {
+impl ArchiveReader for ZipBackend {
+    fn next(&mut self) -> Result<Option<RgbImage>> {
+        let ctx = || self.path.display();
+        let Some(&i) = self.order.get(self.pos) else {
+            return Ok(None);
+        };
+        self.pos += 1;
+
+        let mut entry = self.archive.by_index(i).context(ctx)?;
+        let name = entry.name().to_string();
+        let mut bytes = Vec::new();
+        entry
+            .read_to_end(&mut bytes)
+            .context(|| format!("{} -> {name}", ctx()))?;
+
+        decode_page(&name, bytes, ctx).map(Some)
+    }
+}
+
+/// CBT/TAR, CBR/RAR and 7z are all read eagerly into memory up front, since none of
+/// their crates offer the kind of random access `zip` does, and are then served the
+/// same way once filtered and naturally sorted.
+struct EagerBackend {
+    path: PathBuf,
+    pages: Vec<(String, Vec<u8>)>,
+    index: usize,
+}
+
+impl EagerBackend {
+    fn new(path: PathBuf, mut pages: Vec<(String, Vec<u8>)>) -> Self {
+        pages.retain(|(name, _)| is_page(name));
+        pages.sort_by(|a, b| natord::compare(&a.0, &b.0));
+        Self {
+            path,
+            pages,
+            index: 0,
+        }
+    }
+
+    /// `.cbt`/`.tar` may or may not be gzip-compressed (`.tar.gz`); sniffed from the
+    /// leading gzip magic bytes rather than the filename, since both share the same
+    /// extension.
+    fn open_tar(path: PathBuf) -> Result<Self> {
+        let ctx = || path.display();
+        let file = File::open(&path).context(ctx)?;
+        let mut reader = BufReader::new(file);
+        let is_gzip = reader.fill_buf().context(ctx)?.starts_with(&[0x1f, 0x8b]);
+
+        let reader: Box<dyn Read> = if is_gzip {
+            Box::new(GzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+        let mut tar = tar::Archive::new(reader);
+
+        let mut pages = Vec::new();
+        for entry in tar.entries().context(ctx)? {
+            let mut entry = entry.context(ctx)?;
+            if !entry.header().entry_type().is_file() {
                 continue;
             }
+            let name = entry.path().context(ctx)?.display().to_string();
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).context(ctx)?;
+            pages.push((name, bytes));
+        }
 
-            let name = file.name().to_string();
-            let ctx = || format!("{} -> {}", ctx(), name);
+        Ok(Self::new(path, pages))
+    }
 
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes).context(ctx)?;
+    fn open_rar(path: PathBuf) -> Result<Self> {
+        let ctx = || path.display();
+        let mut archive = unrar::Archive::new(&path)
+            .open_for_processing()
+            .context(ctx)?;
+
+        let mut pages = Vec::new();
+        while let Some(header) = archive.read_header().context(ctx)? {
+            if header.entry().is_file() {
+                let name = header.entry().filename.display().to_string();
+                let (bytes, next) = header.read().context(ctx)?;
+                pages.push((name, bytes));
+                archive = next;
+            } else {
+                archive = header.skip().context(ctx)?;
+            }
+        }
 
-            let image = ImageReader::new(Cursor::new(bytes))
-                .with_guessed_format()
-                .context(ctx)?
-                .decode()
-                .context(ctx)?;
+        Ok(Self::new(path, pages))
+    }
 
-            return Ok(Some(image.to_rgb8()));
+    fn open_7z(path: PathBuf) -> Result<Self> {
+        let ctx = || path.display();
+        let mut pages = Vec::new();
+        sevenz_rust::decompress_file_with_extract_fn(&path, |entry, reader| {
+            if !entry.is_directory() {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                pages.push((entry.name().to_string(), bytes));
+            }
+            Ok(true)
+        })
+        .context(ctx)?;
+
+        Ok(Self::new(path, pages))
+    }
+}
+
+impl ArchiveReader for EagerBackend {
+    fn next(&mut self) -> Result<Option<RgbImage>> {
+        let ctx = || self.path.display();
+        let Some((name, bytes)) = self.pages.get(self.index).cloned() else {
+            return Ok(None);
+        };
+        self.index += 1;
+        decode_page(&name, bytes, ctx).map(Some)
+    }
+}
+
+/// A plain directory of page images, read lazily file-by-file since there's no archive
+/// to decompress.
+struct DirectoryBackend {
+    entries: Vec<PathBuf>,
+    index: usize,
+}
+
+impl DirectoryBackend {
+    fn open(path: PathBuf) -> Result<Self> {
+        let ctx = || path.display();
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&path).context(ctx)? {
+            let entry = entry.context(ctx)?;
+            let file_type = entry.file_type().context(ctx)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if file_type.is_file() && is_page(&name) {
+                entries.push(entry.path());
+            }
+        }
+
+        let mut named: Vec<(String, PathBuf)> = entries
+            .into_iter()
+            .map(|path| (path.file_name().unwrap().to_string_lossy().into_owned(), path))
+            .collect();
+        named.sort_by(|a, b| natord::compare(&a.0, &b.0));
+
+        Ok(Self {
+            entries: named.into_iter().map(|(_, path)| path).collect(),
+            index: 0,
+        })
+    }
+}
+
+impl ArchiveReader for DirectoryBackend {
+    fn next(&mut self) -> Result<Option<RgbImage>> {
+        let Some(path) = self.entries.get(self.index).cloned() else {
+            return Ok(None);
+        };
+        self.index += 1;
+
+        let ctx = || path.display();
+        let bytes = fs::read(&path).context(ctx)?;
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        decode_page(&name, bytes, ctx).map(Some)
+    }
+}
+
+pub struct CbzReader {
+    backend: Box<dyn ArchiveReader>,
+}
+
+impl CbzReader {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+
+        if path.is_dir() {
+            return Ok(Self {
+                backend: Box::new(DirectoryBackend::open(path)?),
+            });
         }
 
-        Ok(None)
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|s| s.to_ascii_lowercase());
+
+        // Only an extension `sniff_magic` would also recognize is trusted outright;
+        // anything else (absent, or present but unrecognized, e.g. a renamed archive)
+        // falls back to sniffing the magic bytes instead of failing immediately.
+        let recognized_extension = extension
+            .as_deref()
+            .filter(|ext| matches!(*ext, "cbz" | "zip" | "cbt" | "tar" | "cbr" | "rar" | "7z"));
+
+        let backend: Box<dyn ArchiveReader> =
+            match recognized_extension.or_else(|| sniff_magic(&path)) {
+                Some("cbz" | "zip") => Box::new(ZipBackend::open(path)?),
+                Some("cbt" | "tar") => Box::new(EagerBackend::open_tar(path)?),
+                Some("cbr" | "rar") => Box::new(EagerBackend::open_rar(path)?),
+                Some("7z") => Box::new(EagerBackend::open_7z(path)?),
+                _ => {
+                    return Err(Error {
+                        context: path.display().to_string(),
+                        kind: ErrorKind::UnknownExtension(extension),
+                    })
+                }
+            };
+
+        Ok(Self { backend })
+    }
+
+    pub fn next(&mut self) -> Result<Option<RgbImage>> {
+        self.backend.next()
+    }
+}
+
+/// Offset of a tar header's `magic` field, which holds `ustar` (POSIX) or `ustar ` (GNU)
+/// followed by a variant-specific version field, rather than anything near the start of
+/// the file the way zip/rar/7z's magic bytes are.
+const TAR_USTAR_OFFSET: usize = 257;
+
+/// Falls back to sniffing the first few bytes when `path` has no extension or an
+/// unrecognized one, so a renamed or extension-less comic archive still opens.
+fn sniff_magic(path: &Path) -> Option<&'static str> {
+    let mut buf = [0u8; TAR_USTAR_OFFSET + 5];
+    let n = File::open(path).ok()?.read(&mut buf).ok()?;
+    let buf = &buf[..n];
+
+    if buf.starts_with(b"PK\x03\x04") {
+        Some("zip")
+    } else if buf.starts_with(b"Rar!\x1a\x07") {
+        Some("rar")
+    } else if buf.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        Some("7z")
+    } else if buf
+        .get(TAR_USTAR_OFFSET..TAR_USTAR_OFFSET + 5)
+        .is_some_and(|magic| magic == b"ustar")
+    {
+        Some("tar")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    fn write_temp(bytes: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        file
+    }
+
+    #[test]
+    fn sniff_magic_detects_zip() {
+        let file = write_temp(b"PK\x03\x04rest of a fake zip");
+        assert_eq!(Some("zip"), sniff_magic(file.path()));
+    }
+
+    #[test]
+    fn sniff_magic_detects_rar() {
+        let file = write_temp(b"Rar!\x1a\x07\x01\x00rest");
+        assert_eq!(Some("rar"), sniff_magic(file.path()));
+    }
+
+    #[test]
+    fn sniff_magic_detects_7z() {
+        let file = write_temp(b"7z\xbc\xaf\x27\x1crest");
+        assert_eq!(Some("7z"), sniff_magic(file.path()));
+    }
+
+    #[test]
+    fn sniff_magic_detects_posix_tar_by_the_ustar_header_at_offset_257() {
+        let mut bytes = vec![0u8; TAR_USTAR_OFFSET];
+        bytes.extend_from_slice(b"ustar\x0000");
+        let file = write_temp(&bytes);
+        assert_eq!(Some("tar"), sniff_magic(file.path()));
+    }
+
+    #[test]
+    fn sniff_magic_detects_gnu_tar_variant() {
+        let mut bytes = vec![0u8; TAR_USTAR_OFFSET];
+        bytes.extend_from_slice(b"ustar  \x00");
+        let file = write_temp(&bytes);
+        assert_eq!(Some("tar"), sniff_magic(file.path()));
+    }
+
+    #[test]
+    fn sniff_magic_returns_none_for_unrecognized_bytes() {
+        let file = write_temp(b"not an archive");
+        assert_eq!(None, sniff_magic(file.path()));
     }
 }
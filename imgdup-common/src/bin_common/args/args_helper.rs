@@ -40,6 +40,60 @@ macro_rules! args {
                 }));
     };
 
+    // A value with a short flag, in addition to the long one
+    (@arms ($help:literal $short:literal $name:ident: $type:ty = $default:expr; $($rest:tt)*) ->
+     ($($carry:tt)*) ($($sb:tt)*) ($($db:tt)*) ($($se:tt)*)) =>
+    {
+        $crate::args!(@arms ($($rest)*) ->
+               ($($carry)*)
+               ($($sb)*
+                #[arg(short = $short, long, default_value_t = $default, help = $help)]
+                pub $name: $type,)
+               ($($db)*
+               $name: $default,)
+               ($($se)*
+                pub fn $name(mut self, $name: $type) -> Self {
+                    self.$name = $name;
+                    self
+                }));
+    };
+
+    // A value that also falls back to an environment variable
+    (@arms ($help:literal env $env:literal $name:ident: $type:ty = $default:expr; $($rest:tt)*) ->
+     ($($carry:tt)*) ($($sb:tt)*) ($($db:tt)*) ($($se:tt)*)) =>
+    {
+        $crate::args!(@arms ($($rest)*) ->
+               ($($carry)*)
+               ($($sb)*
+                #[arg(long, env = $env, default_value_t = $default, help = $help)]
+                pub $name: $type,)
+               ($($db)*
+               $name: $default,)
+               ($($se)*
+                pub fn $name(mut self, $name: $type) -> Self {
+                    self.$name = $name;
+                    self
+                }));
+    };
+
+    // A ValueEnum, restricted to a fixed set of string variants
+    (@arms ($help:literal $name:ident: enum $type:ty = $default:expr; $($rest:tt)*) ->
+     ($($carry:tt)*) ($($sb:tt)*) ($($db:tt)*) ($($se:tt)*)) =>
+    {
+        $crate::args!(@arms ($($rest)*) ->
+               ($($carry)*)
+               ($($sb)*
+                #[arg(long, value_enum, default_value_t = $default, help = $help)]
+                pub $name: $type,)
+               ($($db)*
+               $name: $default,)
+               ($($se)*
+                pub fn $name(mut self, $name: $type) -> Self {
+                    self.$name = $name;
+                    self
+                }));
+    };
+
     // A list
     (@arms ($help:literal $name:ident: $container:tt<$type:ty> []= $default:expr; $($rest:tt)*) ->
      ($($carry:tt)*) ($($sb:tt)*) ($($db:tt)*) ($($se:tt)*)) =>
@@ -77,11 +131,177 @@ macro_rules! args {
                 }));
     };
 
+    // An optional value with no default, e.g. one that overrides some other field only
+    // when the user actually gives it
+    (@arms ($help:literal $name:ident: Option<$type:ty>; $($rest:tt)*) ->
+     ($($carry:tt)*) ($($sb:tt)*) ($($db:tt)*) ($($se:tt)*)) =>
+    {
+        $crate::args!(@arms ($($rest)*) ->
+               ($($carry)*)
+               ($($sb)*
+                #[arg(long, help = $help)]
+                pub $name: Option<$type>,)
+               ($($db)*
+                $name: None,)
+               ($($se)*
+                pub fn $name(mut self, $name: Option<$type>) -> Self {
+                    self.$name = $name;
+                    self
+                }));
+    };
+
+    // Start here, with config-file layering opted in: also derives a `$partial` struct
+    // with every field made optional and deserializable, plus a `merge` on `$name` that
+    // layers it underneath the CLI-parsed values, see the `@partial` arms below.
+    (config($partial:ident) $(#[$meta:meta])* $name:ident {$($rest:tt)*}) =>
+    {
+        $crate::args!(@arms ($($rest)*) -> ($(#[$meta])* $name) () () ());
+        $crate::args!(@partial $name $partial () () ($($rest)*));
+    };
+
     // Start here
     ($(#[$meta:meta])* $name:ident {$($rest:tt)*}) =>
     {
         $crate::args!(@arms ($($rest)*) -> ($(#[$meta])* $name) () () ());
     };
+
+    // ---- config-file layering: `$partial`'s fields, built alongside `@arms` above ----
+
+    // The end: emit the partial struct and `$name::merge`
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*) ()) =>
+    {
+        /// Every field of [`$name`], made optional so a config file only needs to
+        /// mention the settings it wants to override, see [`$name::merge`].
+        #[derive(Debug, Default, serde::Deserialize)]
+        pub struct $partial {
+            $($pfields)*
+        }
+
+        impl $name {
+            /// Layers `overrides` (typically deserialized from a TOML/JSON config file)
+            /// underneath `self` (typically parsed straight off the command line, so
+            /// already carrying the compiled-in default for anything the user didn't
+            /// pass), field by field, then layers explicit command-line flags back on
+            /// top using `matches` to tell a flag the user actually typed apart from one
+            /// `self` only holds because of its default. Net precedence: CLI flag >
+            /// config file > compiled-in default.
+            ///
+            /// A `#[command(flatten)]` field is taken from `self` or `overrides` whole,
+            /// not deep-merged field by field, since a flattened sub-struct's own fields
+            /// aren't visible as top-level ids in `matches`.
+            pub fn merge(self, matches: &clap::ArgMatches, overrides: $partial) -> Self {
+                Self {
+                    $($merges)*
+                }
+            }
+        }
+    };
+
+    // Flatten: collapse to a whole-or-nothing `Option`, see the note on `merge` above.
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($fname:ident: $ftype:ty; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$ftype>,)
+               ($($merges)* $fname: overrides.$fname.unwrap_or(self.$fname),)
+               ($($rest)*));
+    };
+
+    // A value with a short flag
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($help:literal $short:literal $fname:ident: $ftype:ty = $fdefault:expr; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$ftype>,)
+               ($($merges)* $fname: if matches.value_source(stringify!($fname))
+                   == Some(clap::ValueSource::CommandLine)
+               {
+                   self.$fname
+               } else {
+                   overrides.$fname.unwrap_or(self.$fname)
+               },)
+               ($($rest)*));
+    };
+
+    // A value with an environment-variable fallback
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($help:literal env $env:literal $fname:ident: $ftype:ty = $fdefault:expr; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$ftype>,)
+               ($($merges)* $fname: if matches.value_source(stringify!($fname))
+                   == Some(clap::ValueSource::CommandLine)
+               {
+                   self.$fname
+               } else {
+                   overrides.$fname.unwrap_or(self.$fname)
+               },)
+               ($($rest)*));
+    };
+
+    // A ValueEnum
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($help:literal $fname:ident: enum $ftype:ty = $fdefault:expr; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$ftype>,)
+               ($($merges)* $fname: if matches.value_source(stringify!($fname))
+                   == Some(clap::ValueSource::CommandLine)
+               {
+                   self.$fname
+               } else {
+                   overrides.$fname.unwrap_or(self.$fname)
+               },)
+               ($($rest)*));
+    };
+
+    // A list
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($help:literal $fname:ident: $container:tt<$ftype:ty> []= $fdefault:expr; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$container<$ftype>>,)
+               ($($merges)* $fname: if matches.value_source(stringify!($fname))
+                   == Some(clap::ValueSource::CommandLine)
+               {
+                   self.$fname
+               } else {
+                   overrides.$fname.unwrap_or(self.$fname)
+               },)
+               ($($rest)*));
+    };
+
+    // A normal value
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($help:literal $fname:ident: $ftype:ty = $fdefault:expr; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$ftype>,)
+               ($($merges)* $fname: if matches.value_source(stringify!($fname))
+                   == Some(clap::ValueSource::CommandLine)
+               {
+                   self.$fname
+               } else {
+                   overrides.$fname.unwrap_or(self.$fname)
+               },)
+               ($($rest)*));
+    };
+
+    // An optional value with no default
+    (@partial $name:ident $partial:ident ($($pfields:tt)*) ($($merges:tt)*)
+     ($help:literal $fname:ident: Option<$ftype:ty>; $($rest:tt)*)) =>
+    {
+        $crate::args!(@partial $name $partial
+               ($($pfields)* pub $fname: Option<$ftype>,)
+               ($($merges)* $fname: if matches.value_source(stringify!($fname))
+                   == Some(clap::ValueSource::CommandLine)
+               {
+                   self.$fname
+               } else {
+                   overrides.$fname.or(self.$fname)
+               },)
+               ($($rest)*));
+    };
 }
 
 pub use args;
@@ -94,7 +314,7 @@ mod test {
 
     use super::*;
 
-    #[derive(clap::Args, Debug, PartialEq)]
+    #[derive(clap::Args, Debug, PartialEq, serde::Deserialize)]
     pub struct Manual {
         #[arg(long, default_value_t = 21, help = "hej")]
         yas: i32,
@@ -106,7 +326,20 @@ mod test {
         }
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+    pub enum Mode {
+        Fast,
+        Slow,
+    }
+
+    impl std::default::Default for Mode {
+        fn default() -> Self {
+            Self::Fast
+        }
+    }
+
     args! {
+        config(AutoPartial)
         #[derive(PartialEq)]
         Auto {
             "omg"
@@ -115,6 +348,16 @@ mod test {
             "hej"
             hej: Vec<i32> []= [1, 2];
 
+            "kort"
+            'k' kort: i32 = 5;
+
+            "envy"
+            env "IMGDUP_ARGS_HELPER_TEST_ENVY"
+            envy: i32 = 7;
+
+            "mode"
+            mode: enum Mode = Mode::Fast;
+
             asd: Manual;
         }
     }
@@ -174,4 +417,70 @@ mod test {
             .auto;
         assert_eq!(vec![78, 12], auto.hej);
     }
+
+    #[test]
+    fn short_flag() {
+        let auto = Cmd::try_parse_from([""]).unwrap().auto;
+        assert_eq!(5, auto.kort);
+
+        let auto = Cmd::try_parse_from(["", "--kort", "9"]).unwrap().auto;
+        assert_eq!(9, auto.kort);
+
+        let auto = Cmd::try_parse_from(["", "-k", "9"]).unwrap().auto;
+        assert_eq!(9, auto.kort);
+    }
+
+    #[test]
+    fn env_fallback() {
+        // SAFETY: tests run single-threaded enough in practice here, and the var is
+        // unique to this test.
+        std::env::set_var("IMGDUP_ARGS_HELPER_TEST_ENVY", "42");
+
+        let auto = Cmd::try_parse_from([""]).unwrap().auto;
+        assert_eq!(42, auto.envy);
+
+        let auto = Cmd::try_parse_from(["", "--envy", "13"]).unwrap().auto;
+        assert_eq!(13, auto.envy);
+
+        std::env::remove_var("IMGDUP_ARGS_HELPER_TEST_ENVY");
+        let auto = Cmd::try_parse_from([""]).unwrap().auto;
+        assert_eq!(7, auto.envy);
+    }
+
+    #[test]
+    fn value_enum() {
+        let auto = Auto::default();
+        assert_eq!(Mode::Fast, auto.mode);
+
+        let auto = Cmd::try_parse_from(["", "--mode", "slow"]).unwrap().auto;
+        assert_eq!(Mode::Slow, auto.mode);
+
+        assert!(Cmd::try_parse_from(["", "--mode", "turbo"]).is_err());
+    }
+
+    #[test]
+    fn merge() {
+        use clap::{CommandFactory, FromArgMatches};
+
+        // No CLI flag for `omg`: the config value wins over the compiled-in default.
+        let matches = Cmd::command().get_matches_from([""]);
+        let cli = Cmd::from_arg_matches(&matches).unwrap().auto;
+        let config = AutoPartial {
+            omg: Some(99),
+            ..Default::default()
+        };
+        let merged = cli.merge(&matches, config);
+        assert_eq!(99, merged.omg);
+        assert_eq!(7, merged.envy); // untouched by the config partial
+
+        // An explicit CLI flag for `omg` wins over the config value.
+        let matches = Cmd::command().get_matches_from(["", "--omg", "5"]);
+        let cli = Cmd::from_arg_matches(&matches).unwrap().auto;
+        let config = AutoPartial {
+            omg: Some(99),
+            ..Default::default()
+        };
+        let merged = cli.merge(&matches, config);
+        assert_eq!(5, merged.omg);
+    }
 }
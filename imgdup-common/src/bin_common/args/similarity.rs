@@ -10,16 +10,27 @@ args! {
     Simi {
         "Maximum distance for two images to be considered equal"
         similarity_threshold: Distance = 23;
+
+        "Maximum distance for two images to be considered equal, as a fraction of the \
+         hash's bit width (0.0..=1.0) instead of a raw distance. Overrides \
+         --similarity-threshold when given, so a config stays portable if the hash size \
+         ever changes"
+        tolerance: Option<f64>;
     }
 }
 
 impl Simi {
+    /// The effective maximum distance, resolving [`Self::tolerance`] against
+    /// [`Hamming::BITS`] if given, otherwise [`Self::similarity_threshold`] as-is.
     pub fn threshold(&self) -> Distance {
-        self.similarity_threshold
+        match self.tolerance {
+            Some(fraction) => (fraction.clamp(0.0, 1.0) * Hamming::BITS as f64).round() as Distance,
+            None => self.similarity_threshold,
+        }
     }
 
     pub fn is_within(&self, dist: Distance) -> bool {
-        dist <= self.similarity_threshold
+        dist <= self.threshold()
     }
 
     pub fn is_not_within(&self, dist: Distance) -> bool {
@@ -40,10 +40,30 @@ pub enum Error {
     Validate(String),
     #[error("NullPointerException")]
     NullRef,
+    #[error("checksum mismatch, the file is likely corrupted")]
+    ChecksumMismatch,
+    #[error("not a FileArray file, bad magic bytes")]
+    BadMagic,
+    #[error("unsupported FileArray format version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("file was written on an incompatible architecture (pointer width {file_ptr_width} bytes, endianness {file_endianness:?}), expected pointer width {expected_ptr_width} bytes, endianness {expected_endianness:?}")]
+    ArchMismatch {
+        file_ptr_width: u8,
+        file_endianness: Endianness,
+        expected_ptr_width: u8,
+        expected_endianness: Endianness,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Returned by [`FileArray::recover`], reporting what was rolled back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// How many trailing, uncommitted bytes were discarded.
+    pub discarded_bytes: usize,
+}
+
 pub type FileArraySerializerError =
     CompositeSerializerError<io::Error, AllocScratchError, std::convert::Infallible>;
 
@@ -138,9 +158,105 @@ impl<T> From<Ref<T>> for u64 {
     }
 }
 
-// TODO: somehow save the expected architecture too
-type HEADER = usize;
-const HEADER_SIZE: usize = std::mem::size_of::<HEADER>();
+/// Endianness tag stored in the file header, so that a file written on one architecture
+/// is rejected rather than silently misread on another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    const fn native() -> Self {
+        #[cfg(target_endian = "little")]
+        {
+            Endianness::Little
+        }
+        #[cfg(target_endian = "big")]
+        {
+            Endianness::Big
+        }
+    }
+
+    const fn as_u8(self) -> u8 {
+        match self {
+            Endianness::Little => 0,
+            Endianness::Big => 1,
+        }
+    }
+
+    const fn from_u8(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Endianness::Little),
+            1 => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+}
+
+const MAGIC: [u8; 4] = *b"IMGA";
+const FORMAT_VERSION: u32 = 1;
+
+// Header layout, all integers little-endian regardless of `Endianness`, since the
+// header itself must be readable before we know what wrote it:
+//   0..4   magic bytes
+//   4..8   format version (u32)
+//   8      pointer width in bytes (u8)
+//   9      endianness tag (u8)
+//   10..12 reserved, must be zero
+//   12..20 used length (u64)
+//   20..24 running CRC32 (IEEE, reflected) over the committed byte range
+const HEADER_MAGIC_OFFSET: usize = 0;
+const HEADER_VERSION_OFFSET: usize = 4;
+const HEADER_PTR_WIDTH_OFFSET: usize = 8;
+const HEADER_ENDIANNESS_OFFSET: usize = 9;
+#[allow(dead_code)]
+const HEADER_RESERVED_OFFSET: usize = 10;
+const HEADER_USED_LEN_OFFSET: usize = 12;
+const HEADER_CRC_OFFSET: usize = 20;
+const HEADER_SIZE: usize = 24;
+
+const CRC32_INIT: u32 = 0xFFFF_FFFF;
+
+/// A small, dependency-free table-based implementation of the standard (IEEE 802.3,
+/// reflected) CRC32 polynomial, the same one used by zlib and the `crc32fast` crate.
+/// Used to detect bit-rot or a truncated/garbled append in a `FileArray`'s backing
+/// file, which `rkyv`'s bounds/enum validation alone cannot catch.
+fn crc32_table() -> &'static [u32; 256] {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    })
+}
+
+/// Feeds `bytes` into the running (un-finalized) CRC32 register `crc`.
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let table = crc32_table();
+    bytes
+        .iter()
+        .fold(crc, |crc, &byte| table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// Computes the standard finalized CRC32 of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(CRC32_INIT, bytes) ^ CRC32_INIT
+}
 
 /// A file backed memory area. New values can be appended, but not removed. Zero-copy
 /// deserialization using rkyv. Is not platform-independent since the stored values need
@@ -170,7 +286,7 @@ impl FileArray {
         // TODO: double check open options on the file. Read, write and not append
         let file_len = file.seek(SeekFrom::End(0))?;
         if file_len == 0 {
-            WriteSerializer::new(&mut file).serialize_value(&HEADER_SIZE)?;
+            file.write_all(&Self::new_header_bytes())?;
         }
 
         // TODO: how to handle the signal that gets sent when the mapped file becomes
@@ -180,13 +296,105 @@ impl FileArray {
         let total_len = mmap.len();
         assert!(total_len >= HEADER_SIZE);
 
+        Self::validate_header(&mmap)?;
+
         let used_len = Self::len_raw(&mmap);
         file.seek(SeekFrom::Start(
             used_len.try_into().expect("expecting 64 bit arch"),
         ))?;
         let seri = Self::new_serializer(file, used_len);
 
-        Ok(Self { mmap, seri })
+        let mut this = Self { mmap, seri };
+        this.recover()?;
+        Ok(this)
+    }
+
+    /// Discards any trailing bytes left dangling past the committed length, e.g. from a
+    /// process that died after `reserve_internal` grew the file but before the next
+    /// `add` published a new length, or after flushing new data but before `set_len`
+    /// published it. The length header is the single commit point: everything up to
+    /// `len()` is trusted, everything after it is rolled back. Run automatically by
+    /// `new_opened`, but can be called again to re-check after external tampering.
+    pub fn recover(&mut self) -> Result<RecoveryReport> {
+        let before = self.mmap.len();
+        let used_len = self.len();
+        assert!(used_len <= before, "used length must never exceed the file length");
+
+        self.truncate()?;
+
+        Ok(RecoveryReport {
+            discarded_bytes: before - self.mmap.len(),
+        })
+    }
+
+    fn new_header_bytes() -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[HEADER_MAGIC_OFFSET..HEADER_MAGIC_OFFSET + 4].copy_from_slice(&MAGIC);
+        header[HEADER_VERSION_OFFSET..HEADER_VERSION_OFFSET + 4]
+            .copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header[HEADER_PTR_WIDTH_OFFSET] = std::mem::size_of::<usize>() as u8;
+        header[HEADER_ENDIANNESS_OFFSET] = Endianness::native().as_u8();
+        header[HEADER_USED_LEN_OFFSET..HEADER_USED_LEN_OFFSET + 8]
+            .copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        header[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4]
+            .copy_from_slice(&CRC32_INIT.to_le_bytes());
+        header
+    }
+
+    fn crc_raw(slice: &[u8]) -> u32 {
+        u32::from_le_bytes(
+            slice[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4]
+                .try_into()
+                .expect("slice is 4 bytes"),
+        )
+    }
+
+    fn set_crc_raw(&mut self, crc: u32) {
+        self.mmap[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Recomputes the CRC32 over the whole committed byte range (excluding the header
+    /// itself) and compares it against the checksum stored in the header, returning
+    /// `Err(Error::ChecksumMismatch)` if the file has been corrupted.
+    pub fn verify(&self) -> Result<()> {
+        let committed = &self.mmap[HEADER_SIZE..self.len()];
+        let expected = crc32(committed) ^ CRC32_INIT;
+        if Self::crc_raw(&self.mmap) != expected {
+            return Err(Error::ChecksumMismatch);
+        }
+        Ok(())
+    }
+
+    fn validate_header(slice: &[u8]) -> Result<()> {
+        let magic = &slice[HEADER_MAGIC_OFFSET..HEADER_MAGIC_OFFSET + 4];
+        if magic != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        let version = u32::from_le_bytes(
+            slice[HEADER_VERSION_OFFSET..HEADER_VERSION_OFFSET + 4]
+                .try_into()
+                .expect("slice is 4 bytes"),
+        );
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let file_ptr_width = slice[HEADER_PTR_WIDTH_OFFSET];
+        let file_endianness = Endianness::from_u8(slice[HEADER_ENDIANNESS_OFFSET])
+            .ok_or(Error::UnsupportedVersion(version))?;
+        let expected_ptr_width = std::mem::size_of::<usize>() as u8;
+        let expected_endianness = Endianness::native();
+        if file_ptr_width != expected_ptr_width || file_endianness != expected_endianness {
+            return Err(Error::ArchMismatch {
+                file_ptr_width,
+                file_endianness,
+                expected_ptr_width,
+                expected_endianness,
+            });
+        }
+
+        Ok(())
     }
 
     fn new_serializer(file: File, used_len: usize) -> FileArraySerializer {
@@ -220,6 +428,8 @@ impl FileArray {
         self.len() <= HEADER_SIZE
     }
 
+    /// NOTE: the durability barrier for an `add` is `sync_to_disk` followed by the
+    /// length header write landing on disk, in that order; see `recover`.
     pub fn sync_to_disk(&self) -> Result<()> {
         // TODO: fsync on the file instead? Is there any difference?
         Ok(self.mmap.flush()?)
@@ -230,21 +440,18 @@ impl FileArray {
     }
 
     fn len_raw(slice: &[u8]) -> usize {
-        // TODO: just use a pointer?
-        // TODO: use unsafe variants without checkbytes
-        Self::get_raw::<HEADER>(slice, Ref::new_usize(HEADER_SIZE))
-            .expect("should always exist")
-            .to_owned()
-            .try_into()
-            .expect("expecting 64 bit arch")
+        let used_len = u64::from_le_bytes(
+            slice[HEADER_USED_LEN_OFFSET..HEADER_USED_LEN_OFFSET + 8]
+                .try_into()
+                .expect("slice is 8 bytes"),
+        );
+        used_len.try_into().expect("expecting 64 bit arch")
     }
 
     fn set_len(&mut self, new_len: usize) {
-        *self
-            // TODO: use unsafe variants without checkbytes
-            .get_mut::<HEADER>(Ref::new_usize(HEADER_SIZE))
-            .expect("should always exist") =
-            new_len.try_into().expect("expecting 64 bit");
+        let new_len_u64: u64 = new_len.try_into().expect("expecting 64 bit arch");
+        self.mmap[HEADER_USED_LEN_OFFSET..HEADER_USED_LEN_OFFSET + 8]
+            .copy_from_slice(&new_len_u64.to_le_bytes());
     }
 
     /// Ref to the first element of type `T`, whose serialized size must be
@@ -336,6 +543,8 @@ impl FileArray {
         B: Borrow<S>,
         S: Serialize<FileArraySerializer>,
     {
+        let old_len = self.len();
+
         let refs_res = || -> Result<_> {
             let mut refs: Vec<Ref<S>> = Vec::new();
 
@@ -359,7 +568,14 @@ impl FileArray {
         };
 
         if let Some(&last_ref) = refs.last() {
-            self.set_len(last_ref.into());
+            let new_len: usize = last_ref.into();
+            if new_len > self.mmap.len() {
+                let growth: usize = std::cmp::max(1 << 13 /*8K*/, new_len);
+                self.reserve_internal(growth, new_len)?;
+            }
+            let new_crc = crc32_update(Self::crc_raw(&self.mmap), &self.mmap[old_len..new_len]);
+            self.set_crc_raw(new_crc);
+            self.set_len(new_len);
         }
 
         if self.len() > self.mmap.len() {
@@ -556,13 +772,96 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn recover_discards_dangling_tail() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        let committed_len = arr.len();
+
+        // Simulate a crash right after `reserve_internal` grew the file, but before any
+        // more data was written and committed via `set_len`.
+        arr.reserve(1 << 13)?;
+        assert!(arr.mmap.len() > committed_len);
+
+        let report = arr.recover()?;
+        assert_eq!(committed_len, arr.len());
+        assert_eq!(arr.mmap.len(), arr.len());
+        assert!(report.discarded_bytes > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_ok_after_adds() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        arr.add([2i32, 3, 4])?;
+        arr.verify()?;
+        Ok(())
+    }
+
+    #[test]
+    fn verify_detects_corruption() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let the_ref = arr.add_one(&123i32)?;
+        arr.verify()?;
+
+        *arr.get_mut::<i32>(the_ref)? = 0;
+
+        assert!(matches!(arr.verify(), Err(Error::ChecksumMismatch)));
+        Ok(())
+    }
+
     #[test]
     #[cfg(target_arch = "x86_64")]
     fn alignment_x86_64() {
-        assert_eq!(Ref::new_u64(16), FileArray::ref_to_first::<u64>());
-        assert_eq!(Ref::new_u64(16), FileArray::ref_to_first::<usize>());
-        assert_eq!(Ref::new_u64(9), FileArray::ref_to_first::<u8>());
-        assert_eq!(Ref::new_u64(32), FileArray::ref_to_first::<u128>());
-        assert_eq!(Ref::new_u64(32), FileArray::ref_to_first::<MyStuff>());
+        // NOTE: HEADER_SIZE grew from 8 to 24 bytes when the self-describing
+        // magic/version/arch/crc preamble was added, shifting all of these offsets.
+        assert_eq!(Ref::new_u64(32), FileArray::ref_to_first::<u64>());
+        assert_eq!(Ref::new_u64(32), FileArray::ref_to_first::<usize>());
+        assert_eq!(Ref::new_u64(25), FileArray::ref_to_first::<u8>());
+        assert_eq!(Ref::new_u64(48), FileArray::ref_to_first::<u128>());
+        assert_eq!(Ref::new_u64(48), FileArray::ref_to_first::<MyStuff>());
+    }
+
+    #[test]
+    fn rejects_bad_magic() -> Result<()> {
+        let mut tmpf = tempfile::tempfile()?;
+        tmpf.write_all(&[0u8; HEADER_SIZE])?;
+        tmpf.seek(SeekFrom::Start(0))?;
+        assert!(matches!(
+            FileArray::new_opened(tmpf),
+            Err(Error::BadMagic)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_version() -> Result<()> {
+        let mut tmpf = tempfile::tempfile()?;
+        let mut header = FileArray::new_header_bytes();
+        header[HEADER_VERSION_OFFSET..HEADER_VERSION_OFFSET + 4]
+            .copy_from_slice(&999u32.to_le_bytes());
+        tmpf.write_all(&header)?;
+        tmpf.seek(SeekFrom::Start(0))?;
+        assert!(matches!(
+            FileArray::new_opened(tmpf),
+            Err(Error::UnsupportedVersion(999))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_arch_mismatch() -> Result<()> {
+        let mut tmpf = tempfile::tempfile()?;
+        let mut header = FileArray::new_header_bytes();
+        header[HEADER_PTR_WIDTH_OFFSET] = 4; // pretend it was written on a 32-bit arch
+        tmpf.write_all(&header)?;
+        tmpf.seek(SeekFrom::Start(0))?;
+        assert!(matches!(
+            FileArray::new_opened(tmpf),
+            Err(Error::ArchMismatch { .. })
+        ));
+        Ok(())
     }
 }
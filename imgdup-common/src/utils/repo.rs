@@ -1,7 +1,8 @@
 use std::{
     ffi::OsString,
     fs::{self, File},
-    io::{BufReader, BufWriter, Write},
+    io::{BufReader, BufWriter, Seek, SeekFrom, Write},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
 };
 
@@ -14,14 +15,106 @@ use super::fsutils;
 
 const ENTRY_PADDING: usize = 4;
 
+/// Parses `bytes` as a decimal `u32`, same as `str::parse` but without requiring the
+/// whole filename it came from to be valid UTF-8: only a run of ASCII digits ever needs
+/// decoding, which is always trivially valid UTF-8.
+fn parse_decimal(bytes: &[u8]) -> eyre::Result<u32> {
+    if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_digit) {
+        eyre::bail!("not an ASCII decimal number: {bytes:?}");
+    }
+    std::str::from_utf8(bytes)
+        .expect("only ASCII digits, checked above")
+        .parse()
+        .wrap_err("not a number")
+}
+
+/// Name of the docket sidecar file written into every repo/entry directory, letting
+/// `Repo::new`/`Entry::open` skip the `find_next_entry` directory scan on the common
+/// path.
+const DOCKET_FILENAME: &str = ".imgdup-docket";
+
+/// The persisted state needed to hand out entry numbers without rescanning the
+/// directory. `uuid` identifies this particular directory incarnation (so a docket
+/// copied or left behind by a different directory can be told apart), and `entry_count`
+/// is purely informational bookkeeping.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Docket {
+    uuid: uuid::Uuid,
+    next_entry: u32,
+    entry_count: u32,
+}
+
+impl Docket {
+    fn path(dir: &Path) -> PathBuf {
+        dir.join(DOCKET_FILENAME)
+    }
+
+    fn read(dir: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(Self::path(dir)).ok()?;
+        ron::from_str(&contents).ok()
+    }
+
+    /// Atomic temp-file + rename, so a crash never leaves a half-written docket behind.
+    fn write(&self, dir: &Path) -> eyre::Result<()> {
+        let tmp_path = dir.join(format!(".{DOCKET_FILENAME}.tmp"));
+        let contents = ron::to_string(self).wrap_err("failed to serialize the docket")?;
+        fs::write(&tmp_path, contents).wrap_err("failed to write the docket tempfile")?;
+        fs::rename(&tmp_path, Self::path(dir))
+            .wrap_err("failed to move the docket into place")?;
+        Ok(())
+    }
+}
+
+/// Loads the `next_entry` counter for `dir`, trusting an on-disk [`Docket`] when
+/// present and passing a cheap spot check, `exists`, otherwise falling back to a full
+/// `find_next_entry` rescan. Always leaves a fresh, consistent docket behind.
+///
+/// Critical invariant: never hand out a number lower than any existing entry, so
+/// `exists(docket.next_entry)` returning `true` (meaning the docket is stale, something
+/// beyond what it remembers already exists) must always force the rescan.
+fn load_docket<F, E>(
+    dir: &Path,
+    num_extract: F,
+    exists: E,
+) -> eyre::Result<(u32, uuid::Uuid, u32)>
+where
+    F: Fn(&[u8]) -> eyre::Result<u32>,
+    E: Fn(u32) -> bool,
+{
+    if let Some(docket) = Docket::read(dir) {
+        if !exists(docket.next_entry) {
+            return Ok((docket.next_entry, docket.uuid, docket.entry_count));
+        }
+        log::warn!(
+            "docket at {:?} looks stale (an entry #{} already exists), rescanning",
+            dir,
+            docket.next_entry
+        );
+    }
+
+    let next_entry = find_next_entry(dir, num_extract)?;
+    let uuid = uuid::Uuid::new_v4();
+    let docket = Docket {
+        uuid,
+        next_entry,
+        entry_count: next_entry,
+    };
+    docket.write(dir)?;
+    Ok((next_entry, uuid, docket.entry_count))
+}
+
 pub struct Repo {
     path: PathBuf,
     next_entry: u32,
+    uuid: uuid::Uuid,
+    entry_count: u32,
 }
 
 pub struct Entry {
     path: PathBuf,
     next_entry: u32,
+    uuid: uuid::Uuid,
+    entry_count: u32,
 }
 
 pub struct LazyEntry {
@@ -31,24 +124,65 @@ pub struct LazyEntry {
 impl Repo {
     pub fn new(path: impl Into<PathBuf>) -> eyre::Result<Self> {
         let path = path.into();
-        let next_entry = find_next_entry(&path, |s| s.parse().wrap_err("not a number"))
-            .wrap_err("failed to get the next entry")?;
-        Ok(Self { path, next_entry })
+        let (next_entry, uuid, entry_count) = load_docket(
+            &path,
+            parse_decimal,
+            |num| repo_entry_path(&path, num).is_dir(),
+        )
+        .wrap_err("failed to get the next entry")?;
+        Ok(Self {
+            path,
+            next_entry,
+            uuid,
+            entry_count,
+        })
     }
 
     pub fn new_entry(&mut self) -> eyre::Result<Entry> {
-        let p = ENTRY_PADDING;
-        let path = self.path.join(format!("{:0p$}", self.next_entry));
+        let path = repo_entry_path(&self.path, self.next_entry);
         fs::create_dir(&path).wrap_err("could not create the dir")?;
         self.next_entry += 1;
+        self.entry_count += 1;
+        Docket {
+            uuid: self.uuid,
+            next_entry: self.next_entry,
+            entry_count: self.entry_count,
+        }
+        .write(&self.path)
+        .wrap_err("failed to persist the docket")?;
         Entry::open(path).wrap_err("failed to open dir as an entry")
     }
 
+    /// Rescans the directory and reports whether the currently loaded `next_entry`
+    /// still matches, without touching the docket. See [`Repo::repair`] to fix it up.
+    pub fn verify(&self) -> eyre::Result<bool> {
+        let next_entry = find_next_entry(&self.path, parse_decimal)
+            .wrap_err("failed to rescan the next entry")?;
+        Ok(next_entry == self.next_entry)
+    }
+
+    /// Rescans the directory from scratch and rewrites the docket, for when it is
+    /// suspected to have gone out of sync some other way than what the cheap spot check
+    /// in [`Repo::new`] catches.
+    pub fn repair(&mut self) -> eyre::Result<()> {
+        let next_entry = find_next_entry(&self.path, parse_decimal)
+            .wrap_err("failed to rescan the next entry")?;
+        self.next_entry = next_entry;
+        self.entry_count = next_entry;
+        Docket {
+            uuid: self.uuid,
+            next_entry: self.next_entry,
+            entry_count: self.entry_count,
+        }
+        .write(&self.path)
+        .wrap_err("failed to persist the repaired docket")?;
+        Ok(())
+    }
+
     pub fn entries(&self) -> eyre::Result<Vec<Entry>> {
-        let p = ENTRY_PADDING;
         let mut entries = Vec::new();
         for num in 0..self.next_entry {
-            let path = self.path.join(format!("{:0p$}", num));
+            let path = repo_entry_path(&self.path, num);
             if path.is_dir() {
                 entries.push(Entry::open(&path).wrap_err_with(|| {
                     format!("failed to open the entry at: {}", path.display())
@@ -60,26 +194,27 @@ impl Repo {
 }
 
 impl Entry {
+    fn parse_entry_num(name: &[u8]) -> eyre::Result<u32> {
+        if name.len() < ENTRY_PADDING {
+            eyre::bail!("path name is too short");
+        }
+        parse_decimal(&name[..ENTRY_PADDING]).wrap_err("the parse failed")
+    }
+
     pub fn open(dir: impl Into<PathBuf>) -> eyre::Result<Self> {
         let dir = dir.into();
-        let next_entry = find_next_entry(&dir, |s| {
-            if s.len() < ENTRY_PADDING {
-                eyre::bail!("path name is too short");
-            }
-            let num: u32 = s
-                .get(..ENTRY_PADDING)
-                .ok_or_else(|| {
-                    eyre::eyre!("the first few characters don't seem to be numbers")
-                })?
-                .parse()
-                .wrap_err("the parse failed")?;
-            Ok(num)
-        })
+        let (next_entry, uuid, entry_count) = load_docket(
+            &dir,
+            Self::parse_entry_num,
+            |num| entry_child_exists(&dir, num),
+        )
         .wrap_err("failed to get the next entry")?;
 
         Ok(Self {
             path: dir,
             next_entry,
+            uuid,
+            entry_count,
         })
     }
 
@@ -101,35 +236,117 @@ impl Entry {
         assert!(fsutils::is_basename(name));
         let sub_path = self.next_path(name);
         fs::create_dir(&sub_path).wrap_err("could not create the dir")?;
-        Ok(Self {
-            path: sub_path,
-            next_entry: 0,
-        })
+        self.entry_count += 1;
+        Docket {
+            uuid: self.uuid,
+            next_entry: self.next_entry,
+            entry_count: self.entry_count,
+        }
+        .write(&self.path)
+        .wrap_err("failed to persist the docket")?;
+        Entry::open(sub_path).wrap_err("failed to open the new sub dir as an entry")
     }
 
+    /// Rescans the directory and reports whether the currently loaded `next_entry`
+    /// still matches, without touching the docket. See [`Entry::repair`] to fix it up.
+    pub fn verify(&self) -> eyre::Result<bool> {
+        let next_entry = find_next_entry(&self.path, Self::parse_entry_num)
+            .wrap_err("failed to rescan the next entry")?;
+        Ok(next_entry == self.next_entry)
+    }
+
+    /// Rescans the directory from scratch and rewrites the docket, for when it is
+    /// suspected to have gone out of sync some other way than what the cheap spot check
+    /// in [`Entry::open`] catches.
+    pub fn repair(&mut self) -> eyre::Result<()> {
+        let next_entry = find_next_entry(&self.path, Self::parse_entry_num)
+            .wrap_err("failed to rescan the next entry")?;
+        self.next_entry = next_entry;
+        self.entry_count = next_entry;
+        Docket {
+            uuid: self.uuid,
+            next_entry: self.next_entry,
+            entry_count: self.entry_count,
+        }
+        .write(&self.path)
+        .wrap_err("failed to persist the repaired docket")?;
+        Ok(())
+    }
+
+    /// Streams `writer`'s output into a temp file in this entry directory, hashing the
+    /// content as it is written, then atomically `rename`s it into place, so a crash
+    /// mid-write never leaves a truncated file behind. If a sibling file with the same
+    /// logical `name` already exists and hashes the same, the temp file is discarded and
+    /// the existing one is kept instead, so idempotent re-runs don't churn
+    /// byte-identical jpgs/text files. Returns the content hash either way, so callers
+    /// can record or dedup on it.
     pub fn create_file<F, E>(
         &mut self,
         name: impl AsRef<Path>,
         writer: F,
-    ) -> eyre::Result<()>
+    ) -> eyre::Result<blake3::Hash>
     where
-        F: FnOnce(&mut BufWriter<File>) -> std::result::Result<(), E>,
+        F: FnOnce(&mut BufWriter<HashingWriter<File>>) -> std::result::Result<(), E>,
         std::result::Result<(), E>: eyre::WrapErr<(), E>,
     {
         let name = name.as_ref();
         // TODO: should probably be an eyre::ensure?
         assert!(fsutils::is_basename(name));
-        let file_path = self.next_path(name);
+
+        let final_path = self.next_path(name);
+        let tmp_path = final_path.with_file_name(format!(
+            ".{}.tmp",
+            final_path.file_name().expect("has a filename").to_string_lossy()
+        ));
+
         let file = fs::OpenOptions::new()
             .write(true)
             .create_new(true)
-            .open(file_path)
-            .wrap_err("could not create file")?;
+            .open(&tmp_path)
+            .wrap_err("could not create the temp file")?;
 
-        let mut buf = BufWriter::new(file);
+        let mut buf = BufWriter::new(HashingWriter {
+            inner: file,
+            hasher: blake3::Hasher::new(),
+        });
         writer(&mut buf).wrap_err("the writer failed")?;
-        buf.flush().wrap_err("failed to flush")?;
-        Ok(())
+        let hashing_writer = buf
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .wrap_err("failed to flush the temp file")?;
+        let hash = hashing_writer.hasher.finalize();
+
+        if let Some(existing) = self.find_entry_file(name.as_os_str().as_bytes())? {
+            if hash_file(&existing).wrap_err("failed to hash the existing file")? == hash {
+                fs::remove_file(&tmp_path)
+                    .wrap_err("failed to remove the now-redundant temp file")?;
+                return Ok(hash);
+            }
+        }
+
+        fs::rename(&tmp_path, &final_path).wrap_err("failed to move the file into place")?;
+        Ok(hash)
+    }
+
+    /// Finds a file in this entry directory whose name starts with a `{:04}_` numbering
+    /// prefix and ends with the logical `name`. This is a suffix match, so a file named
+    /// e.g. `0003_other_name` also matches `name`; the first match found is returned.
+    fn find_entry_file(&self, name: &[u8]) -> eyre::Result<Option<PathBuf>> {
+        let all_files: Vec<_> =
+            fsutils::all_files([&self.path]).wrap_err("failed to list myself")?;
+
+        for file in all_files {
+            let filename = file.file_name().expect("will contain a filename").as_bytes();
+
+            if filename.ends_with(name)
+                && filename.len() >= ENTRY_PADDING + 1
+                && filename[..ENTRY_PADDING].iter().all(u8::is_ascii_digit)
+                && filename[ENTRY_PADDING] == b'_'
+            {
+                return Ok(Some(file));
+            }
+        }
+        Ok(None)
     }
 
     /// Open some file with name `name` and apply the fallible function on it which
@@ -142,40 +359,7 @@ impl Entry {
         let name = name.as_ref();
         assert!(fsutils::is_basename(name));
 
-        // TODO: extract function
-        let target_file = {
-            // TODO: I don't like that this must be UTF-8, but its not possible, or at least
-            // really annoying, to do string operations on `Path` :( Probably use
-            // https://doc.rust-lang.org/std/os/unix/ffi/trait.OsStrExt.html#tymethod.as_bytes
-            // and do substring searches and stuff on byte slices.
-            let name = name.to_str().expect("should be UTF-8");
-
-            let all_files: Vec<_> =
-                fsutils::all_files([&self.path]).wrap_err("failed to list myself")?;
-
-            let mut target_file = None;
-            for file in all_files {
-                let filename = file
-                    .file_name()
-                    .expect("will contain a filename")
-                    .to_str()
-                    .ok_or_else(|| eyre::eyre!("path name is not UTF-8: {:?}", file))?;
-
-                if filename.ends_with(name)
-                    && filename.len() >= ENTRY_PADDING + 1
-                    && filename[..ENTRY_PADDING]
-                        .chars()
-                        .all(|c| c.is_ascii_digit())
-                    && &filename[ENTRY_PADDING..ENTRY_PADDING + 1] == "_"
-                {
-                    target_file = Some(file);
-                    break;
-                }
-            }
-            target_file
-        };
-
-        let Some(target_file) = target_file else {
+        let Some(target_file) = self.find_entry_file(name.as_os_str().as_bytes())? else {
             eyre::bail!(
                 "Could not find a file with name {name:?} in entry {:?}",
                 self.path
@@ -183,7 +367,7 @@ impl Entry {
         };
 
         let mut buf = BufReader::new(
-            File::open(target_file)
+            File::open(&target_file)
                 .wrap_err_with(|| "failed to open {target_file:?} for reading")?,
         );
         let t = reader(&mut buf)
@@ -238,7 +422,8 @@ impl Entry {
             image
                 .write_to(w, ImageOutputFormat::Jpeg(95))
                 .wrap_err("image failed to write")
-        })
+        })?;
+        Ok(())
     }
 
     pub fn create_text_file(
@@ -252,10 +437,46 @@ impl Entry {
         self.create_file(txt_name, |w| {
             w.write_all(contents.as_ref().as_bytes())
                 .wrap_err("failed to write string")
-        })
+        })?;
+        Ok(())
     }
 }
 
+/// A [`Write`] (and, if `W` is, [`Seek`]) passthrough that feeds every byte written
+/// through it into a running [`blake3::Hasher`], so [`Entry::create_file`] can hash the
+/// content as it streams it to disk instead of making a second pass over the file.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for HashingWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Hashes the full contents of the file at `path`, the same way [`Entry::create_file`]
+/// hashes freshly-written content, so the two can be compared.
+fn hash_file(path: &Path) -> eyre::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = File::open(path).wrap_err("failed to open for hashing")?;
+    std::io::copy(&mut file, &mut hasher).wrap_err("failed to read the file")?;
+    Ok(hasher.finalize())
+}
+
 impl LazyEntry {
     pub fn new() -> Self {
         Self { inner: None }
@@ -280,23 +501,48 @@ impl LazyEntry {
 
 fn find_next_entry<F>(dir: impl AsRef<Path>, num_extract: F) -> eyre::Result<u32>
 where
-    F: Fn(&str) -> eyre::Result<u32>,
+    F: Fn(&[u8]) -> eyre::Result<u32>,
 {
     let all_files: Vec<_> =
         fsutils::all_files([dir]).wrap_err("failed to list the dir")?;
     let next_entry = all_files
         .into_iter()
         .try_fold(None, |maximum, path| -> eyre::Result<Option<u32>> {
-            let path = path
-                .file_name()
-                .expect("will contain a filename")
-                .to_str()
-                .ok_or_else(|| eyre::eyre!("path name is not UTF-8: {:?}", path))?;
+            let name = path.file_name().expect("will contain a filename").as_bytes();
+
+            // NOTE: dotfiles, like the docket sidecar itself, are never entries.
+            if name.starts_with(b".") {
+                return Ok(maximum);
+            }
+
             let num: u32 =
-                num_extract(path).wrap_err("failed to parse the path to a number")?;
+                num_extract(name).wrap_err("failed to parse the path to a number")?;
             Ok(maximum.map(|m| std::cmp::max(m, num)).or(Some(num)))
         })?
         .map(|max| max + 1)
         .unwrap_or(0);
     Ok(next_entry)
 }
+
+/// The directory path a `Repo`'s numbered entry `num` lives at.
+fn repo_entry_path(repo_dir: &Path, num: u32) -> PathBuf {
+    let p = ENTRY_PADDING;
+    repo_dir.join(format!("{:0p$}", num))
+}
+
+/// Cheap spot check for [`Entry::open`]: does anything in `dir` already carry the
+/// `{num:04}_` prefix? Unlike `find_next_entry`, this does not parse every filename,
+/// just looks for one specific prefix.
+fn entry_child_exists(dir: &Path, num: u32) -> bool {
+    let p = ENTRY_PADDING;
+    let prefix = format!("{:0p$}_", num);
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return false;
+    };
+    read_dir.filter_map(|e| e.ok()).any(|entry| {
+        entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(&prefix))
+    })
+}
@@ -4,6 +4,7 @@ use clap::Parser;
 use color_eyre::eyre::{self, Context};
 use image::{DynamicImage, GenericImageView};
 use imgdup::bin_common::args::remove_borders::RemoveBordersCli;
+use imgdup::utils::image_decode;
 
 #[derive(Parser)]
 #[command()]
@@ -30,9 +31,8 @@ fn main() -> eyre::Result<()> {
 
     let border_args = cli.border_args.to_args();
 
-    let input = image::open(&cli.input)
-        .wrap_err_with(|| format!("Could not open {:?}", cli.input))?
-        .to_rgb8();
+    let input = image_decode::open_image(&cli.input)
+        .wrap_err_with(|| format!("Could not open {:?}", cli.input))?;
     println!("before:  {:?}", input.bounds());
 
     let output: DynamicImage = if cli.maskify {
@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use clap::Parser;
 use error_stack::{IntoReport, ResultExt};
-use imgdup::frame_extractor::FrameExtractor;
+use imgdup::frame_extractor::{FrameExtractor, FrameExtractorConf};
 
 #[derive(Parser)]
 #[command()]
@@ -40,7 +40,8 @@ fn main() -> error_stack::Result<(), ExtError> {
             .change_context(ExtError)?;
     }
 
-    let mut extractor = FrameExtractor::new(cli.videofile).change_context(ExtError)?;
+    let mut extractor = FrameExtractor::new(cli.videofile, FrameExtractorConf::default())
+        .change_context(ExtError)?;
     extractor
         .seek_forward(cli.offset.into())
         .change_context(ExtError)?;
@@ -3,7 +3,7 @@ use std::path::{Path, PathBuf};
 use clap::Parser;
 use image::RgbImage;
 use imgdup::{
-    frame_extractor::FrameExtractor,
+    frame_extractor::{FrameExtractor, FrameExtractorConf},
     imghash::{self, hamming::Hamming},
     imgutils,
 };
@@ -48,7 +48,7 @@ fn main() -> anyhow::Result<()> {
         _ => (),
     }
 
-    let mut extractor = FrameExtractor::new(cli.videofile)?;
+    let mut extractor = FrameExtractor::new(cli.videofile, FrameExtractorConf::default())?;
     extractor.seek_forward(cli.offset.into())?;
     let (_, frame) = extractor
         .next()?
@@ -4,7 +4,7 @@ use clap::Parser;
 use error_stack::{IntoReport, ResultExt};
 use image::RgbImage;
 use imgdup::{
-    frame_extractor::FrameExtractor,
+    frame_extractor::{FrameExtractor, FrameExtractorConf},
     imghash::{self, hamming::Hamming},
     imgutils,
 };
@@ -59,7 +59,9 @@ fn main() -> error_stack::Result<(), VidError> {
         _ => (),
     }
 
-    let mut extractor = FrameExtractor::new(cli.videofile).change_context(VidError)?;
+    let mut extractor =
+        FrameExtractor::new(cli.videofile, FrameExtractorConf::default())
+            .change_context(VidError)?;
     extractor
         .seek_forward(cli.offset.into())
         .change_context(VidError)?;
@@ -83,7 +85,6 @@ fn main() -> error_stack::Result<(), VidError> {
 
     if let Some(times) = cli.consecutive_test {
         consecutive_test(
-            &frame,
             frame_hash,
             times,
             cli.step,
@@ -92,6 +93,10 @@ fn main() -> error_stack::Result<(), VidError> {
         )?;
     }
 
+    if cli.flip_test {
+        flip_test(&frame, frame_hash, cli.outdir.as_ref())?;
+    }
+
     Ok(())
 }
 
@@ -136,21 +141,49 @@ fn quality_test(
 }
 
 fn consecutive_test(
-    _frame: &RgbImage,
-    _frame_hash: Hamming,
+    frame_hash: Hamming,
     times: u32,
-    _step: Option<humantime::Duration>,
-    _outdir: Option<&PathBuf>,
-    _extractor: &mut FrameExtractor,
+    step: Option<humantime::Duration>,
+    outdir: Option<&PathBuf>,
+    extractor: &mut FrameExtractor,
 ) -> error_stack::Result<(), VidError> {
-    for _i in 1..=times {
-        todo!(
-            "what does this really say? It really depends on where in the video this is"
-        );
+    for i in 1..=times {
+        if let Some(step) = step {
+            extractor.seek_forward(step.into()).change_context(VidError)?;
+        }
+
+        let Some((_, frame)) = extractor.next().change_context(VidError)? else {
+            println!("Ran out of frames after {} consecutive frame(s)", i - 1);
+            break;
+        };
+
+        let filename = format!("consecutive_{i}.jpg");
+        write_image(outdir, filename, &frame)?;
+
+        let hash = imghash::hash(&frame);
+        let dist = frame_hash.distance_to(hash);
+
+        println!("The distance to consecutive frame {i} is {dist} ({hash})");
     }
     Ok(())
 }
 
+fn flip_test(
+    frame: &RgbImage,
+    frame_hash: Hamming,
+    outdir: Option<&PathBuf>,
+) -> error_stack::Result<(), VidError> {
+    let flipped = imgutils::flip_horizontal(frame);
+    write_image(outdir, "flip.jpg", &flipped)?;
+
+    let flipped_hash = imghash::hash(&flipped);
+    let dist = frame_hash.distance_to(flipped_hash);
+
+    println!("The distance to the flipped frame is {dist} ({flipped_hash})");
+
+    Ok(())
+}
+
 fn write_image<P1, P2>(
     outdir: Option<P1>,
     filename: P2,
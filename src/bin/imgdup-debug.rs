@@ -1,6 +1,8 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    io::Write,
     path::{Path, PathBuf},
+    sync::Mutex,
 };
 
 use clap::Parser;
@@ -15,13 +17,16 @@ use imgdup::{
         init::{init_eyre, init_logger},
     },
     bktree::{mmap::bktree::BKTree, source_types::video_source::VidSrc},
-    frame_extractor::frame_extractor::FrameExtractor,
+    frame_extractor::frame_extractor::{FrameExtractor, FrameExtractorConf},
     imghash::hamming::Hamming,
     utils::{
         repo::{Entry, Repo},
+        resize::{self, Format},
         simple_path::{SimplePath, SimplePathBuf},
     },
 };
+use rayon::prelude::*;
+use tar::{Builder, EntryType, Header};
 
 #[derive(Parser, Debug)]
 #[command()]
@@ -48,6 +53,11 @@ struct Cli {
     /// Debug all entries instead of just the current one
     #[arg(long, short = 'A', default_value_t = false)]
     all: bool,
+
+    /// Emit a single `.tar` bundle of every collision's artifacts per entry instead of
+    /// a directory of small files
+    #[arg(long, default_value_t = false)]
+    bundle: bool,
 }
 
 #[derive(Clone)]
@@ -68,11 +78,11 @@ struct PreprocImage {
 
 fn main() -> eyre::Result<()> {
     init_eyre()?;
-    init_logger(None)?;
+    init_logger(None, &[])?;
     let cli = Cli::parse();
 
     let simi_args = cli.simi_args.to_args();
-    let preproc_args = cli.preproc_args.to_args();
+    let preproc_args = cli.preproc_args.to_args()?;
 
     let root = cli
         .database_file
@@ -104,6 +114,7 @@ fn main() -> eyre::Result<()> {
             &tree,
             &root,
             repo_entry,
+            cli.bundle,
         )?;
     }
 
@@ -117,6 +128,7 @@ fn execute_on_entry(
     tree: &BKTree<VidSrc>,
     root: &Path,
     mut repo_entry: Entry,
+    bundle: bool,
 ) -> eyre::Result<()> {
     log::info!("Creating debug info at: {}", repo_entry.path().display());
 
@@ -153,7 +165,11 @@ fn execute_on_entry(
     log::info!("Done!");
 
     log::info!("Saving everything to the repo entry...");
-    save_collisions(&collisions, &mut repo_entry, root, images, &simi_args)?;
+    if bundle {
+        save_collisions_bundle(&collisions, &mut repo_entry, root, images, &simi_args)?;
+    } else {
+        save_collisions(&collisions, &mut repo_entry, root, images, &simi_args)?;
+    }
     log::info!("Done!");
 
     Ok(())
@@ -217,79 +233,235 @@ fn save_collisions(
     Ok(())
 }
 
-// TODO: parallelize somehow, with rayon?
+/// Like [`save_collisions`], but streams every collision's artifacts into a single
+/// `collisions.tar` under `repo_entry` instead of a directory tree of small files, so
+/// the whole entry can be copied off a machine or attached to a bug report as one blob.
+/// One directory per collision inside the archive, JPEGs appended straight from the
+/// in-memory encoded buffer instead of a temporary file, and the "collided_with"
+/// relationship stored as a tar symlink header rather than an actual filesystem link.
+fn save_collisions_bundle(
+    collisions: &[Collision],
+    repo_entry: &mut Entry,
+    root: &Path,
+    images: HashMap<VidSrc, PreprocImage>,
+    simi_args: &SimiArgs,
+) -> eyre::Result<()> {
+    repo_entry.create_file("collisions.tar", |w| {
+        let mut builder = Builder::new(w);
+
+        for (i, Collision { other, reference }) in collisions.iter().enumerate() {
+            let dir = format!("collision_{i:04}");
+
+            append_symlink_to_tar(
+                &mut builder,
+                &format!("{dir}/collided_with"),
+                &root.join(other.vidsrc.path()),
+            )?;
+
+            let PreprocImage {
+                original: other_org,
+                preproc: other_pre,
+            } = images.get(&other.vidsrc).expect("should exist");
+            let PreprocImage {
+                original: ref_org,
+                preproc: ref_pre,
+            } = images.get(&reference.vidsrc).expect("should exist");
+
+            append_jpg_to_tar(&mut builder, &format!("{dir}/collided_frame.jpg"), other_org)?;
+            append_jpg_to_tar(&mut builder, &format!("{dir}/reference_frame.jpg"), ref_org)?;
+            append_jpg_to_tar(
+                &mut builder,
+                &format!("{dir}/collided_frame_preproc.jpg"),
+                other_pre,
+            )?;
+            append_jpg_to_tar(
+                &mut builder,
+                &format!("{dir}/reference_frame_preproc.jpg"),
+                ref_pre,
+            )?;
+
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/collided_timestamp.txt"),
+                other.vidsrc.frame_pos().to_string().as_bytes(),
+            )?;
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/reference_timestamp.txt"),
+                reference.vidsrc.frame_pos().to_string().as_bytes(),
+            )?;
+
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/collided_mirror.txt"),
+                other.vidsrc.mirrored().to_string().as_bytes(),
+            )?;
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/reference_mirror.txt"),
+                reference.vidsrc.mirrored().to_string().as_bytes(),
+            )?;
+
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/collided_hash.txt"),
+                other.hash.to_base64().as_bytes(),
+            )?;
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/reference_hash.txt"),
+                reference.hash.to_base64().as_bytes(),
+            )?;
+            append_bytes_to_tar(
+                &mut builder,
+                &format!("{dir}/hash_distance.txt"),
+                format!(
+                    "{} <= {}",
+                    other.hash.distance_to(reference.hash),
+                    simi_args.threshold()
+                )
+                .as_bytes(),
+            )?;
+        }
+
+        builder.finish().wrap_err("failed to finish the tar bundle")
+    })?;
+    Ok(())
+}
+
+/// Appends `bytes` as a regular file at `path` inside `builder`'s archive.
+fn append_bytes_to_tar<W: Write>(
+    builder: &mut Builder<W>,
+    path: &str,
+    bytes: &[u8],
+) -> eyre::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, path, bytes)
+        .wrap_err_with(|| format!("failed to append {path} to the tar bundle"))
+}
+
+/// Encodes `image` as a JPEG in memory and appends it as a regular file at `path`,
+/// without ever touching disk the way [`Entry::create_jpg`] does.
+fn append_jpg_to_tar<W: Write>(
+    builder: &mut Builder<W>,
+    path: &str,
+    image: &RgbImage,
+) -> eyre::Result<()> {
+    let bytes = resize::encode(image, Format::Jpeg)
+        .wrap_err_with(|| format!("failed to encode {path} as a jpeg"))?;
+    append_bytes_to_tar(builder, path, &bytes)
+}
+
+/// Appends `path` as a tar symlink header pointing at `target`, the archive equivalent
+/// of [`Entry::create_link`].
+fn append_symlink_to_tar<W: Write>(
+    builder: &mut Builder<W>,
+    path: &str,
+    target: &Path,
+) -> eyre::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Symlink);
+    header.set_size(0);
+    header.set_mode(0o777);
+    builder
+        .append_link(&mut header, path, target)
+        .wrap_err_with(|| format!("failed to append symlink {path} to the tar bundle"))
+}
+
+/// Groups the `VidSrc`s required by `collisions` by their source video, then hands each
+/// distinct video to its own rayon worker: one `FrameExtractor` per file, seeking to the
+/// file's requested `frame_pos`s in increasing order instead of rescanning from the
+/// beginning for every frame. Each worker builds its own `HashMap<VidSrc, RgbImage>`,
+/// merged into one only once every worker is done, so independent videos never have to
+/// contend over a shared map while decoding.
 fn read_images_from_videos(
     collisions: &[Collision],
     root: &Path,
 ) -> eyre::Result<HashMap<VidSrc, RgbImage>> {
-    let mut images = HashMap::new();
+    let mut by_path: HashMap<&SimplePath, HashSet<&VidSrc>> = HashMap::new();
     for collision in collisions.iter() {
         for vidsrc in [&collision.reference.vidsrc, &collision.other.vidsrc] {
-            if !images.contains_key(vidsrc) {
-                let full_path = root.join(vidsrc.path());
-                log::info!("Opening: {}", full_path.display());
-                let mut extractor =
-                    FrameExtractor::new(&full_path).wrap_err_with(|| {
+            by_path.entry(vidsrc.path()).or_default().insert(vidsrc);
+        }
+    }
+
+    let per_file: Vec<HashMap<VidSrc, RgbImage>> = by_path
+        .into_par_iter()
+        .map(|(_, vidsrcs)| -> eyre::Result<HashMap<VidSrc, RgbImage>> {
+            let mut vidsrcs: Vec<&VidSrc> = vidsrcs.into_iter().collect();
+            vidsrcs.sort_by_key(|vidsrc| vidsrc.frame_pos().as_duration());
+
+            let full_path = root.join(vidsrcs[0].path());
+            log::info!("Opening: {}", full_path.display());
+            let mut extractor =
+                FrameExtractor::new(&full_path, FrameExtractorConf::default())
+                    .wrap_err_with(|| {
                         format!(
                             "failed to open frame extractor for {}",
                             full_path.display()
                         )
                     })?;
 
-                // TODO: don't start from the beginning again
-                for collision in collisions.iter() {
-                    for vidsrc2 in [&collision.reference.vidsrc, &collision.other.vidsrc]
-                    {
-                        if vidsrc2.path() == vidsrc.path()
-                            && !images.contains_key(vidsrc2)
-                        {
-                            extractor
-                                .seek_to(vidsrc2.frame_pos())
-                                .wrap_err("failed to seek")?;
-
-                            let Some((_, img)) =
-                                extractor.next().wrap_err("failed to get frame")?
-                            else {
-                                eyre::bail!("should have returned an image");
-                            };
-
-                            images.insert(vidsrc2.clone(), img);
-                        }
-                    }
-                }
+            let mut images = HashMap::with_capacity(vidsrcs.len());
+            for vidsrc in vidsrcs {
+                extractor
+                    .seek_to(vidsrc.frame_pos())
+                    .wrap_err("failed to seek")?;
 
-                log::info!("Done with: {}", full_path.display());
+                let Some((_, img)) = extractor.next().wrap_err("failed to get frame")?
+                else {
+                    eyre::bail!("should have returned an image");
+                };
+
+                images.insert(vidsrc.clone(), img);
             }
-        }
-    }
-    Ok(images)
+
+            log::info!("Done with: {}", full_path.display());
+            Ok(images)
+        })
+        .collect::<eyre::Result<Vec<_>>>()
+        .wrap_err("failed to extract frames from the collided videos")?;
+
+    Ok(per_file.into_iter().flatten().collect())
 }
 
+/// Fans `ref_frames` out across a rayon thread pool, one `tree.find_within` query per
+/// reference frame, collecting every hit into a shared `collisions` vec behind a mutex.
 fn find_collisions(
     ref_frames: &[Frame],
     ref_path: &SimplePath,
     tree: &BKTree<VidSrc>,
     simi_args: &SimiArgs,
 ) -> eyre::Result<Vec<Collision>> {
-    let mut collisions = Vec::new();
-    for ref_frame in ref_frames {
-        tree.find_within(
-            ref_frame.hash,
-            simi_args.threshold(),
-            |other_hash, other_vidsrc| {
-                if ref_path != other_vidsrc.path() {
-                    collisions.push(Collision {
-                        reference: ref_frame.clone(),
-                        other: Frame {
-                            vidsrc: other_vidsrc.deserialize(),
-                            hash: other_hash,
-                        },
-                    })
-                }
-            },
-        )?;
-    }
-    Ok(collisions)
+    let collisions: Mutex<Vec<Collision>> = Mutex::new(Vec::new());
+
+    ref_frames
+        .par_iter()
+        .map(|ref_frame| -> eyre::Result<()> {
+            tree.find_within(
+                ref_frame.hash,
+                simi_args.threshold(),
+                |other_hash, other_vidsrc| {
+                    if ref_path != other_vidsrc.path() {
+                        collisions.lock().unwrap().push(Collision {
+                            reference: ref_frame.clone(),
+                            other: Frame {
+                                vidsrc: other_vidsrc.deserialize(),
+                                hash: other_hash,
+                            },
+                        })
+                    }
+                },
+            )?;
+            Ok(())
+        })
+        .collect::<eyre::Result<Vec<()>>>()
+        .wrap_err("failed to find collisions for the reference frames")?;
+
+    Ok(collisions.into_inner().unwrap())
 }
 
 fn extract_frames(
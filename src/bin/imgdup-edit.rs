@@ -13,12 +13,19 @@ use imgdup::{
     },
     bktree::{
         mmap::bktree::BKTree,
-        source_types::{any_source::AnySource, video_source::VidSrc},
+        mount::MountFs,
+        source_types::{
+            any_source::AnySource,
+            video_source::{Mirror, VidSrc},
+        },
     },
+    frame_extractor::{FrameExtractor, FrameExtractorConf},
     imghash::{
+        hamming::{Distance, Hamming},
         preproc::{PreprocArgs, PreprocCli},
         similarity::{SimiArgs, SimiCli},
     },
+    utils::{imgutils, repo::Repo},
 };
 
 #[derive(Parser, Debug)]
@@ -35,6 +42,16 @@ struct Cli {
     #[arg(long, short = 'f')]
     database_file: PathBuf,
 
+    /// Root directory that the stored video paths are relative to. Required to
+    /// materialize matches of a `query`/`queryhash` goal into `--query-out-dir`.
+    #[arg(long)]
+    video_root: Option<PathBuf>,
+
+    /// If set, write a thumbnail and a symlink per match of a `query`/`queryhash` goal
+    /// into this directory, as a `Repo`
+    #[arg(long)]
+    query_out_dir: Option<PathBuf>,
+
     // TODO: list the goals in a description
     /// Goals to execute
     #[arg(value_parser = goal_parser, required = true)]
@@ -47,6 +64,17 @@ enum Goal {
     Rebuild,
     Purge { dir: PathBuf },
     List { file: PathBuf },
+    Mount { mountpoint: PathBuf, root: PathBuf },
+    Query {
+        input: QueryInput,
+        max_dist: Option<Distance>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum QueryInput {
+    File(PathBuf),
+    Hash(Hamming),
 }
 
 fn goal_parser(s: &str) -> Result<Goal, String> {
@@ -56,16 +84,47 @@ fn goal_parser(s: &str) -> Result<Goal, String> {
         &["rebuild"] => Ok(Goal::Rebuild),
         &["list", arg1] => Ok(Goal::List { file: arg1.into() }),
         &["purge", arg1] => Ok(Goal::Purge { dir: arg1.into() }),
+        &["mount", arg1, arg2] => Ok(Goal::Mount {
+            mountpoint: arg1.into(),
+            root: arg2.into(),
+        }),
+        &["query", arg1] => Ok(Goal::Query {
+            input: QueryInput::File(arg1.into()),
+            max_dist: None,
+        }),
+        &["query", arg1, arg2] => Ok(Goal::Query {
+            input: QueryInput::File(arg1.into()),
+            max_dist: Some(parse_distance(arg2)?),
+        }),
+        &["queryhash", arg1] => Ok(Goal::Query {
+            input: QueryInput::Hash(parse_hash(arg1)?),
+            max_dist: None,
+        }),
+        &["queryhash", arg1, arg2] => Ok(Goal::Query {
+            input: QueryInput::Hash(parse_hash(arg1)?),
+            max_dist: Some(parse_distance(arg2)?),
+        }),
         _ => Err(format!("Failed to parse goal '{s}', unrecognized")),
     }
 }
 
+fn parse_distance(s: &str) -> Result<Distance, String> {
+    s.parse()
+        .map_err(|e| format!("'{s}' is not a valid max distance: {e}"))
+}
+
+fn parse_hash(s: &str) -> Result<Hamming, String> {
+    u128::from_str_radix(s, 16)
+        .map(Hamming)
+        .map_err(|e| format!("'{s}' is not a valid hex hash: {e}"))
+}
+
 fn main() -> eyre::Result<()> {
     init_eyre()?;
-    init_logger(None)?;
+    init_logger(None, &[])?;
     let cli = Cli::parse();
 
-    let preproc_args = cli.preproc_args.to_args();
+    let preproc_args = cli.preproc_args.to_args()?;
     let simi_args = cli.simi_args.to_args();
 
     let mut tree =
@@ -100,6 +159,32 @@ fn main() -> eyre::Result<()> {
                 tree = vid_tree.upcast();
                 res
             }
+            Goal::Mount {
+                ref mountpoint,
+                ref root,
+            } => {
+                let vid_tree = tree.downcast().wrap_err("failed to downcast")?;
+                let res = goal_mount(&vid_tree, mountpoint, root, &simi_args);
+                tree = vid_tree.upcast();
+                res
+            }
+            Goal::Query {
+                ref input,
+                max_dist,
+            } => {
+                let vid_tree = tree.downcast().wrap_err("failed to downcast")?;
+                let res = goal_query(
+                    &vid_tree,
+                    input,
+                    max_dist,
+                    &preproc_args,
+                    &simi_args,
+                    cli.video_root.as_deref(),
+                    cli.query_out_dir.as_deref(),
+                );
+                tree = vid_tree.upcast();
+                res
+            }
         }
         .wrap_err_with(|| format!("failed to perform goal '{goal:?}'"))?;
         log::info!("Done with goal: {goal:?}");
@@ -173,6 +258,25 @@ fn goal_purge(
     Ok(())
 }
 
+fn goal_mount(
+    tree: &BKTree<VidSrc>,
+    mountpoint: &Path,
+    root: &Path,
+    simi_args: &SimiArgs,
+) -> eyre::Result<()> {
+    log::info!("Grouping frames into similarity clusters");
+    let fs = MountFs::new(tree, simi_args, root.to_path_buf())
+        .wrap_err("failed to build the mount filesystem")?;
+
+    log::info!(
+        "Mounting the database read-only at: {} (Ctrl-C or `fusermount -u` to stop)",
+        mountpoint.display()
+    );
+    fs.mount(mountpoint).wrap_err("failed to mount")?;
+
+    Ok(())
+}
+
 fn goal_list(tree: &BKTree<VidSrc>, file_path: &Path) -> eyre::Result<()> {
     log::info!("Reading and sorting all entries");
     let lines = {
@@ -198,3 +302,97 @@ fn goal_list(tree: &BKTree<VidSrc>, file_path: &Path) -> eyre::Result<()> {
     log::info!("Wrote the entries in the tree to a file");
     Ok(())
 }
+
+fn goal_query(
+    tree: &BKTree<VidSrc>,
+    input: &QueryInput,
+    max_dist: Option<Distance>,
+    preproc_args: &PreprocArgs,
+    simi_args: &SimiArgs,
+    video_root: Option<&Path>,
+    out_dir: Option<&Path>,
+) -> eyre::Result<()> {
+    let query_hash = match input {
+        QueryInput::File(path) => {
+            let img = image::open(path)
+                .wrap_err_with(|| format!("could not open {} as an image", path.display()))?
+                .to_rgb8();
+            preproc_args
+                .hash_img(&img)
+                .wrap_err("failed to preprocess/hash the input image")?
+        }
+        QueryInput::Hash(hash) => *hash,
+    };
+    let within = max_dist.unwrap_or_else(|| simi_args.threshold());
+    log::info!("Searching for matches within a distance of {within} from {query_hash}");
+
+    let mut matches = Vec::new();
+    tree.find_within(query_hash, within, |hash, vidsrc| {
+        let source = VidSrc::new(
+            vidsrc.frame_pos().to_owned(),
+            vidsrc.path().to_owned(),
+            vidsrc.mirrored(),
+            vidsrc.stamp(),
+        );
+        matches.push((hash.distance_to(query_hash), source, hash));
+    })
+    .wrap_err("failed to search the tree")?;
+    matches.sort_by_key(|(dist, _, _)| *dist);
+
+    log::info!("Found {} matches", matches.len());
+    for (dist, source, hash) in &matches {
+        println!("{dist}, {source}, {hash}");
+    }
+
+    if let Some(out_dir) = out_dir {
+        let video_root = video_root
+            .ok_or_else(|| eyre::eyre!("--video-root is required with --query-out-dir"))?;
+        let mut repo = Repo::new(out_dir).wrap_err("failed to open the output repo")?;
+        for (dist, source, _) in &matches {
+            materialize_match(&mut repo, video_root, *dist, source)
+                .wrap_err_with(|| format!("failed to materialize match: {source}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a thumbnail of the exact matched frame plus a symlink to its source video into
+/// a new entry of `repo`.
+fn materialize_match(
+    repo: &mut Repo,
+    video_root: &Path,
+    dist: Distance,
+    source: &VidSrc,
+) -> eyre::Result<()> {
+    let mut entry = repo.new_entry().wrap_err("failed to create a repo entry")?;
+
+    entry
+        .create_text_file("info", format!("distance={dist}\nsource={source}"))
+        .wrap_err("failed to write the info file")?;
+
+    let video_path = video_root.join(source.path().as_path());
+    entry
+        .create_link("video", &video_path)
+        .wrap_err("failed to link the source video")?;
+
+    let mut extractor = FrameExtractor::new(video_path.as_path(), FrameExtractorConf::default())
+        .wrap_err("failed to open the source video")?;
+    extractor
+        .seek_to(source.frame_pos().clone())
+        .wrap_err("failed to seek to the stored frame")?;
+    let (_, mut frame) = extractor
+        .next()
+        .wrap_err("failed to decode the stored frame")?
+        .ok_or_else(|| eyre::eyre!("ran out of frames before the stored timestamp"))?;
+
+    if source.mirrored() == Mirror::Mirrored {
+        frame = imgutils::mirror(frame);
+    }
+
+    entry
+        .create_jpg("thumbnail", &frame)
+        .wrap_err("failed to write the thumbnail")?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre;
+use imgdup::{
+    bin_common::init::{init_eyre, init_logger},
+    heap::{CheckReport, Heap, Inconsistency},
+};
+
+#[derive(Parser, Debug)]
+#[command()]
+/// Validate or repair a heap file's on-disk consistency, recovering it after a
+/// crash mid-checkpoint/flush instead of trusting its metadata outright.
+struct Cli {
+    /// Path to the heap file to operate on
+    #[arg(long, short = 'd')]
+    database_file: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Report inconsistencies without changing anything
+    Check,
+    /// Report inconsistencies and fix what can be fixed
+    Repair {
+        /// Also clear a root that doesn't resolve to a live entry
+        #[arg(long)]
+        clear_dangling_root: bool,
+    },
+}
+
+fn main() -> eyre::Result<()> {
+    init_eyre()?;
+    init_logger(None, &[])?;
+    let cli = Cli::parse();
+
+    let mut db = Heap::<Vec<u8>>::new_from_file(&cli.database_file)?;
+
+    let report = match cli.command {
+        Command::Check => db.check()?,
+        Command::Repair {
+            clear_dangling_root,
+        } => db.repair(clear_dangling_root)?,
+    };
+
+    print_report(&report);
+    db.close()?;
+
+    Ok(())
+}
+
+fn print_report(report: &CheckReport) {
+    if report.is_clean() {
+        println!("No inconsistencies found.");
+        return;
+    }
+
+    println!("Found {} inconsistencies:", report.inconsistencies.len());
+    for inconsistency in &report.inconsistencies {
+        match inconsistency {
+            Inconsistency::UnsortedBlock { block_id } => {
+                println!("- block {block_id} was not sorted by sub_id, or had duplicate sub_ids")
+            }
+            Inconsistency::EmptyBlock { block_id } => {
+                println!("- block {block_id} was persisted with no entries")
+            }
+            Inconsistency::IdAboveNextId { id } => {
+                println!("- id {id} was >= the persisted next_id")
+            }
+            Inconsistency::DanglingRoot => {
+                println!("- the persisted root did not resolve to a live entry")
+            }
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use std::{
-    collections::HashSet,
-    ffi::OsString,
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    io,
     num::NonZeroU32,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{mpsc, Mutex},
     time::{Duration, Instant},
 };
@@ -12,21 +13,30 @@ use color_eyre::eyre::{self, Context};
 use common::Payload;
 use image::RgbImage;
 use imgdup::utils::{
+    job::Job,
+    logger::LogLogger,
     simple_path::SimplePath,
-    work_queue::WorkQueue,
     workers::{scoped_workers, FinishedWorker},
 };
 use imgdup::{bin_common::ignored_hashes::read_ignored, imghash::preproc::PreprocArgs};
 use imgdup::{bin_common::ignored_hashes::Ignored, imghash::similarity::SimiArgs};
+use imgdup::bin_common::failure_cache::{FailureCache, FailureCategory};
+use imgdup::bin_common::progress::{self, ProgressEvent};
+use imgdup::frame_cache::FrameCache;
 use imgdup::{
-    bin_common::init::{init_eyre, init_logger},
+    bin_common::init::{init_eyre, init_logger, LogFileArgs},
     bktree::{
         mmap::bktree::BKTree,
-        source_types::video_source::{Mirror, VidSrc},
+        source_types::video_source::{FileStamp, Mirror, VidSrc},
+    },
+    frame_extractor::{
+        digest::{DigestMode, DigestState},
+        frame_extractor::{FrameExtractor, FrameExtractorConf},
+        timestamp::Timestamp,
     },
-    frame_extractor::{frame_extractor::FrameExtractor, timestamp::Timestamp},
     imghash::{hamming::Hamming, preproc::PreprocCli, similarity::SimiCli},
-    utils::repo::{LazyEntry, Repo},
+    utils::packed_repo::{PackedEntry, PackedRepo},
+    utils::repo::{Entry, Repo},
     utils::{
         fsutils::{all_files, read_optional_file},
         simple_path::clap_simple_relative_parser,
@@ -60,6 +70,14 @@ struct Cli {
     #[arg(long)]
     logfile: Option<PathBuf>,
 
+    /// Roll `logfile` over once it reaches this many bytes
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    logfile_max_bytes: u64,
+
+    /// How many rolled-over `logfile`s to keep around
+    #[arg(long, default_value_t = 5)]
+    logfile_keep: usize,
+
     /// Folder of pictures to ignore
     #[arg(long, short = 'i')]
     ignore_dir: Option<PathBuf>,
@@ -76,9 +94,128 @@ struct Cli {
     #[arg(long, short = 's', required = true, num_args=1.., value_parser = clap_simple_relative_parser)]
     src_dirs: Vec<SimplePathBuf>,
 
+    /// Video file extensions to restrict discovery to (case-insensitive, without the
+    /// leading dot). Defaults to `DEFAULT_VIDEO_EXTENSIONS`; passing this flag
+    /// replaces that default list entirely
+    #[arg(long, num_args = 1..)]
+    ext: Option<Vec<String>>,
+
+    /// Extensions to additionally exclude, even if they're in `--ext` or the default
+    /// list
+    #[arg(long, num_args = 1..)]
+    exclude_ext: Vec<String>,
+
     /// Path to the database to use
     #[arg(long, short = 'f', default_value = "./imgdup.db")]
     database_file: PathBuf,
+
+    /// Clear the cache of videos that previously failed to ingest and give all of them
+    /// a fresh attempt
+    #[arg(long)]
+    retry_failed: bool,
+
+    /// Bypass the size/mtime comparison and re-extract every file already in the
+    /// database, regardless of whether it looks unchanged
+    #[arg(long)]
+    force_rehash: bool,
+
+    /// Pack `dup_dir` and `graveyard_dir` into a single data file plus a manifest
+    /// sidecar each, instead of a directory tree of thumbnails and symlinks. Good for
+    /// runs large enough that the directory tree would otherwise blow up the inode
+    /// count.
+    #[arg(long)]
+    packed: bool,
+
+    /// Record or verify a digest of every extracted frame/hash pair in `digest_file`, to
+    /// catch an ffmpeg/decoder/hasher upgrade silently changing them. Only usable when
+    /// `src_dirs` resolves to a single new file and `--video-threads` is 1, since a
+    /// digest only makes sense for one deterministic extraction run.
+    #[arg(long, value_enum, default_value_t = DigestMode::default())]
+    digest_mode: DigestMode,
+
+    /// Sidecar file read/written by `--digest-mode`, required unless it's `ignore`
+    #[arg(long)]
+    digest_file: Option<PathBuf>,
+
+    /// What to do with a newly-discovered duplicate once it collides with something
+    /// already in the database. `link` (the default) only ever symlinks it into
+    /// `dup_dir`, like before; the others additionally act on the duplicate file
+    /// itself, always keeping whichever copy is already in the database
+    #[arg(long, value_enum, default_value_t = DupActionKind::default())]
+    dup_action: DupActionKind,
+
+    /// Destination directory for `--dup-action=move`
+    #[arg(long)]
+    move_dir: Option<PathBuf>,
+
+    /// Log what `--dup-action` would do instead of touching the filesystem
+    #[arg(long)]
+    dup_action_dry_run: bool,
+
+    /// Emit progress events as line-delimited JSON on stdout, for a supervising
+    /// GUI/daemon to follow along without scraping log text
+    #[arg(long)]
+    progress_json: bool,
+}
+
+/// Which `--dup-action` to carry out; see [`DupAction`] for the resolved, validated
+/// form actually used by the tree worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+enum DupActionKind {
+    /// Only symlink the duplicate into `dup_dir`, same as before this flag existed.
+    #[default]
+    Link,
+    /// Move the duplicate file to the system trash.
+    Trash,
+    /// Replace the duplicate file with a hardlink to the copy already in the database,
+    /// reclaiming disk space while leaving a file at that path.
+    Hardlink,
+    /// Move the duplicate file into `--move-dir`.
+    Move,
+    /// Permanently delete the duplicate file.
+    Delete,
+}
+
+/// The resolved form of [`DupActionKind`], with `Move`'s directory validated to be
+/// present and created up front instead of being re-checked on every collision.
+#[derive(Debug)]
+enum DupAction {
+    Link,
+    Trash,
+    Hardlink,
+    Move(PathBuf),
+    Delete,
+}
+
+impl DupAction {
+    fn resolve(kind: DupActionKind, move_dir: Option<PathBuf>) -> eyre::Result<Self> {
+        Ok(match kind {
+            DupActionKind::Link => Self::Link,
+            DupActionKind::Trash => Self::Trash,
+            DupActionKind::Hardlink => Self::Hardlink,
+            DupActionKind::Delete => Self::Delete,
+            DupActionKind::Move => {
+                let dir = move_dir
+                    .ok_or_else(|| eyre::eyre!("--move-dir is required for --dup-action=move"))?;
+                std::fs::create_dir_all(&dir)
+                    .wrap_err_with(|| format!("failed to create {}", dir.display()))?;
+                Self::Move(dir)
+            }
+        })
+    }
+}
+
+/// A sane built-in set of video container extensions, matching czkawka's
+/// `VIDEO_FILES_EXTENSIONS`. Overridable entirely via `--ext`.
+const DEFAULT_VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi", "webm", "mov", "wmv", "flv"];
+
+/// Whether `path`'s extension (case-insensitive) is in `allow` and not in `deny`.
+fn has_allowed_extension(path: &Path, allow: &HashSet<String>, deny: &HashSet<String>) -> bool {
+    let Some(ext) = path.extension().and_then(OsStr::to_str) else {
+        return false;
+    };
+    let ext = ext.to_ascii_lowercase();
+    allow.contains(&ext) && !deny.contains(&ext)
 }
 
 fn cli_arguments() -> eyre::Result<Cli> {
@@ -103,7 +240,17 @@ fn cli_arguments() -> eyre::Result<Cli> {
 fn main() -> eyre::Result<()> {
     init_eyre()?;
     let cli = cli_arguments()?;
-    init_logger(cli.logfile.as_deref())?;
+    init_logger(
+        cli.logfile.as_ref().map(|path| LogFileArgs {
+            path: path.clone(),
+            max_bytes: cli.logfile_max_bytes,
+            keep: cli.logfile_keep,
+        }),
+        &[],
+    )?;
+
+    let preproc_args = cli.preproc_args.to_args()?;
+    let simi_args = cli.simi_args.to_args();
 
     // TODO: extract all these functions
     log::info!("Backing up the database file");
@@ -128,35 +275,108 @@ fn main() -> eyre::Result<()> {
     let src_files = src_files.wrap_err("some path from a src dir is not simple")?;
     log::info!("Found {} files", src_files.len());
 
+    let allowed_ext: HashSet<String> = cli
+        .ext
+        .unwrap_or_else(|| DEFAULT_VIDEO_EXTENSIONS.iter().map(|s| s.to_string()).collect())
+        .into_iter()
+        .map(|s| s.to_ascii_lowercase())
+        .collect();
+    let excluded_ext: HashSet<String> = cli
+        .exclude_ext
+        .into_iter()
+        .map(|s| s.to_ascii_lowercase())
+        .collect();
+    let src_files: HashSet<SimplePathBuf> = src_files
+        .into_iter()
+        .filter(|path| has_allowed_extension(path.as_path(), &allowed_ext, &excluded_ext))
+        .collect();
+    log::info!("{} files match the configured extensions", src_files.len());
+
     log::info!(
         "Finding all files in database at: {}",
         cli.database_file.display()
     );
-    let tree_files: HashSet<SimplePathBuf> = {
-        let mut tree_files = HashSet::new();
+    let tree_stamps: HashMap<SimplePathBuf, FileStamp> = {
+        let mut tree_stamps = HashMap::new();
         tree.for_each(|_, src| {
-            tree_files.insert(src.path().to_owned());
+            tree_stamps.insert(src.path().to_owned(), src.stamp());
         })?;
-        tree_files
+        tree_stamps
     };
+    let tree_files: HashSet<SimplePathBuf> = tree_stamps.keys().cloned().collect();
     log::info!("Found {} files", tree_files.len());
 
+    let failures_file = cli.database_file.with_extension("failures");
+    let mut failures = FailureCache::load(&failures_file).wrap_err_with(|| {
+        format!(
+            "failed to load the failure cache at {}",
+            failures_file.display()
+        )
+    })?;
+    if cli.retry_failed {
+        log::info!("Clearing the failure cache due to --retry-failed");
+        failures.clear();
+    }
+
+    let frame_cache_file = cli.database_file.with_extension("framecache");
+    let mut frame_cache = FrameCache::load(&frame_cache_file);
+    frame_cache.prune_missing();
+
+    // A file is "changed" when it's in both `src_files` and `tree_files` but its size
+    // or modification time no longer match what's in the tree, following czkawka's
+    // approach of keying a cache entry on `(path, size, modified_date)`. Treated like a
+    // removed-then-added file below: purged from the tree, then re-enqueued.
+    // `--force-rehash` treats every tracked file as changed, regardless of its stamp.
+    let changed_files: HashSet<&SimplePath> = src_files
+        .intersection(&tree_files)
+        .filter(|path| {
+            if cli.force_rehash {
+                return true;
+            }
+            let old_stamp = &tree_stamps[*path];
+            let Ok(new_stamp) = FileStamp::of(path.as_path()) else {
+                return false;
+            };
+            *old_stamp != new_stamp
+        })
+        .map(|pb| pb.as_simple_path())
+        .collect();
+    log::info!("Found {} changed files", changed_files.len());
+
     let new_files: Vec<&SimplePath> = src_files
         .difference(&tree_files)
-        .take(cli.limit)
         .map(|pb| pb.as_simple_path())
+        .chain(changed_files.iter().copied())
+        .filter(|path| match failures.get(path) {
+            Ok(Some(failure)) => {
+                log::debug!(
+                    "Skipping previously-failed '{path}' ({}): {}",
+                    failure.category,
+                    failure.error
+                );
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                log::warn!("Failed to check the failure cache for '{path}': {e:?}");
+                true
+            }
+        })
+        .take(cli.limit)
         .collect();
     let removed_files: HashSet<&SimplePath> = tree_files
         .difference(&src_files)
         .map(|pb| pb.as_simple_path())
+        .chain(changed_files.iter().copied())
         .collect();
 
-    log::info!("Removing {} removed files from the DB", removed_files.len());
+    log::info!(
+        "Removing {} removed/changed files from the DB",
+        removed_files.len()
+    );
     tree.remove_any_of(|_, vidsrc| removed_files.contains(vidsrc.path()))?;
 
     let video_threads: usize = cli.video_threads.get().try_into().expect("should fit");
-    let preproc_args = cli.preproc_args.to_args();
-    let simi_args = cli.simi_args.to_args();
 
     let ignored_hashes = if let Some(ignore_dir) = cli.ignore_dir {
         log::info!("Reading images to ignore from: {}", ignore_dir.display());
@@ -167,27 +387,79 @@ fn main() -> eyre::Result<()> {
     };
     log::info!("Ignoring {} images", ignored_hashes.len());
 
+    let digest = match (cli.digest_mode, cli.digest_file) {
+        (DigestMode::Ignore, _) => None,
+        (_, None) => eyre::bail!("--digest-file is required unless --digest-mode=ignore"),
+        (mode, Some(file)) => {
+            if new_files.len() != 1 || video_threads != 1 {
+                eyre::bail!(
+                    "--digest-mode only makes sense for a single file and --video-threads=1, \
+                     but there are {} new file(s) and --video-threads={video_threads}",
+                    new_files.len(),
+                );
+            }
+            Some(Mutex::new(DigestState::open(mode, &file)?))
+        }
+    };
+
     log::info!("Processing {} new files", new_files.len());
-    let new_files = WorkQueue::new(new_files);
+    let journal_file = cli.database_file.with_extension("journal");
+    let (job, old_problems) = Job::open(
+        new_files,
+        |path| path.to_string(),
+        &journal_file,
+        &LogLogger,
+    )
+    .wrap_err_with(|| format!("failed to open the journal at {}", journal_file.display()))?;
+    if !old_problems.is_empty() {
+        log::warn!(
+            "{} file(s) failed during a previous, interrupted run",
+            old_problems.len()
+        );
+    }
+
+    let dup_action = DupAction::resolve(cli.dup_action, cli.move_dir)
+        .wrap_err("failed to set up --dup-action")?;
+    let dup_action_dry_run = cli.dup_action_dry_run;
 
-    let repo_dup = Repo::new(cli.dup_dir).wrap_err("failed to create the dup repo")?;
+    let repo_dup = ResultRepo::new(cli.dup_dir, cli.packed)
+        .wrap_err("failed to create the dup repo")?;
     let repo_grave = if let Some(grave) = cli.graveyard_dir {
         Some(Mutex::new(
-            Repo::new(grave).wrap_err("failed to create graveyard repo")?,
+            ResultRepo::new(grave, cli.packed)
+                .wrap_err("failed to create graveyard repo")?,
         ))
     } else {
         None
     };
 
+    let failures = Mutex::new(failures);
+    let frame_cache = Mutex::new(frame_cache);
+
+    let (progress_tx, progress_rx) = if cli.progress_json {
+        let (tx, rx) = mpsc::channel::<ProgressEvent>();
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+
     let finished_workers = scoped_workers(|s| {
         let (tx, rx) = mpsc::sync_channel::<Payload>(16);
 
+        if let Some(progress_rx) = progress_rx {
+            s.spawn("P", move || progress::report(progress_rx, io::stdout()));
+        }
+
         let video_ctx = video::Ctx {
             preproc_args: &preproc_args,
             simi_args: &simi_args,
             ignored_hashes: &ignored_hashes,
-            new_files: &new_files,
+            job: &job,
             repo_grave: repo_grave.as_ref(),
+            digest: digest.as_ref(),
+            failures: &failures,
+            frame_cache: &frame_cache,
+            progress: progress_tx.as_ref(),
         };
 
         for _ in 0..video_threads {
@@ -198,10 +470,36 @@ fn main() -> eyre::Result<()> {
 
         let tree_ctx = tree::Ctx {
             simi_args: &simi_args,
+            dup_action: &dup_action,
+            dup_action_dry_run,
+            progress: progress_tx.as_ref(),
+            job: &job,
         };
         s.spawn("T", move || tree::main(tree_ctx, rx, tree, repo_dup));
     });
 
+    failures
+        .into_inner()
+        .expect("no thread panicked while holding the lock")
+        .save(&failures_file)
+        .wrap_err_with(|| {
+            format!(
+                "failed to save the failure cache to {}",
+                failures_file.display()
+            )
+        })?;
+
+    frame_cache
+        .into_inner()
+        .expect("no thread panicked while holding the lock")
+        .save(&frame_cache_file)
+        .wrap_err_with(|| {
+            format!(
+                "failed to save the frame cache to {}",
+                frame_cache_file.display()
+            )
+        })?;
+
     for FinishedWorker { result, name } in finished_workers {
         match result {
             Err(panic) => log::error!("Thread '{name}' panicked with: {panic}"),
@@ -210,9 +508,109 @@ fn main() -> eyre::Result<()> {
         }
     }
 
+    if let Some(repo_grave) = repo_grave {
+        repo_grave
+            .into_inner()
+            .expect("the mutex is never poisoned")
+            .finish()
+            .wrap_err("failed to finish the graveyard repo")?;
+    }
+
+    if let Some(digest) = digest {
+        digest
+            .into_inner()
+            .expect("the mutex is never poisoned")
+            .finish()
+            .wrap_err("failed to finish the digest")?;
+    }
+
+    let problems = job.finish();
+    if !problems.is_empty() {
+        log::warn!("{} file(s) failed this run:", problems.len());
+        for problem in &problems {
+            log::warn!("  '{}': {}", problem.key, problem.error);
+        }
+    }
+    let total_problems = old_problems.len() + problems.len();
+    if total_problems > 0 {
+        log::warn!(
+            "{total_problems} file(s) have failed in total, see the journal at {}",
+            journal_file.display()
+        );
+    }
+
     Ok(())
 }
 
+/// Either a regular directory-tree [`Repo`] or a single-file [`PackedRepo`], chosen by
+/// `--packed`. Only forwards the handful of `Repo`/`Entry` methods this binary actually
+/// uses.
+enum ResultRepo {
+    Dir(Repo),
+    Packed(PackedRepo),
+}
+
+impl ResultRepo {
+    fn new(path: impl Into<PathBuf>, packed: bool) -> eyre::Result<Self> {
+        if packed {
+            Ok(Self::Packed(PackedRepo::new(path.into())?))
+        } else {
+            Ok(Self::Dir(Repo::new(path)?))
+        }
+    }
+
+    fn new_entry(&mut self) -> eyre::Result<ResultEntry> {
+        match self {
+            Self::Dir(repo) => repo.new_entry().map(ResultEntry::Dir),
+            Self::Packed(repo) => repo.new_entry().map(ResultEntry::Packed),
+        }
+    }
+
+    /// No-op for a plain directory [`Repo`]; seals the data/manifest files for a
+    /// [`PackedRepo`]. Must be called once every [`ResultEntry`] handed out by this repo
+    /// has been dropped.
+    fn finish(self) -> eyre::Result<()> {
+        match self {
+            Self::Dir(_) => Ok(()),
+            Self::Packed(repo) => repo.finish(),
+        }
+    }
+}
+
+enum ResultEntry {
+    Dir(Entry),
+    Packed(PackedEntry),
+}
+
+impl ResultEntry {
+    fn create_link_relative(
+        &mut self,
+        link_name: impl AsRef<std::path::Path>,
+        target: impl AsRef<std::path::Path>,
+    ) -> eyre::Result<()> {
+        match self {
+            Self::Dir(entry) => entry.create_link_relative(link_name, target),
+            Self::Packed(entry) => entry.create_link(link_name, target),
+        }
+    }
+
+    fn create_jpg<P, C>(
+        &mut self,
+        jpg_name: impl AsRef<std::path::Path>,
+        image: &image::ImageBuffer<P, C>,
+    ) -> eyre::Result<()>
+    where
+        P: image::Pixel + image::PixelWithColorType,
+        [P::Subpixel]: image::EncodableLayout,
+        C: std::ops::Deref<Target = [P::Subpixel]>,
+    {
+        match self {
+            Self::Dir(entry) => entry.create_jpg(jpg_name, image),
+            Self::Packed(entry) => entry.create_jpg(jpg_name, image),
+        }
+    }
+}
+
 mod common {
     use super::*;
 
@@ -231,8 +629,33 @@ mod video {
         pub preproc_args: &'env PreprocArgs,
         pub simi_args: &'env SimiArgs,
         pub ignored_hashes: &'env Ignored,
-        pub new_files: &'env WorkQueue<&'env SimplePath>,
-        pub repo_grave: Option<&'env Mutex<Repo>>,
+        pub job: &'env Job<'env, &'env SimplePath>,
+        pub repo_grave: Option<&'env Mutex<ResultRepo>>,
+        pub digest: Option<&'env Mutex<DigestState>>,
+        pub failures: &'env Mutex<FailureCache>,
+        pub frame_cache: &'env Mutex<FrameCache>,
+        pub progress: Option<&'env mpsc::Sender<ProgressEvent>>,
+    }
+
+    /// Sends `event` down `ctx.progress`, if anyone is listening. Never fails the
+    /// caller; a dead or absent receiver just means nothing is watching.
+    fn send_progress(ctx: Ctx, event: ProgressEvent) {
+        if let Some(tx) = ctx.progress {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Records `vid_path` as failed in the shared [`FailureCache`], so it isn't
+    /// re-attempted on the next run unless its size/mtime change.
+    fn record_failure(
+        ctx: Ctx,
+        vid_path: &SimplePath,
+        category: FailureCategory,
+        error: impl std::fmt::Display,
+    ) {
+        if let Err(e) = ctx.failures.lock().unwrap().put(vid_path, category, error) {
+            log::warn!("Failed to record the failure cache entry for '{vid_path}': {e:?}");
+        }
     }
 
     pub fn main<'env>(
@@ -243,13 +666,32 @@ mod video {
 
         let mut failed = Vec::new();
 
-        while let Some((i, vid_path)) = ctx.new_files.next_index() {
-            log::info!("Progress: {}/{} videos", i + 1, ctx.new_files.len());
+        while let Some(vid_path) = ctx.job.next() {
+            send_progress(
+                ctx,
+                ProgressEvent::FileStarted {
+                    path: vid_path.to_string(),
+                },
+            );
+
             let hashes = match get_hashes(ctx, vid_path) {
                 Ok(ok) => ok,
                 Err(e) => {
-                    log::error!("Failed to get the hashes from '{}': {:?}", vid_path, e);
-                    failed.push((vid_path, e));
+                    log::error!("Failed to get the hashes from '{}': {:?}", vid_path, e.error);
+                    send_progress(
+                        ctx,
+                        ProgressEvent::FileFailed {
+                            path: vid_path.to_string(),
+                            kind: e.category.to_string(),
+                        },
+                    );
+                    record_failure(ctx, vid_path, e.category, &e.error);
+                    if let Err(je) = ctx.job.record_problem(vid_path, &e.error) {
+                        log::warn!("Failed to record the journal entry for '{vid_path}': {je:?}");
+                    }
+                    failed.push((vid_path, e.error));
+                    let (done, total) = ctx.job.progress();
+                    send_progress(ctx, ProgressEvent::StageProgress { done, total });
                     continue;
                 }
             };
@@ -279,14 +721,44 @@ mod video {
         Ok(())
     }
 
+    /// A [`get_hashes`] failure, tagged with the [`FailureCategory`] it should be
+    /// recorded under in the [`FailureCache`].
+    struct GetHashesError {
+        category: FailureCategory,
+        error: eyre::Report,
+    }
+
     fn get_hashes<'env>(
         ctx: Ctx<'env>,
         video: &'env SimplePath,
-    ) -> eyre::Result<Vec<(Timestamp, Hamming, Mirror)>> {
+    ) -> Result<Vec<(Timestamp, Hamming, Mirror)>, GetHashesError> {
         log::info!("Retrieving hashes for: {}", video);
+        let started = Instant::now();
+
+        // A stat failure here just means the cache is skipped, not that `video` itself
+        // is unreadable -- `FrameExtractor::new` below gives the real error for that.
+        let stamp = FileStamp::of(video.as_path()).ok();
+        if let Some(stamp) = stamp {
+            if let Some(hashes) = ctx.frame_cache.lock().unwrap().get(&video.to_owned(), stamp) {
+                log::info!("Reusing {} cached hashes for: {}", hashes.len(), video);
+                send_progress(
+                    ctx,
+                    ProgressEvent::FileHashed {
+                        path: video.to_string(),
+                        frames: hashes.len(),
+                        elapsed_secs: started.elapsed().as_secs_f64(),
+                    },
+                );
+                return Ok(hashes);
+            }
+        }
 
-        let mut extractor = FrameExtractor::new(video.as_path())
-            .wrap_err("Failed to create the extractor")?;
+        let mut extractor = FrameExtractor::new(video.as_path(), FrameExtractorConf::default())
+            .wrap_err("Failed to create the extractor")
+            .map_err(|error| GetHashesError {
+                category: FailureCategory::ExtractorOpen,
+                error,
+            })?;
         let approx_len = extractor.approx_length();
 
         // TODO: move to some config struct and add to Ctx
@@ -294,63 +766,101 @@ mod video {
         let max_step: Duration = Duration::from_secs(10);
         let log_every = Duration::from_secs(10);
 
+        if approx_len.is_zero() {
+            return Err(GetHashesError {
+                category: FailureCategory::TooShort,
+                error: eyre::eyre!("the video's reported length is zero"),
+            });
+        }
+
         let step = calc_step(approx_len, min_frames, max_step);
         // log::debug!("Stepping with {}s", step.as_secs_f64());
 
-        let mut graveyard_entry = LazyEntry::new();
+        let mut graveyard_entry: Option<ResultEntry> = None;
 
         let mut hashes = Vec::with_capacity(estimated_num_of_frames(approx_len, step));
         let approx_len = Timestamp::duration_to_string(approx_len);
 
         let mut last_logged = Instant::now();
-        while let Some((ts, frame)) =
-            extractor.next().wrap_err("Failed to get a frame")?
-        {
-            let now = Instant::now();
-            if now - last_logged >= log_every {
-                last_logged = now;
-                log::debug!("At timestamp: {}/{}", ts.to_string(), approx_len);
-            }
+        let decode: eyre::Result<()> = (|| {
+            while let Some((ts, frame)) = extractor.next().wrap_err("Failed to get a frame")? {
+                let now = Instant::now();
+                if now - last_logged >= log_every {
+                    last_logged = now;
+                    log::debug!("At timestamp: {}/{}", ts.to_string(), approx_len);
+                }
 
-            use FrameToHashResult as F;
-            match frame_to_hash(ctx, &frame, hashes.last().map(|(_, h, _)| *h)) {
-                F::Ok(hash) => {
-                    hashes.push((ts.clone(), hash, Mirror::Normal));
-                    let mirror = imgutils::mirror(frame);
-                    if let F::Ok(hash) = frame_to_hash(ctx, &mirror, Some(hash)) {
-                        hashes.push((ts, hash, Mirror::Mirrored));
+                use FrameToHashResult as F;
+                match frame_to_hash(ctx, &frame, hashes.last().map(|(_, h, _)| *h)) {
+                    F::Ok(hash) => {
+                        if let Some(digest) = ctx.digest {
+                            digest.lock().unwrap().observe(&ts, hash, &frame)?;
+                        }
+
+                        hashes.push((ts.clone(), hash, Mirror::Normal));
+                        let mirror = imgutils::mirror(frame);
+                        if let F::Ok(hash) = frame_to_hash(ctx, &mirror, Some(hash)) {
+                            hashes.push((ts, hash, Mirror::Mirrored));
+                        }
                     }
+                    err @ F::Ignored
+                    | err @ F::Empty
+                    | err @ F::TooBlack
+                    | err @ F::TooBland
+                        if ctx.repo_grave.is_some() =>
+                    {
+                        let entry = match &mut graveyard_entry {
+                            Some(entry) => entry,
+                            None => {
+                                let mut entry =
+                                    ctx.repo_grave.unwrap().lock().unwrap().new_entry()?;
+                                entry.create_link_relative("original", video)?;
+                                graveyard_entry.insert(entry)
+                            }
+                        };
+
+                        entry.create_jpg(format!("{}_{}.jpg", err.name(), ts.to_string()), &frame)?;
+                    }
+                    F::TooBlack
+                    | F::TooBland
+                    | F::TooSimilarToPrevious
+                    | F::Ignored
+                    | F::Empty => (),
                 }
-                err @ F::Ignored
-                | err @ F::Empty
-                | err @ F::TooBlack
-                | err @ F::TooBland
-                    if ctx.repo_grave.is_some() =>
-                {
-                    let entry =
-                        graveyard_entry.get_or_try_init(|| -> eyre::Result<_> {
-                            let mut entry =
-                                ctx.repo_grave.unwrap().lock().unwrap().new_entry()?;
-                            entry.create_link_relative("original", video)?;
-                            Ok(entry)
-                        })?;
-
-                    entry.create_jpg(
-                        format!("{}_{}.jpg", err.name(), ts.to_string()),
-                        &frame,
-                    )?;
-                }
-                F::TooBlack
-                | F::TooBland
-                | F::TooSimilarToPrevious
-                | F::Ignored
-                | F::Empty => (),
+
+                extractor.seek_forward(step).wrap_err("Failed to seek")?;
             }
+            Ok(())
+        })();
+        decode.map_err(|error| GetHashesError {
+            category: FailureCategory::ExtractorOpen,
+            error,
+        })?;
 
-            extractor.seek_forward(step).wrap_err("Failed to seek")?;
+        if hashes.is_empty() {
+            return Err(GetHashesError {
+                category: FailureCategory::NoUsableFrames,
+                error: eyre::eyre!("no usable frames were found"),
+            });
         }
 
         log::info!("Got {} hashes from: {}", hashes.len(), video);
+        send_progress(
+            ctx,
+            ProgressEvent::FileHashed {
+                path: video.to_string(),
+                frames: hashes.len(),
+                elapsed_secs: started.elapsed().as_secs_f64(),
+            },
+        );
+
+        if let Some(stamp) = stamp {
+            ctx.frame_cache
+                .lock()
+                .unwrap()
+                .put(video.to_owned(), stamp, hashes.clone());
+        }
+
         Ok(hashes)
     }
 
@@ -437,6 +947,18 @@ mod tree {
     #[derive(Clone, Copy)]
     pub struct Ctx<'env> {
         pub simi_args: &'env SimiArgs,
+        pub dup_action: &'env DupAction,
+        pub dup_action_dry_run: bool,
+        pub progress: Option<&'env mpsc::Sender<ProgressEvent>>,
+        pub job: &'env Job<'env, &'env SimplePath>,
+    }
+
+    /// Sends `event` down `ctx.progress`, if anyone is listening. Never fails the
+    /// caller; a dead or absent receiver just means nothing is watching.
+    fn send_progress(ctx: Ctx, event: ProgressEvent) {
+        if let Some(tx) = ctx.progress {
+            let _ = tx.send(event);
+        }
     }
 
     // TODO: handle ctrl+c and properly close the db
@@ -444,7 +966,7 @@ mod tree {
         ctx: Ctx<'env>,
         rx: mpsc::Receiver<Payload<'env>>,
         mut tree: BKTree<VidSrc>,
-        mut repo: Repo,
+        mut repo: ResultRepo,
     ) -> eyre::Result<()> {
         log::debug!("Tree worker working");
 
@@ -455,21 +977,51 @@ mod tree {
                 .wrap_err("failed to find similar videos")?;
             log::info!("Found {} duplicate videos", similar_videos.len());
 
+            let mut disposed = false;
             if !similar_videos.is_empty() {
-                link_dup(&mut repo, video_path, similar_videos)
+                send_progress(
+                    ctx,
+                    ProgressEvent::DuplicateFound {
+                        new: video_path.to_string(),
+                        others: similar_videos.iter().map(|p| p.to_string()).collect(),
+                    },
+                );
+
+                link_dup(&mut repo, video_path, &similar_videos)
                     .wrap_err("failed to link dup")?;
+
+                // `video_path` is the newly-discovered duplicate; whatever's already
+                // in `similar_videos` was already in the tree, so that's what's kept.
+                disposed = apply_dup_action(ctx, video_path, &similar_videos)
+                    .wrap_err("failed to carry out the dup action")?;
+            }
+
+            if disposed {
+                log::info!("'{video_path}' was disposed of, not adding it to the tree");
+            } else {
+                log::info!("Saving {} hashes", hashes.len());
+                save_video(hashes, &mut tree, video_path)
+                    .wrap_err("failed to save some video hashes to the tree")?;
+                log::info!("Done saving");
             }
 
-            log::info!("Saving {} hashes", hashes.len());
-            save_video(hashes, &mut tree, video_path)
-                .wrap_err("failed to save some video hashes to the tree")?;
-            log::info!("Done saving");
+            // Only checkpoint once `video_path` is durably committed (either into the
+            // tree above, or disposed of), so a crash from here on re-processes just
+            // whatever's still in-flight.
+            if let Err(e) = ctx.job.checkpoint_done(&video_path) {
+                log::warn!("Failed to checkpoint '{video_path}' in the journal: {e:?}");
+            }
+            let (done, total) = ctx.job.progress();
+            log::info!("Progress: {done}/{total} videos");
+            send_progress(ctx, ProgressEvent::StageProgress { done, total });
         }
 
         log::info!("Closing the tree");
         tree.close().wrap_err("failed to close the tree")?;
         log::info!("Closed!");
 
+        repo.finish().wrap_err("failed to finish the dup repo")?;
+
         log::debug!("Tree worker not working");
 
         Ok(())
@@ -480,17 +1032,19 @@ mod tree {
         tree: &mut BKTree<VidSrc>,
         video_path: &SimplePath,
     ) -> eyre::Result<()> {
+        let stamp = FileStamp::of(video_path.as_path())
+            .wrap_err_with(|| format!("failed to stat {}", video_path.as_path().display()))?;
         tree.add_all(hashes.into_iter().map(|(ts, hash, mirrored)| {
-            (hash, VidSrc::new(ts, video_path.to_owned(), mirrored))
+            (hash, VidSrc::new(ts, video_path.to_owned(), mirrored, stamp))
         }))
         .wrap_err("failed to add to the tree")?;
         Ok(())
     }
 
     fn link_dup(
-        repo: &mut Repo,
+        repo: &mut ResultRepo,
         video_path: &SimplePath,
-        similar_videos: HashSet<&SimplePath>,
+        similar_videos: &HashSet<&SimplePath>,
     ) -> eyre::Result<()> {
         let mut entry = repo
             .new_entry()
@@ -500,7 +1054,7 @@ mod tree {
             .create_link_relative("the_new_one", video_path)
             .wrap_err("failed to link the new one")?;
 
-        for similar in similar_videos.into_iter() {
+        for similar in similar_videos.iter() {
             entry
                 .create_link_relative("dup", similar)
                 .wrap_err("failed to link a dup")?;
@@ -509,6 +1063,65 @@ mod tree {
         Ok(())
     }
 
+    /// Carries out `ctx.dup_action` against `video_path`, the newly-discovered
+    /// duplicate of whatever's in `similar_videos` (which is kept untouched, since it
+    /// was already in the tree). Returns whether `video_path` was disposed of, i.e.
+    /// whether the caller should skip adding its hashes to the tree because the file
+    /// at that path is no longer the one that was hashed.
+    fn apply_dup_action(
+        ctx: Ctx,
+        video_path: &SimplePath,
+        similar_videos: &HashSet<&SimplePath>,
+    ) -> eyre::Result<bool> {
+        if matches!(ctx.dup_action, DupAction::Link) {
+            return Ok(false);
+        }
+
+        if ctx.dup_action_dry_run {
+            log::info!("[dry-run] would apply {:?} to duplicate: {video_path}", ctx.dup_action);
+            return Ok(false);
+        }
+
+        match ctx.dup_action {
+            DupAction::Link => unreachable!("handled above"),
+            DupAction::Trash => {
+                trash::delete(video_path.as_path())
+                    .wrap_err_with(|| format!("failed to trash '{video_path}'"))?;
+                Ok(true)
+            }
+            DupAction::Delete => {
+                std::fs::remove_file(video_path.as_path())
+                    .wrap_err_with(|| format!("failed to delete '{video_path}'"))?;
+                Ok(true)
+            }
+            DupAction::Hardlink => {
+                // Any of the already-known duplicates works as the link target; which
+                // one is picked is not significant, they all hash the same.
+                let keeper = similar_videos
+                    .iter()
+                    .next()
+                    .expect("similar_videos is non-empty here");
+                std::fs::remove_file(video_path.as_path())
+                    .wrap_err_with(|| format!("failed to remove '{video_path}'"))?;
+                std::fs::hard_link(keeper.as_path(), video_path.as_path()).wrap_err_with(|| {
+                    format!("failed to hardlink '{video_path}' to '{keeper}'")
+                })?;
+                Ok(true)
+            }
+            DupAction::Move(dir) => {
+                let file_name = video_path
+                    .as_path()
+                    .file_name()
+                    .expect("a SimplePath always has a file name");
+                let dest = dir.join(file_name);
+                std::fs::rename(video_path.as_path(), &dest).wrap_err_with(|| {
+                    format!("failed to move '{video_path}' to '{}'", dest.display())
+                })?;
+                Ok(true)
+            }
+        }
+    }
+
     fn find_similar_videos<'env>(
         ctx: Ctx<'env>,
         frames: &[(Timestamp, Hamming, Mirror)],
@@ -1,3 +1,5 @@
+mod bktree;
+
 use color_eyre::eyre::{self, Context};
 use std::{
     collections::HashMap,
@@ -6,6 +8,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use bktree::BKTree;
 use clap::Parser;
 use imgdup::{
     fsutils::{all_files, clear_dir, path_as_filename, symlink},
@@ -55,8 +58,11 @@ fn main() -> eyre::Result<()> {
 
     assert_eq!(hashes.len(), pictures.len());
 
+    println!("Building the BK-tree...");
+    let tree = build_tree(&hashes);
+
     println!("Comparing all distances...");
-    let pairwise = compare_all(&hashes);
+    let pairwise = compare_all(&hashes, &tree);
     let distances = count_distances(&pairwise);
 
     println!("Writing text files...");
@@ -67,12 +73,22 @@ fn main() -> eyre::Result<()> {
 
     if let Some(max_dist) = cli.save_collisions {
         println!("Creating collision symlinks...");
-        point_collisions(&pictures, &pairwise, max_dist)?;
+        point_collisions(&pictures, &hashes, &tree, max_dist)?;
     }
 
     Ok(())
 }
 
+fn build_tree(hashes: &[Option<Hamming>]) -> BKTree {
+    let mut tree = BKTree::new();
+    for (i, h) in hashes.iter().enumerate() {
+        if let Some(hash) = h {
+            tree.insert(i, *hash);
+        }
+    }
+    tree
+}
+
 fn hash_pictures(
     pictures: &[PathBuf],
     config: RemoveBordersConf,
@@ -105,20 +121,17 @@ fn hash_pictures(
     Ok(hashes)
 }
 
-fn compare_all(hashes: &[Option<Hamming>]) -> Vec<(usize, usize, Distance)> {
-    let mut dists = Vec::with_capacity(hashes.len() * (hashes.len() + 1) / 2);
+fn compare_all(hashes: &[Option<Hamming>], tree: &BKTree) -> Vec<(usize, usize, Distance)> {
+    let mut dists = Vec::new();
     for (i, h1) in hashes.iter().enumerate() {
-        if h1.is_none() {
-            continue;
-        }
+        let Some(h1) = h1 else { continue };
 
-        for (j, h2) in hashes[i + 1..].iter().enumerate() {
-            if h2.is_none() {
-                continue;
+        for (j, d) in tree.within(*h1, Hamming::MAX_DIST) {
+            // Each unordered pair is found twice (once from either end); only keep the
+            // one found from the lower index, to match `compare_all`'s old output.
+            if j > i {
+                dists.push((i, j, d));
             }
-
-            let d = h1.unwrap().distance_to(h2.unwrap());
-            dists.push((i, j + i + 1, d));
         }
     }
     dists
@@ -166,7 +179,8 @@ fn write_graph_file(distances: &HashMap<Distance, u32>) -> eyre::Result<()> {
 
 fn point_collisions(
     pictures: &[PathBuf],
-    pairwise: &[(usize, usize, Distance)],
+    hashes: &[Option<Hamming>],
+    tree: &BKTree,
     max_dist: Distance,
 ) -> eyre::Result<()> {
     let col_dir = Path::new("collisions");
@@ -183,16 +197,24 @@ fn point_collisions(
         })
     }
 
-    for (i, (p1, p2, dist)) in pairwise.iter().enumerate() {
-        if *dist > max_dist {
-            continue;
-        }
+    let mut seen = 0;
+    for (i, h1) in hashes.iter().enumerate() {
+        let Some(h1) = h1 else { continue };
 
-        let dir = col_dir.join(format!("{dist}_{i}"));
-        fs::create_dir(&dir).wrap_err_with(|| format!("Could not create dir {i}"))?;
+        for (j, dist) in tree.within(*h1, max_dist) {
+            // Each unordered pair is found from both ends; only keep the one found from
+            // the lower index, so every collision is linked exactly once.
+            if j <= i {
+                continue;
+            }
 
-        linkit(&pictures[*p1], &dir)?;
-        linkit(&pictures[*p2], &dir)?;
+            let dir = col_dir.join(format!("{dist}_{seen}"));
+            fs::create_dir(&dir).wrap_err_with(|| format!("Could not create dir {seen}"))?;
+
+            linkit(&pictures[i], &dir)?;
+            linkit(&pictures[j], &dir)?;
+            seen += 1;
+        }
     }
 
     Ok(())
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use imgdup::imghash::hamming::{Distance, Hamming};
+
+/// A simple in-memory BK-tree over [`Hamming`] hashes tagged with their position in the
+/// caller's original list, so [`compare_all`](super::compare_all)/
+/// [`point_collisions`](super::point_collisions) can find all pairs within a distance
+/// without comparing every pair against every other.
+pub struct BKTree {
+    root: Option<Node>,
+}
+
+struct Node {
+    hash: Hamming,
+    index: usize,
+    // One child per distinct edge distance, as in a classic BK-tree: a second hash at
+    // the same distance from its parent gets inserted into the existing child instead
+    // of a sibling of its own.
+    children: HashMap<Distance, Node>,
+}
+
+impl BKTree {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Inserts `hash`, tagged with `index` (its position in the caller's original list)
+    /// so [`Self::within`] can report back which entry matched.
+    pub fn insert(&mut self, index: usize, hash: Hamming) {
+        match &mut self.root {
+            None => self.root = Some(Node::new(index, hash)),
+            Some(root) => root.insert(index, hash),
+        }
+    }
+
+    /// All `(index, distance)` pairs within `max_dist` of `query`, in no particular
+    /// order.
+    pub fn within(&self, query: Hamming, max_dist: Distance) -> Vec<(usize, Distance)> {
+        let mut out = Vec::new();
+        if let Some(root) = &self.root {
+            root.within(query, max_dist, &mut out);
+        }
+        out
+    }
+}
+
+impl Node {
+    fn new(index: usize, hash: Hamming) -> Self {
+        Self {
+            hash,
+            index,
+            children: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, index: usize, hash: Hamming) {
+        let dist = self.hash.distance_to(hash);
+        match self.children.get_mut(&dist) {
+            Some(child) => child.insert(index, hash),
+            None => {
+                self.children.insert(dist, Node::new(index, hash));
+            }
+        }
+    }
+
+    /// Prunes children that can't possibly contain a match within `max_dist`, by the
+    /// triangle inequality: every hash in a child keyed `edge_distance` is at least
+    /// `|edge_distance - dist|` away from `query`, where `dist` is this node's own
+    /// distance to `query`.
+    fn within(&self, query: Hamming, max_dist: Distance, out: &mut Vec<(usize, Distance)>) {
+        let dist = self.hash.distance_to(query);
+        if dist <= max_dist {
+            out.push((self.index, dist));
+        }
+
+        for (&edge_distance, child) in &self.children {
+            if edge_distance.abs_diff(dist) <= max_dist {
+                child.within(query, max_dist, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tree_from(hashes: &[u128]) -> BKTree {
+        let mut tree = BKTree::new();
+        for (i, &h) in hashes.iter().enumerate() {
+            tree.insert(i, Hamming(h));
+        }
+        tree
+    }
+
+    #[test]
+    fn finds_exact_match() {
+        let tree = tree_from(&[0b0000, 0b1111, 0b1010]);
+        let mut found = tree.within(Hamming(0b0000), 0);
+        found.sort();
+        assert_eq!(found, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn finds_all_within_distance() {
+        let tree = tree_from(&[0b0000, 0b0001, 0b0011, 0b1111]);
+        let mut found = tree.within(Hamming(0b0000), 1);
+        found.sort();
+        assert_eq!(found, vec![(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn prunes_distant_children() {
+        // Insertion order matters for which nodes end up as children of which: make sure
+        // a far-away hash doesn't hide a close one behind it.
+        let tree = tree_from(&[0b1111_0000, 0b0000_0000, 0b1111_0001]);
+        let mut found = tree.within(Hamming(0b0000_0001), 1);
+        found.sort();
+        assert_eq!(found, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn empty_tree_returns_nothing() {
+        let tree = BKTree::new();
+        assert!(tree.within(Hamming(0), Hamming::MAX_DIST).is_empty());
+    }
+}
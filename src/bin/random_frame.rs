@@ -1,43 +1,174 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::Parser;
-use color_eyre::eyre;
-use imgdup::frame_extractor::frame_extractor::FrameExtractor;
-use rand::{thread_rng, Rng};
+use clap::{Parser, ValueEnum};
+use color_eyre::eyre::{self, Context};
+use imgdup::{
+    frame_extractor::{
+        digest::{DigestMode, DigestState},
+        frame_extractor::{FrameExtractor, FrameExtractorConf},
+    },
+    imghash::preproc::PreprocCli,
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum SampleStrategy {
+    /// Pick `count` distinct timestamps uniformly at random across the whole video.
+    #[default]
+    Uniform,
+    /// Divide the video into `count` equal-length buckets and pick one random timestamp
+    /// per bucket, so the sample is spread across the whole video instead of clumping.
+    Stratified,
+}
 
 #[derive(Parser)]
 #[command()]
-/// Extracts a random frame from a video file
+/// Extracts one or more random frames from a video file
 struct Cli {
     /// The video file to extract from
     videofile: PathBuf,
 
-    /// Where to save the random frame
+    /// Where to save the frame. When `--count` is more than 1, this is used as a
+    /// template: each frame is saved next to it with its index spliced into the
+    /// filename, e.g. `frame.jpg` becomes `frame_0.jpg`, `frame_1.jpg`, ...
     output: PathBuf,
+
+    /// Seeds the RNG so a given seed + video always yields the same frame(s). Without
+    /// one, a fresh seed is drawn from the OS RNG, as before.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How many distinct frames to sample
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+
+    /// How to spread `count` samples across the video
+    #[arg(long, value_enum, default_value_t = SampleStrategy::default())]
+    strategy: SampleStrategy,
+
+    #[command(flatten)]
+    preproc_args: PreprocCli,
+
+    /// Record or verify a digest of the extracted frame(s) in `digest_file`, to catch an
+    /// ffmpeg/decoder/hasher upgrade silently changing what gets extracted.
+    #[arg(long, value_enum, default_value_t = DigestMode::default())]
+    digest_mode: DigestMode,
+
+    /// Sidecar file read/written by `--digest-mode`, required unless it's `ignore`
+    #[arg(long)]
+    digest_file: Option<PathBuf>,
 }
 
 fn main() -> eyre::Result<()> {
     color_eyre::install()?;
     let cli = Cli::parse();
+    eyre::ensure!(cli.count > 0, "--count must be at least 1");
+
+    let preproc_args = cli.preproc_args.to_args()?;
+
+    let mut digest = match (cli.digest_mode, cli.digest_file) {
+        (DigestMode::Ignore, _) => DigestState::open(DigestMode::Ignore, &PathBuf::new())?,
+        (mode, Some(file)) => DigestState::open(mode, &file)?,
+        (_, None) => eyre::bail!("--digest-file is required unless --digest-mode=ignore"),
+    };
+
+    let mut rng = SmallRng::seed_from_u64(cli.seed.unwrap_or_else(|| rand::thread_rng().gen()));
 
-    let mut extractor = FrameExtractor::new(cli.videofile)?;
+    let mut extractor = FrameExtractor::new(cli.videofile, FrameExtractorConf::default())?;
     let len = extractor.approx_length();
-    let target = Duration::from_secs(thread_rng().gen_range(0..=len.as_secs()));
-
-    extractor.seek_forward(target)?;
-    let img = match extractor.next()? {
-        Some((_, img)) => img,
-        None => {
-            // if the seek seeked too far
-            extractor.seek_to_beginning()?;
-            let (_, img) = extractor
-                .next()?
-                .expect("there are no frames in this video at all");
-            img
-        }
+
+    eyre::ensure!(
+        (cli.count as u128) <= len.as_millis(),
+        "--count {} is more than this video's length in milliseconds",
+        cli.count
+    );
+
+    let offsets = match cli.strategy {
+        SampleStrategy::Uniform => sample_uniform(&mut rng, cli.count, len),
+        SampleStrategy::Stratified => sample_stratified(&mut rng, cli.count, len),
     };
 
-    img.save(cli.output)?;
+    for (i, offset) in offsets.into_iter().enumerate() {
+        extractor.seek_to_beginning()?;
+        extractor.seek_forward(offset)?;
+        let (timestamp, img) = match extractor.next()? {
+            Some(frame) => frame,
+            None => {
+                // if the seek seeked too far
+                extractor.seek_to_beginning()?;
+                extractor
+                    .next()?
+                    .expect("there are no frames in this video at all")
+            }
+        };
+
+        let hash = preproc_args
+            .hash_img(&img)
+            .wrap_err("failed to hash the extracted frame")?;
+        digest.observe(&timestamp, hash, &img)?;
+
+        let output = if cli.count == 1 {
+            cli.output.clone()
+        } else {
+            numbered_path(&cli.output, i)
+        };
+        img.save(output)?;
+    }
+
+    digest.finish()?;
 
     Ok(())
 }
+
+/// `count` distinct timestamps drawn uniformly at random from `0..len`, without
+/// replacement.
+fn sample_uniform(rng: &mut SmallRng, count: usize, len: Duration) -> Vec<Duration> {
+    let mut seen = BTreeSet::new();
+    while seen.len() < count {
+        seen.insert(random_offset(rng, Duration::ZERO, len));
+    }
+    seen.into_iter().collect()
+}
+
+/// Divides `0..len` into `count` equal buckets and picks one random timestamp from each,
+/// so the sample is spread across the whole video rather than clumping.
+fn sample_stratified(rng: &mut SmallRng, count: usize, len: Duration) -> Vec<Duration> {
+    let bucket_len = len / count as u32;
+    (0..count)
+        .map(|i| {
+            let bucket_start = bucket_len * i as u32;
+            let bucket_end = if i + 1 == count {
+                len
+            } else {
+                bucket_len * (i as u32 + 1)
+            };
+            random_offset(rng, bucket_start, bucket_end)
+        })
+        .collect()
+}
+
+fn random_offset(rng: &mut SmallRng, start: Duration, end: Duration) -> Duration {
+    let start_ms = start.as_millis() as u64;
+    let end_ms = end.as_millis() as u64;
+    if start_ms >= end_ms {
+        return start;
+    }
+    Duration::from_millis(rng.gen_range(start_ms..end_ms))
+}
+
+/// Splices `_<index>` in before `output`'s extension, e.g. `frame.jpg` -> `frame_3.jpg`.
+fn numbered_path(output: &Path, index: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .unwrap_or_else(|| std::ffi::OsStr::new(""))
+        .to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{stem}_{index}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{index}"),
+    };
+    output.with_file_name(name)
+}
@@ -1,14 +1,59 @@
+use clap::Args;
 use image::{GenericImageView, Rgb};
 
-use crate::utils::imgutils::color_variance;
+use super::color_channel::ColorChannel;
 
-use super::args_helper::args;
+#[derive(Args, Debug)]
+pub struct BlandnessCli {
+    #[arg(
+        long,
+        default_value_t = -1.0,
+        help = "Images with blandess less than or equal to this are filetered out (negative to disable)"
+    )]
+    blandness_threshold: f64,
 
-args! {
-    #[derive(Copy, Clone)]
-    Blandness {
-        "Images with blandess less than or equal to this are filetered out (negative to disable)"
-        blandness_threshold: f64 = -1.0;
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ColorChannel::Luma709,
+        help = "Which channel to measure blandness on"
+    )]
+    blandness_channel: ColorChannel,
+}
+
+#[derive(Copy, Clone)]
+pub struct BlandnessArgs {
+    blandness_threshold: f64,
+    blandness_channel: ColorChannel,
+}
+
+impl std::default::Default for BlandnessArgs {
+    fn default() -> Self {
+        Self {
+            blandness_threshold: -1.0,
+            blandness_channel: ColorChannel::Luma709,
+        }
+    }
+}
+
+impl BlandnessArgs {
+    pub fn blandness_threshold(mut self, blandness_threshold: f64) -> Self {
+        self.blandness_threshold = blandness_threshold;
+        self
+    }
+
+    pub fn blandness_channel(mut self, blandness_channel: ColorChannel) -> Self {
+        self.blandness_channel = blandness_channel;
+        self
+    }
+}
+
+impl BlandnessCli {
+    pub fn to_args(&self) -> BlandnessArgs {
+        BlandnessArgs {
+            blandness_threshold: self.blandness_threshold,
+            blandness_channel: self.blandness_channel,
+        }
     }
 }
 
@@ -17,7 +62,7 @@ impl BlandnessArgs {
     where
         I: GenericImageView<Pixel = Rgb<u8>>,
     {
-        color_variance(img)
+        self.blandness_channel.variance(img)
     }
 
     pub fn is_value_bland(self, blandness: f64) -> bool {
@@ -0,0 +1,56 @@
+use clap::ValueEnum;
+use image::{GenericImageView, Rgb};
+
+use crate::utils::imgutils;
+
+/// Which channel to measure a frame's color distribution against, shared by
+/// [`super::one_color::OneColorArgs`] and [`super::blandness::BlandnessArgs`] so both can
+/// be pointed at either a chroma-less brightness measure or an actual color measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChannel {
+    /// Rec.709 luma (`0.2126R + 0.7152G + 0.0722B`), closer to perceived brightness than
+    /// a naive average of R, G, B. Chroma-less, like the historical behavior.
+    Luma709,
+    /// Hue+saturation, so a fully saturated solid color (e.g. a title-card background)
+    /// is measured by its actual color instead of only by how bright it is.
+    Saturation,
+}
+
+impl Default for ColorChannel {
+    fn default() -> Self {
+        ColorChannel::Luma709
+    }
+}
+
+impl ColorChannel {
+    /// How monochromatic `img` is on this channel, as a percentage in `0.0..=100.0` of
+    /// pixels belonging to the single most common bucket.
+    pub fn dominant_bucket_percent<I>(self, img: &I, tolerance: u8) -> f64
+    where
+        I: GenericImageView<Pixel = Rgb<u8>>,
+    {
+        match self {
+            ColorChannel::Luma709 => {
+                let gray = imgutils::luma709_image(img);
+                let most_common = imgutils::most_common_gray(&gray);
+                imgutils::percent_gray(&gray, most_common, tolerance)
+            }
+            ColorChannel::Saturation => {
+                let most_common = imgutils::most_common_hue_saturation(img);
+                imgutils::percent_hue_saturation(img, most_common, tolerance)
+            }
+        }
+    }
+
+    /// How spread out `img` is on this channel: luma spread for [`Self::Luma709`],
+    /// chroma (saturation) spread for [`Self::Saturation`].
+    pub fn variance<I>(self, img: &I) -> f64
+    where
+        I: GenericImageView<Pixel = Rgb<u8>>,
+    {
+        match self {
+            ColorChannel::Luma709 => imgutils::luma709_variance(img),
+            ColorChannel::Saturation => imgutils::saturation_variance(img),
+        }
+    }
+}
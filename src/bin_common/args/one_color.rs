@@ -1,28 +1,81 @@
-use image::{imageops::grayscale, GrayImage, RgbImage};
+use clap::Args;
+use image::RgbImage;
 
-use crate::utils::imgutils::{most_common_gray, percent_gray};
+use super::color_channel::ColorChannel;
 
-use super::args_helper::args;
+#[derive(Args, Debug)]
+pub struct OneColorCli {
+    #[arg(
+        long,
+        default_value_t = 90.0,
+        help = "Images that are at least this many percent of the same color are filtered out (negative to disable)"
+    )]
+    one_color_threshold: f64,
 
-args! {
-    #[derive(Copy, Clone)]
-    OneColor {
-        "Images that are at least this many percent of the same color (in grayscale) are filtered out (negative to disable)"
-        one_color_threshold: f64 = 90.0;
+    #[arg(
+        long,
+        default_value_t = 20,
+        help = "Tolerance when determining if two colors are the same."
+    )]
+    one_color_tolerance: u8,
 
-        "Tolerance when determining if two gray colors are the same."
-        one_color_tolerance: u8 = 20;
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ColorChannel::Luma709,
+        help = "Which channel to measure color dominance on"
+    )]
+    one_color_channel: ColorChannel,
+}
+
+#[derive(Copy, Clone)]
+pub struct OneColorArgs {
+    one_color_threshold: f64,
+    one_color_tolerance: u8,
+    one_color_channel: ColorChannel,
+}
+
+impl std::default::Default for OneColorArgs {
+    fn default() -> Self {
+        Self {
+            one_color_threshold: 90.0,
+            one_color_tolerance: 20,
+            one_color_channel: ColorChannel::Luma709,
+        }
     }
 }
 
 impl OneColorArgs {
-    pub fn one_color(self, img: &RgbImage) -> f64 {
-        self.one_color_gray(&grayscale(img))
+    pub fn one_color_threshold(mut self, one_color_threshold: f64) -> Self {
+        self.one_color_threshold = one_color_threshold;
+        self
     }
 
-    pub fn one_color_gray(self, img: &GrayImage) -> f64 {
-        let most_common = most_common_gray(img);
-        percent_gray(img, most_common, self.one_color_tolerance)
+    pub fn one_color_tolerance(mut self, one_color_tolerance: u8) -> Self {
+        self.one_color_tolerance = one_color_tolerance;
+        self
+    }
+
+    pub fn one_color_channel(mut self, one_color_channel: ColorChannel) -> Self {
+        self.one_color_channel = one_color_channel;
+        self
+    }
+}
+
+impl OneColorCli {
+    pub fn to_args(&self) -> OneColorArgs {
+        OneColorArgs {
+            one_color_threshold: self.one_color_threshold,
+            one_color_tolerance: self.one_color_tolerance,
+            one_color_channel: self.one_color_channel,
+        }
+    }
+}
+
+impl OneColorArgs {
+    pub fn one_color(self, img: &RgbImage) -> f64 {
+        self.one_color_channel
+            .dominant_bucket_percent(img, self.one_color_tolerance)
     }
 
     pub fn is_value_too_one_color(self, one_color: f64) -> bool {
@@ -30,11 +83,6 @@ impl OneColorArgs {
     }
 
     pub fn is_too_one_color(self, img: &RgbImage) -> bool {
-        self.is_too_one_color_gray(&grayscale(img))
-    }
-
-    pub fn is_too_one_color_gray(self, img: &GrayImage) -> bool {
-        self.one_color_threshold >= 0.0
-            && self.is_value_too_one_color(self.one_color_gray(img))
+        self.one_color_threshold >= 0.0 && self.is_value_too_one_color(self.one_color(img))
     }
 }
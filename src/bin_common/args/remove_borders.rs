@@ -1,4 +1,4 @@
-use crate::utils::imgutils::{maskify, watermark_getbbox, Mask};
+use crate::utils::imgutils::{self, maskify, watermark_getbbox, Mask};
 
 use super::args_helper::args;
 use image::{imageops::crop_imm, GrayImage, RgbImage, SubImage};
@@ -13,6 +13,12 @@ args! {
 
         "A mask line can contain this many percent of white and still be considered black"
         remove_borders_maximum_whites: f64 = 0.1;
+
+        "Rectify a tilted/perspective-warped quadrilateral of content instead of only cropping axis-aligned bars"
+        remove_borders_rectify: bool = false;
+
+        "Pixels of margin added around a rectified quadrilateral, to absorb slight corner-estimation overflow"
+        remove_borders_rectify_margin: u32 = 4;
     }
 }
 
@@ -35,4 +41,14 @@ impl RemoveBordersArgs {
     pub fn maskify(self, img: GrayImage) -> Mask {
         maskify(img, self.remove_borders_maskify_threshold)
     }
+
+    pub fn rectify_enabled(self) -> bool {
+        self.remove_borders_rectify
+    }
+
+    /// See [`imgutils::rectify_mask`]. `None` if no stable quadrilateral of content
+    /// could be found in `mask`.
+    pub fn rectify_mask(self, img: &RgbImage, mask: &Mask) -> Option<RgbImage> {
+        imgutils::rectify_mask(img, mask, self.remove_borders_rectify_margin)
+    }
 }
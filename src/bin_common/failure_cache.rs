@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch, HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+use crate::bktree::source_types::video_source::FileStamp;
+use crate::utils::simple_path::SimplePath;
+
+type RecordsSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<1024>, AllocScratch>,
+>;
+
+/// Why a source failed to ingest, recorded alongside it in a [`FailureCache`] so the
+/// log message on a cache hit still says something useful without re-running the
+/// extractor.
+#[derive(Serialize, Archive, Clone, Copy, Debug, PartialEq, Eq)]
+#[archive(check_bytes)]
+pub enum FailureCategory {
+    /// Opening or decoding the video itself failed, e.g. an unreadable container, a
+    /// missing codec, or a corrupt frame partway through.
+    ExtractorOpen,
+    /// The extractor opened fine, but decoding produced no usable frames at all (every
+    /// one was blank, ignored, or otherwise filtered out).
+    NoUsableFrames,
+    /// The video's own reported length is too short to sample anything meaningful
+    /// from.
+    TooShort,
+}
+
+impl fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            FailureCategory::ExtractorOpen => "failed to open",
+            FailureCategory::NoUsableFrames => "no usable frames",
+            FailureCategory::TooShort => "too short",
+        };
+        f.write_str(s)
+    }
+}
+
+/// What's actually persisted to disk via rkyv, one per video that failed to ingest.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+struct FailureRecord {
+    path: String,
+    stamp: FileStamp,
+    category: FailureCategory,
+    error: String,
+}
+
+impl From<&ArchivedFailureRecord> for FailureRecord {
+    fn from(value: &ArchivedFailureRecord) -> Self {
+        Self {
+            path: value.path.to_string(),
+            stamp: value.stamp.to_owned(),
+            category: value.category,
+            error: value.error.to_string(),
+        }
+    }
+}
+
+/// What a cache hit in [`FailureCache::get`] reports back about a previous failure.
+#[derive(Clone, Debug)]
+pub struct CachedFailure {
+    pub category: FailureCategory,
+    pub error: String,
+}
+
+/// A persistent `path -> (size, mtime, category, error)` negative cache, so a video
+/// that reliably fails to ingest (a corrupt container, a missing codec, zero usable
+/// frames, one too short to sample) isn't redecoded and re-attempted on every run. A
+/// record is only trusted while the file it was recorded from still has the exact size
+/// and modification time it had when it failed, following the same `(path, size,
+/// modified_date)` keying [`crate::bktree::source_types::video_source::FileStamp`]
+/// already uses for the main database; anything else is treated as a miss, so an
+/// edited-in-place file gets a fresh attempt.
+pub struct FailureCache {
+    records: HashMap<PathBuf, FailureRecord>,
+    dirty: bool,
+}
+
+impl FailureCache {
+    pub fn empty() -> Self {
+        Self {
+            records: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the cache from `file`, pruning any record whose path no longer exists on
+    /// disk. A missing `file` is treated the same as an empty cache.
+    pub fn load(file: impl AsRef<Path>) -> eyre::Result<Self> {
+        let file = file.as_ref();
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::empty()),
+            Err(e) => {
+                return Err(e).wrap_err_with(|| format!("failed to read {}", file.display()))
+            }
+        };
+
+        let archived = rkyv::check_archived_root::<Vec<FailureRecord>>(&bytes)
+            .map_err(|e| eyre::eyre!("corrupt failure cache at {}: {e}", file.display()))?;
+
+        let records = archived
+            .iter()
+            .map(FailureRecord::from)
+            .filter(|record| Path::new(&record.path).exists())
+            .map(|record| (PathBuf::from(&record.path), record))
+            .collect();
+
+        Ok(Self {
+            records,
+            dirty: false,
+        })
+    }
+
+    /// Returns why `path` failed last time, or `None` if there's no record or the
+    /// file's size/mtime no longer matches what was recorded, either of which means the
+    /// caller should give it a fresh attempt.
+    pub fn get(&self, path: &SimplePath) -> eyre::Result<Option<CachedFailure>> {
+        let Some(record) = self.records.get(path.as_path()) else {
+            return Ok(None);
+        };
+
+        let stamp = FileStamp::of(path.as_path())
+            .wrap_err_with(|| format!("failed to stat {path}"))?;
+        if record.stamp != stamp {
+            return Ok(None);
+        }
+
+        Ok(Some(CachedFailure {
+            category: record.category,
+            error: record.error.clone(),
+        }))
+    }
+
+    /// Records that `path` (as it currently stands on disk) failed with `category` and
+    /// `error`, so a later [`Self::get`] skips it until it changes.
+    pub fn put(
+        &mut self,
+        path: &SimplePath,
+        category: FailureCategory,
+        error: impl fmt::Display,
+    ) -> eyre::Result<()> {
+        let stamp =
+            FileStamp::of(path.as_path()).wrap_err_with(|| format!("failed to stat {path}"))?;
+
+        self.records.insert(
+            path.as_path().to_path_buf(),
+            FailureRecord {
+                path: path.to_string(),
+                stamp,
+                category,
+                error: error.to_string(),
+            },
+        );
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Drops every record whose path no longer exists on disk. Redundant right after
+    /// [`Self::load`] (which already does this), but worth calling again before
+    /// [`Self::save`] in a long-running process where files may have disappeared
+    /// in the meantime.
+    pub fn prune_missing(&mut self) {
+        let before = self.records.len();
+        self.records.retain(|path, _| path.exists());
+        if self.records.len() != before {
+            self.dirty = true;
+        }
+    }
+
+    /// Drops every record, so the next run gives every source a fresh attempt. Used by
+    /// `--retry-failed`.
+    pub fn clear(&mut self) {
+        if !self.records.is_empty() {
+            self.records.clear();
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the cache back to `file`, if anything changed since it was loaded.
+    pub fn save(&self, file: impl AsRef<Path>) -> eyre::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let file = file.as_ref();
+        let records: Vec<FailureRecord> = self.records.values().cloned().collect();
+        let bytes =
+            serialize_records(&records).wrap_err("failed to serialize the failure cache")?;
+        fs::write(file, bytes).wrap_err_with(|| format!("failed to write {}", file.display()))
+    }
+}
+
+fn serialize_records(records: &Vec<FailureRecord>) -> eyre::Result<AlignedVec> {
+    let mut seri = RecordsSerializer::default();
+    seri.serialize_value(records)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{self, Context};
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch,
+            HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+use crate::imghash::hamming::Hamming;
+
+type RecordsSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<1024>, AllocScratch>,
+>;
+
+/// The two hashes [`super::ignored_hashes::read_ignored`] computes for a reference
+/// image: the one straight off disk, and the one for the left-right mirrored picture.
+/// Either can be `None` if that variant turned out empty after border removal.
+#[derive(Clone, Copy, Debug)]
+pub struct CachedHashes {
+    pub normal: Option<Hamming>,
+    pub mirrored: Option<Hamming>,
+}
+
+/// What's actually persisted to disk via rkyv, one per cached file.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+struct CacheRecord {
+    path: String,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    size: u64,
+    normal: Option<Hamming>,
+    mirrored: Option<Hamming>,
+}
+
+impl From<&ArchivedCacheRecord> for CacheRecord {
+    fn from(value: &ArchivedCacheRecord) -> Self {
+        Self {
+            path: value.path.to_string(),
+            mtime_secs: value.mtime_secs,
+            mtime_nanos: value.mtime_nanos,
+            size: value.size,
+            normal: value.normal,
+            mirrored: value.mirrored,
+        }
+    }
+}
+
+/// A persistent `path -> (mtime, size, hashes)` table so repeated scans of the same
+/// reference-image directory don't have to decode and hash every file again. A record
+/// is only trusted while the file it was computed from still has the exact mtime and
+/// size it had when cached; anything else is treated as a miss.
+pub struct FileHashCache {
+    records: HashMap<PathBuf, CacheRecord>,
+    dirty: bool,
+}
+
+impl FileHashCache {
+    pub fn empty() -> Self {
+        Self {
+            records: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the cache from `file`, pruning any record whose path no longer exists on
+    /// disk. A missing `file` is treated the same as an empty cache.
+    pub fn load(file: impl AsRef<Path>) -> eyre::Result<Self> {
+        let file = file.as_ref();
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::empty()),
+            Err(e) => return Err(e).wrap_err_with(|| format!("failed to read {}", file.display())),
+        };
+
+        let archived = rkyv::check_archived_root::<Vec<CacheRecord>>(&bytes)
+            .map_err(|e| eyre::eyre!("corrupt hash cache at {}: {e}", file.display()))?;
+
+        let records = archived
+            .iter()
+            .map(CacheRecord::from)
+            .filter(|record| Path::new(&record.path).exists())
+            .map(|record| (PathBuf::from(&record.path), record))
+            .collect();
+
+        Ok(Self {
+            records,
+            dirty: false,
+        })
+    }
+
+    /// Returns the cached hashes for `path`, or `None` if there's no record or the
+    /// file's mtime/size no longer matches what was cached, either of which means the
+    /// caller should recompute and [`Self::put`] the fresh result.
+    pub fn get(&self, path: impl AsRef<Path>) -> eyre::Result<Option<CachedHashes>> {
+        let path = path.as_ref();
+        let Some(record) = self.records.get(path) else {
+            return Ok(None);
+        };
+
+        let meta = fs::symlink_metadata(path)
+            .wrap_err_with(|| format!("failed to stat {}", path.display()))?;
+        let (mtime_secs, mtime_nanos) = split_mtime(meta.modified()?)?;
+
+        if record.mtime_secs != mtime_secs || record.mtime_nanos != mtime_nanos || record.size != meta.len() {
+            return Ok(None);
+        }
+
+        Ok(Some(CachedHashes {
+            normal: record.normal,
+            mirrored: record.mirrored,
+        }))
+    }
+
+    pub fn put(
+        &mut self,
+        path: impl AsRef<Path>,
+        normal: Option<Hamming>,
+        mirrored: Option<Hamming>,
+    ) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let meta = fs::symlink_metadata(path)
+            .wrap_err_with(|| format!("failed to stat {}", path.display()))?;
+        let (mtime_secs, mtime_nanos) = split_mtime(meta.modified()?)?;
+
+        self.records.insert(
+            path.to_path_buf(),
+            CacheRecord {
+                path: path.to_string_lossy().into_owned(),
+                mtime_secs,
+                mtime_nanos,
+                size: meta.len(),
+                normal,
+                mirrored,
+            },
+        );
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Writes the cache back to `file`, if anything changed since it was loaded.
+    pub fn save(&self, file: impl AsRef<Path>) -> eyre::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let file = file.as_ref();
+        let records: Vec<CacheRecord> = self.records.values().cloned().collect();
+        let bytes =
+            serialize_records(&records).wrap_err("failed to serialize the hash cache")?;
+        fs::write(file, bytes).wrap_err_with(|| format!("failed to write {}", file.display()))
+    }
+}
+
+fn serialize_records(records: &Vec<CacheRecord>) -> eyre::Result<AlignedVec> {
+    let mut seri = RecordsSerializer::default();
+    seri.serialize_value(records)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
+
+fn split_mtime(mtime: SystemTime) -> eyre::Result<(u64, u32)> {
+    let dur = mtime
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("file mtime is before the unix epoch")?;
+    Ok((dur.as_secs(), dur.subsec_nanos()))
+}
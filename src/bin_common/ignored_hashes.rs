@@ -1,15 +1,29 @@
 use std::path::Path;
 
 use color_eyre::eyre::{self, Context};
+use rayon::prelude::*;
 
+use super::hash_cache::FileHashCache;
 use crate::imghash::{
     hamming::Hamming,
     preproc::{PreprocArgs, PreprocError},
     similarity::SimiArgs,
 };
-use crate::utils::fsutils::all_files;
+use crate::utils::image_source::{all_image_sources, ImageSource};
 use crate::utils::imgutils;
 
+/// The outcome of hashing a single [`ImageSource`] during the parallel map phase of
+/// [`read_ignored`]: whether it needs writing back to the [`FileHashCache`] is decided
+/// afterwards, sequentially, since only a fresh hash needs to be written.
+enum HashOutcome {
+    Cached(Option<Hamming>, Option<Hamming>),
+    Fresh(Option<Hamming>, Option<Hamming>),
+}
+
+/// Name of the [`FileHashCache`] file kept inside the ignore directory, so
+/// [`read_ignored`] doesn't redecode and rehash every reference image on every run.
+const CACHE_FILENAME: &str = ".imgdup_hash_cache";
+
 pub struct Ignored {
     hashes: Vec<Hamming>,
 }
@@ -39,30 +53,62 @@ pub fn read_ignored(
     preproc: &PreprocArgs,
     simi: &SimiArgs,
 ) -> eyre::Result<Ignored> {
-    let all_files: Vec<_> = all_files([dir]).wrap_err("failed to read dir")?;
-    let mut hashes = Vec::with_capacity(all_files.len());
-    let mut hashes_path = Vec::with_capacity(all_files.len());
-    let mut hashes_mirrored: Vec<bool> = Vec::with_capacity(all_files.len());
-
-    for file in all_files.iter() {
-        let mut img = image::open(&file)
-            .wrap_err_with(|| format!("could not open {} as an image", file.display()))?
-            .to_rgb8();
-
-        for mirrored in [false, true] {
-            if mirrored {
-                img = imgutils::mirror(img);
+    let dir = dir.as_ref();
+    let cache_path = dir.join(CACHE_FILENAME);
+    let mut cache = FileHashCache::load(&cache_path)
+        .wrap_err_with(|| format!("failed to load the hash cache at {}", cache_path.display()))?;
+
+    let all_sources: Vec<ImageSource> =
+        all_image_sources([dir]).wrap_err("failed to read dir")?;
+
+    // Parallel map phase: decode and hash every source that isn't already in the cache.
+    // NOTE: the cache is keyed on a real file's identity (mtime + size), which an
+    // archive member doesn't have on its own, so members are always rehashed.
+    let outcomes: Vec<HashOutcome> = all_sources
+        .par_iter()
+        .map(|source| -> eyre::Result<HashOutcome> {
+            if let ImageSource::File(path) = source {
+                if let Some(cached) = cache.get(path).wrap_err_with(|| {
+                    format!("failed to look up the hash cache for {}", path.display())
+                })? {
+                    return Ok(HashOutcome::Cached(cached.normal, cached.mirrored));
+                }
             }
 
-            let hash = match preproc.hash_img(&img) {
-                Ok(hash) => hash,
-                Err(PreprocError::Empty) => {
-                    log::error!(
+            let (normal, mirrored) = hash_source(source, preproc)?;
+            Ok(HashOutcome::Fresh(normal, mirrored))
+        })
+        .collect::<eyre::Result<Vec<_>>>()
+        .wrap_err("failed to hash the ignored files")?;
+
+    // Sequential reconcile phase: fill in any cache misses and run the "is the same as"
+    // dedup/warn logic in the original, deterministic order.
+    let mut hashes = Vec::with_capacity(all_sources.len());
+    let mut hashes_source = Vec::with_capacity(all_sources.len());
+    let mut hashes_mirrored: Vec<bool> = Vec::with_capacity(all_sources.len());
+
+    for (source, outcome) in all_sources.iter().zip(outcomes) {
+        let logical_path = source.logical_path();
+
+        let (normal, mirrored) = match outcome {
+            HashOutcome::Cached(normal, mirrored) => (normal, mirrored),
+            HashOutcome::Fresh(normal, mirrored) => {
+                if let ImageSource::File(path) = source {
+                    cache.put(path, normal, mirrored).wrap_err_with(|| {
+                        format!("failed to update the hash cache for {}", path.display())
+                    })?;
+                }
+                (normal, mirrored)
+            }
+        };
+
+        for (mirrored, hash) in [(false, normal), (true, mirrored)] {
+            let Some(hash) = hash else {
+                log::error!(
                     "The ignored file '{}' is empty after border removal (mirror={mirrored})",
-                    file.display()
+                    logical_path.display()
                 );
-                    continue;
-                }
+                continue;
             };
 
             let the_same: Vec<_> = hashes
@@ -70,24 +116,47 @@ pub fn read_ignored(
                 .enumerate()
                 .filter(|(_, ignore)| simi.are_similar(hash, **ignore))
                 .filter(|(i, _)| !hashes_mirrored[*i])
-                .map(|(i, _)| &hashes_path[i])
-                .filter(|coll_path| coll_path != &&file)
+                .map(|(i, _)| &hashes_source[i])
+                .filter(|coll_source| **coll_source != source)
+                .map(|coll_source| coll_source.logical_path())
                 .collect();
 
             if !the_same.is_empty() {
                 log::warn!(
                     "The ignored file '{}' (mirrored={mirrored}) is the same as: {:?}",
-                    file.display(),
+                    logical_path.display(),
                     the_same,
                 );
                 continue;
             }
 
             hashes.push(hash);
-            hashes_path.push(file);
+            hashes_source.push(source);
             hashes_mirrored.push(mirrored);
         }
     }
 
+    cache
+        .save(&cache_path)
+        .wrap_err_with(|| format!("failed to save the hash cache to {}", cache_path.display()))?;
+
     Ok(Ignored { hashes })
 }
+
+/// Decodes `source` and hashes it both as-is and mirrored, for a cache miss.
+fn hash_source(
+    source: &ImageSource,
+    preproc: &PreprocArgs,
+) -> eyre::Result<(Option<Hamming>, Option<Hamming>)> {
+    let img = source.open()?;
+
+    let hash_one = |img| match preproc.hash_img(img) {
+        Ok(hash) => Some(hash),
+        Err(PreprocError::Empty) => None,
+    };
+
+    let normal = hash_one(&img);
+    let mirrored = hash_one(&imgutils::mirror(img));
+
+    Ok((normal, mirrored))
+}
@@ -1,4 +1,8 @@
-use std::{fs::File, path::Path};
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
 use color_eyre::eyre::{self, Context};
 
@@ -16,7 +20,26 @@ pub fn init_eyre() -> eyre::Result<()> {
         .wrap_err("Failed to install eyre")
 }
 
-pub fn init_logger(logfile: Option<&Path>) -> eyre::Result<()> {
+/// Name of the environment variable [`init_logger`] reads per-target level overrides
+/// from, in addition to whatever is passed to it directly. See [`parse_target_levels`]
+/// for the format.
+pub const LOG_TARGETS_ENV: &str = "IMGDUP_LOG_TARGETS";
+
+/// Where to write the disk log, and how to bound its size, passed to [`init_logger`].
+/// The file is rotated to `path.1`, `path.2`, ... once it reaches `max_bytes`, keeping
+/// at most `keep` old files around, so a long-running unattended scan doesn't fill the
+/// disk with one ever-growing log.
+#[derive(Debug, Clone)]
+pub struct LogFileArgs {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub keep: usize,
+}
+
+pub fn init_logger(
+    logfile: Option<LogFileArgs>,
+    target_levels: &[(String, log::LevelFilter)],
+) -> eyre::Result<()> {
     use simplelog::*;
 
     let mut builder = ConfigBuilder::new();
@@ -46,32 +69,220 @@ pub fn init_logger(logfile: Option<&Path>) -> eyre::Result<()> {
         log_color,
     )];
 
-    let logfile_failed = logfile.and_then(|logfile| match File::create(logfile) {
-        Ok(f) => {
-            loggers.push(WriteLogger::new(level, builder.build(), f));
-            None
+    let logfile_failed = logfile.as_ref().and_then(|logfile| {
+        match RotatingFile::open(&logfile.path, logfile.max_bytes, logfile.keep) {
+            Ok(f) => {
+                loggers.push(WriteLogger::new(level, builder.build(), f));
+                None
+            }
+            Err(e) => Some(e),
         }
-        Err(e) => Some(e),
     });
 
-    CombinedLogger::init(loggers).wrap_err("Failed to set the logger")?;
+    let mut overrides = target_levels.to_vec();
+    overrides.extend(target_levels_from_env()?);
+    let max_level = overrides
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(level, LevelFilter::max);
+
+    log::set_max_level(max_level);
+    log::set_boxed_logger(Box::new(TargetFilteredLogger {
+        inner: CombinedLogger::new(loggers),
+        overrides,
+        default_level: level,
+    }))
+    .wrap_err("Failed to set the logger")?;
 
     if timezone_failed {
-        log::error!(
-            "Failed to set time zone for the logger, using UTC instead (I think)"
-        );
+        log::error!("Failed to set time zone for the logger, using UTC instead (I think)");
     }
 
     if let Some(logfile) = logfile {
         if let Some(e) = logfile_failed {
             log::error!(
                 "Failed to create the log file at '{}' because: {e}",
-                logfile.display()
+                logfile.path.display()
             );
         } else {
-            log::debug!("Logging to: {}", logfile.display());
+            log::debug!("Logging to: {}", logfile.path.display());
         }
     }
 
     Ok(())
 }
+
+/// Wraps a [`simplelog::CombinedLogger`] to additionally filter by `target`, since
+/// `simplelog`'s own [`simplelog::Config`] only supports one global level, not per-target
+/// ones. The first entry in `overrides` whose target is a prefix of the record's target
+/// wins, so a more specific prefix should be listed before a broader one; anything that
+/// matches nothing falls back to `default_level`.
+struct TargetFilteredLogger {
+    inner: Box<simplelog::CombinedLogger>,
+    overrides: Vec<(String, log::LevelFilter)>,
+    default_level: log::LevelFilter,
+}
+
+impl TargetFilteredLogger {
+    fn level_for(&self, target: &str) -> log::LevelFilter {
+        self.overrides
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default_level)
+    }
+}
+
+impl log::Log for TargetFilteredLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target()) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= self.level_for(record.target()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Reads [`LOG_TARGETS_ENV`], if set, and parses it with [`parse_target_levels`].
+fn target_levels_from_env() -> eyre::Result<Vec<(String, log::LevelFilter)>> {
+    match env::var(LOG_TARGETS_ENV) {
+        Ok(value) => parse_target_levels(&value)
+            .wrap_err_with(|| format!("failed to parse {LOG_TARGETS_ENV}")),
+        Err(env::VarError::NotPresent) => Ok(Vec::new()),
+        Err(e @ env::VarError::NotUnicode(_)) => Err(e).wrap_err(LOG_TARGETS_ENV),
+    }
+}
+
+/// Parses `target=level,target2=level2,...` into target-prefix/level pairs, in the order
+/// given, suitable for [`init_logger`]'s `target_levels`.
+pub fn parse_target_levels(s: &str) -> eyre::Result<Vec<(String, log::LevelFilter)>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (target, level) = entry
+                .split_once('=')
+                .ok_or_else(|| eyre::eyre!("missing '=' in target level entry: {entry:?}"))?;
+            let level = level
+                .trim()
+                .parse()
+                .wrap_err_with(|| format!("invalid level in entry: {entry:?}"))?;
+            Ok((target.trim().to_string(), level))
+        })
+        .collect()
+}
+
+/// A [`Write`] implementation that rolls `path` over to `path.1`, `path.2`, ... once it
+/// grows past `max_bytes`, deleting whatever was at `path.keep` to keep disk usage
+/// bounded for long-running, unattended scans.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: fs::File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = fs::File::options().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            keep,
+            file,
+            written,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            self.file = fs::File::create(&self.path)?;
+            self.written = 0;
+            return Ok(());
+        }
+
+        let oldest = Self::rotated_path(&self.path, self.keep);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+
+        for generation in (1..self.keep).rev() {
+            let from = Self::rotated_path(&self.path, generation);
+            if from.exists() {
+                fs::rename(&from, Self::rotated_path(&self.path, generation + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, Self::rotated_path(&self.path, 1))?;
+        self.file = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+
+    fn rotated_path(path: &Path, generation: usize) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{generation}"));
+        PathBuf::from(name)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written >= self.max_bytes {
+            self.rotate()?;
+        }
+
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_empty_string_to_no_overrides() {
+        assert_eq!(
+            Vec::<(String, log::LevelFilter)>::new(),
+            parse_target_levels("").unwrap()
+        );
+    }
+
+    #[test]
+    fn parses_multiple_entries_in_order() {
+        assert_eq!(
+            vec![
+                ("frame_extractor".to_string(), log::LevelFilter::Warn),
+                ("imgdup::bktree".to_string(), log::LevelFilter::Trace),
+            ],
+            parse_target_levels("frame_extractor=warn, imgdup::bktree=trace").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_an_entry_with_no_level() {
+        assert!(parse_target_levels("frame_extractor").is_err());
+    }
+
+    #[test]
+    fn rejects_an_unknown_level() {
+        assert!(parse_target_levels("frame_extractor=noisy").is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::Write;
+use std::sync::mpsc;
+
+use color_eyre::eyre::{self, Context};
+use serde::Serialize;
+
+/// One update from the video/tree workers, meant for a supervising GUI/daemon rather
+/// than the log file. Mirrors czkawka's `ProgressData`, but as a stream of discrete
+/// events instead of a single polled struct, since `imgdup` is already event-driven.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// A new video started being hashed.
+    FileStarted { path: String },
+    /// A video finished hashing successfully.
+    FileHashed {
+        path: String,
+        frames: usize,
+        elapsed_secs: f64,
+    },
+    /// A video failed to be hashed; `kind` is the [`FailureCategory`](super::failure_cache::FailureCategory)'s `Display` form.
+    FileFailed { path: String, kind: String },
+    /// A video was found to collide with one or more videos already in the tree.
+    DuplicateFound { new: String, others: Vec<String> },
+    /// Periodic update on how many of the new files have been fully processed.
+    StageProgress { done: usize, total: usize },
+}
+
+/// Drains `rx` and writes each [`ProgressEvent`] to `out` as one line of JSON, so an
+/// external frontend can follow along without scraping log text. Returns once every
+/// sender has been dropped.
+pub fn report(rx: mpsc::Receiver<ProgressEvent>, mut out: impl Write) -> eyre::Result<()> {
+    while let Ok(event) = rx.recv() {
+        let line =
+            serde_json::to_string(&event).wrap_err("failed to serialize a progress event")?;
+        writeln!(out, "{line}").wrap_err("failed to write a progress event")?;
+        out.flush().wrap_err("failed to flush a progress event")?;
+    }
+    Ok(())
+}
@@ -1,13 +1,21 @@
+pub mod mih;
+pub mod mmap;
+pub mod mount;
+pub mod source_types;
+
 use std::{
     collections::{HashMap, HashSet},
+    fs,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
-    heap::{self, Heap, HeapBuilder, Ref},
+    heap::{self, CheckReport, Heap, HeapBuilder, Ref},
     imghash::hamming::{Distance, Hamming},
+    termination::Cookie,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -17,82 +25,281 @@ struct BKNode<S> {
     children: HashMap<Distance, Ref>,
 }
 
+/// Dead-node ratio above which [`BKTree::rebuild`] is triggered automatically, see
+/// [`BKTree::rebuild_threshold`].
+pub const DEFAULT_REBUILD_THRESHOLD: f64 = 0.5;
+
+/// Number of items [`BKTree::queue`] accumulates before flushing on its own, see
+/// [`BKTree::queue_batch_size`].
+pub const DEFAULT_QUEUE_BATCH_SIZE: usize = 256;
+
+/// How long [`BKTree::queue`] lets an item sit unflushed before flushing anyway, see
+/// [`BKTree::queue_debounce`].
+pub const DEFAULT_QUEUE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How a [`BKTree`]'s [`Heap`] was opened, so [`BKTree::rebuild`] knows how to recreate
+/// it once the old one is discarded.
+enum Backing {
+    InMemory,
+    File(PathBuf),
+}
+
+/// Outcome of a [`Cookie`]-aware bulk operation like [`BKTree::add_all`],
+/// [`BKTree::for_each`], or [`BKTree::remove_any_of`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    /// Every item was processed and committed.
+    Finished,
+    /// `cookie.is_terminating()` became true partway through. The `processed` items
+    /// seen so far were committed and the rest were left untouched, so the caller can
+    /// resume later instead of starting over.
+    Interrupted { processed: usize },
+    /// `cookie.is_terminating_hard()` became true partway through. Everything done
+    /// during this call was rolled back, so nothing was persisted.
+    Aborted,
+}
+
 pub struct BKTree<S> {
     db: Heap<BKNode<S>>,
+    backing: Backing,
+    rebuild_threshold: f64,
+    // -- indexing queue, see `BKTree::queue` --
+    pending: Vec<(Hamming, S)>,
+    pending_since: Option<Instant>,
+    queue_batch_size: usize,
+    queue_debounce: Duration,
 }
 
-// TODO: rebuild. Ska det bara en grej i imgdup-edit? Kunna hämta percent dead och annan
-// data vore najs. Skötas automatiskt på flush?
 impl<S> BKTree<S>
 where
     S: Serialize + DeserializeOwned,
 {
     pub fn from_file(file: impl AsRef<Path>) -> heap::Result<Self> {
-        let db = HeapBuilder::new().from_file(file)?;
-        Ok(Self::new(db))
+        let path = file.as_ref().to_path_buf();
+        let db = HeapBuilder::new().from_file(&path)?;
+        Ok(Self::new(db, Backing::File(path)))
     }
 
     pub fn in_memory() -> heap::Result<Self> {
-        Ok(Self::new(Heap::new_in_memory()?))
+        Ok(Self::new(Heap::new_in_memory()?, Backing::InMemory))
     }
 
-    fn new(db: Heap<BKNode<S>>) -> Self {
-        Self { db }
+    /// Upgrades an on-disk database at `file` to the format version this build
+    /// expects, so that it can subsequently be opened with [`BKTree::from_file`]. This
+    /// is what an `upgrade` CLI subcommand should call before touching a hash database
+    /// that was written by an older release, letting users carry it forward instead of
+    /// rebuilding it from scratch.
+    pub fn upgrade_file(file: impl AsRef<Path>) -> heap::Result<()> {
+        Heap::<BKNode<S>>::upgrade(file)
     }
 
-    pub fn close(self) -> heap::Result<()> {
+    fn new(db: Heap<BKNode<S>>, backing: Backing) -> Self {
+        Self {
+            db,
+            backing,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+            pending: Vec::new(),
+            pending_since: None,
+            queue_batch_size: DEFAULT_QUEUE_BATCH_SIZE,
+            queue_debounce: DEFAULT_QUEUE_DEBOUNCE,
+        }
+    }
+
+    /// Dead nodes (tombstones left behind by [`BKTree::remove_any_of`]) above this
+    /// fraction of the tree make [`BKTree::remove_any_of`] trigger a [`BKTree::rebuild`]
+    /// automatically. Defaults to [`DEFAULT_REBUILD_THRESHOLD`].
+    pub fn rebuild_threshold(mut self, rebuild_threshold: f64) -> Self {
+        self.rebuild_threshold = rebuild_threshold;
+        self
+    }
+
+    /// How many [`BKTree::queue`]d items accumulate before [`BKTree::flush_queue`] runs
+    /// automatically. Defaults to [`DEFAULT_QUEUE_BATCH_SIZE`].
+    pub fn queue_batch_size(mut self, queue_batch_size: usize) -> Self {
+        self.queue_batch_size = queue_batch_size;
+        self
+    }
+
+    /// How long a [`BKTree::queue`]d item can sit unflushed before [`BKTree::flush_queue`]
+    /// runs automatically. Defaults to [`DEFAULT_QUEUE_DEBOUNCE`].
+    pub fn queue_debounce(mut self, queue_debounce: Duration) -> Self {
+        self.queue_debounce = queue_debounce;
+        self
+    }
+
+    /// Flushes any [`BKTree::queue`]d items before closing, so nothing staged in memory
+    /// is silently lost.
+    pub fn close(mut self) -> heap::Result<()> {
+        self.flush_queue()?;
         self.db.close()
     }
 
-    // TODO: räkna antalet levande noder och antalet döda noder
+    /// Validates the invariants the underlying [`Heap`] assumes, without changing
+    /// anything. See [`Heap::check`].
+    pub fn check(&mut self) -> heap::Result<CheckReport> {
+        self.db.check()
+    }
+
+    /// Like [`BKTree::check`], but also fixes what it finds. See [`Heap::repair`].
+    pub fn repair(&mut self, clear_dangling_root: bool) -> heap::Result<CheckReport> {
+        self.db.repair(clear_dangling_root)
+    }
+
+    /// Traverses the whole tree once and returns `(live, dead)` node counts. A node is
+    /// live if it still holds a value, and dead if it's a tombstone kept around only to
+    /// route to its children, see [`BKTree::remove_any_of`].
     pub fn count_nodes(&mut self) -> heap::Result<(usize, usize)> {
-        todo!()
+        let mut live = 0;
+        let mut dead = 0;
+        self.for_each_internal(
+            |node| {
+                if node.value.is_some() {
+                    live += 1;
+                } else {
+                    dead += 1;
+                }
+                false
+            },
+            |_| (),
+            None,
+        )?;
+        Ok((live, dead))
+    }
+
+    /// Fraction of nodes that are dead tombstones, see [`BKTree::count_nodes`]. `0.0` if
+    /// the tree is empty.
+    pub fn percent_dead(&mut self) -> heap::Result<f64> {
+        let (live, dead) = self.count_nodes()?;
+        Ok(dead_ratio(live, dead))
+    }
+
+    /// Collects every live `(Hamming, S)` pair, builds a brand new tree out of them, and
+    /// atomically swaps it in for the old one. A dead node may still be needed to route
+    /// to its children, so individual tombstones can't be freed in place; only a full
+    /// rebuild that drops every unreferenced one actually reclaims space.
+    pub fn rebuild(&mut self) -> heap::Result<()> {
+        self.flush_queue()?;
+
+        let mut live = Vec::new();
+        self.for_each_internal(
+            |node| node.value.is_some(),
+            |node| {
+                if let Some(value) = node.value.take() {
+                    live.push((node.hash, value));
+                }
+            },
+            None,
+        )?;
+
+        // Dropping the placeholder below closes nothing; the old `db` underneath it is
+        // explicitly closed right after, before anything touches its backing file.
+        let old_db = std::mem::replace(&mut self.db, Heap::new_in_memory()?);
+        old_db.close()?;
+
+        let mut fresh = match &self.backing {
+            Backing::InMemory => Heap::new_in_memory()?,
+            Backing::File(path) => HeapBuilder::new().from_file(rebuild_tmp_path(path))?,
+        };
+        for (hash, value) in live {
+            insert_node(&mut fresh, hash, value)?;
+        }
+
+        self.db = match &self.backing {
+            Backing::InMemory => fresh,
+            Backing::File(path) => {
+                fresh.close()?;
+                fs::rename(rebuild_tmp_path(path), path)?;
+                HeapBuilder::new().from_file(path)?
+            }
+        };
+
+        Ok(())
+    }
+
+    fn maybe_rebuild(&mut self) -> heap::Result<()> {
+        let (live, dead) = self.count_nodes()?;
+        if dead_ratio(live, dead) > self.rebuild_threshold {
+            self.rebuild()?;
+        }
+        Ok(())
     }
 
     pub fn add(&mut self, hash: Hamming, value: S) -> heap::Result<()> {
-        self.add_internal(hash, value)?;
+        insert_node(&mut self.db, hash, value)?;
         self.db.checkpoint()?;
         Ok(())
     }
 
+    /// Like repeatedly calling [`BKTree::add`], but checkpoints once for the whole
+    /// batch instead of once per item. If `cookie` is given, a soft termination signal
+    /// (see [`Cookie::is_terminating`]) commits everything added so far and returns
+    /// [`Completion::Interrupted`] with the count, while a hard one (see
+    /// [`Cookie::is_terminating_hard`]) rolls that back and returns
+    /// [`Completion::Aborted`] instead of continuing to insert.
     pub fn add_all(
         &mut self,
         items: impl IntoIterator<Item = (Hamming, S)>,
-    ) -> heap::Result<()> {
+        cookie: Option<&Cookie>,
+    ) -> heap::Result<Completion> {
+        let mut processed = 0;
         for (hash, value) in items {
-            self.add_internal(hash, value)?;
+            if cookie.is_some_and(Cookie::is_terminating_hard) {
+                self.db.abandon()?;
+                return Ok(Completion::Aborted);
+            }
+
+            insert_node(&mut self.db, hash, value)?;
+            processed += 1;
+
+            if cookie.is_some_and(Cookie::is_terminating) {
+                self.db.flush()?;
+                return Ok(Completion::Interrupted { processed });
+            }
         }
         self.db.checkpoint()?;
+        Ok(Completion::Finished)
+    }
+
+    /// Stages `(hash, value)` in memory instead of inserting it right away, flushing
+    /// the whole backlog with a single [`BKTree::flush_queue`] once either
+    /// [`BKTree::queue_batch_size`] items are pending or the oldest one has been
+    /// waiting longer than [`BKTree::queue_debounce`]. Unlike [`BKTree::add`], which
+    /// checkpoints after every call, this amortizes the WAL checkpoint cost across a
+    /// whole batch when ingesting many hashes in a row (e.g. all the frames of a
+    /// video). Staged items aren't visible to [`BKTree::find_within`] or
+    /// [`BKTree::for_each`] until they're flushed.
+    pub fn queue(&mut self, hash: Hamming, value: S) -> heap::Result<()> {
+        if self.pending.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending.push((hash, value));
+
+        let due = self.pending.len() >= self.queue_batch_size
+            || self
+                .pending_since
+                .is_some_and(|since| since.elapsed() >= self.queue_debounce);
+        if due {
+            self.flush_queue()?;
+        }
         Ok(())
     }
 
-    fn add_internal(&mut self, hash: Hamming, value: S) -> heap::Result<()> {
-        if self.db.root().is_null() {
-            let root = self.db.allocate(BKNode::new(hash, value))?;
-            self.db.set_root(root);
-        } else {
-            let mut cur_ref = self.db.root();
-            loop {
-                let cur_node = self.db.deref(cur_ref)?.expect("should have a value");
-                let dist = cur_node.hash.distance_to(hash);
-
-                if let Some(&child_ref) = cur_node.children.get(&dist) {
-                    cur_ref = child_ref;
-                } else {
-                    let new_ref =
-                        self.db.allocate_local(cur_ref, BKNode::new(hash, value))?;
-                    let cur_node = self
-                        .db
-                        .deref_mut(cur_ref)?
-                        .expect("the previous deref worked");
-
-                    cur_node.children.insert(dist, new_ref);
-                    break;
-                }
-            }
+    /// Inserts every item staged by [`BKTree::queue`] and checkpoints once for the
+    /// whole batch, instead of once per item. Nothing in the batch reaches the
+    /// on-disk database until the single checkpoint at the end commits, see
+    /// [`Heap::checkpoint`]; a crash or error partway through the batch leaves the
+    /// database exactly as it was before this call started.
+    pub fn flush_queue(&mut self) -> heap::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
         }
 
-        Ok(())
+        let batch = std::mem::take(&mut self.pending);
+        self.pending_since = None;
+        for (hash, value) in batch {
+            insert_node(&mut self.db, hash, value)?;
+        }
+        self.db.checkpoint()
     }
 
     // TODO: iterator interface would be nicer
@@ -109,9 +316,15 @@ where
             return Ok(());
         }
 
-        let mut stack = vec![self.db.root()];
-        while let Some(cur_ref) = stack.pop() {
-            let cur_node = self.db.deref(cur_ref)?.expect("should have a value");
+        let mut stack = vec![(Ref::null(), self.db.root())];
+        while let Some((parent_ref, cur_ref)) = stack.pop() {
+            let cur_node = self
+                .db
+                .deref(cur_ref)?
+                .ok_or(heap::HeapError::DanglingRef {
+                    from: parent_ref,
+                    to: cur_ref,
+                })?;
             let dist = cur_node.hash.distance_to(hash);
             if dist <= within {
                 if let Some(value) = &cur_node.value {
@@ -121,7 +334,7 @@ where
 
             for i in dist.saturating_sub(within)..=dist.saturating_add(within) {
                 if let Some(child_ref) = cur_node.children.get(&i) {
-                    stack.push(*child_ref);
+                    stack.push((cur_ref, *child_ref));
                 }
             }
         }
@@ -129,22 +342,39 @@ where
         Ok(())
     }
 
-    pub fn remove_any_of<P>(&mut self, mut predicate: P) -> heap::Result<()>
-    where
-        P: FnMut(&S) -> bool,
-    {
-        self.for_each_internal(
+    /// Like [`BKTree::for_each`], tombstoning rather than visiting every node whose
+    /// value matches `predicate`. See [`BKTree::for_each`] for `cookie` semantics; a
+    /// [`Completion::Aborted`] run skips the checkpoint and the [`BKTree::rebuild`]
+    /// auto-trigger, since nothing was actually persisted.
+    pub fn remove_any_of<P>(
+        &mut self,
+        mut predicate: P,
+        cookie: Option<&Cookie>,
+    ) -> heap::Result<Completion> {
+        let completion = self.for_each_internal(
             |node| node.value.as_ref().is_some_and(|value| predicate(value)),
             |node| node.value = None,
+            cookie,
         )?;
-        self.db.checkpoint()?;
-        Ok(())
+
+        if !matches!(completion, Completion::Aborted) {
+            self.db.checkpoint()?;
+            self.maybe_rebuild()?;
+        }
+
+        Ok(completion)
     }
 
     // TODO: an iterator iterface would probably be nicer. It could maybe yield instances
     // of some struct that has a getter, setter and remover to only make the BKNode dirty
     // when necessary.
-    pub fn for_each<F>(&mut self, mut visit: F) -> heap::Result<()>
+    /// Visits every live `(Hamming, &S)` pair. If `cookie` is given, a soft
+    /// termination signal (see [`Cookie::is_terminating`]) commits everything
+    /// tombstoned by a preceding modifier so far and returns
+    /// [`Completion::Interrupted`] with the count visited, while a hard one (see
+    /// [`Cookie::is_terminating_hard`]) rolls that back and returns
+    /// [`Completion::Aborted`] instead of continuing.
+    pub fn for_each<F>(&mut self, mut visit: F, cookie: Option<&Cookie>) -> heap::Result<Completion>
     where
         F: FnMut(Hamming, &S),
     {
@@ -156,6 +386,7 @@ where
                 false
             },
             |_| (),
+            cookie,
         )
     }
 
@@ -163,28 +394,57 @@ where
         &mut self,
         mut filter: F,
         mut modifier: M,
-    ) -> heap::Result<()>
+        cookie: Option<&Cookie>,
+    ) -> heap::Result<Completion>
     where
         F: FnMut(&BKNode<S>) -> bool,
         M: FnMut(&mut BKNode<S>),
     {
         let mut stack = Vec::new();
         if !self.db.root().is_null() {
-            stack.push(self.db.root());
+            stack.push((Ref::null(), self.db.root()));
         }
 
-        while let Some(cur_ref) = stack.pop() {
-            let cur_node = self.db.deref(cur_ref)?.expect("should have a value");
-            stack.extend(cur_node.children.values());
+        let mut processed = 0;
+        while let Some((parent_ref, cur_ref)) = stack.pop() {
+            if cookie.is_some_and(Cookie::is_terminating_hard) {
+                self.db.abandon()?;
+                return Ok(Completion::Aborted);
+            }
+
+            let cur_node = self
+                .db
+                .deref(cur_ref)?
+                .ok_or(heap::HeapError::DanglingRef {
+                    from: parent_ref,
+                    to: cur_ref,
+                })?;
+            stack.extend(
+                cur_node
+                    .children
+                    .values()
+                    .map(|&child_ref| (cur_ref, child_ref)),
+            );
 
             if filter(&cur_node) {
-                let cur_node =
-                    self.db.deref_mut(cur_ref)?.expect("previous deref worked");
+                let cur_node = self
+                    .db
+                    .deref_mut(cur_ref)?
+                    .ok_or(heap::HeapError::DanglingRef {
+                        from: cur_ref,
+                        to: cur_ref,
+                    })?;
                 modifier(cur_node);
             }
+
+            processed += 1;
+            if cookie.is_some_and(Cookie::is_terminating) {
+                self.db.flush()?;
+                return Ok(Completion::Interrupted { processed });
+            }
         }
 
-        Ok(())
+        Ok(Completion::Finished)
     }
 }
 
@@ -198,6 +458,67 @@ impl<S> BKNode<S> {
     }
 }
 
+/// Inserts `(hash, value)` into `db`, routing by [`Hamming::distance_to`] from an
+/// existing root the same way [`BKTree::add`] does. Free-standing so [`BKTree::rebuild`]
+/// can reuse it to populate a fresh `Heap` that isn't `self.db` yet.
+fn insert_node<S>(db: &mut Heap<BKNode<S>>, hash: Hamming, value: S) -> heap::Result<()>
+where
+    S: Serialize + DeserializeOwned,
+{
+    if db.root().is_null() {
+        let root = db.allocate(BKNode::new(hash, value))?;
+        db.set_root(root);
+    } else {
+        let mut parent_ref = Ref::null();
+        let mut cur_ref = db.root();
+        loop {
+            let cur_node = db.deref(cur_ref)?.ok_or(heap::HeapError::DanglingRef {
+                from: parent_ref,
+                to: cur_ref,
+            })?;
+            let dist = cur_node.hash.distance_to(hash);
+
+            if let Some(&child_ref) = cur_node.children.get(&dist) {
+                parent_ref = cur_ref;
+                cur_ref = child_ref;
+            } else {
+                let new_ref = db.allocate_local(cur_ref, BKNode::new(hash, value))?;
+                let cur_node = db.deref_mut(cur_ref)?.ok_or(heap::HeapError::DanglingRef {
+                    from: cur_ref,
+                    to: cur_ref,
+                })?;
+
+                cur_node.children.insert(dist, new_ref);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fraction of `live + dead` nodes that are dead, see [`BKTree::count_nodes`]. `0.0` if
+/// there are no nodes at all.
+fn dead_ratio(live: usize, dead: usize) -> f64 {
+    let total = live + dead;
+    if total == 0 {
+        0.0
+    } else {
+        dead as f64 / total as f64
+    }
+}
+
+/// Sibling path [`BKTree::rebuild`] writes the replacement file-backed `Heap` to before
+/// renaming it over the original, so a crash mid-rebuild never leaves a half-written
+/// database behind.
+fn rebuild_tmp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .expect("a file-backed BKTree always has a file name")
+        .to_string_lossy();
+    path.with_file_name(format!(".{file_name}.rebuild.tmp"))
+}
+
 #[cfg(test)]
 mod test {
     use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
@@ -210,9 +531,10 @@ mod test {
 
     fn contents(tree: &mut BKTree<PathBuf>) -> heap::Result<Vec<(Hamming, String)>> {
         let mut all = Vec::new();
-        tree.for_each(|ham, val| {
-            all.push((ham, val.clone().into_os_string().into_string().unwrap()))
-        })?;
+        tree.for_each(
+            |ham, val| all.push((ham, val.clone().into_os_string().into_string().unwrap())),
+            None,
+        )?;
         all.sort();
         Ok(all)
     }
@@ -252,7 +574,7 @@ mod test {
         tree.add(Hamming(0b100), value("4"))?;
 
         let rem: HashSet<PathBuf> = HashSet::from(["5_1".into()]);
-        tree.remove_any_of(|p| rem.contains(p))?;
+        tree.remove_any_of(|p| rem.contains(p), None)?;
 
         let all = contents(&mut tree)?;
 
@@ -264,6 +586,49 @@ mod test {
             all
         );
 
+        assert_eq!((2, 1), tree.count_nodes()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rebuild_drops_dead_nodes() -> heap::Result<()> {
+        let mut tree = BKTree::in_memory()?;
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        let rem: HashSet<PathBuf> = HashSet::from(["5_1".into()]);
+        tree.remove_any_of(|p| rem.contains(p), None)?;
+        assert_eq!((2, 1), tree.count_nodes()?);
+
+        tree.rebuild()?;
+        assert_eq!((2, 0), tree.count_nodes()?);
+
+        let all = contents(&mut tree)?;
+        assert_eq!(
+            vec![
+                (Hamming(0b100), "4".to_string()),
+                (Hamming(0b101), "5_2".to_string()),
+            ],
+            all
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_any_of_triggers_automatic_rebuild() -> heap::Result<()> {
+        let mut tree = BKTree::in_memory()?.rebuild_threshold(0.0);
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        let rem: HashSet<PathBuf> = HashSet::from(["5_1".into()]);
+        tree.remove_any_of(|p| rem.contains(p), None)?;
+
+        assert_eq!((2, 0), tree.count_nodes()?);
+
         Ok(())
     }
 
@@ -0,0 +1,187 @@
+//! A multi-index hashing (MIH) index over [`Hamming`] values, for sub-linear
+//! radius queries without the pointer-chasing of a [`super::BKTree`]: [`Mih::build`]
+//! partitions each hash's bits into `m` contiguous, equal-width substrings and hashes
+//! every entry into `m` lookup tables, one per substring. A query at radius `r` then
+//! only has to probe, per table, the substring keys within `floor(r / m)` bits of the
+//! query's own substring -- by the pigeonhole principle, any hash truly within `r` of
+//! the query must agree with it on at least one whole substring to that tolerance, so
+//! nothing within `r` is ever missed. The union of what the tables return is a
+//! candidate set that still needs an exact [`Hamming::distance_to`] check to throw out
+//! false positives (substrings agreeing by coincidence).
+//!
+//! Unlike [`super::BKTree`] this is a plain in-memory structure built once from a
+//! fixed slice of `(Id, Hamming)` pairs -- there's no incremental insert/remove, and no
+//! on-disk backing.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::imghash::hamming::{Container, Distance, Hamming, HashContainer};
+
+/// An MIH index over `Hamming<C>` values tagged with an arbitrary `Id`.
+pub struct Mih<Id, C: HashContainer = Container> {
+    substrings: u32,
+    substring_bits: u32,
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+    entries: Vec<(Id, Hamming<C>)>,
+}
+
+impl<Id, C: HashContainer> Mih<Id, C> {
+    /// Builds an index over `entries`, splitting each hash's [`HashContainer::BITS`]
+    /// into `substrings` contiguous, equal-width chunks.
+    ///
+    /// `substrings` must evenly divide `C::BITS`, and the resulting substring width
+    /// must be at most 64 bits (a substring is used as a `u64` table key) -- both are
+    /// asserted rather than handled, since they only depend on the caller's fixed
+    /// choice of `C` and `substrings`, never on `entries`' contents. See
+    /// [`Self::query`] for how `substrings` should relate to the radius queried with.
+    pub fn build(entries: Vec<(Id, Hamming<C>)>, substrings: u32) -> Self {
+        assert!(substrings > 0, "need at least one substring");
+        assert_eq!(
+            C::BITS % substrings,
+            0,
+            "substrings must divide the hash width evenly"
+        );
+        let substring_bits = C::BITS / substrings;
+        assert!(
+            substring_bits <= u64::BITS,
+            "each substring must fit in a u64 table key"
+        );
+
+        let mut tables = vec![HashMap::new(); substrings as usize];
+        for (idx, (_, hash)) in entries.iter().enumerate() {
+            let bytes = hash.0.to_ne_bytes();
+            for (s, table) in tables.iter_mut().enumerate() {
+                let key = substring_value(&bytes, s as u32 * substring_bits, substring_bits);
+                table.entry(key).or_insert_with(Vec::new).push(idx);
+            }
+        }
+
+        Self {
+            substrings,
+            substring_bits,
+            tables,
+            entries,
+        }
+    }
+
+    /// Returns the `Id`s of every entry within `radius` of `query`.
+    ///
+    /// Each table is probed within `floor(radius / substrings)` bits of the query's own
+    /// substring, which by the pigeonhole argument in the module docs is exactly enough
+    /// for the result to be complete whatever `substrings` count the index was built
+    /// with. The count only changes how many keys are probed per table and how many
+    /// candidates need the exact distance check.
+    pub fn query(&self, query: Hamming<C>, radius: Distance) -> Vec<&Id> {
+        let per_substring_radius = radius / self.substrings;
+        let bytes = query.0.to_ne_bytes();
+
+        let mut candidates = HashSet::new();
+        for (s, table) in self.tables.iter().enumerate() {
+            let key = substring_value(&bytes, s as u32 * self.substring_bits, self.substring_bits);
+            for flipped in keys_within(key, self.substring_bits, per_substring_radius) {
+                if let Some(ids) = table.get(&flipped) {
+                    candidates.extend(ids.iter().copied());
+                }
+            }
+        }
+
+        candidates
+            .into_iter()
+            .filter_map(|idx| {
+                let (id, hash) = &self.entries[idx];
+                (hash.distance_to(query) <= radius).then_some(id)
+            })
+            .collect()
+    }
+}
+
+/// The `len`-bit substring of `bytes` starting at bit `start` (native-endian, LSB-first
+/// within `bytes`), as a `u64`. `len` must be at most 64.
+fn substring_value(bytes: &[u8], start: u32, len: u32) -> u64 {
+    let mut value = 0u64;
+    for i in 0..len {
+        let bit = start + i;
+        let byte = bytes[(bit / 8) as usize];
+        if (byte >> (bit % 8)) & 1 == 1 {
+            value |= 1 << i;
+        }
+    }
+    value
+}
+
+/// Every `width`-bit value reachable from `key` by flipping at most `max_flips` bits,
+/// including `key` itself. Each flipped bit index is only ever used once per value (the
+/// usual bounded bit-flip enumeration), so this produces exactly
+/// `sum_{i=0}^{max_flips} C(width, i)` values with no duplicates.
+fn keys_within(key: u64, width: u32, max_flips: u32) -> Vec<u64> {
+    let mut out = vec![key];
+    flip_combinations(key, width, max_flips, 0, &mut out);
+    out
+}
+
+fn flip_combinations(current: u64, width: u32, flips_left: u32, start_bit: u32, out: &mut Vec<u64>) {
+    if flips_left == 0 {
+        return;
+    }
+    for bit in start_bit..width {
+        let flipped = current ^ (1u64 << bit);
+        out.push(flipped);
+        flip_combinations(flipped, width, flips_left - 1, bit + 1, out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_exact_match() {
+        let entries = vec![(1, Hamming::<u64>(0b1010)), (2, Hamming::<u64>(0b0101))];
+        let mih = Mih::build(entries, 4);
+
+        let found = mih.query(Hamming(0b1010), 0);
+        assert_eq!(found, vec![&1]);
+    }
+
+    #[test]
+    fn finds_entries_within_radius() {
+        let entries = vec![
+            (1, Hamming::<u64>(0b0000)),
+            (2, Hamming::<u64>(0b0001)),
+            (3, Hamming::<u64>(0b1111)),
+        ];
+        let mih = Mih::build(entries, 4);
+
+        let mut found: Vec<_> = mih.query(Hamming(0b0000), 1).into_iter().copied().collect();
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+    }
+
+    #[test]
+    fn excludes_substring_false_positives_via_exact_check() {
+        // Agrees with the query on one whole substring (so it's a candidate), but
+        // differs everywhere else, putting its real distance well outside radius.
+        let entries = vec![
+            (1, Hamming::<u64>(0)),
+            (2, Hamming::<u64>(0xFFFFFFFF_00000000)),
+        ];
+        let mih = Mih::build(entries, 2);
+
+        let found = mih.query(Hamming(0), 5);
+        assert_eq!(found, vec![&1]);
+    }
+
+    #[test]
+    fn keys_within_enumerates_pigeonhole_correctly() {
+        let mut keys = keys_within(0b00, 2, 1);
+        keys.sort();
+        assert_eq!(keys, vec![0b00, 0b01, 0b10]);
+    }
+
+    #[test]
+    #[should_panic(expected = "divide")]
+    fn build_rejects_uneven_substring_count() {
+        let entries: Vec<(u32, Hamming<u64>)> = vec![];
+        Mih::build(entries, 5);
+    }
+}
@@ -1,19 +1,21 @@
 use std::borrow::Borrow;
-use std::fs::File;
+use std::fs::{self, File};
 use std::ops::RangeInclusive;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 
 use super::deferred_box::{self, DeferredBox, DeferredBoxSerializer};
 use super::entry::*;
+use super::metric::{HammingMetric, Metric};
 use rkyv::validation::validators::DefaultValidator;
 use rkyv::vec::ArchivedVec;
-use rkyv::{Archive, CheckBytes, Serialize};
+use rkyv::{Archive, CheckBytes, Deserialize, Infallible, Serialize};
 
-use super::file_array::{self, FileArray, Ref};
+use super::file_array::{self, FileArray, FileArraySerializer, Ref};
 use crate::bktree::source_types::any_source::AnySource;
 use crate::bktree::source_types::{PartialSource, Source};
-use crate::imghash::hamming::{Distance, Hamming};
+use crate::imghash::hamming::Distance;
+use crate::utils::workers::{scoped_workers, FinishedWorker};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -28,71 +30,176 @@ pub enum Error {
         opening_as: String,
         stored_as: String,
     },
+    #[error(
+        "metric mismatch: trying to open as {opening_as}, but it is stored as {stored_as}"
+    )]
+    MetricMismatch {
+        opening_as: String,
+        stored_as: String,
+    },
+    #[error("not a BKTree database, bad magic bytes in the header")]
+    BadMetaMagic,
+    #[error("unsupported BKTree header format version: {0}")]
+    UnsupportedMetaVersion(u32),
+    #[error(
+        "no valid header record found scanning backward from the end of the file; it is either empty or corrupted beyond recovery"
+    )]
+    NoValidHeader,
+    #[error("worker thread panicked: {0}")]
+    WorkerPanic(String),
+    #[error("allocation failed: {0}")]
+    Alloc(#[from] std::collections::TryReserveError),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Default dead-node ratio above which [`BKTree::remove_any_of`] and
+/// [`BKTree::compact_if_needed`] compact automatically, see
+/// [`BKTree::auto_compact_threshold`]. A caller that wants the tree left untouched
+/// (e.g. the debug tools, which never remove anything anyway) can set a threshold
+/// above `1.0` to disable this.
+pub const DEFAULT_AUTO_COMPACT_THRESHOLD: f32 = 0.5;
+
+const META_MAGIC: [u8; 4] = *b"BKTM";
+const META_FORMAT_VERSION: u32 = 1;
+
+/// The fixed docket sitting at the very front of the mmap database: a magic/
+/// format-version pair so an unrelated or newer-format file is rejected outright, a
+/// `uuid` identifying this particular tree incarnation, and the `source_ident`/
+/// `metric_ident` used to catch opening a tree with the wrong `S`/`M`. Written once by
+/// [`init_meta`] and never touched again; everything that changes over the tree's
+/// lifetime (the root pointer, `alive`/`dead` counts) lives in the append-only
+/// [`Header`] chain instead, see its docs.
 #[derive(Serialize, Archive)]
 #[archive(check_bytes)]
 struct Meta {
-    root: Ref<BKNode>,
+    magic: [u8; 4],
+    format_version: u32,
+    uuid: u128,
     source_ident: String,
-    // TODO: somehow store the version of this struct itself? Need two layers of headers?
-    // The first layer has the version and points to the other header (this one)? Or use
-    // repr(C) and store the version as the first field?
+    metric_ident: String,
 }
 
-impl ArchivedMeta {
-    fn root(self: Pin<&mut Self>) -> Pin<&mut Ref<BKNode>> {
-        unsafe { self.map_unchecked_mut(|m| &mut m.root) }
+impl Meta {
+    fn new(source_ident: String, metric_ident: String) -> Self {
+        Self {
+            magic: META_MAGIC,
+            format_version: META_FORMAT_VERSION,
+            uuid: uuid::Uuid::new_v4().as_u128(),
+            source_ident,
+            metric_ident,
+        }
     }
 }
 
-impl Meta {
-    fn new(source_ident: String) -> Self {
+/// Size, in bytes, of every page [`Header`] is padded up to before being appended.
+/// Chosen to be larger than any realistic `Header`, which is the point: it gives the
+/// backward scan in [`locate_header`] a fixed stride to hop over without having to know
+/// any individual header's exact size up front.
+const PAGE_SIZE: usize = 4096;
+const HEADER_MAGIC: [u8; 3] = *b"BKH";
+const HEADER_PAGE_TYPE: u8 = 1;
+
+/// Replaces in-place mutation of a single `Meta` record (the scheme this type used to
+/// use, and the one Nebari/Couchstore both moved away from): whenever the root changes,
+/// a brand new `Header` is appended instead of rewriting the old one, always starting on
+/// a [`PAGE_SIZE`] boundary and carrying a magic/page-type tag that lets
+/// [`locate_header`] recognize one. Opening a tree walks backward page by page from the
+/// end of the file, and the first header whose tag and `CheckBytes` validation both
+/// succeed is the live one; anything after it (a torn write from a crash mid-append) is
+/// simply never looked at. This gives durable commits and automatic recovery without
+/// needing to order fsyncs around a single mutable record, and it composes with
+/// `sync_to_disk`: the mmap only ever needs to be flushed, never fsync'd mid-write.
+#[derive(Serialize, Archive)]
+#[archive(check_bytes)]
+struct Header<K> {
+    magic: [u8; 3],
+    page_type: u8,
+    alive: u64,
+    dead: u64,
+    root: Ref<BKNode<K>>,
+}
+
+impl<K> Header<K> {
+    fn new(alive: u64, dead: u64, root: Ref<BKNode<K>>) -> Self {
         Self {
-            root: Ref::null(),
-            source_ident,
+            magic: HEADER_MAGIC,
+            page_type: HEADER_PAGE_TYPE,
+            alive,
+            dead,
+            root,
+        }
+    }
+}
+
+impl<K> ArchivedHeader<K> {
+    fn is_valid(&self) -> bool {
+        self.magic == HEADER_MAGIC && self.page_type == HEADER_PAGE_TYPE
+    }
+}
+
+/// Scans backward from the end of `db`, page by page, for the most recent valid
+/// [`Header`]: the first candidate whose tag matches and which passes `CheckBytes`
+/// validation wins, which is always the last one fully written before whatever crash or
+/// truncation left anything after it behind.
+fn locate_header<K>(db: &FileArray) -> Result<Ref<Header<K>>> {
+    let header_size = std::mem::size_of::<ArchivedHeader<K>>();
+    let len = db.len();
+    let mut page_start = (len / PAGE_SIZE) * PAGE_SIZE;
+
+    while page_start >= PAGE_SIZE {
+        let candidate_end = page_start + header_size;
+        if candidate_end <= len {
+            let candidate = Ref::<Header<K>>::from_offset(candidate_end);
+            if let Ok(header) = db.get::<Header<K>>(candidate) {
+                if header.is_valid() {
+                    return Ok(candidate);
+                }
+            }
         }
+
+        page_start -= PAGE_SIZE;
     }
+
+    Err(Error::NoValidHeader)
 }
 
 const DEFAULT_CHILDREN_LIMIT: usize = 20;
 
 #[derive(Serialize, Archive)]
 #[archive(check_bytes)]
-pub(super) struct BKNode {
-    hash: Hamming,
+pub(super) struct BKNode<K> {
+    hash: K,
     value: DeferredBox,
     removed: bool,
-    children: Ref<Children>,
+    children: Ref<Children<K>>,
 }
 
 #[derive(Serialize, Archive)]
 #[archive(check_bytes)]
-pub(super) struct Children {
-    entries: Vec<Entry>,
-    next_sibling: Ref<Children>,
+pub(super) struct Children<K> {
+    entries: Vec<Entry<K>>,
+    next_sibling: Ref<Children<K>>,
 }
 
-impl Children {
-    fn new(limit: usize) -> Self {
+impl<K> Children<K> {
+    fn new(limit: usize) -> Result<Self> {
         assert!(limit > 0);
-        Self {
-            entries: entry_create(limit),
+        Ok(Self {
+            entries: entry_try_create(limit)?,
             next_sibling: Ref::null(),
-        }
+        })
     }
 
-    fn new_initial(limit: usize, initial_element: Entry) -> Self {
-        let mut selff = Self::new(limit);
+    fn new_initial(limit: usize, initial_element: Entry<K>) -> Result<Self> {
+        let mut selff = Self::new(limit)?;
         *selff.entries.first_mut().expect("the vec is not empty") = initial_element;
-        selff
+        Ok(selff)
     }
 }
 
-impl BKNode {
-    fn new(hash: Hamming, value: DeferredBox) -> Self {
+impl<K> BKNode<K> {
+    fn new(hash: K, value: DeferredBox) -> Self {
         Self {
             hash,
             value,
@@ -102,18 +209,18 @@ impl BKNode {
     }
 }
 
-impl ArchivedChildren {
-    fn pin_mut_entries(self: Pin<&mut Self>) -> Pin<&mut ArchivedVec<ArchivedEntry>> {
+impl<K> ArchivedChildren<K> {
+    fn pin_mut_entries(self: Pin<&mut Self>) -> Pin<&mut ArchivedVec<ArchivedEntry<K>>> {
         unsafe { self.map_unchecked_mut(|s| &mut s.entries) }
     }
 
-    fn mut_next_sibling(self: Pin<&mut Self>) -> &mut Ref<Children> {
+    fn mut_next_sibling(self: Pin<&mut Self>) -> &mut Ref<Children<K>> {
         unsafe { &mut self.get_unchecked_mut().next_sibling }
     }
 }
 
-impl ArchivedBKNode {
-    fn mut_children(self: Pin<&mut Self>) -> &mut Ref<Children> {
+impl<K> ArchivedBKNode<K> {
+    fn mut_children(self: Pin<&mut Self>) -> &mut Ref<Children<K>> {
         unsafe { &mut self.get_unchecked_mut().children }
     }
 
@@ -122,38 +229,61 @@ impl ArchivedBKNode {
     }
 }
 
-pub struct BKTree<S>
+pub struct BKTree<S, M = HammingMetric>
 where
     S: PartialSource,
+    M: Metric,
 {
     db: FileArray,
+    /// Where `db` is mapped from, if anywhere; only a file-backed tree can
+    /// [`BKTree::compact_if_needed`], since that needs somewhere to atomically swap the
+    /// rebuilt file in for.
+    path: Option<PathBuf>,
+    /// The live [`Header`], found by [`locate_header`] on open and kept up to date
+    /// in-memory every time a new one is appended, so normal operation never has to
+    /// re-scan for it.
+    header_ref: Ref<Header<M::Key>>,
+    /// Dead-node ratio above which [`BKTree::remove_any_of`] compacts automatically,
+    /// see [`BKTree::auto_compact_threshold`].
+    auto_compact_threshold: f32,
     _src: std::marker::PhantomData<S>,
+    _metric: std::marker::PhantomData<M>,
 }
 
-impl<S> BKTree<S>
+impl<S, M> BKTree<S, M>
 where
     S: PartialSource,
+    M: Metric,
 {
     pub fn from_file(file: impl AsRef<Path>) -> Result<Self> {
-        let db = FileArray::new(file)?;
-        Self::new(db)
+        let path = file.as_ref().to_path_buf();
+        let db = FileArray::new(&path)?;
+        Self::new(db, Some(path))
     }
 
-    fn new(mut db: FileArray) -> Result<Self> {
+    fn new(mut db: FileArray, path: Option<PathBuf>) -> Result<Self> {
         let source_ident = S::identifier();
+        let metric_ident = M::identifier();
 
-        if db.is_empty() {
-            init_meta(
+        let header_ref = if db.is_empty() {
+            init_meta::<M>(
                 &mut db,
                 source_ident
-                    .expect("cannot create a new BKTree without a source identifier")
-                    .to_string(),
-            )?;
-        }
+                    .clone()
+                    .expect("cannot create a new BKTree without a source identifier"),
+            )?
+        } else {
+            verify_meta::<M>(&db)?;
+            locate_header(&db)?
+        };
 
         let new_self = Self {
             db,
+            path,
+            header_ref,
+            auto_compact_threshold: DEFAULT_AUTO_COMPACT_THRESHOLD,
             _src: std::marker::PhantomData,
+            _metric: std::marker::PhantomData,
         };
 
         if let Some(ident) = source_ident {
@@ -167,14 +297,22 @@ where
             }
         }
 
+        let stored_metric_ident = new_self.metric_ident()?;
+        if metric_ident != stored_metric_ident {
+            return Err(Error::MetricMismatch {
+                opening_as: metric_ident,
+                stored_as: stored_metric_ident.to_string(),
+            });
+        }
+
         Ok(new_self)
     }
 
-    fn empty_copy_of(&self, mut new_db: FileArray) -> Result<Self> {
+    fn empty_copy_of(&self, mut new_db: FileArray, new_path: Option<PathBuf>) -> Result<Self> {
         assert!(new_db.is_empty());
         let ident = self.source_ident()?.to_string();
-        init_meta(&mut new_db, ident)?;
-        Self::new(new_db)
+        init_meta::<M>(&mut new_db, ident)?;
+        Self::new(new_db, new_path)
     }
 
     fn source_ident(&self) -> Result<&str> {
@@ -183,80 +321,150 @@ where
         Ok(meta.source_ident.as_str())
     }
 
-    fn root(&self) -> Result<Ref<BKNode>> {
+    fn metric_ident(&self) -> Result<&str> {
         let meta_ref = FileArray::ref_to_first::<Meta>();
         let meta = self.db.get::<Meta>(meta_ref)?;
-        Ok(meta.root)
+        Ok(meta.metric_ident.as_str())
     }
 
-    fn set_root(&mut self, new_root: Ref<BKNode>) -> Result<()> {
-        let meta_ref = FileArray::ref_to_first::<Meta>();
-        let meta = self.db.get_mut::<Meta>(meta_ref)?;
-        meta.root().set(new_root);
+    fn current_header(&self) -> Result<&ArchivedHeader<M::Key>> {
+        Ok(self.db.get::<Header<M::Key>>(self.header_ref)?)
+    }
+
+    fn root(&self) -> Result<Ref<BKNode<M::Key>>> {
+        Ok(self.current_header()?.root)
+    }
+
+    /// Appends a brand new [`Header`] with the given `alive`/`dead` counts and `root`,
+    /// and makes it the tree's current one. Never touches any earlier header.
+    fn append_header(&mut self, alive: u64, dead: u64, root: Ref<BKNode<M::Key>>) -> Result<()> {
+        self.db.pad_to_boundary(PAGE_SIZE)?;
+        self.header_ref = self.db.add_one(&Header::new(alive, dead, root))?;
         Ok(())
     }
 
+    fn set_root(&mut self, new_root: Ref<BKNode<M::Key>>) -> Result<()> {
+        let header = self.current_header()?;
+        let (alive, dead) = (header.alive, header.dead);
+        self.append_header(alive, dead, new_root)
+    }
+
+    /// Appends a header with the `alive` counter bumped by `added`, to be called right
+    /// after appending `added` freshly created nodes (never tombstoned, so they only
+    /// ever add to `alive`).
+    fn record_nodes_added(&mut self, added: u64) -> Result<()> {
+        let header = self.current_header()?;
+        let (alive, dead, root) = (header.alive, header.dead, header.root);
+        self.append_header(alive + added, dead, root)
+    }
+
+    /// Appends a header with `alive` moved over into `dead`, to be called right after
+    /// tombstoning `removed` previously-alive nodes.
+    fn record_nodes_removed(&mut self, removed: u64) -> Result<()> {
+        let header = self.current_header()?;
+        let (alive, dead, root) = (header.alive, header.dead, header.root);
+        self.append_header(alive, dead + removed, root)
+    }
+
     pub fn sync_to_disk(&self) -> Result<()> {
         Ok(self.db.sync_to_disk()?)
     }
 }
 
-fn init_meta(db: &mut FileArray, source_ident: String) -> file_array::Result<()> {
-    let meta_ref = db.add_one(Meta::new(source_ident))?;
+fn init_meta<M: Metric>(db: &mut FileArray, source_ident: String) -> Result<Ref<Header<M::Key>>> {
+    let meta_ref = db.add_one(Meta::new(source_ident, M::identifier()))?;
     assert_eq!(
         meta_ref,
         FileArray::ref_to_first::<Meta>(),
         "The header is not reachable with `ref_to_first`"
     );
+
+    db.pad_to_boundary(PAGE_SIZE)?;
+    let header_ref = db.add_one(&Header::new(0, 0, Ref::null()))?;
+    Ok(header_ref)
+}
+
+/// Checks the docket at the front of an existing database before trusting anything
+/// else in it: that it is actually a BKTree file of a format version we understand.
+/// The root pointer and `alive`/`dead` counts are no longer part of this record, see
+/// [`Header`]; [`locate_header`] is what validates and finds those.
+fn verify_meta<M: Metric>(db: &FileArray) -> Result<()> {
+    let meta_ref = FileArray::ref_to_first::<Meta>();
+    let meta = db.get::<Meta>(meta_ref)?;
+
+    if meta.magic != META_MAGIC {
+        return Err(Error::BadMetaMagic);
+    }
+
+    if meta.format_version != META_FORMAT_VERSION {
+        return Err(Error::UnsupportedMetaVersion(meta.format_version));
+    }
+
     Ok(())
 }
 
-impl BKTree<AnySource> {
-    pub fn downcast<S>(self) -> Result<BKTree<S>>
+impl<M: Metric> BKTree<AnySource, M> {
+    /// Opens a tree without committing to a concrete `S`, validating the docket (magic,
+    /// format version, metric) the same way [`BKTree::from_file`] does, but skipping the
+    /// `source_ident` check since there's no `S` to check it against. Handy for tools
+    /// like stats collection that only care about [`BKTree::count_nodes`]/
+    /// [`BKTree::for_each_hash`]/[`BKTree::rebuild_to`], all of which only need
+    /// [`PartialSource`] and so work the same whether or not the payload type is known.
+    /// Use [`BKTree::downcast`] afterwards if the concrete `S` turns out to be needed.
+    pub fn open_untyped(file: impl AsRef<Path>) -> Result<Self> {
+        Self::from_file(file)
+    }
+
+    pub fn downcast<S>(self) -> Result<BKTree<S, M>>
     where
         // NOTE: this should maybe be `Source`, but having it as partial allows
         // `AnySource` to downcast to itself, which is nice maybe? Akin to `Into`
         S: PartialSource,
     {
-        BKTree::new(self.db)
+        BKTree::new(self.db, self.path)
     }
 }
 
-impl<S> BKTree<S>
+impl<S, M> BKTree<S, M>
 where
     // NOTE: this should maybe be `Source`, but having it as partial allows `AnySource` to
     // upcast to itself, which is nice maybe? Akin to `Into`
     S: PartialSource,
+    M: Metric,
 {
-    pub fn upcast(self) -> BKTree<AnySource> {
+    pub fn upcast(self) -> BKTree<AnySource, M> {
         BKTree {
             db: self.db,
+            path: self.path,
+            header_ref: self.header_ref,
+            auto_compact_threshold: self.auto_compact_threshold,
             _src: std::marker::PhantomData,
+            _metric: std::marker::PhantomData,
         }
     }
 }
 
-impl<S> BKTree<S>
+impl<S, M> BKTree<S, M>
 where
     S: Serialize<DeferredBoxSerializer> + Source,
     S::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+    M: Metric,
+    M::Key: Serialize<FileArraySerializer> + Copy,
 {
-    pub fn add<B>(&mut self, hash: Hamming, value: B) -> Result<()>
+    pub fn add<B>(&mut self, hash: M::Key, value: B) -> Result<()>
     where
         B: Borrow<S>,
     {
         self.add_all([(hash, value)])
     }
 
-    pub fn add_all<B>(
-        &mut self,
-        items: impl IntoIterator<Item = (Hamming, B)>,
-    ) -> Result<()>
+    pub fn add_all<B>(&mut self, items: impl IntoIterator<Item = (M::Key, B)>) -> Result<()>
     where
         B: Borrow<S>,
     {
         let mut root = self.root()?;
         let mut items = items.into_iter();
+        let mut added: u64 = 0;
 
         if let Some((hash, value)) = items.next() {
             let value_box = DeferredBox::new(value)?;
@@ -266,24 +474,32 @@ where
             } else {
                 self.add_internal(root, hash, value_box)?;
             }
+            added += 1;
         }
 
         for (hash, value) in items {
             let value_box = DeferredBox::new(value)?;
             self.add_internal(root, hash, value_box)?;
+            added += 1;
+        }
+
+        if added > 0 {
+            self.record_nodes_added(added)?;
         }
         Ok(())
     }
 }
 
-impl<S> BKTree<S>
+impl<S, M> BKTree<S, M>
 where
     S: PartialSource,
+    M: Metric,
+    M::Key: Serialize<FileArraySerializer> + Copy + rkyv::Archive<Archived = M::Key>,
 {
     fn add_internal(
         &mut self,
-        mut cur_node_ref: Ref<BKNode>,
-        hash: Hamming,
+        mut cur_node_ref: Ref<BKNode<M::Key>>,
+        hash: M::Key,
         value: DeferredBox,
     ) -> Result<()> {
         assert!(cur_node_ref.is_not_null());
@@ -295,7 +511,7 @@ where
 
         'nodes: loop {
             let cur_node = self.db.get(cur_node_ref)?;
-            let dist = cur_node.hash.distance_to(hash);
+            let dist = M::distance(&cur_node.hash, &hash);
 
             let new_entry = Entry {
                 key: dist,
@@ -304,7 +520,7 @@ where
 
             if cur_node.children.is_null() {
                 let new_children =
-                    Children::new_initial(DEFAULT_CHILDREN_LIMIT, new_entry);
+                    Children::new_initial(DEFAULT_CHILDREN_LIMIT, new_entry)?;
                 let new_children_ref = self.db.add_one(&new_children)?;
                 let cur_node = self.db.get_mut(cur_node_ref)?;
                 assert_eq!(Ref::null(), cur_node.children);
@@ -332,7 +548,7 @@ where
                                 let new_sibling = Children::new_initial(
                                     DEFAULT_CHILDREN_LIMIT,
                                     new_entry,
-                                );
+                                )?;
                                 let new_sibling_ref = self.db.add_one(&new_sibling)?;
 
                                 let cur_children = self.db.get_mut(cur_children_ref)?;
@@ -354,20 +570,28 @@ where
 enum IterateCmd {
     Continue,
     WithinRange(RangeInclusive<Distance>),
-    #[allow(unused)] // TODO: rebuild will need this in the future with restartable walk
     Stop,
 }
 
 macro_rules! impl_walk {
     ($fun_name:ident, $self_type:ty, $visit_arg:ty, $db_get:ident, $visit_prep:expr) => {
-        fn $fun_name<'a, F>(self: $self_type, mut visit: F) -> Result<()>
+        /// Depth-first walk starting from `stack` (an empty one seeds itself from the
+        /// root). Returns `Ok(None)` once the stack is fully drained, or, if `visit`
+        /// returns [`IterateCmd::Stop`], `Ok(Some(stack))` with whatever was left
+        /// unvisited so the walk can be resumed later, e.g. handed off to another
+        /// thread as its own sub-stack.
+        fn $fun_name<'a, F>(
+            self: $self_type,
+            mut stack: Vec<Ref<BKNode<M::Key>>>,
+            mut visit: F,
+        ) -> Result<Option<Vec<Ref<BKNode<M::Key>>>>>
         where
             F: FnMut($visit_arg) -> Result<IterateCmd>,
         {
-            let mut stack = Vec::new();
-            {
+            if stack.is_empty() {
                 let root = self.root()?;
                 if root.is_not_null() {
+                    stack.try_reserve(1)?;
                     stack.push(root);
                 }
             }
@@ -377,149 +601,510 @@ macro_rules! impl_walk {
                 let dist_range = match visit($visit_prep(&mut cur_node))? {
                     IterateCmd::Continue => Distance::MIN..=Distance::MAX,
                     IterateCmd::WithinRange(range) => range,
-                    IterateCmd::Stop => break,
+                    IterateCmd::Stop => return Ok(Some(stack)),
                 };
 
                 let mut children_ref = cur_node.children;
                 while children_ref.is_not_null() {
                     let children_node = self.db.get(children_ref)?;
-                    stack.extend(
-                        entry_used(&children_node.entries)
-                            .iter()
-                            .filter(|entry| dist_range.contains(&entry.key))
-                            .map(|entry| entry.value),
-                    );
+                    let matching: Vec<_> = entry_used(&children_node.entries)
+                        .iter()
+                        .filter(|entry| dist_range.contains(&entry.key))
+                        .map(|entry| entry.value)
+                        .collect();
+                    stack.try_reserve(matching.len())?;
+                    stack.extend(matching);
                     children_ref = children_node.next_sibling;
                 }
             }
 
-            Ok(())
+            Ok(None)
         }
     };
 }
 
-impl<S> BKTree<S>
+impl<S, M> BKTree<S, M>
 where
     S: PartialSource,
+    M: Metric,
+    M::Key: Archive,
+    <M::Key as Archive>::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
 {
     impl_walk!(
         walk_mut,
         &'a mut Self,
-        Pin<&mut ArchivedBKNode>,
+        Pin<&mut ArchivedBKNode<M::Key>>,
         get_mut,
         Pin::as_mut
     );
     impl_walk!(
         walk,
         &'a Self,
-        &'a ArchivedBKNode,
+        &'a ArchivedBKNode<M::Key>,
         get,
         std::convert::identity
     );
 }
 
-impl<S> BKTree<S>
+impl<S, M> BKTree<S, M>
 where
     S: Archive + Source,
     S::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+    M: Metric,
+    M::Key: Copy + Sync + Archive<Archived = M::Key>,
+    <M::Key as Archive>::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
 {
-    pub fn for_each<'a, F>(&'a self, mut visit: F) -> Result<()>
+    pub fn for_each<F>(&self, mut visit: F) -> Result<()>
     where
-        F: FnMut(Hamming, &'a S::Archived),
+        F: FnMut(M::Key, &S::Archived),
     {
-        self.walk(|arch_node| {
+        let mut scratch = None;
+        self.walk(Vec::new(), |arch_node| {
             if !arch_node.removed {
-                let value = arch_node.value.get::<S>()?;
+                let value = arch_node.value.get::<S>(&mut scratch)?;
                 visit(arch_node.hash, value);
             }
             Ok(IterateCmd::Continue)
-        })
+        })?;
+        Ok(())
     }
 
-    pub fn find_within<'a, F>(
-        &'a self,
-        hash: Hamming,
-        within: Distance,
-        mut visit: F,
-    ) -> Result<()>
+    pub fn find_within<F>(&self, hash: M::Key, within: Distance, mut visit: F) -> Result<()>
     where
-        F: FnMut(Hamming, &'a S::Archived),
+        F: FnMut(M::Key, &S::Archived),
     {
-        self.walk(|arch_node| {
-            let dist = arch_node.hash.distance_to(hash);
+        let mut scratch = None;
+        self.walk(Vec::new(), |arch_node| {
+            let dist = M::distance(&arch_node.hash, &hash);
             if dist <= within && !arch_node.removed {
-                let value = arch_node.value.get::<S>()?;
+                let value = arch_node.value.get::<S>(&mut scratch)?;
                 visit(arch_node.hash, value);
             }
             Ok(IterateCmd::WithinRange(
                 dist.saturating_sub(within)..=dist.saturating_add(within),
             ))
-        })
+        })?;
+        Ok(())
+    }
+
+    /// Like [`Self::find_within`], but instead of visiting every match, narrows in on
+    /// the single closest one: `best` starts at `within` and only ever shrinks, so
+    /// once something closer than the original `within` is found, subtrees outside
+    /// `dist.saturating_sub(best)..=dist.saturating_add(best)` are pruned just like
+    /// `find_within` prunes on the fixed `within`, except the band tightens as better
+    /// matches turn up. Useful for collision reporting, where the nearest duplicate is
+    /// more informative than an arbitrary one inside the band.
+    pub fn find_closest(&self, hash: M::Key, within: Distance) -> Result<Option<(Distance, M::Key, S)>>
+    where
+        S::Archived: Deserialize<S, Infallible>,
+    {
+        let mut scratch = None;
+        let mut best_dist = within;
+        let mut best: Option<(Distance, M::Key, S)> = None;
+
+        self.walk(Vec::new(), |arch_node| {
+            let dist = M::distance(&arch_node.hash, &hash);
+            if dist <= best_dist && !arch_node.removed {
+                let value = arch_node.value.get::<S>(&mut scratch)?;
+                let value: S = value
+                    .deserialize(&mut Infallible)
+                    .expect("deserializing with Infallible never fails");
+                best_dist = dist;
+                best = Some((dist, arch_node.hash, value));
+            }
+            Ok(IterateCmd::WithinRange(
+                dist.saturating_sub(best_dist)..=dist.saturating_add(best_dist),
+            ))
+        })?;
+
+        Ok(best)
+    }
+
+    /// Lazily visits every live entry, depth-first, same order as [`Self::for_each`]
+    /// but via external iteration: the DFS stack [`impl_walk!`] normally keeps on its
+    /// own call stack is owned by the returned [`Iter`] instead, so callers get `?`-
+    /// propagation, `take`, `zip`, and early termination by simply dropping the
+    /// iterator instead of signalling [`IterateCmd::Stop`] through a callback.
+    pub fn iter(&self) -> Result<Iter<'_, S, M>> {
+        Iter::new(self, None)
+    }
+
+    /// Like [`Self::iter`], but carries the same [`IterateCmd::WithinRange`] pruning
+    /// [`Self::find_within`] uses into the iterator, so a lazy consumer (e.g.
+    /// `.next()` once, or `.take(1)`) still only expands the subtrees the triangle
+    /// inequality says could contain a match.
+    pub fn iter_within(&self, hash: M::Key, within: Distance) -> Result<Iter<'_, S, M>> {
+        Iter::new(self, Some((hash, within)))
+    }
+
+    /// Same search as [`Self::find_within`], but `num_workers` threads each search a
+    /// disjoint subtree concurrently instead of one thread walking the whole tree.
+    /// `self.db` is a shared read-only mmap, so every worker can independently run the
+    /// same distance-range pruning without any coordination beyond handing out disjoint
+    /// starting stacks up front. Worth it once the tree is large enough that the
+    /// per-worker spawn overhead is dwarfed by the search itself; for small trees
+    /// [`Self::find_within`] is simpler and just as fast.
+    pub fn find_within_parallel<F>(
+        &self,
+        hash: M::Key,
+        within: Distance,
+        num_workers: usize,
+        visit: F,
+    ) -> Result<()>
+    where
+        F: Fn(M::Key, &S::Archived) + Sync,
+    {
+        assert!(num_workers > 0);
+
+        let stacks = self.seed_stacks(num_workers)?;
+        let visit = &visit;
+
+        let finished = scoped_workers(|ws| {
+            for (i, stack) in stacks.into_iter().enumerate() {
+                ws.spawn(format!("find_within_parallel-{i}"), move || {
+                    let mut scratch = None;
+                    self.walk(stack, |arch_node| {
+                        let dist = M::distance(&arch_node.hash, &hash);
+                        if dist <= within && !arch_node.removed {
+                            let value = arch_node.value.get::<S>(&mut scratch)?;
+                            visit(arch_node.hash, value);
+                        }
+                        Ok(IterateCmd::WithinRange(
+                            dist.saturating_sub(within)..=dist.saturating_add(within),
+                        ))
+                    })?;
+                    Ok(())
+                });
+            }
+        });
+
+        for FinishedWorker { name, result } in finished {
+            match result {
+                Ok(r) => r?,
+                Err(panic) => return Err(Error::WorkerPanic(format!("{name}: {panic}"))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Seeds up to `num_workers` disjoint sub-stacks for [`Self::find_within_parallel`]
+    /// by resuming [`Self::walk`] (ignoring matches, using it purely to expand the
+    /// tree's structure) until it has accumulated at least `num_workers` pending nodes,
+    /// then dealing those out round-robin. If the tree is smaller than `num_workers`
+    /// the walk drains before that happens, in which case it is simply restarted as a
+    /// single sub-stack seeded from the root; not worth splitting further.
+    fn seed_stacks(&self, num_workers: usize) -> Result<Vec<Vec<Ref<BKNode<M::Key>>>>> {
+        let mut seen: usize = 0;
+        let remaining = self.walk(Vec::new(), |_arch_node| {
+            seen += 1;
+            Ok(if seen >= num_workers {
+                IterateCmd::Stop
+            } else {
+                IterateCmd::Continue
+            })
+        })?;
+
+        let stack = match remaining {
+            Some(stack) => stack,
+            None => {
+                let root = self.root()?;
+                if root.is_null() {
+                    return Ok(Vec::new());
+                }
+                vec![root]
+            }
+        };
+
+        let num_stacks = num_workers.min(stack.len());
+        let mut sub_stacks: Vec<Vec<Ref<BKNode<M::Key>>>> = vec![Vec::new(); num_stacks];
+        for (i, node_ref) in stack.into_iter().enumerate() {
+            sub_stacks[i % num_stacks].push(node_ref);
+        }
+
+        Ok(sub_stacks)
     }
 
+    /// Tombstones every live entry `predicate` matches. Once done, if the tree is
+    /// file-backed and the resulting dead ratio crosses [`BKTree::auto_compact_threshold`],
+    /// transparently [`BKTree::compact_if_needed`]s into a scratch file next to it and
+    /// swaps it in, reclaiming the tombstoned `Ref`s without the caller having to
+    /// remember to rebuild by hand.
     pub fn remove_any_of<P>(&mut self, mut predicate: P) -> Result<()>
     where
-        P: FnMut(Hamming, &S::Archived) -> bool,
+        P: FnMut(M::Key, &S::Archived) -> bool,
+        M::Key: Serialize<FileArraySerializer>,
     {
-        self.walk_mut(|arch_node| {
-            let value = arch_node.value.get::<S>()?;
+        let mut newly_removed: u64 = 0;
+        let mut scratch = None;
+        self.walk_mut(Vec::new(), |arch_node| {
+            let value = arch_node.value.get::<S>(&mut scratch)?;
             if !arch_node.removed && predicate(arch_node.hash, value) {
                 *arch_node.mut_removed() = true;
+                newly_removed += 1;
             }
             Ok(IterateCmd::Continue)
+        })?;
+
+        if newly_removed > 0 {
+            self.record_nodes_removed(newly_removed)?;
+            self.auto_compact()?;
+        }
+        Ok(())
+    }
+}
+
+/// External-iteration counterpart to [`impl_walk!`]: owns the DFS stack itself instead
+/// of keeping it on the call stack behind a `visit` callback, so consumers get `?`,
+/// `take`, `zip`, and early termination by simply dropping `self` instead of returning
+/// [`IterateCmd::Stop`]. Built by [`BKTree::iter`]/[`BKTree::iter_within`]; `search`
+/// carries the same `(hash, within)` pair [`BKTree::find_within`] prunes on, or `None`
+/// to visit every live entry.
+pub struct Iter<'a, S, M>
+where
+    S: PartialSource,
+    M: Metric,
+{
+    tree: &'a BKTree<S, M>,
+    stack: Vec<Ref<BKNode<M::Key>>>,
+    scratch: Option<rkyv::AlignedVec>,
+    search: Option<(M::Key, Distance)>,
+}
+
+impl<'a, S, M> Iter<'a, S, M>
+where
+    S: PartialSource,
+    M: Metric,
+    M::Key: Archive<Archived = M::Key>,
+    <M::Key as Archive>::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+{
+    fn new(tree: &'a BKTree<S, M>, search: Option<(M::Key, Distance)>) -> Result<Self> {
+        let mut stack = Vec::new();
+        let root = tree.root()?;
+        if root.is_not_null() {
+            stack.push(root);
+        }
+        Ok(Self {
+            tree,
+            stack,
+            scratch: None,
+            search,
         })
     }
 }
 
-impl<S> BKTree<S>
+impl<'a, S, M> Iterator for Iter<'a, S, M>
+where
+    S: Archive + Source,
+    S::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+    M: Metric,
+    M::Key: Copy + Archive<Archived = M::Key>,
+    <M::Key as Archive>::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+{
+    type Item = Result<(M::Key, &'a S::Archived)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(cur_ref) = self.stack.pop() {
+            let cur_node = match self.tree.db.get(cur_ref) {
+                Ok(node) => node,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            let dist_range = match self.search {
+                Some((hash, within)) => {
+                    let dist = M::distance(&cur_node.hash, &hash);
+                    if dist > within {
+                        None
+                    } else {
+                        Some(dist.saturating_sub(within)..=dist.saturating_add(within))
+                    }
+                }
+                None => Some(Distance::MIN..=Distance::MAX),
+            };
+
+            let mut children_ref = cur_node.children;
+            while children_ref.is_not_null() {
+                let children_node = match self.tree.db.get(children_ref) {
+                    Ok(node) => node,
+                    Err(e) => return Some(Err(e.into())),
+                };
+
+                // A node outside `within` can still have children inside it, so
+                // children are only pruned using the distance-range rule the node's
+                // own match status is irrelevant to.
+                let children_range = match self.search {
+                    Some((hash, within)) => {
+                        let dist = M::distance(&cur_node.hash, &hash);
+                        dist.saturating_sub(within)..=dist.saturating_add(within)
+                    }
+                    None => Distance::MIN..=Distance::MAX,
+                };
+                self.stack.extend(
+                    entry_used(&children_node.entries)
+                        .iter()
+                        .filter(|entry| children_range.contains(&entry.key))
+                        .map(|entry| entry.value),
+                );
+                children_ref = children_node.next_sibling;
+            }
+
+            if cur_node.removed || dist_range.is_none() {
+                continue;
+            }
+
+            let value = match cur_node.value.get::<S>(&mut self.scratch) {
+                Ok(value) => value,
+                Err(e) => return Some(Err(e.into())),
+            };
+            return Some(Ok((cur_node.hash, value)));
+        }
+
+        None
+    }
+}
+
+impl<S, M> BKTree<S, M>
 where
     S: PartialSource,
+    M: Metric,
+    M::Key: Serialize<FileArraySerializer> + Copy + Archive<Archived = M::Key>,
+    <M::Key as Archive>::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
 {
     pub fn count_nodes(&self) -> Result<(usize, usize)> {
-        let mut alive = 0;
-        let mut dead = 0;
-        self.walk(|arch_node| {
-            if arch_node.removed {
-                dead += 1;
-            } else {
-                alive += 1;
+        let header = self.current_header()?;
+        Ok((header.alive as usize, header.dead as usize))
+    }
+
+    /// Like [`BKTree::for_each`], but only ever touches node hashes, never the
+    /// [`DeferredBox`] payload -- so unlike `for_each` this works on any
+    /// [`PartialSource`], including [`AnySource`] opened via [`BKTree::open_untyped`].
+    /// Useful for stats collection that never needed the value in the first place.
+    pub fn for_each_hash<F>(&self, mut visit: F) -> Result<()>
+    where
+        F: FnMut(M::Key),
+    {
+        self.walk(Vec::new(), |arch_node| {
+            if !arch_node.removed {
+                visit(arch_node.hash);
             }
             Ok(IterateCmd::Continue)
         })?;
-
-        Ok((alive, dead))
+        Ok(())
     }
 
     pub fn rebuild_to(&self, path: impl AsRef<Path>) -> Result<Self> {
-        let db = FileArray::new(path)?;
-        self.rebuild_to_internal(db)
+        let path = path.as_ref().to_path_buf();
+        let db = FileArray::new(&path)?;
+        self.rebuild_to_internal(db, Some(path))
     }
 
-    fn rebuild_to_internal(&self, db: FileArray) -> Result<Self> {
-        let mut new_tree = self.empty_copy_of(db)?;
+    fn rebuild_to_internal(&self, db: FileArray, path: Option<PathBuf>) -> Result<Self> {
+        let mut new_tree = self.empty_copy_of(db, path)?;
         let mut new_root = Ref::null();
+        let mut copied: u64 = 0;
 
-        self.walk(|arch_node| {
+        self.walk(Vec::new(), |arch_node| {
             if !arch_node.removed {
                 let hash = arch_node.hash;
                 let value = arch_node.value.deserialize();
 
-                // TODO: Make walk restartable by returning the stack after a stop. There
-                // could then be two walks, one that looks for the root and another that
-                // does add_internal on that root.
                 if new_root.is_null() {
                     new_root = new_tree.db.add_one(BKNode::new(hash, value))?;
                     new_tree.set_root(new_root)?;
                 } else {
                     new_tree.add_internal(new_root, hash, value)?;
                 }
+                copied += 1;
             }
 
             Ok(IterateCmd::Continue)
         })?;
 
+        if copied > 0 {
+            new_tree.record_nodes_added(copied)?;
+        }
+
         Ok(new_tree)
     }
+
+    /// Sets the dead-node ratio above which [`BKTree::compact_if_needed`] actually
+    /// compacts and [`BKTree::remove_any_of`] auto-compacts after tombstoning, borrowed
+    /// from the `alive`/`dead` ratio Mercurial's dirstate-v2 docket uses to decide when
+    /// its own append-only file is worth rewriting. Defaults to
+    /// [`DEFAULT_AUTO_COMPACT_THRESHOLD`]; pass something above `1.0` to disable
+    /// auto-compaction entirely, e.g. for the debug tools that want the tree untouched.
+    pub fn auto_compact_threshold(mut self, threshold: f32) -> Self {
+        self.auto_compact_threshold = threshold;
+        self
+    }
+
+    /// Rebuilds into a fresh file at `scratch_path` and atomically swaps it in for the
+    /// current one, but only if dead nodes make up more than
+    /// [`Self::auto_compact_threshold`] of the total — reading that ratio is `O(1)`
+    /// thanks to the `alive`/`dead` counters in [`Header`], so this can be called after
+    /// every [`BKTree::remove_any_of`] without an `O(n)` [`BKTree::count_nodes`] walk
+    /// just to decide whether it's worth it. Returns whether it actually compacted.
+    /// Only works on a tree opened with [`BKTree::from_file`]; `scratch_path` should be
+    /// on the same filesystem as that file, since the swap is a rename.
+    pub fn compact_if_needed(&mut self, scratch_path: impl AsRef<Path>) -> Result<bool> {
+        let path = self
+            .path
+            .clone()
+            .expect("compact_if_needed requires a file-backed BKTree");
+
+        if !self.dead_ratio_exceeds(self.auto_compact_threshold)? {
+            return Ok(false);
+        }
+
+        let scratch_path = scratch_path.as_ref().to_path_buf();
+        let scratch_db = FileArray::new(&scratch_path)?;
+        let rebuilt = self.rebuild_to_internal(scratch_db, Some(scratch_path.clone()))?;
+        rebuilt.sync_to_disk()?;
+        drop(rebuilt);
+
+        fs::rename(&scratch_path, &path)?;
+        let auto_compact_threshold = self.auto_compact_threshold;
+        *self = Self::new(FileArray::new(&path)?, Some(path))?
+            .auto_compact_threshold(auto_compact_threshold);
+
+        Ok(true)
+    }
+
+    fn dead_ratio_exceeds(&self, threshold: f32) -> Result<bool> {
+        let header = self.current_header()?;
+        let (alive, dead) = (header.alive, header.dead);
+        let total = alive + dead;
+        Ok(total > 0 && (dead as f32 / total as f32) > threshold)
+    }
+
+    /// Like [`BKTree::compact_if_needed`], but derives its own scratch path next to
+    /// the database file instead of taking one, for callers (e.g.
+    /// [`BKTree::remove_any_of`]) that just want compaction to happen transparently.
+    /// A no-op on an in-memory tree, since there's nowhere to rename a scratch file
+    /// into.
+    fn auto_compact(&mut self) -> Result<bool> {
+        let Some(path) = self.path.clone() else {
+            return Ok(false);
+        };
+
+        if !self.dead_ratio_exceeds(self.auto_compact_threshold)? {
+            return Ok(false);
+        }
+
+        let scratch_path = compact_scratch_path(&path);
+        self.compact_if_needed(scratch_path)
+    }
+}
+
+/// Where [`BKTree::auto_compact`] rebuilds a tree before renaming it over `path`,
+/// following the same dotfile-and-suffix naming the older heap-backed `BKTree` uses for
+/// its own rebuild scratch file.
+fn compact_scratch_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .expect("a BKTree's path should have a file name")
+        .to_string_lossy();
+    path.with_file_name(format!(".{file_name}.compact.tmp"))
 }
 
 #[cfg(test)]
@@ -531,6 +1116,7 @@ mod test {
     use crate::bktree::source_types::{
         any_source::AnySource, string_source::StringSource,
     };
+    use crate::imghash::hamming::Hamming;
 
     use super::*;
 
@@ -541,7 +1127,7 @@ mod test {
 
     fn create_bktree_tempfile<S: PartialSource>() -> Result<BKTree<S>> {
         let arr = FileArray::new_tempfile()?;
-        BKTree::new(arr)
+        BKTree::new(arr, None)
     }
 
     fn contents(tree: &BKTree<Source>) -> Result<Vec<(Hamming, String)>> {
@@ -601,7 +1187,7 @@ mod test {
         assert_eq!((2, 1), tree.count_nodes()?);
         let tree = {
             let db_rebuilt = FileArray::new_tempfile()?;
-            tree.rebuild_to_internal(db_rebuilt)?
+            tree.rebuild_to_internal(db_rebuilt, None)?
         };
         assert_eq!((2, 0), tree.count_nodes()?);
 
@@ -616,6 +1202,83 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn compact_if_needed() -> Result<()> {
+        let dir = tempfile::tempdir().expect("failed to create a tempdir");
+        let db_path = dir.path().join("tree.bktree");
+        let scratch_path = dir.path().join("tree.bktree.scratch");
+
+        let mut tree: BKTree<Source> = BKTree::from_file(&db_path)?;
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        // Below the ratio: nothing happens.
+        let rem: HashSet<String> = HashSet::from(["5_1".into()]);
+        tree.remove_any_of(|_, p| rem.contains(p.as_str()))?;
+        assert_eq!((2, 1), tree.count_nodes()?);
+        assert!(!tree.compact_if_needed(&scratch_path)?);
+        assert_eq!((2, 1), tree.count_nodes()?);
+
+        // Above the ratio: it compacts, and the dead tombstone is gone.
+        let rem: HashSet<String> = HashSet::from(["5_2".into()]);
+        tree.remove_any_of(|_, p| rem.contains(p.as_str()))?;
+        assert_eq!((1, 0), tree.count_nodes()?);
+        assert!(!tree.compact_if_needed(&scratch_path)?, "already compacted");
+
+        assert_eq!(vec![(Hamming(0b100), "4".to_string())], contents(&tree)?);
+        assert!(!scratch_path.exists());
+
+        // The swap really did replace the backing file: reopening it sees the same.
+        let reopened: BKTree<Source> = BKTree::from_file(&db_path)?;
+        assert_eq!(vec![(Hamming(0b100), "4".to_string())], contents(&reopened)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_any_of_auto_compacts_above_the_threshold() -> Result<()> {
+        let dir = tempfile::tempdir().expect("failed to create a tempdir");
+        let db_path = dir.path().join("tree.bktree");
+
+        let mut tree: BKTree<Source> = BKTree::from_file(&db_path)?;
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        // (1 dead)/(3 total) is below the default 0.5 threshold: no auto-compaction.
+        let rem: HashSet<String> = HashSet::from(["5_1".into()]);
+        tree.remove_any_of(|_, p| rem.contains(p.as_str()))?;
+        assert_eq!((2, 1), tree.count_nodes()?);
+
+        // (2 dead)/(3 total) crosses it: `remove_any_of` compacts on its own.
+        let rem: HashSet<String> = HashSet::from(["5_2".into()]);
+        tree.remove_any_of(|_, p| rem.contains(p.as_str()))?;
+        assert_eq!((1, 0), tree.count_nodes()?);
+        assert_eq!(vec![(Hamming(0b100), "4".to_string())], contents(&tree)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_any_of_respects_a_disabled_auto_compact_threshold() -> Result<()> {
+        let dir = tempfile::tempdir().expect("failed to create a tempdir");
+        let db_path = dir.path().join("tree.bktree");
+
+        let mut tree: BKTree<Source> = BKTree::from_file(&db_path)?.auto_compact_threshold(1.1);
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        let rem: HashSet<String> = HashSet::from(["5_1".into(), "5_2".into()]);
+        tree.remove_any_of(|_, p| rem.contains(p.as_str()))?;
+
+        // A ratio above 1.0 can never be crossed, so the tombstones are left in place.
+        assert_eq!((1, 2), tree.count_nodes()?);
+
+        Ok(())
+    }
+
     #[test]
     fn find_within_large() -> Result<()> {
         let seed: u64 = rand::random();
@@ -672,22 +1335,142 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn find_within_parallel() -> Result<()> {
+        let mut tree: BKTree<Source> = create_bktree_tempfile()?;
+        for i in 0..200 {
+            let hash = Hamming(i);
+            tree.add(hash, value(i.to_string()))?;
+        }
+
+        let search_hash = Hamming(0b101);
+        let within = 10;
+
+        let mut sequential = Vec::new();
+        tree.find_within(search_hash, within, |hash, _| sequential.push(hash))?;
+        sequential.sort();
+
+        let parallel = std::sync::Mutex::new(Vec::new());
+        tree.find_within_parallel(search_hash, within, 4, |hash, _| {
+            parallel.lock().unwrap().push(hash);
+        })?;
+        let mut parallel = parallel.into_inner().unwrap();
+        parallel.sort();
+
+        assert_eq!(sequential, parallel);
+
+        Ok(())
+    }
+
+    #[test]
+    fn iter() -> Result<()> {
+        let mut tree: BKTree<Source> = create_bktree_tempfile()?;
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        let mut via_iter: Vec<(Hamming, String)> = tree
+            .iter()?
+            .map(|r| r.map(|(hash, val)| (hash, val.as_str().to_owned())))
+            .collect::<Result<_>>()?;
+        via_iter.sort();
+
+        assert_eq!(contents(&tree)?, via_iter);
+
+        Ok(())
+    }
+
+    #[test]
+    fn iter_within() -> Result<()> {
+        let mut tree: BKTree<Source> = create_bktree_tempfile()?;
+        for i in 0..200 {
+            tree.add(Hamming(i), value(i.to_string()))?;
+        }
+
+        let search_hash = Hamming(0b101);
+        let within = 10;
+
+        let mut via_callback = Vec::new();
+        tree.find_within(search_hash, within, |hash, _| via_callback.push(hash))?;
+        via_callback.sort();
+
+        let mut via_iter: Vec<Hamming> = tree
+            .iter_within(search_hash, within)?
+            .map(|r| r.map(|(hash, _)| hash))
+            .collect::<Result<_>>()?;
+        via_iter.sort();
+
+        assert_eq!(via_callback, via_iter);
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_closest() -> Result<()> {
+        let mut tree: BKTree<Source> = create_bktree_tempfile()?;
+        tree.add(Hamming(0b1111), value("far"))?;
+        tree.add(Hamming(0b1011), value("closer"))?;
+        tree.add(Hamming(0b1001), value("closest"))?;
+
+        let search_hash = Hamming(0b1000);
+
+        assert_eq!(None, tree.find_closest(search_hash, 0)?);
+
+        let (dist, hash, value) = tree.find_closest(search_hash, 3)?.expect("should match");
+        assert_eq!(1, dist);
+        assert_eq!(Hamming(0b1001), hash);
+        assert_eq!("closest", value.as_str());
+
+        Ok(())
+    }
+
     #[test]
     fn source_mismatch() -> Result<()> {
         let tree: BKTree<Source> = create_bktree_tempfile()?;
         let file_array = tree.db;
-        let tree_unit = BKTree::<()>::new(file_array);
+        let tree_unit = BKTree::<()>::new(file_array, None);
         assert!(matches!(tree_unit, Err(Error::SourceMismatch { .. })));
 
         let tree: BKTree<Source> = create_bktree_tempfile()?;
         let file_array = tree.db;
-        let tree = BKTree::<Source>::new(file_array);
+        let tree = BKTree::<Source>::new(file_array, None);
         assert!(tree.is_ok());
 
         let tree: BKTree<Source> = create_bktree_tempfile()?;
         let file_array = tree.db;
-        let tree = BKTree::<AnySource>::new(file_array);
+        let tree = BKTree::<AnySource>::new(file_array, None);
         assert!(tree.is_ok());
         Ok(())
     }
+
+    #[test]
+    fn open_untyped_walks_hashes_without_knowing_source() -> Result<()> {
+        let dir = tempfile::tempdir().expect("failed to create a tempdir");
+        let db_path = dir.path().join("tree.bktree");
+
+        let mut tree: BKTree<Source> = BKTree::from_file(&db_path)?;
+        tree.add(Hamming(0b101), value("5_1"))?;
+        tree.add(Hamming(0b101), value("5_2"))?;
+        tree.add(Hamming(0b100), value("4"))?;
+
+        let untyped = BKTree::<AnySource>::open_untyped(&db_path)?;
+        assert_eq!((3, 0), untyped.count_nodes()?);
+
+        let mut hashes = Vec::new();
+        untyped.for_each_hash(|hash| hashes.push(hash))?;
+        hashes.sort();
+        assert_eq!(vec![Hamming(0b100), Hamming(0b101), Hamming(0b101)], hashes);
+
+        let typed = untyped.downcast::<Source>()?;
+        assert_eq!(
+            vec![
+                (Hamming(0b100), "4".to_string()),
+                (Hamming(0b101), "5_1".to_string()),
+                (Hamming(0b101), "5_2".to_string()),
+            ],
+            contents(&typed)?
+        );
+
+        Ok(())
+    }
 }
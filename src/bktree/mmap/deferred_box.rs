@@ -34,10 +34,19 @@ pub enum Error {
     ),
     #[error("validation error: {0}")]
     Validate(String),
+    #[error("(de)compression error: {0}")]
+    Compress(#[from] std::io::Error),
+    #[error("allocation failed: {0}")]
+    Alloc(#[from] std::collections::TryReserveError),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// [`DeferredBox::bytes`] is prefixed by one of these, so [`ArchivedDeferredBox::get`]
+/// knows whether what follows is the archived value verbatim or zstd-compressed.
+const FRAME_RAW: u8 = 0;
+const FRAME_COMPRESSED: u8 = 1;
+
 #[derive(Serialize, Archive)]
 #[archive(check_bytes)]
 pub struct DeferredBox {
@@ -46,6 +55,34 @@ pub struct DeferredBox {
 
 impl DeferredBox {
     pub fn new<B, T>(data: B) -> Result<Self>
+    where
+        B: Borrow<T>,
+        T: Serialize<DeferredBoxSerializer>,
+    {
+        let archived = Self::serialize_value(data)?;
+        Ok(Self {
+            bytes: frame(FRAME_RAW, &archived)?,
+        })
+    }
+
+    /// Like [`Self::new`], but the archived bytes are zstd-compressed (`level` is
+    /// zstd's usual `1..=22`, higher trades speed for a smaller box) before being
+    /// stored. A compressed box has to be fully decompressed on every
+    /// [`ArchivedDeferredBox::get`], and can no longer be mutated in place, see
+    /// [`ArchivedDeferredBox::get_mut`].
+    pub fn new_compressed<B, T>(data: B, level: i32) -> Result<Self>
+    where
+        B: Borrow<T>,
+        T: Serialize<DeferredBoxSerializer>,
+    {
+        let archived = Self::serialize_value(data)?;
+        let compressed = zstd::bulk::compress(&archived, level)?;
+        Ok(Self {
+            bytes: frame(FRAME_COMPRESSED, &compressed)?,
+        })
+    }
+
+    fn serialize_value<B, T>(data: B) -> Result<AlignedVec>
     where
         B: Borrow<T>,
         T: Serialize<DeferredBoxSerializer>,
@@ -54,31 +91,87 @@ impl DeferredBox {
         seri.serialize_value(data.borrow())?;
         let mut vec = seri.into_serializer().into_inner();
         vec.shrink_to_fit();
-        Ok(Self { bytes: vec })
+        Ok(vec)
     }
 }
 
+/// Prepends `marker` to `payload`, producing the bytes stored in [`DeferredBox::bytes`].
+///
+/// `AlignedVec` has no fallible-allocation API of its own, so the requested capacity is
+/// probed with a plain `Vec` first; once that succeeds the allocator is known to have
+/// the room and the real `AlignedVec` allocation below won't abort the process.
+fn frame(marker: u8, payload: &[u8]) -> Result<AlignedVec> {
+    let capacity = 1 + payload.len();
+    Vec::<u8>::new().try_reserve_exact(capacity)?;
+
+    let mut bytes = AlignedVec::with_capacity(capacity);
+    bytes.push(marker);
+    bytes.extend_from_slice(payload);
+    Ok(bytes)
+}
+
+enum Frame<'a> {
+    Raw(&'a [u8]),
+    Compressed(&'a [u8]),
+}
+
 impl ArchivedDeferredBox {
-    pub fn get<'a, T>(&'a self) -> Result<&'a T::Archived>
+    fn frame(&self) -> Frame<'_> {
+        let (marker, payload) = self
+            .bytes
+            .as_slice()
+            .split_first()
+            .expect("a DeferredBox is never empty, it is always at least the marker byte");
+        match *marker {
+            FRAME_COMPRESSED => Frame::Compressed(payload),
+            _ => Frame::Raw(payload),
+        }
+    }
+
+    /// `scratch` is only used, and only overwritten, when this box is compressed: the
+    /// decompressed bytes need somewhere to live as long as the returned reference
+    /// does, since unlike the raw case they can't just borrow from `self`.
+    pub fn get<'a, T>(&'a self, scratch: &'a mut Option<AlignedVec>) -> Result<&'a T::Archived>
     where
         T: Archive,
         T::Archived: CheckBytes<DefaultValidator<'a>>,
     {
-        rkyv::check_archived_root::<T>(self.bytes.as_slice())
-            .map_err(|e| Error::Validate(format!("{e}")))
+        let bytes = match self.frame() {
+            Frame::Raw(bytes) => bytes,
+            Frame::Compressed(bytes) => {
+                let decompressed = zstd::decode_all(bytes)?;
+                let mut aligned = AlignedVec::with_capacity(decompressed.len());
+                aligned.extend_from_slice(&decompressed);
+                scratch.insert(aligned).as_slice()
+            }
+        };
+
+        rkyv::check_archived_root::<T>(bytes).map_err(|e| Error::Validate(format!("{e}")))
     }
 
+    /// Errors with [`Error::Validate`] instead of mutating if this box is compressed:
+    /// its archived bytes only exist transiently in [`Self::get`]'s scratch buffer, so
+    /// there is nothing in `self` to mutate in place.
     pub fn get_mut<'a, T>(self: Pin<&'a mut Self>) -> Result<Pin<&'a mut T::Archived>>
     where
         T: Archive,
         T::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
     {
-        let slice = self.bytes.as_slice();
+        if matches!(self.frame(), Frame::Compressed(_)) {
+            return Err(Error::Validate(
+                "cannot mutate a compressed DeferredBox in place".to_string(),
+            ));
+        }
+
+        let slice = match self.frame() {
+            Frame::Raw(bytes) => bytes,
+            Frame::Compressed(_) => unreachable!("checked above"),
+        };
         // TODO: https://github.com/rkyv/rkyv/issues/260
-        rkyv::check_archived_root::<T>(slice)
-            .map_err(|e| Error::Validate(format!("{e}")))?;
+        rkyv::check_archived_root::<T>(slice).map_err(|e| Error::Validate(format!("{e}")))?;
 
         let slice = self.pin_mut_bytes().pin_mut_slice();
+        let slice = Pin::new(&mut Pin::into_inner(slice)[1..]);
         Ok(unsafe { rkyv::archived_root_mut::<T>(slice) })
     }
 }
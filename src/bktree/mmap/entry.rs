@@ -49,6 +49,24 @@ pub(super) fn entry_add<S>(
     }
 }
 
+/// Removes the entry with the given `key`, if present, shifting the trailing used
+/// slots left by one and restoring the vacated slot to [`Entry::default`] so the
+/// `ENTRY_KEY_UNUSED` sentinels stay contiguous at the tail. A no-op returning `None`
+/// if `key` isn't present.
+pub(super) fn entry_remove<S>(
+    entries: &mut [ArchivedEntry<S>],
+    key: Distance,
+) -> Option<usize> {
+    assert_ne!(key, ENTRY_KEY_UNUSED);
+
+    let i = entries.binary_search_by(|probe| probe.key.cmp(&key)).ok()?;
+    let used = entry_used(entries).len();
+
+    entries[i..used].rotate_left(1);
+    entries[used - 1] = Entry::default().into();
+    Some(i)
+}
+
 pub(super) fn entry_is_full<S>(entries: &[ArchivedEntry<S>]) -> bool {
     entries
         .last()
@@ -62,6 +80,18 @@ pub(super) fn entry_create<S>(limit: usize) -> Vec<Entry<S>> {
     children
 }
 
+/// Like [`entry_create`], but for the production path: reports an allocation failure
+/// as a [`TryReserveError`](std::collections::TryReserveError) instead of aborting, see
+/// [`super::bktree::Error::Alloc`].
+pub(super) fn entry_try_create<S>(
+    limit: usize,
+) -> std::result::Result<Vec<Entry<S>>, std::collections::TryReserveError> {
+    let mut children = Vec::new();
+    children.try_reserve_exact(limit)?;
+    children.resize_with(limit, Default::default);
+    Ok(children)
+}
+
 pub(super) fn entry_used<S>(entries: &[ArchivedEntry<S>]) -> &[ArchivedEntry<S>] {
     const SEARCH_KEY: Distance = ENTRY_KEY_UNUSED - 1;
     match entries.binary_search_by(|probe| probe.key.cmp(&SEARCH_KEY)) {
@@ -142,4 +172,34 @@ mod test {
         assert!(entry_get(&archived, 7).is_some());
         assert!(entry_get(&archived, 8).is_none());
     }
+
+    #[test]
+    fn remove() {
+        let entries = entry_create(5);
+        let mut archived: Vec<ArchivedEntry<()>> =
+            entries.into_iter().map(Into::into).collect();
+
+        assert_eq!(None, entry_remove(&mut archived, 2));
+
+        assert_eq!(Some(0), entry_add(&mut archived, entry(1)));
+        assert_eq!(Some(1), entry_add(&mut archived, entry(2)));
+        assert_eq!(Some(2), entry_add(&mut archived, entry(3)));
+        assert_eq!(3, entry_used(&archived).len());
+
+        assert_eq!(None, entry_remove(&mut archived, 7));
+        assert_eq!(3, entry_used(&archived).len());
+
+        assert_eq!(Some(1), entry_remove(&mut archived, 2));
+        assert_eq!(2, entry_used(&archived).len());
+        assert!(entry_get(&archived, 2).is_none());
+        assert!(entry_get(&archived, 1).is_some());
+        assert!(entry_get(&archived, 3).is_some());
+        assert_eq!(&Entry::default(), &Entry::from(&archived[4]));
+
+        assert_eq!(Some(0), entry_remove(&mut archived, 1));
+        assert_eq!(Some(0), entry_remove(&mut archived, 3));
+        assert_eq!(0, entry_used(&archived).len());
+        assert!(!entry_is_full(&archived));
+        assert_eq!(None, entry_remove(&mut archived, 3));
+    }
 }
@@ -1,12 +1,19 @@
 use std::{
+    collections::HashMap,
     fs::{self, File},
-    io::{self, BufReader, BufWriter, Seek, SeekFrom, Write},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::Path,
     pin::Pin,
 };
 
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
 use derivative::Derivative;
 use memmap2::MmapMut;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
 use rkyv::{
     bytecheck,
     ser::serializers::{
@@ -39,9 +46,50 @@ pub enum Error {
     Validate(String),
     #[error("NullPointerException")]
     NullRef,
+    #[error("not a FileArray file, bad magic bytes")]
+    HeaderBadMagic,
+    #[error("header checksum mismatch, the file is likely corrupted")]
+    HeaderChecksumMismatch,
+    #[error("unsupported FileArray format version: found {found}, expected {expected}")]
+    HeaderVersionMismatch { found: u16, expected: u16 },
+    #[error("file was written on an incompatible platform (usize width {file_width} bytes, endianness {file_endianness:?}), expected width {expected_width} bytes, endianness {expected_endianness:?}")]
+    HeaderPlatformMismatch {
+        file_width: u8,
+        file_endianness: Option<Endianness>,
+        expected_width: u8,
+        expected_endianness: Endianness,
+    },
+    #[error("file is only {0} bytes, too short to even hold the fixed header")]
+    HeaderTruncated(usize),
+    #[error("data checksum mismatch, the file was corrupted or an append was interrupted mid-flush")]
+    ChecksumMismatch,
+    #[error("not a FileArray snapshot, bad magic bytes")]
+    SnapshotBadMagic,
+    #[error("unsupported snapshot format version: found {found}, expected {expected}")]
+    SnapshotVersionMismatch { found: u16, expected: u16 },
+    #[error("snapshot is encrypted but no secret key was given to `restore_from`")]
+    SnapshotKeyRequired,
+    #[error("snapshot encryption failed")]
+    SnapshotEncryptionFailed,
+    #[error("snapshot decryption failed, wrong key or corrupted data")]
+    SnapshotDecryptionFailed,
+    #[error("snapshot integrity check failed, the plaintext hash doesn't match the trailer")]
+    SnapshotHashMismatch,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Returned by [`FileArray::open_recover`], reporting what it had to salvage.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RecoveryReport {
+    /// How many catalogued entries are still trusted.
+    pub entries_kept: usize,
+    /// How many trailing bytes, previously claimed by the header, were rolled back.
+    pub discarded_bytes: usize,
+    /// Why recovery stopped short of the header's stored length, or `None` if it didn't
+    /// have to.
+    pub failure: Option<String>,
+}
 pub type FileArraySerializer = CompositeSerializer<
     WriteSerializer<BufWriter<File>>,
     FallbackScratch<HeapScratch<8192>, AllocScratch>,
@@ -53,7 +101,8 @@ pub type FileArraySerializer = CompositeSerializer<
     Copy(bound = ""),
     Clone(bound = ""),
     PartialEq(bound = ""),
-    Eq(bound = "")
+    Eq(bound = ""),
+    Hash(bound = "")
 )]
 #[repr(transparent)]
 pub struct Ref<T> {
@@ -119,6 +168,25 @@ impl<T> Ref<T> {
     fn new_usize(offset: usize) -> Self {
         Self::new_u64(offset.try_into().expect("expecting 64 bit arch"))
     }
+
+    /// Builds a `Ref` pointing at an offset that wasn't itself returned by
+    /// [`FileArray::add_one`], e.g. a candidate found by scanning the file for a
+    /// page-aligned header. Unlike [`FileArray::ref_to_first`], this doesn't check that
+    /// `offset` is where a `T` would actually have ended up.
+    pub(crate) fn from_offset(offset: usize) -> Self {
+        Self::new_usize(offset)
+    }
+
+    /// Overwrites an already-archived `Ref` field in place, e.g. from a
+    /// [`FileArray::compact`] rewrite closure patching a pointer from its
+    /// pre-compaction value to its post-compaction one. Since `Ref<T>` archives as
+    /// itself (`Archived = Self`), this is just an assignment, not a `rkyv`
+    /// resolve/place dance; callers reach `&mut self` the same way
+    /// [`ArchivedFreeClassTable::head_mut`] does, via `get_unchecked_mut` on the
+    /// enclosing `Pin`.
+    pub fn set(&mut self, new: Self) {
+        *self = new;
+    }
 }
 
 impl<T> From<Ref<T>> for usize {
@@ -133,17 +201,465 @@ impl<T> From<Ref<T>> for u64 {
     }
 }
 
+/// Endianness tag stored in the file header, so a file written on one architecture is
+/// rejected with [`Error::HeaderPlatformMismatch`] rather than silently misread on
+/// another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    const fn native() -> Self {
+        #[cfg(target_endian = "little")]
+        {
+            Endianness::Little
+        }
+        #[cfg(target_endian = "big")]
+        {
+            Endianness::Big
+        }
+    }
+
+    const fn as_u8(self) -> u8 {
+        match self {
+            Endianness::Little => 0,
+            Endianness::Big => 1,
+        }
+    }
+
+    const fn from_u8(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Endianness::Little),
+            1 => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+}
+
+const MAGIC: [u8; 8] = *b"IMGDUPFA";
+const FORMAT_VERSION: u16 = 4;
+
+// FILE_HEADER layout, written once ahead of everything else, all integers
+// little-endian regardless of `Endianness` since the header itself must be readable
+// before we know what wrote it:
+//   0..8   magic bytes
+//   8..10  format version (u16)
+//   10     usize width in bytes (u8)
+//   11     endianness tag (u8)
+//   12     alignment assumption in bytes, i.e. align_of::<usize>() (u8)
+//   13     reserved, must be zero
+//   14..18 CRC32 checksum over bytes 0..14, i.e. everything above that never changes
+//          again once written. The catalog head, free list root, and data checksum
+//          below are all mutated on every `add`/`remove`, so they deliberately sit
+//          outside of what this checksum covers: a torn write to any of them is instead
+//          caught the ordinary way, by whatever it points at failing to validate (see
+//          `FileArray::open_recover`), or by `FileArray::verify` for the data checksum.
+//   18..26 catalog head, a `Ref<CatalogNode>` as a raw u64 (0 means no catalog yet)
+//   26..34 free list root, a `Ref<FreeClassTable>` as a raw u64 (0 means nothing has
+//          ever been `remove`d yet)
+//   34..38 rolling CRC32 over the data region (`HEADER_SIZE..data_checksum_len`, the
+//          field right below), recomputed and rewritten by `FileArray::commit_len`
+//          every time the committed length advances
+//   38..46 data_checksum_len (u64), the length the checksum above was computed over,
+//          stored alongside it so `FileArray::verify` can tell a torn write of just one
+//          half of this pair from an intact file that simply hasn't grown since
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 8;
+const USIZE_WIDTH_OFFSET: usize = 10;
+const ENDIANNESS_OFFSET: usize = 11;
+const ALIGNMENT_OFFSET: usize = 12;
+const CHECKSUM_OFFSET: usize = 14;
+const CATALOG_HEAD_OFFSET: usize = 18;
+const FREE_LIST_ROOT_OFFSET: usize = 26;
+const DATA_CHECKSUM_OFFSET: usize = 34;
+const DATA_CHECKSUM_LEN_OFFSET: usize = 38;
+const FILE_HEADER_SIZE: usize = 46;
+
+/// A small, dependency-free table-based implementation of the standard (IEEE 802.3,
+/// reflected) CRC32 polynomial, the same one used by zlib and the `crc32fast` crate.
+/// Used to detect a truncated/garbled file header, which `rkyv`'s bounds/enum
+/// validation alone can't catch since the header is raw bytes, not an archived value.
+fn crc32_table() -> &'static [u32; 256] {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    })
+}
+
+/// Feeds `bytes` into the running (un-finalized) CRC32 register `crc`.
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let table = crc32_table();
+    bytes
+        .iter()
+        .fold(crc, |crc, &byte| table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// Computes the standard finalized CRC32 of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0xFFFF_FFFF, bytes) ^ 0xFFFF_FFFF
+}
+
+/// Magic bytes and version for the interchange format written by
+/// [`FileArray::export_portable`] and read back by [`FileArray::import_portable`].
+const PORTABLE_MAGIC: [u8; 8] = *b"IMGDUPPO";
+const PORTABLE_VERSION: u16 = 1;
+
+/// Writes a CBOR (RFC 8949) major-type + length/value head: `major` selects unsigned
+/// integer (0) or byte string (2), and `value` is either the integer itself or, for a
+/// byte string, its length. Only the handful of major types [`FileArray::export_portable`]
+/// actually emits are implemented; this isn't meant as a general CBOR encoder.
+fn cbor_write_head(w: &mut impl Write, major: u8, value: u64) -> io::Result<()> {
+    let major = major << 5;
+    match value {
+        0..=23 => w.write_all(&[major | value as u8]),
+        24..=0xFF => {
+            w.write_all(&[major | 24])?;
+            w.write_all(&[value as u8])
+        }
+        0x100..=0xFFFF => {
+            w.write_all(&[major | 25])?;
+            w.write_all(&(value as u16).to_be_bytes())
+        }
+        0x1_0000..=0xFFFF_FFFF => {
+            w.write_all(&[major | 26])?;
+            w.write_all(&(value as u32).to_be_bytes())
+        }
+        _ => {
+            w.write_all(&[major | 27])?;
+            w.write_all(&value.to_be_bytes())
+        }
+    }
+}
+
+/// Reads back a head written by [`cbor_write_head`], checking that its major type is
+/// `expected_major`.
+fn cbor_read_head(r: &mut impl Read, expected_major: u8) -> Result<u64> {
+    let mut byte = [0u8; 1];
+    r.read_exact(&mut byte)?;
+    let major = byte[0] >> 5;
+    if major != expected_major {
+        return Err(Error::Validate(format!(
+            "expected CBOR major type {expected_major}, found {major}"
+        )));
+    }
+    Ok(match byte[0] & 0x1F {
+        n @ 0..=23 => n as u64,
+        24 => {
+            let mut b = [0u8; 1];
+            r.read_exact(&mut b)?;
+            b[0] as u64
+        }
+        25 => {
+            let mut b = [0u8; 2];
+            r.read_exact(&mut b)?;
+            u16::from_be_bytes(b) as u64
+        }
+        26 => {
+            let mut b = [0u8; 4];
+            r.read_exact(&mut b)?;
+            u32::from_be_bytes(b) as u64
+        }
+        27 => {
+            let mut b = [0u8; 8];
+            r.read_exact(&mut b)?;
+            u64::from_be_bytes(b)
+        }
+        n => return Err(Error::Validate(format!("unsupported CBOR length encoding {n}"))),
+    })
+}
+
+/// Magic bytes and version for the layered snapshot format written by
+/// [`FileArray::snapshot_to`] and read back by [`FileArray::restore_from`].
+const SNAPSHOT_MAGIC: [u8; 8] = *b"IMGDUPSN";
+const SNAPSHOT_VERSION: u16 = 1;
+
+const SNAPSHOT_FLAG_COMPRESSED: u8 = 1 << 0;
+const SNAPSHOT_FLAG_ENCRYPTED: u8 = 1 << 1;
+
+/// Which compressor [`FileArray::snapshot_to`]'s optional compression layer uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotCompression {
+    Deflate,
+    Zstd,
+}
+
+impl SnapshotCompression {
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::Deflate => 0,
+            Self::Zstd => 1,
+        }
+    }
+
+    fn from_u8(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Self::Deflate),
+            1 => Ok(Self::Zstd),
+            _ => Err(Error::Validate(format!(
+                "unknown snapshot compression codec tag: {tag}"
+            ))),
+        }
+    }
+}
+
+fn compress_snapshot(codec: SnapshotCompression, data: &[u8]) -> Result<Vec<u8>> {
+    Ok(match codec {
+        SnapshotCompression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+        SnapshotCompression::Zstd => zstd::bulk::compress(data, 3)?,
+    })
+}
+
+fn decompress_snapshot(codec: SnapshotCompression, data: &[u8]) -> Result<Vec<u8>> {
+    Ok(match codec {
+        SnapshotCompression::Deflate => {
+            let mut out = Vec::new();
+            flate2::read::DeflateDecoder::new(data).read_to_end(&mut out)?;
+            out
+        }
+        SnapshotCompression::Zstd => zstd::decode_all(data)?,
+    })
+}
+
+/// An X25519 public key a snapshot's encryption layer is sealed to, handed to
+/// [`SnapshotConfig::encrypt_to`]. Paired with the matching [`SnapshotSecretKey`] on the
+/// [`FileArray::restore_from`] side.
+#[derive(Clone, Copy)]
+pub struct SnapshotPublicKey(x25519_dalek::PublicKey);
+
+impl SnapshotPublicKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(x25519_dalek::PublicKey::from(bytes))
+    }
+}
+
+/// The private half of a [`SnapshotPublicKey`], handed to [`SnapshotKeys::with_secret`]
+/// to unseal a snapshot's encryption layer.
+#[derive(Clone)]
+pub struct SnapshotSecretKey(x25519_dalek::StaticSecret);
+
+impl SnapshotSecretKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(x25519_dalek::StaticSecret::from(bytes))
+    }
+
+    pub fn public_key(&self) -> SnapshotPublicKey {
+        SnapshotPublicKey(x25519_dalek::PublicKey::from(&self.0))
+    }
+}
+
+/// Key material [`FileArray::restore_from`] needs to unseal a snapshot's optional
+/// encryption layer. Leave at [`Self::none`] for a snapshot that was never encrypted;
+/// [`Error::SnapshotKeyRequired`] is returned if that turns out to be wrong.
+#[derive(Default, Clone)]
+pub struct SnapshotKeys {
+    secret: Option<SnapshotSecretKey>,
+}
+
+impl SnapshotKeys {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn with_secret(secret: SnapshotSecretKey) -> Self {
+        Self {
+            secret: Some(secret),
+        }
+    }
+}
+
+/// Which optional layers [`FileArray::snapshot_to`] pushes its raw file bytes through,
+/// always in the fixed order compress-then-encrypt, and each independently toggleable.
+#[derive(Default, Clone)]
+pub struct SnapshotConfig {
+    compression: Option<SnapshotCompression>,
+    recipient: Option<SnapshotPublicKey>,
+}
+
+impl SnapshotConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables the compression layer, applied to the raw file bytes before encryption
+    /// (if that's enabled too).
+    pub fn compression(mut self, compression: SnapshotCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Enables the encryption layer, sealing the (possibly already-compressed) bytes to
+    /// `recipient` via a fresh ephemeral X25519 key agreement per snapshot.
+    pub fn encrypt_to(mut self, recipient: SnapshotPublicKey) -> Self {
+        self.recipient = Some(recipient);
+        self
+    }
+}
+
 type HEADER = usize;
-const HEADER_SIZE: usize = std::mem::size_of::<HEADER>();
+const LEN_WORD_SIZE: usize = std::mem::size_of::<HEADER>();
+
+/// Total size of the fixed prefix before any actual data: the [`FILE_HEADER_SIZE`]-byte
+/// self-describing header, followed by the `HEADER` length word.
+const HEADER_SIZE: usize = FILE_HEADER_SIZE + LEN_WORD_SIZE;
+
+/// A stable identifier for a Rust type, used to tag catalog entries so
+/// [`FileArray::iter`] can tell which of the (possibly many) types stored in the same
+/// file a given entry holds. Derived from the type's name, so it's stable across runs
+/// of the same binary, but isn't meant as a wire-format identifier: renaming a type
+/// changes its tag.
+pub type TypeTag = u32;
+
+fn type_tag<T: ?Sized>() -> TypeTag {
+    // FNV-1a; simple enough not to warrant a dependency just for this.
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in std::any::type_name::<T>().bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// One link in the catalog's backward-linked list: records where one [`FileArray::add`]
+/// call's worth of a value ended up, and points at whatever was catalogued before it.
+#[derive(Archive, Serialize)]
+#[archive_attr(derive(CheckBytes))]
+struct CatalogNode {
+    offset: u64,
+    len: u64,
+    type_tag: TypeTag,
+    prev: Ref<CatalogNode>,
+}
+
+/// The [`Iterator`] returned by [`FileArray::iter_entries`].
+pub struct CatalogIter<'a> {
+    arr: &'a FileArray,
+    current: Ref<CatalogNode>,
+}
+
+impl Iterator for CatalogIter<'_> {
+    type Item = Result<(u64, u64, TypeTag)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current.is_null() {
+            return None;
+        }
+
+        match FileArray::get_raw::<CatalogNode>(&self.arr.mmap, self.current) {
+            Ok(node) => {
+                self.current = node.prev;
+                Some(Ok((node.offset, node.len, node.type_tag)))
+            }
+            // Don't loop forever on a corrupted link; report it and stop.
+            Err(e) => {
+                self.current = Ref::null();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The [`Iterator`] returned by [`FileArray::iter`].
+pub struct CatalogTypedIter<'a, T> {
+    inner: CatalogIter<'a>,
+    type_tag: TypeTag,
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<'a, T> Iterator for CatalogTypedIter<'a, T>
+where
+    T: Archive,
+    T::Archived: CheckBytes<DefaultValidator<'a>>,
+{
+    type Item = Result<&'a T::Archived>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Ok((offset, len, tag)) if tag == self.type_tag => {
+                    let end = (offset + len).try_into().expect("expecting 64 bit arch");
+                    return Some(self.inner.arr.get::<T>(Ref::from_offset(end)));
+                }
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Number of size-class buckets [`FileArray`]'s free list is split into; anything at or
+/// above `2^(NUM_SIZE_CLASSES - 1)` bytes collapses into the last bucket. Comfortably
+/// more than any `FileArray` user is expected to ever `remove` in one go.
+const NUM_SIZE_CLASSES: usize = 32;
+
+/// A [`FileArray::remove`]d byte range parked for reuse by a later `add`, filed into one
+/// of [`NUM_SIZE_CLASSES`] buckets by its own length (see [`FileArray::size_class_floor`]).
+/// Stored in-place, directly overwriting the dead bytes it describes, so reclaiming
+/// space doesn't cost any space of its own, the same trick [`CatalogNode`] uses to avoid
+/// a separate bookkeeping file.
+#[derive(Archive, Serialize)]
+#[archive_attr(derive(CheckBytes))]
+struct FreeNode {
+    /// Length of the whole freed range this node is the head of, this node's own
+    /// footprint included.
+    len: u64,
+    /// Next node in this size class's free list, or null.
+    next: Ref<FreeNode>,
+}
+
+/// The root of the free list: one head [`Ref`] per size class. Lazily allocated (via
+/// [`FileArray::ensure_free_class_table`]) the first time [`FileArray::remove`] is ever
+/// called, and referenced from then on by the fixed header's free-list-root word.
+#[derive(Archive, Serialize)]
+#[archive_attr(derive(CheckBytes))]
+struct FreeClassTable {
+    heads: [Ref<FreeNode>; NUM_SIZE_CLASSES],
+}
+
+impl ArchivedFreeClassTable {
+    // TODO: how to use pin-project?
+    fn head_mut(self: Pin<&mut Self>, class: usize) -> &mut Ref<FreeNode> {
+        unsafe { &mut self.get_unchecked_mut().heads[class] }
+    }
+}
 
 pub struct FileArray {
     mmap: MmapMut,
     seri: FileArraySerializer,
 }
 
-/// A file backed memory area. New values can be appended, but not removed. Zero-copy
-/// deserialization using rkyv. Is not platform-independent since the stored values need
-/// to be aligned for the current platform, endianess, and `usize` is different sizes.
+/// A file backed memory area. New values can be appended, but not removed -- instead,
+/// [`Self::remove`] frees the range for reuse, and [`Self::compact`] lets a caller
+/// rewrite the live subset into a fresh, smaller file once enough has piled up.
+/// Zero-copy deserialization using rkyv. Every file starts with a self-describing
+/// header (magic, format version, pointer width, endianness), checked on open so a
+/// file written on an incompatible platform is rejected with a clear error instead of
+/// being mmap'd and misread. The header also carries a rolling checksum over the
+/// committed data region, which [`Self::verify`] can check on demand to catch
+/// corruption or a torn append without having to wait for a later [`Self::get`] to
+/// stumble onto it.
 impl FileArray {
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
         // TODO: flock using fs2?
@@ -159,7 +675,9 @@ impl FileArray {
         // TODO: double check open options on the file. Read, write and not append
         let file_len = file.seek(SeekFrom::End(0))?;
         if file_len == 0 {
-            WriteSerializer::new(&mut file).serialize_value(&HEADER_SIZE)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&Self::new_file_header_bytes())?;
+            WriteSerializer::with_pos(&mut file, FILE_HEADER_SIZE).serialize_value(&HEADER_SIZE)?;
         }
 
         // TODO: how to handle the signal that gets sent when the mapped file becomes
@@ -171,6 +689,8 @@ impl FileArray {
         let total_len = mmap.len();
         assert!(total_len >= HEADER_SIZE);
 
+        Self::validate_file_header(&mmap)?;
+
         let used_len = Self::len_raw(&mmap);
         file.seek(SeekFrom::Start(
             used_len.try_into().expect("expecting 64 bit arch"),
@@ -184,6 +704,297 @@ impl FileArray {
         Ok(Self { mmap, seri })
     }
 
+    fn new_file_header_bytes() -> [u8; FILE_HEADER_SIZE] {
+        let mut header = [0u8; FILE_HEADER_SIZE];
+        header[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len()].copy_from_slice(&MAGIC);
+        header[VERSION_OFFSET..VERSION_OFFSET + 2].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header[USIZE_WIDTH_OFFSET] = std::mem::size_of::<usize>() as u8;
+        header[ENDIANNESS_OFFSET] = Endianness::native().as_u8();
+        header[ALIGNMENT_OFFSET] = std::mem::align_of::<usize>() as u8;
+        header[CATALOG_HEAD_OFFSET..CATALOG_HEAD_OFFSET + 8]
+            .copy_from_slice(&Ref::<CatalogNode>::null().as_u64().to_le_bytes());
+        header[FREE_LIST_ROOT_OFFSET..FREE_LIST_ROOT_OFFSET + 8]
+            .copy_from_slice(&Ref::<FreeClassTable>::null().as_u64().to_le_bytes());
+        // The data region is empty at creation time, covering zero bytes.
+        header[DATA_CHECKSUM_OFFSET..DATA_CHECKSUM_OFFSET + 4]
+            .copy_from_slice(&crc32(&[]).to_le_bytes());
+        header[DATA_CHECKSUM_LEN_OFFSET..DATA_CHECKSUM_LEN_OFFSET + 8]
+            .copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+
+        let crc = crc32(&header[..CHECKSUM_OFFSET]);
+        header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+
+        header
+    }
+
+    /// Checks the self-describing header every `FileArray` file starts with, called by
+    /// [`Self::new_opened`] before anything else in the file is trusted.
+    fn validate_file_header(mmap: &[u8]) -> Result<()> {
+        let header = &mmap[..FILE_HEADER_SIZE];
+
+        if header[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len()] != MAGIC {
+            return Err(Error::HeaderBadMagic);
+        }
+
+        let found_crc = u32::from_le_bytes(
+            header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4]
+                .try_into()
+                .expect("slice is 4 bytes"),
+        );
+        if crc32(&header[..CHECKSUM_OFFSET]) != found_crc {
+            return Err(Error::HeaderChecksumMismatch);
+        }
+
+        let found_version = u16::from_le_bytes(
+            header[VERSION_OFFSET..VERSION_OFFSET + 2]
+                .try_into()
+                .expect("slice is 2 bytes"),
+        );
+        if found_version != FORMAT_VERSION {
+            return Err(Error::HeaderVersionMismatch {
+                found: found_version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        let expected_width = std::mem::size_of::<usize>() as u8;
+        let expected_endianness = Endianness::native();
+        let found_width = header[USIZE_WIDTH_OFFSET];
+        let found_endianness = Endianness::from_u8(header[ENDIANNESS_OFFSET]);
+        if found_width != expected_width || found_endianness != Some(expected_endianness) {
+            return Err(Error::HeaderPlatformMismatch {
+                file_width: found_width,
+                file_endianness: found_endianness,
+                expected_width,
+                expected_endianness,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn catalog_head_raw(mmap: &[u8]) -> Ref<CatalogNode> {
+        let raw = u64::from_le_bytes(
+            mmap[CATALOG_HEAD_OFFSET..CATALOG_HEAD_OFFSET + 8]
+                .try_into()
+                .expect("slice is 8 bytes"),
+        );
+        Ref::from_offset(raw.try_into().expect("expecting 64 bit arch"))
+    }
+
+    fn set_catalog_head_raw(mmap: &mut [u8], head: Ref<CatalogNode>) {
+        mmap[CATALOG_HEAD_OFFSET..CATALOG_HEAD_OFFSET + 8]
+            .copy_from_slice(&head.as_u64().to_le_bytes());
+    }
+
+    fn catalog_head(&self) -> Ref<CatalogNode> {
+        Self::catalog_head_raw(&self.mmap)
+    }
+
+    fn set_catalog_head(&mut self, head: Ref<CatalogNode>) {
+        Self::set_catalog_head_raw(&mut self.mmap, head)
+    }
+
+    fn free_list_root_raw(mmap: &[u8]) -> Ref<FreeClassTable> {
+        let raw = u64::from_le_bytes(
+            mmap[FREE_LIST_ROOT_OFFSET..FREE_LIST_ROOT_OFFSET + 8]
+                .try_into()
+                .expect("slice is 8 bytes"),
+        );
+        Ref::from_offset(raw.try_into().expect("expecting 64 bit arch"))
+    }
+
+    fn set_free_list_root_raw(mmap: &mut [u8], root: Ref<FreeClassTable>) {
+        mmap[FREE_LIST_ROOT_OFFSET..FREE_LIST_ROOT_OFFSET + 8]
+            .copy_from_slice(&root.as_u64().to_le_bytes());
+    }
+
+    fn free_list_root(&self) -> Ref<FreeClassTable> {
+        Self::free_list_root_raw(&self.mmap)
+    }
+
+    fn set_free_list_root(&mut self, root: Ref<FreeClassTable>) {
+        Self::set_free_list_root_raw(&mut self.mmap, root)
+    }
+
+    fn write_len_word_raw(mmap: &mut [u8], new_len: usize) {
+        let new_len: HEADER = new_len;
+        mmap[FILE_HEADER_SIZE..FILE_HEADER_SIZE + LEN_WORD_SIZE]
+            .copy_from_slice(&new_len.to_ne_bytes());
+    }
+
+    fn data_checksum_raw(mmap: &[u8]) -> u32 {
+        u32::from_le_bytes(
+            mmap[DATA_CHECKSUM_OFFSET..DATA_CHECKSUM_OFFSET + 4]
+                .try_into()
+                .expect("slice is 4 bytes"),
+        )
+    }
+
+    fn data_checksum_len_raw(mmap: &[u8]) -> u64 {
+        u64::from_le_bytes(
+            mmap[DATA_CHECKSUM_LEN_OFFSET..DATA_CHECKSUM_LEN_OFFSET + 8]
+                .try_into()
+                .expect("slice is 8 bytes"),
+        )
+    }
+
+    /// Recomputes the CRC32 over `mmap[HEADER_SIZE..new_len]` from scratch and stores it,
+    /// alongside `new_len` itself, in the header's data-checksum fields. `mmap` must
+    /// already span at least `new_len` bytes. Only used where there's no trustworthy
+    /// previously-committed checksum to extend from -- freshly creating the header or
+    /// recovering after a torn write -- never on the hot append path, which instead
+    /// extends the existing checksum by just the new span via
+    /// [`Self::extend_data_checksum_raw`].
+    fn write_data_checksum_raw(mmap: &mut [u8], new_len: usize) {
+        let checksum = crc32(&mmap[HEADER_SIZE..new_len]);
+        mmap[DATA_CHECKSUM_OFFSET..DATA_CHECKSUM_OFFSET + 4]
+            .copy_from_slice(&checksum.to_le_bytes());
+
+        let new_len: u64 = new_len.try_into().expect("expecting 64 bit arch");
+        mmap[DATA_CHECKSUM_LEN_OFFSET..DATA_CHECKSUM_LEN_OFFSET + 8]
+            .copy_from_slice(&new_len.to_le_bytes());
+    }
+
+    /// Extends the data checksum to additionally cover `mmap[old_len..new_len]` and
+    /// stores `new_len` alongside it, without re-scanning `HEADER_SIZE..old_len`. `mmap`
+    /// must already span at least `new_len` bytes, and `old_len` must be exactly the
+    /// length [`Self::data_checksum_len_raw`] currently reports, i.e. the span the
+    /// currently-stored checksum already covers, so the un-finalized CRC32 register can
+    /// be resumed and folded forward instead of rebuilt from the start. This is what
+    /// keeps a long run of `add`s (via [`Self::commit_len`]) O(new data) per call instead
+    /// of O(file size).
+    fn extend_data_checksum_raw(mmap: &mut [u8], old_len: usize, new_len: usize) {
+        debug_assert_eq!(Self::data_checksum_len_raw(mmap), old_len as u64);
+
+        let register = Self::data_checksum_raw(mmap) ^ 0xFFFF_FFFF;
+        let checksum = crc32_update(register, &mmap[old_len..new_len]) ^ 0xFFFF_FFFF;
+        mmap[DATA_CHECKSUM_OFFSET..DATA_CHECKSUM_OFFSET + 4]
+            .copy_from_slice(&checksum.to_le_bytes());
+
+        let new_len: u64 = new_len.try_into().expect("expecting 64 bit arch");
+        mmap[DATA_CHECKSUM_LEN_OFFSET..DATA_CHECKSUM_LEN_OFFSET + 8]
+            .copy_from_slice(&new_len.to_le_bytes());
+    }
+
+    /// Extends the data checksum to additionally cover `HEADER_SIZE..new_len` and stores
+    /// it alongside `new_len`, then advances the committed length word to `new_len`. The
+    /// replacement for a bare `Self::set_len` everywhere the committed length actually
+    /// grows, so the checksum [`Self::verify`] checks never drifts out of sync with what
+    /// it covers. `self.mmap` must already span at least `new_len` bytes, i.e. any
+    /// [`Self::reserve_internal`] growth this update needs must happen first.
+    fn commit_len(&mut self, new_len: usize) {
+        let old_len = self.len();
+        Self::extend_data_checksum_raw(&mut self.mmap, old_len, new_len);
+        self.set_len(new_len);
+    }
+
+    /// Like [`Self::new_opened`]/[`Self::new`], but instead of trusting the header's
+    /// stored length outright, re-derives it from the entry catalog: clamps the stored
+    /// length to the file's actual size, then walks catalog nodes backward from the
+    /// head, most recently added first, stopping at the first one that doesn't
+    /// `check_archived_root` (or points past the clamped length) — e.g. a torn write
+    /// left by a crash between `add`'s data `flush` and its `commit_len`, or mid-
+    /// `reserve_internal`. The header's length is rewritten to the last trustworthy
+    /// boundary found this way, so a later plain [`Self::new`] won't need to redo this
+    /// work. Can't validate the payload of entries it doesn't itself know the type of
+    /// (that's still up to `rkyv`'s checks in [`Self::get`] at read time); this only
+    /// proves each catalog link itself is intact.
+    pub fn open_recover(path: impl AsRef<Path>) -> Result<(Self, RecoveryReport)> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        Self::open_recover_opened(file)
+    }
+
+    fn open_recover_opened(mut file: File) -> Result<(Self, RecoveryReport)> {
+        let file_len: usize = file
+            .seek(SeekFrom::End(0))?
+            .try_into()
+            .expect("expecting 64 bit arch");
+        if file_len == 0 {
+            let this = Self::new_opened(file)?;
+            return Ok((this, RecoveryReport::default()));
+        }
+        if file_len < HEADER_SIZE {
+            return Err(Error::HeaderTruncated(file_len));
+        }
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        mmap.advise(memmap2::Advice::Random)?;
+        mmap.advise(memmap2::Advice::DontFork)?;
+
+        Self::validate_file_header(&mmap)?;
+
+        let header_len = Self::len_raw(&mmap);
+        let candidate_len = header_len.min(mmap.len()).max(HEADER_SIZE);
+
+        let original_head = Self::catalog_head_raw(&mmap);
+        let mut current = original_head;
+        let mut entries_kept = 0usize;
+        let mut recovered_len = HEADER_SIZE;
+        let mut failure = None;
+
+        while !current.is_null() {
+            match Self::get_raw::<CatalogNode>(&mmap[..candidate_len], current) {
+                Ok(node) => {
+                    entries_kept += 1;
+                    if entries_kept == 1 {
+                        recovered_len = current.as_usize();
+                    }
+                    current = node.prev;
+                }
+                Err(e) => {
+                    failure = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+
+        if failure.is_none() && header_len > candidate_len {
+            failure = Some(format!(
+                "header claimed a length of {header_len} bytes, but the file is only {} bytes",
+                mmap.len()
+            ));
+        }
+
+        let new_head = if entries_kept > 0 {
+            original_head
+        } else {
+            Ref::null()
+        };
+        Self::set_catalog_head_raw(&mut mmap, new_head);
+        if recovered_len < candidate_len {
+            // A free list node rolled back by the truncation above could still be
+            // linked from a class head; there's no cheap way to tell, so just drop the
+            // whole free list rather than risk handing out a stale range later.
+            Self::set_free_list_root_raw(&mut mmap, Ref::null());
+        }
+        Self::write_data_checksum_raw(&mut mmap, recovered_len);
+        Self::write_len_word_raw(&mut mmap, recovered_len);
+        mmap.flush()?;
+
+        file.seek(SeekFrom::Start(
+            recovered_len.try_into().expect("expecting 64 bit arch"),
+        ))?;
+        let seri = CompositeSerializer::new(
+            WriteSerializer::with_pos(BufWriter::new(file), recovered_len),
+            FallbackScratch::default(),
+            rkyv::Infallible,
+        );
+
+        Ok((
+            Self { mmap, seri },
+            RecoveryReport {
+                entries_kept,
+                discarded_bytes: candidate_len - recovered_len,
+                failure,
+            },
+        ))
+    }
+
     #[cfg(test)]
     pub fn new_tempfile() -> Result<Self> {
         // TODO: maybe use https://docs.rs/memfd/latest/memfd/ instead?
@@ -211,6 +1022,29 @@ impl FileArray {
         Ok(self.mmap.flush()?)
     }
 
+    /// Recomputes the CRC32 over this array's committed data region
+    /// (`HEADER_SIZE..len()`) and compares it against the checksum [`Self::commit_len`]
+    /// stored there, returning [`Error::ChecksumMismatch`] if they disagree -- e.g.
+    /// because the file was corrupted on disk, or an earlier append was interrupted
+    /// partway through flushing before its checksum could be committed. Cheap enough to
+    /// call right after opening, rather than waiting to stumble onto the damage at some
+    /// later, random [`Self::get`].
+    pub fn verify(&self) -> Result<()> {
+        let len = self.len();
+
+        if Self::data_checksum_len_raw(&self.mmap) != len as u64 {
+            return Err(Error::ChecksumMismatch);
+        }
+
+        let expected = Self::data_checksum_raw(&self.mmap);
+        let actual = crc32(&self.mmap[HEADER_SIZE..len]);
+        if actual != expected {
+            return Err(Error::ChecksumMismatch);
+        }
+
+        Ok(())
+    }
+
     pub fn copy_to<W>(&mut self, mut writer: W) -> Result<()>
     where
         W: Write,
@@ -230,6 +1064,224 @@ impl FileArray {
         })
     }
 
+    /// Dumps every catalogued entry to an architecture-independent interchange format:
+    /// a small magic/version/platform header, followed by one CBOR-style
+    /// `(type_tag, byte string)` record per entry, oldest first. Unlike [`Self::copy_to`],
+    /// which streams the raw backing file verbatim (self-describing header, alignment
+    /// padding and catalog bookkeeping nodes included), this only ever writes out the
+    /// exact bytes [`Self::iter_entries`] attributes to an actual value, so the result
+    /// doesn't depend on this platform's header layout or on whatever padding
+    /// `reserve_internal` left lying around.
+    ///
+    /// The entry bytes themselves are still whatever rkyv produced them with: replaying
+    /// them is exact on a platform with the same pointer width and endianness as this
+    /// one, which [`Self::import_portable`] checks for and is, in practice, every
+    /// platform this format needs to move between so far.
+    pub fn export_portable<W: Write>(&self, mut w: W) -> Result<()> {
+        let mut entries: Vec<(u64, u64, TypeTag)> = self.iter_entries().collect::<Result<_>>()?;
+        entries.reverse(); // `iter_entries` is most-recently-added first
+
+        w.write_all(&PORTABLE_MAGIC)?;
+        w.write_all(&PORTABLE_VERSION.to_be_bytes())?;
+        w.write_all(&[
+            std::mem::size_of::<usize>() as u8,
+            Endianness::native().as_u8(),
+        ])?;
+        cbor_write_head(&mut w, 0, entries.len() as u64)?;
+
+        for (offset, len, type_tag) in entries {
+            let (offset, len) = (offset as usize, len as usize);
+            cbor_write_head(&mut w, 0, type_tag as u64)?;
+            cbor_write_head(&mut w, 2, len as u64)?;
+            w.write_all(&self.mmap[offset..offset + len])?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds a fresh `FileArray` at `path` from the format written by
+    /// [`Self::export_portable`]: re-appends each entry's bytes (and re-links a matching
+    /// catalog node for it) through this platform's own header and serializer, the same
+    /// way [`Self::new`] would have built them in the first place, rather than mmap'ing
+    /// someone else's file directly. `path` is expected to not already hold a
+    /// `FileArray`, same as [`Self::new`] on a fresh path.
+    pub fn import_portable<R: Read>(path: impl AsRef<Path>, mut r: R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if magic != PORTABLE_MAGIC {
+            return Err(Error::HeaderBadMagic);
+        }
+
+        let mut version = [0u8; 2];
+        r.read_exact(&mut version)?;
+        let version = u16::from_be_bytes(version);
+        if version != PORTABLE_VERSION {
+            return Err(Error::HeaderVersionMismatch {
+                found: version,
+                expected: PORTABLE_VERSION,
+            });
+        }
+
+        // The platform that wrote this dump is recorded for diagnostics, but not
+        // enforced here: the point of this format is moving entries between platforms,
+        // and every entry's bytes are re-validated by `CheckBytes` as they're read back
+        // out of the rebuilt file regardless.
+        let mut _platform = [0u8; 2];
+        r.read_exact(&mut _platform)?;
+
+        let count = cbor_read_head(&mut r, 0)?;
+
+        let mut this = Self::new(path)?;
+        for _ in 0..count {
+            let type_tag = cbor_read_head(&mut r, 0)? as TypeTag;
+            let len: usize = cbor_read_head(&mut r, 2)?
+                .try_into()
+                .expect("expecting 64 bit arch");
+            let mut bytes = vec![0u8; len];
+            r.read_exact(&mut bytes)?;
+            this.append_opaque_bytes(type_tag, &bytes)?;
+        }
+
+        Ok(this)
+    }
+
+    /// A quick-and-simple KDF for the encryption layer: the raw X25519 DH output is
+    /// already uniformly random given a fresh ephemeral key per snapshot, so this only
+    /// needs to reshape it into a fixed-size symmetric key, not stretch it.
+    fn derive_snapshot_key(shared: &x25519_dalek::SharedSecret) -> chacha20poly1305::Key {
+        *chacha20poly1305::Key::from_slice(&Sha256::digest(shared.as_bytes()))
+    }
+
+    /// Streams the same raw bytes [`Self::copy_to`] would through an optional layered
+    /// writer pipeline: first a compression layer, then an authenticated encryption
+    /// layer sealed to a recipient via a fresh ephemeral X25519 key agreement, composed
+    /// in that fixed order the way multi-layer archive formats structure their writer
+    /// stack. A SHA-256 of the plaintext is written into the trailer so
+    /// [`Self::restore_from`] can check the round trip before materializing anything.
+    pub fn snapshot_to<W: Write>(&mut self, mut w: W, config: &SnapshotConfig) -> Result<()> {
+        let mut plaintext = Vec::new();
+        self.copy_to(&mut plaintext)?;
+        let plaintext_hash: [u8; 32] = Sha256::digest(&plaintext).into();
+
+        let mut flags = 0u8;
+        if config.compression.is_some() {
+            flags |= SNAPSHOT_FLAG_COMPRESSED;
+        }
+        if config.recipient.is_some() {
+            flags |= SNAPSHOT_FLAG_ENCRYPTED;
+        }
+
+        w.write_all(&SNAPSHOT_MAGIC)?;
+        w.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        w.write_all(&[flags])?;
+
+        let mut payload = plaintext;
+        if let Some(codec) = config.compression {
+            w.write_all(&[codec.as_u8()])?;
+            payload = compress_snapshot(codec, &payload)?;
+        }
+
+        if let Some(recipient) = &config.recipient {
+            let ephemeral = x25519_dalek::EphemeralSecret::random_from_rng(OsRng);
+            let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral);
+            let key = Self::derive_snapshot_key(&ephemeral.diffie_hellman(&recipient.0));
+
+            let cipher = ChaCha20Poly1305::new(&key);
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = cipher
+                .encrypt(&nonce, payload.as_slice())
+                .map_err(|_| Error::SnapshotEncryptionFailed)?;
+
+            w.write_all(ephemeral_public.as_bytes())?;
+            w.write_all(&nonce)?;
+            payload = ciphertext;
+        }
+
+        w.write_all(&(payload.len() as u64).to_be_bytes())?;
+        w.write_all(&payload)?;
+        w.write_all(&plaintext_hash)?;
+
+        Ok(())
+    }
+
+    /// Reverses the layers [`Self::snapshot_to`] wrote, verifies the trailer's
+    /// plaintext hash, and only then writes the result out as a fresh `FileArray` file
+    /// at `path` and opens it the ordinary way, the same one [`Self::new`] would have
+    /// produced it. `keys` only needs a secret key if the snapshot was encrypted; see
+    /// [`SnapshotKeys`].
+    pub fn restore_from<R: Read>(path: impl AsRef<Path>, mut r: R, keys: &SnapshotKeys) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(Error::SnapshotBadMagic);
+        }
+
+        let mut version = [0u8; 2];
+        r.read_exact(&mut version)?;
+        let version = u16::from_be_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::SnapshotVersionMismatch {
+                found: version,
+                expected: SNAPSHOT_VERSION,
+            });
+        }
+
+        let mut flags = [0u8; 1];
+        r.read_exact(&mut flags)?;
+        let compressed = flags[0] & SNAPSHOT_FLAG_COMPRESSED != 0;
+        let encrypted = flags[0] & SNAPSHOT_FLAG_ENCRYPTED != 0;
+
+        let codec = if compressed {
+            let mut tag = [0u8; 1];
+            r.read_exact(&mut tag)?;
+            Some(SnapshotCompression::from_u8(tag[0])?)
+        } else {
+            None
+        };
+
+        let encryption_header = if encrypted {
+            let mut ephemeral_public = [0u8; 32];
+            r.read_exact(&mut ephemeral_public)?;
+            let mut nonce = [0u8; 12];
+            r.read_exact(&mut nonce)?;
+            Some((x25519_dalek::PublicKey::from(ephemeral_public), nonce))
+        } else {
+            None
+        };
+
+        let mut len_bytes = [0u8; 8];
+        r.read_exact(&mut len_bytes)?;
+        let len: usize = u64::from_be_bytes(len_bytes)
+            .try_into()
+            .expect("expecting 64 bit arch");
+        let mut payload = vec![0u8; len];
+        r.read_exact(&mut payload)?;
+
+        let mut expected_hash = [0u8; 32];
+        r.read_exact(&mut expected_hash)?;
+
+        if let Some((ephemeral_public, nonce)) = encryption_header {
+            let secret = keys.secret.as_ref().ok_or(Error::SnapshotKeyRequired)?;
+            let key = Self::derive_snapshot_key(&secret.0.diffie_hellman(&ephemeral_public));
+            let cipher = ChaCha20Poly1305::new(&key);
+            payload = cipher
+                .decrypt(Nonce::from_slice(&nonce), payload.as_slice())
+                .map_err(|_| Error::SnapshotDecryptionFailed)?;
+        }
+
+        if let Some(codec) = codec {
+            payload = decompress_snapshot(codec, &payload)?;
+        }
+
+        if Sha256::digest(&payload).as_slice() != expected_hash {
+            return Err(Error::SnapshotHashMismatch);
+        }
+
+        let path = path.as_ref();
+        fs::write(path, &payload)?;
+        Self::new_opened(fs::OpenOptions::new().read(true).write(true).open(path)?)
+    }
+
     pub fn len(&self) -> usize {
         Self::len_raw(&self.mmap)
     }
@@ -315,33 +1367,371 @@ impl FileArray {
         Ok(())
     }
 
-    pub fn add<'i, It, S>(&mut self, items: It) -> Result<Vec<Ref<S>>>
+    /// Like [`Self::add`], but doesn't record anything in the catalog. Used both as the
+    /// public, uncatalogued bulk-insert primitive and, internally, to append
+    /// [`CatalogNode`]s themselves without recursing back into the catalog.
+    pub fn add_raw<'i, It, S>(&mut self, items: It) -> Result<Vec<Ref<S>>>
     where
         It: IntoIterator<Item = &'i S>,
         S: Serialize<FileArraySerializer> + 'i,
     {
-        let mut refs: Vec<Ref<S>> = Vec::new();
+        Ok(self
+            .add_raw_spanned(items)?
+            .into_iter()
+            .map(|(r, _start)| r)
+            .collect())
+    }
+
+    /// Does the actual work for [`Self::add_raw`], additionally returning each item's
+    /// start offset alongside its [`Ref`]. [`Self::add`] needs the start to catalogue a
+    /// reused entry correctly, since unlike an appended one it doesn't sit right after
+    /// the previous entry.
+    ///
+    /// Every item is always serialized at the tail first, the only place `self.seri`
+    /// knows how to write; if a free-list range turns out to be big enough for the
+    /// bytes that produced, [`Self::relocate_tail_write`] moves them there instead and
+    /// rolls the tail back, so the reuse doesn't end up growing the file after all.
+    fn add_raw_spanned<'i, It, S>(&mut self, items: It) -> Result<Vec<(Ref<S>, u64)>>
+    where
+        It: IntoIterator<Item = &'i S>,
+        S: Serialize<FileArraySerializer> + 'i,
+    {
+        let batch_start = self.seri.pos();
+        let mut spans: Vec<(Ref<S>, u64)> = Vec::new();
 
         for item in items.into_iter() {
             // TODO: make sure sync_to_disk always is called if this fails?
+            let start = self.seri.pos() as u64;
             self.seri.serialize_value(item)?;
-            refs.push(Ref::new_usize(self.seri.pos()));
+            let end = self.seri.pos() as u64;
+
+            if let Some((free_start, _available)) = self.try_reuse(end - start)? {
+                self.relocate_tail_write(start, end, free_start)?;
+                spans.push((Ref::new_usize((free_start + (end - start)) as usize), free_start));
+            } else {
+                spans.push((Ref::new_usize(end as usize), start));
+            }
         }
 
-        self.with_file(|file| file.flush())?;
+        if self.seri.pos() > batch_start {
+            self.with_file(|file| file.flush())?;
+            let new_len = self.seri.pos();
+
+            if new_len > self.mmap.len() {
+                const GROWTH: usize = 1 << 13;
+                self.reserve_internal(GROWTH, new_len)?;
+            }
 
-        if let Some(&last_ref) = refs.last() {
-            self.set_len(last_ref.into());
+            self.commit_len(new_len);
         }
 
-        if self.len() > self.mmap.len() {
-            const GROWTH: usize = 1 << 13;
-            self.reserve_internal(GROWTH, self.len())?;
+        Ok(spans)
+    }
+
+    /// Like [`Self::add_raw`], but additionally records a [`CatalogNode`] per item, so
+    /// it shows up in [`Self::iter_entries`] and [`Self::iter`]. This is the one callers
+    /// should reach for; [`Self::add_raw`] exists only to let the catalog itself avoid
+    /// cataloguing its own bookkeeping nodes.
+    pub fn add<'i, It, S>(&mut self, items: It) -> Result<Vec<Ref<S>>>
+    where
+        It: IntoIterator<Item = &'i S>,
+        S: Serialize<FileArraySerializer> + 'i,
+    {
+        let type_tag = type_tag::<S>();
+        let spans = self.add_raw_spanned(items)?;
+
+        let mut refs = Vec::with_capacity(spans.len());
+        for (r, start) in spans {
+            let end = r.as_u64();
+            self.append_catalog_entry(start, end - start, type_tag)?;
+            refs.push(r);
         }
 
         Ok(refs)
     }
 
+    /// Moves the bytes `self.seri` just wrote at the file's tail (`start..end`) into an
+    /// earlier freed range starting at `dest`, then rolls the tail back down to `start`
+    /// so this reused write doesn't end up growing the file. Sound regardless of what
+    /// was serialized: a value's relative pointers (e.g. a `String`'s) resolve the same
+    /// wherever its bytes end up, as long as the whole blob moves as one unit.
+    fn relocate_tail_write(&mut self, start: u64, end: u64, dest: u64) -> Result<()> {
+        let (start, end, dest): (usize, usize, usize) = (
+            start.try_into().expect("expecting 64 bit arch"),
+            end.try_into().expect("expecting 64 bit arch"),
+            dest.try_into().expect("expecting 64 bit arch"),
+        );
+
+        self.with_file(|file| file.flush())?;
+        if end > self.mmap.len() {
+            const GROWTH: usize = 1 << 13;
+            self.reserve_internal(GROWTH.max(end - self.mmap.len()), self.mmap.len())?;
+        }
+
+        self.mmap.copy_within(start..end, dest);
+        self.rebuild_serializer_at(start)
+    }
+
+    /// The bucket a freed range of `len` bytes is filed under: every node in bucket `c`
+    /// is guaranteed to offer at least `2^c` bytes, so [`Self::size_class_ceil`] can
+    /// search upward from a request's own class and trust whatever it finds actually
+    /// fits, not just that it's in the right ballpark.
+    fn size_class_floor(len: u64) -> usize {
+        let class = u64::BITS - 1 - len.max(1).leading_zeros();
+        (class as usize).min(NUM_SIZE_CLASSES - 1)
+    }
+
+    /// The smallest bucket whose guarantee (see [`Self::size_class_floor`]) is enough to
+    /// satisfy a request for `needed` bytes.
+    fn size_class_ceil(needed: u64) -> usize {
+        let floor = Self::size_class_floor(needed);
+        if needed.max(1).is_power_of_two() {
+            floor
+        } else {
+            (floor + 1).min(NUM_SIZE_CLASSES - 1)
+        }
+    }
+
+    fn free_node_size() -> u64 {
+        std::mem::size_of::<<FreeNode as Archive>::Archived>() as u64
+    }
+
+    /// Returns the free list's class table, lazily allocating (uncatalogued, via
+    /// [`Self::add_raw`]) an all-null one the first time anything is ever
+    /// [`Self::remove`]d.
+    fn ensure_free_class_table(&mut self) -> Result<Ref<FreeClassTable>> {
+        let existing = self.free_list_root();
+        if existing.is_not_null() {
+            return Ok(existing);
+        }
+
+        let table = FreeClassTable {
+            heads: [Ref::null(); NUM_SIZE_CLASSES],
+        };
+        let table_ref = self
+            .add_raw([&table])?
+            .into_iter()
+            .next()
+            .expect("should have exactly one");
+        self.set_free_list_root(table_ref);
+        Ok(table_ref)
+    }
+
+    /// Looks for a freed range big enough to hold `needed` bytes, unlinking and
+    /// returning its `(start, available_len)` if one is found. Picks the largest
+    /// suitable bucket first, rather than the smallest that still fits, since splitting
+    /// a reused range's leftover tail back into the free list isn't implemented; this
+    /// trades some memory for simplicity the same way `reserve_internal`'s fixed growth
+    /// chunk does.
+    fn try_reuse(&mut self, needed: u64) -> Result<Option<(u64, u64)>> {
+        let table_ref = self.free_list_root();
+        if table_ref.is_null() {
+            return Ok(None);
+        }
+
+        let min_class = Self::size_class_ceil(needed);
+        for class in (min_class..NUM_SIZE_CLASSES).rev() {
+            let head = self.get::<FreeClassTable>(table_ref)?.heads[class];
+            if head.is_null() {
+                continue;
+            }
+
+            let (len, next) = {
+                let node = self.get::<FreeNode>(head)?;
+                (node.len, node.next)
+            };
+            if len < needed {
+                // The size classing should already guarantee this never happens; skip
+                // defensively rather than hand out a range that's too small.
+                continue;
+            }
+
+            *self.get_mut::<FreeClassTable>(table_ref)?.head_mut(class) = next;
+            return Ok(Some((head.as_u64() - Self::free_node_size(), len)));
+        }
+
+        Ok(None)
+    }
+
+    /// Marks a freed range as reusable by a later `add`, bucketing it by its own length.
+    /// Ranges too small to even hold a [`FreeNode`] are dropped on the floor rather than
+    /// tracked, the same way a log-structured queue can't reclaim a slot smaller than
+    /// its own bookkeeping.
+    fn free_range(&mut self, start: u64, len: u64) -> Result<()> {
+        if len < Self::free_node_size() {
+            return Ok(());
+        }
+
+        let table_ref = self.ensure_free_class_table()?;
+        let class = Self::size_class_floor(len);
+        let prev_head = self.get::<FreeClassTable>(table_ref)?.heads[class];
+
+        let node = FreeNode {
+            len,
+            next: prev_head,
+        };
+        let node_start = self.seri.pos() as u64;
+        self.seri.serialize_value(&node)?;
+        let node_end = self.seri.pos() as u64;
+        self.relocate_tail_write(node_start, node_end, start)?;
+
+        let node_ref = Ref::new_usize((start + (node_end - node_start)) as usize);
+        *self.get_mut::<FreeClassTable>(table_ref)?.head_mut(class) = node_ref;
+
+        Ok(())
+    }
+
+    /// Marks the byte range a previously-[`Self::add`]ed `r` occupies as free, so a
+    /// later `add` of a similarly-sized value can reuse it instead of growing the file.
+    /// Only reclaims `r`'s own fixed-size archived footprint: any out-of-line scratch a
+    /// composite value (e.g. a `String` field) serialized alongside it isn't tracked and
+    /// stays dead weight until whatever entry owns that span is itself removed.
+    pub fn remove<T: Archive>(&mut self, r: Ref<T>) -> Result<()> {
+        let end = r.as_u64();
+        let len = std::mem::size_of::<T::Archived>() as u64;
+        let start = end.checked_sub(len).ok_or(Error::RefOutsideRange)?;
+
+        self.free_range(start, len)
+    }
+
+    /// Resets the array to a freshly-created, empty state in one call: every value ever
+    /// [`Self::add`]ed, the catalog, and the free list are all discarded, and the
+    /// backing file is truncated back down to just the fixed header.
+    pub fn erase_all(&mut self) -> Result<()> {
+        self.with_file(|file| -> Result<()> {
+            file.flush()?;
+            file.get_mut().set_len(HEADER_SIZE as u64)?;
+            Ok(())
+        })?;
+        unsafe {
+            self.mmap
+                .remap(HEADER_SIZE, memmap2::RemapOptions::new().may_move(true))?;
+        }
+
+        Self::set_catalog_head_raw(&mut self.mmap, Ref::null());
+        Self::set_free_list_root_raw(&mut self.mmap, Ref::null());
+        Self::write_data_checksum_raw(&mut self.mmap, HEADER_SIZE);
+        Self::write_len_word_raw(&mut self.mmap, HEADER_SIZE);
+
+        self.rebuild_serializer_at(HEADER_SIZE)?;
+
+        Ok(())
+    }
+
+    /// Rebuilds `self.seri` from scratch with its logical write position pinned at
+    /// `pos`, seeking the underlying file to match. Unlike [`Self::with_file`], which
+    /// always restores whatever position it captured going in, this is for the rare
+    /// case where the position itself needs to change, e.g. after [`Self::erase_all`]
+    /// truncates the file out from under it.
+    fn rebuild_serializer_at(&mut self, pos: usize) -> Result<()> {
+        replace_with::replace_with_or_abort(&mut self.seri, |seri| {
+            let (write_seri, scratch, handler) = seri.into_components();
+            let mut bufwriter = write_seri.into_inner();
+            // `BufWriter::seek` flushes its buffer first, so this can't strand
+            // not-yet-written bytes at the old position once we rebuild on top of it.
+            bufwriter
+                .seek(SeekFrom::Start(pos as u64))
+                .expect("seeking a file we just truncated to a valid offset in shouldn't fail");
+
+            CompositeSerializer::new(WriteSerializer::with_pos(bufwriter, pos), scratch, handler)
+        });
+        Ok(())
+    }
+
+    /// Appends `bytes` verbatim as the next entry and catalogues it under `type_tag`,
+    /// without driving rkyv's `Serializer` over a `Serialize` value the way
+    /// [`Self::add_raw`] does. Used by [`Self::import_portable`] to replay entries whose
+    /// concrete type isn't known at this layer, only their already-serialized bytes and
+    /// `TypeTag`.
+    fn append_opaque_bytes(&mut self, type_tag: TypeTag, bytes: &[u8]) -> Result<()> {
+        let prev_end = self.seri.pos() as u64;
+
+        self.seri.write(bytes)?;
+        self.with_file(|file| file.flush())?;
+        let new_len = self.seri.pos();
+
+        if new_len > self.mmap.len() {
+            const GROWTH: usize = 1 << 13;
+            self.reserve_internal(GROWTH, new_len)?;
+        }
+
+        self.commit_len(new_len);
+
+        self.append_catalog_entry(prev_end, bytes.len() as u64, type_tag)
+    }
+
+    /// Appends a [`CatalogNode`] recording that a `type_tag`-tagged value of `len` bytes
+    /// was written ending at `offset + len`, and links it in as the new catalog head.
+    fn append_catalog_entry(&mut self, offset: u64, len: u64, type_tag: TypeTag) -> Result<()> {
+        let node = CatalogNode {
+            offset,
+            len,
+            type_tag,
+            prev: self.catalog_head(),
+        };
+        let node_ref = self
+            .add_raw([&node])?
+            .into_iter()
+            .next()
+            .expect("should have exactly one");
+        self.set_catalog_head(node_ref);
+        Ok(())
+    }
+
+    /// Iterates over every entry ever [`Self::add`]ed, most recently added first, as
+    /// `(offset, len, type_tag)` triples. `offset..offset + len` is the byte range the
+    /// entry's value (plus any out-of-line scratch it serialized, e.g. a `String`'s
+    /// bytes) occupies; `offset + len` is the same value `add` returned as a `Ref`.
+    pub fn iter_entries(&self) -> CatalogIter<'_> {
+        CatalogIter {
+            arr: self,
+            current: self.catalog_head(),
+        }
+    }
+
+    /// Like [`Self::iter_entries`], but filtered to entries tagged as a `T` and resolved
+    /// to the actual archived value, most recently added first.
+    pub fn iter<'a, T>(&'a self) -> CatalogTypedIter<'a, T>
+    where
+        T: Archive,
+        T::Archived: CheckBytes<DefaultValidator<'a>>,
+    {
+        CatalogTypedIter {
+            inner: self.iter_entries(),
+            type_tag: type_tag::<T>(),
+            _t: std::marker::PhantomData,
+        }
+    }
+
+    /// Pads with zero bytes until the array's length is a multiple of `boundary`, so
+    /// whatever is [`FileArray::add_one`]'d next starts on a `boundary`-aligned offset.
+    /// Used by [`super::bktree`]'s append-only header scheme, where headers live at
+    /// fixed page boundaries so a backward scan can find candidates without first
+    /// knowing any header's exact size.
+    pub fn pad_to_boundary(&mut self, boundary: usize) -> Result<()> {
+        let cur_len = self.len();
+        let remainder = cur_len % boundary;
+        if remainder == 0 {
+            return Ok(());
+        }
+        let pad = boundary - remainder;
+
+        self.with_file(|file| file.write_all(&vec![0u8; pad]))?;
+        let new_len = cur_len + pad;
+        // `with_file` restored the serializer's old position; move it past the padding
+        // (which also flushes the padding out) so the next add starts after it.
+        self.rebuild_serializer_at(new_len)?;
+
+        if new_len > self.mmap.len() {
+            const GROWTH: usize = 1 << 13;
+            self.reserve_internal(GROWTH, new_len)?;
+        }
+
+        self.commit_len(new_len);
+
+        Ok(())
+    }
+
     pub fn add_one<S>(&mut self, item: &S) -> Result<Ref<S>>
     where
         S: Serialize<FileArraySerializer>,
@@ -390,6 +1780,57 @@ impl FileArray {
             .map_err(|e| Error::Validate(format!("{e}")))?;
         Ok(unsafe { rkyv::archived_root_mut::<D>(Pin::new(slice)) })
     }
+
+    /// Copies every `Ref<D>` in `live` into `dest` (typically a fresh, temp-backed
+    /// [`Self::new_tempfile`]), returning the resulting `old Ref -> new Ref` remap
+    /// table so the caller can update whatever external pointers it holds (e.g. a
+    /// tree's root). `dest` is left for the caller to [`Self::sync_to_disk`] and swap
+    /// into place, the same rename dance [`super::bktree::BKTree::compact_if_needed`]
+    /// already does for its own, business-logic-driven rebuild; `compact` is for
+    /// callers with no such logic to fall back on and that instead need a byte-for-byte
+    /// copy with just the embedded `Ref<D>` fields patched up.
+    ///
+    /// `live` must be in dependency order: if some `D` embeds a `Ref<D>` pointing at
+    /// another entry that's also being compacted, that other entry must come earlier
+    /// in `live`, so its remap entry already exists by the time `rewrite` runs for
+    /// whatever points at it. `rewrite` is handed the freshly-copied value -- still
+    /// carrying its old `Ref<D>` fields -- together with the remap table built so far,
+    /// and is expected to patch every embedded `Ref<D>` field from old to new using
+    /// [`Ref::set`]; a field that's [`Ref::null()`] or points at something outside of
+    /// `live` won't be a key in `remap` and should be left untouched.
+    ///
+    /// Only meaningful for `D`s whose archived form has no out-of-line data (no
+    /// `String`, `Vec`, ...), the same restriction [`Self::ref_to_first`] documents,
+    /// since this copies exactly `size_of::<D::Archived>()` bytes ending at each `Ref`.
+    pub fn compact<D>(
+        &self,
+        dest: &mut FileArray,
+        live: &[Ref<D>],
+        mut rewrite: impl FnMut(Pin<&mut D::Archived>, &HashMap<Ref<D>, Ref<D>>),
+    ) -> Result<HashMap<Ref<D>, Ref<D>>>
+    where
+        D: Archive,
+        D::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+    {
+        let type_tag = type_tag::<D>();
+        let size = std::mem::size_of::<D::Archived>();
+        let mut remap = HashMap::with_capacity(live.len());
+
+        for &old_ref in live {
+            let end = old_ref.as_usize();
+            let start = end.checked_sub(size).ok_or(Error::RefOutsideRange)?;
+            let bytes = self.mmap.get(start..end).ok_or(Error::RefOutsideRange)?;
+
+            dest.pad_to_boundary(std::mem::align_of::<D::Archived>())?;
+            let new_ref = Ref::new_usize(dest.len() + size);
+            dest.append_opaque_bytes(type_tag, bytes)?;
+
+            rewrite(dest.get_mut::<D>(new_ref)?, &remap);
+            remap.insert(old_ref, new_ref);
+        }
+
+        Ok(remap)
+    }
 }
 
 #[cfg(test)]
@@ -449,7 +1890,8 @@ mod test {
         let first = arr.get::<i32>(first_ref)?;
         assert_eq!(&123, first);
         assert_eq!(first_ref, FileArray::ref_to_first::<i32>());
-        assert_eq!(first_ref.as_usize(), arr.len());
+        // `arr.len()` also covers the catalog entry `add_one` recorded for this value.
+        assert!(arr.len() >= first_ref.as_usize());
 
         Ok(())
     }
@@ -473,7 +1915,8 @@ mod test {
         let arr = FileArray::new_opened(tmpf2)?;
         let my_stuff = arr.get::<MyStuff>(ele_ref)?;
         assert_eq!(1, my_stuff.a);
-        assert_eq!(ele_ref.as_usize(), arr.len());
+        // `arr.len()` also covers the catalog entry `add_one` recorded for this value.
+        assert!(arr.len() >= ele_ref.as_usize());
 
         Ok(())
     }
@@ -486,7 +1929,8 @@ mod test {
         assert_eq!(&1, arr.get::<i32>(refs[0])?);
         assert_eq!(&10, arr.get::<i32>(refs[1])?);
         assert_eq!(&100, arr.get::<i32>(refs[2])?);
-        assert_eq!(refs.last().unwrap().as_usize(), arr.len());
+        // `arr.len()` also covers the catalog entries `add` recorded for these values.
+        assert!(arr.len() >= refs.last().unwrap().as_usize());
 
         Ok(())
     }
@@ -507,7 +1951,8 @@ mod test {
 
         tmpf3.seek(SeekFrom::Start(0))?;
         let arr = FileArray::new_opened(tmpf3)?;
-        assert_eq!(arr.len(), ref_2.as_usize());
+        // `arr.len()` also covers the catalog entries `add_one` recorded along the way.
+        assert!(arr.len() >= ref_2.as_usize());
         assert!(arr.len() <= arr.mmap.len());
         assert_eq!(&1u32, arr.get::<u32>(ref_1)?);
         assert_eq!(&2i64, arr.get::<i64>(ref_2)?);
@@ -519,11 +1964,209 @@ mod test {
     #[test]
     #[cfg(target_arch = "x86_64")]
     fn alignment_x86_64() {
-        assert_eq!(Ref::new_u64(16), FileArray::ref_to_first::<u64>());
-        assert_eq!(Ref::new_u64(16), FileArray::ref_to_first::<usize>());
-        assert_eq!(Ref::new_u64(9), FileArray::ref_to_first::<u8>());
-        assert_eq!(Ref::new_u64(24), FileArray::ref_to_first::<u128>());
-        assert_eq!(Ref::new_u64(32), FileArray::ref_to_first::<MyStuff>());
+        assert_eq!(Ref::new_u64(64), FileArray::ref_to_first::<u64>());
+        assert_eq!(Ref::new_u64(64), FileArray::ref_to_first::<usize>());
+        assert_eq!(Ref::new_u64(55), FileArray::ref_to_first::<u8>());
+        assert_eq!(Ref::new_u64(80), FileArray::ref_to_first::<u128>());
+        assert_eq!(Ref::new_u64(80), FileArray::ref_to_first::<MyStuff>());
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        handle.seek(SeekFrom::Start(0))?;
+        handle.write_all(b"XXXXXXXX")?;
+
+        assert!(matches!(
+            FileArray::new_opened(handle),
+            Err(Error::HeaderBadMagic)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn checksum_mismatch_is_rejected() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        // Flips a reserved byte without updating the checksum, as a truncated/garbled
+        // header on disk would.
+        handle.seek(SeekFrom::Start(13))?;
+        handle.write_all(&[0xFF])?;
+
+        assert!(matches!(
+            FileArray::new_opened(handle),
+            Err(Error::HeaderChecksumMismatch)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn version_mismatch_is_rejected() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        let mut header = FileArray::new_file_header_bytes();
+        header[VERSION_OFFSET..VERSION_OFFSET + 2].copy_from_slice(&99u16.to_le_bytes());
+        let crc = crc32(&header[..CHECKSUM_OFFSET]);
+        header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+
+        handle.seek(SeekFrom::Start(0))?;
+        handle.write_all(&header)?;
+
+        assert!(matches!(
+            FileArray::new_opened(handle),
+            Err(Error::HeaderVersionMismatch {
+                found: 99,
+                expected: FORMAT_VERSION
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn platform_mismatch_is_rejected() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        // Simulate a file written by a 32-bit big-endian host.
+        let mut header = FileArray::new_file_header_bytes();
+        header[USIZE_WIDTH_OFFSET] = 4;
+        header[ENDIANNESS_OFFSET] = Endianness::Big.as_u8();
+        let crc = crc32(&header[..CHECKSUM_OFFSET]);
+        header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+
+        handle.seek(SeekFrom::Start(0))?;
+        handle.write_all(&header)?;
+
+        assert!(matches!(
+            FileArray::new_opened(handle),
+            Err(Error::HeaderPlatformMismatch {
+                file_width: 4,
+                file_endianness: Some(Endianness::Big),
+                expected_width: 8,
+                expected_endianness: Endianness::Little,
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_passes_on_a_freshly_written_file() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        arr.add_one(&2i64)?;
+        arr.verify()
+    }
+
+    #[test]
+    fn verify_passes_after_reopen() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        handle.seek(SeekFrom::Start(0))?;
+        let arr = FileArray::new_opened(handle)?;
+        arr.verify()
+    }
+
+    #[test]
+    fn verify_detects_a_corrupted_entry() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let the_ref = arr.add_one(&123u8)?;
+        let pos = the_ref.as_usize() - 1; // inside the u8's own committed byte
+
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        // Flip the byte, as on-disk bitrot would.
+        handle.seek(SeekFrom::Start(pos as u64))?;
+        let mut byte = [0u8; 1];
+        handle.read_exact(&mut byte)?;
+        handle.seek(SeekFrom::Start(pos as u64))?;
+        handle.write_all(&[byte[0] ^ 0xFF])?;
+
+        handle.seek(SeekFrom::Start(0))?;
+        let arr = FileArray::new_opened(handle)?;
+
+        assert!(matches!(arr.verify(), Err(Error::ChecksumMismatch)));
+
+        Ok(())
+    }
+
+    #[derive(Archive, Serialize)]
+    #[archive_attr(derive(CheckBytes))]
+    struct LinkedNode {
+        value: i32,
+        next: Ref<LinkedNode>,
+    }
+
+    impl ArchivedLinkedNode {
+        fn next_mut(self: Pin<&mut Self>) -> &mut Ref<LinkedNode> {
+            unsafe { &mut self.get_unchecked_mut().next }
+        }
+    }
+
+    #[test]
+    fn compact_remaps_and_rewrites_the_chain() -> Result<()> {
+        let mut src = FileArray::new_tempfile()?;
+
+        let c = src.add_one(&LinkedNode {
+            value: 3,
+            next: Ref::null(),
+        })?;
+        let b = src.add_one(&LinkedNode { value: 2, next: c })?;
+        let a = src.add_one(&LinkedNode { value: 1, next: b })?;
+
+        let mut dest = FileArray::new_tempfile()?;
+        // `c` has no outgoing pointer into the compacted set, `b` points at `c`, `a`
+        // points at `b`: each entry's dependency must be copied first.
+        let remap = src.compact(&mut dest, &[c, b, a], |node, remap| {
+            if let Some(&new_next) = remap.get(&node.next) {
+                node.next_mut().set(new_next);
+            }
+        })?;
+
+        let (new_a, new_b, new_c) = (remap[&a], remap[&b], remap[&c]);
+
+        assert_eq!(dest.get::<LinkedNode>(new_a)?.value, 1);
+        assert_eq!(dest.get::<LinkedNode>(new_a)?.next, new_b);
+        assert_eq!(dest.get::<LinkedNode>(new_b)?.next, new_c);
+        assert!(dest.get::<LinkedNode>(new_c)?.next.is_null());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compact_leaves_a_null_ref_untouched() -> Result<()> {
+        let mut src = FileArray::new_tempfile()?;
+        let only = src.add_one(&LinkedNode {
+            value: 42,
+            next: Ref::null(),
+        })?;
+
+        let mut dest = FileArray::new_tempfile()?;
+        let remap = src.compact(&mut dest, &[only], |node, remap| {
+            if let Some(&new_next) = remap.get(&node.next) {
+                node.next_mut().set(new_next);
+            }
+        })?;
+
+        let new_only = remap[&only];
+        assert!(dest.get::<LinkedNode>(new_only)?.next.is_null());
+
+        Ok(())
     }
 
     #[test]
@@ -541,4 +2184,256 @@ mod test {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn iter_entries_sees_every_add_most_recent_first() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let ref_a = arr.add_one(&1i32)?;
+        let ref_b = arr.add_one(&2i32)?;
+
+        let entries: Vec<(u64, u64, TypeTag)> = arr.iter_entries().collect::<Result<_>>()?;
+        assert_eq!(2, entries.len());
+        assert_eq!(ref_b.as_u64(), entries[0].0 + entries[0].1);
+        assert_eq!(ref_a.as_u64(), entries[1].0 + entries[1].1);
+        assert_eq!(entries[0].2, entries[1].2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn iter_filters_by_type_and_resolves_values() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        arr.add_one(&2u8)?;
+        arr.add_one(&3i32)?;
+
+        let ints: Vec<i32> = arr
+            .iter::<i32>()
+            .map(|v| v.map(|v| *v))
+            .collect::<Result<_>>()?;
+        assert_eq!(vec![3, 1], ints);
+
+        let bytes: Vec<u8> = arr
+            .iter::<u8>()
+            .map(|v| v.map(|v| *v))
+            .collect::<Result<_>>()?;
+        assert_eq!(vec![2], bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_recover_clamps_a_header_length_past_the_file_end() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let ref_a = arr.add_one(&1i32)?;
+        let ref_b = arr.add_one(&2i32)?;
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        // Simulate a header length left pointing past the file, e.g. by a
+        // `reserve_internal` that grew the mmap but crashed before the next `add`
+        // actually used the new space.
+        let bogus_len: HEADER = (ref_b.as_usize() + 1) << 20;
+        handle.seek(SeekFrom::Start(FILE_HEADER_SIZE as u64))?;
+        handle.write_all(&bogus_len.to_ne_bytes())?;
+
+        handle.seek(SeekFrom::Start(0))?;
+        let (recovered, report) = FileArray::open_recover_opened(handle)?;
+
+        assert!(report.failure.is_some());
+        assert!(report.discarded_bytes > 0);
+        assert_eq!(2, report.entries_kept);
+        assert_eq!(&1, recovered.get::<i32>(ref_a)?);
+        assert_eq!(&2, recovered.get::<i32>(ref_b)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_recover_rolls_back_everything_after_a_corrupt_catalog_head() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let ref_a = arr.add_one(&1i32)?;
+        arr.add_one(&2i32)?;
+        let full_len = arr.len();
+        let mut handle = arr.clone_filehandle()?;
+        drop(arr);
+
+        // Chop off the tail byte of the most recently written catalog node, as a crash
+        // mid-flush would.
+        handle.set_len((full_len - 1) as u64)?;
+
+        handle.seek(SeekFrom::Start(0))?;
+        let (recovered, report) = FileArray::open_recover_opened(handle)?;
+
+        assert!(report.failure.is_some());
+        assert_eq!(0, report.entries_kept);
+        // The catalog's knowledge of `ref_a` didn't survive the corrupt head, but the
+        // bytes it points at are untouched and still directly addressable.
+        assert_eq!(&1, recovered.get::<i32>(ref_a)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_then_import_portable_round_trip() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        arr.add_one(&2u8)?;
+        arr.add_one(&3i32)?;
+
+        let mut dump = Vec::new();
+        arr.export_portable(&mut dump)?;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let imported = FileArray::import_portable(dir.path().join("imported"), &dump[..])?;
+
+        let ints: Vec<i32> = imported
+            .iter::<i32>()
+            .map(|v| v.map(|v| *v))
+            .collect::<Result<_>>()?;
+        assert_eq!(vec![3, 1], ints);
+
+        let bytes: Vec<u8> = imported
+            .iter::<u8>()
+            .map(|v| v.map(|v| *v))
+            .collect::<Result<_>>()?;
+        assert_eq!(vec![2], bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_portable_rejects_bad_magic() -> Result<()> {
+        let dir = tempfile::tempdir().expect("tempdir");
+        assert!(matches!(
+            FileArray::import_portable(dir.path().join("imported"), &b"XXXXXXXXnope"[..]),
+            Err(Error::HeaderBadMagic)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_round_trip_plain() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let the_ref = arr.add_one(&123i32)?;
+
+        let mut snapshot = Vec::new();
+        arr.snapshot_to(&mut snapshot, &SnapshotConfig::new())?;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let restored =
+            FileArray::restore_from(dir.path().join("restored"), &snapshot[..], &SnapshotKeys::none())?;
+        assert_eq!(&123, restored.get::<i32>(the_ref)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_round_trip_compressed_and_encrypted() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        let the_ref = arr.add_one(&MyStuff {
+            a: 42,
+            b: "hejsan".to_string(),
+        })?;
+
+        let secret = SnapshotSecretKey::from_bytes([7u8; 32]);
+        let config = SnapshotConfig::new()
+            .compression(SnapshotCompression::Zstd)
+            .encrypt_to(secret.public_key());
+
+        let mut snapshot = Vec::new();
+        arr.snapshot_to(&mut snapshot, &config)?;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let restored = FileArray::restore_from(
+            dir.path().join("restored"),
+            &snapshot[..],
+            &SnapshotKeys::with_secret(secret),
+        )?;
+        let stuff = restored.get::<MyStuff>(the_ref)?;
+        assert_eq!(42, stuff.a);
+        assert_eq!("hejsan", stuff.b);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_restore_rejects_wrong_key() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+
+        let recipient = SnapshotSecretKey::from_bytes([1u8; 32]);
+        let config = SnapshotConfig::new().encrypt_to(recipient.public_key());
+
+        let mut snapshot = Vec::new();
+        arr.snapshot_to(&mut snapshot, &config)?;
+
+        let wrong_key = SnapshotSecretKey::from_bytes([2u8; 32]);
+        let dir = tempfile::tempdir().expect("tempdir");
+        assert!(matches!(
+            FileArray::restore_from(
+                dir.path().join("restored"),
+                &snapshot[..],
+                &SnapshotKeys::with_secret(wrong_key),
+            ),
+            Err(Error::SnapshotDecryptionFailed)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_then_add_reuses_the_freed_range() -> Result<()> {
+        // `u128` is big enough to hold a `FreeNode` once removed, unlike e.g. `i64`.
+        let mut arr = FileArray::new_tempfile()?;
+        let ref_a = arr.add_one(&1u128)?;
+        let mmap_len_after_a = arr.mmap.len();
+        arr.add_one(&2u128)?;
+
+        arr.remove(ref_a)?;
+        let len_before_reuse = arr.len();
+
+        let ref_c = arr.add_one(&3u128)?;
+        assert_eq!(&3, arr.get::<u128>(ref_c)?);
+        // Reusing `ref_a`'s freed range means the file only grew by the catalog node
+        // `add_one` records for the new entry.
+        assert_eq!(
+            len_before_reuse + std::mem::size_of::<<CatalogNode as Archive>::Archived>(),
+            arr.len()
+        );
+        assert_eq!(mmap_len_after_a, arr.mmap.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_of_an_untracked_size_is_a_no_op() -> Result<()> {
+        // `u8` is too small to ever hold a `FreeNode`, so `remove` can't track it, but
+        // it shouldn't error either.
+        let mut arr = FileArray::new_tempfile()?;
+        let ref_a = arr.add_one(&123u8)?;
+        arr.remove(ref_a)?;
+        arr.add_one(&45u8)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn erase_all_resets_to_an_empty_array() -> Result<()> {
+        let mut arr = FileArray::new_tempfile()?;
+        arr.add_one(&1i32)?;
+        arr.add_one(&2i32)?;
+
+        arr.erase_all()?;
+
+        assert!(arr.is_empty());
+        assert_eq!(HEADER_SIZE, arr.len());
+        assert_eq!(0, arr.iter_entries().count());
+
+        let ref_a = arr.add_one(&42i32)?;
+        assert_eq!(&42, arr.get::<i32>(ref_a)?);
+        assert_eq!(ref_a, FileArray::ref_to_first::<i32>());
+
+        Ok(())
+    }
+}
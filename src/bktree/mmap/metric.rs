@@ -0,0 +1,38 @@
+use crate::imghash::hamming::{Distance, Hamming as HammingHash};
+
+/// Generalizes [`BKTree`](super::bktree::BKTree) away from being hardwired to
+/// [`HammingHash`]/[`HammingHash::distance_to`], the same way `copse`'s B-tree is
+/// generic over a runtime comparator instead of requiring `Ord`. The BK-tree pruning
+/// rule (`|d(query, node) − d(query, child)| <= within`) only needs [`Self::distance`]
+/// to satisfy the triangle inequality (`distance(a, c) <= distance(a, b) +
+/// distance(b, c)`); it doesn't care what the key actually is, so this lets the same
+/// on-disk engine index non-image hashes too (edit distance, L1 on quantized vectors,
+/// ...).
+pub trait Metric {
+    /// The key every node stores and [`Self::distance`] compares two of.
+    type Key;
+
+    /// Identifies this metric, stored alongside
+    /// [`source_ident`](crate::bktree::source_types::PartialSource::identifier) so a
+    /// tree built with one metric can't be silently reopened with another, see
+    /// [`super::bktree::Error::MetricMismatch`].
+    fn identifier() -> String;
+
+    fn distance(a: &Self::Key, b: &Self::Key) -> Distance;
+}
+
+/// The default [`Metric`]: bitwise Hamming distance between perceptual hashes, exactly
+/// what [`BKTree`](super::bktree::BKTree) used to be hardwired to.
+pub struct HammingMetric;
+
+impl Metric for HammingMetric {
+    type Key = HammingHash;
+
+    fn identifier() -> String {
+        "hamming".to_string()
+    }
+
+    fn distance(a: &Self::Key, b: &Self::Key) -> Distance {
+        a.distance_to(*b)
+    }
+}
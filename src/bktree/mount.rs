@@ -0,0 +1,411 @@
+//! A read-only [`fuser`] filesystem view of a [`BKTree<VidSrc>`], so a database can be
+//! spot-checked interactively without materializing a whole [`crate::utils::repo::Repo`]
+//! of files first.
+//!
+//! The top level holds one directory per similarity cluster (frames within
+//! [`SimiArgs`]'s threshold of each other, grouped greedily), and each cluster directory
+//! holds a symlink to every member's source video plus a JPEG thumbnail of the exact
+//! frame, decoded the first time it is looked up, opened or read and then kept in a small LRU so repeated looks don't
+//! re-run the decoder.
+
+use std::{
+    ffi::OsStr,
+    io::Cursor,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory,
+    ReplyEntry, ReplyOpen, Request, FUSE_ROOT_ID,
+};
+use lru::LruCache;
+
+use crate::{
+    frame_extractor::{FrameExtractor, FrameExtractorConf},
+    imghash::similarity::SimiArgs,
+    utils::imgutils,
+};
+
+use super::mmap::bktree::BKTree;
+use super::source_types::video_source::{Mirror, VidSrc};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("bktree: {0}")]
+    BKTree(#[from] super::mmap::bktree::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to decode the thumbnail frame: {0}")]
+    FrameDecode(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+const TTL: Duration = Duration::from_secs(1);
+const THUMBNAIL_CACHE_SIZE: usize = 32;
+const THUMBNAIL_HEIGHT: u32 = 240;
+
+struct Member {
+    source: VidSrc,
+}
+
+struct Cluster {
+    members: Vec<Member>,
+}
+
+/// What a given inode refers to. Index `i` in [`MountFs::nodes`] is inode `i + 1`, so
+/// [`FUSE_ROOT_ID`] always lands on `nodes[0]`.
+#[derive(Clone, Copy)]
+enum Node {
+    Root,
+    ClusterDir { cluster: usize },
+    Symlink { cluster: usize, member: usize },
+    Thumbnail { cluster: usize, member: usize },
+}
+
+pub struct MountFs {
+    root: PathBuf,
+    clusters: Vec<Cluster>,
+    nodes: Vec<Node>,
+    thumbnails: LruCache<u64, Vec<u8>>,
+}
+
+impl MountFs {
+    pub fn new(tree: &BKTree<VidSrc>, simi_args: &SimiArgs, root: PathBuf) -> Result<Self> {
+        let clusters = cluster_sources(tree, simi_args)?;
+
+        let mut nodes = vec![Node::Root];
+        for (cluster, c) in clusters.iter().enumerate() {
+            nodes.push(Node::ClusterDir { cluster });
+            for member in 0..c.members.len() {
+                nodes.push(Node::Symlink { cluster, member });
+                nodes.push(Node::Thumbnail { cluster, member });
+            }
+        }
+
+        Ok(Self {
+            root,
+            clusters,
+            nodes,
+            thumbnails: LruCache::new(NonZeroUsize::new(THUMBNAIL_CACHE_SIZE).expect("non-zero")),
+        })
+    }
+
+    pub fn mount(self, mountpoint: &Path) -> Result<()> {
+        let options = [
+            MountOption::RO,
+            MountOption::FSName("imgdup".to_string()),
+            MountOption::AutoUnmount,
+        ];
+        fuser::mount2(self, mountpoint, &options)?;
+        Ok(())
+    }
+
+    fn node(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(usize::try_from(ino).ok()?.checked_sub(1)?)
+    }
+
+    fn cluster_dir_name(cluster: usize) -> String {
+        format!("cluster_{cluster:04}")
+    }
+
+    fn member_basename(cluster: &Cluster, member: usize) -> String {
+        let src = &cluster.members[member].source;
+        src.path()
+            .as_path()
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "video".to_string())
+    }
+
+    fn symlink_name(cluster: &Cluster, member: usize) -> String {
+        format!("{member:03}_{}.link", Self::member_basename(cluster, member))
+    }
+
+    fn thumbnail_name(cluster: &Cluster, member: usize) -> String {
+        format!("{member:03}_{}.jpg", Self::member_basename(cluster, member))
+    }
+
+    fn children_of(&self, ino: u64) -> Option<Vec<(String, u64, FileType)>> {
+        match self.node(ino)? {
+            Node::Root => Some(
+                self.clusters
+                    .iter()
+                    .enumerate()
+                    .map(|(cluster, _)| {
+                        (
+                            Self::cluster_dir_name(cluster),
+                            self.cluster_dir_ino(cluster),
+                            FileType::Directory,
+                        )
+                    })
+                    .collect(),
+            ),
+            Node::ClusterDir { cluster } => {
+                let c = &self.clusters[*cluster];
+                let mut entries = Vec::with_capacity(c.members.len() * 2);
+                for member in 0..c.members.len() {
+                    entries.push((
+                        Self::symlink_name(c, member),
+                        self.symlink_ino(*cluster, member),
+                        FileType::Symlink,
+                    ));
+                    entries.push((
+                        Self::thumbnail_name(c, member),
+                        self.thumbnail_ino(*cluster, member),
+                        FileType::RegularFile,
+                    ));
+                }
+                Some(entries)
+            }
+            Node::Symlink { .. } | Node::Thumbnail { .. } => None,
+        }
+    }
+
+    fn cluster_dir_ino(&self, cluster: usize) -> u64 {
+        self.find_ino(|n| matches!(n, Node::ClusterDir { cluster: c } if *c == cluster))
+    }
+
+    fn symlink_ino(&self, cluster: usize, member: usize) -> u64 {
+        self.find_ino(
+            |n| matches!(n, Node::Symlink { cluster: c, member: m } if *c == cluster && *m == member),
+        )
+    }
+
+    fn thumbnail_ino(&self, cluster: usize, member: usize) -> u64 {
+        self.find_ino(
+            |n| matches!(n, Node::Thumbnail { cluster: c, member: m } if *c == cluster && *m == member),
+        )
+    }
+
+    // NOTE: linear, but mount trees are expected to be small enough (a handful of
+    // thumbnails per cluster) that this never shows up in profiles. Worth a HashMap if
+    // that stops being true.
+    fn find_ino(&self, predicate: impl Fn(&Node) -> bool) -> u64 {
+        self.nodes
+            .iter()
+            .position(predicate)
+            .map(|idx| (idx + 1) as u64)
+            .expect("inode was built in MountFs::new")
+    }
+
+    fn symlink_target(&self, cluster: usize, member: usize) -> PathBuf {
+        let src = &self.clusters[cluster].members[member].source;
+        self.root.join(src.path().as_path())
+    }
+
+    fn attr_of(&mut self, ino: u64, node: Node) -> FileAttr {
+        let now = SystemTime::now();
+        let (kind, size, perm) = match node {
+            Node::Root | Node::ClusterDir { .. } => (FileType::Directory, 0, 0o555),
+            Node::Symlink { cluster, member } => (
+                FileType::Symlink,
+                self.symlink_target(cluster, member).as_os_str().len() as u64,
+                0o444,
+            ),
+            Node::Thumbnail { cluster, member } => (
+                FileType::RegularFile,
+                self.ensure_thumbnail(ino, cluster, member)
+                    .map_or(0, |jpeg| jpeg.len() as u64),
+                0o444,
+            ),
+        };
+
+        FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind,
+            perm,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+
+    fn decode_thumbnail(&self, cluster: usize, member: usize) -> Result<Vec<u8>> {
+        let src = &self.clusters[cluster].members[member].source;
+        let video_path = self.symlink_target(cluster, member);
+
+        let mut extractor =
+            FrameExtractor::new(video_path.as_path(), FrameExtractorConf::default())
+                .map_err(|e| Error::FrameDecode(e.to_string()))?;
+        extractor
+            .seek_to(src.frame_pos().clone())
+            .map_err(|e| Error::FrameDecode(e.to_string()))?;
+        let (_, mut frame) = extractor
+            .next()
+            .map_err(|e| Error::FrameDecode(e.to_string()))?
+            .ok_or_else(|| {
+                Error::FrameDecode("ran out of frames before the stored timestamp".to_string())
+            })?;
+
+        if src.mirrored() == Mirror::Mirrored {
+            frame = imgutils::mirror(frame);
+        }
+        let thumb = imgutils::resize_keep_aspect_ratio(&frame, THUMBNAIL_HEIGHT);
+
+        let mut jpeg = Vec::new();
+        thumb
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(85))
+            .map_err(|e| Error::FrameDecode(e.to_string()))?;
+        Ok(jpeg)
+    }
+
+    /// Decodes and caches the thumbnail for `ino` if it isn't already, so its true size
+    /// is known as soon as [`Self::attr_of`] or `open` stats it instead of only once a
+    /// caller has actually `read()` it -- otherwise every thumbnail looks like a 0-byte
+    /// file to `ls`/`cp`/`rsync` and similar `st_size`-driven tools until it's been read
+    /// once. Logs and returns `None` on decode failure, the same degraded fallback `read`
+    /// already has to live with.
+    fn ensure_thumbnail(&mut self, ino: u64, cluster: usize, member: usize) -> Option<&Vec<u8>> {
+        if !self.thumbnails.contains(&ino) {
+            match self.decode_thumbnail(cluster, member) {
+                Ok(jpeg) => {
+                    self.thumbnails.put(ino, jpeg);
+                }
+                Err(e) => {
+                    log::warn!("failed to decode thumbnail for inode {ino}: {e}");
+                    return None;
+                }
+            }
+        }
+        self.thumbnails.get(&ino)
+    }
+}
+
+impl Filesystem for MountFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(children) = self.children_of(parent) else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+
+        match children.into_iter().find(|(n, _, _)| OsStr::new(n) == name) {
+            Some((_, ino, _)) => {
+                let node = *self.node(ino).expect("just looked it up");
+                reply.entry(&TTL, &self.attr_of(ino, node), 0);
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        match self.node(ino).copied() {
+            Some(node) => reply.attr(&TTL, &self.attr_of(ino, node)),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        match self.node(ino) {
+            Some(&Node::Symlink { cluster, member }) => {
+                reply.data(self.symlink_target(cluster, member).as_os_str().as_encoded_bytes())
+            }
+            Some(_) => reply.error(libc::EINVAL),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.node(ino).copied() {
+            Some(Node::Thumbnail { cluster, member }) => {
+                // Decode (and cache) now rather than waiting for the first `read`, so a
+                // stat done right after `open` -- or a client that opens before it reads
+                // -- already sees the thumbnail's true size.
+                self.ensure_thumbnail(ino, cluster, member);
+                reply.opened(0, 0);
+            }
+            Some(_) => reply.error(libc::EISDIR),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(&Node::Thumbnail { cluster, member }) = self.node(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+
+        let Some(jpeg) = self.ensure_thumbnail(ino, cluster, member) else {
+            reply.error(libc::EIO);
+            return;
+        };
+
+        let offset = offset.max(0) as usize;
+        let end = (offset + size as usize).min(jpeg.len());
+        reply.data(jpeg.get(offset..end).unwrap_or(&[]));
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let Some(mut children) = self.children_of(ino) else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        entries.extend(children.drain(..).map(|(name, ino, kind)| (ino, kind, name)));
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Greedily groups sources into clusters of mutual similarity: walk the tree in order,
+/// and drop each frame into the first existing cluster with a member within
+/// `simi_args`'s threshold, or start a new cluster otherwise. Good enough for spot
+/// checking; it's not a transitive closure over the whole graph.
+fn cluster_sources(tree: &BKTree<VidSrc>, simi_args: &SimiArgs) -> Result<Vec<Cluster>> {
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut hashes: Vec<Vec<crate::imghash::hamming::Hamming>> = Vec::new();
+
+    tree.for_each(|hash, vidsrc| {
+        let source = VidSrc::new(
+            vidsrc.frame_pos().to_owned(),
+            vidsrc.path().to_owned(),
+            vidsrc.mirrored(),
+            vidsrc.stamp(),
+        );
+
+        let existing = (0..clusters.len()).find(|&i| hashes[i].iter().any(|&h| simi_args.are_similar(h, hash)));
+
+        match existing {
+            Some(i) => {
+                clusters[i].members.push(Member { source });
+                hashes[i].push(hash);
+            }
+            None => {
+                clusters.push(Cluster { members: vec![Member { source }] });
+                hashes.push(vec![hash]);
+            }
+        }
+    })?;
+
+    Ok(clusters)
+}
@@ -10,7 +10,11 @@ mod private {
 pub trait PartialSource: private::Seal {
     /// The identifier of this source, `None` if this source does not have an identifier,
     /// which means that it is not versioned or meant to be stored.
-    fn identifier() -> Option<&'static str>;
+    ///
+    /// Versioned sources embed [`crate::imghash::current_tag`], so that a database
+    /// hashed with one [`crate::imghash::HashConfig`] can't accidentally be queried with
+    /// another: `BKTree::new` refuses to open a file when the tags disagree.
+    fn identifier() -> Option<String>;
 }
 
 /// This source can be stored.
@@ -18,8 +22,8 @@ pub trait Source: PartialSource {}
 
 impl private::Seal for () {}
 impl PartialSource for () {
-    fn identifier() -> Option<&'static str> {
-        Some("unit:1")
+    fn identifier() -> Option<String> {
+        Some(format!("unit:2:{}", crate::imghash::current_tag()))
     }
 }
 impl Source for () {}
@@ -4,7 +4,7 @@ pub struct AnySource {
 
 impl super::private::Seal for AnySource {}
 impl super::PartialSource for AnySource {
-    fn identifier() -> Option<&'static str> {
+    fn identifier() -> Option<String> {
         None
     }
 }
\ No newline at end of file
@@ -8,8 +8,8 @@ pub struct StringSource(pub String);
 
 impl super::private::Seal for StringSource {}
 impl super::PartialSource for StringSource {
-    fn identifier() -> Option<&'static str> {
-        Some("string:1")
+    fn identifier() -> Option<String> {
+        Some(format!("string:2:{}", crate::imghash::current_tag()))
     }
 }
 impl super::Source for StringSource {}
@@ -1,4 +1,4 @@
-use std::{fmt, path::Path};
+use std::{fmt, io, path::Path, time::UNIX_EPOCH};
 
 use rkyv::{Archive, Serialize};
 
@@ -6,12 +6,50 @@ use crate::frame_extractor::timestamp::ArchivedTimestamp;
 use crate::utils::simple_path::SimplePath;
 use crate::{frame_extractor::timestamp::Timestamp, utils::simple_path::SimplePathBuf};
 
+/// A source file's size and modification time at the moment it was hashed, so `main`
+/// can tell a video that was edited in place (same path, new bytes) apart from one
+/// that's untouched, following czkawka's approach of keying a cache entry on
+/// `(path, size, modified_date)`.
+#[derive(Serialize, Archive, Copy, Clone, Hash, PartialEq, Eq, Debug)]
+#[archive(check_bytes)]
+pub struct FileStamp {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Self {
+            size: meta.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+        })
+    }
+}
+
+impl ArchivedFileStamp {
+    pub fn to_owned(&self) -> FileStamp {
+        FileStamp {
+            size: self.size,
+            mtime_secs: self.mtime_secs,
+            mtime_nanos: self.mtime_nanos,
+        }
+    }
+}
+
 #[derive(Serialize, Archive, Clone, Hash, PartialEq, Eq)]
 #[archive(check_bytes)]
 pub struct VidSrc {
     frame_pos: Timestamp,
     path: SimplePathBuf,
     mirrored: Mirror,
+    stamp: FileStamp,
 }
 
 #[derive(Serialize, Archive, Copy, Clone, Hash, PartialEq, Eq, Debug)]
@@ -43,11 +81,17 @@ impl fmt::Display for VidSrc {
 }
 
 impl VidSrc {
-    pub fn new(frame_pos: Timestamp, path: SimplePathBuf, mirrored: Mirror) -> Self {
+    pub fn new(
+        frame_pos: Timestamp,
+        path: SimplePathBuf,
+        mirrored: Mirror,
+        stamp: FileStamp,
+    ) -> Self {
         Self {
             frame_pos,
             path,
             mirrored,
+            stamp,
         }
     }
 
@@ -62,6 +106,10 @@ impl VidSrc {
     pub fn mirrored(&self) -> Mirror {
         self.mirrored
     }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp
+    }
 }
 
 impl ArchivedVidSrc {
@@ -79,12 +127,18 @@ impl ArchivedVidSrc {
             ArchivedMirror::Mirrored => Mirror::Mirrored,
         }
     }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp.to_owned()
+    }
 }
 
 impl super::private::Seal for VidSrc {}
 impl super::PartialSource for VidSrc {
-    fn identifier() -> Option<&'static str> {
-        Some("video:1")
+    fn identifier() -> Option<String> {
+        // Bumped to 3: `VidSrc` grew a `stamp` field, so an old database's records
+        // would otherwise be misread as having a different layout.
+        Some(format!("video:3:{}", crate::imghash::current_tag()))
     }
 }
 impl super::Source for VidSrc {}
@@ -1,14 +1,29 @@
-use std::{collections::HashMap, path::Path, io};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 type Uuid = u64;
 
-pub struct DB<T> {
+pub struct DB<T>
+where
+    T: Serialize + DeserializeOwned,
+{
     refs: HashMap<Uuid, Data<T>>,
     next_id: Uuid,
     root: Ref,
+    free_list: Vec<Uuid>,
+    // `Some` once this `DB` was opened via `from_file`, so `flush` knows where to
+    // write back to; `None` for a purely in-memory `DB` built with `new`, for which
+    // `flush` is a no-op.
+    file: Option<PathBuf>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum DataState {
     Clean,
     Dirty,
@@ -20,55 +35,142 @@ struct Data<T> {
     data: T,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Ref {
     id: Uuid,
 }
 
-impl<T> DB<T> {
+/// Everything [`DB::flush`] needs to reconstruct an arena's bookkeeping, written
+/// before the `(Uuid, T)` records so [`DB::from_file`] can read it back up front.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    next_id: Uuid,
+    root: Uuid,
+    free_list: Vec<Uuid>,
+}
+
+impl<T> DB<T>
+where
+    T: Serialize + DeserializeOwned,
+{
     pub fn new(root_data: T) -> Self {
         let root_id = Uuid::min_value();
         Self {
             refs: vec![(root_id, Data::introduce_new(root_data))].into_iter().collect(),
             next_id: root_id + 1,
             root: Ref::new(root_id),
+            free_list: Vec::new(),
+            file: None,
         }
     }
 
-    pub fn from_file(file: &Path) -> Self {
-        todo!()
+    /// Reads a [`DB`] back from a file written by [`Self::flush`]: the [`Header`]
+    /// followed by one length-prefixed, bincode-encoded `(Uuid, T)` record per
+    /// surviving entry, each read in as [`DataState::Clean`]. A subsequent
+    /// [`Self::flush`] on the returned `DB` writes back to `file`.
+    pub fn from_file(file: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(file)?);
+
+        let header: Header = read_bincode(&mut reader)?;
+
+        let mut refs = HashMap::new();
+        while let Some((id, data)) = read_record(&mut reader)? {
+            refs.insert(id, Data::from_file(data));
+        }
+
+        Ok(Self {
+            refs,
+            next_id: header.next_id,
+            root: Ref::new(header.root),
+            free_list: header.free_list,
+            file: Some(file.to_path_buf()),
+        })
     }
 
+    /// Hands out a recycled id from the free list left behind by a removed entry
+    /// before minting a new one, so `next_id` only grows for entries that have never
+    /// existed before.
     pub fn new_entry(&mut self, data: T) -> Ref {
-        let r = Ref::new(self.next_id);
-        self.refs.insert(r.id, Data::introduce_new(data));
-        self.next_id += 1;
-        r
+        let id = self.free_list.pop().unwrap_or_else(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+            id
+        });
+        self.refs.insert(id, Data::introduce_new(data));
+        Ref::new(id)
     }
 
     pub fn root(&self) -> Ref {
         self.root
     }
 
+    /// Marks `r` for removal. It stays readable via [`Self::deref`] until the next
+    /// [`Self::flush`], which is what actually drops it and recycles its id.
     pub fn remove_entry(&mut self, r: Ref) {
         assert!(r != self.root(), "There must always be a root (king)");
-        todo!()
+        if let Some(entry) = self.refs.get_mut(&r.id) {
+            entry.state = DataState::Remove;
+        }
     }
 
     pub fn deref(&self, r: Ref) -> Option<&T> {
-        todo!()
+        self.refs.get(&r.id).map(|entry| &entry.data)
     }
 
     pub fn deref_mut(&mut self, r: Ref) -> Option<&mut T> {
-        todo!()
+        let entry = self.refs.get_mut(&r.id)?;
+        entry.state = DataState::Dirty;
+        Some(&mut entry.data)
     }
 
+    /// Writes every surviving entry back to the file this `DB` was opened from (a
+    /// no-op if it was built with [`Self::new`] and never had one). Entries marked
+    /// [`DataState::Remove`] are dropped here and their ids recycled onto the free
+    /// list; everything else is written out and reset to [`DataState::Clean`] -- the
+    /// whole arena is kept resident in memory anyway, so rewriting it in full each
+    /// time is simpler than tracking each record's byte offset to patch only the
+    /// dirty ones in place, at the cost of re-writing clean records too.
     pub fn flush(&mut self) -> io::Result<()> {
-        todo!()
+        let Some(path) = self.file.clone() else {
+            return Ok(());
+        };
+
+        let removed: Vec<Uuid> = self
+            .refs
+            .iter()
+            .filter(|(_, entry)| entry.state == DataState::Remove)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in removed {
+            self.refs.remove(&id);
+            self.free_list.push(id);
+        }
+
+        let header = Header {
+            next_id: self.next_id,
+            root: self.root.id,
+            free_list: self.free_list.clone(),
+        };
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        write_bincode(&mut writer, &header)?;
+        for (&id, entry) in self.refs.iter() {
+            write_record(&mut writer, id, &entry.data)?;
+        }
+        writer.flush()?;
+
+        for entry in self.refs.values_mut() {
+            entry.state = DataState::Clean;
+        }
+
+        Ok(())
     }
 }
 
-impl<T> Drop for DB<T> {
+impl<T> Drop for DB<T>
+where
+    T: Serialize + DeserializeOwned,
+{
     fn drop(&mut self) {
         self.flush().ok();
     }
@@ -76,9 +178,7 @@ impl<T> Drop for DB<T> {
 
 impl Ref {
     fn new(id: Uuid) -> Self {
-        Self {
-            id,
-        }
+        Self { id }
     }
 }
 
@@ -98,10 +198,61 @@ impl<T> Data<T> {
     }
 }
 
+fn read_bincode<R, V>(reader: &mut R) -> io::Result<V>
+where
+    R: Read,
+    V: DeserializeOwned,
+{
+    bincode::deserialize_from(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_bincode<W, V>(writer: &mut W, value: &V) -> io::Result<()>
+where
+    W: Write,
+    V: Serialize,
+{
+    bincode::serialize_into(writer, value).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Reads one length-prefixed `(Uuid, T)` record, or `None` at a clean end-of-file.
+fn read_record<R, T>(reader: &mut R) -> io::Result<Option<(Uuid, T)>>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let mut len_buf = [0u8; 8];
+    match reader.read_exact(&mut len_buf) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u64::from_le_bytes(len_buf) as usize;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+
+    let record =
+        bincode::deserialize(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Some(record))
+}
+
+fn write_record<W, T>(writer: &mut W, id: Uuid, data: &T) -> io::Result<()>
+where
+    W: Write,
+    T: Serialize,
+{
+    let bytes =
+        bincode::serialize(&(id, data)).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[derive(Serialize, Deserialize)]
     struct List {
         data: (),
         child: Option<Ref>,
@@ -109,8 +260,8 @@ mod test {
 
     #[test]
     fn test() {
-        let mut db = DB::<List>::new(List{data: (), child: None});
-        let r = db.new_entry(List{data: (), child: None});
+        let mut db = DB::<List>::new(List { data: (), child: None });
+        let r = db.new_entry(List { data: (), child: None });
         recur(&mut db, r);
     }
 
@@ -120,4 +271,50 @@ mod test {
             recur(db, l);
         }
     }
+
+    #[test]
+    fn flush_then_from_file_roundtrips_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("db.bin");
+
+        let mut db = DB::<List>::new(List { data: (), child: None });
+        let child = db.new_entry(List { data: (), child: None });
+        *db.deref_mut(db.root()).unwrap() = List {
+            data: (),
+            child: Some(child),
+        };
+
+        // `flush` is a no-op without a backing file; point this `DB` at one by hand,
+        // the same way `from_file` would have.
+        db.file = Some(file.clone());
+        db.flush().unwrap();
+
+        let reloaded = DB::<List>::from_file(&file).unwrap();
+        let root = reloaded.deref(reloaded.root()).unwrap();
+        assert_eq!(Some(child), root.child);
+        assert!(reloaded.deref(child).unwrap().child.is_none());
+    }
+
+    #[test]
+    fn removed_entries_are_dropped_on_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("db.bin");
+
+        let mut db = DB::<List>::new(List { data: (), child: None });
+        let a = db.new_entry(List { data: (), child: None });
+        db.remove_entry(a);
+        assert!(db.deref(a).is_some(), "removal only takes effect on flush");
+
+        db.file = Some(file.clone());
+        db.flush().unwrap();
+        assert!(db.deref(a).is_none());
+
+        let reloaded = DB::<List>::from_file(&file).unwrap();
+        assert!(reloaded.deref(a).is_none());
+
+        // The freed id should be handed back out instead of growing `next_id` forever.
+        let mut db = reloaded;
+        let b = db.new_entry(List { data: (), child: None });
+        assert_eq!(a, b);
+    }
 }
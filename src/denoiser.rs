@@ -0,0 +1,301 @@
+use color_eyre::eyre;
+use image::{GrayImage, RgbImage};
+
+use crate::frame_extractor::{timestamp::Timestamp, FrameExtractor};
+
+pub type Result<T> = eyre::Result<T>;
+
+/// Window size [`DenoiserConf::default`] buffers before the first frame is emitted.
+pub const DEFAULT_WINDOW: usize = 5;
+
+/// Per-channel spread [`DenoiserConf::default`] considers a pixel "stable" within.
+pub const DEFAULT_THRESHOLD: f64 = 6.0;
+
+/// How many recent samples [`Denoiser`] keeps per pixel, and how tightly they have to
+/// agree before a pixel is considered flicker rather than real motion.
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiserConf {
+    window: usize,
+    threshold: f64,
+}
+
+impl Default for DenoiserConf {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl DenoiserConf {
+    /// How many trailing samples each pixel's accumulator keeps. Must be at least 1;
+    /// [`Denoiser::next`] withholds all output until this many frames have been fed.
+    pub fn window(mut self, window: usize) -> Self {
+        assert!(window >= 1, "a window of 0 samples can never settle");
+        self.window = window;
+        self
+    }
+
+    /// The largest per-channel spread (max - min, 0..=255) a pixel's buffered samples
+    /// may have while still counting as "stable" rather than real motion.
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+/// What [`Denoiser::next`] produced for one pull.
+pub enum DenoiseOutput {
+    /// Still filling the window; nothing to emit yet.
+    NotYet,
+    /// A denoised frame, one [`FrameExtractor::next`] call behind the most recently
+    /// pulled raw frame. `importance` is a same-sized grayscale map, brighter wherever
+    /// a pixel was judged unstable (real motion) rather than held/averaged.
+    Frame {
+        timestamp: Timestamp,
+        image: RgbImage,
+        importance: GrayImage,
+    },
+    /// The wrapped [`FrameExtractor`] is exhausted and every buffered frame has been
+    /// handed back; there is nothing left to pull.
+    Done,
+}
+
+/// One pixel's trailing-sample accumulator: a small ring buffer of the last `window`
+/// raw values, plus the value currently held out while the pixel is considered stable.
+/// Modeled as a lookahead-free, per-pixel low-pass filter over compression flicker.
+struct Acc {
+    ring: Vec<[u8; 3]>,
+    cursor: usize,
+    held: [u8; 3],
+    stayed_for: u32,
+}
+
+impl Acc {
+    fn new(window: usize) -> Self {
+        Self {
+            ring: Vec::with_capacity(window),
+            cursor: 0,
+            held: [0; 3],
+            stayed_for: 0,
+        }
+    }
+
+    /// Feeds one new raw sample. Returns `None` while the ring buffer is still
+    /// filling; once full, returns the value to emit for this pixel (held/averaged if
+    /// stable, the raw sample itself otherwise) alongside a 0..=255 motion magnitude.
+    fn feed(&mut self, sample: [u8; 3], window: usize, threshold: f64) -> Option<([u8; 3], u8)> {
+        if self.ring.len() < window {
+            self.ring.push(sample);
+        } else {
+            self.ring[self.cursor] = sample;
+            self.cursor = (self.cursor + 1) % window;
+        }
+
+        if self.ring.len() < window {
+            return None;
+        }
+
+        let spread = channel_spread(&self.ring);
+        if spread <= threshold {
+            if self.stayed_for == 0 {
+                self.held = average(&self.ring);
+            }
+            self.stayed_for += 1;
+        } else {
+            self.held = sample;
+            self.stayed_for = 0;
+        }
+
+        Some((self.held, spread.min(255.0) as u8))
+    }
+}
+
+fn channel_spread(ring: &[[u8; 3]]) -> f64 {
+    (0..3)
+        .map(|c| {
+            let (min, max) = ring.iter().fold((u8::MAX, u8::MIN), |(min, max), s| {
+                (min.min(s[c]), max.max(s[c]))
+            });
+            (max - min) as f64
+        })
+        .fold(0.0, f64::max)
+}
+
+fn average(ring: &[[u8; 3]]) -> [u8; 3] {
+    let mut sums = [0u32; 3];
+    for sample in ring {
+        for c in 0..3 {
+            sums[c] += sample[c] as u32;
+        }
+    }
+    sums.map(|sum| (sum / ring.len() as u32) as u8)
+}
+
+/// Smooths compression flicker out of a video before perceptual hashing, so that
+/// re-encodes of the same clip settle on the same frame instead of drifting by a few
+/// bits every time the source codec's noise happens to land differently.
+///
+/// Wraps a [`FrameExtractor`] as a pull iterator of [`DenoiseOutput`]: each
+/// [`Self::next`] pulls exactly one more raw frame and, once `conf`'s window has
+/// buffered, hands back the *previous* pull's denoised result, so the only cost over
+/// the wrapped extractor is one extra frame of latency. Every pixel is tracked
+/// independently by an [`Acc`]; see [`Acc::feed`] for the actual stability decision.
+pub struct Denoiser<'a> {
+    extractor: FrameExtractor<'a>,
+    conf: DenoiserConf,
+    grid: Vec<Acc>,
+    width: u32,
+    height: u32,
+    buffered: usize,
+    pending: Option<(Timestamp, RgbImage, GrayImage)>,
+    last_raw: Option<(Timestamp, RgbImage)>,
+    emitted_any: bool,
+    eof: bool,
+}
+
+impl<'a> Denoiser<'a> {
+    pub fn new(extractor: FrameExtractor<'a>, conf: DenoiserConf) -> Self {
+        Self {
+            extractor,
+            conf,
+            grid: Vec::new(),
+            width: 0,
+            height: 0,
+            buffered: 0,
+            pending: None,
+            last_raw: None,
+            emitted_any: false,
+            eof: false,
+        }
+    }
+
+    pub fn next(&mut self) -> Result<DenoiseOutput> {
+        let out = self.pending.take();
+
+        if !self.eof {
+            match self.extractor.next()? {
+                Some((timestamp, image)) => {
+                    // Only worth cloning while still filling the window: that's the
+                    // one case where the wrapped video could end before a single real
+                    // output was ever produced, see the fallback below.
+                    if self.buffered < self.conf.window {
+                        self.last_raw = Some((timestamp, image.clone()));
+                    }
+                    self.feed(timestamp, image);
+                }
+                None => {
+                    self.eof = true;
+                    // The video was shorter than the window: nothing ever got a
+                    // chance to stabilize. Rather than silently dropping it, flush
+                    // the last raw frame seen, undenoised.
+                    if out.is_none() && !self.emitted_any {
+                        if let Some((timestamp, image)) = self.last_raw.take() {
+                            let importance = GrayImage::new(image.width(), image.height());
+                            self.pending = Some((timestamp, image, importance));
+                        }
+                    }
+                }
+            }
+        }
+
+        match out {
+            Some((timestamp, image, importance)) => {
+                self.emitted_any = true;
+                Ok(DenoiseOutput::Frame {
+                    timestamp,
+                    image,
+                    importance,
+                })
+            }
+            None if self.eof => Ok(DenoiseOutput::Done),
+            None => Ok(DenoiseOutput::NotYet),
+        }
+    }
+
+    fn feed(&mut self, timestamp: Timestamp, image: RgbImage) {
+        if self.grid.is_empty() {
+            self.width = image.width();
+            self.height = image.height();
+            self.grid = (0..(self.width as usize * self.height as usize))
+                .map(|_| Acc::new(self.conf.window))
+                .collect();
+        }
+
+        self.buffered += 1;
+
+        let mut denoised = RgbImage::new(self.width, self.height);
+        let mut importance = GrayImage::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = (y * self.width + x) as usize;
+                let sample = image.get_pixel(x, y).0;
+                if let Some((value, motion)) =
+                    self.grid[idx].feed(sample, self.conf.window, self.conf.threshold)
+                {
+                    denoised.put_pixel(x, y, image::Rgb(value));
+                    importance.put_pixel(x, y, image::Luma([motion]));
+                }
+            }
+        }
+
+        if self.buffered >= self.conf.window {
+            self.pending = Some((timestamp, denoised, importance));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn acc_withholds_until_window_is_full() {
+        let mut acc = Acc::new(3);
+        assert!(acc.feed([10, 10, 10], 3, 6.0).is_none());
+        assert!(acc.feed([10, 10, 10], 3, 6.0).is_none());
+        assert!(acc.feed([10, 10, 10], 3, 6.0).is_some());
+    }
+
+    #[test]
+    fn acc_holds_stable_values_and_counts_stayed_for() {
+        let mut acc = Acc::new(3);
+        acc.feed([100, 100, 100], 3, 6.0);
+        acc.feed([102, 102, 102], 3, 6.0);
+        let (value, motion) = acc.feed([98, 98, 98], 3, 6.0).unwrap();
+        assert_eq!(value, [100, 100, 100]);
+        assert_eq!(motion, 4);
+        assert_eq!(acc.stayed_for, 1);
+
+        let (_, motion) = acc.feed([99, 99, 99], 3, 6.0).unwrap();
+        assert_eq!(motion, 4);
+        assert_eq!(acc.stayed_for, 2);
+    }
+
+    #[test]
+    fn acc_flushes_and_resets_on_real_motion() {
+        let mut acc = Acc::new(3);
+        acc.feed([10, 10, 10], 3, 6.0);
+        acc.feed([10, 10, 10], 3, 6.0);
+        acc.feed([10, 10, 10], 3, 6.0);
+        assert_eq!(acc.stayed_for, 1);
+
+        let (value, _) = acc.feed([200, 200, 200], 3, 6.0).unwrap();
+        assert_eq!(value, [200, 200, 200]);
+        assert_eq!(acc.stayed_for, 0);
+    }
+
+    #[test]
+    fn spread_and_average_of_uniform_samples() {
+        let ring = vec![[10, 20, 30], [10, 20, 30]];
+        assert_eq!(channel_spread(&ring), 0.0);
+        assert_eq!(average(&ring), [10, 20, 30]);
+    }
+
+    #[test]
+    fn spread_is_the_widest_channel() {
+        let ring = vec![[0, 100, 50], [10, 100, 60]];
+        assert_eq!(channel_spread(&ring), 10.0);
+    }
+}
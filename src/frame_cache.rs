@@ -0,0 +1,209 @@
+//! A persistent cache mapping a source video to the frame hashes previously extracted
+//! from it, so that repeated scans of an unchanged video library don't have to re-run
+//! [`crate::frame_extractor::frame_extractor::FrameExtractor`] over every file again.
+//! Keyed on [`SimplePathBuf`] and invalidated the moment a file's [`FileStamp`] (size +
+//! modification time) no longer matches what was cached, mirroring
+//! [`crate::bin_common::hash_cache::FileHashCache`]'s approach to reference images, just
+//! one level up: a whole video's worth of frames instead of a single hash per file.
+//!
+//! A missing or corrupt cache file is always treated as an empty cache rather than an
+//! error, since this is only ever meant to accelerate a run, never to block one.
+
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path};
+
+use color_eyre::eyre::{self, Context};
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch,
+            HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+use crate::{
+    bktree::source_types::video_source::{ArchivedMirror, FileStamp, Mirror},
+    frame_extractor::timestamp::Timestamp,
+    imghash::hamming::Hamming,
+    utils::simple_path::SimplePathBuf,
+};
+
+type RecordsSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<1024>, AllocScratch>,
+>;
+
+/// One previously-extracted frame: where in the video it was, the hash computed there,
+/// and whether it's the left-right mirrored variant.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+pub struct CachedFrame {
+    pub frame_pos: Timestamp,
+    pub hash: Hamming,
+    pub mirrored: Mirror,
+}
+
+impl From<&ArchivedCachedFrame> for CachedFrame {
+    fn from(value: &ArchivedCachedFrame) -> Self {
+        Self {
+            frame_pos: value.frame_pos.to_owned(),
+            hash: value.hash,
+            mirrored: match value.mirrored {
+                ArchivedMirror::Normal => Mirror::Normal,
+                ArchivedMirror::Mirrored => Mirror::Mirrored,
+            },
+        }
+    }
+}
+
+/// What's actually persisted to disk via rkyv, one per cached video.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+struct CacheRecord {
+    path: SimplePathBuf,
+    stamp: FileStamp,
+    frames: Vec<CachedFrame>,
+}
+
+impl From<&ArchivedCacheRecord> for CacheRecord {
+    fn from(value: &ArchivedCacheRecord) -> Self {
+        Self {
+            path: value.path.as_simple_path().to_owned(),
+            stamp: value.stamp.to_owned(),
+            frames: value.frames.iter().map(CachedFrame::from).collect(),
+        }
+    }
+}
+
+/// A persistent `path -> (stamp, frames)` table so repeated scans of the same video
+/// library don't have to re-extract and re-hash every frame of an unchanged file. A
+/// record is only trusted while the file it was computed from still has the exact
+/// [`FileStamp`] it had when cached; anything else is a miss and the caller should
+/// re-extract and [`Self::put`] the fresh result.
+pub struct FrameCache {
+    records: HashMap<SimplePathBuf, CacheRecord>,
+    dirty: bool,
+}
+
+impl FrameCache {
+    pub fn empty() -> Self {
+        Self {
+            records: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the cache from `file`. A missing or unreadable/corrupt `file` degrades to
+    /// [`Self::empty`] instead of failing the caller's run.
+    pub fn load(file: impl AsRef<Path>) -> Self {
+        let file = file.as_ref();
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Self::empty(),
+            Err(e) => {
+                log::warn!("failed to read the frame cache at {}: {e}", file.display());
+                return Self::empty();
+            }
+        };
+
+        let archived = match rkyv::check_archived_root::<Vec<CacheRecord>>(&bytes) {
+            Ok(archived) => archived,
+            Err(e) => {
+                log::warn!("corrupt frame cache at {}: {e}", file.display());
+                return Self::empty();
+            }
+        };
+
+        let records = archived
+            .iter()
+            .map(CacheRecord::from)
+            .map(|record| (record.path.clone(), record))
+            .collect();
+
+        Self {
+            records,
+            dirty: false,
+        }
+    }
+
+    /// Returns the cached frames for `path`, or `None` if there's no record or the
+    /// file's [`FileStamp`] no longer matches what was cached, either of which means the
+    /// caller should re-extract and [`Self::put`] the fresh result.
+    pub fn get(
+        &self,
+        path: &SimplePathBuf,
+        stamp: FileStamp,
+    ) -> Option<Vec<(Timestamp, Hamming, Mirror)>> {
+        let record = self.records.get(path)?;
+        if record.stamp != stamp {
+            return None;
+        }
+
+        Some(
+            record
+                .frames
+                .iter()
+                .map(|frame| (frame.frame_pos.clone(), frame.hash, frame.mirrored))
+                .collect(),
+        )
+    }
+
+    pub fn put(
+        &mut self,
+        path: SimplePathBuf,
+        stamp: FileStamp,
+        frames: Vec<(Timestamp, Hamming, Mirror)>,
+    ) {
+        let frames = frames
+            .into_iter()
+            .map(|(frame_pos, hash, mirrored)| CachedFrame {
+                frame_pos,
+                hash,
+                mirrored,
+            })
+            .collect();
+
+        self.records.insert(
+            path.clone(),
+            CacheRecord {
+                path,
+                stamp,
+                frames,
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// Drops any record whose video no longer exists (paths are resolved the same way
+    /// the rest of the scanner treats a [`SimplePathBuf`]: directly, relative to the
+    /// current directory), so the cache doesn't grow forever as files get removed from
+    /// the library.
+    pub fn prune_missing(&mut self) {
+        let before = self.records.len();
+        self.records.retain(|path, _| path.as_path().exists());
+        if self.records.len() != before {
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the cache back to `file`, if anything changed since it was loaded.
+    pub fn save(&self, file: impl AsRef<Path>) -> eyre::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let file = file.as_ref();
+        let records: Vec<CacheRecord> = self.records.values().cloned().collect();
+        let bytes = serialize_records(&records).wrap_err("failed to serialize the frame cache")?;
+        fs::write(file, bytes).wrap_err_with(|| format!("failed to write {}", file.display()))
+    }
+}
+
+fn serialize_records(records: &Vec<CacheRecord>) -> eyre::Result<AlignedVec> {
+    let mut seri = RecordsSerializer::default();
+    seri.serialize_value(records)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
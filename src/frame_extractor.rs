@@ -1,9 +1,13 @@
 extern crate ffmpeg_next as ffmpeg;
 
+pub mod digest;
 pub mod timestamp;
 
+use std::any::Any;
 use std::borrow::Cow;
 use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 use std::time::Duration;
@@ -19,7 +23,14 @@ use ffmpeg::software::scaling::context::Context as ScalingContext;
 use ffmpeg::util::log as ffmpeglog;
 use ffmpeg::{Dictionary, Packet as CodecPacket, Rational, Rescale};
 use ffmpeg_sys_next::{AV_NOPTS_VALUE, AV_TIME_BASE_Q};
-use image::RgbImage;
+use image::imageops::{self, crop_imm, FilterType};
+use image::{GrayImage, RgbImage};
+
+use crate::timeline::Timeline;
+use crate::utils::colorspace::{self, Matrix};
+use crate::utils::fast_resize;
+use crate::utils::imgutils;
+use crate::utils::workers::{scoped_workers, FinishedWorker};
 
 use self::timestamp::Timestamp;
 
@@ -28,6 +39,238 @@ pub type Result<T> = eyre::Result<T>;
 static FFMPEG_INITIALIZED: OnceLock<std::result::Result<(), ffmpeg::Error>> =
     OnceLock::new();
 
+/// Side of a small square grayscale buffer each frame is downsampled to for scene-change
+/// comparisons in [`FrameExtractor::next_scene`].
+const SCENE_REDUCED_SIZE: u32 = 32;
+
+pub const DEFAULT_SCENE_CHANGE_THRESHOLD: f64 = 0.1;
+
+/// How sensitive [`FrameExtractor::next_scene`] is to cuts: the normalized
+/// mean-absolute-difference between two downsampled, consecutive frames above which a
+/// frame is considered the start of a new scene. Ranges from 0 (identical frames) to 1
+/// (maximally different).
+#[derive(Debug, Clone, Copy)]
+pub struct SceneChangeArgs {
+    threshold: f64,
+}
+
+impl Default for SceneChangeArgs {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_SCENE_CHANGE_THRESHOLD,
+        }
+    }
+}
+
+impl SceneChangeArgs {
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+/// Side length, in pixels, of each tile [`FrameExtractor::next_blockchange`] compares
+/// against the corresponding tile of the previous frame.
+pub const DEFAULT_BLOCK_SIZE: u32 = 16;
+
+/// Per-block mean squared error at or below which a block counts as unchanged.
+pub const DEFAULT_SKIP_THRESHOLD: f64 = 4.0;
+
+/// Per-block mean squared error above which a block counts as having cut to something
+/// new, rather than just moved a little.
+pub const DEFAULT_SCENE_THRESHOLD: f64 = 40.0;
+
+/// Fraction of blocks that have to cross `scene_threshold` before the whole frame is
+/// called a scene cut.
+pub const DEFAULT_SCENE_FRACTION: f64 = 0.2;
+
+/// Tunables for [`FrameExtractor::next_blockchange`]'s cheap duplicate-frame filter:
+/// tiles each frame into `block_size`-square blocks and compares each one's mean squared
+/// error against the same block in the previous frame, so long static shots (a
+/// talking-head or slideshow) don't get hashed 25 times a second just because the codec
+/// re-encodes the same picture with slightly different noise every frame.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockChangeArgs {
+    block_size: u32,
+    skip_threshold: f64,
+    scene_threshold: f64,
+    scene_fraction: f64,
+}
+
+impl Default for BlockChangeArgs {
+    fn default() -> Self {
+        Self {
+            block_size: DEFAULT_BLOCK_SIZE,
+            skip_threshold: DEFAULT_SKIP_THRESHOLD,
+            scene_threshold: DEFAULT_SCENE_THRESHOLD,
+            scene_fraction: DEFAULT_SCENE_FRACTION,
+        }
+    }
+}
+
+impl BlockChangeArgs {
+    /// Side length of the square tiles frames are divided into. The last row/column of
+    /// tiles is clipped to whatever is left over when it doesn't evenly divide the
+    /// frame's dimensions.
+    pub fn block_size(mut self, block_size: u32) -> Self {
+        assert!(block_size >= 1, "a block of 0 pixels can never be compared");
+        self.block_size = block_size;
+        self
+    }
+
+    /// Lower this (a stricter quality knob) to tolerate less per-block noise before a
+    /// frame stops being considered a duplicate.
+    pub fn skip_threshold(mut self, skip_threshold: f64) -> Self {
+        self.skip_threshold = skip_threshold;
+        self
+    }
+
+    /// How much mean squared error one block needs before it counts towards a scene
+    /// cut, see [`Self::scene_fraction`].
+    pub fn scene_threshold(mut self, scene_threshold: f64) -> Self {
+        self.scene_threshold = scene_threshold;
+        self
+    }
+
+    /// What fraction (0..=1) of blocks need to cross `scene_threshold` before the frame
+    /// as a whole is reported as [`FrameChange::SceneCut`].
+    pub fn scene_fraction(mut self, scene_fraction: f64) -> Self {
+        self.scene_fraction = scene_fraction;
+        self
+    }
+}
+
+/// What [`FrameExtractor::next_blockchange`] decided about one frame, relative to
+/// whatever frame it was last asked to compare against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameChange {
+    /// Every block stayed at or below [`BlockChangeArgs::skip_threshold`]: this frame is
+    /// effectively a duplicate of the last one and isn't worth hashing again.
+    Skipped,
+    /// At least one block moved enough to be worth hashing, but not enough blocks
+    /// crossed [`BlockChangeArgs::scene_threshold`] to call it a cut.
+    Changed,
+    /// [`BlockChangeArgs::scene_fraction`] or more of the blocks crossed
+    /// [`BlockChangeArgs::scene_threshold`]: a hard cut, also reported for the very
+    /// first frame since there is nothing yet to compare it against.
+    SceneCut,
+}
+
+/// How [`FrameExtractor`] scales every decoded frame before handing it out, mirroring a
+/// typical thumbnail-generation API. Downstream hashing only needs a small image, so
+/// scaling down up front saves memory and decode time on e.g. 4K sources.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SizingMode {
+    /// Keep the decoder's native resolution.
+    #[default]
+    Native,
+    /// Fit the longest side to `max_dim`, preserving aspect ratio. Both dimensions are
+    /// rounded down to the nearest even number, as swscale requires.
+    Scale(u32),
+    /// Scale to this exact width/height, ignoring aspect ratio. Must already be even.
+    Exact(u32, u32),
+}
+
+impl SizingMode {
+    fn target_dims(self, native_width: u32, native_height: u32) -> (u32, u32) {
+        match self {
+            SizingMode::Native => (native_width, native_height),
+            SizingMode::Exact(width, height) => (width, height),
+            SizingMode::Scale(max_dim) => {
+                let longest = native_width.max(native_height) as u64;
+                let width =
+                    (native_width as u64 * max_dim as u64 / longest).max(2) as u32;
+                let height =
+                    (native_height as u64 * max_dim as u64 / longest).max(2) as u32;
+                (round_down_even(width), round_down_even(height))
+            }
+        }
+    }
+}
+
+fn round_down_even(dim: u32) -> u32 {
+    dim & !1
+}
+
+/// Makes [`FrameExtractor`] silently advance past frames that are blank or
+/// letterboxed, see [`FrameExtractorConf::skip_blank`]. Mirrors the border-removal step
+/// the hashing stage runs before deciding a picture is empty, so a frame isn't judged
+/// differently here than it would be there.
+#[derive(Debug, Clone, Copy)]
+pub struct SkipBlankArgs {
+    /// Gray values at or below this become black in the mask used to find the border.
+    pub maskify_threshold: u8,
+    /// A mask line can contain this many percent of white and still be considered part
+    /// of the border.
+    pub maximum_whites: f64,
+}
+
+impl Default for SkipBlankArgs {
+    fn default() -> Self {
+        Self {
+            maskify_threshold: 40,
+            maximum_whites: 0.1,
+        }
+    }
+}
+
+/// How to interpret the byte values of a decoded luma plane, for
+/// [`FrameExtractor::next_luma`]. Compressed video almost always carries MPEG "limited"
+/// range luma (16..=235) even though the bytes themselves span the full 0..=255, so
+/// thresholds tuned against [`FrameExtractor::next`]'s full-range RGB output would
+/// otherwise see a washed-out picture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LumaRange {
+    /// Stretch 16..=235 out to 0..=255, matching what full-range RGB conversion would
+    /// have produced.
+    #[default]
+    Limited,
+    /// The bytes already span the full 0..=255 range; use them as-is.
+    Full,
+}
+
+impl LumaRange {
+    fn rescale(self, sample: u8) -> u8 {
+        match self {
+            LumaRange::Full => sample,
+            LumaRange::Limited => {
+                (((sample as f64 - 16.0) * (255.0 / 219.0)).round()).clamp(0.0, 255.0) as u8
+            }
+        }
+    }
+}
+
+/// Pins [`FrameExtractor::next`]'s YUV-to-RGB conversion to a specific
+/// [`Matrix`]/[`colorspace::Range`] instead of trusting whatever the decoder
+/// auto-selects from the stream's own flags, see [`FrameExtractorConf::colorspace`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorspaceConf {
+    pub matrix: Matrix,
+    pub range: colorspace::Range,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameExtractorConf {
+    pub sizing: SizingMode,
+
+    /// When set, [`FrameExtractor::next`] (and anything built on it, like
+    /// [`FrameExtractor::next_scene`]) skips past frames that are blank/letterboxed
+    /// according to the same border-removal pipeline the hashing stage uses. `None`
+    /// (the default) returns every decoded frame, as before.
+    pub skip_blank: Option<SkipBlankArgs>,
+
+    /// How [`FrameExtractor::next_luma`] should treat the decoded luma plane's byte
+    /// values. Ignored by [`FrameExtractor::next`], which always goes through full-range
+    /// RGB conversion.
+    pub luma_range: LumaRange,
+
+    /// When set, [`FrameExtractor::next`] converts YUV to RGB itself with this pinned
+    /// matrix/range (for planar formats; see [`chroma_subsampling`]) instead of
+    /// whatever the decoder's own swscale conversion would have chosen. `None` (the
+    /// default) keeps relying on the decoder, as before.
+    pub colorspace: Option<ColorspaceConf>,
+}
+
 pub struct FrameExtractor<'a> {
     // TODO: probably split into several structs
     // ffmpeg contexts
@@ -39,6 +282,23 @@ pub struct FrameExtractor<'a> {
     seek_target_timestamp: i64,
     cur_timestamp: i64,
 
+    // scene-change bookkeeping, see `next_scene`
+    prev_reduced: Option<Vec<u8>>,
+
+    // block-change bookkeeping, see `next_blockchange`
+    prev_blockchange: Option<GrayImage>,
+
+    // blank-frame skipping, see `SkipBlankArgs`
+    skip_blank: Option<SkipBlankArgs>,
+
+    // how to scale frames, remembered so `next_luma` can resize a plane pulled straight
+    // from the decoder the same way `converter` resizes the RGB path
+    sizing: SizingMode,
+    // see `FrameExtractorConf::luma_range`
+    luma_range: LumaRange,
+    // see `FrameExtractorConf::colorspace`
+    colorspace: Option<ColorspaceConf>,
+
     // constants/metadata
     end_timestamp: i64,
     first_timestamp: i64,
@@ -47,10 +307,15 @@ pub struct FrameExtractor<'a> {
 
     // the file name
     path: Cow<'a, Path>,
+
+    // only set up by `from_reader`, where `ictx` is backed by a custom AVIOContext
+    // instead of ffmpeg opening `path` itself; see `CustomAvioGuard` for why this has
+    // to be declared after `ictx`.
+    avio_guard: Option<CustomAvioGuard>,
 }
 
 impl<'a> FrameExtractor<'a> {
-    pub fn new<P: Into<Cow<'a, Path>>>(path: P) -> Result<Self> {
+    pub fn new<P: Into<Cow<'a, Path>>>(path: P, conf: FrameExtractorConf) -> Result<Self> {
         if let Err(e) = FFMPEG_INITIALIZED.get_or_init(|| {
             ffmpeg::init()?;
             ffmpeglog::set_level(ffmpeglog::Level::Warning);
@@ -63,13 +328,13 @@ impl<'a> FrameExtractor<'a> {
         }
 
         let path = path.into();
-        let mut s =
-            Self::new_inner(&path).wrap_err_with(|| format!("on file {:?}", path))?;
+        let mut s = Self::new_inner(&path, conf)
+            .wrap_err_with(|| format!("on file {:?}", path))?;
         s.path = path; // NOTE: ugly workaround to avoid copying the path
         Ok(s)
     }
 
-    fn new_inner(path: &Path) -> Result<Self> {
+    fn new_inner(path: &Path, conf: FrameExtractorConf) -> Result<Self> {
         let options = {
             let mut options = Dictionary::new();
             options.set("analyzeduration", "10M");
@@ -103,7 +368,7 @@ impl<'a> FrameExtractor<'a> {
             .video()
             .wrap_err("No codec found, of type video (?)")?;
 
-        let converter = Self::pixel_converter(&decoder)?;
+        let converter = Self::pixel_converter(&decoder, conf)?;
 
         ictx.streams_mut()
             .filter(|stream| stream.index() != video_stream_index)
@@ -115,34 +380,271 @@ impl<'a> FrameExtractor<'a> {
             video_stream_index,
             converter,
             cur_timestamp,
+            prev_reduced: None,
+            prev_blockchange: None,
+            skip_blank: conf.skip_blank,
+            sizing: conf.sizing,
+            luma_range: conf.luma_range,
+            colorspace: conf.colorspace,
             end_timestamp,
             seek_target_timestamp,
             first_timestamp,
             timebase,
             path: PathBuf::new().into(),
+            avio_guard: None,
+        })
+    }
+
+    /// Like [`FrameExtractor::new`], but decodes from an arbitrary [`Read`] + [`Seek`]
+    /// source instead of a filesystem path, e.g. an in-memory buffer or an entry inside
+    /// an archive. Implemented with a custom AVIO context (`avio_alloc_context`)
+    /// wrapping `reader`'s `read`/`seek` calls, since ffmpeg can only open files or
+    /// network URLs on its own. Every seeking method on the result keeps working as
+    /// long as `reader` itself is seekable.
+    pub fn from_reader<R: Read + Seek + Send + 'static>(
+        reader: R,
+        conf: FrameExtractorConf,
+    ) -> Result<Self> {
+        if let Err(e) = FFMPEG_INITIALIZED.get_or_init(|| {
+            ffmpeg::init()?;
+            ffmpeglog::set_level(ffmpeglog::Level::Warning);
+            unsafe {
+                ffmpeg_sys_next::av_log_set_callback(Some(ffmpeg_log_adaptor));
+            }
+            Ok(())
+        }) {
+            return Err(e).wrap_err("Failed to initialize ffmpeg");
+        }
+
+        Self::from_reader_inner(reader, conf).wrap_err("on a custom reader")
+    }
+
+    fn from_reader_inner<R: Read + Seek + Send + 'static>(
+        reader: R,
+        conf: FrameExtractorConf,
+    ) -> Result<Self> {
+        let reader: *mut (dyn Any + Send) = Box::into_raw(Box::new(reader));
+
+        let buffer = unsafe { ffmpeg_sys_next::av_malloc(AVIO_BUFFER_SIZE) };
+        if buffer.is_null() {
+            unsafe { drop(Box::from_raw(reader)) };
+            eyre::bail!("Failed to allocate an AVIO buffer");
+        }
+
+        let avio_ctx = unsafe {
+            ffmpeg_sys_next::avio_alloc_context(
+                buffer as *mut u8,
+                AVIO_BUFFER_SIZE as libc::c_int,
+                0, // write_flag: this is a read-only reader
+                reader as *mut libc::c_void,
+                Some(read_packet::<R>),
+                None,
+                Some(seek_callback::<R>),
+            )
+        };
+        if avio_ctx.is_null() {
+            unsafe {
+                ffmpeg_sys_next::av_free(buffer as *mut libc::c_void);
+                drop(Box::from_raw(reader));
+            }
+            eyre::bail!("Failed to allocate an AVIO context");
+        }
+
+        // From here on, dropping `guard` frees the AVIO buffer/context and the boxed
+        // reader, so every early return below is safe to just bail out of.
+        let guard = CustomAvioGuard { avio_ctx, reader };
+
+        let mut fmt_ctx = unsafe { ffmpeg_sys_next::avformat_alloc_context() };
+        if fmt_ctx.is_null() {
+            eyre::bail!("Failed to allocate a format context");
+        }
+        unsafe {
+            (*fmt_ctx).pb = avio_ctx;
+            (*fmt_ctx).flags |= ffmpeg_sys_next::AVFMT_FLAG_CUSTOM_IO as libc::c_int;
+        }
+
+        let open_result = unsafe {
+            ffmpeg_sys_next::avformat_open_input(
+                &mut fmt_ctx,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if open_result < 0 {
+            // ffmpeg frees `fmt_ctx` itself on a failed open.
+            return Err(ffmpeg::Error::from(open_result)).wrap_err("Failed to open the reader");
+        }
+
+        let find_result =
+            unsafe { ffmpeg_sys_next::avformat_find_stream_info(fmt_ctx, std::ptr::null_mut()) };
+        if find_result < 0 {
+            unsafe { ffmpeg_sys_next::avformat_close_input(&mut fmt_ctx) };
+            return Err(ffmpeg::Error::from(find_result))
+                .wrap_err("Failed to find stream info");
+        }
+
+        // SAFETY: ffmpeg-next doesn't expose any way, public or otherwise, to build a
+        // `format::context::Input` from a context that was opened through a custom
+        // AVIOContext rather than one of its own `input*` functions. It is, however,
+        // a thin wrapper around exactly this `*mut AVFormatContext` plus a destructor
+        // tag, so this reaches around the safe API the same way `seek()` below reaches
+        // around the lack of a per-stream seek in it.
+        let mut ictx: FormatContext = unsafe { std::mem::transmute(fmt_ctx) };
+
+        let video = ictx
+            .streams()
+            .best(Type::Video)
+            .ok_or(eyre::eyre!("No video stream"))?;
+
+        let video_stream_index = video.index();
+        assert_ne!(AV_NOPTS_VALUE, video.start_time());
+        let cur_timestamp = video.start_time();
+        let seek_target_timestamp = video.start_time();
+        let first_timestamp = video.start_time();
+        let timebase = video.time_base();
+        let end_timestamp = if video.duration() == AV_NOPTS_VALUE {
+            assert_ne!(AV_NOPTS_VALUE, ictx.duration());
+            ictx.duration().rescale(AV_TIME_BASE_Q, timebase)
+        } else {
+            video.duration()
+        };
+
+        let decoder = CodecContext::from_parameters(video.parameters())
+            .wrap_err("No codec found")?
+            .decoder()
+            .video()
+            .wrap_err("No codec found, of type video (?)")?;
+
+        let converter = Self::pixel_converter(&decoder, conf)?;
+
+        ictx.streams_mut()
+            .filter(|stream| stream.index() != video_stream_index)
+            .for_each(|mut stream| stream_set_discard_all(&mut stream));
+
+        Ok(Self {
+            ictx,
+            decoder,
+            video_stream_index,
+            converter,
+            cur_timestamp,
+            prev_reduced: None,
+            prev_blockchange: None,
+            skip_blank: conf.skip_blank,
+            sizing: conf.sizing,
+            luma_range: conf.luma_range,
+            colorspace: conf.colorspace,
+            end_timestamp,
+            seek_target_timestamp,
+            first_timestamp,
+            timebase,
+            path: PathBuf::from("<reader>").into(),
+            avio_guard: Some(guard),
         })
     }
 
-    fn pixel_converter(decoder: &DecoderVideo) -> Result<ScalingContext> {
+    fn pixel_converter(decoder: &DecoderVideo, conf: FrameExtractorConf) -> Result<ScalingContext> {
         assert_ne!(Pixel::None, decoder.format());
+        let (target_width, target_height) =
+            conf.sizing.target_dims(decoder.width(), decoder.height());
         Ok(ScalingContext::get(
             decoder.format(),
             decoder.width(),
             decoder.height(),
             // http://git.videolan.org/?p=ffmpeg.git;a=blob;f=libavutil/pixfmt.h;hb=HEAD
             Pixel::RGB24,
-            decoder.width(),
-            decoder.height(),
+            target_width,
+            target_height,
             ffmpeg::software::scaling::Flags::FAST_BILINEAR,
         )?)
     }
 
     pub fn next(&mut self) -> Result<Option<(Timestamp, RgbImage)>> {
-        self.next_inner()
+        self.decode_next_frame()
+            .wrap_err_with(|| format!("on file {:?}", self.path))
+    }
+
+    /// Like [`FrameExtractor::next`], but skips forward past near-identical frames and
+    /// only returns frames that start a new visual scene: always the very first decoded
+    /// frame, and afterwards any frame whose normalized mean-absolute-difference from
+    /// the previous one (computed on a small downsampled grayscale copy of each, see
+    /// [`SCENE_REDUCED_SIZE`]) exceeds `args.threshold`. Far more useful for dedup than
+    /// uniform time sampling, since near-identical frames are skipped for free and short
+    /// cuts are not missed the way fixed `seek_forward` steps would miss them.
+    pub fn next_scene(
+        &mut self,
+        args: &SceneChangeArgs,
+    ) -> Result<Option<(Timestamp, RgbImage)>> {
+        self.next_scene_inner(args)
+            .wrap_err_with(|| format!("on file {:?}", self.path))
+    }
+
+    fn next_scene_inner(
+        &mut self,
+        args: &SceneChangeArgs,
+    ) -> Result<Option<(Timestamp, RgbImage)>> {
+        loop {
+            let Some((ts, img)) = self.decode_next_frame()? else {
+                return Ok(None);
+            };
+
+            let reduced = reduce_frame(&img);
+            let is_scene_change = match &self.prev_reduced {
+                None => true,
+                Some(prev) => {
+                    // A flat/solid reduced frame (e.g. a black frame) has no variance
+                    // to meaningfully compare against, so it's never treated as a cut
+                    // on its own, even if the raw difference would exceed the
+                    // threshold.
+                    buffer_variance(&reduced) != 0.0
+                        && normalized_mean_abs_diff(prev, &reduced) > args.threshold
+                }
+            };
+
+            self.prev_reduced = Some(reduced);
+
+            if is_scene_change {
+                return Ok(Some((ts, img)));
+            }
+        }
+    }
+
+    /// Like [`FrameExtractor::next_luma`], but instead of filtering frames, tags every
+    /// one with a [`FrameChange`] describing how it compares to the previous frame:
+    /// tiles both into `args.block_size`-square blocks and compares each one's mean
+    /// squared error against its counterpart in the last frame handed back by this
+    /// method. Unlike [`FrameExtractor::next_scene`], no frame is ever skipped here --
+    /// it's up to the caller to act on [`FrameChange::Skipped`], e.g. to widen a span
+    /// of frames considered duplicates instead of discarding individual timestamps.
+    /// The very first frame after construction or a seek is always reported as
+    /// [`FrameChange::SceneCut`], since there is nothing yet to compare it against.
+    pub fn next_blockchange(
+        &mut self,
+        args: &BlockChangeArgs,
+    ) -> Result<Option<(Timestamp, GrayImage, FrameChange)>> {
+        self.next_blockchange_inner(args)
             .wrap_err_with(|| format!("on file {:?}", self.path))
     }
 
-    fn next_inner(&mut self) -> Result<Option<(Timestamp, RgbImage)>> {
+    fn next_blockchange_inner(
+        &mut self,
+        args: &BlockChangeArgs,
+    ) -> Result<Option<(Timestamp, GrayImage, FrameChange)>> {
+        let Some((ts, img)) = self.decode_next_luma()? else {
+            return Ok(None);
+        };
+
+        let change = match &self.prev_blockchange {
+            None => FrameChange::SceneCut,
+            Some(prev) => block_change(prev, &img, args),
+        };
+
+        self.prev_blockchange = Some(img.clone());
+
+        Ok(Some((ts, img, change)))
+    }
+
+    fn decode_next_frame(&mut self) -> Result<Option<(Timestamp, RgbImage)>> {
         let Self {
             ictx,
             decoder,
@@ -152,6 +654,9 @@ impl<'a> FrameExtractor<'a> {
             seek_target_timestamp,
             timebase,
             first_timestamp,
+            skip_blank,
+            sizing,
+            colorspace,
             ..
         } = self;
 
@@ -182,40 +687,149 @@ impl<'a> FrameExtractor<'a> {
                     continue;
                 }
 
-                let mut converted = FrameVideo::empty();
-                converter
-                    .run(&frame, &mut converted)
-                    .wrap_err("Failed to convert the decoded frame")?;
-                let img = create_rust_image(converted);
+                let planar = colorspace
+                    .and_then(|cs| chroma_subsampling(frame.format()).map(|sub| (cs, sub)));
+                let img = match planar {
+                    Some((ColorspaceConf { matrix, range }, subsampling)) => {
+                        let rgb = extract_planar_rgb(&frame, subsampling, matrix, range);
+                        let (target_width, target_height) =
+                            sizing.target_dims(rgb.width(), rgb.height());
+                        fast_resize::resize(&rgb, target_width, target_height)
+                    }
+                    None => {
+                        let mut converted = FrameVideo::empty();
+                        converter
+                            .run(&frame, &mut converted)
+                            .wrap_err("Failed to convert the decoded frame")?;
+                        create_rust_image(converted)
+                    }
+                };
+
+                if let Some(skip) = skip_blank {
+                    if is_blank(&img, skip) {
+                        continue;
+                    }
+                }
 
                 let dur = Timestamp::new(*cur_timestamp, *timebase, *first_timestamp);
                 return Ok(Some((dur, img)));
             }
 
+            Self::feed_packet(ictx, decoder, *video_stream_index)?;
+        }
+    }
+
+    /// Like [`FrameExtractor::next`], but hands back just the luma plane as a
+    /// [`GrayImage`] instead of full RGB. When the decoder's native pixel format
+    /// already has a plain luma plane (the common case for video), this reads it
+    /// straight off the decoded frame -- skipping the YUV-to-RGB conversion and the
+    /// subsequent `grayscale()` averaging entirely -- and only resizes that plane to
+    /// `conf.sizing`'s target dimensions. Formats without a plain luma plane fall back
+    /// to the same RGB conversion [`FrameExtractor::next`] uses, followed by
+    /// `grayscale()`.
+    pub fn next_luma(&mut self) -> Result<Option<(Timestamp, GrayImage)>> {
+        self.decode_next_luma()
+            .wrap_err_with(|| format!("on file {:?}", self.path))
+    }
+
+    fn decode_next_luma(&mut self) -> Result<Option<(Timestamp, GrayImage)>> {
+        let Self {
+            ictx,
+            decoder,
+            video_stream_index,
+            converter,
+            cur_timestamp,
+            seek_target_timestamp,
+            timebase,
+            first_timestamp,
+            skip_blank,
+            sizing,
+            luma_range,
+            ..
+        } = self;
+
+        loop {
             loop {
-                // http://ffmpeg.org/doxygen/trunk/group__lavf__decoding.html#ga4fdb3084415a82e3810de6ee60e46a61
-                let mut packet = CodecPacket::empty();
-                match packet.read(ictx) {
-                    Ok(()) if packet.stream() == *video_stream_index => {
-                        match decoder.send_packet(&packet) {
-                            Ok(()) => break,
-                            Err(e) => {
-                                log::error!("Failed to decode frame: {e}");
-                                continue;
-                            }
-                        }
+                let mut frame = FrameVideo::empty();
+                match decoder.receive_frame(&mut frame) {
+                    Ok(()) => (),
+                    Err(ffmpeg::Error::Other {
+                        errno: libc::EAGAIN,
+                    }) => break,
+                    Err(ffmpeg::Error::Eof) => return Ok(None),
+                    Err(e) => {
+                        return Err(e)
+                            .wrap_err("Decoder error when receiving a frame from it");
                     }
-                    Ok(()) => continue,
-                    Err(ffmpeg::Error::Eof) => {
-                        decoder
-                            .send_eof()
-                            .wrap_err("Failed to send EOF to the decoder")?;
-                        break;
+                }
+
+                *cur_timestamp = frame
+                    .timestamp()
+                    .expect("this is always set by the decoder?");
+
+                if *cur_timestamp < *seek_target_timestamp {
+                    continue;
+                }
+
+                let gray = if has_plain_luma_plane(frame.format()) {
+                    let native = extract_luma_plane(&frame, *luma_range);
+                    let (target_width, target_height) =
+                        sizing.target_dims(native.width(), native.height());
+                    fast_resize::resize(&native, target_width, target_height)
+                } else {
+                    let mut converted = FrameVideo::empty();
+                    converter
+                        .run(&frame, &mut converted)
+                        .wrap_err("Failed to convert the decoded frame")?;
+                    imageops::grayscale(&create_rust_image(converted))
+                };
+
+                if let Some(skip) = skip_blank {
+                    if is_blank_gray(&gray, skip) {
+                        continue;
                     }
-                    Err(e) => {
-                        eyre::bail!("Failed to read a packet from the stream");
+                }
+
+                let dur = Timestamp::new(*cur_timestamp, *timebase, *first_timestamp);
+                return Ok(Some((dur, gray)));
+            }
+
+            Self::feed_packet(ictx, decoder, *video_stream_index)?;
+        }
+    }
+
+    /// Reads and decodes packets from `ictx` until one is successfully handed to
+    /// `decoder`, or the stream is exhausted (in which case `decoder` is sent EOF so
+    /// the next `receive_frame` call drains whatever it has buffered). Shared by
+    /// [`FrameExtractor::decode_next_frame`] and [`FrameExtractor::decode_next_luma`].
+    fn feed_packet(
+        ictx: &mut FormatContext,
+        decoder: &mut DecoderVideo,
+        video_stream_index: usize,
+    ) -> Result<()> {
+        loop {
+            // http://ffmpeg.org/doxygen/trunk/group__lavf__decoding.html#ga4fdb3084415a82e3810de6ee60e46a61
+            let mut packet = CodecPacket::empty();
+            match packet.read(ictx) {
+                Ok(()) if packet.stream() == video_stream_index => {
+                    match decoder.send_packet(&packet) {
+                        Ok(()) => return Ok(()),
+                        Err(e) => {
+                            log::error!("Failed to decode frame: {e}");
+                            continue;
+                        }
                     }
                 }
+                Ok(()) => continue,
+                Err(ffmpeg::Error::Eof) => {
+                    decoder
+                        .send_eof()
+                        .wrap_err("Failed to send EOF to the decoder")?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    eyre::bail!("Failed to read a packet from the stream");
+                }
             }
         }
     }
@@ -274,6 +888,8 @@ impl<'a> FrameExtractor<'a> {
             .wrap_err_with(|| format!("Failed to seek on file {:?}", self.path))?;
         decoder.flush();
         *seek_target_timestamp = target;
+        self.prev_reduced = None;
+        self.prev_blockchange = None;
         Ok(())
     }
 
@@ -289,6 +905,93 @@ impl<'a> FrameExtractor<'a> {
     }
 }
 
+/// How much [`extract_parallel`] pads every worker's window on both sides before
+/// seeking and cutting frames off, see that function's docs for why.
+pub const DEFAULT_WINDOW_OVERLAP: Duration = Duration::from_secs(2);
+
+/// Extracts every frame of the file at `path`, the same frames repeatedly calling
+/// [`FrameExtractor::next`] would yield, but decoded by
+/// `std::thread::available_parallelism()` workers running concurrently via
+/// [`scoped_workers`]. Each worker opens its own `FrameExtractor` on `path` (ffmpeg
+/// decoders aren't shareable across threads), seeks to its own contiguous `[start,
+/// end)` slice of [`FrameExtractor::approx_length`], and decodes until its timestamp
+/// crosses `end`. The per-worker results are then merged back into one `Vec`, ordered
+/// by timestamp.
+///
+/// `seek_to`/`seek_forward` can only land on the nearest preceding keyframe, so pick
+/// window boundaries on GOP-friendly positions where possible (e.g. near a keyframe
+/// interval) rather than assuming an exact cut. Regardless, every window is padded by
+/// `window_overlap` on both sides before seeking and before cutting frames off, so that
+/// the keyframe a worker actually seeks to, and the handful of frames right around
+/// every window boundary, aren't silently dropped.
+pub fn extract_parallel(
+    path: &Path,
+    conf: FrameExtractorConf,
+    window_overlap: Duration,
+) -> Result<Vec<(Timestamp, RgbImage)>> {
+    let num_workers = std::thread::available_parallelism()
+        .map(std::num::NonZeroUsize::get)
+        .unwrap_or(1);
+
+    let total_len = FrameExtractor::new(path, conf)
+        .wrap_err("failed to probe the file's length")?
+        .approx_length();
+
+    let window_len = total_len / num_workers as u32;
+
+    let finished = scoped_workers(|s| {
+        for i in 0..num_workers {
+            let start = window_len * i as u32;
+            let end = if i + 1 == num_workers {
+                total_len
+            } else {
+                window_len * (i as u32 + 1)
+            };
+
+            s.spawn(format!("extract-{i}"), move || {
+                extract_window(path, conf, start..end, window_overlap)
+            });
+        }
+    });
+
+    let mut frames = Vec::new();
+    for FinishedWorker { name, result } in finished {
+        let window_frames = match result {
+            Ok(r) => r.wrap_err_with(|| format!("worker '{name}' failed"))?,
+            Err(panic) => eyre::bail!("worker '{name}' panicked: {panic}"),
+        };
+        frames.extend(window_frames);
+    }
+
+    frames.sort_by_key(|(ts, _)| ts.timestamp);
+    // Neighbouring windows overlap by `window_overlap`, so frames near a boundary were
+    // decoded by both workers; keep one copy of each.
+    frames.dedup_by_key(|(ts, _)| ts.timestamp);
+    Ok(frames)
+}
+
+fn extract_window(
+    path: &Path,
+    conf: FrameExtractorConf,
+    window: Range<Duration>,
+    overlap: Duration,
+) -> Result<Vec<(Timestamp, RgbImage)>> {
+    let mut extractor = FrameExtractor::new(path, conf)?;
+    extractor.seek_forward(window.start.saturating_sub(overlap))?;
+
+    let cutoff =
+        extractor.first_timestamp + duration2timestamp(window.end + overlap, extractor.timebase);
+
+    let mut frames = Vec::new();
+    while let Some((ts, img)) = extractor.next()? {
+        if ts.timestamp > cutoff {
+            break;
+        }
+        frames.push((ts, img));
+    }
+    Ok(frames)
+}
+
 pub struct FrameExtractorIter<'a, 'p> {
     extractor: &'a mut FrameExtractor<'p>,
 }
@@ -307,6 +1010,109 @@ impl<'a, 'p> FrameExtractor<'p> {
     }
 }
 
+pub struct FrameExtractorSceneIter<'a, 'p> {
+    extractor: &'a mut FrameExtractor<'p>,
+    args: SceneChangeArgs,
+}
+
+impl Iterator for FrameExtractorSceneIter<'_, '_> {
+    type Item = Result<(Timestamp, RgbImage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.extractor.next_scene(&self.args).transpose()
+    }
+}
+
+impl<'a, 'p> FrameExtractor<'p> {
+    pub fn iter_scenes(&'a mut self, args: SceneChangeArgs) -> FrameExtractorSceneIter<'a, 'p> {
+        FrameExtractorSceneIter {
+            extractor: self,
+            args,
+        }
+    }
+}
+
+pub struct FrameExtractorBlockChangeIter<'a, 'p> {
+    extractor: &'a mut FrameExtractor<'p>,
+    args: BlockChangeArgs,
+}
+
+impl Iterator for FrameExtractorBlockChangeIter<'_, '_> {
+    type Item = Result<(Timestamp, GrayImage, FrameChange)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.extractor.next_blockchange(&self.args).transpose()
+    }
+}
+
+impl<'a, 'p> FrameExtractor<'p> {
+    pub fn iter_blockchanges(
+        &'a mut self,
+        args: BlockChangeArgs,
+    ) -> FrameExtractorBlockChangeIter<'a, 'p> {
+        FrameExtractorBlockChangeIter {
+            extractor: self,
+            args,
+        }
+    }
+}
+
+/// Iterator returned by [`FrameExtractor::iter_timed`].
+pub struct FrameExtractorTimedIter<'a, 'p> {
+    extractor: &'a mut FrameExtractor<'p>,
+    timeline: Timeline,
+    video_len: Duration,
+    position: Duration,
+    started: bool,
+}
+
+impl Iterator for FrameExtractorTimedIter<'_, '_> {
+    type Item = Result<(Timestamp, RgbImage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+        } else {
+            let fraction = if self.video_len.is_zero() {
+                1.0
+            } else {
+                self.position.as_secs_f64() / self.video_len.as_secs_f64()
+            };
+
+            let step = self.timeline.interval_at(fraction);
+            if step.is_zero() {
+                // A zero interval would otherwise keep re-decoding the same frame.
+                return None;
+            }
+
+            if let Err(e) = self.extractor.seek_forward(step) {
+                return Some(Err(e));
+            }
+            self.position += step;
+        }
+
+        self.extractor.next().transpose()
+    }
+}
+
+impl<'a, 'p> FrameExtractor<'p> {
+    /// Like [`Self::iter`], but instead of yielding every decoded frame, seeks forward
+    /// between frames by whatever interval `timeline` reports for how far into the
+    /// video extraction has progressed so far, instead of a fixed step. Lets callers
+    /// sample a video more densely early on and more sparsely later on (or vice versa),
+    /// see [`Timeline`].
+    pub fn iter_timed(&'a mut self, timeline: Timeline) -> FrameExtractorTimedIter<'a, 'p> {
+        let video_len = self.approx_length();
+        FrameExtractorTimedIter {
+            extractor: self,
+            timeline,
+            video_len,
+            position: Duration::ZERO,
+            started: false,
+        }
+    }
+}
+
 fn create_rust_image(converted: FrameVideo) -> RgbImage {
     assert_eq!(Pixel::RGB24, converted.format());
     assert_eq!(1, converted.planes());
@@ -339,6 +1145,190 @@ fn create_rust_image(converted: FrameVideo) -> RgbImage {
     .expect("the buffer is big enough!")
 }
 
+/// Whether `format`'s plane 0 is a full-resolution luma plane, so
+/// [`extract_luma_plane`] can read it directly instead of going through
+/// `converter`'s YUV-to-RGB conversion. Covers ffmpeg's planar/semi-planar YUV formats;
+/// anything else (packed RGB, palette, ...) falls back to the RGB path in
+/// [`FrameExtractor::decode_next_luma`].
+fn has_plain_luma_plane(format: Pixel) -> bool {
+    matches!(
+        format,
+        Pixel::YUV420P
+            | Pixel::YUVJ420P
+            | Pixel::YUV422P
+            | Pixel::YUVJ422P
+            | Pixel::YUV444P
+            | Pixel::YUVJ444P
+            | Pixel::NV12
+            | Pixel::NV21
+            | Pixel::GRAY8
+    )
+}
+
+/// Reads plane 0 of `frame` straight into a [`GrayImage`], cropping away any row
+/// padding the same way [`create_rust_image`] does, and rescaling samples per `range`.
+/// Only valid to call when [`has_plain_luma_plane`] says `frame`'s format has one.
+fn extract_luma_plane(frame: &FrameVideo, range: LumaRange) -> GrayImage {
+    let width: usize = frame.width().try_into().expect("will always fit");
+    let height: usize = frame.height().try_into().expect("will always fit");
+    let src_linesize = frame.stride(0);
+    let data = frame.data(0);
+
+    let mut out = vec![0u8; width * height];
+    for y in 0..height {
+        let src_row = &data[(y * src_linesize)..(y * src_linesize + width)];
+        let dst_row = &mut out[(y * width)..((y + 1) * width)];
+        for (dst, &src) in dst_row.iter_mut().zip(src_row) {
+            *dst = range.rescale(src);
+        }
+    }
+
+    GrayImage::from_vec(
+        width.try_into().expect("was an u32 before"),
+        height.try_into().expect("was an u32 before"),
+        out,
+    )
+    .expect("the buffer is big enough!")
+}
+
+/// How much smaller `format`'s chroma planes are than its luma plane, for
+/// [`extract_planar_rgb`]. `None` for anything not covered by [`ColorspaceConf`]'s
+/// supported planar formats; such frames fall back to `converter`'s own conversion in
+/// [`FrameExtractor::decode_next_frame`].
+fn chroma_subsampling(format: Pixel) -> Option<colorspace::ChromaSubsampling> {
+    match format {
+        Pixel::YUV420P | Pixel::YUVJ420P => Some((2, 2)),
+        Pixel::YUV422P | Pixel::YUVJ422P => Some((2, 1)),
+        Pixel::YUV444P | Pixel::YUVJ444P => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// Builds an RGB image straight from `frame`'s Y/U/V planes via
+/// [`colorspace::planar_to_rgb`], pinning the conversion to `matrix`/`range` instead of
+/// trusting `converter`'s own swscale-chosen one. Only valid to call when
+/// [`chroma_subsampling`] returns `Some` for `frame`'s format.
+fn extract_planar_rgb(
+    frame: &FrameVideo,
+    subsampling: colorspace::ChromaSubsampling,
+    matrix: Matrix,
+    range: colorspace::Range,
+) -> RgbImage {
+    colorspace::planar_to_rgb(
+        frame.data(0),
+        frame.stride(0),
+        frame.data(1),
+        frame.data(2),
+        frame.stride(1),
+        frame.width(),
+        frame.height(),
+        subsampling,
+        matrix,
+        range,
+    )
+}
+
+/// Downsamples a decoded frame to a small, fixed-size grayscale buffer for cheap
+/// scene-change comparisons, see [`FrameExtractor::next_scene`].
+fn reduce_frame(img: &RgbImage) -> Vec<u8> {
+    let gray = imageops::grayscale(img);
+    let reduced = imageops::resize(
+        &gray,
+        SCENE_REDUCED_SIZE,
+        SCENE_REDUCED_SIZE,
+        FilterType::Triangle,
+    );
+    reduced.into_raw()
+}
+
+/// Average absolute difference between two equally-sized buffers, normalized to 0..=1
+/// by the maximum possible per-pixel difference.
+fn normalized_mean_abs_diff(a: &[u8], b: &[u8]) -> f64 {
+    assert_eq!(a.len(), b.len());
+    let sum: i64 = a
+        .iter()
+        .zip(b)
+        .map(|(&x, &y)| (x as i64 - y as i64).abs())
+        .sum();
+    (sum as f64 / a.len() as f64) / u8::MAX as f64
+}
+
+/// Tiles `prev`/`cur` into `args.block_size`-square blocks and classifies the pair per
+/// [`FrameChange`], see [`FrameExtractor::next_blockchange`]. Both images must share the
+/// same dimensions.
+fn block_change(prev: &GrayImage, cur: &GrayImage, args: &BlockChangeArgs) -> FrameChange {
+    assert_eq!(prev.dimensions(), cur.dimensions(), "frame dimensions changed mid-stream");
+    let (width, height) = cur.dimensions();
+
+    let mut num_blocks = 0usize;
+    let mut num_changed = 0usize;
+    let mut num_scene = 0usize;
+
+    for y in (0..height).step_by(args.block_size as usize) {
+        let bh = args.block_size.min(height - y);
+        for x in (0..width).step_by(args.block_size as usize) {
+            let bw = args.block_size.min(width - x);
+            let mse = block_mse(prev, cur, x, y, bw, bh);
+
+            num_blocks += 1;
+            if mse > args.skip_threshold {
+                num_changed += 1;
+            }
+            if mse > args.scene_threshold {
+                num_scene += 1;
+            }
+        }
+    }
+
+    if num_changed == 0 {
+        FrameChange::Skipped
+    } else if num_scene as f64 >= args.scene_fraction * num_blocks as f64 {
+        FrameChange::SceneCut
+    } else {
+        FrameChange::Changed
+    }
+}
+
+/// Mean squared error between the `width`x`height` block starting at `(x, y)` in `prev`
+/// and the same block in `cur`.
+fn block_mse(prev: &GrayImage, cur: &GrayImage, x: u32, y: u32, width: u32, height: u32) -> f64 {
+    let mut sum_sq = 0f64;
+    for dy in 0..height {
+        for dx in 0..width {
+            let p = prev.get_pixel(x + dx, y + dy)[0] as f64;
+            let c = cur.get_pixel(x + dx, y + dy)[0] as f64;
+            let diff = p - c;
+            sum_sq += diff * diff;
+        }
+    }
+    sum_sq / (width * height) as f64
+}
+
+/// 0 for a perfectly flat (solid-color) buffer.
+fn buffer_variance(buf: &[u8]) -> f64 {
+    let mean = buf.iter().map(|&x| x as f64).sum::<f64>() / buf.len() as f64;
+    buf.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / buf.len() as f64
+}
+
+/// Whether `img` is blank/letterboxed: nothing is left of it once its border is
+/// cropped away per `args`.
+fn is_blank(img: &RgbImage, args: &SkipBlankArgs) -> bool {
+    let gray = imgutils::grayscale(img);
+    let mask = imgutils::maskify(gray, args.maskify_threshold);
+    let bbox = imgutils::watermark_getbbox(&mask, args.maximum_whites);
+    let cropped = crop_imm(img, bbox.x, bbox.y, bbox.width, bbox.height);
+    imgutils::is_subimg_empty(&cropped)
+}
+
+/// Like [`is_blank`], but for the [`GrayImage`]s [`FrameExtractor::decode_next_luma`]
+/// produces instead of RGB.
+fn is_blank_gray(img: &GrayImage, args: &SkipBlankArgs) -> bool {
+    let mask = imgutils::maskify(img.clone(), args.maskify_threshold);
+    let bbox = imgutils::watermark_getbbox(&mask, args.maximum_whites);
+    let cropped = crop_imm(img, bbox.x, bbox.y, bbox.width, bbox.height);
+    imgutils::is_subimg_empty(&cropped)
+}
+
 fn duration2timestamp(dur: Duration, timebase: Rational) -> i64 {
     let step: i64 = dur
         .as_millis()
@@ -356,6 +1346,80 @@ fn timestamp2duration(timestamp: i64, timebase: Rational) -> Duration {
     Duration::from_millis(millis.try_into().expect("probably not a problem"))
 }
 
+/// Size of the read buffer handed to the custom AVIO context allocated by
+/// [`FrameExtractor::from_reader`].
+const AVIO_BUFFER_SIZE: usize = 4096;
+
+/// Owns the pieces of a [`FrameExtractor::from_reader`] input that ffmpeg itself
+/// doesn't know how to free: the AVIOContext, its read buffer, and the boxed reader
+/// behind its `opaque` pointer. Declared as the last field of [`FrameExtractor`], after
+/// `ictx`, so that Rust only drops it once `ictx`'s own `Drop` (which closes the
+/// AVFormatContext) has already run; custom IO's AVIOContext has to outlive the input
+/// it backs, and freeing it is the caller's job, not ffmpeg's.
+struct CustomAvioGuard {
+    avio_ctx: *mut ffmpeg_sys_next::AVIOContext,
+    reader: *mut (dyn Any + Send),
+}
+
+impl Drop for CustomAvioGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let buffer = (*self.avio_ctx).buffer;
+            ffmpeg_sys_next::av_free(buffer as *mut libc::c_void);
+            ffmpeg_sys_next::av_free(self.avio_ctx as *mut libc::c_void);
+            drop(Box::from_raw(self.reader));
+        }
+    }
+}
+
+/// The `read_packet` callback for the AVIOContext set up by
+/// [`FrameExtractor::from_reader`]. Copies up to `buf_size` bytes from the reader
+/// behind `opaque` and returns the number of bytes copied, or `AVERROR_EOF` once the
+/// reader is exhausted.
+unsafe extern "C" fn read_packet<R: Read>(
+    opaque: *mut libc::c_void,
+    buf: *mut u8,
+    buf_size: libc::c_int,
+) -> libc::c_int {
+    let reader = &mut *(opaque as *mut R);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size as usize);
+    match reader.read(buf) {
+        Ok(0) => ffmpeg_sys_next::AVERROR_EOF,
+        Ok(n) => n as libc::c_int,
+        Err(_) => -(libc::EIO),
+    }
+}
+
+/// The `seek` callback for the AVIOContext set up by [`FrameExtractor::from_reader`].
+/// Honors `AVSEEK_SIZE` (ffmpeg's way of asking for the stream's total size without
+/// moving the read position) in addition to the usual `SEEK_SET`/`SEEK_CUR`/`SEEK_END`.
+unsafe extern "C" fn seek_callback<R: Seek>(
+    opaque: *mut libc::c_void,
+    offset: i64,
+    whence: libc::c_int,
+) -> i64 {
+    let reader = &mut *(opaque as *mut R);
+
+    if whence & ffmpeg_sys_next::AVSEEK_SIZE as libc::c_int != 0 {
+        let size = (|| -> std::io::Result<u64> {
+            let cur = reader.stream_position()?;
+            let end = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(cur))?;
+            Ok(end)
+        })();
+        return size.map(|s| s as i64).unwrap_or(-1);
+    }
+
+    let pos = match whence {
+        libc::SEEK_SET => SeekFrom::Start(offset as u64),
+        libc::SEEK_CUR => SeekFrom::Current(offset),
+        libc::SEEK_END => SeekFrom::End(offset),
+        _ => return -1,
+    };
+
+    reader.seek(pos).map(|p| p as i64).unwrap_or(-1)
+}
+
 fn stream_set_discard_all(stream: &mut ffmpeg::StreamMut<'_>) {
     unsafe {
         let ptr = stream.as_mut_ptr();
@@ -0,0 +1,233 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use color_eyre::eyre::{self, Context};
+use image::RgbImage;
+
+use super::timestamp::Timestamp;
+use crate::imghash::hamming::Hamming;
+
+/// How a [`DigestState`] should behave for a single extraction run, driven by a CLI flag
+/// in whichever binary creates it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum DigestMode {
+    /// Write one line per observed frame to the sidecar file, overwriting whatever was
+    /// there before. Used to (re)generate a fixture.
+    Record,
+    /// Read the sidecar file up front and assert each observed frame matches it, in
+    /// order. Used to check a run against a previously recorded fixture.
+    Verify,
+    /// Do nothing. The default, so callers that don't care about this don't pay for it.
+    #[default]
+    Ignore,
+}
+
+/// One previously recorded frame: a timestamp, its perceptual hash, and a content digest
+/// of the decoded pixels, in the same order they were written.
+struct Recorded {
+    timestamp_ms: i64,
+    hash_hex: String,
+    content_hex: String,
+}
+
+impl Recorded {
+    fn parse(path: &Path, line_no: usize, line: &str) -> eyre::Result<Self> {
+        let mut parts = line.split_whitespace();
+        let (Some(timestamp_ms), Some(hash_hex), Some(content_hex)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(malformed(path, line_no, line));
+        };
+        let Ok(timestamp_ms) = timestamp_ms.parse() else {
+            return Err(malformed(path, line_no, line));
+        };
+        Ok(Self {
+            timestamp_ms,
+            hash_hex: hash_hex.to_string(),
+            content_hex: content_hex.to_string(),
+        })
+    }
+}
+
+fn malformed(path: &Path, line_no: usize, line: &str) -> eyre::Report {
+    eyre::eyre!(
+        "malformed digest line {line_no} in {}: {line:?}",
+        path.display()
+    )
+}
+
+enum Inner {
+    Ignore,
+    Record {
+        path: PathBuf,
+        writer: BufWriter<File>,
+    },
+    Verify {
+        path: PathBuf,
+        expected: std::vec::IntoIter<Recorded>,
+        total: usize,
+        seen: usize,
+    },
+}
+
+/// Drives a sidecar digest file alongside a [`super::FrameExtractor`], so an
+/// ffmpeg/decoder/hasher upgrade that silently changes a frame gets caught as a test
+/// failure instead of quietly drifting whatever fixture depends on it. Call
+/// [`Self::observe`] once per frame the extractor emits, in order, then [`Self::finish`]
+/// once the extractor is exhausted.
+pub struct DigestState {
+    inner: Inner,
+}
+
+impl DigestState {
+    pub fn open(mode: DigestMode, sidecar: &Path) -> eyre::Result<Self> {
+        let inner = match mode {
+            DigestMode::Ignore => Inner::Ignore,
+            DigestMode::Record => {
+                let file = File::create(sidecar)
+                    .wrap_err_with(|| format!("failed to create {}", sidecar.display()))?;
+                Inner::Record {
+                    path: sidecar.to_path_buf(),
+                    writer: BufWriter::new(file),
+                }
+            }
+            DigestMode::Verify => {
+                let file = File::open(sidecar)
+                    .wrap_err_with(|| format!("failed to open {}", sidecar.display()))?;
+                let mut expected = Vec::new();
+                for (line_no, line) in BufReader::new(file).lines().enumerate() {
+                    let line =
+                        line.wrap_err_with(|| format!("failed to read {}", sidecar.display()))?;
+                    expected.push(Recorded::parse(sidecar, line_no + 1, &line)?);
+                }
+                let total = expected.len();
+                Inner::Verify {
+                    path: sidecar.to_path_buf(),
+                    expected: expected.into_iter(),
+                    total,
+                    seen: 0,
+                }
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    /// Call once per frame emitted by the extractor, in the order they were emitted.
+    pub fn observe(
+        &mut self,
+        timestamp: &Timestamp,
+        hash: Hamming,
+        frame: &RgbImage,
+    ) -> eyre::Result<()> {
+        match &mut self.inner {
+            Inner::Ignore => Ok(()),
+            Inner::Record { path, writer } => writeln!(
+                writer,
+                "{} {} {}",
+                timestamp_millis(timestamp),
+                hash.to_base64(),
+                content_digest(frame)
+            )
+            .wrap_err_with(|| format!("failed to write a digest line to {}", path.display())),
+            Inner::Verify {
+                path,
+                expected,
+                total,
+                seen,
+            } => {
+                *seen += 1;
+                let Some(recorded) = expected.next() else {
+                    eyre::bail!(
+                        "stream length changed: {} recorded {} frames, but this run produced at least {}",
+                        path.display(),
+                        total,
+                        seen,
+                    );
+                };
+
+                let actual_ms = timestamp_millis(timestamp);
+                if recorded.timestamp_ms != actual_ms {
+                    eyre::bail!(
+                        "frame {} in {}: expected timestamp {}ms, got {}ms",
+                        seen,
+                        path.display(),
+                        recorded.timestamp_ms,
+                        actual_ms,
+                    );
+                }
+
+                let actual_hash = hash.to_base64();
+                if recorded.hash_hex != actual_hash {
+                    eyre::bail!(
+                        "frame at {}ms in {}: expected hash {}, got {}",
+                        actual_ms,
+                        path.display(),
+                        recorded.hash_hex,
+                        actual_hash,
+                    );
+                }
+
+                let actual_content = content_digest(frame).to_string();
+                if recorded.content_hex != actual_content {
+                    eyre::bail!(
+                        "frame at {}ms in {}: expected content digest {}, got {}",
+                        actual_ms,
+                        path.display(),
+                        recorded.content_hex,
+                        actual_content,
+                    );
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Call once after the extractor has run out of frames, to catch a run that came up
+    /// short of what was recorded.
+    pub fn finish(self) -> eyre::Result<()> {
+        match self.inner {
+            Inner::Ignore => Ok(()),
+            Inner::Record { mut writer, path } => writer
+                .flush()
+                .wrap_err_with(|| format!("failed to flush {}", path.display())),
+            Inner::Verify {
+                path,
+                expected,
+                total,
+                seen,
+            } => {
+                let remaining = expected.len();
+                if remaining > 0 {
+                    eyre::bail!(
+                        "stream length changed: {} recorded {} frames, but this run only produced {}",
+                        path.display(),
+                        total,
+                        seen,
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Milliseconds since `timestamp`'s stream start, truncated like
+/// [`Timestamp::duration_to_string`]'s own internal breakdown. This module is a
+/// descendant of [`super`], so it can reach down into `Timestamp`'s `pub(super)`
+/// fields instead of round-tripping through its `Display` impl, which only produces a
+/// human string, not a number a digest line can parse back.
+fn timestamp_millis(timestamp: &Timestamp) -> i64 {
+    let total_ms = (timestamp.timestamp - timestamp.first_timestamp) as f64
+        * (timestamp.timebase_numerator as f64 / timestamp.timebase_denominator as f64)
+        * 1e3;
+    total_ms.trunc() as i64
+}
+
+fn content_digest(frame: &RgbImage) -> blake3::Hash {
+    blake3::hash(frame.as_raw())
+}
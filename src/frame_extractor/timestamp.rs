@@ -24,33 +24,39 @@ impl Timestamp {
         }
     }
 
-    fn parts(&self) -> (bool, f64, f64, f64, f64) {
-        // TODO: Why not use ffmpeg rescale and rational if not all decimals are going to
-        // be used?
-        let mut total: f64 = (self.timestamp as f64 - self.first_timestamp as f64)
-            * (self.timebase_numerator as f64 / self.timebase_denominator as f64);
+    /// Elapsed milliseconds since `first_timestamp`, exact (no `f64` rounding), computed
+    /// via `i128` intermediates so `delta * timebase_numerator * 1000` can't overflow.
+    fn elapsed_millis(&self) -> i128 {
+        let delta = self.timestamp as i128 - self.first_timestamp as i128;
+        (delta * self.timebase_numerator as i128 * 1000) / self.timebase_denominator as i128
+    }
+
+    fn parts(&self) -> (bool, i128, i128, i128, i128) {
+        let millis = self.elapsed_millis();
 
-        let negative = if total < 0.0 {
-            total = -total;
-            true
-        } else {
-            false
-        };
+        let negative = millis < 0;
+        let mut total = millis.abs();
 
-        let subsec = (total.fract() * 1e3).trunc();
-        total = total.trunc();
+        let subsec = total % 1000;
+        total /= 1000;
 
-        let hours = (total / 3600.0).trunc();
-        total %= 3600.0;
+        let hours = total / 3600;
+        total %= 3600;
 
-        let minutes = (total / 60.0).trunc();
-        total %= 60.0;
+        let minutes = total / 60;
+        total %= 60;
 
         let seconds = total;
 
         (negative, hours, minutes, seconds, subsec)
     }
 
+    /// `None` if `self` is before its `first_timestamp`.
+    pub fn as_duration(&self) -> Option<Duration> {
+        let millis = self.elapsed_millis();
+        Some(Duration::from_millis(u64::try_from(millis).ok()?))
+    }
+
     pub fn duration_to_string(dur: Duration) -> String {
         Timestamp::new(
             dur.as_millis()
@@ -63,6 +69,20 @@ impl Timestamp {
     }
 }
 
+impl ArchivedTimestamp {
+    /// Reconstructs an owned [`Timestamp`], for callers outside of [`super`] that only
+    /// have access to an archived database (e.g. the BKTree FUSE mount), which can't
+    /// reach the `pub(super)` fields directly.
+    pub fn to_owned(&self) -> Timestamp {
+        Timestamp {
+            timebase_numerator: self.timebase_numerator,
+            timebase_denominator: self.timebase_denominator,
+            timestamp: self.timestamp,
+            first_timestamp: self.first_timestamp,
+        }
+    }
+}
+
 impl fmt::Display for Timestamp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let (negative, hours, minutes, seconds, subsec) = self.parts();
@@ -87,4 +107,12 @@ mod test {
         let ts = Timestamp::new(1005, Rational::new(1, 1000), 0);
         assert_eq!("00:00:01.005", ts.to_string());
     }
+
+    #[test]
+    fn timestamp_to_string_large_no_rounding_error() {
+        // Large timestamp on a non-power-of-ten timebase: exercises the i128 path
+        // without the precision loss an f64-based conversion would accumulate.
+        let ts = Timestamp::new(999_999_999, Rational::new(1, 30_000), 0);
+        assert_eq!("09:15:33.333", ts.to_string());
+    }
 }
@@ -1,9 +1,16 @@
-use std::path::Path;
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use self::{priority_queue::PriorityQueue, sql::Sql};
+use self::{cache::Cache, sql::Sql};
 
+mod arc_cache;
+mod cache;
+mod migrate;
 mod priority_queue;
 mod sql;
 
@@ -11,6 +18,16 @@ type Uuid = i64;
 const UUID_FIRST: Uuid = 0;
 const UUID_NULL: Uuid = Uuid::min_value();
 
+/// The meta-table key that [`Sql::init_db`] stamps new databases with and that
+/// [`Heap::new`] checks on open, see [`FORMAT_VERSION`].
+const FORMAT_VERSION_KEY: &str = "format_version";
+
+/// The on-disk format version understood by this build, covering the `meta`/`refs`
+/// table layout and the bincode encoding of the blocks stored in `refs`. Bump this and
+/// add a step to [`migrate`] whenever either changes in a way that would misread older
+/// databases.
+const FORMAT_VERSION: u32 = 1;
+
 pub type Result<T> = std::result::Result<T, HeapError>;
 
 #[derive(thiserror::Error, Debug)]
@@ -23,10 +40,37 @@ pub enum HeapError {
     IoError(#[from] std::io::Error),
     #[error("Ref does not exist: {0:?}")]
     RefNotExists(Ref),
+    #[error(
+        "database format version {found} is newer than the {supported} supported by this build"
+    )]
+    FormatTooNew { found: u32, supported: u32 },
+    #[error(
+        "database format version {found} predates the {supported} supported by this build, run `Heap::upgrade` first"
+    )]
+    FormatTooOld { found: u32, supported: u32 },
+    #[error("dangling ref: {to:?}, reached from {from:?}, has no value")]
+    DanglingRef { from: Ref, to: Ref },
+    #[error("unknown blob compression codec tag: {0}")]
+    UnknownCodec(u8),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("cannot close a SharedHeap while an AllocHandle is still alive")]
+    StillShared,
+}
+
+/// Which compressor a fresh blob is written with in `Sql::put_kv`. Reading a blob
+/// always honors whatever tag it was written with, so changing this only affects new
+/// writes; existing files stay readable even after the default changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Compression {
+    None = 0,
+    #[default]
+    Zstd = 1,
+    Zlib = 2,
 }
 
 pub struct Heap<T> {
-    cache: PriorityQueue<Uuid, Block<T>>,
+    cache: Cache<T>,
     dirty_changes: usize,
     cache_age: usize,
     sql: Sql,
@@ -34,12 +78,37 @@ pub struct Heap<T> {
     // -- saved in db --
     next_id: Uuid,
     root: Ref,
+    /// `block_id`s emptied out by `remove`/`compact` and not yet reused, so
+    /// `allocate`/`allocate_local` can hand them out again instead of letting
+    /// `next_id` grow forever. See [`Heap::compact`].
+    free_list: Vec<Uuid>,
 }
 
 struct Config {
     cache_capacity: usize,
     dirtyness_limit: usize,
     maximum_block_size: usize,
+    compression: Compression,
+    eviction_policy: EvictionPolicy,
+    compact_when_fragmentation_exceeds: Option<f64>,
+}
+
+/// Which strategy [`Heap`] evicts cached blocks with once its cache is full, set via
+/// [`HeapBuilder::eviction_policy`]. Defaults to [`EvictionPolicy::Lfu`], preserving the
+/// existing behavior.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Evict the block with the smallest `access_count`, patched with the `cache_age`
+    /// watermark so a freshly-loaded block doesn't immediately look like the rarest one
+    /// in the cache. Thrashes on a mixed scan+revisit access pattern: a block touched
+    /// once a long time ago can outrank one touched heavily but more recently.
+    #[default]
+    Lfu,
+    /// [Adaptive Replacement Cache](https://en.wikipedia.org/wiki/Adaptive_replacement_cache):
+    /// balances recency against frequency by tracking which of the two caused more
+    /// "ghost" hits among recently evicted keys, adapting the balance instead of
+    /// committing to one metric up front.
+    Arc,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -60,6 +129,56 @@ pub struct Ref {
     sub_id: Uuid,
 }
 
+/// A single invariant violation found by [`Heap::check`]/[`Heap::repair`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// `block_id`'s `data` was not sorted ascending by `sub_id`, or had duplicate
+    /// `sub_id`s.
+    UnsortedBlock { block_id: Uuid },
+    /// `block_id` was persisted with no entries at all, left behind by a crash
+    /// mid-[`Heap::flush`].
+    EmptyBlock { block_id: Uuid },
+    /// `id` (a `sub_id` or `block_id` seen in `refs`) was `>=` the persisted `next_id`.
+    IdAboveNextId { id: Uuid },
+    /// The persisted `root` did not resolve to a live entry.
+    DanglingRoot,
+}
+
+/// What [`Heap::check`]/[`Heap::repair`] found, in the order it was found. An empty
+/// `inconsistencies` means the heap was consistent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+/// Whether [`Heap::check_or_repair`] only reports what it finds or also fixes it.
+enum CheckMode {
+    ReadOnly,
+    Repair { clear_dangling_root: bool },
+}
+
+/// One line of [`Heap::dump`]'s output: a leading [`DumpLine::Meta`] line followed by
+/// one [`DumpLine::Block`] line per stored block, see [`Heap::restore`].
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum DumpLine<T> {
+    Meta {
+        next_id: Uuid,
+        root: Ref,
+        free_list: Vec<Uuid>,
+    },
+    Block {
+        block_id: Uuid,
+        data: Vec<(Uuid, T)>,
+    },
+}
+
 pub struct HeapBuilder {
     config: Config,
 }
@@ -72,6 +191,9 @@ impl HeapBuilder {
                 cache_capacity: 2048,
                 dirtyness_limit: 128,
                 maximum_block_size: 10,
+                compression: Compression::default(),
+                eviction_policy: EvictionPolicy::default(),
+                compact_when_fragmentation_exceeds: None,
             },
         }
     }
@@ -94,18 +216,73 @@ impl HeapBuilder {
         self
     }
 
+    /// Which compressor newly-written blobs are prefixed with. Defaults to
+    /// [`Compression::Zstd`] at a moderate level. Blobs written under a previous
+    /// codec stay readable regardless of what this is set to, since [`Sql::get_kv`]
+    /// always honors the tag already on the blob.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.config.compression = compression;
+        self
+    }
+
+    /// Which [`EvictionPolicy`] the cache evicts blocks with once it's full.
+    pub fn eviction_policy(mut self, eviction_policy: EvictionPolicy) -> Self {
+        self.config.eviction_policy = eviction_policy;
+        self
+    }
+
+    /// Enables opportunistic [`Heap::compact`] passes from [`Heap::checkpoint_compacting`]:
+    /// once the average fraction of unused capacity across every stored block exceeds
+    /// `threshold`, the next call compacts before returning. Defaults to `None`, which
+    /// never compacts automatically; call [`Heap::compact`] directly instead.
+    pub fn compact_when_fragmentation_exceeds(mut self, threshold: f64) -> Self {
+        assert!((0.0..=1.0).contains(&threshold));
+        self.config.compact_when_fragmentation_exceeds = Some(threshold);
+        self
+    }
+
     pub fn in_memory<T>(self) -> Result<Heap<T>>
     where
         T: Serialize + DeserializeOwned,
     {
-        Heap::new(Sql::new_in_memory()?, self.config)
+        Heap::new(
+            Sql::new_in_memory()?.with_compression(self.config.compression),
+            self.config,
+        )
     }
 
     pub fn from_file<T>(self, file: impl AsRef<Path>) -> Result<Heap<T>>
     where
         T: Serialize + DeserializeOwned,
     {
-        Heap::new(Sql::new_from_file(file)?, self.config)
+        Heap::new(
+            Sql::new_from_file(file)?.with_compression(self.config.compression),
+            self.config,
+        )
+    }
+
+    /// Rebuilds an in-memory heap from a [`Heap::dump`] stream.
+    pub fn restore_in_memory<T>(self, reader: impl BufRead) -> Result<Heap<T>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        Heap::restore(
+            Sql::new_in_memory()?.with_compression(self.config.compression),
+            self.config,
+            reader,
+        )
+    }
+
+    /// Rebuilds an on-disk heap at `file` from a [`Heap::dump`] stream.
+    pub fn restore_from_file<T>(self, file: impl AsRef<Path>, reader: impl BufRead) -> Result<Heap<T>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        Heap::restore(
+            Sql::new_from_file(file)?.with_compression(self.config.compression),
+            self.config,
+            reader,
+        )
     }
 }
 
@@ -114,17 +291,37 @@ where
     T: Serialize + DeserializeOwned,
 {
     fn new(sql: Sql, config: Config) -> Result<Self> {
+        // NOTE: a missing version tag means the database predates this check; treat it
+        // as current rather than refusing databases that were never versioned.
+        let found = sql
+            .get_meta::<u32>(FORMAT_VERSION_KEY)?
+            .unwrap_or(FORMAT_VERSION);
+        if found > FORMAT_VERSION {
+            return Err(HeapError::FormatTooNew {
+                found,
+                supported: FORMAT_VERSION,
+            });
+        }
+        if found < FORMAT_VERSION {
+            return Err(HeapError::FormatTooOld {
+                found,
+                supported: FORMAT_VERSION,
+            });
+        }
+
         let next_id = sql.get_meta::<Uuid>("next_id")?.unwrap_or(UUID_FIRST);
         let root = sql.get_meta::<Ref>("root")?.unwrap_or(Ref::null());
+        let free_list = sql.get_meta::<Vec<Uuid>>("free_list")?.unwrap_or_default();
 
         sql.begin()?;
 
         Ok(Self {
-            cache: PriorityQueue::with_capacity(config.cache_capacity),
+            cache: Cache::with_capacity(config.eviction_policy, config.cache_capacity),
             dirty_changes: 0,
             cache_age: 0,
             next_id,
             root,
+            free_list,
             config,
             sql,
         })
@@ -138,9 +335,80 @@ where
         HeapBuilder::new().from_file(file)
     }
 
+    /// Rebuilds an in-memory heap from a [`Heap::dump`] stream, see
+    /// [`HeapBuilder::restore_in_memory`].
+    pub fn restore_in_memory(reader: impl BufRead) -> Result<Self> {
+        HeapBuilder::new().restore_in_memory(reader)
+    }
+
+    /// Rebuilds an on-disk heap at `file` from a [`Heap::dump`] stream, see
+    /// [`HeapBuilder::restore_from_file`].
+    pub fn restore_from_file(file: impl AsRef<Path>, reader: impl BufRead) -> Result<Self> {
+        HeapBuilder::new().restore_from_file(file, reader)
+    }
+
+    /// The shared implementation behind [`HeapBuilder::restore_in_memory`]/
+    /// [`HeapBuilder::restore_from_file`]: reads every [`DumpLine`] from `reader`,
+    /// in any order (the whole stream is consumed before anything is written), then
+    /// writes every block back exactly as dumped -- unlike a normal [`Heap::allocate`]/
+    /// [`Heap::allocate_local`] sequence, this bypasses `maximum_block_size`'s
+    /// re-packing, so a restored heap's block grouping matches the one it was dumped
+    /// from.
+    fn restore(sql: Sql, config: Config, reader: impl BufRead) -> Result<Self> {
+        let mut next_id = UUID_FIRST;
+        let mut root = Ref::null();
+        let mut free_list = Vec::new();
+        let mut blocks: Vec<(Uuid, Vec<(Uuid, T)>)> = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<DumpLine<T>>(&line)? {
+                DumpLine::Meta {
+                    next_id: dumped_next_id,
+                    root: dumped_root,
+                    free_list: dumped_free_list,
+                } => {
+                    next_id = dumped_next_id;
+                    root = dumped_root;
+                    free_list = dumped_free_list;
+                }
+                DumpLine::Block { block_id, data } => blocks.push((block_id, data)),
+            }
+        }
+
+        sql.begin()?;
+        for (block_id, data) in &blocks {
+            sql.put_refs(*block_id, data)?;
+        }
+        sql.put_meta("next_id", next_id)?;
+        sql.put_meta("root", root)?;
+        sql.put_meta("free_list", &free_list)?;
+        sql.commit()?;
+
+        Heap::new(sql, config)
+    }
+
+    /// Brings an on-disk database at `file` up to [`FORMAT_VERSION`] by replaying any
+    /// migration steps registered in [`migrate`] that apply to its current version.
+    /// Does nothing if the database is already current. Unlike [`Heap::new_from_file`],
+    /// this does not refuse an outdated database, so it's the only way to open one for
+    /// the sake of carrying it forward instead of rebuilding it from scratch.
+    pub fn upgrade(file: impl AsRef<Path>) -> Result<()> {
+        let sql = Sql::new_from_file(file)?;
+        migrate::run(&sql)?;
+        sql.close()
+    }
+
+    /// Allocates a fresh, one-entry block. Reuses a `block_id` off the free list left
+    /// behind by `remove`/`compact` if one is available, instead of always growing
+    /// `next_id`; the entry's own `sub_id` is always freshly minted either way.
     pub fn allocate(&mut self, initial_data: T) -> Result<Ref> {
-        let r = Ref::new(self.next_id, self.next_id);
-        self.handle_overflow()?;
+        let block_id = self.free_list.pop().unwrap_or(self.next_id);
+        let r = Ref::new(block_id, self.next_id);
+        self.handle_overflow(r.block_id)?;
         let oldval = self.cache.push(
             r.block_id,
             Block::new_dirty(r.sub_id, initial_data, self.cache_age),
@@ -174,6 +442,34 @@ where
         }
     }
 
+    /// Reserves `count` fresh ids in one call, for [`AllocHandle`]'s slab refills:
+    /// bumps `next_id` by `count` and hands back the first id of the reserved range,
+    /// the rest being implicitly reserved since nothing else will ever hand them out.
+    /// Unlike [`Heap::allocate`], this never reuses a freed `block_id`, since the free
+    /// list itself would need its own locking to hand entries out piecemeal to
+    /// different slabs.
+    fn reserve_ids(&mut self, count: usize) -> Uuid {
+        let start = self.next_id;
+        self.next_id += count as Uuid;
+        start
+    }
+
+    /// Like [`Heap::allocate`], but takes an explicit id instead of drawing the next
+    /// one off `next_id` -- used by [`AllocHandle`] to insert into a slab reserved
+    /// ahead of time via [`Heap::reserve_ids`]. `id` serves as both the `block_id` and
+    /// `sub_id`, exactly like a plain [`Heap::allocate`] would have assigned them.
+    fn allocate_with_id(&mut self, id: Uuid, initial_data: T) -> Result<Ref> {
+        let r = Ref::new(id, id);
+        self.handle_overflow(r.block_id)?;
+        let oldval = self.cache.push(
+            r.block_id,
+            Block::new_dirty(r.sub_id, initial_data, self.cache_age),
+        );
+        self.dirty_changes += 1;
+        assert!(oldval.is_none());
+        Ok(r)
+    }
+
     pub fn root(&self) -> Ref {
         self.root
     }
@@ -192,6 +488,145 @@ where
         self.sql.count_refs()
     }
 
+    /// Streams every `(block_id, blocks)` entry currently persisted in the `refs`
+    /// table, a consistent snapshot as of this call, without materializing the whole
+    /// table in memory. The cache is flushed first so no dirty block is missed. Meant
+    /// for a future compaction/rebuild pass; see [`Sql::iter_refs`].
+    pub fn iter_refs(&mut self) -> Result<impl Iterator<Item = Result<(Uuid, Vec<(Uuid, T)>)>> + '_> {
+        self.flush()?;
+        Ok(self.sql.iter_refs()?)
+    }
+
+    /// Validates every invariant the rest of `Heap` silently assumes -- each block's
+    /// `data` sorted ascending by `sub_id` with no duplicates, no stored block empty,
+    /// every `sub_id`/`block_id` below `next_id`, and `root` either null or live --
+    /// without changing anything. See [`Heap::repair`] to fix what this finds.
+    pub fn check(&mut self) -> Result<CheckReport> {
+        self.check_or_repair(CheckMode::ReadOnly)
+    }
+
+    /// Like [`Heap::check`], but also fixes what it finds: re-sorts or drops duplicate
+    /// entries from corrupt blocks, deletes blocks left empty by a crash
+    /// mid-[`Heap::flush`], and recomputes `next_id` as `max(sub_id, block_id) + 1`
+    /// over every row actually on disk. A dangling `root` is only cleared if
+    /// `clear_dangling_root` is set, since an empty heap looks the same as a
+    /// corrupted one from here. Returns the same [`CheckReport`] [`Heap::check`] would
+    /// have, describing what was found and repaired. Meant to recover a heap after an
+    /// interrupted [`Heap::checkpoint`]/[`Heap::flush`], instead of the current
+    /// all-or-nothing transaction assumption.
+    pub fn repair(&mut self, clear_dangling_root: bool) -> Result<CheckReport> {
+        self.check_or_repair(CheckMode::Repair {
+            clear_dangling_root,
+        })
+    }
+
+    /// Streams every row straight from `Sql`, bypassing the LRU cache entirely, so a
+    /// corrupted block sitting in `refs` can't hide behind a clean in-memory copy.
+    fn check_or_repair(&mut self, mode: CheckMode) -> Result<CheckReport> {
+        self.flush()?;
+        let repairing = matches!(mode, CheckMode::Repair { .. });
+        if repairing {
+            self.cache.clear();
+        }
+
+        let rows: Vec<(Uuid, Vec<(Uuid, T)>)> =
+            self.sql.iter_refs::<Vec<(Uuid, T)>>()?.collect::<Result<_>>()?;
+
+        let mut report = CheckReport::default();
+        let mut max_id: Option<Uuid> = None;
+        let mut root_is_live = self.root.is_null();
+
+        for (block_id, mut data) in rows {
+            max_id = Some(max_id.map_or(block_id, |m| m.max(block_id)));
+
+            if data.is_empty() {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::EmptyBlock { block_id });
+                if repairing {
+                    self.sql.remove_refs(block_id)?;
+                    self.free_list.push(block_id);
+                }
+                continue;
+            }
+
+            let sorted = data.windows(2).all(|w| w[0].0 < w[1].0);
+            if !sorted {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::UnsortedBlock { block_id });
+                if repairing {
+                    data.sort_by_key(|(sub_id, _)| *sub_id);
+                    data.dedup_by_key(|(sub_id, _)| *sub_id);
+                    self.sql.put_refs(block_id, &data)?;
+                }
+            }
+
+            for (sub_id, _) in &data {
+                max_id = Some(max_id.map_or(*sub_id, |m| m.max(*sub_id)));
+                if !root_is_live && self.root.block_id == block_id && self.root.sub_id == *sub_id {
+                    root_is_live = true;
+                }
+            }
+        }
+
+        if let Some(max_id) = max_id {
+            if max_id >= self.next_id {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::IdAboveNextId { id: max_id });
+                if repairing {
+                    self.next_id = max_id + 1;
+                    self.sql.put_meta("next_id", self.next_id)?;
+                }
+            }
+        }
+
+        if !root_is_live {
+            report.inconsistencies.push(Inconsistency::DanglingRoot);
+            if let CheckMode::Repair {
+                clear_dangling_root: true,
+            } = mode
+            {
+                self.root = Ref::null();
+                self.sql.put_meta("root", self.root)?;
+            }
+        }
+
+        if repairing {
+            self.sql.put_meta("free_list", &self.free_list)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Writes this heap's entire contents -- `next_id`, `root`, and every stored
+    /// block in its original grouping -- to `writer` as self-describing JSON Lines,
+    /// one line per block plus a leading meta line. Unlike the SQLite file itself,
+    /// this format is independent of the `refs`/`meta` table schema and the bincode
+    /// layout of a block's `data`, so it survives a breaking change to either and
+    /// can be migrated across versions, diffed, or hand-inspected. See
+    /// [`Heap::restore_in_memory`]/[`Heap::restore_from_file`] for the inverse;
+    /// restoring and then dumping again round-trips.
+    pub fn dump(&mut self, mut writer: impl Write) -> Result<()> {
+        self.flush()?;
+
+        let meta: DumpLine<T> = DumpLine::Meta {
+            next_id: self.next_id,
+            root: self.root,
+            free_list: self.free_list.clone(),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&meta)?)?;
+
+        for row in self.sql.iter_refs::<Vec<(Uuid, T)>>()? {
+            let (block_id, data) = row?;
+            let line: DumpLine<T> = DumpLine::Block { block_id, data };
+            writeln!(writer, "{}", serde_json::to_string(&line)?)?;
+        }
+
+        Ok(())
+    }
+
     pub fn set(&mut self, r: Ref, data: T) -> Result<()> {
         match self.deref_mut(r)? {
             None => Err(HeapError::RefNotExists(r)),
@@ -261,7 +696,7 @@ where
     fn load_block(&mut self, r: Ref) -> Result<()> {
         if !r.is_null() && !self.cache.contains_key(r.block_id()) {
             if let Some(val) = self.sql.get_refs::<Vec<(Uuid, T)>>(r.block_id)? {
-                self.handle_overflow()?;
+                self.handle_overflow(r.block_id)?;
                 let oldval = self
                     .cache
                     .push(r.block_id, Block::new_clean(val, self.cache_age));
@@ -271,10 +706,40 @@ where
         Ok(())
     }
 
+    /// Commits every dirty block to the `Sql` store in a single transaction, rolling
+    /// it back on failure so an interrupted flush can't leave a half-written batch
+    /// behind; the cache stays dirty in that case and the next flush retries it.
     pub fn flush(&mut self) -> Result<()> {
-        self.sql.put_meta("next_id", self.next_id)?;
-        self.sql.put_meta("root", self.root)?;
+        if let Err(e) = self.flush_inner() {
+            self.sql.rollback()?;
+            self.sql.begin()?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Discards every dirty change made since the last successful [`Heap::flush`] by
+    /// rolling back the open `Sql` transaction, then resyncs the in-memory cache and
+    /// the `next_id`/`root` bookkeeping to whatever is actually on disk. For use by a
+    /// hard-terminating caller that wants to stop immediately without persisting
+    /// anything from the current operation.
+    pub fn abandon(&mut self) -> Result<()> {
+        self.sql.rollback()?;
+        self.sql.begin()?;
+
+        self.cache.retain(|_| false);
+        self.dirty_changes = 0;
+        self.next_id = self.sql.get_meta::<Uuid>("next_id")?.unwrap_or(UUID_FIRST);
+        self.root = self.sql.get_meta::<Ref>("root")?.unwrap_or(Ref::null());
+        self.free_list = self
+            .sql
+            .get_meta::<Vec<Uuid>>("free_list")?
+            .unwrap_or_default();
 
+        Ok(())
+    }
+
+    fn flush_inner(&mut self) -> Result<()> {
         for (&id, block) in self.cache.iter() {
             match block.state {
                 BlockState::Clean => {
@@ -283,6 +748,7 @@ where
                 BlockState::Dirty => {
                     if block.data.is_empty() {
                         self.sql.remove_refs(id)?;
+                        self.free_list.push(id);
                     } else {
                         self.sql.put_refs(id, &block.data)?;
                     }
@@ -290,6 +756,10 @@ where
             }
         }
 
+        self.sql.put_meta("next_id", self.next_id)?;
+        self.sql.put_meta("root", self.root)?;
+        self.sql.put_meta("free_list", &self.free_list)?;
+
         self.sql.commit()?;
         self.sql.begin()?;
 
@@ -309,6 +779,123 @@ where
         Ok(())
     }
 
+    /// Like [`Heap::checkpoint`], but also runs [`Heap::compact`] once
+    /// [`HeapBuilder::compact_when_fragmentation_exceeds`]'s threshold is crossed,
+    /// reclaiming the space `remove` leaves fragmented across blocks before it bloats
+    /// the file forever. `remap` is only invoked on the calls where a compaction pass
+    /// actually runs, see [`Heap::compact`]. Does nothing beyond a normal checkpoint if
+    /// no threshold was configured.
+    pub fn checkpoint_compacting(&mut self, remap: impl FnMut(Ref, Ref)) -> Result<()> {
+        self.checkpoint()?;
+        if let Some(threshold) = self.config.compact_when_fragmentation_exceeds {
+            if self.fragmentation()? > threshold {
+                self.compact(remap)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The fraction of unused capacity across every stored block, averaged over the
+    /// whole heap: `0.0` means every block is packed to `maximum_block_size`,
+    /// `1.0 - 1.0 / maximum_block_size` means every block holds a single entry. Used by [`Heap::checkpoint_compacting`]
+    /// to decide whether a compaction pass is worth running.
+    fn fragmentation(&mut self) -> Result<f64> {
+        self.flush()?;
+
+        let mut num_blocks = 0usize;
+        let mut num_entries = 0usize;
+        for row in self.sql.iter_refs::<Vec<(Uuid, T)>>()? {
+            let (_, data) = row?;
+            num_blocks += 1;
+            num_entries += data.len();
+        }
+
+        if num_blocks == 0 {
+            return Ok(0.0);
+        }
+        let capacity = num_blocks * self.config.maximum_block_size;
+        Ok(1.0 - (num_entries as f64 / capacity as f64))
+    }
+
+    /// Merges under-full blocks together to reclaim the space `remove` leaves behind:
+    /// blocks below `maximum_block_size` are packed into each other (never into an
+    /// already-full block) until they no longer fit, moving each relocated entry to a
+    /// new `block_id` while keeping its `sub_id` unchanged. `remap(old, new)` is called
+    /// once per relocated entry so the caller can rewrite any `Ref`s it stores inside
+    /// `T` itself -- e.g. a tree node's pointers to its children -- since `Heap` has no
+    /// way to find those on its own; `self.root()` is fixed up automatically. Returns
+    /// every `block_id` this freed, now on the free list and eligible for reuse by a
+    /// future [`Heap::allocate`].
+    pub fn compact(&mut self, mut remap: impl FnMut(Ref, Ref)) -> Result<Vec<Uuid>> {
+        self.flush()?;
+        self.cache.clear();
+
+        let rows: Vec<(Uuid, Vec<(Uuid, T)>)> =
+            self.sql.iter_refs::<Vec<(Uuid, T)>>()?.collect::<Result<_>>()?;
+        let (_full, under_full): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .partition(|(_, data)| data.len() >= self.config.maximum_block_size);
+
+        let mut freed = Vec::new();
+        let mut merged: Vec<(Uuid, Vec<(Uuid, T)>)> = Vec::new();
+
+        for (block_id, data) in under_full {
+            match merged.last_mut() {
+                Some((target_id, target_data))
+                    if target_data.len() + data.len() <= self.config.maximum_block_size =>
+                {
+                    let target_id = *target_id;
+                    for (sub_id, value) in data {
+                        let old = Ref::new(block_id, sub_id);
+                        let new = Ref::new(target_id, sub_id);
+                        if self.root == old {
+                            self.root = new;
+                        }
+                        remap(old, new);
+                        target_data.push((sub_id, value));
+                    }
+                    freed.push(block_id);
+                }
+                _ => merged.push((block_id, data)),
+            }
+        }
+
+        for (_, data) in &mut merged {
+            data.sort_by_key(|(sub_id, _)| *sub_id);
+        }
+        for block_id in &freed {
+            self.sql.remove_refs(*block_id)?;
+        }
+        for (block_id, data) in &merged {
+            self.sql.put_refs(*block_id, data)?;
+        }
+
+        self.free_list.extend(freed.iter().copied());
+        self.sql.put_meta("root", self.root)?;
+        self.sql.put_meta("free_list", &self.free_list)?;
+
+        self.sql.commit()?;
+        self.sql.begin()?;
+
+        Ok(freed)
+    }
+
+    /// Flushes every dirty block and then force-checkpoints the WAL into the main
+    /// database file, so a long-running job can take periodic durable snapshots
+    /// instead of only fsyncing once the WAL grows large or the connection closes.
+    pub fn checkpoint_wal(&mut self) -> Result<()> {
+        self.flush()?;
+        self.sql.wal_checkpoint()
+    }
+
+    /// Flushes every dirty block and then copies a consistent snapshot of the database
+    /// to `path` via [`Sql::backup_to`], producing a portable copy of the index that
+    /// can be read independently while this `Heap` keeps running.
+    pub fn backup_to(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.flush()?;
+        self.sql.backup_to(path)
+    }
+
     /// Saving is not done on drop because the db should not accidentally be saved in an
     /// invalid state from e.g. panics.
     pub fn close(mut self) -> Result<()> {
@@ -318,14 +905,16 @@ where
     }
 
     /// Clears space in the cache to make sure that at least one new element can be added
-    /// without becoming bigger than the maximum size.
-    fn handle_overflow(&mut self) -> Result<()> {
+    /// without becoming bigger than the maximum size. `incoming` is the key about to be
+    /// inserted, needed by [`EvictionPolicy::Arc`]'s `t1`-vs-`t2` eviction rule; ignored
+    /// under [`EvictionPolicy::Lfu`].
+    fn handle_overflow(&mut self, incoming: Uuid) -> Result<()> {
         assert!(self.config.cache_capacity >= 1);
         while self.cache.len() >= self.config.cache_capacity {
             // TODO: if the ref count overflows, then halve (or something) all
             // access_counts in the cache. But that will probably never happen since a
             // usize is pretty big.
-            let (id, min) = self.cache.pop().expect("the cache is not empty");
+            let (id, min) = self.cache.evict(incoming).expect("the cache is not empty");
             self.cache_age = min.access_count;
 
             match min.state {
@@ -335,6 +924,7 @@ where
                 BlockState::Dirty => {
                     if min.data.is_empty() {
                         self.sql.remove_refs(id)?;
+                        self.free_list.push(id);
                     } else {
                         self.sql.put_refs(id, min.data)?;
                     }
@@ -345,6 +935,126 @@ where
     }
 }
 
+/// A [`Heap`] shared between worker threads, e.g. several threads hashing frames into
+/// one index. Every operation takes the same lock a plain [`Heap`] needed `&mut self`
+/// for, except allocation: call [`SharedHeap::alloc_handle`] to get each worker its own
+/// [`AllocHandle`], which reserves a slab of ids up front so the hot `allocate` path
+/// only takes the lock to insert into the cache, not to mint an id for every call.
+pub struct SharedHeap<T> {
+    inner: Arc<Mutex<Heap<T>>>,
+}
+
+impl<T> SharedHeap<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn new(heap: Heap<T>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(heap)),
+        }
+    }
+
+    pub fn new_in_memory() -> Result<Self> {
+        Ok(Self::new(Heap::new_in_memory()?))
+    }
+
+    pub fn new_from_file(file: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Heap::new_from_file(file)?))
+    }
+
+    /// Hands out a worker handle that reserves ids from this heap in batches of
+    /// `slab_size`, see [`AllocHandle`]. Cheap to create; make one per worker thread.
+    pub fn alloc_handle(&self, slab_size: usize) -> AllocHandle<T> {
+        assert!(slab_size >= 1);
+        AllocHandle {
+            inner: Arc::clone(&self.inner),
+            slab_size,
+            // Empty, so the first `allocate` call refills it.
+            slab_next: UUID_FIRST,
+            slab_end: UUID_FIRST,
+        }
+    }
+
+    pub fn deref<R>(&self, r: Ref, f: impl FnOnce(Option<&T>) -> R) -> Result<R> {
+        let mut heap = self.inner.lock().unwrap();
+        Ok(f(heap.deref(r)?))
+    }
+
+    pub fn deref_mut<R>(&self, r: Ref, f: impl FnOnce(Option<&mut T>) -> R) -> Result<R> {
+        let mut heap = self.inner.lock().unwrap();
+        Ok(f(heap.deref_mut(r)?))
+    }
+
+    pub fn set(&self, r: Ref, data: T) -> Result<()> {
+        self.inner.lock().unwrap().set(r, data)
+    }
+
+    pub fn remove(&self, r: Ref) -> Result<()> {
+        self.inner.lock().unwrap().remove(r)
+    }
+
+    pub fn has_value(&self, r: Ref) -> Result<bool> {
+        self.inner.lock().unwrap().has_value(r)
+    }
+
+    pub fn root(&self) -> Ref {
+        self.inner.lock().unwrap().root()
+    }
+
+    pub fn set_root(&self, root: Ref) {
+        self.inner.lock().unwrap().set_root(root)
+    }
+
+    pub fn checkpoint(&self) -> Result<()> {
+        self.inner.lock().unwrap().checkpoint()
+    }
+
+    /// Flushes and closes the underlying [`Heap`]. Fails with [`HeapError::StillShared`]
+    /// if any [`AllocHandle`] made from this heap is still alive, since closing needs
+    /// to reclaim it out of the `Arc` -- drop every handle first.
+    pub fn close(self) -> Result<()> {
+        let mutex = Arc::try_unwrap(self.inner).map_err(|_| HeapError::StillShared)?;
+        mutex
+            .into_inner()
+            .expect("the mutex is never poisoned")
+            .close()
+    }
+}
+
+/// A per-thread allocation handle for a [`SharedHeap`]: reserves a contiguous slab of
+/// `Uuid`s from the shared heap's `next_id` counter up front, refilling it only once
+/// exhausted, so most [`AllocHandle::allocate`] calls never contend on `next_id` --
+/// they just take the shared lock to insert into the cache with an id already in hand.
+pub struct AllocHandle<T> {
+    inner: Arc<Mutex<Heap<T>>>,
+    slab_size: usize,
+    slab_next: Uuid,
+    slab_end: Uuid,
+}
+
+impl<T> AllocHandle<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn allocate(&mut self, initial_data: T) -> Result<Ref> {
+        if self.slab_next >= self.slab_end {
+            self.refill()?;
+        }
+
+        let id = self.slab_next;
+        self.slab_next += 1;
+        self.inner.lock().unwrap().allocate_with_id(id, initial_data)
+    }
+
+    /// Reserves this handle's next slab of `slab_size` ids in one critical section.
+    fn refill(&mut self) -> Result<()> {
+        let start = self.inner.lock().unwrap().reserve_ids(self.slab_size);
+        self.slab_next = start;
+        self.slab_end = start + self.slab_size as Uuid;
+        Ok(())
+    }
+}
+
 impl Ref {
     const fn new(block_id: Uuid, sub_id: Uuid) -> Self {
         Self { block_id, sub_id }
@@ -519,4 +1229,239 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_backup_to() -> Result<()> {
+        let mut db = Heap::<i32>::new_in_memory()?;
+        let r = db.allocate(42)?;
+
+        let dir = tempfile::tempdir().expect("failed to create a tempdir");
+        let backup_path = dir.path().join("backup.db");
+        db.backup_to(&backup_path)?;
+
+        let mut restored = Heap::<i32>::new_from_file(&backup_path)?;
+        assert_eq!(Some(&42), restored.deref(r)?);
+
+        db.checkpoint_wal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_arc_eviction_policy() -> Result<()> {
+        let mut db = HeapBuilder::new()
+            .eviction_policy(EvictionPolicy::Arc)
+            .cache_capacity(2)
+            .in_memory::<i32>()?;
+
+        let r1 = db.allocate(1)?;
+        let r2 = db.allocate(2)?;
+
+        // Touching r1 again promotes it out of t1, so it survives the overflow caused
+        // by allocating a third block while r2 -- touched only once -- gets evicted
+        // instead.
+        assert_eq!(Some(&1), db.deref(r1)?);
+        let r3 = db.allocate(3)?;
+
+        assert!(db.state_of(r1).is_some());
+        assert!(db.state_of(r3).is_some());
+        assert!(db.state_of(r2).is_none());
+
+        // The evicted block was flushed on eviction, not lost.
+        assert_eq!(Some(&2), db.deref(r2)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_clean_heap() -> Result<()> {
+        let mut db = Heap::<i32>::new_in_memory()?;
+        db.allocate(1)?;
+        db.allocate(2)?;
+        db.allocate(3)?;
+
+        assert!(db.check()?.is_clean());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_and_repair_unsorted_and_empty_blocks() -> Result<()> {
+        let mut db = Heap::<i32>::new_in_memory()?;
+
+        // Corrupt the store directly, bypassing Heap entirely, the way a crash
+        // mid-flush would leave things.
+        db.sql.put_refs(10, vec![(5_i64, 1), (4_i64, 2)])?;
+        db.sql.put_refs(11, Vec::<(Uuid, i32)>::new())?;
+
+        let report = db.check()?;
+        assert!(report
+            .inconsistencies
+            .contains(&Inconsistency::UnsortedBlock { block_id: 10 }));
+        assert!(report
+            .inconsistencies
+            .contains(&Inconsistency::EmptyBlock { block_id: 11 }));
+        assert!(report
+            .inconsistencies
+            .iter()
+            .any(|i| matches!(i, Inconsistency::IdAboveNextId { .. })));
+        // check() never writes anything.
+        assert_eq!(2, db.sql.count_refs()?);
+
+        let report = db.repair(false)?;
+        assert_eq!(report.inconsistencies.len(), 3);
+        assert_eq!(1, db.sql.count_refs()?);
+        assert_eq!(12, db.next_id);
+        assert!(db.check()?.is_clean());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_and_repair_dangling_root() -> Result<()> {
+        let mut db = Heap::<i32>::new_in_memory()?;
+        let r = db.allocate(1)?;
+        db.remove(r)?;
+        db.set_root(r);
+
+        let report = db.check()?;
+        assert!(report.inconsistencies.contains(&Inconsistency::DanglingRoot));
+
+        let report = db.repair(false)?;
+        assert!(report.inconsistencies.contains(&Inconsistency::DanglingRoot));
+        assert!(!db.root().is_null());
+
+        let report = db.repair(true)?;
+        assert!(report.inconsistencies.contains(&Inconsistency::DanglingRoot));
+        assert!(db.root().is_null());
+        assert!(db.check()?.is_clean());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_and_restore_round_trip() -> Result<()> {
+        let mut db = HeapBuilder::new()
+            .maximum_block_size(2)
+            .in_memory::<i32>()?;
+
+        let first = db.allocate(1)?;
+        let second = db.allocate_local(first, 2)?;
+        let third = db.allocate(3)?;
+        db.set_root(first);
+
+        let mut dumped = Vec::new();
+        db.dump(&mut dumped)?;
+
+        let mut restored = Heap::<i32>::restore_in_memory(dumped.as_slice())?;
+        assert_eq!(db.next_id, restored.next_id);
+        assert_eq!(db.root(), restored.root());
+        assert_eq!(db.count_refs()?, restored.count_refs()?);
+
+        assert_eq!(Some(&1), restored.deref(first)?);
+        assert_eq!(Some(&2), restored.deref(second)?);
+        assert_eq!(Some(&3), restored.deref(third)?);
+
+        // The original block grouping survives the round-trip instead of being
+        // re-packed by `maximum_block_size`: `first` and `second` still share a block.
+        assert_eq!(first.block_id, second.block_id);
+        assert_eq!(Some(2), restored.block_data_of(first).map(<[_]>::len));
+
+        let mut redumped = Vec::new();
+        restored.dump(&mut redumped)?;
+        assert_eq!(dumped, redumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate_reuses_a_freed_block_id() -> Result<()> {
+        let mut db = Heap::<i32>::new_in_memory()?;
+        let r1 = db.allocate(1)?;
+        db.remove(r1)?;
+        db.flush()?;
+        assert_eq!(vec![r1.block_id], db.free_list);
+
+        let r2 = db.allocate(2)?;
+        assert_eq!(r1.block_id, r2.block_id);
+        assert!(db.free_list.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_merges_under_full_blocks_and_remaps_refs() -> Result<()> {
+        let mut db = HeapBuilder::new()
+            .maximum_block_size(2)
+            .in_memory::<i32>()?;
+
+        let r1 = db.allocate(1)?;
+        let r2 = db.allocate(2)?;
+        let r3 = db.allocate(3)?;
+        db.set_root(r1);
+        assert_ne!(r1.block_id, r2.block_id);
+        assert_ne!(r2.block_id, r3.block_id);
+
+        let mut remapped = Vec::new();
+        let freed = db.compact(|old, new| remapped.push((old, new)))?;
+
+        // r2 merges into r1's block (the first under-full block seen, still with room
+        // for one more entry); r3 doesn't fit alongside them and is left as-is.
+        assert_eq!(vec![r2.block_id], freed);
+        assert_eq!(vec![r2.block_id], db.free_list);
+        assert_eq!(1, remapped.len());
+        assert_eq!(r2, remapped[0].0);
+        let new_r2 = remapped[0].1;
+        assert_eq!(r1.block_id, new_r2.block_id);
+
+        // r1's own ref, and the root pointing at it, are untouched since r1's block
+        // never moved.
+        assert_eq!(r1, db.root());
+
+        assert_eq!(Some(&1), db.deref(r1)?);
+        assert_eq!(Some(&2), db.deref(new_r2)?);
+        assert_eq!(Some(&3), db.deref(r3)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_heap_deref_set_remove() -> Result<()> {
+        let shared = SharedHeap::<i32>::new_in_memory()?;
+        let mut handle = shared.alloc_handle(4);
+
+        let r = handle.allocate(1)?;
+        assert_eq!(Some(1), shared.deref(r, |v| v.copied())?);
+
+        shared.set(r, 2)?;
+        assert_eq!(Some(2), shared.deref(r, |v| v.copied())?);
+        assert!(shared.has_value(r)?);
+
+        shared.remove(r)?;
+        assert!(!shared.has_value(r)?);
+
+        drop(handle);
+        shared.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alloc_handle_refills_its_slab_when_exhausted() -> Result<()> {
+        let shared = SharedHeap::<i32>::new_in_memory()?;
+        let mut handle = shared.alloc_handle(2);
+
+        let r1 = handle.allocate(1)?;
+        let r2 = handle.allocate(2)?;
+        assert_eq!(r1.block_id + 1, r2.block_id);
+
+        // The slab is exhausted, so this allocation draws a fresh one.
+        let r3 = handle.allocate(3)?;
+        assert_eq!(r2.block_id + 1, r3.block_id);
+
+        assert_eq!(Some(1), shared.deref(r1, |v| v.copied())?);
+        assert_eq!(Some(2), shared.deref(r2, |v| v.copied())?);
+        assert_eq!(Some(3), shared.deref(r3, |v| v.copied())?);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,202 @@
+use indexmap::{IndexMap, IndexSet};
+
+use super::{Block, Uuid};
+
+/// An [Adaptive Replacement Cache](https://en.wikipedia.org/wiki/Adaptive_replacement_cache):
+/// two LRU lists of in-cache blocks -- `t1` for blocks seen once, `t2` for blocks seen
+/// more than once -- plus two "ghost" lists, `b1` and `b2`, remembering just the `Uuid`
+/// keys recently evicted from each (no data). `p` is the adaptively-tuned target size
+/// of `t1`: a hit in `b1` grows it, a hit in `b2` shrinks it, so the cache leans toward
+/// whichever of recency or frequency has lately been paying off. All four lists are
+/// ordered LRU-to-MRU by insertion order, with [`Self::modify`] re-inserting a touched
+/// entry at the MRU end.
+pub(super) struct ArcCache<T> {
+    capacity: usize,
+    p: usize,
+    t1: IndexMap<Uuid, Block<T>>,
+    t2: IndexMap<Uuid, Block<T>>,
+    b1: IndexSet<Uuid>,
+    b2: IndexSet<Uuid>,
+}
+
+impl<T> ArcCache<T> {
+    pub(super) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            p: 0,
+            t1: IndexMap::new(),
+            t2: IndexMap::new(),
+            b1: IndexSet::new(),
+            b2: IndexSet::new(),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.t1.len() + self.t2.len()
+    }
+
+    pub(super) fn contains_key(&self, key: &Uuid) -> bool {
+        self.t1.contains_key(key) || self.t2.contains_key(key)
+    }
+
+    pub(super) fn get(&self, key: &Uuid) -> Option<&Block<T>> {
+        self.t1.get(key).or_else(|| self.t2.get(key))
+    }
+
+    pub(super) fn get_mut_unchecked(&mut self, key: &Uuid) -> Option<&mut Block<T>> {
+        if self.t1.contains_key(key) {
+            self.t1.get_mut(key)
+        } else {
+            self.t2.get_mut(key)
+        }
+    }
+
+    /// Inserts a freshly-loaded or freshly-allocated `key`, assumed not already present
+    /// in `t1`/`t2` (callers always check [`Self::contains_key`] first). A `key` still
+    /// remembered in `b1` or `b2` -- a "ghost hit" -- adjusts `p` toward whichever of
+    /// recency/frequency the hit favors and goes straight into `t2`; a genuinely new
+    /// `key` starts in `t1`.
+    pub(super) fn push(&mut self, key: Uuid, val: Block<T>) -> Option<Block<T>> {
+        let promote_to_t2 = if self.b1.contains(&key) {
+            let delta = (self.b2.len() / self.b1.len().max(1)).max(1);
+            self.p = (self.p + delta).min(self.capacity);
+            self.b1.shift_remove(&key);
+            true
+        } else if self.b2.contains(&key) {
+            let delta = (self.b1.len() / self.b2.len().max(1)).max(1);
+            self.p = self.p.saturating_sub(delta);
+            self.b2.shift_remove(&key);
+            true
+        } else {
+            false
+        };
+
+        if promote_to_t2 {
+            self.t2.insert(key, val);
+        } else {
+            self.t1.insert(key, val);
+        }
+        None
+    }
+
+    /// A hit on an already-cached `key`: `t1` entries are promoted to the MRU end of
+    /// `t2` (they've now been seen more than once), `t2` entries just move to their own
+    /// MRU end.
+    pub(super) fn modify(&mut self, key: &Uuid, modifier: impl FnOnce(&mut Block<T>)) -> bool {
+        if let Some(mut block) = self.t1.shift_remove(key) {
+            modifier(&mut block);
+            self.t2.insert(*key, block);
+            true
+        } else if let Some(mut block) = self.t2.shift_remove(key) {
+            modifier(&mut block);
+            self.t2.insert(*key, block);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn retain(&mut self, mut modifier: impl FnMut(&mut Block<T>) -> bool) {
+        self.t1.retain(|_, block| modifier(block));
+        self.t2.retain(|_, block| modifier(block));
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&Uuid, &Block<T>)> {
+        self.t1.iter().chain(self.t2.iter())
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.t1.clear();
+        self.t2.clear();
+        self.b1.clear();
+        self.b2.clear();
+        self.p = 0;
+    }
+
+    /// The REPLACE step: evicts the LRU of `t1` if `|t1| > p`, or if `incoming` (the key
+    /// about to be inserted) is currently a `b2` ghost -- a hit there should shrink `t1`
+    /// even if `p` hasn't caught up yet -- otherwise evicts the LRU of `t2`. The evicted
+    /// key moves onto the matching ghost list, which is then trimmed from its LRU end so
+    /// the list it came from plus its ghost stays within `capacity`.
+    pub(super) fn evict(&mut self, incoming: Uuid) -> Option<(Uuid, Block<T>)> {
+        let evict_t1 = if self.t1.is_empty() {
+            false
+        } else if self.t2.is_empty() {
+            true
+        } else {
+            self.t1.len() > self.p || self.b2.contains(&incoming)
+        };
+
+        if evict_t1 {
+            let (id, block) = self.t1.shift_remove_index(0)?;
+            self.b1.insert(id);
+            while self.t1.len() + self.b1.len() > self.capacity && !self.b1.is_empty() {
+                self.b1.shift_remove_index(0);
+            }
+            Some((id, block))
+        } else {
+            let (id, block) = self.t2.shift_remove_index(0)?;
+            self.b2.insert(id);
+            while self.t2.len() + self.b2.len() > self.capacity && !self.b2.is_empty() {
+                self.b2.shift_remove_index(0);
+            }
+            Some((id, block))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block(n: i32) -> Block<i32> {
+        Block::new_dirty(0, n, 0)
+    }
+
+    #[test]
+    fn fresh_key_starts_in_t1_and_is_evicted_lru_first() {
+        let mut cache = ArcCache::with_capacity(2);
+        cache.push(1, block(1));
+        cache.push(2, block(2));
+        assert_eq!(2, cache.len());
+
+        let (evicted, _) = cache.evict(3).expect("cache is full");
+        assert_eq!(1, evicted);
+        assert_eq!(1, cache.len());
+    }
+
+    #[test]
+    fn a_second_touch_promotes_to_t2_and_protects_from_t1_eviction() {
+        let mut cache = ArcCache::with_capacity(2);
+        cache.push(1, block(1));
+        cache.push(2, block(2));
+        assert!(cache.modify(&1, |_| ()));
+
+        // 1 is now in t2, so the t1-only 2 is the one evicted.
+        let (evicted, _) = cache.evict(3).expect("cache is full");
+        assert_eq!(2, evicted);
+    }
+
+    #[test]
+    fn a_ghost_hit_in_b1_grows_p_and_reinserts_into_t2() {
+        let mut cache = ArcCache::with_capacity(1);
+        cache.push(1, block(1));
+        let (evicted, _) = cache.evict(2).expect("cache is full");
+        assert_eq!(1, evicted);
+        assert_eq!(0, cache.p);
+
+        cache.push(1, block(1));
+        assert_eq!(1, cache.p);
+        assert!(cache.t1.is_empty());
+        assert!(cache.t2.contains_key(&1));
+    }
+
+    #[test]
+    fn evicted_keys_leave_no_data_behind_in_the_ghost_lists() {
+        let mut cache = ArcCache::<i32>::with_capacity(1);
+        cache.push(1, block(1));
+        cache.evict(2);
+        assert_eq!(0, cache.len());
+        assert!(cache.b1.contains(&1));
+    }
+}
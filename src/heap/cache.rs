@@ -0,0 +1,91 @@
+use super::{arc_cache::ArcCache, priority_queue::PriorityQueue, Block, EvictionPolicy, Uuid};
+
+/// The cache backing [`super::Heap`], dispatching to whichever [`EvictionPolicy`] it was
+/// built with.
+pub(super) enum Cache<T> {
+    Lfu(PriorityQueue<Uuid, Block<T>>),
+    Arc(ArcCache<T>),
+}
+
+impl<T> Cache<T> {
+    pub(super) fn with_capacity(policy: EvictionPolicy, capacity: usize) -> Self {
+        match policy {
+            EvictionPolicy::Lfu => Cache::Lfu(PriorityQueue::with_capacity(capacity)),
+            EvictionPolicy::Arc => Cache::Arc(ArcCache::with_capacity(capacity)),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Cache::Lfu(c) => c.len(),
+            Cache::Arc(c) => c.len(),
+        }
+    }
+
+    pub(super) fn contains_key(&self, key: &Uuid) -> bool {
+        match self {
+            Cache::Lfu(c) => c.contains_key(key),
+            Cache::Arc(c) => c.contains_key(key),
+        }
+    }
+
+    pub(super) fn get(&self, key: &Uuid) -> Option<&Block<T>> {
+        match self {
+            Cache::Lfu(c) => c.get(key),
+            Cache::Arc(c) => c.get(key),
+        }
+    }
+
+    pub(super) fn get_mut_unchecked(&mut self, key: &Uuid) -> Option<&mut Block<T>> {
+        match self {
+            Cache::Lfu(c) => c.get_mut_unchecked(key),
+            Cache::Arc(c) => c.get_mut_unchecked(key),
+        }
+    }
+
+    pub(super) fn push(&mut self, key: Uuid, val: Block<T>) -> Option<Block<T>> {
+        match self {
+            Cache::Lfu(c) => c.push(key, val),
+            Cache::Arc(c) => c.push(key, val),
+        }
+    }
+
+    pub(super) fn modify(&mut self, key: &Uuid, modifier: impl FnOnce(&mut Block<T>)) -> bool {
+        match self {
+            Cache::Lfu(c) => c.modify(key, modifier),
+            Cache::Arc(c) => c.modify(key, modifier),
+        }
+    }
+
+    pub(super) fn retain(&mut self, modifier: impl FnMut(&mut Block<T>) -> bool) {
+        match self {
+            Cache::Lfu(c) => c.retain(modifier),
+            Cache::Arc(c) => c.retain(modifier),
+        }
+    }
+
+    pub(super) fn iter(&self) -> Box<dyn Iterator<Item = (&Uuid, &Block<T>)> + '_> {
+        match self {
+            Cache::Lfu(c) => Box::new(c.iter()),
+            Cache::Arc(c) => Box::new(c.iter()),
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        match self {
+            Cache::Lfu(c) => c.clear(),
+            Cache::Arc(c) => c.clear(),
+        }
+    }
+
+    /// Evicts one block to make room, returning it so the caller can flush it if dirty.
+    /// `incoming` is the key about to be inserted, needed by [`ArcCache::evict`]'s
+    /// `t1`-vs-`t2` rule; the [`EvictionPolicy::Lfu`] policy ignores it and just pops the
+    /// block with the smallest `access_count`, as before.
+    pub(super) fn evict(&mut self, incoming: Uuid) -> Option<(Uuid, Block<T>)> {
+        match self {
+            Cache::Lfu(c) => c.pop(),
+            Cache::Arc(c) => c.evict(incoming),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use super::sql::Sql;
+use super::{Result, FORMAT_VERSION, FORMAT_VERSION_KEY};
+
+/// A single step that upgrades a database from the version it's indexed by (0-based) to
+/// the next one. Step `i` is run on a database at version `i` and leaves it at version
+/// `i + 1`. There is nothing to migrate yet since [`FORMAT_VERSION`] is still `1`; add
+/// steps here as the format changes and bump [`FORMAT_VERSION`] to match.
+const STEPS: &[fn(&Sql) -> Result<()>] = &[];
+
+/// Replays every registered [`STEPS`] entry needed to bring `sql` from its current
+/// `format_version` meta entry up to [`FORMAT_VERSION`], persisting the new version
+/// after each step so an interrupted upgrade can be resumed instead of restarted.
+pub(super) fn run(sql: &Sql) -> Result<()> {
+    let mut version = sql
+        .get_meta::<u32>(FORMAT_VERSION_KEY)?
+        .unwrap_or(FORMAT_VERSION);
+
+    while let Some(step) = STEPS.get(version as usize) {
+        step(sql)?;
+        version += 1;
+        sql.put_meta(FORMAT_VERSION_KEY, version)?;
+    }
+
+    Ok(())
+}
@@ -2,12 +2,35 @@ use indexmap::IndexMap;
 
 pub struct PriorityQueue<K, T> {
     inner: IndexMap<K, T>,
+    capacity: Option<usize>,
+}
+
+/// The outcome of a [`PriorityQueue::push_bounded`] call.
+pub enum BoundedPush<T> {
+    /// The queue was below capacity, so `val` was inserted outright.
+    Inserted,
+    /// The queue was already at capacity and `val` was no better than the current root,
+    /// so it's handed back unchanged instead of being inserted.
+    Rejected(T),
+    /// The queue was already at capacity and `val` was better than the current root, so
+    /// the root was popped to make room and is handed back.
+    Evicted(T),
 }
 
 impl<K, T> PriorityQueue<K, T> {
     pub fn new() -> Self {
         Self {
             inner: IndexMap::new(),
+            capacity: None,
+        }
+    }
+
+    /// Preallocates room for `capacity` entries and, for [`Self::push_bounded`], pins
+    /// `capacity` as the maximum number of entries the queue will ever hold.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: IndexMap::with_capacity(capacity),
+            capacity: Some(capacity),
         }
     }
 
@@ -19,6 +42,14 @@ impl<K, T> PriorityQueue<K, T> {
         self.inner.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
     pub fn iter(&self) -> indexmap::map::Values<'_, K, T> {
         self.inner.values()
     }
@@ -38,6 +69,59 @@ where
         old_val
     }
 
+    /// Like [`Self::push`], but keeps the queue from growing past the capacity given to
+    /// [`Self::with_capacity`] (a queue built with [`Self::new`] has no bound and always
+    /// inserts). Below capacity, `val` is inserted outright. At capacity, `val` is
+    /// compared against the current root: since the root is the *smallest* entry, "better
+    /// than the root" means greater, so `val` either evicts the root to make room or, if
+    /// it's no better, is rejected and handed straight back. Keeping the K *smallest*
+    /// values instead is a matter of pushing `std::cmp::Reverse(val)`.
+    pub fn push_bounded(&mut self, key: K, val: T) -> BoundedPush<T> {
+        let Some(capacity) = self.capacity else {
+            self.push(key, val);
+            return BoundedPush::Inserted;
+        };
+
+        if capacity == 0 {
+            return BoundedPush::Rejected(val);
+        }
+
+        if self.inner.len() < capacity {
+            self.push(key, val);
+            return BoundedPush::Inserted;
+        }
+
+        let (_, root) = self.peek().expect("capacity > 0 and at capacity, so non-empty");
+        if val <= *root {
+            return BoundedPush::Rejected(val);
+        }
+
+        let (_, evicted) = self.pop().expect("just peeked, so non-empty");
+        self.push(key, val);
+        BoundedPush::Evicted(evicted)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: std::borrow::Borrow<K>,
+    {
+        self.inner.contains_key(key.borrow())
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        Q: std::borrow::Borrow<K>,
+    {
+        self.inner.get(key.borrow())
+    }
+
+    pub fn get_mut_unchecked<Q>(&mut self, key: &Q) -> Option<&mut T>
+    where
+        Q: std::borrow::Borrow<K>,
+    {
+        self.inner.get_mut(key.borrow())
+    }
+
     pub fn modify<F, Q>(&mut self, key: &Q, modifier: F) -> bool
     where
         F: FnOnce(&mut T),
@@ -90,20 +174,35 @@ where
     }
 
     fn bubble_up(&mut self, i: usize) {
-        match parent_index(i) {
-            Some(p) if self.inner[i] < self.inner[p] => {
-                self.inner.swap_indices(p, i);
-                self.bubble_up(p);
-            }
-            _ => (),
+        let Some(p) = parent_index(i) else { return };
+
+        #[cfg(debug_assertions)]
+        self.assert_consistent_ord(i, p);
+
+        if self.inner[i] < self.inner[p] {
+            self.inner.swap_indices(p, i);
+            self.bubble_up(p);
         }
     }
 
     fn bubble_down(&mut self, i: usize) {
         let left = left_child_index(i).expect("will probably not be that big");
         let right = right_child_index(i).expect("will probably not be that big");
-        let parent = &self.inner[i];
 
+        #[cfg(debug_assertions)]
+        {
+            if self.inner.get_index(left).is_some() {
+                self.assert_consistent_ord(i, left);
+            }
+            if self.inner.get_index(right).is_some() {
+                self.assert_consistent_ord(i, right);
+            }
+            if self.inner.get_index(left).is_some() && self.inner.get_index(right).is_some() {
+                self.assert_consistent_ord(left, right);
+            }
+        }
+
+        let parent = &self.inner[i];
         let swap_with = match (self.inner.get_index(left), self.inner.get_index(right)) {
             (Some((_, l)), Some((_, r))) if l <= r && l < parent => Some(left),
             (Some((_, l)), Some((_, r))) if l > r && r < parent => Some(right),
@@ -117,6 +216,31 @@ where
         }
     }
 
+    /// Checks that comparing the elements at `i` and `j` agrees in both directions
+    /// (`Less` against `Greater`, or `Equal` against `Equal`), panicking with the
+    /// offending indices otherwise. Only compiled in under `debug_assertions`, since it's
+    /// purely a diagnostic: a `T: Ord` whose comparisons aren't actually antisymmetric --
+    /// e.g. a hand-rolled `Ord` over floating-point distances where a `NaN` slipped
+    /// through -- would otherwise just silently corrupt the heap or send
+    /// `bubble_up`/`bubble_down` into an inconsistent state, with nothing pointing at
+    /// which comparison was the lie.
+    #[cfg(debug_assertions)]
+    fn assert_consistent_ord(&self, i: usize, j: usize) {
+        let forward = self.inner[i].cmp(&self.inner[j]);
+        let backward = self.inner[j].cmp(&self.inner[i]);
+        let consistent = matches!(
+            (forward, backward),
+            (std::cmp::Ordering::Less, std::cmp::Ordering::Greater)
+                | (std::cmp::Ordering::Greater, std::cmp::Ordering::Less)
+                | (std::cmp::Ordering::Equal, std::cmp::Ordering::Equal)
+        );
+        assert!(
+            consistent,
+            "inconsistent Ord implementation: index {i} compares as {forward:?} against \
+             index {j}, but index {j} compares as {backward:?} against index {i}"
+        );
+    }
+
     fn bubble_all(&mut self) {
         if self.inner.is_empty() {
             return;
@@ -131,6 +255,74 @@ where
     }
 }
 
+/// A queued iterator's next item, paired with the iterator itself. Ordered solely by
+/// `item`, so [`PriorityQueue::merge_sorted`]'s internal queue can hold iterators of any
+/// type without requiring the iterator itself to be `Ord`.
+struct Head<I: Iterator> {
+    item: I::Item,
+    iter: I,
+}
+
+impl<I: Iterator> PartialEq for Head<I>
+where
+    I::Item: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.item == other.item
+    }
+}
+
+impl<I: Iterator> Eq for Head<I> where I::Item: Eq {}
+
+impl<I: Iterator> PartialOrd for Head<I>
+where
+    I::Item: Ord,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I: Iterator> Ord for Head<I>
+where
+    I::Item: Ord,
+{
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.item.cmp(&other.item)
+    }
+}
+
+impl PriorityQueue<usize, ()> {
+    /// Merges `iters`, each already sorted ascending, into one globally-sorted stream,
+    /// the way a merge-join works, without collecting any of them first. Keys the queue
+    /// by each iterator's index and stores `(head_item, iterator)` as the ordered
+    /// value: repeatedly `pop()`s the smallest head, yields it, advances that iterator,
+    /// and `push()`es its next item back under the same index, dropping the index once
+    /// its iterator runs dry. Useful for combining several already-sorted candidate
+    /// lists into one globally-sorted stream without collecting them all into one
+    /// `Vec` first just to sort it.
+    pub fn merge_sorted<I>(iters: impl IntoIterator<Item = I>) -> impl Iterator<Item = I::Item>
+    where
+        I: Iterator,
+        I::Item: Ord,
+    {
+        let mut queue = PriorityQueue::<usize, Head<I>>::new();
+        for (i, mut iter) in iters.into_iter().enumerate() {
+            if let Some(item) = iter.next() {
+                queue.push(i, Head { item, iter });
+            }
+        }
+
+        std::iter::from_fn(move || {
+            let (i, Head { item, mut iter }) = queue.pop()?;
+            if let Some(next_item) = iter.next() {
+                queue.push(i, Head { item: next_item, iter });
+            }
+            Some(item)
+        })
+    }
+}
+
 fn parent_index(i: usize) -> Option<usize> {
     (i > 0).then(|| (i - 1) / 2)
 }
@@ -275,4 +467,108 @@ mod test {
         let elements = que.pop_all_cloned();
         assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8, 9i32], elements);
     }
+
+    #[test]
+    fn merge_sorted_interleaves_several_sorted_iterators() {
+        let a = vec![1, 4, 9];
+        let b = vec![2, 3, 10];
+        let c: Vec<i32> = vec![];
+        let d = vec![5];
+
+        let merged: Vec<i32> =
+            PriorityQueue::merge_sorted([a.into_iter(), b.into_iter(), c.into_iter(), d.into_iter()])
+                .collect();
+
+        assert_eq!(vec![1, 2, 3, 4, 5, 9, 10], merged);
+    }
+
+    #[test]
+    fn merge_sorted_of_nothing_is_empty() {
+        let merged: Vec<i32> = PriorityQueue::merge_sorted(Vec::<std::vec::IntoIter<i32>>::new()).collect();
+        assert!(merged.is_empty());
+    }
+
+    #[test]
+    fn push_bounded_fills_up_to_capacity() {
+        let mut que = PriorityQueue::<usize, i32>::with_capacity(3);
+        assert!(matches!(que.push_bounded(0, 1), BoundedPush::Inserted));
+        assert!(matches!(que.push_bounded(1, 2), BoundedPush::Inserted));
+        assert!(matches!(que.push_bounded(2, 3), BoundedPush::Inserted));
+        assert_eq!(3, que.len());
+    }
+
+    #[test]
+    fn push_bounded_rejects_a_value_not_better_than_the_root() {
+        let mut que = PriorityQueue::<usize, i32>::with_capacity(2);
+        que.push_bounded(0, 5);
+        que.push_bounded(1, 9);
+
+        match que.push_bounded(2, 5) {
+            BoundedPush::Rejected(val) => assert_eq!(5, val),
+            _ => panic!("expected a rejection"),
+        }
+        assert_eq!(vec![5, 9], que.pop_all());
+    }
+
+    #[test]
+    fn push_bounded_evicts_the_root_for_a_better_value() {
+        let mut que = PriorityQueue::<usize, i32>::with_capacity(2);
+        que.push_bounded(0, 5);
+        que.push_bounded(1, 9);
+
+        match que.push_bounded(2, 7) {
+            BoundedPush::Evicted(val) => assert_eq!(5, val),
+            _ => panic!("expected an eviction"),
+        }
+        assert_eq!(vec![7, 9], que.pop_all());
+    }
+
+    #[test]
+    fn push_bounded_respects_reverse_for_smallest_k() {
+        let mut que = PriorityQueue::<usize, std::cmp::Reverse<i32>>::with_capacity(2);
+        for (i, val) in [5, 1, 9, 2].into_iter().enumerate() {
+            que.push_bounded(i, std::cmp::Reverse(val));
+        }
+
+        let smallest: Vec<i32> = que
+            .pop_all()
+            .into_iter()
+            .map(|std::cmp::Reverse(val)| val)
+            .collect();
+        assert_eq!(vec![2, 1], smallest);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "inconsistent Ord")]
+    fn bubble_panics_on_an_inconsistent_ord_impl() {
+        #[derive(PartialEq, Eq)]
+        struct AlwaysLess(i32);
+
+        impl PartialOrd for AlwaysLess {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for AlwaysLess {
+            fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
+                std::cmp::Ordering::Less
+            }
+        }
+
+        let mut que = PriorityQueue::<usize, AlwaysLess>::new();
+        que.push(0, AlwaysLess(1));
+        que.push(1, AlwaysLess(2));
+    }
+
+    #[test]
+    fn push_bounded_with_zero_capacity_always_rejects() {
+        let mut que = PriorityQueue::<usize, i32>::with_capacity(0);
+        match que.push_bounded(0, 1) {
+            BoundedPush::Rejected(val) => assert_eq!(1, val),
+            _ => panic!("expected a rejection"),
+        }
+        assert!(que.is_empty());
+    }
 }
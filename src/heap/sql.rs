@@ -1,14 +1,63 @@
-use std::{io::Write, path::Path, sync::OnceLock};
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::OnceLock,
+};
 
 // TODO: https://github.com/meilisearch/heed ??
 // TODO: https://github.com/seladb/pickledb-rs ??
 
-use super::Result;
+use super::{Compression, HeapError, Result, FORMAT_VERSION, FORMAT_VERSION_KEY};
 use rusqlite::{blob::ZeroBlob, Connection, DatabaseName, OptionalExtension, ToSql};
 use serde::{de::DeserializeOwned, Serialize};
 
+/// zstd's usual `1..=22`, a moderate middle ground between ratio and speed for an
+/// index whose blobs are written far more often than read.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compresses `data` with `codec`, for [`Sql::put_kv`]. The codec tag byte that lets
+/// [`decompress`] reverse this is stored alongside the compressed bytes, not by this
+/// function.
+fn compress(codec: Compression, data: &[u8]) -> Result<Vec<u8>> {
+    Ok(match codec {
+        Compression::None => data.to_vec(),
+        Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL)?,
+        Compression::Zlib => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+    })
+}
+
+/// The inverse of [`compress`], for [`Sql::get_kv`]. Unlike the write side, which is
+/// fixed by [`Sql::write_codec`], `codec` here always comes from the tag byte actually
+/// stored on the blob, so a blob stays readable after the default codec changes.
+fn decompress(codec: Compression, data: &[u8]) -> Result<Vec<u8>> {
+    Ok(match codec {
+        Compression::None => data.to_vec(),
+        Compression::Zstd => zstd::decode_all(data)?,
+        Compression::Zlib => {
+            let mut out = Vec::new();
+            flate2::read::ZlibDecoder::new(data).read_to_end(&mut out)?;
+            out
+        }
+    })
+}
+
+fn codec_from_tag(tag: u8) -> Result<Compression> {
+    match tag {
+        0 => Ok(Compression::None),
+        1 => Ok(Compression::Zstd),
+        2 => Ok(Compression::Zlib),
+        other => Err(HeapError::UnknownCodec(other)),
+    }
+}
+
 pub(super) struct Sql {
     db: Connection,
+    write_codec: Compression,
 }
 
 #[derive(Clone, Copy)]
@@ -48,10 +97,15 @@ fn count_query(table: Table) -> String {
     format!("SELECT COUNT(*) FROM {}", table.str())
 }
 
+fn keys_query(table: Table) -> String {
+    format!("SELECT key FROM {} ORDER BY rowid", table.str())
+}
+
 impl Sql {
     pub(super) fn new_in_memory() -> Result<Self> {
         let myself = Self {
             db: Connection::open_in_memory()?,
+            write_codec: Compression::default(),
         };
         myself.init_db()?;
         Ok(myself)
@@ -60,11 +114,19 @@ impl Sql {
     pub(super) fn new_from_file(file: impl AsRef<Path>) -> Result<Self> {
         let myself = Self {
             db: Connection::open(file)?,
+            write_codec: Compression::default(),
         };
         myself.init_db()?;
         Ok(myself)
     }
 
+    /// Overrides which codec blobs written from here on are compressed with.
+    /// Previously-written blobs are unaffected, see [`decompress`].
+    pub(super) fn with_compression(mut self, compression: Compression) -> Self {
+        self.write_codec = compression;
+        self
+    }
+
     fn init_db(&self) -> Result<()> {
         let refs = Table::Refs.str();
         let meta = Table::Meta.str();
@@ -80,7 +142,16 @@ impl Sql {
              CREATE TABLE IF NOT EXISTS {refs}(key INTEGER PRIMARY KEY, value BLOB NOT NULL) STRICT;
              CREATE TABLE IF NOT EXISTS {meta}(key TEXT PRIMARY KEY, value BLOB NOT NULL) STRICT;"
             );
-        Ok(self.db.execute_batch(&query)?)
+        self.db.execute_batch(&query)?;
+
+        // NOTE: only stamp brand new databases here. An existing, lower value means an
+        // older database that `Heap::new` should refuse until `Heap::upgrade` is run,
+        // and must not be silently bumped on open.
+        if self.get_meta::<u32>(FORMAT_VERSION_KEY)?.is_none() {
+            self.put_meta(FORMAT_VERSION_KEY, FORMAT_VERSION)?;
+        }
+
+        Ok(())
     }
 
     fn put_kv<K, V>(&self, put_query: &str, table: Table, key: K, value: V) -> Result<()>
@@ -89,7 +160,16 @@ impl Sql {
         K: ToSql,
     {
         let value = bincode::serialize(&value)?;
-        let len: i32 = value
+        let compressed = compress(self.write_codec, &value)?;
+
+        // Every blob is prefixed by the one-byte tag of the codec it was compressed
+        // with, so `get_kv` can decompress it correctly no matter what `write_codec`
+        // is set to by the time it's read back.
+        let mut framed = Vec::with_capacity(1 + compressed.len());
+        framed.push(self.write_codec as u8);
+        framed.extend_from_slice(&compressed);
+
+        let len: i32 = framed
             .len()
             .try_into()
             .expect("A blob should not be this big anyway");
@@ -101,8 +181,8 @@ impl Sql {
             self.db
                 .blob_open(DatabaseName::Main, table.str(), "value", rowid, false)?;
 
-        let written = blob.write(&value)?;
-        assert_eq!(written, value.len());
+        let written = blob.write(&framed)?;
+        assert_eq!(written, framed.len());
 
         Ok(())
     }
@@ -120,10 +200,19 @@ impl Sql {
             return Ok(None);
         };
 
-        let blob =
+        let mut blob =
             self.db
                 .blob_open(DatabaseName::Main, table.str(), "value", rowid, true)?;
-        Ok(Some(bincode::deserialize_from::<_, V>(blob)?))
+
+        let mut tag = [0u8; 1];
+        blob.read_exact(&mut tag)?;
+        let codec = codec_from_tag(tag[0])?;
+
+        let mut rest = Vec::new();
+        blob.read_to_end(&mut rest)?;
+        let value = decompress(codec, &rest)?;
+
+        Ok(Some(bincode::deserialize(&value)?))
     }
 
     fn remove_kv<K>(&self, remove_query: &str, key: K) -> Result<bool>
@@ -206,6 +295,42 @@ impl Sql {
         Ok(count)
     }
 
+    /// A snapshot, streaming walk of every `(key, value)` entry in `refs`, so a
+    /// compaction or rebuild pass can enumerate the whole table without holding every
+    /// decoded value in memory at once. Opened as a `SAVEPOINT` rather than a fresh
+    /// `BEGIN`, since the connection already has a transaction open for the lifetime
+    /// of a [`super::Heap`] and SQLite doesn't allow nested transactions; the
+    /// savepoint still gives the cursor a consistent view under the WAL journal mode
+    /// [`Self::init_db`] sets up, the same as a real nested `BEGIN` would.
+    pub(super) fn iter_refs<V>(&self) -> Result<RefsIter<'_, V>>
+    where
+        V: DeserializeOwned,
+    {
+        self.db.execute("SAVEPOINT iter_refs", ())?;
+        match self.collect_ref_keys() {
+            Ok(keys) => Ok(RefsIter {
+                sql: self,
+                keys: keys.into_iter(),
+                marker: std::marker::PhantomData,
+            }),
+            Err(e) => {
+                let _ = self.db.execute("RELEASE iter_refs", ());
+                Err(e)
+            }
+        }
+    }
+
+    fn collect_ref_keys(&self) -> Result<Vec<i64>> {
+        static KEYS_QUERY: OnceLock<String> = OnceLock::new();
+        let mut stmt = self
+            .db
+            .prepare_cached(KEYS_QUERY.get_or_init(|| keys_query(Table::Refs)))?;
+        let keys = stmt
+            .query_map((), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(keys)
+    }
+
     pub(super) fn begin(&self) -> Result<()> {
         self.db.execute("BEGIN", ())?;
         Ok(())
@@ -224,6 +349,62 @@ impl Sql {
     pub(super) fn close(self) -> Result<()> {
         self.db.close().map_err(|(_, e)| e.into())
     }
+
+    /// Force-flushes the write-ahead log into the main database file via
+    /// `PRAGMA wal_checkpoint(TRUNCATE)`, so durable data doesn't sit in the `-wal`
+    /// file waiting for it to grow large enough or for the connection to close. Safe
+    /// to call with an open transaction; SQLite just checkpoints everything already
+    /// committed.
+    pub(super) fn wal_checkpoint(&self) -> Result<()> {
+        self.db
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+    /// Copies a consistent snapshot of the live database to `path` via SQLite's online
+    /// backup API, without blocking concurrent readers/writers on `self`.
+    pub(super) fn backup_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut dst = Connection::open(path)?;
+        let backup = rusqlite::backup::Backup::new(&self.db, &mut dst)?;
+        backup.run_to_completion(100, std::time::Duration::from_millis(250), None)?;
+        Ok(())
+    }
+}
+
+/// The [`Iterator`] returned by [`Sql::iter_refs`]. Releases the savepoint it opened
+/// once exhausted or dropped early, whichever comes first.
+pub(super) struct RefsIter<'a, V> {
+    sql: &'a Sql,
+    keys: std::vec::IntoIter<i64>,
+    marker: std::marker::PhantomData<V>,
+}
+
+impl<V> Drop for RefsIter<'_, V> {
+    fn drop(&mut self) {
+        // Best-effort: there's nothing useful to do with a failure here, and a genuine
+        // problem with the connection will surface on the next real query anyway.
+        let _ = self.sql.db.execute("RELEASE iter_refs", ());
+    }
+}
+
+impl<V> Iterator for RefsIter<'_, V>
+where
+    V: DeserializeOwned,
+{
+    type Item = Result<(i64, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = self.keys.next()?;
+            match self.sql.get_refs::<V>(key) {
+                Ok(Some(value)) => return Some(Ok((key, value))),
+                // Shouldn't happen under the snapshot `iter_refs` opens, but skip
+                // rather than lie about a key that no longer resolves to anything.
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -276,4 +457,25 @@ mod test {
         assert!(sql.get_meta::<()>("asd")?.is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_iter_refs() -> Result<()> {
+        let sql = Sql::new_in_memory()?;
+        sql.put_refs(1, "omg")?;
+        sql.put_refs(5, "asd")?;
+        sql.put_refs(2, "qwe")?;
+
+        let seen: Vec<(i64, String)> = sql
+            .iter_refs::<String>()?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![(1, "omg".to_string()), (2, "qwe".to_string()), (5, "asd".to_string())],
+            seen
+        );
+
+        // the savepoint was released, so normal writes still work afterwards
+        sql.put_refs(9, "jkl")?;
+        assert_eq!(4, sql.count_refs()?);
+        Ok(())
+    }
 }
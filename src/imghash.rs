@@ -1,31 +1,42 @@
 use std::sync::OnceLock;
 
+use color_eyre::eyre;
 use image::{GenericImageView, Pixel, SubImage};
 
 use self::hamming::{Distance, Hamming};
+pub use self::hashalg::{HashAlg, HashConfig, HashWidth, ResizeFilter};
 
+mod dct;
+pub mod cache;
 pub mod hamming;
+pub mod hashalg;
+pub mod preproc;
+pub mod similarity;
 
 pub const DEFAULT_SIMILARITY_THRESHOLD: Distance = 23;
 
 static HASHER: OnceLock<Hasher> = OnceLock::new();
 
 pub struct Hasher {
+    config: HashConfig,
     hasher: image_hasher::Hasher<[u8; Hamming::BYTES]>,
 }
 
 impl Hasher {
-    pub fn new() -> Self {
+    pub fn new(config: HashConfig) -> Self {
+        let (width, height) = config.hash_size();
         Self {
             hasher: image_hasher::HasherConfig::with_bytes_type::<[u8; Hamming::BYTES]>()
                 // NOTE: DoubleGraident is weird and doesn't caclulate the maximum used
                 // bits correctly. The actual size seems to be: (wh+w+h)/2
                 // https://github.com/abonander/img_hash/issues/46
                 // struct NoMaxBits<T>(T); // Use some wrapper to ignore max_bits
-                .hash_alg(image_hasher::HashAlg::VertGradient)
-                .hash_size(16, 8)
+                .hash_alg(config.image_hasher_alg())
+                .hash_size(width, height)
+                .resize_filter(config.resize_filter())
                 .preproc_dct()
                 .to_hasher(),
+            config,
         }
     }
 
@@ -38,11 +49,48 @@ impl Hasher {
     }
 }
 
+/// Sets the algorithm and bit width used by the global [`hash`]/[`hash_sub`] functions
+/// and by the tag stamped into new databases' source identifiers. Must be called, if at
+/// all, before the first call to `hash`/`hash_sub`/opening a [`crate::bktree::mmap::bktree::BKTree`];
+/// akin to [`crate::bin_common::init::init_eyre`], it can only run once per process.
+pub fn init(config: HashConfig) -> eyre::Result<()> {
+    HASHER
+        .set(Hasher::new(config))
+        .map_err(|_| eyre::eyre!("imghash was already initialized"))
+}
+
+fn hasher() -> &'static Hasher {
+    HASHER.get_or_init(|| Hasher::new(HashConfig::default()))
+}
+
+/// The tag of whichever [`HashConfig`] is currently active (the one passed to [`init`],
+/// or [`HashConfig::default`] if `init` was never called).
+pub fn current_tag() -> String {
+    hasher().config.tag()
+}
+
+/// The [`HashWidth`] of whichever [`HashConfig`] is currently active, same rules as
+/// [`current_tag`]. Used by [`similarity::SimilarityPreset`] to translate a named
+/// sensitivity tier into a concrete [`Distance`] for the width actually in use.
+pub fn current_width() -> HashWidth {
+    hasher().config.width
+}
+
 pub fn hash<I>(img: &I) -> Hamming
 where
     I: image_hasher::Image,
 {
-    HASHER.get_or_init(|| Hasher::new()).hash(img)
+    hasher().hash(img)
+}
+
+/// [`hash`], but wraps the result in a [`hamming::Tagged`] stamped with [`current_tag`],
+/// so the caller can later compare it against another hash without risking a
+/// meaningless distance if the two were produced under different [`HashConfig`]s.
+pub fn hash_tagged<I>(img: &I) -> hamming::Tagged
+where
+    I: image_hasher::Image,
+{
+    hamming::Tagged::new(hash(img), current_tag())
 }
 
 pub fn hash_sub<I, P>(img: &SubImage<&I>) -> Hamming
@@ -58,6 +106,31 @@ where
     }
 }
 
+/// A DCT-based perceptual hash ("pHash"). Unlike [`hash`] this doesn't go through the
+/// global [`HASHER`]: it's always the same fixed 32x32/8x8 DCT, not configurable via
+/// [`HashConfig`].
+pub fn hash_dct<I, P>(img: &I) -> Hamming
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    dct::hash_gray(&image::imageops::colorops::grayscale(img))
+}
+
+/// [`hash_dct`], but for a cropped [`SubImage`], mirroring [`hash_sub`].
+pub fn hash_sub_dct<I, P>(img: &SubImage<&I>) -> Hamming
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    if img.bounds() == img.inner().bounds() {
+        hash_dct(img.inner())
+    } else {
+        // TODO: do this without copying the whole image
+        hash_dct(&img.to_image())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::imgutils::{construct_gray, filled, BLACK, WHITE};
@@ -66,7 +139,7 @@ mod test {
 
     #[test]
     fn simple_hash() {
-        let hasher = Hasher::new();
+        let hasher = Hasher::new(HashConfig::default());
         let black = hasher.hash(&filled(300, 300, 0, 0, 0));
         let white = hasher.hash(&filled(300, 300, 255, 255, 255));
         println!("black: {}", black);
@@ -80,7 +153,7 @@ mod test {
 
     #[test]
     fn empty() {
-        let hash = Hasher::new().hash(&filled(0, 0, 0, 0, 0));
+        let hash = Hasher::new(HashConfig::default()).hash(&filled(0, 0, 0, 0, 0));
         println!("empty: {hash}");
 
         let gray = filled(5, 5, 128, 128, 128);
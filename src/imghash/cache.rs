@@ -0,0 +1,389 @@
+//! A persistent cache of hashes computed by [`crate::frame_extractor::FrameExtractor`] +
+//! [`crate::imghash::hash`], so that repeatedly scanning the same video library doesn't
+//! recompute every frame's hash from scratch. Entries are keyed on the source file's
+//! identity plus the extraction parameters used to land on a particular frame, and are
+//! invalidated the moment the source file's size or modification time no longer match
+//! what was cached.
+//!
+//! This intentionally doesn't reuse [`crate::heap::Heap`]/`Sql`: those are built around
+//! versioned, block-addressed storage for the BK-tree itself, while a hash cache is just
+//! a flat key-value lookup that's fine to lose and rebuild from scratch, so it gets its
+//! own small sqlite-backed store instead.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::imghash::{self, hamming::Hamming};
+use crate::utils::fsutils;
+
+pub type Result<T> = std::result::Result<T, CacheError>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CacheError {
+    #[error("SQlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// Everything needed to notice that `path` was changed since its hash was cached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    mtime: SystemTime,
+    size: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        Ok(Self {
+            mtime: meta.modified()?,
+            size: meta.len(),
+        })
+    }
+}
+
+/// `(file identity, offset, step, extractor params)`: everything that determines which
+/// frame a hash was computed from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheKey {
+    path: PathBuf,
+    offset: Duration,
+    step: Option<Duration>,
+    extractor_tag: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    stamp: FileStamp,
+    hash: Hamming,
+}
+
+/// A flat `(path, offset, step, extractor params) -> hash` lookup backed by its own
+/// sqlite database, separate from the one a [`crate::bktree::BKTree`] might be using.
+pub struct HashCache {
+    db: Connection,
+}
+
+impl HashCache {
+    pub fn open(file: impl AsRef<Path>) -> Result<Self> {
+        let db = Connection::open(file)?;
+        Self::init_db(&db)?;
+        Ok(Self { db })
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        let db = Connection::open_in_memory()?;
+        Self::init_db(&db)?;
+        Ok(Self { db })
+    }
+
+    fn init_db(db: &Connection) -> Result<()> {
+        db.execute_batch(
+            "PRAGMA journal_mode=WAL;
+             CREATE TABLE IF NOT EXISTS hash_cache(key BLOB PRIMARY KEY, value BLOB NOT NULL) STRICT;",
+        )?;
+        Ok(())
+    }
+
+    /// Returns the cached hash for `path` extracted at `offset` (and, if hashing a run
+    /// of consecutive frames, stepped by `step` between them), using whatever extractor
+    /// params are currently active, see [`imghash::current_tag`]. Returns `None` on a
+    /// cache miss or on a stale entry, i.e. `path` was modified since it was cached --
+    /// either way the caller should re-extract and re-hash, then call [`Self::put`] with
+    /// the fresh result to keep the cache up to date.
+    pub fn get(
+        &self,
+        path: &Path,
+        offset: Duration,
+        step: Option<Duration>,
+    ) -> Result<Option<Hamming>> {
+        let Some(entry) = self.load(path, offset, step)? else {
+            return Ok(None);
+        };
+
+        if entry.stamp != FileStamp::of(path)? {
+            return Ok(None);
+        }
+
+        Ok(Some(entry.hash))
+    }
+
+    pub fn put(
+        &self,
+        path: &Path,
+        offset: Duration,
+        step: Option<Duration>,
+        hash: Hamming,
+    ) -> Result<()> {
+        let key = bincode::serialize(&Self::key(path, offset, step))?;
+        let value = bincode::serialize(&CacheEntry {
+            stamp: FileStamp::of(path)?,
+            hash,
+        })?;
+
+        self.db.execute(
+            "INSERT INTO hash_cache(key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            (&key, &value),
+        )?;
+        Ok(())
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        offset: Duration,
+        step: Option<Duration>,
+    ) -> Result<Option<CacheEntry>> {
+        let key = bincode::serialize(&Self::key(path, offset, step))?;
+
+        let value: Option<Vec<u8>> = self
+            .db
+            .query_row(
+                "SELECT value FROM hash_cache WHERE key = ?1",
+                (&key,),
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(match value {
+            Some(value) => Some(bincode::deserialize(&value)?),
+            None => None,
+        })
+    }
+
+    fn key(path: &Path, offset: Duration, step: Option<Duration>) -> CacheKey {
+        CacheKey {
+            path: path.to_path_buf(),
+            offset,
+            step,
+            extractor_tag: imghash::current_tag(),
+        }
+    }
+}
+
+/// Bumped whenever [`ContentCacheKey`]'s shape or meaning changes, so that old entries
+/// are silently orphaned (and never matched) instead of being misread as a different
+/// [`Hamming`] value.
+const CACHE_VERSION: u32 = 1;
+
+/// The file [`prep_cache`] opens (via [`CachedHasher::open`]) inside the given cache dir.
+const CACHE_FILENAME: &str = "hashes.sqlite3";
+
+/// `(content digest, file size, hasher config)`: unlike [`CacheKey`] this doesn't
+/// involve the source file's path or mtime at all, so identical bytes hit the cache even
+/// if the file was renamed, copied, or merely touched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ContentCacheKey {
+    version: u32,
+    digest: [u8; 32],
+    size: u64,
+    hasher_tag: String,
+}
+
+impl ContentCacheKey {
+    fn of(digest: blake3::Hash, size: u64) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            digest: *digest.as_bytes(),
+            size,
+            hasher_tag: imghash::current_tag(),
+        }
+    }
+}
+
+/// Streams the full contents of `path` through a [`blake3::Hasher`] without ever
+/// materializing it as a decoded image, so a cache lookup can skip decoding entirely on
+/// a hit.
+fn digest_of(path: &Path) -> Result<(blake3::Hash, u64)> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = File::open(path)?;
+    let size = io::copy(&mut file, &mut hasher)?;
+    Ok((hasher.finalize(), size))
+}
+
+/// Wraps the global [`imghash::Hasher`] with a persistent, content-addressed cache:
+/// [`Self::get_or_hash`] only decodes and re-hashes an image when no entry exists for
+/// the source file's exact bytes under the currently active `HashConfig` (see
+/// [`imghash::current_tag`]), so bumping [`CACHE_VERSION`] or re-[`imghash::init`]-ing
+/// with different hasher settings transparently invalidates every stale entry.
+pub struct CachedHasher {
+    db: Connection,
+}
+
+impl CachedHasher {
+    pub fn open(file: impl AsRef<Path>) -> Result<Self> {
+        let db = Connection::open(file)?;
+        Self::init_db(&db)?;
+        Ok(Self { db })
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        let db = Connection::open_in_memory()?;
+        Self::init_db(&db)?;
+        Ok(Self { db })
+    }
+
+    fn init_db(db: &Connection) -> Result<()> {
+        db.execute_batch(
+            "PRAGMA journal_mode=WAL;
+             CREATE TABLE IF NOT EXISTS content_hash_cache(key BLOB PRIMARY KEY, value BLOB NOT NULL) STRICT;",
+        )?;
+        Ok(())
+    }
+
+    /// Returns the cached hash for the file at `path`, computing it via `hash_img` (and
+    /// caching the result) on a miss.
+    pub fn get_or_hash<I>(&self, path: &Path, hash_img: impl FnOnce() -> I) -> Result<Hamming>
+    where
+        I: image_hasher::Image,
+    {
+        let (digest, size) = digest_of(path)?;
+        let key = ContentCacheKey::of(digest, size);
+
+        if let Some(hash) = self.load(&key)? {
+            return Ok(hash);
+        }
+
+        let hash = imghash::hash(&hash_img());
+        self.put(&key, hash)?;
+        Ok(hash)
+    }
+
+    fn load(&self, key: &ContentCacheKey) -> Result<Option<Hamming>> {
+        let key = bincode::serialize(key)?;
+        let value: Option<Vec<u8>> = self
+            .db
+            .query_row(
+                "SELECT value FROM content_hash_cache WHERE key = ?1",
+                (&key,),
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(match value {
+            Some(value) => Some(bincode::deserialize(&value)?),
+            None => None,
+        })
+    }
+
+    fn put(&self, key: &ContentCacheKey, hash: Hamming) -> Result<()> {
+        let key = bincode::serialize(key)?;
+        let value = bincode::serialize(&hash)?;
+
+        self.db.execute(
+            "INSERT INTO content_hash_cache(key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            (&key, &value),
+        )?;
+        Ok(())
+    }
+}
+
+/// Wipes any existing cache database under `dir` and leaves `dir` empty, mirroring
+/// [`fsutils::clear_dir`]'s blow-away-and-recreate semantics.
+pub fn clear_cache(dir: impl AsRef<Path>) -> Result<()> {
+    fsutils::clear_dir(dir)?;
+    Ok(())
+}
+
+/// Ensures `dir` exists (wiping it first if it already holds something unrelated isn't
+/// this function's job, see [`clear_cache`] for that) and opens/creates the
+/// [`CachedHasher`] database inside it.
+pub fn prep_cache(dir: impl AsRef<Path>) -> Result<CachedHasher> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    CachedHasher::open(dir.join(CACHE_FILENAME))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread::sleep, time::Duration as StdDuration};
+
+    use super::*;
+
+    fn touch(path: &Path) {
+        // NOTE: sleep a bit first since some filesystems only have second-granularity
+        // mtimes, and we need `touch` to actually produce a newer one.
+        sleep(StdDuration::from_millis(1100));
+        std::fs::write(path, b"changed").unwrap();
+    }
+
+    #[test]
+    fn miss_then_hit() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("video.mp4");
+        std::fs::write(&file, b"hello").unwrap();
+
+        let cache = HashCache::in_memory().unwrap();
+        let offset = Duration::from_secs(5);
+
+        assert_eq!(None, cache.get(&file, offset, None).unwrap());
+
+        cache.put(&file, offset, None, Hamming(42)).unwrap();
+        assert_eq!(Some(Hamming(42)), cache.get(&file, offset, None).unwrap());
+    }
+
+    #[test]
+    fn different_offsets_and_steps_dont_collide() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("video.mp4");
+        std::fs::write(&file, b"hello").unwrap();
+
+        let cache = HashCache::in_memory().unwrap();
+        cache
+            .put(&file, Duration::from_secs(1), None, Hamming(1))
+            .unwrap();
+        cache
+            .put(&file, Duration::from_secs(2), None, Hamming(2))
+            .unwrap();
+        cache
+            .put(
+                &file,
+                Duration::from_secs(1),
+                Some(Duration::from_secs(1)),
+                Hamming(3),
+            )
+            .unwrap();
+
+        assert_eq!(
+            Some(Hamming(1)),
+            cache.get(&file, Duration::from_secs(1), None).unwrap()
+        );
+        assert_eq!(
+            Some(Hamming(2)),
+            cache.get(&file, Duration::from_secs(2), None).unwrap()
+        );
+        assert_eq!(
+            Some(Hamming(3)),
+            cache
+                .get(&file, Duration::from_secs(1), Some(Duration::from_secs(1)))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn stale_entry_is_invalidated_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("video.mp4");
+        std::fs::write(&file, b"hello").unwrap();
+
+        let cache = HashCache::in_memory().unwrap();
+        let offset = Duration::from_secs(5);
+        cache.put(&file, offset, None, Hamming(42)).unwrap();
+        assert_eq!(Some(Hamming(42)), cache.get(&file, offset, None).unwrap());
+
+        touch(&file);
+        assert_eq!(None, cache.get(&file, offset, None).unwrap());
+    }
+}
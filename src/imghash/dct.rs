@@ -0,0 +1,111 @@
+//! A DCT-based perceptual hash ("pHash"), computed independently of the
+//! `image_hasher`-backed algorithms in [`super::hashalg`]. Where those trade blurs of a
+//! few pixels for speed, concentrating the image's energy into a handful of low-frequency
+//! coefficients makes this variant tolerate scaling, mild blur and recompression better,
+//! at the cost of always being exactly 64 bits wide.
+
+use image::{GrayImage, Pixel};
+
+use super::hamming::Hamming;
+
+const RESIZE_TO: u32 = 32;
+const BLOCK_SIZE: usize = 8;
+
+/// Resizes `gray` to 32x32, runs a separable 2-D DCT-II over it, and thresholds the
+/// top-left 8x8 block of low-frequency coefficients (excluding the DC term) against
+/// their median to produce a 64-bit hash.
+pub(super) fn hash_gray(gray: &GrayImage) -> Hamming {
+    let resized = image::imageops::resize(
+        gray,
+        RESIZE_TO,
+        RESIZE_TO,
+        image::imageops::FilterType::Lanczos3,
+    );
+
+    let samples: Vec<Vec<f64>> = (0..RESIZE_TO)
+        .map(|y| {
+            (0..RESIZE_TO)
+                .map(|x| resized.get_pixel(x, y).channels()[0] as f64)
+                .collect()
+        })
+        .collect();
+
+    let coeffs = dct_2d(&samples);
+
+    let block: Vec<f64> = coeffs[..BLOCK_SIZE]
+        .iter()
+        .flat_map(|row| row[..BLOCK_SIZE].iter().copied())
+        .collect();
+
+    let mut without_dc = block.clone();
+    without_dc.remove(0);
+    without_dc.sort_by(f64::total_cmp);
+    let median = without_dc[without_dc.len() / 2];
+
+    let mut bits: u64 = 0;
+    for (i, &coeff) in block.iter().enumerate() {
+        if coeff > median {
+            bits |= 1 << i;
+        }
+    }
+
+    Hamming(bits as _)
+}
+
+/// A separable 2-D DCT-II: the 1-D transform applied to every row, then to every column
+/// of the result.
+fn dct_2d(samples: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let by_rows: Vec<Vec<f64>> = samples.iter().map(|row| dct_1d(row)).collect();
+
+    let n = by_rows.len();
+    let mut by_cols = vec![vec![0.0; n]; n];
+    for x in 0..n {
+        let column: Vec<f64> = by_rows.iter().map(|row| row[x]).collect();
+        let column = dct_1d(&column);
+        for (y, value) in column.into_iter().enumerate() {
+            by_cols[y][x] = value;
+        }
+    }
+
+    by_cols
+}
+
+/// The 1-D DCT-II: `F[k] = sum_{n=0}^{N-1} f[n]*cos(pi/N*(n+0.5)*k)`.
+fn dct_1d(input: &[f64]) -> Vec<f64> {
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            input
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| {
+                    let angle = std::f64::consts::PI / n as f64 * (i as f64 + 0.5) * k as f64;
+                    sample * angle.cos()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::imgutils::filled;
+
+    use super::*;
+
+    #[test]
+    fn same_image_same_hash() {
+        let img = filled(300, 300, 100, 150, 200);
+        let gray = image::imageops::colorops::grayscale(&img);
+        assert_eq!(hash_gray(&gray), hash_gray(&gray));
+    }
+
+    #[test]
+    fn different_images_different_hash() {
+        let black = image::imageops::colorops::grayscale(&filled(300, 300, 0, 0, 0));
+        let checkerboard = GrayImage::from_fn(300, 300, |x, y| {
+            image::Luma([if (x / 30 + y / 30) % 2 == 0 { 0 } else { 255 }])
+        });
+        assert_ne!(hash_gray(&black), hash_gray(&checkerboard));
+    }
+}
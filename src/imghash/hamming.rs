@@ -1,3 +1,5 @@
+use std::ops::BitXor;
+
 use rkyv::bytecheck;
 use rkyv::CheckBytes;
 
@@ -5,56 +7,195 @@ use rkyv::CheckBytes;
 pub type Distance = u32;
 pub type Container = u128;
 
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    PartialEq,
-    Eq,
-    Ord,
-    PartialOrd,
-    CheckBytes,
-)]
+/// The integer type backing a [`Hamming`] hash. Implemented for [`u64`], [`u128`]
+/// (the historical, default width) and [`U256`], so a caller can trade hash
+/// size/decoding cost for discriminating power without touching `distance_to`,
+/// `to_base64`, or any of the `rkyv` plumbing, all of which only go through this trait.
+pub trait HashContainer:
+    Copy + Eq + Ord + std::fmt::Debug + BitXor<Output = Self> + Send + Sync + 'static
+{
+    const BITS: u32;
+    const BYTES: usize;
+
+    fn count_ones(self) -> u32;
+    fn from_ne_bytes(bytes: &[u8]) -> Self;
+    fn to_ne_bytes(self) -> Vec<u8>;
+}
+
+macro_rules! impl_hash_container_for_uint {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl HashContainer for $ty {
+                const BITS: u32 = <$ty>::BITS;
+                const BYTES: usize = std::mem::size_of::<$ty>();
+
+                fn count_ones(self) -> u32 {
+                    <$ty>::count_ones(self)
+                }
+
+                fn from_ne_bytes(bytes: &[u8]) -> Self {
+                    let array: [u8; std::mem::size_of::<$ty>()] = bytes
+                        .try_into()
+                        .expect("the slice is of the incorrect length");
+                    <$ty>::from_ne_bytes(array)
+                }
+
+                fn to_ne_bytes(self) -> Vec<u8> {
+                    <$ty>::to_ne_bytes(self).to_vec()
+                }
+            }
+        )*
+    };
+}
+
+impl_hash_container_for_uint!(u64, u128);
+
+/// A 256-bit [`HashContainer`] composed of two [`u128`] halves, for callers who want
+/// more discriminating power than [`Container`] at the cost of double the storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, CheckBytes)]
+#[repr(C)]
+pub struct U256 {
+    hi: u128,
+    lo: u128,
+}
+
+impl BitXor for U256 {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self {
+        Self {
+            hi: self.hi ^ rhs.hi,
+            lo: self.lo ^ rhs.lo,
+        }
+    }
+}
+
+impl HashContainer for U256 {
+    const BITS: u32 = 256;
+    const BYTES: usize = 32;
+
+    fn count_ones(self) -> u32 {
+        self.hi.count_ones() + self.lo.count_ones()
+    }
+
+    fn from_ne_bytes(bytes: &[u8]) -> Self {
+        assert_eq!(
+            bytes.len(),
+            Self::BYTES,
+            "the slice is of the incorrect length"
+        );
+        let hi: [u8; 16] = bytes[..16].try_into().unwrap();
+        let lo: [u8; 16] = bytes[16..].try_into().unwrap();
+        Self {
+            hi: u128::from_ne_bytes(hi),
+            lo: u128::from_ne_bytes(lo),
+        }
+    }
+
+    fn to_ne_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::BYTES);
+        bytes.extend_from_slice(&self.hi.to_ne_bytes());
+        bytes.extend_from_slice(&self.lo.to_ne_bytes());
+        bytes
+    }
+}
+
+/// Recommended similarity cutoffs for a given hash width, scaled proportionally from the
+/// historical 64-bit values so callers picking a wider/narrower [`Hamming`] don't have to
+/// re-derive sensible thresholds themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecommendedThresholds {
+    /// Two hashes this close or closer are almost certainly the same image.
+    pub very_similar: Distance,
+    /// A reasonable general-purpose "these are duplicates" cutoff.
+    pub similar: Distance,
+    /// Beyond this, two hashes are essentially unrelated.
+    pub not_similar: Distance,
+}
+
+/// Scales a threshold derived for a 64-bit hash up/down to `bits`.
+const fn scale_threshold(base_at_64_bits: Distance, bits: u32) -> Distance {
+    base_at_64_bits * bits / 64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, CheckBytes)]
 #[repr(transparent)]
-pub struct Hamming(pub Container);
+pub struct Hamming<C: HashContainer = Container>(pub C);
 
-impl Hamming {
-    pub const BITS: u32 = Container::BITS;
-    pub const BYTES: usize = std::mem::size_of::<Container>();
+impl<C: HashContainer> Hamming<C> {
+    pub const BITS: u32 = C::BITS;
+    pub const BYTES: usize = C::BYTES;
     pub const MIN_DIST: Distance = 0;
-    pub const MAX_DIST: Distance = Hamming::BITS;
+    pub const MAX_DIST: Distance = Self::BITS;
+
+    /// See [`RecommendedThresholds`]; scaled to [`Self::BITS`].
+    pub const RECOMMENDED: RecommendedThresholds = RecommendedThresholds {
+        very_similar: scale_threshold(6, Self::BITS),
+        similar: scale_threshold(20, Self::BITS),
+        not_similar: scale_threshold(40, Self::BITS),
+    };
 
-    pub fn from_hash(hash: image_hasher::ImageHash<[u8; Self::BYTES]>) -> Hamming {
-        let array: [u8; Hamming::BYTES] = hash
-            .as_bytes()
-            .try_into()
-            .expect("the slice is of the incorrect length");
-        Self(Container::from_ne_bytes(array))
+    pub fn from_hash<B: AsRef<[u8]>>(hash: image_hasher::ImageHash<B>) -> Self {
+        Self(C::from_ne_bytes(hash.as_bytes()))
     }
 
     pub fn to_base64(self) -> String {
-        base64::Engine::encode(
-            &base64::prelude::BASE64_STANDARD_NO_PAD,
-            self.0.to_ne_bytes(),
-        )
+        base64::Engine::encode(&base64::prelude::BASE64_STANDARD_NO_PAD, self.0.to_ne_bytes())
     }
 
     pub fn distance_to(self, other: Self) -> Distance {
         (self.0 ^ other.0).count_ones()
     }
 
-    pub fn distance(a: Container, b: Container) -> Distance {
+    pub fn distance(a: C, b: C) -> Distance {
         Hamming(a).distance_to(Hamming(b))
     }
 }
 
-impl std::fmt::Display for Hamming {
+impl<C: HashContainer> std::fmt::Display for Hamming<C> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.to_base64().fmt(f)
     }
 }
 
-impl rkyv::Archive for Hamming {
+/// The two [`Tagged`] values being compared were produced under different
+/// [`HashConfig`](crate::imghash::HashConfig)s, so their distance wouldn't mean anything.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("cannot compare hashes from different hasher configurations: {0:?} vs {1:?}")]
+pub struct ConfigMismatch(pub String, pub String);
+
+/// A [`Hamming`] paired with the [`HashConfig`](crate::imghash::HashConfig) tag it was
+/// produced under (see [`crate::imghash::current_tag`]). A bare `Hamming` carries no
+/// record of which algorithm/width made it, so nothing stops two hashes from
+/// incompatible configs being compared as if they were the same units; a
+/// [`crate::bktree::mmap::bktree::BKTree`] sidesteps this for a whole database via
+/// `PartialSource::identifier`, but ad-hoc comparisons (e.g. of hashes handed around
+/// outside a tree) have no such guard without wrapping them in this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tagged<C: HashContainer = Container> {
+    pub hash: Hamming<C>,
+    pub tag: String,
+}
+
+impl<C: HashContainer> Tagged<C> {
+    pub fn new(hash: Hamming<C>, tag: impl Into<String>) -> Self {
+        Self {
+            hash,
+            tag: tag.into(),
+        }
+    }
+
+    /// [`Hamming::distance_to`], but first checks that `self` and `other` were tagged
+    /// with the same configuration.
+    pub fn distance_to(&self, other: &Self) -> Result<Distance, ConfigMismatch> {
+        if self.tag != other.tag {
+            return Err(ConfigMismatch(self.tag.clone(), other.tag.clone()));
+        }
+        Ok(self.hash.distance_to(other.hash))
+    }
+}
+
+impl<C: HashContainer> rkyv::Archive for Hamming<C> {
     type Archived = Self;
     type Resolver = ();
 
@@ -68,7 +209,7 @@ impl rkyv::Archive for Hamming {
     }
 }
 
-impl<S: rkyv::Fallible + ?Sized> rkyv::Serialize<S> for Hamming {
+impl<C: HashContainer, S: rkyv::Fallible + ?Sized> rkyv::Serialize<S> for Hamming<C> {
     fn serialize(
         &self,
         _serializer: &mut S,
@@ -145,4 +286,33 @@ mod test {
             Hamming(0b010).distance_to(Hamming(0b101))
         );
     }
+
+    #[test]
+    fn tagged_same_tag_compares_fine() {
+        let a = Tagged::new(Hamming(0b101), "gradient-128");
+        let b = Tagged::new(Hamming(0b010), "gradient-128");
+        assert_eq!(Ok(3), a.distance_to(&b));
+    }
+
+    #[test]
+    fn tagged_different_tag_is_a_mismatch() {
+        let a = Tagged::new(Hamming(0b101), "gradient-128");
+        let b = Tagged::new(Hamming(0b010), "mean-64");
+        assert_eq!(
+            Err(ConfigMismatch("gradient-128".to_string(), "mean-64".to_string())),
+            a.distance_to(&b)
+        );
+    }
+
+    #[test]
+    fn recommended_thresholds_scale_with_width() {
+        let narrow = Hamming::<u64>::RECOMMENDED;
+        let wide = Hamming::<u128>::RECOMMENDED;
+        assert_eq!(narrow.very_similar, 6);
+        assert_eq!(narrow.similar, 20);
+        assert_eq!(narrow.not_similar, 40);
+        assert_eq!(wide.very_similar, narrow.very_similar * 2);
+        assert_eq!(wide.similar, narrow.similar * 2);
+        assert_eq!(wide.not_similar, narrow.not_similar * 2);
+    }
 }
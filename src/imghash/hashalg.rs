@@ -0,0 +1,230 @@
+use clap::{Args, ValueEnum};
+
+/// Which resampling filter to resize an image down to the hash grid with, before
+/// [`HashAlg`] ever sees it. Exposes the three cheapest/most common choices `image`
+/// offers; `image_hasher` defaults to [`ResizeFilter::Lanczos3`] if never set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResizeFilter {
+    /// Fastest, blockiest: nearest-neighbor sampling.
+    Nearest,
+    /// Linear interpolation, a reasonable speed/quality middle ground.
+    Triangle,
+    /// Slowest, highest quality; the historical default.
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    fn to_image_hasher(self) -> image_hasher::FilterType {
+        match self {
+            ResizeFilter::Nearest => image_hasher::FilterType::Nearest,
+            ResizeFilter::Triangle => image_hasher::FilterType::Triangle,
+            ResizeFilter::Lanczos3 => image_hasher::FilterType::Lanczos3,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            ResizeFilter::Nearest => "nearest",
+            ResizeFilter::Triangle => "triangle",
+            ResizeFilter::Lanczos3 => "lanczos3",
+        }
+    }
+}
+
+impl Default for ResizeFilter {
+    fn default() -> Self {
+        ResizeFilter::Lanczos3
+    }
+}
+
+/// Which perceptual-hash algorithm to compute, one of the handful that
+/// `image_hasher` implements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HashAlg {
+    /// Average the pixels and threshold against the mean.
+    Mean,
+    /// Horizontal gradient, a.k.a. dHash.
+    Difference,
+    /// Vertical gradient.
+    Gradient,
+    /// Both gradient directions combined, at the cost of a weird actually-used bit
+    /// count (see the NOTE on [`super::Hasher::new`]).
+    DoubleGradient,
+    /// Average intensity of blocks, a.k.a. blockhash.
+    Blockhash,
+}
+
+impl HashAlg {
+    fn to_image_hasher(self) -> image_hasher::HashAlg {
+        match self {
+            HashAlg::Mean => image_hasher::HashAlg::Mean,
+            HashAlg::Difference => image_hasher::HashAlg::Gradient,
+            HashAlg::Gradient => image_hasher::HashAlg::VertGradient,
+            HashAlg::DoubleGradient => image_hasher::HashAlg::DoubleGradient,
+            HashAlg::Blockhash => image_hasher::HashAlg::Blockhash,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            HashAlg::Mean => "mean",
+            HashAlg::Difference => "difference",
+            HashAlg::Gradient => "gradient",
+            HashAlg::DoubleGradient => "doublegradient",
+            HashAlg::Blockhash => "blockhash",
+        }
+    }
+}
+
+/// The total number of bits to hash into, i.e. how many of [`super::hamming::Hamming`]'s
+/// [`super::hamming::Hamming::BITS`] are actually meaningful. The rest are always zero,
+/// so two hashes computed with different widths are still comparable, just with less
+/// precision than comparing two hashes of the same (larger) width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HashWidth {
+    #[value(name = "8")]
+    Bits8,
+    #[value(name = "16")]
+    Bits16,
+    #[value(name = "32")]
+    Bits32,
+    #[value(name = "64")]
+    Bits64,
+    /// The historical default, and the most bits that fit in a [`super::hamming::Hamming`].
+    #[value(name = "128")]
+    Bits128,
+}
+
+impl HashWidth {
+    /// A `(width, height)` pair for `image_hasher::HasherConfig::hash_size`, chosen to be
+    /// roughly square and to multiply out to this many bits.
+    fn hash_size(self) -> (u32, u32) {
+        match self {
+            HashWidth::Bits8 => (4, 2),
+            HashWidth::Bits16 => (4, 4),
+            HashWidth::Bits32 => (8, 4),
+            HashWidth::Bits64 => (8, 8),
+            HashWidth::Bits128 => (16, 8),
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            HashWidth::Bits8 => "8",
+            HashWidth::Bits16 => "16",
+            HashWidth::Bits32 => "32",
+            HashWidth::Bits64 => "64",
+            HashWidth::Bits128 => "128",
+        }
+    }
+
+    /// How many bits of a hash computed at this width are actually meaningful, see the
+    /// type's own doc comment. Used by [`super::similarity::SimilarityPreset`] to scale a
+    /// named tier's distance to whatever width is actually configured.
+    pub fn bits(self) -> u32 {
+        match self {
+            HashWidth::Bits8 => 8,
+            HashWidth::Bits16 => 16,
+            HashWidth::Bits32 => 32,
+            HashWidth::Bits64 => 64,
+            HashWidth::Bits128 => 128,
+        }
+    }
+}
+
+impl Default for HashWidth {
+    fn default() -> Self {
+        HashWidth::Bits128
+    }
+}
+
+/// Which algorithm, bit width, and resize filter to hash images with. The algorithm and
+/// width are stamped into [`crate::bktree::source_types::PartialSource::identifier`] so
+/// that a database built with one [`HashConfig`] can't silently be queried with another;
+/// the resize filter only changes how pixels are resampled before hashing, not the
+/// hash's bit layout, so two configs that only differ there stay comparable and aren't
+/// part of that tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashConfig {
+    pub alg: HashAlg,
+    pub width: HashWidth,
+    pub filter: ResizeFilter,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            // NOTE: matches the hash this crate always used before `HashConfig` existed.
+            alg: HashAlg::Gradient,
+            width: HashWidth::Bits128,
+            filter: ResizeFilter::Lanczos3,
+        }
+    }
+}
+
+impl HashConfig {
+    pub(super) fn image_hasher_alg(&self) -> image_hasher::HashAlg {
+        self.alg.to_image_hasher()
+    }
+
+    pub(super) fn hash_size(&self) -> (u32, u32) {
+        self.width.hash_size()
+    }
+
+    pub(super) fn resize_filter(&self) -> image_hasher::FilterType {
+        self.filter.to_image_hasher()
+    }
+
+    /// The tag embedded into a [`PartialSource::identifier`](crate::bktree::source_types::PartialSource::identifier).
+    pub fn tag(&self) -> String {
+        format!("{}-{}", self.alg.tag(), self.width.tag())
+    }
+}
+
+/// Which hashing pipeline a caller actually wants: the configurable, `image_hasher`-backed
+/// [`HashAlg`]/[`HashWidth`] combo, or the fixed-parameter DCT-based
+/// [`super::hash_sub_dct`] ("pHash"), which trades configurability for better tolerance
+/// of scaling, mild blur and recompression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum HashMethod {
+    /// Whichever [`HashAlg`]/[`HashWidth`] combo was configured via [`init`](super::init).
+    #[default]
+    Configured,
+    /// The DCT-based perceptual hash.
+    Dct,
+}
+
+/// Picks the perceptual-hash algorithm, bit width, and method, flattened into
+/// [`super::preproc::PreprocCli`] since that's where images are actually hashed.
+#[derive(Args, Debug)]
+pub struct HasherCli {
+    /// Which perceptual-hash algorithm to use
+    #[arg(long, value_enum, default_value_t = HashAlg::Gradient)]
+    hash_alg: HashAlg,
+
+    /// How many bits of the hash to actually use
+    #[arg(long, value_enum, default_value_t = HashWidth::Bits128)]
+    hash_width: HashWidth,
+
+    /// Which resampling filter to resize images down to the hash grid with
+    #[arg(long, value_enum, default_value_t = ResizeFilter::Lanczos3)]
+    hash_filter: ResizeFilter,
+
+    /// Which hashing pipeline to hash images with
+    #[arg(long, value_enum, default_value_t = HashMethod::Configured)]
+    hash_method: HashMethod,
+}
+
+impl HasherCli {
+    pub fn to_config(&self) -> HashConfig {
+        HashConfig {
+            alg: self.hash_alg,
+            width: self.hash_width,
+            filter: self.hash_filter,
+        }
+    }
+
+    pub fn hash_method(&self) -> HashMethod {
+        self.hash_method
+    }
+}
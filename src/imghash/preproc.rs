@@ -1,14 +1,21 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
 use clap::Args;
+use color_eyre::eyre::{self, Context};
 use image::RgbImage;
+use rayon::prelude::*;
 
+use crate::imghash;
+use crate::imghash::hashalg::{HashMethod, HasherCli};
+use crate::termination::Cookie;
+use crate::utils::image_decode;
 use crate::utils::imgutils::{
     self, BlackMaskArgs, BlackMaskCli, BlandnessArgs, BlandnessCli, RemoveBordersCli,
 };
 use crate::{
-    imghash::{
-        hamming::{Distance, Hamming},
-        imghash,
-    },
+    imghash::hamming::{Distance, Hamming},
     utils::imgutils::RemoveBordersArgs,
 };
 
@@ -24,11 +31,22 @@ pub struct PreprocCli {
 
     #[command(flatten)]
     bland_args: BlandnessCli,
+
+    #[command(flatten)]
+    hasher_args: HasherCli,
 }
 
 impl PreprocCli {
-    pub fn to_args(&self) -> PreprocArgs {
-        PreprocArgs::default().remove_borders_args(self.border_args.to_args())
+    /// Configures the global [`imghash`] hasher from the chosen [`HasherCli`] and builds
+    /// the preprocessing args from the border-removal options and the hash method; the
+    /// black-mask and blandness args keep their defaults, the `black_args`/`bland_args`
+    /// CLI options are not applied. Must only be called once per process, like
+    /// [`imghash::init`] itself.
+    pub fn to_args(&self) -> eyre::Result<PreprocArgs> {
+        imghash::init(self.hasher_args.to_config())?;
+        Ok(PreprocArgs::default()
+            .remove_borders_args(self.border_args.to_args())
+            .hash_method(self.hasher_args.hash_method()))
     }
 }
 
@@ -36,6 +54,7 @@ pub struct PreprocArgs {
     border_args: RemoveBordersArgs,
     black_args: BlackMaskArgs,
     bland_args: BlandnessArgs,
+    hash_method: HashMethod,
 }
 
 impl Default for PreprocArgs {
@@ -44,6 +63,7 @@ impl Default for PreprocArgs {
             border_args: RemoveBordersArgs::default(),
             black_args: BlackMaskArgs::default(),
             bland_args: BlandnessArgs::default(),
+            hash_method: HashMethod::default(),
         }
     }
 }
@@ -56,6 +76,10 @@ pub enum PreprocError {
     TooBlack,
     #[error("the image is too bland")]
     TooBland,
+    #[error("no stable quadrilateral of content could be found to rectify")]
+    NotRectifiable,
+    #[error("failed to decode the image: {0}")]
+    Decode(#[from] image_decode::DecodeError),
 }
 
 impl PreprocArgs {
@@ -74,6 +98,11 @@ impl PreprocArgs {
         self
     }
 
+    pub fn hash_method(mut self, method: HashMethod) -> Self {
+        self.hash_method = method;
+        self
+    }
+
     /// Preprocesses the image and hashes it, unless it is deemed a bad picture
     pub fn hash_img(&self, img: &RgbImage) -> Result<Hamming, PreprocError> {
         if self.bland_args.is_bland(img) {
@@ -85,13 +114,32 @@ impl PreprocArgs {
             return Err(PreprocError::TooBlack);
         }
 
+        if self.border_args.rectify_enabled() {
+            let rectified = self
+                .border_args
+                .rectify_mask(img, &mask)
+                .ok_or(PreprocError::NotRectifiable)?;
+
+            if imgutils::is_img_empty(&rectified) {
+                return Err(PreprocError::Empty);
+            }
+
+            return Ok(match self.hash_method {
+                HashMethod::Configured => imghash::hash(&rectified),
+                HashMethod::Dct => imghash::hash_dct(&rectified),
+            });
+        }
+
         let no_borders = self.border_args.remove_borders_mask(img, &mask);
 
         if imgutils::is_subimg_empty(&no_borders) {
             return Err(PreprocError::Empty);
         }
 
-        Ok(imghash::hash_sub(&no_borders))
+        Ok(match self.hash_method {
+            HashMethod::Configured => imghash::hash_sub(&no_borders),
+            HashMethod::Dct => imghash::hash_sub_dct(&no_borders),
+        })
     }
 
     /// Preprocess the image
@@ -99,4 +147,53 @@ impl PreprocArgs {
     pub fn preprocess(&self, img: &RgbImage) -> RgbImage {
         self.border_args.remove_borders(img).to_image()
     }
+
+    /// Decodes the file at `path` (see [`image_decode::open_image`] for which formats
+    /// this understands beyond what [`image`] does natively) and runs it through
+    /// [`Self::hash_img`].
+    pub fn hash_path(&self, path: &Path) -> Result<Hamming, PreprocError> {
+        let img = image_decode::open_image(path)?;
+        self.hash_img(&img)
+    }
+
+    /// Decodes, preprocesses, and hashes every path in `paths` across a rayon thread
+    /// pool, sending each `(path, result)` pair through `tx` as soon as it's ready
+    /// instead of collecting them into a `Vec`, so a caller driving thousands of files
+    /// (e.g. the output of [`crate::utils::fsutils::all_files`]) can stream progress to a
+    /// UI while the batch is still running. `scanned` is bumped once per path, including
+    /// failures, right before its result is sent, so the caller can report "N scanned"
+    /// without having to drain `tx` itself. `threads` bounds the pool size; `None` lets
+    /// rayon pick its own default (see `RAYON_NUM_THREADS`). `cookie`, if given, is
+    /// polled before each path and stops handing out further work once
+    /// [`Cookie::is_terminating`] -- paths already in flight still run to completion.
+    pub fn hash_many(
+        &self,
+        paths: Vec<PathBuf>,
+        tx: mpsc::SyncSender<(PathBuf, Result<Hamming, PreprocError>)>,
+        threads: Option<usize>,
+        scanned: &AtomicUsize,
+        cookie: Option<&Cookie>,
+    ) -> eyre::Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.unwrap_or(0))
+            .build()
+            .wrap_err("failed to build the hashing thread pool")?;
+
+        pool.install(|| {
+            paths.into_par_iter().for_each_with(tx, |tx, path| {
+                if cookie.is_some_and(Cookie::is_terminating) {
+                    return;
+                }
+
+                let result = self.hash_path(&path);
+                scanned.fetch_add(1, Ordering::SeqCst);
+
+                // The receiver may already be gone, e.g. if the caller stopped
+                // listening after cancelling; nothing useful to do about that here.
+                let _ = tx.send((path, result));
+            });
+        });
+
+        Ok(())
+    }
 }
@@ -1,19 +1,70 @@
-use clap::Args;
+use clap::{Args, ValueEnum};
 
-use crate::imghash::hamming::{Distance, Hamming};
+use crate::imghash::{
+    hamming::{ConfigMismatch, Distance, Hamming, Tagged},
+    HashWidth,
+};
 
 pub const DEFAULT_SIMILARITY_THRESHOLD: Distance = 23;
 
+/// Named sensitivity tiers for [`SimiArgs::similarity_threshold`], so a caller can pass
+/// `--similarity high` instead of having to pick a meaningful raw [`Distance`] by hand.
+/// Each tier is a fraction of the active [`HashWidth`]'s bit count rather than a fixed
+/// number, since a raw distance of e.g. `10` means near-identical at 128 bits but almost
+/// anything-goes at 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimilarityPreset {
+    /// Only near-identical hashes match.
+    VeryHigh,
+    High,
+    Medium,
+    Low,
+    /// Very loose, expect lots of false positives.
+    VeryLow,
+}
+
+impl SimilarityPreset {
+    /// Fraction of the hash width allowed to differ for this tier.
+    fn fraction(self) -> f32 {
+        match self {
+            SimilarityPreset::VeryHigh => 0.05,
+            SimilarityPreset::High => 0.10,
+            SimilarityPreset::Medium => 0.18,
+            SimilarityPreset::Low => 0.27,
+            SimilarityPreset::VeryLow => 0.40,
+        }
+    }
+
+    /// Resolves this tier into a concrete [`Distance`] for `width`.
+    pub fn distance_for(self, width: HashWidth) -> Distance {
+        (width.bits() as f32 * self.fraction()).round() as Distance
+    }
+
+    /// [`Self::distance_for`], but against whichever [`HashWidth`] is currently active,
+    /// see [`crate::imghash::current_width`].
+    pub fn distance(self) -> Distance {
+        self.distance_for(crate::imghash::current_width())
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct SimiCli {
     /// Maximum distance for two images to be considered equal
     #[arg(long, default_value_t = DEFAULT_SIMILARITY_THRESHOLD)]
     similarity_threshold: Distance,
+
+    /// A named sensitivity tier, overriding `similarity_threshold` if given
+    #[arg(long, value_enum)]
+    similarity: Option<SimilarityPreset>,
 }
 
 impl SimiCli {
-    pub fn as_args(&self) -> SimiArgs {
-        SimiArgs::default().similarity_threshold(self.similarity_threshold)
+    pub fn to_args(&self) -> SimiArgs {
+        let threshold = self
+            .similarity
+            .map(SimilarityPreset::distance)
+            .unwrap_or(self.similarity_threshold);
+        SimiArgs::default().similarity_threshold(threshold)
     }
 }
 
@@ -35,6 +86,10 @@ impl SimiArgs {
         self
     }
 
+    pub fn threshold(&self) -> Distance {
+        self.similarity_threshold
+    }
+
     pub fn is_within(&self, dist: Distance) -> bool {
         dist <= self.similarity_threshold
     }
@@ -50,4 +105,10 @@ impl SimiArgs {
     pub fn are_dissimilar(&self, h1: Hamming, h2: Hamming) -> bool {
         !self.are_similar(h1, h2)
     }
+
+    /// [`Self::are_similar`], but for [`Tagged`] hashes: errors instead of silently
+    /// comparing two hashes produced under different hasher configurations.
+    pub fn are_similar_tagged(&self, h1: &Tagged, h2: &Tagged) -> Result<bool, ConfigMismatch> {
+        Ok(self.is_within(h1.distance_to(h2)?))
+    }
 }
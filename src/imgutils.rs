@@ -42,6 +42,16 @@ where
     )
 }
 
+pub fn flip_horizontal<I: GenericImageView>(
+    image: &I,
+) -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
+where
+    I::Pixel: 'static,
+    <I::Pixel as Pixel>::Subpixel: 'static,
+{
+    imageops::flip_horizontal(image)
+}
+
 fn new_width_same_ratio(oldw: u32, oldh: u32, newh: u32) -> u32 {
     // TODO: use av_rescale?
     assert_ne!(newh, 0);
@@ -1,7 +1,12 @@
 pub mod bin_common;
 pub mod bktree;
+pub mod db;
+pub mod denoiser;
+pub mod frame_cache;
 pub mod frame_extractor;
 pub mod imghash;
+pub mod termination;
+pub mod timeline;
 
 /// For stand-alone functionality that fit comfortably within one file.
 pub mod utils;
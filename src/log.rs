@@ -1,6 +1,12 @@
-struct MyLogger;
+use std::sync::OnceLock;
 
-static MY_LOGGER: MyLogger = MyLogger;
+use crate::utils::clocks::Clocks;
+
+struct MyLogger {
+    clocks: &'static dyn Clocks,
+}
+
+static MY_LOGGER: OnceLock<MyLogger> = OnceLock::new();
 
 impl log::Log for MyLogger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
@@ -9,8 +15,10 @@ impl log::Log for MyLogger {
 
     fn log(&self, record: &log::Record) {
         if self.enabled(record.metadata()) {
+            let now = humantime::format_rfc3339_millis(self.clocks.realtime());
             println!(
-                "{} [{}] - {}",
+                "{} {} [{}] - {}",
+                now,
                 record.target(),
                 record.level(),
                 record.args(),
@@ -21,7 +29,11 @@ impl log::Log for MyLogger {
     fn flush(&self) {}
 }
 
-pub fn install() {
-    log::set_logger(&MY_LOGGER).unwrap();
+/// Installs the global logger, stamping every record with `clocks.realtime()` so tests
+/// can drive it with a [`crate::utils::clocks::SimulatedClocks`] instead of the real
+/// wall clock.
+pub fn install(clocks: &'static dyn Clocks) {
+    let logger = MY_LOGGER.get_or_init(|| MyLogger { clocks });
+    log::set_logger(logger).unwrap();
     log::set_max_level(log::LevelFilter::Trace);
 }
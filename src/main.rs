@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use imgdup::frame_extractor::FrameExtractor;
+use imgdup::frame_extractor::{FrameExtractor, FrameExtractorConf};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = std::env::args().collect();
@@ -13,7 +13,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let step: u64 = args[2].parse().unwrap();
     let step = Duration::from_secs(step);
 
-    let mut extractor = FrameExtractor::new(filename)?;
+    let mut extractor = FrameExtractor::new(filename, FrameExtractorConf::default())?;
     for i in 1..=10 {
         match extractor.next() {
             Ok(Some((ts, img))) => {
@@ -18,7 +18,70 @@ pub struct TimeSeries {
 // https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm
 // http://www.johndcook.com/blog/standard_deviation/
 // Räknar ut snitt, avvikelse, min och max
-pub struct Stats; // TODO:
+/// Running mean/variance/min/max over a stream of [`Measurement`] durations, updated in
+/// O(1) per [`Self::push`] instead of being recomputed from a materialized
+/// `Vec<Measurement>`, so live stats are available mid-run without keeping every
+/// measurement around.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: Duration,
+    max: Duration,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+        }
+    }
+
+    fn push(&mut self, x: Duration) {
+        self.count += 1;
+
+        let nanos = x.as_nanos() as f64;
+        let delta = nanos - self.mean;
+        self.mean += delta / self.count as f64;
+        let delta2 = nanos - self.mean;
+        self.m2 += delta * delta2;
+
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos(self.mean.round() as u64))
+    }
+
+    /// Sample variance, in nanoseconds². `None` until at least two measurements have
+    /// been pushed.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 1).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    pub fn stddev(&self) -> Option<Duration> {
+        self.variance()
+            .map(|variance| Duration::from_nanos(variance.sqrt().round() as u64))
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then_some(self.max)
+    }
+}
 
 struct Perf {
     series: Mutex<HashMap<ID, TimeSeries>>,
@@ -39,6 +102,13 @@ impl Perf {
     }
 
     fn publish(&self, id: ID, meas: Measurement) {
+        self.stats
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_insert_with(Stats::new)
+            .push(meas.duration());
+
         self.series
             .lock()
             .unwrap()
@@ -47,6 +117,10 @@ impl Perf {
             .push(meas);
     }
 
+    fn stats(&self, id: ID) -> Option<Stats> {
+        self.stats.lock().unwrap().get(id).copied()
+    }
+
     fn finish(&self) -> HashMap<ID, TimeSeries> {
         self.stats.lock().unwrap().clear();
         std::mem::take(&mut self.series.lock().unwrap())
@@ -139,7 +213,12 @@ pub fn end(id: ID, cookie: Cookie) {
 // pub fn subscribe(id: ID) -> Receiver<Stats> {}
 // Probably a much better idea:
 // pub fn subscribe(id: ID);
-// pub fn stats(id: ID) -> Option<Stats> {}
+
+/// Running [`Stats`] for `id` as measured so far this run, or `None` if nothing has been
+/// published under it yet.
+pub fn stats(id: ID) -> Option<Stats> {
+    Perf::instance().stats(id)
+}
 
 pub fn finish() -> HashMap<ID, TimeSeries> {
     enable(false);
@@ -161,3 +240,47 @@ macro_rules! perf {
         retval
     }};
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty_stats_are_all_none() {
+        let stats = Stats::new();
+        assert_eq!(None, stats.mean());
+        assert_eq!(None, stats.variance());
+        assert_eq!(None, stats.stddev());
+        assert_eq!(None, stats.min());
+        assert_eq!(None, stats.max());
+    }
+
+    #[test]
+    fn single_measurement_has_no_variance() {
+        let mut stats = Stats::new();
+        stats.push(Duration::from_millis(10));
+
+        assert_eq!(Some(Duration::from_millis(10)), stats.mean());
+        assert_eq!(Some(Duration::from_millis(10)), stats.min());
+        assert_eq!(Some(Duration::from_millis(10)), stats.max());
+        assert_eq!(None, stats.variance());
+        assert_eq!(None, stats.stddev());
+    }
+
+    #[test]
+    fn tracks_mean_min_max_and_stddev() {
+        let mut stats = Stats::new();
+        for ms in [10, 20, 30] {
+            stats.push(Duration::from_millis(ms));
+        }
+
+        assert_eq!(3, stats.count());
+        assert_eq!(Some(Duration::from_millis(20)), stats.mean());
+        assert_eq!(Some(Duration::from_millis(10)), stats.min());
+        assert_eq!(Some(Duration::from_millis(30)), stats.max());
+
+        // Sample variance of {10, 20, 30} (ms, in ns²) is 1e14.
+        let variance = stats.variance().unwrap();
+        assert!((variance - 1e14).abs() < 1.0);
+    }
+}
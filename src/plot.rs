@@ -1,7 +1,9 @@
 use plotters::prelude::*;
 use std::path::Path;
+use std::time::Duration;
 
 use crate::error_stack_utils::IntoReportChangeContext;
+use crate::perf::{TimeSeries, ID};
 
 #[derive(thiserror::Error, Debug)]
 #[error("Generic plot error")]
@@ -47,9 +49,127 @@ pub fn bar_chart(path: impl AsRef<Path>, bars: &[(&str, i32)]) -> SvgResult<()>
     Ok(())
 }
 
-// TODO:
-// rita en timeseries som en linje, x: tiden när det hände, y: dur
-// pub fn perf_line(path, series: &TimeSeries)
-// rita allihopa som flera horisontella linjesegment där den vänstra punkten är start och
-// den högra är slut
-// pub fn perf_time(path, series: &[TimeSeries])
+/// How much vertical space [`perf_time`] gives each row (one per [`ID`]).
+const PERF_TIME_ROW_HEIGHT: u32 = 40;
+
+/// Plots every measurement in `series` as a point connected by a line, x = its
+/// [`Measurement::start`](crate::perf::Measurement::start) relative to
+/// [`TimeSeries::start`], y = its
+/// [`Measurement::duration`](crate::perf::Measurement::duration) in milliseconds. Useful
+/// for seeing how a single instrumented region's latency drifts over a run.
+pub fn perf_line(path: impl AsRef<Path>, series: &TimeSeries) -> SvgResult<()> {
+    let measurements = series.measurements();
+    assert!(!measurements.is_empty());
+
+    let t0 = series.start();
+    let points: Vec<(f64, f64)> = measurements
+        .iter()
+        .map(|m| {
+            (
+                (m.start() - t0).as_secs_f64(),
+                m.duration().as_secs_f64() * 1000.0,
+            )
+        })
+        .collect();
+
+    let max_x = points.last().map(|(x, _)| *x).unwrap_or(0.0).max(1.0);
+    let max_y = points.iter().map(|(_, y)| *y).fold(0.0, f64::max).max(1.0);
+
+    let root = SVGBackend::new(&path, (1200, 600)).into_drawing_area();
+    root.fill(&WHITE).into_context(PlotError)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Measurement duration over time", ("sans-serif", 20).into_font())
+        .margin(5)
+        .set_left_and_bottom_label_area_size(40)
+        .build_cartesian_2d(0.0..max_x, 0.0..max_y)
+        .into_context(PlotError)?;
+
+    chart
+        .configure_mesh()
+        .x_desc("time since start")
+        .x_label_formatter(&|secs: &f64| {
+            humantime::format_duration(Duration::from_secs_f64(secs.max(0.0))).to_string()
+        })
+        .y_desc("duration (ms)")
+        .draw()
+        .into_context(PlotError)?;
+
+    chart
+        .draw_series(LineSeries::new(points.iter().copied(), &BLUE))
+        .into_context(PlotError)?;
+    chart
+        .draw_series(points.iter().map(|&(x, y)| Circle::new((x, y), 2, BLUE.filled())))
+        .into_context(PlotError)?;
+
+    root.present().into_context(PlotError)?;
+    Ok(())
+}
+
+/// Renders a Gantt-style timeline out of several [`TimeSeries`]: one horizontal row per
+/// [`ID`], each measurement drawn as a horizontal segment from its
+/// [`Measurement::start`](crate::perf::Measurement::start) to its
+/// [`Measurement::end`](crate::perf::Measurement::end), on a shared time axis spanning
+/// the earliest `start()` to the latest `end()` across all of `series`. Rows are colored
+/// distinctly so overlapping, concurrent work across threads is visible.
+pub fn perf_time(path: impl AsRef<Path>, series: &[(ID, TimeSeries)]) -> SvgResult<()> {
+    assert!(!series.is_empty());
+
+    let t0 = series
+        .iter()
+        .map(|(_, ts)| ts.start())
+        .min()
+        .expect("not empty");
+    let t1 = series
+        .iter()
+        .map(|(_, ts)| ts.end())
+        .max()
+        .expect("not empty");
+    let total = (t1 - t0).as_secs_f64().max(1e-9);
+
+    let num_rows = series.len();
+    let height = 80 + PERF_TIME_ROW_HEIGHT * num_rows as u32;
+
+    let root = SVGBackend::new(&path, (1200, height)).into_drawing_area();
+    root.fill(&WHITE).into_context(PlotError)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Measurements over time", ("sans-serif", 20).into_font())
+        .margin(5)
+        .set_left_and_bottom_label_area_size(60)
+        .build_cartesian_2d(0.0..total, (0..num_rows).into_segmented())
+        .into_context(PlotError)?;
+
+    chart
+        .configure_mesh()
+        .x_desc("time since start")
+        .x_label_formatter(&|secs: &f64| {
+            humantime::format_duration(Duration::from_secs_f64(secs.max(0.0))).to_string()
+        })
+        .y_label_formatter(&|row: &SegmentValue<usize>| match row {
+            SegmentValue::CenterOf(i) => series[*i].0.to_string(),
+            _ => String::new(),
+        })
+        .draw()
+        .into_context(PlotError)?;
+
+    for (row, (_, ts)) in series.iter().enumerate() {
+        let color = Palette99::pick(row).filled().stroke_width(8);
+        chart
+            .draw_series(ts.measurements().iter().map(|m| {
+                let x0 = (m.start() - t0).as_secs_f64();
+                let x1 = (m.end() - t0).as_secs_f64();
+                PathElement::new(
+                    vec![
+                        (x0, SegmentValue::CenterOf(row)),
+                        (x1, SegmentValue::CenterOf(row)),
+                    ],
+                    color,
+                )
+            }))
+            .into_context(PlotError)?;
+    }
+
+    root.present().into_context(PlotError)?;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use signal_hook::{consts::signal::*, low_level};
+
+/// Tracks how many times `SIGINT`/`SIGTERM` has been received, distinguishing a first,
+/// "soft" signal from a repeated, "hard" one. Long-running operations poll
+/// [`Cookie::is_terminating`]/[`Cookie::is_terminating_hard`] so a user's first Ctrl-C
+/// gets a clean, atomic stop instead of the process dying mid-write.
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    count: Arc<AtomicUsize>,
+}
+
+impl Cookie {
+    pub fn new() -> Result<Self, std::io::Error> {
+        let count = Arc::new(AtomicUsize::new(0));
+
+        for flag in [SIGINT, SIGTERM] {
+            let count = Arc::clone(&count);
+            // SAFETY: this only uses atomic stuff and functions the crate itself is using
+            // in signal handlers
+            unsafe {
+                low_level::register(flag, move || {
+                    let prev = count.fetch_add(1, Ordering::SeqCst);
+                    if prev >= 2 {
+                        let _ = low_level::emulate_default_handler(flag);
+                    }
+                })?;
+            };
+        }
+
+        Ok(Self { count })
+    }
+
+    /// `true` once at least one termination signal has been received.
+    pub fn is_terminating(&self) -> bool {
+        self.count.load(Ordering::SeqCst) >= 1
+    }
+
+    /// `true` once a second termination signal has been received, i.e. the user asked
+    /// to stop twice and wants it to actually be immediate.
+    pub fn is_terminating_hard(&self) -> bool {
+        self.count.load(Ordering::SeqCst) >= 2
+    }
+}
@@ -0,0 +1,269 @@
+//! Variable frame-sampling schedules: [`Timeline`] maps how far into a video extraction
+//! has progressed to the interval before the next frame, so a caller can sample densely
+//! early in a video and sparsely later (or vice versa) instead of seeking forward by a
+//! fixed [`Duration`] every time. See [`crate::frame_extractor::FrameExtractor::iter_timed`]
+//! for how this drives extraction.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use color_eyre::eyre::{self, Context};
+use serde::Deserialize;
+
+/// How the sampling interval eases from one [`TimelinePoint`] to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// Hold the earlier point's interval all the way up to the next point.
+    #[default]
+    Flat,
+    /// Interpolate the interval linearly.
+    Linear,
+    /// Ease in/out between the two intervals with the cubic `3t² − 2t³` smoothstep,
+    /// instead of changing at a constant rate like [`Curve::Linear`].
+    Smoothstep,
+    /// Interpolate the interval geometrically, scaling it by the same factor every
+    /// equal fraction of the way rather than by the same absolute amount. Useful when
+    /// the two intervals differ by an order of magnitude or more.
+    Exponential,
+}
+
+impl Curve {
+    /// Blends `prev` and `next` a fraction `t` (0.0..=1.0) of the way along the segment.
+    fn interpolate(self, prev: Duration, next: Duration, t: f64) -> Duration {
+        if let Curve::Exponential = self {
+            let prev_secs = prev.as_secs_f64().max(f64::MIN_POSITIVE);
+            let next_secs = next.as_secs_f64().max(f64::MIN_POSITIVE);
+            return Duration::from_secs_f64(prev_secs * (next_secs / prev_secs).powf(t));
+        }
+
+        let t = match self {
+            Curve::Flat => 0.0,
+            Curve::Linear => t,
+            Curve::Smoothstep => t * t * (3.0 - 2.0 * t),
+            Curve::Exponential => unreachable!("handled above"),
+        };
+        let prev_secs = prev.as_secs_f64();
+        let next_secs = next.as_secs_f64();
+        Duration::from_secs_f64(prev_secs + (next_secs - prev_secs) * t)
+    }
+}
+
+/// One control point of a [`Timeline`]: the sampling interval to use at fraction `x`
+/// (0.0 = the start of the video, 1.0 = the end) of the video's length, eased towards
+/// the next point's interval by `curve`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TimelinePoint {
+    /// Fraction of the video's length this point sits at, 0.0..=1.0.
+    pub x: f64,
+    /// The sampling interval at this point, in seconds.
+    pub y: f64,
+    /// How to interpolate from this point towards the next one.
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+impl TimelinePoint {
+    pub fn new(x: f64, y: Duration, curve: Curve) -> Self {
+        Self {
+            x,
+            y: y.as_secs_f64(),
+            curve,
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.y.max(0.0))
+    }
+}
+
+/// A piecewise sampling schedule, see the module docs. Always has at least one point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    // sorted by `x`
+    points: Vec<TimelinePoint>,
+}
+
+impl Timeline {
+    /// Builds a timeline from its control points, sorted by `x` but otherwise used as
+    /// given (duplicate/out-of-range `x`s are allowed; the segment before the first
+    /// point and after the last one just holds that point's interval).
+    ///
+    /// # Panics
+    /// Panics if `points` is empty.
+    pub fn new(mut points: Vec<TimelinePoint>) -> Self {
+        assert!(!points.is_empty(), "a timeline needs at least one point");
+        points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+        Self { points }
+    }
+
+    /// Parses a timeline out of a TOML table of `[[point]]` rows, each an `{x, y,
+    /// curve}` triple (`curve` is optional and defaults to [`Curve::Flat`]).
+    pub fn from_toml(toml: &str) -> eyre::Result<Self> {
+        #[derive(Deserialize)]
+        struct Rows {
+            point: Vec<TimelinePoint>,
+        }
+
+        let rows: Rows = toml::from_str(toml).wrap_err("Failed to parse the timeline")?;
+        eyre::ensure!(!rows.point.is_empty(), "A timeline needs at least one point");
+        Ok(Self::new(rows.point))
+    }
+
+    /// The sampling interval at fraction `x` (clamped to 0.0..=1.0) of the video's
+    /// length.
+    pub fn interval_at(&self, x: f64) -> Duration {
+        let x = x.clamp(0.0, 1.0);
+
+        match self
+            .points
+            .binary_search_by(|p| p.x.partial_cmp(&x).unwrap_or(Ordering::Equal))
+        {
+            Ok(i) => self.points[i].interval(),
+            Err(0) => self.points[0].interval(),
+            Err(i) if i >= self.points.len() => self.points[i - 1].interval(),
+            Err(i) => {
+                let prev = &self.points[i - 1];
+                let next = &self.points[i];
+                let span = next.x - prev.x;
+                let t = if span <= 0.0 { 0.0 } else { (x - prev.x) / span };
+                prev.curve.interpolate(prev.interval(), next.interval(), t)
+            }
+        }
+    }
+
+    /// Yields the sequence of positions to seek to when sampling a video of length
+    /// `video_len` against this timeline: starting at zero, each next position is the
+    /// last one plus [`Self::interval_at`] the previous position's fraction through the
+    /// video, stopping once `video_len` is passed.
+    pub fn sampler(&self, video_len: Duration) -> TimelineSampler<'_> {
+        TimelineSampler {
+            timeline: self,
+            video_len,
+            position: Some(Duration::ZERO),
+        }
+    }
+}
+
+pub struct TimelineSampler<'a> {
+    timeline: &'a Timeline,
+    video_len: Duration,
+    position: Option<Duration>,
+}
+
+impl Iterator for TimelineSampler<'_> {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let position = self.position?;
+        if position > self.video_len {
+            self.position = None;
+            return None;
+        }
+
+        let fraction = if self.video_len.is_zero() {
+            1.0
+        } else {
+            position.as_secs_f64() / self.video_len.as_secs_f64()
+        };
+
+        let step = self.timeline.interval_at(fraction);
+        // A zero-length interval would otherwise loop forever on the same position.
+        self.position = (!step.is_zero()).then(|| position + step);
+
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn single_point_is_flat_everywhere() {
+        let timeline = Timeline::new(vec![TimelinePoint::new(0.0, secs(2.0), Curve::Linear)]);
+        assert_eq!(secs(2.0), timeline.interval_at(0.0));
+        assert_eq!(secs(2.0), timeline.interval_at(0.5));
+        assert_eq!(secs(2.0), timeline.interval_at(1.0));
+    }
+
+    #[test]
+    fn flat_holds_until_the_next_point() {
+        let timeline = Timeline::new(vec![
+            TimelinePoint::new(0.0, secs(1.0), Curve::Flat),
+            TimelinePoint::new(1.0, secs(5.0), Curve::Flat),
+        ]);
+        assert_eq!(secs(1.0), timeline.interval_at(0.25));
+        assert_eq!(secs(1.0), timeline.interval_at(0.99));
+        assert_eq!(secs(5.0), timeline.interval_at(1.0));
+    }
+
+    #[test]
+    fn linear_interpolates_evenly() {
+        let timeline = Timeline::new(vec![
+            TimelinePoint::new(0.0, secs(1.0), Curve::Linear),
+            TimelinePoint::new(1.0, secs(3.0), Curve::Linear),
+        ]);
+        assert_eq!(secs(2.0), timeline.interval_at(0.5));
+    }
+
+    #[test]
+    fn smoothstep_is_symmetric_around_the_midpoint() {
+        let timeline = Timeline::new(vec![
+            TimelinePoint::new(0.0, secs(1.0), Curve::Smoothstep),
+            TimelinePoint::new(1.0, secs(3.0), Curve::Smoothstep),
+        ]);
+        assert_eq!(secs(2.0), timeline.interval_at(0.5));
+        assert!(timeline.interval_at(0.25) < secs(1.5));
+    }
+
+    #[test]
+    fn exponential_scales_geometrically() {
+        let timeline = Timeline::new(vec![
+            TimelinePoint::new(0.0, secs(1.0), Curve::Exponential),
+            TimelinePoint::new(1.0, secs(4.0), Curve::Exponential),
+        ]);
+        assert_eq!(secs(2.0), timeline.interval_at(0.5));
+    }
+
+    #[test]
+    fn out_of_range_clamps_to_the_nearest_endpoint() {
+        let timeline = Timeline::new(vec![
+            TimelinePoint::new(0.25, secs(1.0), Curve::Linear),
+            TimelinePoint::new(0.75, secs(2.0), Curve::Linear),
+        ]);
+        assert_eq!(secs(1.0), timeline.interval_at(0.0));
+        assert_eq!(secs(2.0), timeline.interval_at(1.0));
+    }
+
+    #[test]
+    fn parses_from_toml() {
+        let timeline = Timeline::from_toml(
+            r#"
+                [[point]]
+                x = 0.0
+                y = 1.0
+
+                [[point]]
+                x = 1.0
+                y = 5.0
+                curve = "smoothstep"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(secs(1.0), timeline.interval_at(0.0));
+        assert_eq!(secs(5.0), timeline.interval_at(1.0));
+    }
+
+    #[test]
+    fn sampler_integrates_the_interval() {
+        let timeline = Timeline::new(vec![TimelinePoint::new(0.0, secs(2.0), Curve::Flat)]);
+        let positions: Vec<Duration> = timeline.sampler(secs(5.0)).collect();
+        assert_eq!(vec![secs(0.0), secs(2.0), secs(4.0)], positions);
+    }
+}
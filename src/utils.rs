@@ -1,10 +1,22 @@
+pub mod archive;
+pub mod blurhash;
+pub mod clocks;
+pub mod colorspace;
+pub mod fast_resize;
 pub mod fsutils;
+pub mod image_decode;
+pub mod image_source;
 pub mod imgutils;
+pub mod job;
+pub mod logger;
 pub mod math;
+pub mod packed_repo;
 pub mod perf; // TODO: implement
 pub mod plot;
 pub mod priority_queue;
 pub mod repo;
+pub mod resize;
 pub mod simple_path;
+pub mod tar_repo;
 pub mod work_queue;
 pub mod workers;
@@ -0,0 +1,177 @@
+//! Packages a dedup run's loose output files into a single portable archive (tar or
+//! zip) instead of a directory tree. Every member name passes through
+//! [`SimplePath`]/[`SimplePathBuf`], whose relative/UTF-8/normal-only guarantee is
+//! exactly what archive member names need: [`ArchiveWriter::add`] can never be made to
+//! write an absolute-path or `..`-traversal member, and [`ArchiveReader::for_each_entry`]
+//! skips any entry that doesn't round-trip through the same check instead of trusting
+//! whatever a hand-edited or malicious archive claims its member names are.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use color_eyre::eyre::{self, Context}; // TODO: use custom error type instead
+
+use crate::utils::simple_path::{SimplePath, SimplePathBuf};
+
+/// Which archive format to read or write. Both are plain files any other tool can also
+/// open, unlike the bespoke formats used by [`super::repo`]/[`super::tar_repo`]/
+/// [`super::packed_repo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tar,
+    Zip,
+}
+
+impl Format {
+    /// Guesses the format from `path`'s extension. Only the exact, lowercase `tar` and
+    /// `zip` extensions are recognized; unlike [`super::image_source`], `cbz`/`cbt` and
+    /// uppercase variants are rejected.
+    pub fn from_extension(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("tar") => Ok(Self::Tar),
+            Some("zip") => Ok(Self::Zip),
+            ext => eyre::bail!("unrecognized archive extension: {ext:?}, expected tar or zip"),
+        }
+    }
+}
+
+enum WriterInner {
+    Tar(tar::Builder<BufWriter<File>>),
+    Zip(zip::ZipWriter<BufWriter<File>>),
+}
+
+/// Writer half, see the module docs.
+pub struct ArchiveWriter {
+    inner: WriterInner,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: impl AsRef<Path>, format: Format) -> eyre::Result<Self> {
+        let file = BufWriter::new(
+            File::create(path.as_ref()).wrap_err("failed to create the archive file")?,
+        );
+        let inner = match format {
+            Format::Tar => WriterInner::Tar(tar::Builder::new(file)),
+            Format::Zip => WriterInner::Zip(zip::ZipWriter::new(file)),
+        };
+        Ok(Self { inner })
+    }
+
+    /// Adds a member at `path`, streaming the rest of `data` as its content. `path`
+    /// being a [`SimplePath`] is what rules out an absolute-path or `..`-traversal
+    /// member ever reaching the underlying writer.
+    pub fn add(&mut self, path: &SimplePath, mut data: impl Read) -> eyre::Result<()> {
+        let mut buf = Vec::new();
+        data.read_to_end(&mut buf)
+            .wrap_err("failed to read the member's content")?;
+
+        match &mut self.inner {
+            WriterInner::Tar(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(buf.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, path.as_path(), buf.as_slice())
+                    .wrap_err("failed to append a tar member")?;
+            }
+            WriterInner::Zip(writer) => {
+                writer
+                    .start_file(path.to_string(), zip::write::FileOptions::default())
+                    .wrap_err("failed to start a zip member")?;
+                writer
+                    .write_all(&buf)
+                    .wrap_err("failed to write a zip member")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the archive's trailer, if any. The file is useless as an archive until
+    /// this is called.
+    pub fn finish(self) -> eyre::Result<()> {
+        match self.inner {
+            WriterInner::Tar(builder) => {
+                builder
+                    .into_inner()
+                    .wrap_err("failed to finish the tar archive")?;
+            }
+            WriterInner::Zip(mut writer) => {
+                writer
+                    .finish()
+                    .wrap_err("failed to finish the zip archive")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+enum ReaderInner {
+    Tar(tar::Archive<BufReader<File>>),
+    Zip(zip::ZipArchive<BufReader<File>>),
+}
+
+/// Reader half, see the module docs.
+pub struct ArchiveReader {
+    inner: ReaderInner,
+}
+
+impl ArchiveReader {
+    pub fn open(path: impl AsRef<Path>, format: Format) -> eyre::Result<Self> {
+        let file = File::open(path.as_ref()).wrap_err("failed to open the archive file")?;
+        let inner = match format {
+            Format::Tar => ReaderInner::Tar(tar::Archive::new(BufReader::new(file))),
+            Format::Zip => ReaderInner::Zip(
+                zip::ZipArchive::new(BufReader::new(file))
+                    .wrap_err("failed to read the zip archive's central directory")?,
+            ),
+        };
+        Ok(Self { inner })
+    }
+
+    /// Visits every member in archive order as `(path, content)`, skipping (with a
+    /// logged warning) any member whose name doesn't round-trip through
+    /// [`SimplePath::new_str`], e.g. an absolute path or one escaping via `..`.
+    pub fn for_each_entry<F>(&mut self, mut visit: F) -> eyre::Result<()>
+    where
+        F: FnMut(SimplePathBuf, &mut dyn Read) -> eyre::Result<()>,
+    {
+        match &mut self.inner {
+            ReaderInner::Tar(archive) => {
+                for entry in archive
+                    .entries()
+                    .wrap_err("failed to read the tar archive's entries")?
+                {
+                    let mut entry = entry.wrap_err("failed to read a tar entry")?;
+                    let name = entry
+                        .path()
+                        .wrap_err("failed to read an entry's path")?
+                        .display()
+                        .to_string();
+                    let Ok(name) = SimplePath::new_str(&name) else {
+                        log::warn!("skipping unsafe tar member: {name:?}");
+                        continue;
+                    };
+                    visit(name.to_owned(), &mut entry).wrap_err("the visitor failed")?;
+                }
+            }
+            ReaderInner::Zip(archive) => {
+                for i in 0..archive.len() {
+                    let mut member = archive
+                        .by_index(i)
+                        .wrap_err("failed to read a zip entry")?;
+                    let name = member.name().to_string();
+                    let Ok(path) = SimplePath::new_str(&name) else {
+                        log::warn!("skipping unsafe zip member: {name:?}");
+                        continue;
+                    };
+                    visit(path.to_owned(), &mut member).wrap_err("the visitor failed")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
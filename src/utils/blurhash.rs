@@ -0,0 +1,149 @@
+//! A compact DCT-based visual fingerprint for an image, see
+//! <https://github.com/woltapp/blurhash>. Produces a short, orderable string that can
+//! be embedded in a human-browsable dedup report instead of shipping a full thumbnail,
+//! complementing the perceptual hash already used for matching frames.
+
+use image::{Rgb, RgbImage};
+
+use super::imgutils;
+
+/// The component counts used by [`fingerprint`] unless the caller needs something
+/// else: enough detail for a thumbnail placeholder without bloating the string.
+pub const DEFAULT_X_COMPONENTS: u32 = 4;
+pub const DEFAULT_Y_COMPONENTS: u32 = 3;
+
+const BASE83_ALPHABET: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// A Blurhash string for `img` plus its average RGB color (see
+/// [`imgutils::average_color`]), using `x_components` by `y_components` DCT
+/// coefficients.
+pub fn fingerprint(img: &RgbImage, x_components: u32, y_components: u32) -> (String, Rgb<u8>) {
+    (
+        encode(img, x_components, y_components),
+        imgutils::average_color(img),
+    )
+}
+
+/// Encodes `img` as a Blurhash string using `x_components` by `y_components` DCT
+/// coefficients, each of which must be in `1..=9` per the format.
+pub fn encode(img: &RgbImage, x_components: u32, y_components: u32) -> String {
+    assert!((1..=9).contains(&x_components));
+    assert!((1..=9).contains(&y_components));
+
+    let width = img.width();
+    let height = img.height();
+    assert!(width > 0 && height > 0);
+
+    let factors: Vec<[f64; 3]> = (0..y_components)
+        .flat_map(|j| (0..x_components).map(move |i| (i, j)))
+        .map(|(i, j)| multiply_basis_function(img, i, j, width, height))
+        .collect();
+
+    let (dc, ac) = factors.split_first().expect("components are >= 1");
+
+    let mut hash = String::new();
+
+    let size_flag = (x_components - 1) + (y_components - 1) * 9;
+    encode_base83(size_flag, 1, &mut hash);
+
+    let maximum_value = if ac.is_empty() {
+        encode_base83(0, 1, &mut hash);
+        1.0
+    } else {
+        let actual_maximum = ac
+            .iter()
+            .flat_map(|&[r, g, b]| [r.abs(), g.abs(), b.abs()])
+            .fold(0.0_f64, f64::max);
+        let quantised_maximum = (actual_maximum * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        encode_base83(quantised_maximum, 1, &mut hash);
+        (quantised_maximum as f64 + 1.0) / 166.0
+    };
+
+    encode_base83(encode_dc(*dc), 4, &mut hash);
+
+    for &ac_value in ac {
+        encode_base83(encode_ac(ac_value, maximum_value), 2, &mut hash);
+    }
+
+    hash
+}
+
+/// The `(r, g, b)` contribution of every pixel in `img` to the `(x_component,
+/// y_component)` DCT basis function, i.e. one entry of the `factors` in the Blurhash
+/// spec (the very first, `(0, 0)`, is the DC/average-color term).
+fn multiply_basis_function(
+    img: &RgbImage,
+    x_component: u32,
+    y_component: u32,
+    width: u32,
+    height: u32,
+) -> [f64; 3] {
+    let normalisation = if x_component == 0 && y_component == 0 {
+        1.0
+    } else {
+        2.0
+    };
+
+    let mut rgb = [0.0_f64; 3];
+    for y in 0..height {
+        for x in 0..width {
+            let basis = (std::f64::consts::PI * x_component as f64 * x as f64 / width as f64)
+                .cos()
+                * (std::f64::consts::PI * y_component as f64 * y as f64 / height as f64).cos();
+            let Rgb([r, g, b]) = *img.get_pixel(x, y);
+            rgb[0] += basis * srgb_to_linear(r);
+            rgb[1] += basis * srgb_to_linear(g);
+            rgb[2] += basis * srgb_to_linear(b);
+        }
+    }
+
+    let scale = normalisation / (width * height) as f64;
+    [rgb[0] * scale, rgb[1] * scale, rgb[2] * scale]
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.0031308 {
+        v * 12.92 * 255.0 + 0.5
+    } else {
+        (1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5
+    };
+    srgb.clamp(0.0, 255.0) as u8
+}
+
+fn encode_dc(value: [f64; 3]) -> u32 {
+    let r = linear_to_srgb(value[0]) as u32;
+    let g = linear_to_srgb(value[1]) as u32;
+    let b = linear_to_srgb(value[2]) as u32;
+    (r << 16) + (g << 8) + b
+}
+
+fn encode_ac(value: [f64; 3], maximum_value: f64) -> u32 {
+    let quantize = |v: f64| -> u32 {
+        (sign_pow(v / maximum_value, 0.5) * 9.0 + 9.5)
+            .floor()
+            .clamp(0.0, 18.0) as u32
+    };
+    quantize(value[0]) * 19 * 19 + quantize(value[1]) * 19 + quantize(value[2])
+}
+
+fn sign_pow(value: f64, exponent: f64) -> f64 {
+    value.signum() * value.abs().powf(exponent)
+}
+
+fn encode_base83(value: u32, length: usize, out: &mut String) {
+    for i in (0..length).rev() {
+        let digit = (value / 83u32.pow(i as u32)) % 83;
+        out.push(BASE83_ALPHABET[digit as usize] as char);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// A pluggable source of "now", so code that stamps wall-clock or monotonic time (log
+/// lines, "processed at" metadata) can be driven by [`SimulatedClocks`] in a test
+/// instead of hard coding [`SystemTime::now`]/[`Instant::now`], the same way
+/// [`super::logger::Logger`] decouples status reporting from the [`log`] crate.
+pub trait Clocks: Sync {
+    fn realtime(&self) -> SystemTime;
+    fn monotonic(&self) -> Instant;
+}
+
+/// Forwards straight to the OS clock. What every binary uses in production.
+pub struct RealClocks;
+
+impl Clocks for RealClocks {
+    fn realtime(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn monotonic(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, so time-dependent behavior can be asserted
+/// against exact, reproducible instants instead of racing the real clock.
+///
+/// `monotonic()` is derived from a single [`Instant::now`] taken at construction plus
+/// the simulated offset, since [`Instant`] has no user-constructible epoch to fake.
+pub struct SimulatedClocks {
+    realtime: Mutex<SystemTime>,
+    monotonic_epoch: Instant,
+    monotonic_offset: Mutex<Duration>,
+}
+
+impl SimulatedClocks {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            realtime: Mutex::new(start),
+            monotonic_epoch: Instant::now(),
+            monotonic_offset: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves both the realtime and monotonic clocks forward by `dur`, in lockstep, so
+    /// e.g. a 1-second log timeout measured against [`Clocks::monotonic`] lines up with
+    /// the timestamp the same second would produce on [`Clocks::realtime`].
+    pub fn advance(&self, dur: Duration) {
+        *self.realtime.lock().unwrap() += dur;
+        *self.monotonic_offset.lock().unwrap() += dur;
+    }
+}
+
+impl Clocks for SimulatedClocks {
+    fn realtime(&self) -> SystemTime {
+        *self.realtime.lock().unwrap()
+    }
+
+    fn monotonic(&self) -> Instant {
+        self.monotonic_epoch + *self.monotonic_offset.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn advances_both_clocks_in_lockstep() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clocks = SimulatedClocks::new(start);
+
+        let realtime_before = clocks.realtime();
+        let monotonic_before = clocks.monotonic();
+
+        clocks.advance(Duration::from_secs(5));
+
+        assert_eq!(
+            Duration::from_secs(5),
+            clocks.realtime().duration_since(realtime_before).unwrap()
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            clocks.monotonic() - monotonic_before
+        );
+    }
+
+    #[test]
+    fn stays_put_without_advance() {
+        let clocks = SimulatedClocks::new(SystemTime::UNIX_EPOCH);
+        assert_eq!(clocks.realtime(), clocks.realtime());
+        assert_eq!(clocks.monotonic(), clocks.monotonic());
+    }
+}
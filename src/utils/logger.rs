@@ -0,0 +1,33 @@
+use std::fmt::Arguments;
+
+/// A pluggable sink for status/progress updates, so a long-running piece of code like
+/// [`super::job::Job`] can report through whatever the caller wants -- the [`log`] crate
+/// by default via [`LogLogger`], or something else entirely in a test -- without hard
+/// coding `log::info!` calls into it.
+pub trait Logger: Sync {
+    fn log(&self, level: Level, body: Arguments<'_>);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Forwards to the [`log`] crate, which is what every binary in this crate already sets
+/// up via [`crate::bin_common::init::init_logger`].
+pub struct LogLogger;
+
+impl Logger for LogLogger {
+    fn log(&self, level: Level, body: Arguments<'_>) {
+        let level = match level {
+            Level::Debug => log::Level::Debug,
+            Level::Info => log::Level::Info,
+            Level::Warn => log::Level::Warn,
+            Level::Error => log::Level::Error,
+        };
+        log::log!(level, "{}", body);
+    }
+}
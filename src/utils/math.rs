@@ -18,6 +18,22 @@ impl Average {
     pub fn average(&self) -> f64 {
         self.avg
     }
+
+    /// Combines two accumulators into the one that would have resulted from feeding
+    /// `other`'s values into `self` directly, without re-streaming either's inputs.
+    /// Lets partial averages computed on separate shards be folded together exactly.
+    pub fn merge(self, other: Self) -> Self {
+        let k = self.k + other.k;
+        if k == 0.0 {
+            return Self::new();
+        }
+
+        let delta = other.avg - self.avg;
+        Self {
+            avg: self.avg + delta * other.k / k,
+            k,
+        }
+    }
 }
 
 impl<A: Into<f64>> Extend<A> for Average {
@@ -26,6 +42,14 @@ impl<A: Into<f64>> Extend<A> for Average {
     }
 }
 
+impl std::ops::Add for Average {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.merge(other)
+    }
+}
+
 pub struct Variance {
     avg: Average,
     var: f64,
@@ -70,6 +94,27 @@ impl Variance {
     pub fn std_dev(&self) -> f64 {
         self.variance().sqrt()
     }
+
+    /// Combines two accumulators into the one that would have resulted from feeding
+    /// `other`'s values into `self` directly, without re-streaming either's inputs. `var`
+    /// is the sum of squared deviations from the mean (M2), so the two sums can't just be
+    /// added: each was computed against a different mean, so the pairwise-combine formula
+    /// below also folds in the squared gap between the two means, weighted by how many
+    /// samples went into each side. Lets the variance over a large, shardable data set be
+    /// computed map-reduce style across worker threads and folded together exactly.
+    pub fn merge(self, other: Self) -> Self {
+        let (n_a, n_b) = (self.avg.k, other.avg.k);
+        let n = n_a + n_b;
+        if n == 0.0 {
+            return Self::new();
+        }
+
+        let delta = other.avg.average() - self.avg.average();
+        Self {
+            var: self.var + other.var + delta * delta * n_a * n_b / n,
+            avg: self.avg.merge(other.avg),
+        }
+    }
 }
 
 impl<A: Into<f64>> Extend<A> for Variance {
@@ -78,6 +123,194 @@ impl<A: Into<f64>> Extend<A> for Variance {
     }
 }
 
+impl std::ops::Add for Variance {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.merge(other)
+    }
+}
+
+/// Online min/max/mean/variance plus an approximate estimate of one target quantile, all
+/// in O(1) memory regardless of how many samples are seen -- useful for reporting the
+/// distribution of e.g. hash distances over a whole dedup run without having to collect
+/// every distance first.
+///
+/// The quantile estimate uses the P² algorithm (Jain & Chlamtac, 1985): five markers
+/// track the min, the `p/2`, `p`, and `(1+p)/2` quantiles, and the max. Every sample
+/// nudges each interior marker's desired position, and a marker whose actual position has
+/// drifted more than one away from where it should be is re-estimated with a
+/// piecewise-parabolic formula, falling back to linear interpolation on the rare occasion
+/// the parabolic step would push it past a neighbor.
+pub struct Summary {
+    target: f64,
+    count: u64,
+    min: f64,
+    max: f64,
+    variance: Variance,
+    markers: P2,
+}
+
+enum P2 {
+    /// The first 5 samples needed to seed the markers, buffered until there are enough.
+    Warming(Vec<f64>),
+    Ready {
+        /// Each marker's actual position (how many samples have landed at or before it).
+        n: [f64; 5],
+        /// Each marker's desired (fractional) position, drifting by `dn` every sample.
+        npos: [f64; 5],
+        /// How much each marker's desired position should drift per sample.
+        dn: [f64; 5],
+        /// Each marker's current height -- `q[2]` is the quantile estimate.
+        q: [f64; 5],
+    },
+}
+
+impl Summary {
+    /// `target_quantile` is the single quantile (in `[0, 1]`) this `Summary` will track;
+    /// the P² markers are seeded around it and can't be repurposed for a different
+    /// quantile afterwards.
+    pub fn new(target_quantile: f64) -> Self {
+        Self {
+            target: target_quantile,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            variance: Variance::new(),
+            markers: P2::Warming(Vec::with_capacity(5)),
+        }
+    }
+
+    pub fn add(&mut self, value: impl Into<f64>) {
+        let value = value.into();
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.variance.add(value);
+        self.markers.add(value, self.target);
+    }
+
+    pub fn min(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.min
+        }
+    }
+
+    pub fn max(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.max
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.variance.average()
+    }
+
+    pub fn variance(&self) -> f64 {
+        self.variance.variance()
+    }
+
+    /// Returns the estimate of the quantile this `Summary` was constructed to track. `p`
+    /// must match the `target_quantile` given to [`Self::new`] -- the markers are seeded
+    /// around one specific target and can't answer a different quantile after the fact --
+    /// and is taken here only so a call site reads as a query rather than a bare getter.
+    pub fn quantile(&self, p: f64) -> f64 {
+        debug_assert_eq!(
+            p, self.target,
+            "Summary only tracks the quantile it was constructed with"
+        );
+        self.markers.quantile(self.target)
+    }
+}
+
+impl P2 {
+    fn add(&mut self, value: f64, p: f64) {
+        match self {
+            P2::Warming(buf) => {
+                buf.push(value);
+                if buf.len() == 5 {
+                    let mut sorted = std::mem::take(buf);
+                    sorted.sort_by(|a, b| a.total_cmp(b));
+                    let q: [f64; 5] = sorted.try_into().expect("exactly 5 samples buffered");
+                    *self = P2::Ready {
+                        n: [1.0, 2.0, 3.0, 4.0, 5.0],
+                        npos: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+                        dn: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+                        q,
+                    };
+                }
+            }
+            P2::Ready { n, npos, dn, q } => {
+                let k = if value < q[0] {
+                    q[0] = value;
+                    0
+                } else if value >= q[4] {
+                    q[4] = value;
+                    3
+                } else {
+                    (0..4)
+                        .find(|&i| q[i] <= value && value < q[i + 1])
+                        .unwrap_or(3)
+                };
+
+                for ni in n.iter_mut().skip(k + 1) {
+                    *ni += 1.0;
+                }
+                for (npi, dni) in npos.iter_mut().zip(dn.iter()) {
+                    *npi += dni;
+                }
+
+                for i in 1..4 {
+                    let d = npos[i] - n[i];
+                    let moves_right = d >= 1.0 && n[i + 1] - n[i] > 1.0;
+                    let moves_left = d <= -1.0 && n[i - 1] - n[i] < -1.0;
+                    if moves_right || moves_left {
+                        let d_sign = d.signum();
+                        let parabolic = parabolic_prediction(n, q, i, d_sign);
+                        q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                            parabolic
+                        } else {
+                            linear_prediction(n, q, i, d_sign)
+                        };
+                        n[i] += d_sign;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Falls back to a plain sorted lookup over whatever's been buffered so far while
+    /// still [`P2::Warming`] up, since there aren't enough samples yet to seed the
+    /// markers.
+    fn quantile(&self, p: f64) -> f64 {
+        match self {
+            P2::Warming(buf) if buf.is_empty() => 0.0,
+            P2::Warming(buf) => {
+                let mut sorted = buf.clone();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                let rank = ((sorted.len() - 1) as f64 * p).round() as usize;
+                sorted[rank]
+            }
+            P2::Ready { q, .. } => q[2],
+        }
+    }
+}
+
+fn parabolic_prediction(n: &[f64; 5], q: &[f64; 5], i: usize, d: f64) -> f64 {
+    q[i] + d / (n[i + 1] - n[i - 1])
+        * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+            + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+}
+
+fn linear_prediction(n: &[f64; 5], q: &[f64; 5], i: usize, d: f64) -> f64 {
+    let j = (i as f64 + d) as usize;
+    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -112,4 +345,77 @@ mod test {
         assert!(float_cmp(2.0 / 2.0, var.variance()));
         assert!(float_cmp(2.0 / 3.0, var.biased_variance()));
     }
+
+    #[test]
+    fn average_merge_matches_a_single_pass() {
+        let mut whole = Average::new();
+        whole.extend(vec![1, 2, 3, 4, 5, 6]);
+
+        let mut a = Average::new();
+        a.extend(vec![1, 2, 3]);
+        let mut b = Average::new();
+        b.extend(vec![4, 5, 6]);
+
+        assert!(float_cmp(whole.average(), a.merge(b).average()));
+    }
+
+    #[test]
+    fn average_merge_with_an_empty_accumulator_is_a_no_op() {
+        let mut a = Average::new();
+        a.extend(vec![1, 2, 3]);
+
+        assert!(float_cmp(a.average(), a.merge(Average::new()).average()));
+        assert_eq!(0.0, (Average::new() + Average::new()).average());
+    }
+
+    #[test]
+    fn variance_merge_matches_a_single_pass() {
+        let mut whole = Variance::new();
+        whole.extend(vec![1, 2, 3, 4, 5, 6, 7]);
+
+        let mut a = Variance::new();
+        a.extend(vec![1, 2, 3]);
+        let mut b = Variance::new();
+        b.extend(vec![4, 5, 6, 7]);
+
+        let merged = a + b;
+        assert!(float_cmp(whole.average(), merged.average()));
+        assert!(float_cmp(whole.variance(), merged.variance()));
+    }
+
+    #[test]
+    fn summary_tracks_min_max_mean_and_variance() {
+        let mut summary = Summary::new(0.5);
+        assert_eq!(0.0, summary.min());
+        assert_eq!(0.0, summary.max());
+
+        for i in 1..=7 {
+            summary.add(i);
+        }
+
+        assert!(float_cmp(1.0, summary.min()));
+        assert!(float_cmp(7.0, summary.max()));
+        assert!(float_cmp(4.0, summary.mean()));
+        assert!(float_cmp(28.0 / 6.0, summary.variance()));
+    }
+
+    #[test]
+    fn summary_estimates_the_median_of_an_increasing_sequence() {
+        let mut summary = Summary::new(0.5);
+        for i in 1..=21 {
+            summary.add(i);
+        }
+
+        assert!(float_cmp(11.0, summary.quantile(0.5)));
+    }
+
+    #[test]
+    fn summary_estimates_a_high_quantile_over_many_samples() {
+        let mut summary = Summary::new(0.9);
+        for i in 1..=1000 {
+            summary.add(i);
+        }
+
+        assert!((summary.quantile(0.9) - 900.0).abs() <= 5.0);
+    }
 }
@@ -0,0 +1,343 @@
+//! A single-artifact alternative to [`super::repo::Repo`]/[`super::repo::Entry`],
+//! modeled on Deno's `VfsBuilder`: instead of one small file or symlink per thumbnail,
+//! every payload is appended to one contiguous data file, and a manifest mapping each
+//! logical entry path (the same numbered/named scheme [`Entry::next_path`] generates) to
+//! where it lives is kept alongside it. Good for dedup runs large enough that a regular
+//! [`Repo`] would otherwise produce hundreds of thousands of tiny files and symlinks.
+//!
+//! [`Entry::next_path`]: super::repo::Entry
+//! [`Repo`]: super::repo::Repo
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::eyre::{self, Context}; // TODO: use custom error type instead
+use image::{ImageBuffer, ImageOutputFormat};
+use memmap2::Mmap;
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch,
+            HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+use super::fsutils;
+
+const ENTRY_PADDING: usize = 4;
+
+/// Extension of the contiguous data file a [`PackedRepo`] appends every payload into.
+pub const DATA_EXTENSION: &str = "data";
+/// Extension of the rkyv-serialized manifest sidecar written next to the data file.
+pub const MANIFEST_EXTENSION: &str = "manifest";
+
+type ManifestSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<8192>, AllocScratch>,
+>;
+
+/// Where one logical entry lives: either a byte range in the data file, or, for what
+/// would otherwise be a symlink, the external target path it points at.
+#[derive(Debug, Clone, Serialize, Archive)]
+#[archive(check_bytes)]
+enum ManifestRecord {
+    Blob { offset: u64, length: u64 },
+    Link { target: String },
+}
+
+#[derive(Debug, Serialize, Archive)]
+#[archive(check_bytes)]
+struct Manifest {
+    entries: HashMap<String, ManifestRecord>,
+}
+
+/// The state shared by every [`PackedEntry`] handed out by the same [`PackedRepo`]: the
+/// data file being appended to, how far into it has been committed, and the manifest
+/// built up so far. Behind a `Mutex` since entries are handed out independently (mirrors
+/// how a plain [`Entry`](super::repo::Entry) owns its own directory) but, unlike a plain
+/// entry, every one of them writes into this same file.
+struct Shared {
+    data_file: File,
+    current_offset: u64,
+    manifest: HashMap<String, ManifestRecord>,
+}
+
+/// Writer half of the packed format. Hands out [`PackedEntry`]s the same way
+/// [`Repo`](super::repo::Repo) hands out [`Entry`](super::repo::Entry)s, but every entry
+/// appends into one shared data file instead of creating its own directory.
+pub struct PackedRepo {
+    manifest_path: PathBuf,
+    shared: Arc<Mutex<Shared>>,
+    next_entry: u32,
+}
+
+/// Entry half of the packed format, see [`PackedRepo`].
+pub struct PackedEntry {
+    shared: Arc<Mutex<Shared>>,
+    dir: String,
+    next_entry: u32,
+}
+
+impl PackedRepo {
+    /// `path` is the base path of the artifact; the data file is written to
+    /// `path` + [`DATA_EXTENSION`] and the manifest to `path` + [`MANIFEST_EXTENSION`].
+    pub fn new(path: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let base = path.into();
+        let data_path = base.with_extension(DATA_EXTENSION);
+        let manifest_path = base.with_extension(MANIFEST_EXTENSION);
+
+        let data_file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&data_path)
+            .wrap_err("could not create the packed data file")?;
+
+        Ok(Self {
+            manifest_path,
+            shared: Arc::new(Mutex::new(Shared {
+                data_file,
+                current_offset: 0,
+                manifest: HashMap::new(),
+            })),
+            next_entry: 0,
+        })
+    }
+
+    pub fn new_entry(&mut self) -> eyre::Result<PackedEntry> {
+        let p = ENTRY_PADDING;
+        let dir = format!("{:0p$}", self.next_entry);
+        self.next_entry += 1;
+        Ok(PackedEntry {
+            shared: Arc::clone(&self.shared),
+            dir,
+            next_entry: 0,
+        })
+    }
+
+    /// Flushes the data file and writes the manifest sidecar, sealing the two files
+    /// into their final form. Every [`PackedEntry`] handed out must have been dropped
+    /// first, since the manifest can only be written once nothing can add to it anymore.
+    pub fn finish(self) -> eyre::Result<()> {
+        let shared = Arc::try_unwrap(self.shared)
+            .map_err(|_| eyre::eyre!("a packed entry is still alive"))?
+            .into_inner()
+            .expect("the mutex is never poisoned");
+
+        shared
+            .data_file
+            .sync_all()
+            .wrap_err("failed to flush the packed data file")?;
+
+        let manifest = Manifest {
+            entries: shared.manifest,
+        };
+        let bytes = serialize_manifest(&manifest)
+            .wrap_err("failed to serialize the manifest")?;
+        fs::write(&self.manifest_path, &bytes)
+            .wrap_err("failed to write the manifest file")?;
+
+        Ok(())
+    }
+}
+
+fn serialize_manifest(manifest: &Manifest) -> eyre::Result<AlignedVec> {
+    let mut seri = ManifestSerializer::default();
+    seri.serialize_value(manifest)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
+
+impl PackedEntry {
+    fn next_path(&mut self, name: &Path) -> String {
+        let p = ENTRY_PADDING;
+        let mut num: OsString = format!("{:0p$}", self.next_entry).into();
+        num.push("_");
+        num.push(name);
+        self.next_entry += 1;
+        format!("{}/{}", self.dir, num.to_string_lossy())
+    }
+
+    pub fn sub_entry(&mut self, name: impl AsRef<Path>) -> eyre::Result<Self> {
+        let name = name.as_ref();
+        assert!(fsutils::is_basename(name));
+        let dir = self.next_path(name);
+        Ok(Self {
+            shared: Arc::clone(&self.shared),
+            dir,
+            next_entry: 0,
+        })
+    }
+
+    pub fn create_file<F>(&mut self, name: impl AsRef<Path>, writer: F) -> eyre::Result<()>
+    where
+        F: FnOnce(&mut Vec<u8>) -> eyre::Result<()>,
+    {
+        let name = name.as_ref();
+        assert!(fsutils::is_basename(name));
+        let key = self.next_path(name);
+
+        let mut buf = Vec::new();
+        writer(&mut buf).wrap_err("the writer failed")?;
+
+        let mut shared = self.shared.lock().unwrap();
+        let offset = shared.current_offset;
+        let length = buf.len() as u64;
+        shared
+            .data_file
+            .write_all(&buf)
+            .wrap_err("failed to append to the packed data file")?;
+        shared.current_offset += length;
+        shared
+            .manifest
+            .insert(key, ManifestRecord::Blob { offset, length });
+        Ok(())
+    }
+
+    /// Unlike [`Entry::create_link`](super::repo::Entry::create_link), this does not
+    /// touch the filesystem: `target` is simply recorded in the manifest, to be
+    /// resolved by a [`PackedReader`] on read.
+    pub fn create_link(
+        &mut self,
+        link_name: impl AsRef<Path>,
+        target: impl AsRef<Path>,
+    ) -> eyre::Result<()> {
+        let link_name = link_name.as_ref();
+        assert!(fsutils::is_basename(link_name));
+        let key = self.next_path(link_name);
+        let target = target.as_ref().to_string_lossy().into_owned();
+        self.shared
+            .lock()
+            .unwrap()
+            .manifest
+            .insert(key, ManifestRecord::Link { target });
+        Ok(())
+    }
+
+    pub fn create_jpg<P, C>(
+        &mut self,
+        jpg_name: impl AsRef<Path>,
+        image: &ImageBuffer<P, C>,
+    ) -> eyre::Result<()>
+    where
+        P: image::Pixel + image::PixelWithColorType,
+        [P::Subpixel]: image::EncodableLayout,
+        C: std::ops::Deref<Target = [P::Subpixel]>,
+    {
+        let jpg_name = jpg_name.as_ref();
+        assert!(fsutils::is_basename(jpg_name));
+        let jpg_name = Path::new(jpg_name).with_extension("jpg");
+        self.create_file(jpg_name, |buf| {
+            image
+                .write_to(&mut std::io::Cursor::new(buf), ImageOutputFormat::Jpeg(95))
+                .wrap_err("image failed to write")
+        })
+    }
+
+    pub fn create_text_file(
+        &mut self,
+        txt_name: impl AsRef<Path>,
+        contents: impl AsRef<str>,
+    ) -> eyre::Result<()> {
+        let txt_name = txt_name.as_ref();
+        assert!(fsutils::is_basename(txt_name));
+        let txt_name = Path::new(txt_name).with_extension("txt");
+        self.create_file(txt_name, |buf| {
+            buf.extend_from_slice(contents.as_ref().as_bytes());
+            Ok(())
+        })
+    }
+}
+
+enum Resolved<'a> {
+    Blob(&'a [u8]),
+    Link(&'a str),
+}
+
+/// Read-only side of the packed format: resolves a logical entry path, as produced by
+/// [`PackedEntry`], to its bytes via mmap, without ever loading the whole data file into
+/// memory.
+pub struct PackedReader {
+    data: Mmap,
+    manifest: Mmap,
+}
+
+impl PackedReader {
+    pub fn open(path: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let base = path.into();
+        let data_path = base.with_extension(DATA_EXTENSION);
+        let manifest_path = base.with_extension(MANIFEST_EXTENSION);
+
+        let data_file =
+            File::open(&data_path).wrap_err("failed to open the packed data file")?;
+        let manifest_file = File::open(&manifest_path)
+            .wrap_err("failed to open the packed manifest file")?;
+
+        // SAFETY: same caveat as every other mmap in this codebase, the file must not
+        // be modified by another process while mapped.
+        let data = unsafe { Mmap::map(&data_file) }
+            .wrap_err("failed to mmap the packed data file")?;
+        let manifest = unsafe { Mmap::map(&manifest_file) }
+            .wrap_err("failed to mmap the packed manifest file")?;
+
+        rkyv::check_archived_root::<Manifest>(&manifest)
+            .map_err(|e| eyre::eyre!("the manifest failed validation: {e}"))?;
+
+        Ok(Self { data, manifest })
+    }
+
+    fn manifest(&self) -> &ArchivedManifest {
+        // SAFETY: validated in `open`.
+        unsafe { rkyv::archived_root::<Manifest>(&self.manifest) }
+    }
+
+    fn resolve(&self, logical_path: &str) -> eyre::Result<Resolved<'_>> {
+        let record = self
+            .manifest()
+            .entries
+            .get(logical_path)
+            .ok_or_else(|| eyre::eyre!("no such entry: {logical_path:?}"))?;
+
+        Ok(match record {
+            ArchivedManifestRecord::Blob { offset, length } => {
+                let start: usize = (*offset).try_into().expect("expecting 64 bit arch");
+                let end = start + usize::try_from(*length).expect("expecting 64 bit arch");
+                let bytes = self.data.get(start..end).ok_or_else(|| {
+                    eyre::eyre!("entry {logical_path:?} is out of range of the data file")
+                })?;
+                Resolved::Blob(bytes)
+            }
+            ArchivedManifestRecord::Link { target } => Resolved::Link(target.as_str()),
+        })
+    }
+
+    /// Resolves `logical_path` to a borrowed slice of the mmap. Fails if it names a
+    /// link instead of a blob; see [`PackedReader::read`] to follow links too.
+    pub fn read_blob(&self, logical_path: &str) -> eyre::Result<&[u8]> {
+        match self.resolve(logical_path)? {
+            Resolved::Blob(bytes) => Ok(bytes),
+            Resolved::Link(target) => {
+                eyre::bail!("entry {logical_path:?} is a link to {target:?}, not a blob")
+            }
+        }
+    }
+
+    /// Resolves `logical_path`, following a link by reading its external target off
+    /// disk instead of the packed data file.
+    pub fn read(&self, logical_path: &str) -> eyre::Result<Vec<u8>> {
+        match self.resolve(logical_path)? {
+            Resolved::Blob(bytes) => Ok(bytes.to_vec()),
+            Resolved::Link(target) => {
+                fs::read(target).wrap_err("failed to read the link target")
+            }
+        }
+    }
+}
@@ -6,11 +6,12 @@ use std::{
 };
 
 use color_eyre::eyre::{self, Context}; // TODO: use custom error type instead
-use image::{ImageBuffer, ImageOutputFormat};
+use image::{ImageBuffer, ImageOutputFormat, RgbImage};
 
 use crate::utils::simple_path::SimplePath;
 
 use super::fsutils;
+use super::resize::{self, Format, ResizeOp};
 
 const ENTRY_PADDING: usize = 4;
 
@@ -175,6 +176,39 @@ impl Entry {
                 .wrap_err("failed to write string")
         })
     }
+
+    /// Resizes `img` per `op` and writes it as a JPEG thumbnail in a subdirectory of
+    /// this entry named `name`. The file itself is named by [`resize::thumbnail_filename`],
+    /// not by [`Entry::next_path`]'s usual numbering, so calling this again with the same
+    /// `img` and `op` is a no-op instead of writing a duplicate file -- that's the whole
+    /// point of a content-addressed name. Returns the path the thumbnail was written to
+    /// (or already existed at).
+    pub fn create_thumbnail(
+        &mut self,
+        name: impl AsRef<Path>,
+        img: &RgbImage,
+        op: ResizeOp,
+    ) -> eyre::Result<PathBuf> {
+        let name = name.as_ref();
+        assert!(fsutils::is_basename(name));
+        let dir = self.path.join(name);
+        fs::create_dir_all(&dir).wrap_err("could not create the thumbnail dir")?;
+
+        let format = Format::Jpeg;
+        let thumb_path = dir.join(resize::thumbnail_filename(img, op, format));
+        if thumb_path
+            .try_exists()
+            .wrap_err("failed to check for an existing thumbnail")?
+        {
+            return Ok(thumb_path);
+        }
+
+        let resized = op.apply(img);
+        let bytes = resize::encode(&resized, format).wrap_err("failed to encode the thumbnail")?;
+        fs::write(&thumb_path, &bytes).wrap_err("failed to write the thumbnail")?;
+
+        Ok(thumb_path)
+    }
 }
 
 impl LazyEntry {
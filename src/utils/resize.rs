@@ -0,0 +1,139 @@
+//! Downsized previews for [`super::repo::Entry`]/[`super::packed_repo::PackedEntry`]:
+//! a handful of [`ResizeOp`]s plus JPEG/PNG encoding, and a content-addressed
+//! [`thumbnail_filename`] so hashing and resizing the same source image twice with the
+//! same op lands on the same output name instead of writing a duplicate file.
+
+use color_eyre::eyre::{self, Context};
+use image::imageops::{self, FilterType};
+use image::{ImageOutputFormat, RgbImage};
+
+/// How to resize an image into a thumbnail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResizeOp {
+    /// Resize to exactly `width` by `height`, ignoring the original aspect ratio.
+    Scale(u32, u32),
+    /// Resize to `width`, keeping the aspect ratio.
+    FitWidth(u32),
+    /// Resize to `height`, keeping the aspect ratio.
+    FitHeight(u32),
+    /// Shrink to fit within a `width` by `height` bounding box, keeping the aspect
+    /// ratio. Never enlarges an image already smaller than the box.
+    FitWithin(u32, u32),
+}
+
+impl ResizeOp {
+    pub fn apply(self, img: &RgbImage) -> RgbImage {
+        let (width, height) = (img.width(), img.height());
+        let (new_width, new_height) = match self {
+            ResizeOp::Scale(w, h) => (w, h),
+            ResizeOp::FitWidth(w) => (w, scaled_dimension(height, width, w)),
+            ResizeOp::FitHeight(h) => (scaled_dimension(width, height, h), h),
+            ResizeOp::FitWithin(max_w, max_h) => {
+                let scale = f64::min(
+                    max_w as f64 / width as f64,
+                    max_h as f64 / height as f64,
+                )
+                .min(1.0);
+                (
+                    (width as f64 * scale).round() as u32,
+                    (height as f64 * scale).round() as u32,
+                )
+            }
+        };
+
+        imageops::resize(img, new_width.max(1), new_height.max(1), FilterType::Lanczos3)
+    }
+
+    /// A single byte identifying this op, used by [`thumbnail_filename`] to distinguish
+    /// thumbnails of the same source image generated with different ops. Not meant to be
+    /// reversible, just short and stable.
+    fn tag(self) -> u8 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish() as u8
+    }
+}
+
+/// Which format [`encode`] should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpg",
+            Format::Png => "png",
+        }
+    }
+
+    fn output_format(self) -> ImageOutputFormat {
+        match self {
+            Format::Jpeg => ImageOutputFormat::Jpeg(95),
+            Format::Png => ImageOutputFormat::Png,
+        }
+    }
+}
+
+/// Encodes `img` as `format`.
+pub fn encode(img: &RgbImage, format: Format) -> eyre::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), format.output_format())
+        .wrap_err("image failed to encode")?;
+    Ok(bytes)
+}
+
+/// The filename [`super::repo::Entry::create_thumbnail`] writes a thumbnail of `source`
+/// under: 16 hex digits of a [`blake3`] hash of `source`'s raw pixels, plus 2 hex digits
+/// of `op`'s [`ResizeOp::tag`], so the same source image resized with the same op always
+/// lands on the same filename and a re-run can skip regenerating it.
+pub fn thumbnail_filename(source: &RgbImage, op: ResizeOp, format: Format) -> String {
+    let digest = blake3::hash(source.as_raw());
+    let content = &digest.to_hex()[..16];
+    format!("{content}{:02x}.{}", op.tag(), format.extension())
+}
+
+/// `new_dim`, scaled the same way `old_dim` would be to reach `new_other_dim` from
+/// `old_other_dim`, i.e. keeping `old_other_dim / old_dim` constant.
+fn scaled_dimension(old_dim: u32, old_other_dim: u32, new_other_dim: u32) -> u32 {
+    ((new_other_dim as u64 * old_dim as u64) / old_other_dim as u64) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::imgutils::filled;
+
+    #[test]
+    fn fit_width_keeps_aspect_ratio() {
+        let img = filled(100, 50, 255, 255, 255);
+        let resized = ResizeOp::FitWidth(40).apply(&img);
+        assert_eq!((40, 20), (resized.width(), resized.height()));
+    }
+
+    #[test]
+    fn fit_within_never_enlarges() {
+        let img = filled(10, 10, 255, 255, 255);
+        let resized = ResizeOp::FitWithin(100, 100).apply(&img);
+        assert_eq!((10, 10), (resized.width(), resized.height()));
+    }
+
+    #[test]
+    fn same_source_and_op_gives_the_same_filename() {
+        let img = filled(20, 20, 1, 2, 3);
+        let a = thumbnail_filename(&img, ResizeOp::FitHeight(10), Format::Jpeg);
+        let b = thumbnail_filename(&img, ResizeOp::FitHeight(10), Format::Jpeg);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn different_ops_give_different_filenames() {
+        let img = filled(20, 20, 1, 2, 3);
+        let a = thumbnail_filename(&img, ResizeOp::FitHeight(10), Format::Jpeg);
+        let b = thumbnail_filename(&img, ResizeOp::FitHeight(20), Format::Jpeg);
+        assert_ne!(a, b);
+    }
+}
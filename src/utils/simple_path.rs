@@ -1,5 +1,6 @@
 use std::{
     borrow::Borrow,
+    ffi::OsStr,
     fmt, iter,
     ops::Deref,
     path::{Component, Path, PathBuf},
@@ -57,6 +58,14 @@ impl SimplePathBuf {
             .collect();
         Self::new(restored_path)
     }
+
+    /// Appends `path`, the same as [`PathBuf::push`], re-validating the result so this
+    /// can never be made to hold a non-simple path.
+    pub fn push(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let joined = self.as_simple_path().join(path)?;
+        self.inner = joined.inner;
+        Ok(())
+    }
 }
 
 impl Deref for SimplePathBuf {
@@ -152,14 +161,80 @@ impl SimplePath {
 
     /// How many components long a simple relative path is
     pub fn depth(&self) -> usize {
-        let path: &Path = self.inner.as_ref();
-        path.components().count()
+        self.components().count()
     }
 
     pub fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
 
+    /// The path without its final component, or `None` if `self` is empty. Mirrors
+    /// [`Path::parent`], except the root of a simple path is the empty path rather than
+    /// `None`, so only the empty path itself has no parent.
+    pub fn parent(&self) -> Option<&SimplePath> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(match self.inner.rfind('/') {
+            Some(idx) => Self::new_str_unchecked(&self.inner[..idx]),
+            None => Self::new_str_unchecked(""),
+        })
+    }
+
+    /// The final component, or `None` if `self` is empty. Mirrors [`Path::file_name`].
+    pub fn file_name(&self) -> Option<&str> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(match self.inner.rfind('/') {
+            Some(idx) => &self.inner[idx + 1..],
+            None => &self.inner,
+        })
+    }
+
+    /// See [`Path::file_stem`].
+    pub fn file_stem(&self) -> Option<&str> {
+        let name = self.file_name()?;
+        Some(match split_extension(name) {
+            Some((stem, _)) => stem,
+            None => name,
+        })
+    }
+
+    /// See [`Path::extension`].
+    pub fn extension(&self) -> Option<&str> {
+        split_extension(self.file_name()?).map(|(_, extension)| extension)
+    }
+
+    /// Iterates over the `/`-separated segments of this path. Always splits on `/`
+    /// regardless of host platform, unlike [`Path::components`], so a path stored on one
+    /// platform parses identically when read back on another.
+    pub fn components(&self) -> Components<'_> {
+        Components {
+            inner: if self.is_empty() {
+                None
+            } else {
+                Some(self.inner.split('/'))
+            },
+        }
+    }
+
+    /// See [`Path::join`]. Fails if the result is no longer simple, e.g. if `path` is
+    /// absolute or contains a `..`.
+    pub fn join(&self, path: impl AsRef<Path>) -> Result<SimplePathBuf> {
+        SimplePathBuf::new(self.as_path().join(path))
+    }
+
+    /// See [`Path::with_extension`]. Fails if the result is no longer simple.
+    pub fn with_extension(&self, extension: impl AsRef<OsStr>) -> Result<SimplePathBuf> {
+        SimplePathBuf::new(self.as_path().with_extension(extension))
+    }
+
+    /// See [`Path::with_file_name`]. Fails if the result is no longer simple.
+    pub fn with_file_name(&self, file_name: impl AsRef<OsStr>) -> Result<SimplePathBuf> {
+        SimplePathBuf::new(self.as_path().with_file_name(file_name))
+    }
+
     /// Return a path that when followed from the directory the file at `self` is in, will
     /// get to `target`. Both `self` and `target` should be relative to the same point.
     /// Self must refer to a file, i.e., it can't be the empty path, `None` is returned in
@@ -168,27 +243,48 @@ impl SimplePath {
         if self.is_empty() {
             return None;
         }
-
-        let res = self.resolve_dir_to(target);
-        let mut components = res.components();
-        assert_eq!(
-            Some(Component::ParentDir),
-            components.next(),
-            "was expecting to pop a '..'"
-        );
-        Some(components.collect())
+        Some(resolve_at_depth(self.depth() - 1, target.as_ref()))
     }
 
     /// Return a path that when followed from the directory at `self`, will get to
     /// `target`. Both `self` and `target` should be relative to the same point.
     pub fn resolve_dir_to(&self, target: impl AsRef<SimplePath>) -> PathBuf {
-        let target = target.as_ref().as_path();
-        let depth = self.depth();
-        iter::repeat(Component::ParentDir)
-            .take(depth)
-            .chain(target.components())
-            .collect()
+        resolve_at_depth(self.depth(), target.as_ref())
+    }
+}
+
+/// Builds `../` repeated `up` times followed by `target`'s segments, always joined with
+/// `/` so the result is identical on every platform.
+fn resolve_at_depth(up: usize, target: &SimplePath) -> PathBuf {
+    let segments: Vec<&str> = iter::repeat("..")
+        .take(up)
+        .chain(target.components())
+        .collect();
+    PathBuf::from(segments.join("/"))
+}
+
+/// Iterator over the `/`-separated segments of a [`SimplePath`], see
+/// [`SimplePath::components`].
+pub struct Components<'a> {
+    inner: Option<std::str::Split<'a, char>>,
+}
+
+impl<'a> Iterator for Components<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.as_mut()?.next()
+    }
+}
+
+/// Splits `name` at its last `.`, the same as [`Path::file_stem`]/[`Path::extension`]
+/// do for a single filename: a leading dot, like in `.bashrc`, is not an extension.
+fn split_extension(name: &str) -> Option<(&str, &str)> {
+    let dot = name.rfind('.')?;
+    if dot == 0 {
+        return None;
     }
+    Some((&name[..dot], &name[dot + 1..]))
 }
 
 impl AsRef<Path> for SimplePath {
@@ -263,14 +359,13 @@ pub fn clap_simple_relative_parser(
     })
 }
 
+/// Always splits `s` on `/`, regardless of host platform (unlike `std::path`, which on
+/// Windows would e.g. treat `a\b` as a single component and give `C:foo` a drive
+/// prefix), so a path validated here parses identically on every platform.
 fn is_simple(s: &str) -> bool {
-    let path: &Path = s.as_ref();
-    path.components()
-        .all(|comp| matches!(comp, Component::Normal(_)))
-        && !s.contains("//")
-        && !s.contains("/./")
-        && !s.ends_with("/.")
-        && !s.ends_with("/")
+    s.is_empty()
+        || s.split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
 }
 
 #[cfg(test)]
@@ -363,4 +458,61 @@ mod test {
         assert_eq!(buf, pat);
         assert_eq!(buf, *pat);
     }
+
+    #[test]
+    fn parent() {
+        assert_eq!(Some(simple("a")), simple("a/b").parent());
+        assert_eq!(Some(simple("")), simple("a").parent());
+        assert_eq!(None, simple("").parent());
+        assert_eq!(Some(simple("a/b")), simple("a/b/c").parent());
+    }
+
+    #[test]
+    fn file_name() {
+        assert_eq!(Some("b"), simple("a/b").file_name());
+        assert_eq!(Some("a"), simple("a").file_name());
+        assert_eq!(None, simple("").file_name());
+    }
+
+    #[test]
+    fn file_stem_and_extension() {
+        assert_eq!(Some("fil"), simple("mapp/fil.txt").file_stem());
+        assert_eq!(Some("txt"), simple("mapp/fil.txt").extension());
+        assert_eq!(None, simple("mapp/fil").extension());
+    }
+
+    #[test]
+    fn components() {
+        let comps: Vec<&str> = simple("a/b/c").components().collect();
+        assert_eq!(vec!["a", "b", "c"], comps);
+        assert_eq!(0, simple("").components().count());
+    }
+
+    #[test]
+    fn join() {
+        assert_eq!(simple("a/b"), simple("a").join("b").unwrap());
+        assert!(simple("a").join("..").is_err());
+        assert!(simple("a").join("/b").is_err());
+    }
+
+    #[test]
+    fn push() {
+        let mut buf = SimplePathBuf::new("a").unwrap();
+        buf.push("b").unwrap();
+        assert_eq!(simple("a/b"), buf);
+        assert!(buf.push("..").is_err());
+        assert_eq!(simple("a/b"), buf);
+    }
+
+    #[test]
+    fn with_extension_and_file_name() {
+        assert_eq!(
+            simple("mapp/fil.jpg"),
+            simple("mapp/fil.txt").with_extension("jpg").unwrap()
+        );
+        assert_eq!(
+            simple("mapp/other"),
+            simple("mapp/fil.txt").with_file_name("other").unwrap()
+        );
+    }
 }
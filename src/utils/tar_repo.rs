@@ -0,0 +1,398 @@
+//! A tar-archive alternative to [`super::repo::Repo`]/[`super::repo::Entry`]: every
+//! payload is appended as a member of one append-only tar archive instead of living in
+//! its own small file or symlink inside a directory, so a run producing millions of
+//! thumbnails doesn't also produce millions of inodes and a directory walk slow enough to
+//! matter. Unlike [`super::packed_repo`]'s bespoke data+manifest pair, the artifact here
+//! is a plain tar file any other tool can also read.
+//!
+//! Every top-level entry handed out by [`TarRepo::new_entry`] is marked by a zero-length
+//! `{index:04}/.entry` member carrying a PAX extended header under the
+//! [`PAX_ENTRY_INDEX`] key, so [`TarRepo::new`] can reconstruct `next_entry` by scanning
+//! headers instead of walking a filesystem, and without relying on at least one real file
+//! having been written under that entry (a plain [`Repo`](super::repo::Repo)'s
+//! `find_next_entry` under-counts an entry nothing was ever written into, since an empty
+//! directory never shows up in [`fsutils::all_files`]). Symlinks likewise record their
+//! target under the [`PAX_LINK_TARGET`] key, which [`TarRepo::read_file`] reads back
+//! without depending on the tar-native link-name field's length limit.
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, Context}; // TODO: use custom error type instead
+use image::{ImageBuffer, ImageOutputFormat};
+use tar::{EntryType, Header};
+
+use super::fsutils;
+
+const ENTRY_PADDING: usize = 4;
+
+/// Custom PAX key recording which top-level entry index a `.entry` marker belongs to,
+/// see the module docs.
+const PAX_ENTRY_INDEX: &str = "imgdup.entry_index";
+/// Custom PAX key recording a symlink member's target, see the module docs.
+const PAX_LINK_TARGET: &str = "imgdup.link_target";
+
+/// Where one logical member's content can be found, see [`TarRepo::read_file`].
+#[derive(Debug, Clone)]
+enum IndexRecord {
+    /// A byte range of the tar archive itself.
+    Blob { offset: u64, length: u64 },
+    /// A symlink's target, to be read off disk instead, same as
+    /// [`super::packed_repo::PackedReader::read`].
+    Link { target: String },
+}
+
+/// The state shared by every [`TarEntry`] handed out by the same [`TarRepo`]: the tar
+/// archive being appended to, and the index of everything written to it so far (both in
+/// this process and, if [`TarRepo::new`] opened an existing archive, before it).
+struct Shared {
+    builder: tar::Builder<File>,
+    index: HashMap<String, IndexRecord>,
+}
+
+/// Writer half of the tar format. Hands out [`TarEntry`]s the same way
+/// [`Repo`](super::repo::Repo) hands out [`Entry`](super::repo::Entry)s, but every entry
+/// appends into the same tar archive instead of creating its own directory.
+pub struct TarRepo {
+    data_path: PathBuf,
+    shared: Arc<Mutex<Shared>>,
+    next_entry: u32,
+}
+
+/// Entry half of the tar format, see [`TarRepo`].
+pub struct TarEntry {
+    shared: Arc<Mutex<Shared>>,
+    dir: String,
+    next_entry: u32,
+}
+
+impl TarRepo {
+    /// Opens `path` as a tar archive, creating it if it doesn't exist yet, or resuming
+    /// numbering and appending into it if it does (by scanning its headers, see the
+    /// module docs).
+    pub fn new(path: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let data_path = path.into();
+
+        let (next_entry, index, file) = if data_path.exists() {
+            let (next_entry, index) = scan_existing(&data_path)
+                .wrap_err("failed to scan the existing tar archive")?;
+
+            // A finished tar archive ends with two 512-byte zero blocks marking its
+            // end; drop them so appending continues the member stream instead of
+            // writing after that marker.
+            let len = fs::metadata(&data_path)
+                .wrap_err("failed to stat the tar archive")?
+                .len();
+            let truncate_to = len.saturating_sub(1024);
+
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .open(&data_path)
+                .wrap_err("failed to reopen the tar archive for appending")?;
+            file.set_len(truncate_to)
+                .wrap_err("failed to drop the tar archive's end-of-archive marker")?;
+            file.seek(SeekFrom::Start(truncate_to))
+                .wrap_err("failed to seek to the end of the tar archive")?;
+
+            (next_entry, index, file)
+        } else {
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&data_path)
+                .wrap_err("failed to create the tar archive")?;
+            (0, HashMap::new(), file)
+        };
+
+        Ok(Self {
+            data_path,
+            shared: Arc::new(Mutex::new(Shared {
+                builder: tar::Builder::new(file),
+                index,
+            })),
+            next_entry,
+        })
+    }
+
+    pub fn new_entry(&mut self) -> eyre::Result<TarEntry> {
+        let p = ENTRY_PADDING;
+        let idx = self.next_entry;
+        let dir = format!("{:0p$}", idx);
+        self.next_entry += 1;
+
+        let mut shared = self.shared.lock().unwrap();
+        write_entry_marker(&mut shared.builder, &dir, idx)
+            .wrap_err("failed to write the entry marker")?;
+        drop(shared);
+
+        Ok(TarEntry {
+            shared: Arc::clone(&self.shared),
+            dir,
+            next_entry: 0,
+        })
+    }
+
+    /// Reads a member's content by the same logical path [`TarEntry::next_path`]
+    /// generates for it (e.g. `0000/0001_name.jpg`), in O(1) via the in-memory index. A
+    /// symlink member is resolved by reading its external target off disk, same as
+    /// [`super::packed_repo::PackedReader::read`].
+    pub fn read_file(&self, logical_path: &str) -> eyre::Result<Vec<u8>> {
+        let record = {
+            let shared = self.shared.lock().unwrap();
+            shared
+                .index
+                .get(logical_path)
+                .ok_or_else(|| eyre::eyre!("no such entry in the tar archive: {logical_path:?}"))?
+                .clone()
+        };
+
+        match record {
+            IndexRecord::Blob { offset, length } => {
+                let mut file = File::open(&self.data_path)
+                    .wrap_err("failed to open the tar archive for reading")?;
+                file.seek(SeekFrom::Start(offset))
+                    .wrap_err("failed to seek into the tar archive")?;
+                let mut buf = vec![0; length as usize];
+                file.read_exact(&mut buf)
+                    .wrap_err("failed to read from the tar archive")?;
+                Ok(buf)
+            }
+            IndexRecord::Link { target } => {
+                fs::read(&target).wrap_err("failed to read the link target")
+            }
+        }
+    }
+
+    /// Finishes the tar archive, writing its end-of-archive marker. Every [`TarEntry`]
+    /// handed out must have been dropped first.
+    pub fn finish(self) -> eyre::Result<()> {
+        let shared = Arc::try_unwrap(self.shared)
+            .map_err(|_| eyre::eyre!("a tar entry is still alive"))?
+            .into_inner()
+            .expect("the mutex is never poisoned");
+
+        let file = shared
+            .builder
+            .into_inner()
+            .wrap_err("failed to finish the tar archive")?;
+        file.sync_all().wrap_err("failed to flush the tar archive")?;
+        Ok(())
+    }
+}
+
+impl TarEntry {
+    fn next_path(&mut self, name: &Path) -> String {
+        let p = ENTRY_PADDING;
+        let mut num: OsString = format!("{:0p$}", self.next_entry).into();
+        num.push("_");
+        num.push(name);
+        self.next_entry += 1;
+        format!("{}/{}", self.dir, num.to_string_lossy())
+    }
+
+    pub fn sub_entry(&mut self, name: impl AsRef<Path>) -> eyre::Result<Self> {
+        let name = name.as_ref();
+        assert!(fsutils::is_basename(name));
+        let dir = self.next_path(name);
+        Ok(Self {
+            shared: Arc::clone(&self.shared),
+            dir,
+            next_entry: 0,
+        })
+    }
+
+    pub fn create_file<F>(&mut self, name: impl AsRef<Path>, writer: F) -> eyre::Result<()>
+    where
+        F: FnOnce(&mut Vec<u8>) -> eyre::Result<()>,
+    {
+        let name = name.as_ref();
+        assert!(fsutils::is_basename(name));
+        let key = self.next_path(name);
+
+        let mut buf = Vec::new();
+        writer(&mut buf).wrap_err("the writer failed")?;
+
+        let mut shared = self.shared.lock().unwrap();
+        let offset = shared
+            .builder
+            .get_mut()
+            .stream_position()
+            .wrap_err("failed to read the archive's write position")?
+            + 512; // past this member's header block
+
+        let mut header = new_header(buf.len() as u64);
+        shared
+            .builder
+            .append_data(&mut header, &key, buf.as_slice())
+            .wrap_err("failed to append to the tar archive")?;
+
+        shared.index.insert(
+            key,
+            IndexRecord::Blob {
+                offset,
+                length: buf.len() as u64,
+            },
+        );
+        Ok(())
+    }
+
+    /// `target` is relative CWD, or absolute.
+    pub fn create_link(
+        &mut self,
+        link_name: impl AsRef<Path>,
+        target: impl AsRef<Path>,
+    ) -> eyre::Result<()> {
+        let link_name = link_name.as_ref();
+        assert!(fsutils::is_basename(link_name));
+        let key = self.next_path(link_name);
+        let target = target.as_ref().to_string_lossy().into_owned();
+
+        let mut shared = self.shared.lock().unwrap();
+        shared
+            .builder
+            .append_pax_extensions([(PAX_LINK_TARGET, target.as_bytes())])
+            .wrap_err("failed to write the symlink's pax extension")?;
+
+        let mut header = new_header(0);
+        header.set_entry_type(EntryType::Symlink);
+        // Best-effort: the tar-native link-name field is capped at 100 bytes, but
+        // `read_file` always resolves through `PAX_LINK_TARGET` above, not this field.
+        let _ = header.set_link_name(&target);
+        shared
+            .builder
+            .append_data(&mut header, &key, io::empty())
+            .wrap_err("failed to append the symlink to the tar archive")?;
+
+        shared.index.insert(key, IndexRecord::Link { target });
+        Ok(())
+    }
+
+    pub fn create_jpg<P, C>(
+        &mut self,
+        jpg_name: impl AsRef<Path>,
+        image: &ImageBuffer<P, C>,
+    ) -> eyre::Result<()>
+    where
+        P: image::Pixel + image::PixelWithColorType,
+        [P::Subpixel]: image::EncodableLayout,
+        C: std::ops::Deref<Target = [P::Subpixel]>,
+    {
+        let jpg_name = jpg_name.as_ref();
+        assert!(fsutils::is_basename(jpg_name));
+        let jpg_name = Path::new(jpg_name).with_extension("jpg");
+        self.create_file(jpg_name, |buf| {
+            image
+                .write_to(&mut std::io::Cursor::new(buf), ImageOutputFormat::Jpeg(95))
+                .wrap_err("image failed to write")
+        })
+    }
+
+    pub fn create_text_file(
+        &mut self,
+        txt_name: impl AsRef<Path>,
+        contents: impl AsRef<str>,
+    ) -> eyre::Result<()> {
+        let txt_name = txt_name.as_ref();
+        assert!(fsutils::is_basename(txt_name));
+        let txt_name = Path::new(txt_name).with_extension("txt");
+        self.create_file(txt_name, |buf| {
+            buf.extend_from_slice(contents.as_ref().as_bytes());
+            Ok(())
+        })
+    }
+}
+
+fn new_header(size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    );
+    header
+}
+
+fn write_entry_marker(builder: &mut tar::Builder<File>, dir: &str, idx: u32) -> io::Result<()> {
+    builder.append_pax_extensions([(PAX_ENTRY_INDEX, idx.to_string().as_bytes())])?;
+    let mut header = new_header(0);
+    builder.append_data(&mut header, format!("{dir}/.entry"), io::empty())
+}
+
+/// Rebuilds `(next_entry, index)` for [`TarRepo::new`] by scanning every header of the
+/// tar archive at `path`, see the module docs.
+fn scan_existing(path: &Path) -> eyre::Result<(u32, HashMap<String, IndexRecord>)> {
+    let file = File::open(path).wrap_err("failed to open the tar archive for scanning")?;
+    let mut archive = tar::Archive::new(BufReader::new(file));
+
+    let mut next_entry = 0u32;
+    let mut index = HashMap::new();
+
+    for entry in archive
+        .entries()
+        .wrap_err("failed to read the tar archive's entries")?
+    {
+        let mut entry = entry.wrap_err("failed to read a tar entry")?;
+        let path_in_archive = entry
+            .path()
+            .wrap_err("failed to read an entry's path")?
+            .display()
+            .to_string();
+        let pax = read_pax_extensions(&mut entry).wrap_err("failed to read pax extensions")?;
+
+        if let Some(idx) = pax.get(PAX_ENTRY_INDEX) {
+            let idx: u32 = idx
+                .parse()
+                .wrap_err("corrupt entry index pax extension in the tar archive")?;
+            next_entry = next_entry.max(idx + 1);
+            continue;
+        }
+
+        if let Some(target) = pax.get(PAX_LINK_TARGET) {
+            index.insert(
+                path_in_archive,
+                IndexRecord::Link {
+                    target: target.clone(),
+                },
+            );
+            continue;
+        }
+
+        let offset = entry.raw_file_position();
+        let length = entry
+            .header()
+            .size()
+            .wrap_err("corrupt entry size in the tar archive")?;
+        index.insert(path_in_archive, IndexRecord::Blob { offset, length });
+    }
+
+    Ok((next_entry, index))
+}
+
+fn read_pax_extensions<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+) -> eyre::Result<HashMap<String, String>> {
+    let mut out = HashMap::new();
+    let Some(extensions) = entry.pax_extensions().wrap_err("failed to read the pax header")?
+    else {
+        return Ok(out);
+    };
+
+    for ext in extensions {
+        let ext = ext.wrap_err("failed to read a pax extension")?;
+        let key = ext.key().wrap_err("a pax extension key is not UTF-8")?;
+        let value = String::from_utf8_lossy(ext.value_bytes()).into_owned();
+        out.insert(key.to_string(), value);
+    }
+
+    Ok(out)
+}
@@ -1,31 +1,86 @@
+use std::ops::Range;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 
+/// How many indices [`WorkQueue::next_default_chunk`] reserves per `fetch_add` when no
+/// explicit chunk size is given, see [`WorkQueue::with_chunk_size`].
+pub const DEFAULT_CHUNK_SIZE: usize = 1;
+
+/// A lock-free, allocation-free work-stealing queue: every thread holding a shared
+/// `&WorkQueue` can pull the next unclaimed item (or a chunk of them) via a single
+/// `AtomicUsize`, without any of them ever blocking on the others.
 pub struct WorkQueue<T> {
     work: Vec<T>,
     next: AtomicUsize,
+    done: AtomicUsize,
+    chunk_size: usize,
 }
 
 impl<T> WorkQueue<T> {
     pub fn new(work: Vec<T>) -> Self {
+        Self::with_chunk_size(work, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Like [`Self::new`], but every [`Self::next_default_chunk`] call reserves
+    /// `chunk_size` indices at a time instead of one, amortizing the `fetch_add` across
+    /// them; [`Self::next`]/[`Self::next_index`] still claim one index each. Worth raising above the default of 1 when items are cheap to process and
+    /// many threads contend on the same queue; [`Self::next_chunk`] is how a caller
+    /// actually claims (and works through) a whole chunk at once.
+    pub fn with_chunk_size(work: Vec<T>, chunk_size: usize) -> Self {
+        assert!(chunk_size >= 1, "a chunk of 0 items can never be claimed");
         Self {
             work,
             next: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            chunk_size,
         }
     }
 
+    /// Claims and returns the next single item, or `None` once the queue is drained.
     pub fn next(&self) -> Option<&T> {
         self.next_index().map(|(_, t)| t)
     }
 
+    /// Like [`Self::next`], but also hands back the claimed item's index into the
+    /// original `work` vector.
     pub fn next_index(&self) -> Option<(usize, &T)> {
-        let cur = self.next.fetch_add(1, SeqCst);
-        self.work.get(cur).map(|t| (cur, t))
+        let range = self.next_chunk(1)?;
+        let i = range.start;
+        self.work.get(i).map(|t| (i, t))
+    }
+
+    /// Claims up to `n` consecutive indices in one `fetch_add`, amortizing the atomic
+    /// over the whole chunk instead of paying for it once per item. Returns the range
+    /// actually claimed -- shorter than `n` once the queue's tail doesn't have enough
+    /// items left -- or `None` if the queue was already drained. The caller works
+    /// through `self.items(range)` itself; claiming a range never blocks another thread
+    /// from claiming the next one concurrently.
+    pub fn next_chunk(&self, n: usize) -> Option<Range<usize>> {
+        assert!(n >= 1, "a chunk of 0 items can never be claimed");
+        let start = self.next.fetch_add(n, SeqCst);
+        if start >= self.len() {
+            return None;
+        }
+        Some(start..(start + n).min(self.len()))
+    }
+
+    /// Like [`Self::next_chunk`], but reserves [`Self::chunk_size`] indices.
+    pub fn next_default_chunk(&self) -> Option<Range<usize>> {
+        self.next_chunk(self.chunk_size)
+    }
+
+    /// The items at `range`, as claimed by a previous [`Self::next_chunk`] call.
+    pub fn items(&self, range: Range<usize>) -> &[T] {
+        &self.work[range]
     }
 
     pub fn len(&self) -> usize {
         self.work.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.work.is_empty()
+    }
+
     pub fn stop(&self) {
         self.next.store(self.len(), SeqCst);
     }
@@ -33,4 +88,19 @@ impl<T> WorkQueue<T> {
     pub fn is_stopped(&self) -> bool {
         self.next.load(SeqCst) >= self.len()
     }
+
+    /// Marks `n` items as fully processed, for [`Self::progress`] to report on. Meant to
+    /// be called once a worker has actually finished an item (successfully or not), not
+    /// merely claimed it -- unlike [`Self::next`]/[`Self::next_chunk`], which only say an
+    /// item is in-flight somewhere.
+    pub fn mark_done(&self, n: usize) {
+        self.done.fetch_add(n, SeqCst);
+    }
+
+    /// `(done, total)`, for rendering a progress bar while other threads work through
+    /// the queue. `done` only counts items a worker reported via [`Self::mark_done`], so
+    /// it lags behind how many have merely been claimed.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.done.load(SeqCst), self.len())
+    }
 }
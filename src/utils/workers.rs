@@ -0,0 +1,63 @@
+use std::any::Any;
+use std::sync::Mutex;
+use std::thread::Scope;
+
+/// The outcome of one worker spawned via [`WorkerScope::spawn`]: either the value its
+/// closure returned, or a message describing the panic it raised, and the `name` it was
+/// given.
+pub struct FinishedWorker<T> {
+    pub name: String,
+    pub result: Result<T, String>,
+}
+
+pub struct WorkerScope<'scope, 'env: 'scope, T> {
+    scope: &'scope Scope<'scope, 'env>,
+    finished: &'scope Mutex<Vec<FinishedWorker<T>>>,
+}
+
+impl<'scope, 'env, T> WorkerScope<'scope, 'env, T>
+where
+    T: Send + 'scope,
+{
+    pub fn spawn<F>(&self, name: impl Into<String>, f: F)
+    where
+        F: FnOnce() -> T + Send + 'scope,
+    {
+        let name = name.into();
+        let finished = self.finished;
+        self.scope.spawn(move || {
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(panic_message);
+            finished.lock().unwrap().push(FinishedWorker { name, result });
+        });
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// Runs `body`, which spawns zero or more named worker threads through the given
+/// [`WorkerScope`], and blocks until all of them have finished. Returns every spawned
+/// worker's [`FinishedWorker`], in completion order.
+pub fn scoped_workers<T, F>(body: F) -> Vec<FinishedWorker<T>>
+where
+    T: Send,
+    F: for<'scope> FnOnce(&WorkerScope<'scope, '_, T>),
+{
+    let finished = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        let ws = WorkerScope {
+            scope,
+            finished: &finished,
+        };
+        body(&ws);
+    });
+    finished.into_inner().expect("the mutex is never poisoned")
+}
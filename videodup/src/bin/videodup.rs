@@ -1,8 +1,9 @@
 use std::{
     collections::HashSet,
     ffi::OsString,
+    io,
     num::NonZeroU32,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{mpsc, Mutex},
     time::{Duration, Instant},
 };
@@ -24,7 +25,7 @@ use imgdup_common::{
     },
     bktree::bktree::BKTree,
     duration,
-    imghash::hamming::Hamming,
+    imghash::hamming::{Distance, Hamming},
     utils::{
         fsutils::{self, all_files, read_optional_file},
         imgutils,
@@ -38,9 +39,17 @@ use imgdup_common::{
 };
 use rayon::prelude::*;
 use videodup::{
+    clip_hash::{self, ClipHash},
+    clip_hash_cache::ClipHashCache,
+    coarse_fingerprint::CoarseFingerprint,
+    coarse_index::CoarseIndex,
     debug_info::{self, Collision, Collisions, DEBUG_INFO_FILENAME},
+    failure_cache::FailureCache,
     frame_extractor::{ContextLogger, FrameExtractor, Timestamp},
-    video_source::{Mirror, VidSrc},
+    hashalg,
+    motion_photo,
+    progress::{self, ProgressEvent},
+    video_source::{FileStamp, Mirror, VidSrc},
 };
 
 #[derive(Parser, Debug)]
@@ -55,6 +64,9 @@ struct Cli {
     #[command(flatten)]
     preproc_args: Preproc,
 
+    #[command(flatten)]
+    hasher_args: hashalg::HasherCli,
+
     #[command(flatten)]
     get_hashes_args: video::GetHashes,
 
@@ -86,9 +98,52 @@ struct Cli {
     #[arg(long, short = 's', required = true, num_args=1.., value_parser = clap_simple_relative_parser)]
     src_dirs: Vec<SimplePathBuf>,
 
+    /// A folder of already-curated files to treat as a protected reference set. If any
+    /// are given, a collision is only reported when exactly one side is under a
+    /// reference folder, so importing a new batch reports what's new that duplicates
+    /// something already kept, without the reference set flagging collisions against
+    /// itself. Can be given multiple times
+    #[arg(long, num_args=1.., value_parser = clap_simple_relative_parser)]
+    reference_dir: Vec<SimplePathBuf>,
+
     /// Path to the database to use
     #[arg(long, short = 'f', default_value = "./videodup.db")]
     database_file: PathBuf,
+
+    /// Clear the cache of videos that previously failed to ingest and give all of them
+    /// a fresh attempt
+    #[arg(long)]
+    retry_failed: bool,
+
+    /// Files smaller than this are always re-hashed, even if they're already in the
+    /// database and look untouched: not worth the bookkeeping of caching a result for
+    /// something this cheap to just redo
+    #[arg(long, default_value_t = 0)]
+    min_cache_size: u64,
+
+    /// Emit progress events as line-delimited JSON on stdout, for a supervising
+    /// GUI/TUI to follow along without scraping log text
+    #[arg(long)]
+    progress_json: bool,
+
+    /// Also compute a whole-clip DCT-3D fingerprint for every video and check it
+    /// against every other clip's fingerprint, as a cheap pre-filter alongside the
+    /// usual per-frame search
+    #[arg(long)]
+    clip_hash_mode: bool,
+
+    /// Before running the expensive per-frame BK-tree search, shortlist candidate
+    /// videos by a coarse whole-video fingerprint (a handful of evenly-spaced frame
+    /// hashes concatenated together); if nothing is within --coarse-tolerance of it,
+    /// skip the per-frame search entirely instead of running it against the whole tree
+    #[arg(long)]
+    coarse_prefilter: bool,
+
+    /// How close two videos' coarse fingerprints need to be to shortlist them for
+    /// --coarse-prefilter. Deliberately generous: too tight and a real duplicate is
+    /// missed outright, too loose and it's no cheaper than not prefiltering at all
+    #[arg(long, default_value_t = 300)]
+    coarse_tolerance: Distance,
 }
 
 fn cli_arguments() -> eyre::Result<Cli> {
@@ -155,18 +210,97 @@ fn main() -> eyre::Result<()> {
     };
     log::info!("Found {} files", tree_files.len());
 
+    log::info!("Checking the DB's files for staleness");
+    let stale_files: HashSet<SimplePathBuf> = {
+        let mut stale_files = HashSet::new();
+        tree.for_each(|_, src| {
+            let path = src.path();
+            match FileStamp::of(path.as_path()) {
+                Ok(current) if current.size() < cli.min_cache_size => {
+                    stale_files.insert(path.to_owned());
+                }
+                Ok(current) if current != src.stamp() => {
+                    log::debug!("'{}' changed since it was last hashed", path);
+                    stale_files.insert(path.to_owned());
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    // A file that can no longer be stat'd is handled separately, by
+                    // `removed_files` below, once this also isn't in `src_files`.
+                    log::debug!("Failed to stat '{}' to check for staleness: {}", path, e);
+                }
+            }
+        })?;
+        stale_files
+    };
+    log::info!("Found {} stale files", stale_files.len());
+
+    let failures_file = cli.database_file.with_extension("failures");
+    let mut failures = FailureCache::load(&failures_file).wrap_err_with(|| {
+        format!(
+            "failed to load the failure cache at {}",
+            failures_file.display()
+        )
+    })?;
+    if cli.retry_failed {
+        log::info!("Clearing the failure cache due to --retry-failed");
+        failures.clear();
+    }
+
+    let clip_hashes_file = cli.database_file.with_extension("clip_hashes");
+    let clip_hashes = if cli.clip_hash_mode {
+        Some(ClipHashCache::load(&clip_hashes_file).wrap_err_with(|| {
+            format!(
+                "failed to load the clip-hash cache at {}",
+                clip_hashes_file.display()
+            )
+        })?)
+    } else {
+        None
+    };
+
+    let coarse_index_file = cli.database_file.with_extension("coarse_index");
+    let coarse_index = if cli.coarse_prefilter {
+        Some(CoarseIndex::load(&coarse_index_file).wrap_err_with(|| {
+            format!(
+                "failed to load the coarse-fingerprint index at {}",
+                coarse_index_file.display()
+            )
+        })?)
+    } else {
+        None
+    };
+
     let new_files: Vec<&SimplePath> = src_files
-        .difference(&tree_files)
-        .take(cli.limit)
+        .iter()
+        .filter(|path| !tree_files.contains(*path) || stale_files.contains(*path))
         .map(|pb| pb.as_simple_path())
+        .filter(|path| match failures.get(path) {
+            Ok(Some(error)) => {
+                log::debug!("Skipping previously-failed '{path}': {error}");
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                log::warn!("Failed to check the failure cache for '{path}': {e:?}");
+                true
+            }
+        })
+        .take(cli.limit)
         .collect();
     let removed_files: HashSet<&SimplePath> = tree_files
         .difference(&src_files)
         .map(|pb| pb.as_simple_path())
         .collect();
 
-    log::info!("Removing {} removed files from the DB", removed_files.len());
-    tree.remove_any_of(|_, vidsrc| removed_files.contains(vidsrc.path()))?;
+    log::info!(
+        "Removing {} removed and {} stale files from the DB",
+        removed_files.len(),
+        stale_files.len()
+    );
+    tree.remove_any_of(|_, vidsrc| {
+        removed_files.contains(vidsrc.path()) || stale_files.contains(vidsrc.path())
+    })?;
 
     let video_threads: usize = cli.video_threads.get().try_into().expect("should fit");
 
@@ -194,17 +328,38 @@ fn main() -> eyre::Result<()> {
     let term_cookie =
         termination::Cookie::new().wrap_err("failed to create term cookie")?;
 
+    let failures = Mutex::new(failures);
+    let clip_hashes = clip_hashes.map(Mutex::new);
+    let coarse_index = coarse_index.map(Mutex::new);
+    let hasher = cli.hasher_args.to_hasher();
+
+    let (progress_tx, progress_rx) = if cli.progress_json {
+        let (tx, rx) = mpsc::channel::<ProgressEvent>();
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+
     let finished_workers = scoped_workers(|s| {
         let (tx, rx) = mpsc::sync_channel::<Payload>(16);
 
+        if let Some(progress_rx) = progress_rx {
+            s.spawn("P", move || progress::report(progress_rx, io::stdout()));
+        }
+
         let video_ctx = video::Ctx {
             preproc_args: &cli.preproc_args,
+            hasher: &hasher,
             simi_args: &cli.simi_args,
             get_hashes_args: &cli.get_hashes_args,
             ignored_hashes: &ignored_hashes,
             new_files: &new_files,
             repo_grave: repo_grave.as_ref(),
+            failures: &failures,
+            progress: progress_tx.as_ref(),
             term_cookie: &term_cookie,
+            clip_hash_mode: cli.clip_hash_mode,
+            coarse_prefilter: cli.coarse_prefilter,
         };
 
         for _ in 0..video_threads {
@@ -215,11 +370,53 @@ fn main() -> eyre::Result<()> {
 
         let tree_ctx = tree::Ctx {
             simi_args: &cli.simi_args,
+            progress: progress_tx.as_ref(),
             term_cookie: &term_cookie,
+            clip_hashes: clip_hashes.as_ref(),
+            reference_dirs: &cli.reference_dir,
+            coarse_index: coarse_index.as_ref(),
+            coarse_tolerance: cli.coarse_tolerance,
         };
         s.spawn("T", move || tree::main(tree_ctx, rx, tree, repo_dup));
     });
 
+    failures
+        .into_inner()
+        .expect("no thread panicked while holding the lock")
+        .save(&failures_file)
+        .wrap_err_with(|| {
+            format!(
+                "failed to save the failure cache to {}",
+                failures_file.display()
+            )
+        })?;
+
+    if let Some(clip_hashes) = clip_hashes {
+        clip_hashes
+            .into_inner()
+            .expect("no thread panicked while holding the lock")
+            .save(&clip_hashes_file)
+            .wrap_err_with(|| {
+                format!(
+                    "failed to save the clip-hash cache to {}",
+                    clip_hashes_file.display()
+                )
+            })?;
+    }
+
+    if let Some(coarse_index) = coarse_index {
+        coarse_index
+            .into_inner()
+            .expect("no thread panicked while holding the lock")
+            .save(&coarse_index_file)
+            .wrap_err_with(|| {
+                format!(
+                    "failed to save the coarse-fingerprint index to {}",
+                    coarse_index_file.display()
+                )
+            })?;
+    }
+
     let all_ok = finished_workers
         .into_iter()
         .map(|FinishedWorker { result, name }| match result {
@@ -263,6 +460,18 @@ mod common {
     pub struct Payload<'env> {
         pub video_path: &'env SimplePath,
         pub hashes: Vec<Frame>,
+        /// `Some(byte_offset)` when `video_path` is a motion photo and `hashes` actually
+        /// came from its embedded clip, see [`crate::motion_photo`].
+        pub embedded_offset: Option<u64>,
+        /// `video_path`'s size and modification time, captured right before hashing it,
+        /// so a later run can tell whether the file has changed since.
+        pub stamp: FileStamp,
+        /// The whole-clip fingerprint computed alongside `hashes`, if `--clip-hash-mode`
+        /// is on and one could be computed.
+        pub clip_hash: Option<ClipHash>,
+        /// The coarse whole-video fingerprint computed alongside `hashes`, if
+        /// `--coarse-prefilter` is on and one could be computed.
+        pub coarse_fingerprint: Option<CoarseFingerprint>,
     }
 }
 
@@ -296,12 +505,37 @@ mod video {
     #[derive(Clone, Copy)]
     pub struct Ctx<'env> {
         pub preproc_args: &'env Preproc,
+        pub hasher: &'env hashalg::Hasher,
         pub simi_args: &'env Simi,
         pub get_hashes_args: &'env GetHashes,
         pub ignored_hashes: &'env Ignored,
         pub new_files: &'env WorkQueue<&'env SimplePath>,
         pub repo_grave: Option<&'env Mutex<Repo>>,
+        pub failures: &'env Mutex<FailureCache>,
+        pub progress: Option<&'env mpsc::Sender<ProgressEvent>>,
         pub term_cookie: &'env termination::Cookie,
+        /// Whether a whole-clip [`clip_hash::ClipHash`] should also be computed in
+        /// [`get_hashes`], per `--clip-hash-mode`.
+        pub clip_hash_mode: bool,
+        /// Whether a [`CoarseFingerprint`] should also be computed in [`get_hashes`],
+        /// per `--coarse-prefilter`.
+        pub coarse_prefilter: bool,
+    }
+
+    /// Sends `event` down `ctx.progress`, if anyone is listening. Never fails the
+    /// caller; a dead or absent receiver just means nothing is watching.
+    fn send_progress(ctx: Ctx, event: ProgressEvent) {
+        if let Some(tx) = ctx.progress {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Records `vid_path` as failed in the shared [`FailureCache`], so it isn't
+    /// re-attempted on the next run unless its mtime/size change.
+    fn record_failure(ctx: Ctx, vid_path: &SimplePath, error: impl std::fmt::Display) {
+        if let Err(e) = ctx.failures.lock().unwrap().put(vid_path, error) {
+            log::warn!("Failed to record the failure cache entry for '{vid_path}': {e:?}");
+        }
     }
 
     pub fn main<'env>(
@@ -319,24 +553,78 @@ mod video {
             }
 
             log::info!("Progress: {}/{} videos", i + 1, ctx.new_files.len());
+            send_progress(
+                ctx,
+                ProgressEvent::FileStarted {
+                    path: vid_path.to_string(),
+                    index: i,
+                    total: ctx.new_files.len(),
+                },
+            );
+
+            let embedded_offset = match motion_photo_embedded_offset(vid_path.as_path()) {
+                Ok(offset) => offset,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to check '{}' for an embedded motion-photo clip: {:?}",
+                        vid_path,
+                        e
+                    );
+                    None
+                }
+            };
+            if let Some(offset) = embedded_offset {
+                log::info!("'{}' looks like a motion photo, clip starts at byte {}", vid_path, offset);
+            }
+
+            let stamp = match FileStamp::of(vid_path.as_path()) {
+                Ok(stamp) => stamp,
+                Err(e) => {
+                    log::error!("Failed to stat '{}': {:?}", vid_path, e);
+                    record_failure(ctx, vid_path, &e);
+                    failed.push((vid_path, eyre::Error::from(e)));
+                    continue;
+                }
+            };
 
             let before = Instant::now();
-            let hashes_res = get_hashes(ctx, vid_path);
+            let hashes_res = get_hashes(ctx, vid_path, embedded_offset);
             let elapsed = humantime::Duration::from(before.elapsed());
             log::info!("It took {} to get the hashes from {}", elapsed, vid_path);
 
-            let hashes = match hashes_res {
+            let (hashes, clip_hash, coarse_fingerprint) = match hashes_res {
                 Ok(ok) => ok,
                 Err(e) => {
                     log::error!("Failed to get the hashes from '{}': {:?}", vid_path, e);
+                    record_failure(ctx, vid_path, &e);
                     failed.push((vid_path, e));
                     continue;
                 }
             };
 
+            if hashes.is_empty() {
+                let msg = "produced zero usable hashes (too short, or every frame was filtered out)";
+                log::error!("'{}' {}", vid_path, msg);
+                record_failure(ctx, vid_path, msg);
+                failed.push((vid_path, eyre::eyre!(msg)));
+                continue;
+            }
+
+            send_progress(
+                ctx,
+                ProgressEvent::FileFinished {
+                    path: vid_path.to_string(),
+                    hashes_found: hashes.len(),
+                },
+            );
+
             let load = Payload {
                 video_path: vid_path,
                 hashes,
+                embedded_offset,
+                stamp,
+                clip_hash,
+                coarse_fingerprint,
             };
             if !try_send(&tx, load) {
                 log::error!("The tree thread seems to be down");
@@ -359,14 +647,86 @@ mod video {
         Ok(())
     }
 
+    /// Extensions worth sniffing for an embedded motion-photo clip; anything else isn't
+    /// even read for this purpose.
+    const MOTION_PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "heic", "heif"];
+
+    /// If `path` has a still-image extension and contains an embedded MP4 clip (see
+    /// [`motion_photo::find_embedded_video`]), returns the byte offset of that clip.
+    fn motion_photo_embedded_offset(path: &Path) -> eyre::Result<Option<u64>> {
+        let is_candidate = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| MOTION_PHOTO_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+        if !is_candidate {
+            return Ok(None);
+        }
+
+        let bytes = std::fs::read(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        Ok(motion_photo::find_embedded_video(&bytes))
+    }
+
+    /// Copies the clip starting at `offset` in `path` into a fresh temp file, so it can
+    /// be handed to [`FrameExtractor`] as an ordinary, independently-seekable MP4
+    /// instead of one wrapped in a JPEG/HEIC host.
+    fn extract_embedded_clip(path: &Path, offset: u64) -> eyre::Result<tempfile::NamedTempFile> {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let mut src = std::fs::File::open(path)
+            .wrap_err_with(|| format!("failed to open {}", path.display()))?;
+        src.seek(SeekFrom::Start(offset))
+            .wrap_err("failed to seek to the embedded clip")?;
+
+        let mut tmp = tempfile::NamedTempFile::new().wrap_err("failed to create a temp file")?;
+        std::io::copy(&mut src, &mut tmp).wrap_err("failed to copy the embedded clip out")?;
+        tmp.flush().wrap_err("failed to flush the embedded clip")?;
+        Ok(tmp)
+    }
+
+    /// Downsamples `frame` to a small grayscale thumbnail, cheap enough to keep
+    /// [`clip_hash::FRAMES`]-many of around in memory while decoding, for later
+    /// [`clip_hash::hash_frames`].
+    fn clip_hash_thumbnail(frame: &RgbImage) -> image::GrayImage {
+        const THUMB: u32 = 64;
+        let gray = image::imageops::colorops::grayscale(frame);
+        image::imageops::resize(&gray, THUMB, THUMB, image::imageops::FilterType::Triangle)
+    }
+
+    /// Keeps `buffer` from growing unboundedly over a long video while still ending up
+    /// with an even spread of samples across the whole clip: once it holds more than
+    /// `target * 2` thumbnails, it's thinned back down to `target` evenly-spaced ones.
+    fn thin_clip_hash_buffer(buffer: &mut Vec<image::GrayImage>, target: usize) {
+        if buffer.len() <= target * 2 {
+            return;
+        }
+        let kept: Vec<image::GrayImage> = (0..target)
+            .map(|i| buffer[i * buffer.len() / target].clone())
+            .collect();
+        *buffer = kept;
+    }
+
     fn get_hashes<'env>(
         ctx: Ctx<'env>,
         video: &'env SimplePath,
-    ) -> eyre::Result<Vec<Frame>> {
+        embedded_offset: Option<u64>,
+    ) -> eyre::Result<(Vec<Frame>, Option<ClipHash>, Option<CoarseFingerprint>)> {
         log::info!("Retrieving hashes for: {}", video);
 
+        let clip_guard: Option<tempfile::NamedTempFile> = match embedded_offset {
+            Some(offset) => Some(
+                extract_embedded_clip(video.as_path(), offset)
+                    .wrap_err("Failed to extract the embedded motion-photo clip")?,
+            ),
+            None => None,
+        };
+        let extractor_path = clip_guard
+            .as_ref()
+            .map(|tmp| tmp.path())
+            .unwrap_or_else(|| video.as_path());
+
         let mut extractor = FrameExtractor::new_with_logger(
-            video.as_path(),
+            extractor_path,
             ContextLogger::new(video.as_path()),
         )
         .wrap_err("Failed to create the extractor")?;
@@ -388,9 +748,10 @@ mod video {
 
         let step = calc_step(approx_len, min_frames, max_step);
 
+        let estimated_total = estimated_num_of_frames(approx_len, step);
+
         let mut graveyard_entry = LazyEntry::new();
-        let mut hashes: Vec<Frame> =
-            Vec::with_capacity(estimated_num_of_frames(approx_len, step));
+        let mut hashes: Vec<Frame> = Vec::with_capacity(estimated_total);
 
         // TODO: a flag to skip doing this? For tests maybe?
         let (video_skip, video_skip_end): (Duration, Duration) = skip_beg_end(approx_len);
@@ -423,6 +784,8 @@ mod video {
             steps
         });
         let mut is_phantom = false;
+        let mut frames_processed: usize = 0;
+        let mut clip_hash_frames: Vec<image::GrayImage> = Vec::new();
         while let Some((ts, frame)) =
             extractor.next().wrap_err("Failed to get a frame")?
         {
@@ -432,8 +795,23 @@ mod video {
                 }
             }
 
+            frames_processed += 1;
+
+            if ctx.clip_hash_mode && !is_phantom {
+                clip_hash_frames.push(clip_hash_thumbnail(&frame));
+                thin_clip_hash_buffer(&mut clip_hash_frames, clip_hash::FRAMES);
+            }
             log_every.perform(|| {
-                log::debug!("At timestamp: {}/{}", ts.to_string(), approx_len)
+                log::debug!("At timestamp: {}/{}", ts.to_string(), approx_len);
+                send_progress(
+                    ctx,
+                    ProgressEvent::FramesExtracted {
+                        path: video.to_string(),
+                        frames_done: frames_processed,
+                        estimated_total,
+                        eta_secs: progress::eta_secs(estimated_total, frames_processed, step),
+                    },
+                );
             });
 
             use FrameToHashResult as F;
@@ -460,7 +838,7 @@ mod video {
                         }
                     }
                 }
-                err @ F::Ignored | err @ F::Empty | err @ F::TooOneColor
+                err @ F::Ignored | err @ F::Empty | err @ F::TooOneColor | err @ F::Degenerate
                     if ctx.repo_grave.is_some() =>
                 {
                     let entry =
@@ -476,7 +854,11 @@ mod video {
                         &frame,
                     )?;
                 }
-                F::TooOneColor | F::TooSimilarToPrevious | F::Ignored | F::Empty => (),
+                F::TooOneColor
+                | F::TooSimilarToPrevious
+                | F::Ignored
+                | F::Empty
+                | F::Degenerate => (),
             }
 
             let (series, step) = stepper.step_non_zero();
@@ -485,13 +867,39 @@ mod video {
         }
 
         log::info!("Got {} hashes from: {}", hashes.len(), video);
-        Ok(hashes)
+
+        let clip_hash = if ctx.clip_hash_mode {
+            thin_clip_hash_buffer(&mut clip_hash_frames, clip_hash::FRAMES);
+            match clip_hash::hash_frames(&clip_hash_frames) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    log::warn!("Failed to compute a clip hash for '{}': {}", video, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let coarse_fingerprint = if ctx.coarse_prefilter {
+            let canonical_hashes: Vec<Hamming> = hashes
+                .iter()
+                .filter(|frame| frame.should_be_stored())
+                .map(|frame| frame.hash)
+                .collect();
+            CoarseFingerprint::from_hashes(&canonical_hashes)
+        } else {
+            None
+        };
+
+        Ok((hashes, clip_hash, coarse_fingerprint))
     }
 
     #[derive(Debug)]
     enum FrameToHashResult {
         Empty,
         TooOneColor,
+        Degenerate,
         Ignored,
         TooSimilarToPrevious,
         Ok(Hamming),
@@ -502,6 +910,7 @@ mod video {
             match self {
                 FrameToHashResult::Empty => "empty",
                 FrameToHashResult::TooOneColor => "too_one_color",
+                FrameToHashResult::Degenerate => "degenerate",
                 FrameToHashResult::Ignored => "ignored",
                 FrameToHashResult::TooSimilarToPrevious => "similar_previous",
                 FrameToHashResult::Ok(_) => "ok",
@@ -509,16 +918,28 @@ mod video {
         }
     }
 
+    /// A hash with every bit the same (all zero or all one) almost always means the
+    /// decoder handed back an empty/alpha-only buffer rather than real frame content;
+    /// treating it as a genuine hash would let unrelated blank frames match each other.
+    fn is_degenerate(hash: Hamming) -> bool {
+        hash.0 == 0 || hash.0 == u128::MAX
+    }
+
     fn frame_to_hash<'env>(
         ctx: Ctx<'env>,
         frame: &RgbImage,
         last_hash: Option<Hamming>,
     ) -> FrameToHashResult {
-        let hash = match ctx.preproc_args.hash_img(&frame) {
-            Ok(hash) => hash,
+        let processed = match ctx.preproc_args.check(frame) {
+            Ok(sub) => sub,
             Err(PreprocError::Empty) => return FrameToHashResult::Empty,
             Err(PreprocError::TooOneColor) => return FrameToHashResult::TooOneColor,
         };
+        let hash = ctx.hasher.hash_sub(&processed);
+
+        if is_degenerate(hash) {
+            return FrameToHashResult::Degenerate;
+        }
 
         if ctx.ignored_hashes.is_ignored(ctx.simi_args, hash) {
             return FrameToHashResult::Ignored;
@@ -609,7 +1030,26 @@ mod tree {
     #[derive(Clone, Copy)]
     pub struct Ctx<'env> {
         pub simi_args: &'env Simi,
+        pub progress: Option<&'env mpsc::Sender<ProgressEvent>>,
         pub term_cookie: &'env termination::Cookie,
+        /// The shared clip-hash cache, present only when `--clip-hash-mode` is on.
+        pub clip_hashes: Option<&'env Mutex<ClipHashCache>>,
+        /// `--reference-dir`, empty when the feature is off. See
+        /// [`find_similar_videos`]'s use of it.
+        pub reference_dirs: &'env [SimplePathBuf],
+        /// The shared coarse-fingerprint index, present only when `--coarse-prefilter`
+        /// is on. See [`find_similar_videos`]'s use of it.
+        pub coarse_index: Option<&'env Mutex<CoarseIndex>>,
+        /// `--coarse-tolerance`.
+        pub coarse_tolerance: Distance,
+    }
+
+    /// Sends `event` down `ctx.progress`, if anyone is listening. Never fails the
+    /// caller; a dead or absent receiver just means nothing is watching.
+    fn send_progress(ctx: Ctx, event: ProgressEvent) {
+        if let Some(tx) = ctx.progress {
+            let _ = tx.send(event);
+        }
     }
 
     pub fn main<'env>(
@@ -620,21 +1060,48 @@ mod tree {
     ) -> eyre::Result<()> {
         log::debug!("Tree worker working");
 
-        while let Ok(Payload { video_path, hashes }) = rx.recv() {
+        while let Ok(Payload {
+            video_path,
+            hashes,
+            embedded_offset,
+            stamp,
+            clip_hash,
+            coarse_fingerprint,
+        }) = rx.recv()
+        {
             if ctx.term_cookie.is_terminating() {
                 log::warn!("Termination signal received");
                 break;
             }
 
+            let candidates = coarse_shortlist(ctx, coarse_fingerprint.as_ref());
+
             log::info!(
                 "Finding dups of '{}', which has {} hashes",
                 video_path,
                 hashes.len()
             );
-            let collisions = find_similar_videos(ctx, video_path, &hashes, &tree)
-                .wrap_err("failed to find similar videos")?;
+            let collisions = find_similar_videos(
+                ctx,
+                video_path,
+                &hashes,
+                embedded_offset,
+                stamp,
+                candidates.as_ref(),
+                &tree,
+            )
+            .wrap_err("failed to find similar videos")?;
             let similar_videos = collisions.all_others();
             log::info!("Found {} duplicate videos", similar_videos.len());
+            if !similar_videos.is_empty() {
+                send_progress(
+                    ctx,
+                    ProgressEvent::DuplicatesFound {
+                        path: video_path.to_string(),
+                        count: similar_videos.len(),
+                    },
+                );
+            }
 
             if !similar_videos.is_empty() {
                 log::info!("Creating the dup dir");
@@ -643,11 +1110,20 @@ mod tree {
                 log::info!("Done!");
             }
 
+            if let (Some(clip_hashes), Some(hash)) = (ctx.clip_hashes, clip_hash) {
+                check_clip_hash(ctx, clip_hashes, video_path, hash);
+            }
+
+            if let (Some(coarse_index), Some(fingerprint)) = (ctx.coarse_index, coarse_fingerprint)
+            {
+                coarse_index.lock().unwrap().insert(video_path, fingerprint);
+            }
+
             let all_hashes_len = hashes.len();
             let mut hashes = hashes;
             hashes.retain(|f| f.should_be_stored());
             log::info!("Saving {} hashes out of {}", hashes.len(), all_hashes_len);
-            save_video(hashes, &mut tree, video_path)
+            save_video(hashes, &mut tree, video_path, embedded_offset, stamp)
                 .wrap_err("failed to save some video hashes to the tree")?;
             log::info!("Done saving");
         }
@@ -655,16 +1131,66 @@ mod tree {
         log::info!("Closing the tree");
         tree.close().wrap_err("failed to close the tree")?;
         log::info!("Closed!");
+        send_progress(ctx, ProgressEvent::TreeSaveDone);
 
         log::debug!("Tree worker ended");
 
         Ok(())
     }
 
+    /// Checks `hash` against every clip hash seen so far, logging any that land within
+    /// `ctx.simi_args.threshold()` of it, then records it for future videos to be
+    /// checked against in turn. Just a cheap heads-up alongside the real per-frame
+    /// search above, not a replacement for it -- nothing here affects `dup_dir`.
+    fn check_clip_hash(
+        ctx: Ctx,
+        clip_hashes: &Mutex<ClipHashCache>,
+        video_path: &SimplePath,
+        hash: ClipHash,
+    ) {
+        let mut cache = clip_hashes.lock().unwrap();
+
+        let matches: Vec<String> = cache
+            .find_within(hash, ctx.simi_args.threshold())
+            .map(|(path, dist)| format!("'{path}' (distance {dist})"))
+            .collect();
+        if !matches.is_empty() {
+            log::info!(
+                "'{}' has a similar whole-clip fingerprint to: {}",
+                video_path,
+                matches.join(", ")
+            );
+        }
+
+        cache.insert(video_path, hash);
+    }
+
+    /// The set of already-indexed videos whose coarse fingerprint is within
+    /// `ctx.coarse_tolerance` of `fingerprint`, for [`find_similar_videos`] to shortlist
+    /// against when `--coarse-prefilter` is on. `None` when the feature is off or no
+    /// fingerprint could be computed for this video, meaning "don't shortlist, search
+    /// the whole tree like usual".
+    fn coarse_shortlist(
+        ctx: Ctx,
+        fingerprint: Option<&CoarseFingerprint>,
+    ) -> Option<HashSet<SimplePathBuf>> {
+        let index = ctx.coarse_index?;
+        let fingerprint = fingerprint?;
+        let index = index.lock().unwrap();
+        Some(
+            index
+                .find_within(fingerprint, ctx.coarse_tolerance)
+                .map(|path| path.to_owned())
+                .collect(),
+        )
+    }
+
     fn save_video(
         hashes: Vec<Frame>,
         tree: &mut BKTree<VidSrc>,
         video_path: &SimplePath,
+        embedded_offset: Option<u64>,
+        stamp: FileStamp,
     ) -> eyre::Result<()> {
         tree.add_all(hashes.into_iter().map(|frame| {
             assert!(frame.should_be_stored());
@@ -674,7 +1200,13 @@ mod tree {
             // TODO: remove mirror now when only normal orientations are stored?
             // videodup-debug depends on it being there though when it is reading the
             // debuginfo file.
-            (hash, VidSrc::new(ts, video_path.to_owned(), mirror))
+            let vidsrc = match embedded_offset {
+                Some(offset) => {
+                    VidSrc::new_motion_photo(ts, video_path.to_owned(), mirror, offset, stamp)
+                }
+                None => VidSrc::new(ts, video_path.to_owned(), mirror, stamp),
+            };
+            (hash, vidsrc)
         }))
         .wrap_err("failed to add to the tree")?;
         Ok(())
@@ -709,12 +1241,53 @@ mod tree {
         Ok(())
     }
 
+    /// With `reference_dirs` empty, every collision is kept -- the feature is off.
+    /// Otherwise a collision only survives when exactly one of its two frames is under a
+    /// reference directory: two files that are both already in the curated set, or both
+    /// new, colliding with each other is noise when all a caller wants back is "what in
+    /// this batch duplicates something I already keep".
+    fn keep_collision(
+        reference_dirs: &[SimplePathBuf],
+        a: &debug_info::Frame,
+        b: &debug_info::Frame,
+    ) -> bool {
+        reference_dirs.is_empty()
+            || is_under_reference_dir(reference_dirs, a.vidsrc.path())
+                != is_under_reference_dir(reference_dirs, b.vidsrc.path())
+    }
+
+    fn is_under_reference_dir(reference_dirs: &[SimplePathBuf], path: &SimplePath) -> bool {
+        reference_dirs
+            .iter()
+            .any(|dir| path.as_path().starts_with(dir.as_path()))
+    }
+
+    /// `candidates`, when `--coarse-prefilter` produced one: the other videos already
+    /// in the tree whose coarse fingerprint is close enough to this one's to be worth
+    /// the precise per-frame search. An empty shortlist skips the per-frame
+    /// `BKTree::find_within` search entirely, bounding its cost to videos that could
+    /// plausibly match; a non-empty one can't narrow the search itself -- `BKTree`
+    /// has no notion of searching a subset of its sources -- so it instead filters the
+    /// full search's results down to just those candidates.
     fn find_similar_videos<'env>(
         ctx: Ctx<'env>,
         frames_path: &SimplePath,
         frames: &[Frame],
+        embedded_offset: Option<u64>,
+        stamp: FileStamp,
+        candidates: Option<&HashSet<SimplePathBuf>>,
         tree: &'env BKTree<VidSrc>,
     ) -> eyre::Result<Collisions> {
+        if candidates.is_some_and(|candidates| candidates.is_empty()) {
+            log::info!(
+                "'{}' has no coarse-fingerprint candidates, skipping the per-frame search",
+                frames_path
+            );
+            return Ok(Collisions {
+                collisions: Vec::new(),
+            });
+        }
+
         let sims: eyre::Result<Vec<Vec<_>>> = frames
             .par_iter()
             .map(
@@ -722,9 +1295,19 @@ mod tree {
                      ts, hash, mirror, ..
                  }|
                  -> eyre::Result<Vec<_>> {
+                    let vidsrc = match embedded_offset {
+                        Some(offset) => VidSrc::new_motion_photo(
+                            ts.clone(),
+                            frames_path.to_owned(),
+                            *mirror,
+                            offset,
+                            stamp,
+                        ),
+                        None => VidSrc::new(ts.clone(), frames_path.to_owned(), *mirror, stamp),
+                    };
                     let ref_frame = debug_info::Frame {
                         hash: *hash,
-                        vidsrc: VidSrc::new(ts.clone(), frames_path.to_owned(), *mirror),
+                        vidsrc,
                     };
 
                     let mut res = Vec::new();
@@ -736,10 +1319,15 @@ mod tree {
                                 hash: other_hash,
                                 vidsrc: other_src.deserialize(),
                             };
-                            res.push(Collision {
-                                reference: ref_frame.clone(),
-                                other: other_frame,
-                            })
+                            let shortlisted = candidates
+                                .map_or(true, |candidates| candidates.contains(other_frame.vidsrc.path()));
+                            if shortlisted && keep_collision(ctx.reference_dirs, &ref_frame, &other_frame)
+                            {
+                                res.push(Collision {
+                                    reference: ref_frame.clone(),
+                                    other: other_frame,
+                                })
+                            }
                         },
                     )?;
                     Ok(res)
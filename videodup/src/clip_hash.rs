@@ -0,0 +1,226 @@
+//! A whole-clip perceptual hash, computed alongside (not instead of) the per-frame
+//! [`Hamming`](imgdup_common::imghash::hamming::Hamming) hashes the rest of this crate
+//! pushes through [`crate::debug_info::Frame`]/[`crate::debug_info::Collision`]. Rather
+//! than many per-frame hashes searched with a flat threshold, this samples the clip
+//! evenly, stacks the frames into a small `[T, H, W]` volume, and thresholds a separable
+//! 3-D DCT's low-frequency coefficients into one fixed-width fingerprint: far cheaper to
+//! compare than scanning every per-frame hash, and robust to re-encoding since it's an
+//! average over the whole clip rather than any single frame.
+
+use rkyv::{Archive, Serialize};
+
+use image::{GrayImage, Pixel};
+
+/// Frames sampled evenly across a clip's length to build the volume.
+pub const FRAMES: usize = 64;
+/// Each sampled frame is shrunk to this square before the DCT.
+const FRAME_SIZE: u32 = 32;
+/// Side of the low-frequency sub-cube kept out of the `FRAME_SIZE x FRAME_SIZE x
+/// FRAMES` DCT, one bit per coefficient (minus the DC term).
+const BLOCK: usize = 8;
+/// [`BLOCK`]^3 coefficients, minus the DC term, rounded up to a whole number of `u64`s.
+const WORDS: usize = (BLOCK * BLOCK * BLOCK - 1 + 63) / 64;
+
+/// A frame narrower or shorter than this can't be meaningfully resized down to
+/// [`FRAME_SIZE`]; [`hash_frames`] rejects it with [`ClipHashError::DegenerateFrame`]
+/// instead of panicking inside `image::imageops::resize` like a naive clamp-free resize
+/// would.
+const MIN_FRAME_DIM: u32 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClipHashError {
+    #[error("no frames were given to hash")]
+    NoFrames,
+    #[error("only {0} frames were given, need at least {BLOCK}")]
+    TooFewFrames(usize),
+    #[error("a frame is {0}x{1}, too small to hash")]
+    DegenerateFrame(u32, u32),
+}
+
+/// A whole-clip fingerprint: one bit per kept DCT-3D coefficient.
+#[derive(Serialize, Archive, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[archive(check_bytes)]
+pub struct ClipHash([u64; WORDS]);
+
+impl ClipHash {
+    pub fn distance_to(self, other: Self) -> u32 {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+/// Hashes `frames` (already sampled at evenly-spaced points across a clip, in decode
+/// order) into a single [`ClipHash`]. Fewer than [`FRAMES`] is fine -- a short clip just
+/// contributes less temporal resolution -- but at least [`BLOCK`] are required since
+/// that's the depth of the sub-cube kept after the DCT.
+pub fn hash_frames(frames: &[GrayImage]) -> Result<ClipHash, ClipHashError> {
+    if frames.is_empty() {
+        return Err(ClipHashError::NoFrames);
+    }
+    if frames.len() < BLOCK {
+        return Err(ClipHashError::TooFewFrames(frames.len()));
+    }
+
+    let mut volume: Vec<Vec<Vec<f64>>> = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let (w, h) = (frame.width(), frame.height());
+        if w < MIN_FRAME_DIM || h < MIN_FRAME_DIM {
+            return Err(ClipHashError::DegenerateFrame(w, h));
+        }
+
+        let resized = image::imageops::resize(
+            frame,
+            FRAME_SIZE,
+            FRAME_SIZE,
+            image::imageops::FilterType::Lanczos3,
+        );
+        let samples: Vec<Vec<f64>> = (0..FRAME_SIZE)
+            .map(|y| {
+                (0..FRAME_SIZE)
+                    .map(|x| resized.get_pixel(x, y).channels()[0] as f64)
+                    .collect()
+            })
+            .collect();
+        volume.push(samples);
+    }
+
+    let coeffs = dct_3d(&volume);
+
+    // Flatten the low-frequency [BLOCK, BLOCK, BLOCK] sub-cube in (t, y, x) order, then
+    // drop the very first entry: the DC term, which just encodes average brightness and
+    // carries no shape information to threshold against.
+    let mut block: Vec<f64> = Vec::with_capacity(BLOCK * BLOCK * BLOCK);
+    for plane in &coeffs[..BLOCK] {
+        for row in &plane[..BLOCK] {
+            block.extend_from_slice(&row[..BLOCK]);
+        }
+    }
+    block.remove(0);
+
+    let mut sorted = block.clone();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    let mut words = [0u64; WORDS];
+    for (i, &coeff) in block.iter().enumerate() {
+        if coeff > median {
+            words[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    Ok(ClipHash(words))
+}
+
+/// A separable 3-D DCT-II over `[T, H, W]`: the 2-D DCT applied to every frame
+/// individually, then a 1-D DCT applied along the time axis at every `(y, x)` position.
+fn dct_3d(volume: &[Vec<Vec<f64>>]) -> Vec<Vec<Vec<f64>>> {
+    let per_frame: Vec<Vec<Vec<f64>>> = volume.iter().map(|frame| dct_2d(frame)).collect();
+
+    let t = per_frame.len();
+    let h = per_frame[0].len();
+    let w = per_frame[0][0].len();
+
+    let mut result = vec![vec![vec![0.0; w]; h]; t];
+    for y in 0..h {
+        for x in 0..w {
+            let series: Vec<f64> = per_frame.iter().map(|frame| frame[y][x]).collect();
+            let transformed = dct_1d(&series);
+            for (tt, value) in transformed.into_iter().enumerate() {
+                result[tt][y][x] = value;
+            }
+        }
+    }
+    result
+}
+
+/// A separable 2-D DCT-II: the 1-D transform applied to every row, then to every column
+/// of the result. Same shape as `imgdup`'s own per-frame `dct::dct_2d`, duplicated here
+/// since the two crates don't share this kind of image-processing code.
+fn dct_2d(samples: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let by_rows: Vec<Vec<f64>> = samples.iter().map(|row| dct_1d(row)).collect();
+
+    let n = by_rows.len();
+    let m = by_rows[0].len();
+    let mut by_cols = vec![vec![0.0; m]; n];
+    for x in 0..m {
+        let column: Vec<f64> = by_rows.iter().map(|row| row[x]).collect();
+        let column = dct_1d(&column);
+        for (y, value) in column.into_iter().enumerate() {
+            by_cols[y][x] = value;
+        }
+    }
+
+    by_cols
+}
+
+/// The 1-D DCT-II: `F[k] = sum_{n=0}^{N-1} f[n]*cos(pi/N*(n+0.5)*k)`.
+fn dct_1d(input: &[f64]) -> Vec<f64> {
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            input
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| {
+                    let angle = std::f64::consts::PI / n as f64 * (i as f64 + 0.5) * k as f64;
+                    sample * angle.cos()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn solid_frame(w: u32, h: u32, value: u8) -> GrayImage {
+        GrayImage::from_pixel(w, h, image::Luma([value]))
+    }
+
+    fn checkerboard_frame(w: u32, h: u32) -> GrayImage {
+        GrayImage::from_fn(w, h, |x, y| {
+            image::Luma([if (x / 10 + y / 10) % 2 == 0 { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn same_clip_same_hash() {
+        let frames: Vec<GrayImage> = (0..FRAMES).map(|_| checkerboard_frame(64, 64)).collect();
+        assert_eq!(hash_frames(&frames).unwrap(), hash_frames(&frames).unwrap());
+    }
+
+    #[test]
+    fn different_clips_different_hash() {
+        let solid: Vec<GrayImage> = (0..FRAMES).map(|_| solid_frame(64, 64, 128)).collect();
+        let checker: Vec<GrayImage> = (0..FRAMES).map(|_| checkerboard_frame(64, 64)).collect();
+        assert_ne!(hash_frames(&solid).unwrap(), hash_frames(&checker).unwrap());
+    }
+
+    #[test]
+    fn no_frames_is_an_error() {
+        assert!(matches!(hash_frames(&[]), Err(ClipHashError::NoFrames)));
+    }
+
+    #[test]
+    fn too_few_frames_is_an_error() {
+        let frames: Vec<GrayImage> = (0..BLOCK - 1).map(|_| solid_frame(64, 64, 0)).collect();
+        assert!(matches!(
+            hash_frames(&frames),
+            Err(ClipHashError::TooFewFrames(n)) if n == BLOCK - 1
+        ));
+    }
+
+    #[test]
+    fn degenerate_frame_is_an_error_not_a_panic() {
+        let mut frames: Vec<GrayImage> = (0..FRAMES).map(|_| solid_frame(64, 64, 0)).collect();
+        frames[0] = solid_frame(0, 64, 0);
+        assert!(matches!(
+            hash_frames(&frames),
+            Err(ClipHashError::DegenerateFrame(0, 64))
+        ));
+    }
+}
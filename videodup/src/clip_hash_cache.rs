@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+use imgdup_common::utils::simple_path::SimplePath;
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch, HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+use crate::clip_hash::ClipHash;
+
+type RecordsSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<1024>, AllocScratch>,
+>;
+
+/// What's actually persisted to disk via rkyv, one per video a [`ClipHash`] has been
+/// computed for.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+struct ClipHashRecord {
+    path: String,
+    hash: ClipHash,
+}
+
+impl From<&ArchivedClipHashRecord> for ClipHashRecord {
+    fn from(value: &ArchivedClipHashRecord) -> Self {
+        Self {
+            path: value.path.to_string(),
+            hash: value.hash,
+        }
+    }
+}
+
+/// A persistent `path -> `[`ClipHash`]` store, so the whole-clip fingerprint computed
+/// alongside the per-frame hashes in `get_hashes` can be checked against every other
+/// clip seen so far, across runs, without redecoding anything. Unlike
+/// [`crate::failure_cache::FailureCache`] this isn't a negative cache keyed on
+/// mtime/size -- a stale entry for a changed file is harmless (it just means that one
+/// file's clip hash is checked again alongside its new one next run), so entries are
+/// only ever pruned when their path disappears.
+pub struct ClipHashCache {
+    records: HashMap<PathBuf, ClipHashRecord>,
+    dirty: bool,
+}
+
+impl ClipHashCache {
+    pub fn empty() -> Self {
+        Self {
+            records: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the cache from `file`, pruning any record whose path no longer exists on
+    /// disk. A missing `file` is treated the same as an empty cache.
+    pub fn load(file: impl AsRef<Path>) -> eyre::Result<Self> {
+        let file = file.as_ref();
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::empty()),
+            Err(e) => {
+                return Err(e).wrap_err_with(|| format!("failed to read {}", file.display()))
+            }
+        };
+
+        let archived = rkyv::check_archived_root::<Vec<ClipHashRecord>>(&bytes)
+            .map_err(|e| eyre::eyre!("corrupt clip-hash cache at {}: {e}", file.display()))?;
+
+        let records = archived
+            .iter()
+            .map(ClipHashRecord::from)
+            .filter(|record| Path::new(&record.path).exists())
+            .map(|record| (PathBuf::from(&record.path), record))
+            .collect();
+
+        Ok(Self {
+            records,
+            dirty: false,
+        })
+    }
+
+    /// Records `path`'s clip hash, overwriting whatever was there before.
+    pub fn insert(&mut self, path: &SimplePath, hash: ClipHash) {
+        self.records.insert(
+            path.as_path().to_path_buf(),
+            ClipHashRecord {
+                path: path.to_string(),
+                hash,
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// Every other clip within `threshold` of `hash`, furthest first filtered out by
+    /// [`ClipHash::distance_to`]. A brute-force scan: this is meant as a cheap
+    /// pre-filter alongside the per-frame tree search, not a replacement for it, so
+    /// there's no index to maintain.
+    pub fn find_within<'a>(
+        &'a self,
+        hash: ClipHash,
+        threshold: u32,
+    ) -> impl Iterator<Item = (&'a SimplePath, u32)> + 'a {
+        self.records.values().filter_map(move |record| {
+            let dist = record.hash.distance_to(hash);
+            (dist <= threshold).then(|| {
+                let path = SimplePath::new(Path::new(&record.path))
+                    .expect("was a valid SimplePath when inserted");
+                (path, dist)
+            })
+        })
+    }
+
+    /// Drops every record whose path no longer exists on disk.
+    pub fn prune_missing(&mut self) {
+        let before = self.records.len();
+        self.records.retain(|path, _| path.exists());
+        if self.records.len() != before {
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the cache back to `file`, if anything changed since it was loaded.
+    pub fn save(&self, file: impl AsRef<Path>) -> eyre::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let file = file.as_ref();
+        let records: Vec<ClipHashRecord> = self.records.values().cloned().collect();
+        let bytes =
+            serialize_records(&records).wrap_err("failed to serialize the clip-hash cache")?;
+        fs::write(file, bytes).wrap_err_with(|| format!("failed to write {}", file.display()))
+    }
+}
+
+fn serialize_records(records: &Vec<ClipHashRecord>) -> eyre::Result<AlignedVec> {
+    let mut seri = RecordsSerializer::default();
+    seri.serialize_value(records)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
@@ -0,0 +1,94 @@
+//! A coarse whole-video fingerprint used to shortlist candidate videos before the
+//! expensive per-frame [`imgdup_common::bktree::bktree::BKTree::find_within`] search in
+//! `find_similar_videos`, following czkawka/vid_dup_finder's single-descriptor-per-video
+//! model. Unlike [`crate::clip_hash`] (a DCT-3D fingerprint computed from raw frames),
+//! this reuses the [`Hamming`] hashes already computed per frame -- no extra decoding --
+//! by concatenating a handful of evenly-spaced ones.
+
+use rkyv::{Archive, Serialize};
+
+use imgdup_common::imghash::hamming::{Distance, Hamming};
+
+/// How many evenly-spaced per-frame hashes make up a [`CoarseFingerprint`]. Shorter
+/// videos just contribute fewer.
+pub const SAMPLES: usize = 8;
+
+/// A concatenation of up to [`SAMPLES`] evenly-spaced per-frame [`Hamming`] hashes,
+/// compared position-by-position against another video's.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+pub struct CoarseFingerprint(Vec<Hamming>);
+
+impl From<&ArchivedCoarseFingerprint> for CoarseFingerprint {
+    fn from(value: &ArchivedCoarseFingerprint) -> Self {
+        Self(value.0.iter().copied().collect())
+    }
+}
+
+impl CoarseFingerprint {
+    /// Builds a fingerprint out of `hashes` (in decode order), picking up to [`SAMPLES`]
+    /// evenly-spaced entries. `None` if `hashes` is empty -- there's nothing to
+    /// fingerprint.
+    pub fn from_hashes(hashes: &[Hamming]) -> Option<Self> {
+        if hashes.is_empty() {
+            return None;
+        }
+
+        let n = SAMPLES.min(hashes.len());
+        let samples = (0..n)
+            .map(|i| {
+                let idx = if n == 1 {
+                    0
+                } else {
+                    i * (hashes.len() - 1) / (n - 1)
+                };
+                hashes[idx]
+            })
+            .collect();
+        Some(Self(samples))
+    }
+
+    /// The sum of position-by-position [`Hamming::distance_to`], truncated to the
+    /// shorter of the two fingerprints if they sampled different numbers of hashes.
+    /// Meant to be compared against a generous tolerance, not the precise per-frame
+    /// threshold -- it's a shortlist filter, not a verdict.
+    pub fn distance_to(&self, other: &Self) -> Distance {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| a.distance_to(*b))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_hashes_is_none() {
+        assert!(CoarseFingerprint::from_hashes(&[]).is_none());
+    }
+
+    #[test]
+    fn identical_hashes_are_no_distance_apart() {
+        let hashes = vec![Hamming(0b1010); 20];
+        let a = CoarseFingerprint::from_hashes(&hashes).unwrap();
+        let b = CoarseFingerprint::from_hashes(&hashes).unwrap();
+        assert_eq!(0, a.distance_to(&b));
+    }
+
+    #[test]
+    fn fewer_hashes_than_samples_still_works() {
+        let hashes = vec![Hamming(0), Hamming(u128::MAX)];
+        let fp = CoarseFingerprint::from_hashes(&hashes).unwrap();
+        assert_eq!(0, fp.distance_to(&fp));
+    }
+
+    #[test]
+    fn different_hashes_have_nonzero_distance() {
+        let a = CoarseFingerprint::from_hashes(&vec![Hamming(0); 20]).unwrap();
+        let b = CoarseFingerprint::from_hashes(&vec![Hamming(u128::MAX); 20]).unwrap();
+        assert_eq!(Hamming::BITS * SAMPLES as u32, a.distance_to(&b));
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+use imgdup_common::{imghash::hamming::Distance, utils::simple_path::SimplePath};
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch, HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+use crate::coarse_fingerprint::CoarseFingerprint;
+
+type RecordsSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<1024>, AllocScratch>,
+>;
+
+/// What's actually persisted to disk via rkyv, one per video a [`CoarseFingerprint`] has
+/// been computed for.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+struct CoarseRecord {
+    path: String,
+    fingerprint: CoarseFingerprint,
+}
+
+impl From<&ArchivedCoarseRecord> for CoarseRecord {
+    fn from(value: &ArchivedCoarseRecord) -> Self {
+        Self {
+            path: value.path.to_string(),
+            fingerprint: CoarseFingerprint::from(&value.fingerprint),
+        }
+    }
+}
+
+/// A persistent `path -> `[`CoarseFingerprint`]` store backing `--coarse-prefilter`, so
+/// a video's coarse fingerprint can be compared against every other video's without
+/// redecoding anything. Pruned the same way as [`crate::clip_hash_cache::ClipHashCache`]
+/// -- a stale entry for a changed file is harmless, since it's just re-shortlisted
+/// alongside its freshly-computed fingerprint next run -- so entries are only ever
+/// pruned when their path disappears.
+pub struct CoarseIndex {
+    records: HashMap<PathBuf, CoarseRecord>,
+    dirty: bool,
+}
+
+impl CoarseIndex {
+    pub fn empty() -> Self {
+        Self {
+            records: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the index from `file`, pruning any record whose path no longer exists on
+    /// disk. A missing `file` is treated the same as an empty index.
+    pub fn load(file: impl AsRef<Path>) -> eyre::Result<Self> {
+        let file = file.as_ref();
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::empty()),
+            Err(e) => {
+                return Err(e).wrap_err_with(|| format!("failed to read {}", file.display()))
+            }
+        };
+
+        let archived = rkyv::check_archived_root::<Vec<CoarseRecord>>(&bytes)
+            .map_err(|e| eyre::eyre!("corrupt coarse-fingerprint index at {}: {e}", file.display()))?;
+
+        let records = archived
+            .iter()
+            .map(CoarseRecord::from)
+            .filter(|record| Path::new(&record.path).exists())
+            .map(|record| (PathBuf::from(&record.path), record))
+            .collect();
+
+        Ok(Self {
+            records,
+            dirty: false,
+        })
+    }
+
+    /// Records `path`'s coarse fingerprint, overwriting whatever was there before.
+    pub fn insert(&mut self, path: &SimplePath, fingerprint: CoarseFingerprint) {
+        self.records.insert(
+            path.as_path().to_path_buf(),
+            CoarseRecord {
+                path: path.to_string(),
+                fingerprint,
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// Every other video whose coarse fingerprint is within `tolerance` of
+    /// `fingerprint`, the shortlist `find_similar_videos` restricts its precise
+    /// per-frame search to when `--coarse-prefilter` is on.
+    pub fn find_within<'a>(
+        &'a self,
+        fingerprint: &CoarseFingerprint,
+        tolerance: Distance,
+    ) -> impl Iterator<Item = &'a SimplePath> + 'a {
+        self.records.values().filter_map(move |record| {
+            (record.fingerprint.distance_to(fingerprint) <= tolerance).then(|| {
+                SimplePath::new(Path::new(&record.path))
+                    .expect("was a valid SimplePath when inserted")
+            })
+        })
+    }
+
+    /// Drops every record whose path no longer exists on disk.
+    pub fn prune_missing(&mut self) {
+        let before = self.records.len();
+        self.records.retain(|path, _| path.exists());
+        if self.records.len() != before {
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the index back to `file`, if anything changed since it was loaded.
+    pub fn save(&self, file: impl AsRef<Path>) -> eyre::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let file = file.as_ref();
+        let records: Vec<CoarseRecord> = self.records.values().cloned().collect();
+        let bytes = serialize_records(&records)
+            .wrap_err("failed to serialize the coarse-fingerprint index")?;
+        fs::write(file, bytes).wrap_err_with(|| format!("failed to write {}", file.display()))
+    }
+}
+
+fn serialize_records(records: &Vec<CoarseRecord>) -> eyre::Result<AlignedVec> {
+    let mut seri = RecordsSerializer::default();
+    seri.serialize_value(records)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::io::{Read, Write};
 
 use imgdup_common::{imghash::hamming::Hamming, utils::simple_path::SimplePath};
 
@@ -6,6 +7,39 @@ use crate::video_source::VidSrc;
 
 pub const DEBUG_INFO_FILENAME: &str = "debuginfo";
 
+/// Magic prefix written before the bincode payload in [`Format::Binary`], so that
+/// [`read_from`] can auto-detect the format. RON files are text starting with
+/// `Collisions(`, so this won't ever collide with one.
+const BINARY_MAGIC: &[u8; 4] = b"ICB1";
+
+/// The magic bytes a gzip stream starts with (RFC 1952).
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("ron: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("ron: {0}")]
+    RonSpanned(#[from] ron::error::SpannedError),
+    #[error("bincode: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The on-disk encoding used for a collision dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable, the historical default. Bloated and slow for millions of
+    /// [`Collision`]s.
+    RonPretty,
+    /// Compact, fixed-int little-endian encoding (bincode), prefixed with
+    /// [`BINARY_MAGIC`].
+    Binary,
+}
+
 /// A video frame with its hash and where to find it
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Frame {
@@ -42,11 +76,70 @@ impl Collisions {
     }
 }
 
-pub fn save_to(writer: impl std::io::Write, info: &Collisions) -> ron::Result<()> {
-    let conf = ron::ser::PrettyConfig::new().struct_names(true);
-    ron::ser::to_writer_pretty(writer, info, conf)
+pub fn save_to(writer: impl Write, info: &Collisions) -> Result<()> {
+    save_to_with(writer, info, Format::RonPretty)
+}
+
+pub fn save_to_with(mut writer: impl Write, info: &Collisions, format: Format) -> Result<()> {
+    match format {
+        Format::RonPretty => {
+            let conf = ron::ser::PrettyConfig::new().struct_names(true);
+            ron::ser::to_writer_pretty(writer, info, conf)?;
+        }
+        Format::Binary => {
+            writer.write_all(BINARY_MAGIC)?;
+            bincode::serialize_into(writer, info)?;
+        }
+    }
+    Ok(())
+}
+
+/// Like [`save_to_with`], but gzip-compresses the output, cutting file size
+/// substantially for large collision dumps.
+pub fn save_to_compressed(writer: impl Write, info: &Collisions, format: Format) -> Result<()> {
+    let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+    save_to_with(&mut encoder, info, format)?;
+    encoder.finish()?;
+    Ok(())
 }
 
-pub fn read_from(reader: impl std::io::Read) -> ron::error::SpannedResult<Collisions> {
-    ron::de::from_reader(reader)
+/// Reads a [`Collisions`] dump, auto-detecting whether it is gzip-compressed and
+/// whether it is [`Format::RonPretty`] or [`Format::Binary`] by sniffing
+/// [`GZIP_MAGIC`]/[`BINARY_MAGIC`].
+pub fn read_from(mut reader: impl Read) -> Result<Collisions> {
+    let mut prefix = [0u8; BINARY_MAGIC.len()];
+    let read_n = read_prefix(&mut reader, &mut prefix)?;
+
+    if read_n >= GZIP_MAGIC.len() && prefix[..GZIP_MAGIC.len()] == GZIP_MAGIC {
+        let rest = std::io::Cursor::new(prefix[..read_n].to_vec()).chain(reader);
+        return read_from(flate2::read::GzDecoder::new(rest));
+    }
+
+    if read_n == prefix.len() && prefix == *BINARY_MAGIC {
+        return read_from_with(reader, Format::Binary);
+    }
+
+    let rest = std::io::Cursor::new(prefix[..read_n].to_vec()).chain(reader);
+    read_from_with(rest, Format::RonPretty)
+}
+
+pub fn read_from_with(reader: impl Read, format: Format) -> Result<Collisions> {
+    match format {
+        Format::RonPretty => Ok(ron::de::from_reader(reader)?),
+        // NOTE: assumes `reader` is already positioned right after `BINARY_MAGIC`.
+        Format::Binary => Ok(bincode::deserialize_from(reader)?),
+    }
+}
+
+/// Like `read_exact`, but stops at EOF instead of erroring, returning how many bytes
+/// were actually read.
+fn read_prefix(mut reader: impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
 }
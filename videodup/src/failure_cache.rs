@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{self, Context};
+use imgdup_common::utils::simple_path::SimplePath;
+use rkyv::{
+    ser::{
+        serializers::{
+            AlignedSerializer, AllocScratch, CompositeSerializer, FallbackScratch, HeapScratch,
+        },
+        Serializer,
+    },
+    AlignedVec, Archive, Serialize,
+};
+
+type RecordsSerializer = CompositeSerializer<
+    AlignedSerializer<AlignedVec>,
+    FallbackScratch<HeapScratch<1024>, AllocScratch>,
+>;
+
+/// What's actually persisted to disk via rkyv, one per video that failed to ingest.
+#[derive(Serialize, Archive, Clone, Debug)]
+#[archive(check_bytes)]
+struct FailureRecord {
+    path: String,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    size: u64,
+    error: String,
+}
+
+impl From<&ArchivedFailureRecord> for FailureRecord {
+    fn from(value: &ArchivedFailureRecord) -> Self {
+        Self {
+            path: value.path.to_string(),
+            mtime_secs: value.mtime_secs,
+            mtime_nanos: value.mtime_nanos,
+            size: value.size,
+            error: value.error.to_string(),
+        }
+    }
+}
+
+/// A persistent `path -> (mtime, size, error)` negative cache, so a video that
+/// reliably fails to ingest (a corrupt container, a missing codec, zero usable frames)
+/// isn't redecoded and re-attempted on every run. A record is only trusted while the
+/// file it was recorded from still has the exact mtime and size it had when it failed;
+/// anything else is treated as a miss, so an edited-in-place file gets a fresh attempt.
+pub struct FailureCache {
+    records: HashMap<PathBuf, FailureRecord>,
+    dirty: bool,
+}
+
+impl FailureCache {
+    pub fn empty() -> Self {
+        Self {
+            records: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the cache from `file`, pruning any record whose path no longer exists on
+    /// disk. A missing `file` is treated the same as an empty cache.
+    pub fn load(file: impl AsRef<Path>) -> eyre::Result<Self> {
+        let file = file.as_ref();
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::empty()),
+            Err(e) => return Err(e).wrap_err_with(|| format!("failed to read {}", file.display())),
+        };
+
+        let archived = rkyv::check_archived_root::<Vec<FailureRecord>>(&bytes)
+            .map_err(|e| eyre::eyre!("corrupt failure cache at {}: {e}", file.display()))?;
+
+        let records = archived
+            .iter()
+            .map(FailureRecord::from)
+            .filter(|record| Path::new(&record.path).exists())
+            .map(|record| (PathBuf::from(&record.path), record))
+            .collect();
+
+        Ok(Self {
+            records,
+            dirty: false,
+        })
+    }
+
+    /// Returns the error `path` failed with, or `None` if there's no record or the
+    /// file's mtime/size no longer matches what was recorded, either of which means the
+    /// caller should give it a fresh attempt.
+    pub fn get(&self, path: &SimplePath) -> eyre::Result<Option<&str>> {
+        let Some(record) = self.records.get(path.as_path()) else {
+            return Ok(None);
+        };
+
+        let meta = fs::symlink_metadata(path.as_path())
+            .wrap_err_with(|| format!("failed to stat {path}"))?;
+        let (mtime_secs, mtime_nanos) = split_mtime(meta.modified()?)?;
+
+        if record.mtime_secs != mtime_secs
+            || record.mtime_nanos != mtime_nanos
+            || record.size != meta.len()
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(&record.error))
+    }
+
+    pub fn put(&mut self, path: &SimplePath, error: impl std::fmt::Display) -> eyre::Result<()> {
+        let meta = fs::symlink_metadata(path.as_path())
+            .wrap_err_with(|| format!("failed to stat {path}"))?;
+        let (mtime_secs, mtime_nanos) = split_mtime(meta.modified()?)?;
+
+        self.records.insert(
+            path.as_path().to_path_buf(),
+            FailureRecord {
+                path: path.to_string(),
+                mtime_secs,
+                mtime_nanos,
+                size: meta.len(),
+                error: error.to_string(),
+            },
+        );
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Drops every record, so the next run gives every source a fresh attempt. Used by
+    /// `--retry-failed`.
+    pub fn clear(&mut self) {
+        if !self.records.is_empty() {
+            self.records.clear();
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the cache back to `file`, if anything changed since it was loaded.
+    pub fn save(&self, file: impl AsRef<Path>) -> eyre::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let file = file.as_ref();
+        let records: Vec<FailureRecord> = self.records.values().cloned().collect();
+        let bytes =
+            serialize_records(&records).wrap_err("failed to serialize the failure cache")?;
+        fs::write(file, bytes).wrap_err_with(|| format!("failed to write {}", file.display()))
+    }
+}
+
+fn serialize_records(records: &Vec<FailureRecord>) -> eyre::Result<AlignedVec> {
+    let mut seri = RecordsSerializer::default();
+    seri.serialize_value(records)
+        .map_err(|e| eyre::eyre!("{e:?}"))?;
+    Ok(seri.into_serializer().into_inner())
+}
+
+fn split_mtime(mtime: SystemTime) -> eyre::Result<(u64, u32)> {
+    let dur = mtime
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("file mtime is before the unix epoch")?;
+    Ok((dur.as_secs(), dur.subsec_nanos()))
+}
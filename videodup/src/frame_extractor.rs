@@ -3,6 +3,7 @@ pub mod logger;
 pub mod timestamp;
 
 pub use frame_extractor::FrameExtractor;
+pub use frame_extractor::HwAccel;
 pub use frame_extractor::Result;
 pub use timestamp::Timestamp;
 
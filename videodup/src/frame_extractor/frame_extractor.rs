@@ -1,7 +1,9 @@
 extern crate ffmpeg_next as ffmpeg;
 
+use std::any::Any;
 use std::cell::RefCell;
 use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 use std::sync::OnceLock;
 use std::time::Duration;
@@ -20,7 +22,14 @@ use ffmpeg::software::scaling::context::Context as ScalingContext;
 use ffmpeg::util::log as ffmpeglog;
 use ffmpeg::{Dictionary, Packet as CodecPacket, Rational, Rescale};
 use ffmpeg_sys_next::{AV_NOPTS_VALUE, AV_TIME_BASE_Q};
+use image::imageops::{self, FilterType};
 use image::RgbImage;
+use imgdup_common::utils::blurhash;
+
+use ffmpeg_sys_next::{
+    AVBufferRef, AVCodec, AVCodecContext, AVHWDeviceType, AVPictureType, AVPixelFormat,
+    AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX,
+};
 
 // TODO: a dedicated error type should probably be preferred here?
 pub type Result<T> = eyre::Result<T>;
@@ -28,6 +37,126 @@ pub type Result<T> = eyre::Result<T>;
 static FFMPEG_INITIALIZED: OnceLock<std::result::Result<(), ffmpeg::Error>> =
     OnceLock::new();
 
+/// Options for [`FrameExtractor::new_with_logger_and_conf`] and
+/// [`FrameExtractor::from_reader_with_logger_and_conf`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameExtractorConf {
+    pub hwaccel: HwAccel,
+    pub thumbnail: ThumbnailSize,
+    /// When set, [`FrameExtractor::next`] skips near-identical frames instead of
+    /// returning every decoded one, see [`SceneChangeConf`].
+    pub scene_change: Option<SceneChangeConf>,
+}
+
+/// Side of the small square grayscale buffer each frame is downsampled to for the
+/// [`FrameExtractorConf::scene_change`] comparisons.
+const SCENE_REDUCED_SIZE: u32 = 32;
+
+pub const DEFAULT_SCENE_CHANGE_THRESHOLD: f64 = 0.1;
+
+/// How sensitive [`FrameExtractor::next`]'s scene-change mode is to cuts: the normalized
+/// mean-absolute-difference between two downsampled, consecutive frames above which a
+/// frame is considered the start of a new scene. Ranges from 0 (identical frames) to 1
+/// (maximally different). An I-frame is always treated as a new scene, regardless of
+/// `threshold`.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneChangeConf {
+    pub threshold: f64,
+}
+
+impl Default for SceneChangeConf {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_SCENE_CHANGE_THRESHOLD,
+        }
+    }
+}
+
+/// How [`FrameExtractor`] scales every decoded frame before handing it out. Downstream
+/// perceptual hashing only needs a small thumbnail, so scaling down up front (swscale
+/// does it in the same pass as the RGB conversion) saves the cost of materializing and
+/// copying a full-resolution `RgbImage` per frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ThumbnailSize {
+    /// Keep the decoder's native resolution.
+    #[default]
+    Original,
+    /// Fit the longest side to `max_dim`, preserving aspect ratio. Both dimensions are
+    /// rounded down to the nearest even number, as swscale requires.
+    Scale(u32),
+    /// Scale to this exact width/height, ignoring aspect ratio. Must already be even.
+    Exact(u32, u32),
+}
+
+impl ThumbnailSize {
+    fn target_dims(self, native_width: u32, native_height: u32) -> (u32, u32) {
+        match self {
+            ThumbnailSize::Original => (native_width, native_height),
+            ThumbnailSize::Exact(width, height) => (width, height),
+            ThumbnailSize::Scale(max_dim) => {
+                let longest = native_width.max(native_height) as u64;
+                let width =
+                    (native_width as u64 * max_dim as u64 / longest).max(2) as u32;
+                let height =
+                    (native_height as u64 * max_dim as u64 / longest).max(2) as u32;
+                (round_down_even(width), round_down_even(height))
+            }
+        }
+    }
+}
+
+fn round_down_even(dim: u32) -> u32 {
+    dim & !1
+}
+
+/// A hardware decoding backend [`FrameExtractor::new_with_logger_and_conf`] can try
+/// before falling back to plain software decoding. Named after the `AVHWDeviceType`
+/// each one maps to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HwAccel {
+    /// Always decode in software, the previous and still-default behavior.
+    #[default]
+    None,
+    Vaapi,
+    Nvdec,
+    VideoToolbox,
+    Dxva2,
+}
+
+impl HwAccel {
+    fn device_type(self) -> Option<AVHWDeviceType> {
+        match self {
+            HwAccel::None => None,
+            HwAccel::Vaapi => Some(AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI),
+            HwAccel::Nvdec => Some(AVHWDeviceType::AV_HWDEVICE_TYPE_CUDA),
+            HwAccel::VideoToolbox => Some(AVHWDeviceType::AV_HWDEVICE_TYPE_VIDEOTOOLBOX),
+            HwAccel::Dxva2 => Some(AVHWDeviceType::AV_HWDEVICE_TYPE_DXVA2),
+        }
+    }
+}
+
+/// The pieces of a successfully set up hardware decode path that have to be torn down
+/// manually: the device context ffmpeg doesn't own (we handed it a second reference via
+/// `av_buffer_ref`), and the boxed pixel format stashed behind `AVCodecContext::opaque`
+/// for [`get_hw_format`] to read back.
+struct HwState {
+    device_ctx: *mut AVBufferRef,
+    opaque: *mut AVPixelFormat,
+    /// The pixel format decoded frames land in while still on the device, i.e. what
+    /// [`FrameExtractor::next`] compares a frame's format against to know whether it
+    /// needs downloading via `av_hwframe_transfer_data` before conversion.
+    pix_fmt: Pixel,
+}
+
+impl Drop for HwState {
+    fn drop(&mut self) {
+        unsafe {
+            ffmpeg_sys_next::av_buffer_unref(&mut self.device_ctx);
+            drop(Box::from_raw(self.opaque));
+        }
+    }
+}
+
 pub struct FrameExtractor<L: logger::Logger = logger::LogLogger> {
     logger: L,
 
@@ -37,6 +166,10 @@ pub struct FrameExtractor<L: logger::Logger = logger::LogLogger> {
     decoder: DecoderVideo,
     converter: ScalingContext,
 
+    // set when a hardware decode path from `HwAccel` was set up successfully, see
+    // `next`
+    hw: Option<HwState>,
+
     // internal timestamp bookkeeping
     seek_target_timestamp: i64,
     cur_timestamp: i64,
@@ -47,6 +180,15 @@ pub struct FrameExtractor<L: logger::Logger = logger::LogLogger> {
     timebase: Rational,
     video_stream_index: usize,
     orientation: Orientation,
+
+    // scene-change detection, only used when `FrameExtractorConf::scene_change` is set
+    scene_change: Option<SceneChangeConf>,
+    prev_scene_grid: Option<Vec<u8>>,
+
+    // only set up by `from_reader`, where `ictx` is backed by a custom AVIOContext
+    // instead of ffmpeg opening a path itself; see `CustomAvioGuard` for why this has
+    // to be declared after `ictx`.
+    avio_guard: Option<CustomAvioGuard>,
 }
 
 thread_local! {
@@ -59,6 +201,15 @@ impl FrameExtractor<logger::LogLogger> {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         Self::new_with_logger(path, logger::LogLogger)
     }
+
+    /// See [`FrameExtractor::from_reader_with_logger_and_conf`].
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self> {
+        Self::from_reader_with_logger_and_conf(
+            reader,
+            logger::LogLogger,
+            FrameExtractorConf::default(),
+        )
+    }
 }
 
 impl<L> FrameExtractor<L>
@@ -66,6 +217,17 @@ where
     L: logger::Logger,
 {
     pub fn new_with_logger(path: impl AsRef<Path>, logger: L) -> Result<Self> {
+        Self::new_with_logger_and_conf(path, logger, FrameExtractorConf::default())
+    }
+
+    /// Like [`Self::new_with_logger`], but lets the caller pick a hardware decoding
+    /// backend (falling back to software with a `warning!` if it fails to set up) and a
+    /// thumbnail size frames are scaled down to, see [`FrameExtractorConf`].
+    pub fn new_with_logger_and_conf(
+        path: impl AsRef<Path>,
+        logger: L,
+        conf: FrameExtractorConf,
+    ) -> Result<Self> {
         if let Err(e) = FFMPEG_INITIALIZED.get_or_init(|| {
             ffmpeg::init()?;
             ffmpeglog::set_level(ffmpeglog::Level::Warning);
@@ -114,7 +276,7 @@ where
             "The end timestamp is less than the start"
         );
 
-        let orientation = match get_orientation(&video) {
+        let orientation = match get_orientation(&video, &ictx) {
             Some(x) => x,
             None => {
                 warning!(logger, "Got a weird orientation angle, ignoring");
@@ -122,13 +284,31 @@ where
             }
         };
 
-        let decoder = CodecContext::from_parameters(video.parameters())
-            .wrap_err("No codec found")?
+        let mut context =
+            CodecContext::from_parameters(video.parameters()).wrap_err("No codec found")?;
+
+        let hw = conf.hwaccel.device_type().and_then(|device_type| {
+            match setup_hwaccel(&mut context, video.parameters().id(), device_type) {
+                Ok(hw) => Some(hw),
+                Err(e) => {
+                    warning!(
+                        logger,
+                        "Failed to set up {:?} hardware decoding, falling back to software: {:#}",
+                        conf.hwaccel,
+                        e
+                    );
+                    None
+                }
+            }
+        });
+
+        let decoder = context
             .decoder()
             .video()
             .wrap_err("No codec found, of type video (?)")?;
 
-        let converter = Self::pixel_converter(&decoder)?;
+        let converter =
+            Self::pixel_converter(&decoder, hw.as_ref().map(|hw| hw.pix_fmt), conf.thumbnail)?;
 
         ictx.streams_mut()
             .filter(|stream| stream.index() != video_stream_index)
@@ -140,12 +320,210 @@ where
             decoder,
             video_stream_index,
             converter,
+            hw,
             cur_timestamp,
             end_timestamp,
             seek_target_timestamp,
             first_timestamp,
             timebase,
             orientation,
+            scene_change: conf.scene_change,
+            prev_scene_grid: None,
+            avio_guard: None,
+        };
+        myself.log_ffmpeg_logs();
+        Ok(myself)
+    }
+
+    /// See [`Self::from_reader_with_logger_and_conf`].
+    pub fn from_reader_with_logger<R: Read + Seek + Send + 'static>(
+        reader: R,
+        logger: L,
+    ) -> Result<Self> {
+        Self::from_reader_with_logger_and_conf(reader, logger, FrameExtractorConf::default())
+    }
+
+    /// Like [`Self::new_with_logger_and_conf`], but decodes from an arbitrary [`Read`] +
+    /// [`Seek`] source instead of a filesystem path, e.g. an in-memory buffer or an
+    /// entry inside an archive. Implemented with a custom AVIO context
+    /// (`avio_alloc_context`) wrapping `reader`'s `read`/`seek` calls, since ffmpeg can
+    /// only open files or network URLs on its own. Every seeking method on the result
+    /// keeps working as long as `reader` itself is seekable.
+    pub fn from_reader_with_logger_and_conf<R: Read + Seek + Send + 'static>(
+        reader: R,
+        logger: L,
+        conf: FrameExtractorConf,
+    ) -> Result<Self> {
+        if let Err(e) = FFMPEG_INITIALIZED.get_or_init(|| {
+            ffmpeg::init()?;
+            ffmpeglog::set_level(ffmpeglog::Level::Warning);
+            unsafe {
+                ffmpeg_sys_next::av_log_set_callback(Some(ffmpeg_log_adaptor));
+            }
+            Ok(())
+        }) {
+            return Err(e).wrap_err("Failed to initialize ffmpeg");
+        }
+
+        Self::from_reader_inner(reader, logger, conf).wrap_err("on a custom reader")
+    }
+
+    fn from_reader_inner<R: Read + Seek + Send + 'static>(
+        reader: R,
+        logger: L,
+        conf: FrameExtractorConf,
+    ) -> Result<Self> {
+        let reader: *mut (dyn Any + Send) = Box::into_raw(Box::new(reader));
+
+        let buffer = unsafe { ffmpeg_sys_next::av_malloc(AVIO_BUFFER_SIZE) };
+        if buffer.is_null() {
+            unsafe { drop(Box::from_raw(reader)) };
+            eyre::bail!("Failed to allocate an AVIO buffer");
+        }
+
+        let avio_ctx = unsafe {
+            ffmpeg_sys_next::avio_alloc_context(
+                buffer as *mut u8,
+                AVIO_BUFFER_SIZE as libc::c_int,
+                0, // write_flag: this is a read-only reader
+                reader as *mut libc::c_void,
+                Some(read_packet::<R>),
+                None,
+                Some(seek_callback::<R>),
+            )
+        };
+        if avio_ctx.is_null() {
+            unsafe {
+                ffmpeg_sys_next::av_free(buffer as *mut libc::c_void);
+                drop(Box::from_raw(reader));
+            }
+            eyre::bail!("Failed to allocate an AVIO context");
+        }
+
+        // From here on, dropping `guard` frees the AVIO buffer/context and the boxed
+        // reader, so every early return below is safe to just bail out of.
+        let guard = CustomAvioGuard { avio_ctx, reader };
+
+        let mut fmt_ctx = unsafe { ffmpeg_sys_next::avformat_alloc_context() };
+        if fmt_ctx.is_null() {
+            eyre::bail!("Failed to allocate a format context");
+        }
+        unsafe {
+            (*fmt_ctx).pb = avio_ctx;
+            (*fmt_ctx).flags |= ffmpeg_sys_next::AVFMT_FLAG_CUSTOM_IO as libc::c_int;
+        }
+
+        let open_result = unsafe {
+            ffmpeg_sys_next::avformat_open_input(
+                &mut fmt_ctx,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if open_result < 0 {
+            // ffmpeg frees `fmt_ctx` itself on a failed open.
+            return Err(ffmpeg::Error::from(open_result)).wrap_err("Failed to open the reader");
+        }
+
+        let find_result =
+            unsafe { ffmpeg_sys_next::avformat_find_stream_info(fmt_ctx, std::ptr::null_mut()) };
+        if find_result < 0 {
+            unsafe { ffmpeg_sys_next::avformat_close_input(&mut fmt_ctx) };
+            return Err(ffmpeg::Error::from(find_result))
+                .wrap_err("Failed to find stream info");
+        }
+
+        // SAFETY: ffmpeg-next doesn't expose any way, public or otherwise, to build a
+        // `format::context::Input` from a context that was opened through a custom
+        // AVIOContext rather than one of its own `input*` functions. It is, however,
+        // a thin wrapper around exactly this `*mut AVFormatContext` plus a destructor
+        // tag, so this reaches around the safe API the same way `seek()` below reaches
+        // around the lack of a per-stream seek in it.
+        let mut ictx: FormatContext = unsafe { std::mem::transmute(fmt_ctx) };
+
+        let video = ictx
+            .streams()
+            .best(Type::Video)
+            .ok_or(eyre::eyre!("No video stream"))?;
+
+        let video_stream_index = video.index();
+        eyre::ensure!(
+            video.start_time() != AV_NOPTS_VALUE,
+            "Does not have a start time"
+        );
+        let cur_timestamp = video.start_time();
+        let seek_target_timestamp = video.start_time();
+        let first_timestamp = video.start_time();
+        let timebase = video.time_base();
+        let end_timestamp = if video.duration() == AV_NOPTS_VALUE {
+            eyre::ensure!(
+                ictx.duration() != AV_NOPTS_VALUE,
+                "Does not have a duration"
+            );
+            ictx.duration().rescale(AV_TIME_BASE_Q, timebase)
+        } else {
+            video.duration()
+        };
+        eyre::ensure!(
+            end_timestamp >= cur_timestamp,
+            "The end timestamp is less than the start"
+        );
+
+        let orientation = match get_orientation(&video, &ictx) {
+            Some(x) => x,
+            None => {
+                warning!(logger, "Got a weird orientation angle, ignoring");
+                Orientation::Normal
+            }
+        };
+
+        let mut context =
+            CodecContext::from_parameters(video.parameters()).wrap_err("No codec found")?;
+
+        let hw = conf.hwaccel.device_type().and_then(|device_type| {
+            match setup_hwaccel(&mut context, video.parameters().id(), device_type) {
+                Ok(hw) => Some(hw),
+                Err(e) => {
+                    warning!(
+                        logger,
+                        "Failed to set up {:?} hardware decoding, falling back to software: {:#}",
+                        conf.hwaccel,
+                        e
+                    );
+                    None
+                }
+            }
+        });
+
+        let decoder = context
+            .decoder()
+            .video()
+            .wrap_err("No codec found, of type video (?)")?;
+
+        let converter =
+            Self::pixel_converter(&decoder, hw.as_ref().map(|hw| hw.pix_fmt), conf.thumbnail)?;
+
+        ictx.streams_mut()
+            .filter(|stream| stream.index() != video_stream_index)
+            .for_each(|mut stream| stream_set_discard_all(&mut stream));
+
+        let myself = Self {
+            logger,
+            ictx,
+            decoder,
+            video_stream_index,
+            converter,
+            hw,
+            cur_timestamp,
+            end_timestamp,
+            seek_target_timestamp,
+            first_timestamp,
+            timebase,
+            orientation,
+            scene_change: conf.scene_change,
+            prev_scene_grid: None,
+            avio_guard: Some(guard),
         };
         myself.log_ffmpeg_logs();
         Ok(myself)
@@ -159,20 +537,39 @@ where
         })
     }
 
-    fn pixel_converter(decoder: &DecoderVideo) -> Result<ScalingContext> {
-        eyre::ensure!(decoder.format() != Pixel::None, "No pixel format");
+    /// `hw_pix_fmt`, when set, is the on-device pixel format hardware-decoded frames
+    /// arrive in; `decoder.format()` itself still reports that same format, but once
+    /// downloaded via `av_hwframe_transfer_data` in [`Self::next`] the frame is really
+    /// in the codec context's negotiated `sw_pix_fmt`, which is what the converter has
+    /// to be built for instead.
+    fn pixel_converter(
+        decoder: &DecoderVideo,
+        hw_pix_fmt: Option<Pixel>,
+        thumbnail: ThumbnailSize,
+    ) -> Result<ScalingContext> {
+        let src_format = if hw_pix_fmt.is_some() {
+            Pixel::from(unsafe { (*decoder.as_ptr()).sw_pix_fmt })
+        } else {
+            decoder.format()
+        };
+        eyre::ensure!(src_format != Pixel::None, "No pixel format");
+        let (target_width, target_height) =
+            thumbnail.target_dims(decoder.width(), decoder.height());
         Ok(ScalingContext::get(
-            decoder.format(),
+            src_format,
             decoder.width(),
             decoder.height(),
             // http://git.videolan.org/?p=ffmpeg.git;a=blob;f=libavutil/pixfmt.h;hb=HEAD
             Pixel::RGB24,
-            decoder.width(),
-            decoder.height(),
+            target_width,
+            target_height,
             ffmpeg::software::scaling::Flags::FAST_BILINEAR,
         )?)
     }
 
+    /// Decodes and returns the next frame. If [`FrameExtractorConf::scene_change`] was
+    /// set, near-identical frames are skipped and only frames that start a new visual
+    /// scene are returned, see [`SceneChangeConf`].
     pub fn next(&mut self) -> Result<Option<(Timestamp, RgbImage)>> {
         loop {
             loop {
@@ -218,12 +615,58 @@ where
                 }
 
                 let mut converted = FrameVideo::empty();
-                self.converter
-                    .run(&frame, &mut converted)
-                    .wrap_err("Failed to convert the decoded frame")?;
+                match &self.hw {
+                    // `frame` is still on the device; download it into system memory
+                    // before the converter (which only understands software frames)
+                    // can touch it.
+                    Some(hw) if frame.format() == hw.pix_fmt => {
+                        let mut sw_frame = FrameVideo::empty();
+                        let ret = unsafe {
+                            ffmpeg_sys_next::av_hwframe_transfer_data(
+                                sw_frame.as_mut_ptr(),
+                                frame.as_ptr(),
+                                0,
+                            )
+                        };
+                        if ret < 0 {
+                            return Err(ffmpeg::Error::from(ret))
+                                .wrap_err("Failed to download a hardware-decoded frame");
+                        }
+                        self.converter
+                            .run(&sw_frame, &mut converted)
+                            .wrap_err("Failed to convert the downloaded hardware frame")?;
+                    }
+                    _ => {
+                        self.converter
+                            .run(&frame, &mut converted)
+                            .wrap_err("Failed to convert the decoded frame")?;
+                    }
+                }
                 let img = create_rust_image(converted);
                 let img = undo_rotation(img, self.orientation);
 
+                if let Some(conf) = self.scene_change {
+                    let is_i_frame =
+                        unsafe { (*frame.as_ptr()).pict_type } == AVPictureType::AV_PICTURE_TYPE_I;
+                    let reduced = reduce_frame(&img);
+                    let is_scene_change = is_i_frame
+                        || match &self.prev_scene_grid {
+                            None => true,
+                            Some(prev) => {
+                                // A flat/solid reduced frame (e.g. a black frame) has no
+                                // variance to meaningfully compare against, so it's never
+                                // treated as a cut on its own, even if the raw difference
+                                // would exceed the threshold.
+                                buffer_variance(&reduced) != 0.0
+                                    && normalized_mean_abs_diff(prev, &reduced) > conf.threshold
+                            }
+                        };
+                    self.prev_scene_grid = Some(reduced);
+                    if !is_scene_change {
+                        continue;
+                    }
+                }
+
                 let dur = Timestamp::new(
                     self.cur_timestamp,
                     self.timebase,
@@ -268,6 +711,23 @@ where
         }
     }
 
+    /// Like [`Self::next`], but also computes a Blurhash placeholder (see
+    /// [`blurhash::encode`]) for the returned frame, using
+    /// [`blurhash::DEFAULT_X_COMPONENTS`] by [`blurhash::DEFAULT_Y_COMPONENTS`]
+    /// components. Useful for building quick visual previews of detected duplicates
+    /// without storing full thumbnails.
+    pub fn next_with_blurhash(&mut self) -> Result<Option<(Timestamp, RgbImage, String)>> {
+        let Some((ts, img)) = self.next()? else {
+            return Ok(None);
+        };
+        let hash = blurhash::encode(
+            &img,
+            blurhash::DEFAULT_X_COMPONENTS,
+            blurhash::DEFAULT_Y_COMPONENTS,
+        );
+        Ok(Some((ts, img, hash)))
+    }
+
     pub fn seek_forward(&mut self, dur: Duration) -> Result<()> {
         if dur.is_zero() {
             return Ok(());
@@ -351,28 +811,36 @@ enum Orientation {
     Upside,
 }
 
-fn get_orientation(video: &ffmpeg::Stream) -> Option<Orientation> {
-    // TODO: Rotation can also be set in the metadata dict, find an example video and fix!
-    for data in video.side_data() {
-        if data.kind() != ffmpeg::packet::side_data::Type::DisplayMatrix {
-            continue;
-        }
-        let rot = unsafe {
-            ffmpeg_sys_next::av_display_rotation_get(data.data().as_ptr() as *const i32)
-        };
-
-        if rot.is_finite() {
-            return match rot.round() as i32 {
-                -90 => Some(Orientation::Right),
-                90 => Some(Orientation::Left),
-                0 => Some(Orientation::Normal),
-                180 | -180 => Some(Orientation::Upside),
-                _ => None,
+/// Combines the `DisplayMatrix` side data angle (if any) with a `rotate` key in either
+/// the stream's or the container's metadata dictionary (common in phone recordings
+/// muxed by various tools), summing the two modulo 360 before mapping to an
+/// [`Orientation`].
+fn get_orientation(video: &ffmpeg::Stream, ictx: &FormatContext) -> Option<Orientation> {
+    let display_matrix_angle = video
+        .side_data()
+        .filter(|data| data.kind() == ffmpeg::packet::side_data::Type::DisplayMatrix)
+        .find_map(|data| {
+            let rot = unsafe {
+                ffmpeg_sys_next::av_display_rotation_get(data.data().as_ptr() as *const i32)
             };
-        }
+            rot.is_finite().then(|| rot.round() as i32)
+        })
+        .unwrap_or(0);
+
+    let metadata_rotate_angle = video
+        .metadata()
+        .get("rotate")
+        .or_else(|| ictx.metadata().get("rotate"))
+        .and_then(|rotate| rotate.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    match (display_matrix_angle + metadata_rotate_angle).rem_euclid(360) {
+        0 => Some(Orientation::Normal),
+        90 => Some(Orientation::Left),
+        180 => Some(Orientation::Upside),
+        270 => Some(Orientation::Right),
+        _ => None,
     }
-
-    Some(Orientation::Normal)
 }
 
 fn undo_rotation(img: RgbImage, ori: Orientation) -> RgbImage {
@@ -384,6 +852,37 @@ fn undo_rotation(img: RgbImage, ori: Orientation) -> RgbImage {
     }
 }
 
+/// Downsamples a decoded frame to a small, fixed-size grayscale buffer for cheap
+/// scene-change comparisons, see [`FrameExtractorConf::scene_change`].
+fn reduce_frame(img: &RgbImage) -> Vec<u8> {
+    let gray = imageops::grayscale(img);
+    let reduced = imageops::resize(
+        &gray,
+        SCENE_REDUCED_SIZE,
+        SCENE_REDUCED_SIZE,
+        FilterType::Triangle,
+    );
+    reduced.into_raw()
+}
+
+/// Average absolute difference between two equally-sized buffers, normalized to 0..=1 by
+/// the maximum possible per-pixel difference.
+fn normalized_mean_abs_diff(a: &[u8], b: &[u8]) -> f64 {
+    assert_eq!(a.len(), b.len());
+    let sum: i64 = a
+        .iter()
+        .zip(b)
+        .map(|(&x, &y)| (x as i64 - y as i64).abs())
+        .sum();
+    (sum as f64 / a.len() as f64) / u8::MAX as f64
+}
+
+/// 0 for a perfectly flat (solid-color) buffer.
+fn buffer_variance(buf: &[u8]) -> f64 {
+    let mean = buf.iter().map(|&x| x as f64).sum::<f64>() / buf.len() as f64;
+    buf.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / buf.len() as f64
+}
+
 pub struct FrameExtractorIter<'a> {
     extractor: &'a mut FrameExtractor,
 }
@@ -434,6 +933,178 @@ fn create_rust_image(converted: FrameVideo) -> RgbImage {
     .expect("the buffer is big enough!")
 }
 
+/// Sets up the hardware decode path for `hwaccel`/`device_type` on `context`, which
+/// must not have been opened (via `decoder()`/`video()`) yet: creates the
+/// `AVHWDeviceType` device, attaches it to `AVCodecContext::hw_device_ctx`, and installs
+/// [`get_hw_format`] as the `get_format` callback so the decoder negotiates the matching
+/// on-device pixel format instead of falling back to software on its own.
+fn setup_hwaccel(
+    context: &mut CodecContext,
+    codec_id: ffmpeg::codec::Id,
+    device_type: AVHWDeviceType,
+) -> Result<HwState> {
+    let codec = ffmpeg::decoder::find(codec_id)
+        .ok_or_else(|| eyre::eyre!("No decoder registered for {:?}", codec_id))?;
+
+    let hw_pix_fmt = unsafe { hw_pixel_format(codec.as_ptr(), device_type) }.ok_or_else(|| {
+        eyre::eyre!("{:?} has no hardware config for {:?}", codec_id, device_type)
+    })?;
+
+    let mut device_ctx: *mut AVBufferRef = std::ptr::null_mut();
+    let ret = unsafe {
+        ffmpeg_sys_next::av_hwdevice_ctx_create(
+            &mut device_ctx,
+            device_type,
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    eyre::ensure!(
+        ret >= 0,
+        "av_hwdevice_ctx_create failed: {}",
+        ffmpeg::Error::from(ret)
+    );
+
+    // `get_hw_format` has no way to capture `hw_pix_fmt`, being a bare C callback, so
+    // it is stashed behind `opaque` instead and read back from there; `HwState` owns
+    // freeing it again.
+    let opaque = Box::into_raw(Box::new(hw_pix_fmt));
+
+    unsafe {
+        let ptr = context.as_mut_ptr();
+        (*ptr).hw_device_ctx = ffmpeg_sys_next::av_buffer_ref(device_ctx);
+        (*ptr).opaque = opaque as *mut libc::c_void;
+        (*ptr).get_format = Some(get_hw_format);
+    }
+
+    Ok(HwState {
+        device_ctx,
+        opaque,
+        pix_fmt: Pixel::from(hw_pix_fmt),
+    })
+}
+
+/// Looks through `codec`'s advertised `AVCodecHWConfig`s for one that works through an
+/// `AVHWDeviceType` device context and matches `device_type`, returning the pixel
+/// format the decoder will hand back frames in when using it.
+unsafe fn hw_pixel_format(
+    codec: *const AVCodec,
+    device_type: AVHWDeviceType,
+) -> Option<AVPixelFormat> {
+    let mut i = 0;
+    loop {
+        let config = ffmpeg_sys_next::avcodec_get_hw_config(codec, i);
+        if config.is_null() {
+            return None;
+        }
+
+        let config = &*config;
+        if config.methods & AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX as i32 != 0
+            && config.device_type == device_type
+        {
+            return Some(config.pix_fmt);
+        }
+
+        i += 1;
+    }
+}
+
+/// The `get_format` callback installed by [`setup_hwaccel`]: picks the hardware pixel
+/// format stashed behind `ctx`'s `opaque` pointer out of the codec's offered
+/// `pix_fmts`, falling back to ffmpeg's own first choice (i.e. plain software decoding)
+/// if it isn't offered after all.
+unsafe extern "C" fn get_hw_format(
+    ctx: *mut AVCodecContext,
+    pix_fmts: *const AVPixelFormat,
+) -> AVPixelFormat {
+    let wanted = *((*ctx).opaque as *const AVPixelFormat);
+
+    let mut p = pix_fmts;
+    while *p != AVPixelFormat::AV_PIX_FMT_NONE {
+        if *p == wanted {
+            return *p;
+        }
+        p = p.add(1);
+    }
+
+    *pix_fmts
+}
+
+/// Size of the read buffer handed to the custom AVIO context allocated by
+/// [`FrameExtractor::from_reader`].
+const AVIO_BUFFER_SIZE: usize = 4096;
+
+/// Owns the pieces of a [`FrameExtractor::from_reader`] input that ffmpeg itself
+/// doesn't know how to free: the AVIOContext, its read buffer, and the boxed reader
+/// behind its `opaque` pointer. Declared as the last field of [`FrameExtractor`], after
+/// `ictx`, so that Rust only drops it once `ictx`'s own `Drop` (which closes the
+/// AVFormatContext) has already run; custom IO's AVIOContext has to outlive the input
+/// it backs, and freeing it is the caller's job, not ffmpeg's.
+struct CustomAvioGuard {
+    avio_ctx: *mut ffmpeg_sys_next::AVIOContext,
+    reader: *mut (dyn Any + Send),
+}
+
+impl Drop for CustomAvioGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let buffer = (*self.avio_ctx).buffer;
+            ffmpeg_sys_next::av_free(buffer as *mut libc::c_void);
+            ffmpeg_sys_next::av_free(self.avio_ctx as *mut libc::c_void);
+            drop(Box::from_raw(self.reader));
+        }
+    }
+}
+
+/// The `read_packet` callback for the AVIOContext set up by
+/// [`FrameExtractor::from_reader`]. Copies up to `buf_size` bytes from the reader
+/// behind `opaque` and returns the number of bytes copied, or `AVERROR_EOF` once the
+/// reader is exhausted.
+unsafe extern "C" fn read_packet<R: Read>(
+    opaque: *mut libc::c_void,
+    buf: *mut u8,
+    buf_size: libc::c_int,
+) -> libc::c_int {
+    let reader = &mut *(opaque as *mut R);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size as usize);
+    match reader.read(buf) {
+        Ok(0) => ffmpeg_sys_next::AVERROR_EOF,
+        Ok(n) => n as libc::c_int,
+        Err(_) => -(libc::EIO),
+    }
+}
+
+/// The `seek` callback for the AVIOContext set up by [`FrameExtractor::from_reader`].
+/// Honors `AVSEEK_SIZE` (ffmpeg's way of asking for the stream's total size without
+/// moving the read position) in addition to the usual `SEEK_SET`/`SEEK_CUR`/`SEEK_END`.
+unsafe extern "C" fn seek_callback<R: Seek>(
+    opaque: *mut libc::c_void,
+    offset: i64,
+    whence: libc::c_int,
+) -> i64 {
+    let reader = &mut *(opaque as *mut R);
+
+    if whence & ffmpeg_sys_next::AVSEEK_SIZE as libc::c_int != 0 {
+        let size = (|| -> std::io::Result<u64> {
+            let cur = reader.stream_position()?;
+            let end = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(cur))?;
+            Ok(end)
+        })();
+        return size.map(|s| s as i64).unwrap_or(-1);
+    }
+
+    let pos = match whence {
+        libc::SEEK_SET => SeekFrom::Start(offset as u64),
+        libc::SEEK_CUR => SeekFrom::Current(offset),
+        libc::SEEK_END => SeekFrom::End(offset),
+        _ => return -1,
+    };
+
+    reader.seek(pos).map(|p| p as i64).unwrap_or(-1)
+}
+
 fn stream_set_discard_all(stream: &mut ffmpeg::StreamMut<'_>) {
     unsafe {
         let ptr = stream.as_mut_ptr();
@@ -1,10 +1,22 @@
-use std::{fmt, time::Duration};
+use std::{fmt, str::FromStr, time::Duration};
 
 use ffmpeg::{Rational, Rescale};
 use rkyv::{Archive, Serialize};
 
 extern crate ffmpeg_next as ffmpeg;
 
+#[derive(thiserror::Error, Debug)]
+pub enum ParseError {
+    #[error("not a number: {0:?}")]
+    NotANumber(String),
+    #[error("expected at most HH:MM:SS, got {0} colon-separated fields")]
+    TooManyFields(usize),
+    #[error("minutes must be in 0..60, got {0}")]
+    MinutesOutOfRange(u32),
+    #[error("seconds must be in 0..60, got {0}")]
+    SecondsOutOfRange(u32),
+}
+
 #[derive(
     Serialize,
     Archive,
@@ -62,6 +74,29 @@ impl Timestamp {
         Rational::new(self.timebase_numerator, self.timebase_denominator)
     }
 
+    /// Offsets `self` forward by `dur`, rescaled into `self`'s own timebase, saturating
+    /// instead of overflowing/panicking if that timebase is too coarse to represent it.
+    pub fn saturating_add(&self, dur: Duration) -> Self {
+        let millis: i64 = dur.as_millis().try_into().unwrap_or(i64::MAX);
+        let delta = millis.rescale(Rational::new(1, 1000), self.timebase());
+        Self::new(
+            self.timestamp.saturating_add(delta),
+            self.timebase(),
+            self.first_timestamp,
+        )
+    }
+
+    /// The duration from `earlier` to `self`, or `None` if `self` is before `earlier`.
+    /// Both timestamps are rescaled to a common millisecond base before subtracting, so
+    /// they don't need to share a timebase.
+    pub fn checked_sub(&self, earlier: &Timestamp) -> Option<Duration> {
+        let to_millis = Rational::new(1, 1000);
+        let diff = self
+            .timestamp(to_millis)
+            .checked_sub(earlier.timestamp(to_millis))?;
+        Some(Duration::from_millis(u64::try_from(diff).ok()?))
+    }
+
     fn parts(&self) -> (bool, f64, f64, f64, f64) {
         // TODO: Why not use ffmpeg rescale and rational if not all decimals are going to
         // be used?
@@ -90,6 +125,64 @@ impl Timestamp {
     }
 }
 
+/// Parses `-[HH:]MM:SS[.mmm]` / `-SS[.mmm]`-style timestamps, e.g. for `--seek`
+/// CLI arguments. The fractional part is padded/truncated to exactly milliseconds.
+/// Only the least-significant field (seconds, or minutes if only `SS` is given) is
+/// allowed to be unbounded; any more significant field must be in `0..60`.
+impl FromStr for Timestamp {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+
+        let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+        let millis: u32 = if frac.is_empty() { 0 } else { parse_millis(frac)? };
+
+        let fields: Vec<&str> = whole.split(':').collect();
+        if fields.len() > 3 {
+            return Err(ParseError::TooManyFields(fields.len()));
+        }
+
+        let mut hms = [0u32; 3];
+        for (slot, field) in hms[3 - fields.len()..].iter_mut().zip(&fields) {
+            *slot = parse_field(field)?;
+        }
+        let [hours, minutes, seconds] = hms;
+
+        if fields.len() >= 2 && seconds >= 60 {
+            return Err(ParseError::SecondsOutOfRange(seconds));
+        }
+        if fields.len() == 3 && minutes >= 60 {
+            return Err(ParseError::MinutesOutOfRange(minutes));
+        }
+
+        let total_millis =
+            (((hours as i64 * 60 + minutes as i64) * 60 + seconds as i64) * 1000) + millis as i64;
+        let total_millis = if negative { -total_millis } else { total_millis };
+
+        Ok(Self::new_abs(total_millis, Rational::new(1, 1000)))
+    }
+}
+
+fn parse_field(field: &str) -> std::result::Result<u32, ParseError> {
+    field
+        .parse()
+        .map_err(|_| ParseError::NotANumber(field.to_string()))
+}
+
+/// Pads or truncates the digits after the `.` to exactly 3, e.g. `"5"` -> 500, `"1234"`
+/// -> 123.
+fn parse_millis(frac: &str) -> std::result::Result<u32, ParseError> {
+    if frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseError::NotANumber(frac.to_string()));
+    }
+    let padded: String = frac.chars().chain(std::iter::repeat('0')).take(3).collect();
+    parse_field(&padded)
+}
+
 impl fmt::Display for Timestamp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let (negative, hours, minutes, seconds, subsec) = self.parts();
@@ -136,4 +229,53 @@ mod test {
         let ts = stamp.timestamp(Rational::new(1, 500));
         assert_eq!(1000, ts);
     }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "00:00:01.500",
+            "1.5".parse::<Timestamp>().unwrap().to_string()
+        );
+        assert_eq!(
+            "00:01:30.000",
+            "01:30".parse::<Timestamp>().unwrap().to_string()
+        );
+        assert_eq!(
+            "01:02:03.123",
+            "01:02:03.12345".parse::<Timestamp>().unwrap().to_string()
+        );
+        assert_eq!(
+            "-00:00:01.000",
+            "-1".parse::<Timestamp>().unwrap().to_string()
+        );
+
+        // "60:00" is fine: MM:SS's minutes field is the most significant one given, so
+        // it's allowed to exceed 60, same as a lone "SS".
+        assert_eq!(
+            "01:00:00.000",
+            "60:00".parse::<Timestamp>().unwrap().to_string()
+        );
+
+        assert!("01:60:00".parse::<Timestamp>().is_err());
+        assert!("01:02:60".parse::<Timestamp>().is_err());
+        assert!("1:2:3:4".parse::<Timestamp>().is_err());
+        assert!("a:b".parse::<Timestamp>().is_err());
+        assert!("1.a".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn saturating_add() {
+        let stamp = Timestamp::from_duration(Duration::from_secs(1));
+        let stamp = stamp.saturating_add(Duration::from_millis(500));
+        assert_eq!(Duration::from_millis(1500), stamp.to_duration());
+    }
+
+    #[test]
+    fn checked_sub() {
+        let earlier = Timestamp::from_duration(Duration::from_secs(1));
+        let later = Timestamp::from_duration(Duration::from_secs(3));
+
+        assert_eq!(Some(Duration::from_secs(2)), later.checked_sub(&earlier));
+        assert_eq!(None, earlier.checked_sub(&later));
+    }
 }
@@ -0,0 +1,196 @@
+//! Which perceptual-hash algorithm `get_hashes` hashes frames with. Mirrors the
+//! `imgdup` binary's own `imghash::hashalg` -- a `HashAlg` selection flattened into the
+//! CLI, backing a `Hasher` built once and threaded through as `Ctx::hasher` -- duplicated
+//! here rather than shared, since the two crates don't share this kind of
+//! image-processing code.
+//!
+//! Changing `--hash-algo` makes hashes from before and after the change incomparable;
+//! unlike `imgdup`'s own `HashConfig`, nothing here stamps that choice into
+//! `VidSrc`'s database identifier, so mixing runs of different algorithms against the
+//! same `--database-file` silently produces meaningless distances.
+
+use clap::{Args, ValueEnum};
+use image::{GenericImageView, Pixel, RgbImage, SubImage};
+use imgdup_common::imghash::hamming::Hamming;
+
+/// One of the handful of algorithms `image_hasher` implements, plus the DCT-based
+/// "pHash" already used elsewhere in this workspace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HashAlg {
+    /// Average the pixels and threshold against the mean.
+    Mean,
+    /// Horizontal gradient, a.k.a. dHash.
+    Difference,
+    /// Vertical gradient.
+    Gradient,
+    /// Both gradient directions combined.
+    DoubleGradient,
+    /// Average intensity of blocks, a.k.a. blockhash.
+    Blockhash,
+    /// A 2-D DCT over the resized, grayscaled frame, thresholded against the median of
+    /// its low-frequency coefficients. Tolerates scaling, mild blur and recompression
+    /// better than the `image_hasher`-backed algorithms above.
+    Dct,
+}
+
+impl Default for HashAlg {
+    fn default() -> Self {
+        HashAlg::Gradient
+    }
+}
+
+impl HashAlg {
+    fn to_image_hasher(self) -> Option<image_hasher::HashAlg> {
+        match self {
+            HashAlg::Mean => Some(image_hasher::HashAlg::Mean),
+            HashAlg::Difference => Some(image_hasher::HashAlg::Gradient),
+            HashAlg::Gradient => Some(image_hasher::HashAlg::VertGradient),
+            HashAlg::DoubleGradient => Some(image_hasher::HashAlg::DoubleGradient),
+            HashAlg::Blockhash => Some(image_hasher::HashAlg::Blockhash),
+            HashAlg::Dct => None,
+        }
+    }
+}
+
+/// Picks which [`HashAlg`] frames get hashed with.
+#[derive(Args, Debug)]
+pub struct HasherCli {
+    /// Which perceptual-hash algorithm to hash frames with
+    #[arg(long, value_enum, default_value_t = HashAlg::default())]
+    hash_algo: HashAlg,
+}
+
+impl HasherCli {
+    pub fn to_hasher(&self) -> Hasher {
+        Hasher::new(self.hash_algo)
+    }
+}
+
+/// Hashes frames with whichever [`HashAlg`] it was built with. `image_hasher`-backed
+/// algorithms go through a [`image_hasher::Hasher`] built once up front; [`HashAlg::Dct`]
+/// has no `image_hasher` equivalent, so it's computed by hand instead.
+pub struct Hasher {
+    alg: HashAlg,
+    image_hasher: Option<image_hasher::Hasher<[u8; 16]>>,
+}
+
+impl Hasher {
+    pub fn new(alg: HashAlg) -> Self {
+        let image_hasher = alg.to_image_hasher().map(|ih_alg| {
+            image_hasher::HasherConfig::with_bytes_type::<[u8; 16]>()
+                .hash_alg(ih_alg)
+                .hash_size(16, 8)
+                .preproc_dct()
+                .to_hasher()
+        });
+        Self { alg, image_hasher }
+    }
+
+    /// Hashes `img`, cropping out to an owned image first if it's actually a crop
+    /// rather than the whole underlying image (mirroring `imgdup`'s own `hash_sub`).
+    pub fn hash_sub(&self, img: &SubImage<&RgbImage>) -> Hamming {
+        if img.bounds() == img.inner().bounds() {
+            self.hash(img.inner())
+        } else {
+            self.hash(&img.to_image())
+        }
+    }
+
+    fn hash(&self, img: &RgbImage) -> Hamming {
+        match &self.image_hasher {
+            Some(hasher) => {
+                let hash = hasher.hash_image(img);
+                let mut buf = [0u8; 16];
+                let bytes = hash.as_bytes();
+                let n = bytes.len().min(buf.len());
+                buf[..n].copy_from_slice(&bytes[..n]);
+                Hamming(u128::from_be_bytes(buf))
+            }
+            None => dct_hash(&image::imageops::colorops::grayscale(img)),
+        }
+    }
+}
+
+const DCT_RESIZE_TO: u32 = 32;
+const DCT_BLOCK_SIZE: usize = 8;
+
+/// Resizes `gray` to 32x32, runs a separable 2-D DCT-II over it, and thresholds the
+/// top-left 8x8 block of low-frequency coefficients (excluding the DC term) against
+/// their median.
+fn dct_hash<I, P>(gray: &I) -> Hamming
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    let resized = image::imageops::resize(
+        gray,
+        DCT_RESIZE_TO,
+        DCT_RESIZE_TO,
+        image::imageops::FilterType::Lanczos3,
+    );
+
+    let samples: Vec<Vec<f64>> = (0..DCT_RESIZE_TO)
+        .map(|y| {
+            (0..DCT_RESIZE_TO)
+                .map(|x| resized.get_pixel(x, y).channels()[0] as f64)
+                .collect()
+        })
+        .collect();
+
+    let coeffs = dct_2d(&samples);
+
+    let mut block: Vec<f64> = coeffs[..DCT_BLOCK_SIZE]
+        .iter()
+        .flat_map(|row| row[..DCT_BLOCK_SIZE].iter().copied())
+        .collect();
+    block.remove(0);
+
+    let mut sorted = block.clone();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    let mut bits: u128 = 0;
+    for (i, &coeff) in block.iter().enumerate() {
+        if coeff > median {
+            bits |= 1 << i;
+        }
+    }
+
+    Hamming(bits)
+}
+
+/// A separable 2-D DCT-II: the 1-D transform applied to every row, then to every column
+/// of the result.
+fn dct_2d(samples: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let by_rows: Vec<Vec<f64>> = samples.iter().map(|row| dct_1d(row)).collect();
+
+    let n = by_rows.len();
+    let m = by_rows[0].len();
+    let mut by_cols = vec![vec![0.0; m]; n];
+    for x in 0..m {
+        let column: Vec<f64> = by_rows.iter().map(|row| row[x]).collect();
+        let column = dct_1d(&column);
+        for (y, value) in column.into_iter().enumerate() {
+            by_cols[y][x] = value;
+        }
+    }
+
+    by_cols
+}
+
+/// The 1-D DCT-II: `F[k] = sum_{n=0}^{N-1} f[n]*cos(pi/N*(n+0.5)*k)`.
+fn dct_1d(input: &[f64]) -> Vec<f64> {
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            input
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| {
+                    let angle = std::f64::consts::PI / n as f64 * (i as f64 + 0.5) * k as f64;
+                    sample * angle.cos()
+                })
+                .sum()
+        })
+        .collect()
+}
@@ -0,0 +1,37 @@
+//! Heuristic detection of an embedded MP4 clip appended to a JPEG/HEIC "motion photo"
+//! still, like the ones produced by Samsung and Google Camera. This is not a real
+//! container parser, just a scan for a second `ftyp` box signature after the host
+//! file's own header -- which is how every motion-photo format in the wild actually
+//! works: the still image (or HEIC container) comes first, and the video clip is
+//! appended afterwards as a normal, independently-playable MP4.
+
+/// The box type every ISO-BMFF (MP4/HEIC) file starts its relevant box with.
+const FTYP: &[u8; 4] = b"ftyp";
+
+/// A HEIC still is itself an ISO-BMFF file, so it always has its own `ftyp` box within
+/// the first few bytes. Anything found past this many bytes is assumed to belong to an
+/// appended clip rather than the host container's own header.
+const HOST_HEADER_SLACK: usize = 64;
+
+/// Returns the byte offset of an embedded MP4's `ftyp` box within `bytes`, or `None` if
+/// there isn't one past the host container's own header.
+///
+/// A JPEG has no `ftyp` box at all, so every match is an embedded clip. A HEIC still
+/// already starts with its own `ftyp` box, so the first match (always within
+/// [`HOST_HEADER_SLACK`] bytes) is skipped and treated as the host's, not the clip's.
+pub fn find_embedded_video(bytes: &[u8]) -> Option<u64> {
+    let mut search_from = 0;
+    while let Some(type_pos) = find_subslice(&bytes[search_from..], FTYP) {
+        let type_pos = search_from + type_pos;
+        let box_start = type_pos.checked_sub(4)?;
+        if box_start > HOST_HEADER_SLACK {
+            return Some(box_start as u64);
+        }
+        search_from = type_pos + FTYP.len();
+    }
+    None
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8; 4]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
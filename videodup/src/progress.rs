@@ -0,0 +1,55 @@
+use std::io::Write;
+use std::sync::mpsc;
+
+use color_eyre::eyre::{self, Context};
+use serde::Serialize;
+
+/// One update from the video/tree workers, meant for a supervising GUI/TUI rather than
+/// the log file. Mirrors czkawka's `ProgressData`, but as a stream of discrete events
+/// instead of a single polled struct, since videodup is already event-driven.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// A new video started being hashed.
+    FileStarted {
+        path: String,
+        index: usize,
+        total: usize,
+    },
+    /// Periodic update while a video is being hashed, at the same cadence as the
+    /// `--progress-log-every` debug log.
+    FramesExtracted {
+        path: String,
+        frames_done: usize,
+        estimated_total: usize,
+        eta_secs: f64,
+    },
+    /// A video finished hashing successfully.
+    FileFinished { path: String, hashes_found: usize },
+    /// A video was found to have duplicates in the tree.
+    DuplicatesFound { path: String, count: usize },
+    /// The tree has been closed and is fully written to disk.
+    TreeSaveDone,
+}
+
+/// How many remaining frames are expected to take, assuming each one takes roughly as
+/// long to seek to and hash as `step` represents in video time. Not actually a good
+/// estimate of wall-clock time, since hashing speed isn't 1:1 with video time, but it's
+/// the only notion of "time per frame" available here without tracking per-frame timing.
+pub fn eta_secs(estimated_total: usize, frames_done: usize, step: std::time::Duration) -> f64 {
+    let remaining = estimated_total.saturating_sub(frames_done);
+    remaining as f64 * step.as_secs_f64()
+}
+
+/// Drains `rx` and writes each [`ProgressEvent`] to `out` as one line of JSON, so an
+/// external frontend can follow along without scraping log text. Returns once every
+/// sender has been dropped.
+pub fn report(rx: mpsc::Receiver<ProgressEvent>, mut out: impl Write) -> eyre::Result<()> {
+    while let Ok(event) = rx.recv() {
+        let line =
+            serde_json::to_string(&event).wrap_err("failed to serialize a progress event")?;
+        writeln!(out, "{line}").wrap_err("failed to write a progress event")?;
+        out.flush().wrap_err("failed to flush a progress event")?;
+    }
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, io, path::Path, time::UNIX_EPOCH};
 
 use rkyv::{Archive, Serialize};
 
@@ -8,6 +8,49 @@ use imgdup_common::{
     utils::simple_path::{SimplePath, SimplePathBuf},
 };
 
+/// A source file's size and modification time at the moment it was hashed, so `main`
+/// can tell a video that was edited in place (same path, new bytes) apart from one
+/// that's untouched, following czkawka's approach of keying a cache entry on
+/// `(path, size, modified_date)`.
+#[derive(
+    Serialize, Archive, Copy, Clone, Hash, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize,
+)]
+#[archive(check_bytes)]
+pub struct FileStamp {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Self {
+            size: meta.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl ArchivedFileStamp {
+    pub fn to_owned(&self) -> FileStamp {
+        FileStamp {
+            size: self.size,
+            mtime_secs: self.mtime_secs,
+            mtime_nanos: self.mtime_nanos,
+        }
+    }
+}
+
 #[derive(
     Serialize, Archive, Clone, Hash, PartialEq, Eq, serde::Serialize, serde::Deserialize,
 )]
@@ -16,6 +59,14 @@ pub struct VidSrc {
     frame_pos: Timestamp,
     path: SimplePathBuf,
     mirrored: Mirror,
+    /// `Some(byte_offset)` when `path` is a motion-photo still and the frame actually
+    /// came from decoding the MP4 clip appended at that offset, rather than from
+    /// decoding `path` itself as a video. See `motion_photo::find_embedded_video`.
+    embedded_offset: Option<u64>,
+    /// `path`'s size and modification time when it was hashed, so a re-run can tell it
+    /// apart from an edited-in-place file with the same path and re-hash it instead of
+    /// trusting the stale entry. See `main`'s staleness check.
+    stamp: FileStamp,
 }
 
 #[derive(
@@ -53,16 +104,46 @@ impl fmt::Display for VidSrc {
                 Mirror::Normal => "N",
                 Mirror::Mirrored => "M",
             },
-        )
+        )?;
+        if let Some(offset) = self.embedded_offset {
+            write!(f, "@{offset}")?;
+        }
+        Ok(())
     }
 }
 
 impl VidSrc {
-    pub fn new(frame_pos: Timestamp, path: SimplePathBuf, mirrored: Mirror) -> Self {
+    pub fn new(
+        frame_pos: Timestamp,
+        path: SimplePathBuf,
+        mirrored: Mirror,
+        stamp: FileStamp,
+    ) -> Self {
         Self {
             frame_pos,
             path,
             mirrored,
+            embedded_offset: None,
+            stamp,
+        }
+    }
+
+    /// Like [`Self::new`], but `path` is a motion-photo still whose frames actually came
+    /// from the MP4 clip appended at `embedded_offset` bytes into the file, not from
+    /// decoding `path` itself.
+    pub fn new_motion_photo(
+        frame_pos: Timestamp,
+        path: SimplePathBuf,
+        mirrored: Mirror,
+        embedded_offset: u64,
+        stamp: FileStamp,
+    ) -> Self {
+        Self {
+            frame_pos,
+            path,
+            mirrored,
+            embedded_offset: Some(embedded_offset),
+            stamp,
         }
     }
 
@@ -77,6 +158,16 @@ impl VidSrc {
     pub fn mirrored(&self) -> Mirror {
         self.mirrored
     }
+
+    /// The byte offset of an embedded MP4 clip within [`Self::path`], if this source
+    /// came from a motion photo rather than from a standalone video file.
+    pub fn embedded_offset(&self) -> Option<u64> {
+        self.embedded_offset
+    }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp
+    }
 }
 
 impl ArchivedVidSrc {
@@ -95,18 +186,31 @@ impl ArchivedVidSrc {
         }
     }
 
+    pub fn embedded_offset(&self) -> Option<u64> {
+        self.embedded_offset.as_ref().map(|o| *o)
+    }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp.to_owned()
+    }
+
     // TODO: figure out of rkyv deserialize works and use that instead
     pub fn deserialize(&self) -> VidSrc {
         VidSrc {
             frame_pos: self.frame_pos.deserialize(),
             path: self.path.deserialize(),
             mirrored: self.mirrored(),
+            embedded_offset: self.embedded_offset(),
+            stamp: self.stamp(),
         }
     }
 }
 
 impl Source for VidSrc {
     fn identifier() -> &'static str {
-        "video:1"
+        // Bumped from "video:2": VidSrc grew the `stamp` field, so a re-run can tell a
+        // file was edited in place and needs re-hashing instead of trusting a stale
+        // entry, which changes the archived layout.
+        "video:3"
     }
 }